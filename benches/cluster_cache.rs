@@ -0,0 +1,69 @@
+//! Concurrent cached reads from one segment: the sharded `ClusterCache` against the same
+//! lookups serialized behind one mutex per segment, as the old cache did them.
+//! Run with: cargo bench --bench cluster_cache
+//! Scaling only shows with real parallelism; on fewer than 4 cores expect a tie.
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use did_mmap_cache::archive::cluster_cache::ClusterCache;
+use did_mmap_cache::archive::{ArchiveWriter, Segment};
+use memmap2::Mmap;
+use std::fs::File;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const THREADS: usize = 8;
+const READS_PER_THREAD: usize = 20_000;
+
+/// One segment with `dids` clusters of 4 messages each.
+fn build_segment(dir: &Path, dids: u64) -> Segment {
+    let total = dids * 4;
+    let mut writer = ArchiveWriter::new(dir, 0, 0, total + 1, None).unwrap();
+    for seq in 0..total {
+        let msg = format!("cluster {} message {} {}", seq % dids, seq, "x".repeat(200)).into_bytes();
+        writer.append_message(seq, &format!("did:plc:user{}", seq % dids), &format!("app.bsky.feed.post/{}", seq), &msg).unwrap();
+    }
+    writer.finalize_segment().unwrap();
+
+    let bin = unsafe { Mmap::map(&File::open(dir.join("s0_0.bin")).unwrap()).unwrap() };
+    let idx = unsafe { Mmap::map(&File::open(dir.join("s0_0.idx")).unwrap()).unwrap() };
+    Segment::new(0, bin, idx)
+}
+
+/// Each thread walks its own slice of the index so readers hit different clusters.
+fn run_readers(segment: &Arc<Segment>, cache: &Arc<ClusterCache>, lock: Option<&Arc<Mutex<()>>>) {
+    let count = segment.msg_count();
+    let handles: Vec<_> = (0..THREADS).map(|t| {
+        let segment = Arc::clone(segment);
+        let cache = Arc::clone(cache);
+        let lock = lock.cloned();
+        thread::spawn(move || {
+            for i in 0..READS_PER_THREAD {
+                let index = ((t * 7919 + i) % count) as u64;
+                let _guard = lock.as_ref().map(|l| l.lock().unwrap());
+                segment.get_decompressed_message_by_index(index, None, &cache).unwrap();
+            }
+        })
+    }).collect();
+    for h in handles {
+        h.join().unwrap();
+    }
+}
+
+fn bench_cluster_cache(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let segment = Arc::new(build_segment(dir.path(), 128));
+    let cache = Arc::new(ClusterCache::default());
+    // Warm the cache so both runs measure lookups, not decompression
+    run_readers(&segment, &cache, None);
+    let lock = Arc::new(Mutex::new(()));
+
+    let mut group = c.benchmark_group("concurrent_cached_reads");
+    group.throughput(Throughput::Elements((THREADS * READS_PER_THREAD) as u64));
+    group.bench_function("per_segment_mutex", |b| b.iter(|| run_readers(&segment, &cache, Some(&lock))));
+    group.bench_function("sharded", |b| b.iter(|| run_readers(&segment, &cache, None)));
+    group.finish();
+}
+
+criterion_group!(benches, bench_cluster_cache);
+criterion_main!(benches);
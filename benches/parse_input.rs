@@ -0,0 +1,147 @@
+//! parse_input over the captured frames in tests/fixtures, with a per-phase breakdown.
+//! Run with: cargo bench --features profiling --bench parse_input
+//! Point PARSE_BENCH_CAPTURE at another `<name>` (path without extension) to use a real capture.
+//! The `top_level_sig` group compares parse_input with parse_for_verify on the same commits
+//! with their signature copied up into the payload. The `peek` group compares peek_frame, the
+//! header-only first stage, with it followed by parse_full and with parse_input.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use did_mmap_cache::parser::core::{
+    parse_cbor_len, parse_for_verify, parse_full, parse_input, parse_input_timed, peek_frame, skip_cbor_value, ParseTimings,
+};
+use std::fs;
+use std::path::PathBuf;
+
+/// Frames of `<capture>.raw`, split by the u32 lengths in `<capture>.sizes`.
+fn load_frames() -> Vec<Vec<u8>> {
+    let capture = std::env::var("PARSE_BENCH_CAPTURE")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/synthetic_sample"));
+    let raw = fs::read(capture.with_extension("raw")).expect("capture .raw");
+    let sizes = fs::read(capture.with_extension("sizes")).expect("capture .sizes");
+    let mut offset = 0;
+    sizes
+        .chunks_exact(4)
+        .map(|c| {
+            let size = u32::from_le_bytes(c.try_into().unwrap()) as usize;
+            offset += size;
+            raw[offset - size..offset].to_vec()
+        })
+        .collect()
+}
+
+fn head(major: u8, len: usize, out: &mut Vec<u8>) {
+    let m = major << 5;
+    if len < 24 {
+        out.push(m | len as u8);
+    } else {
+        out.extend_from_slice(&[m | 24, len as u8]);
+    }
+}
+
+/// `frame` with a top-level `sig` payload field, for the commits that have a signature.
+fn with_top_level_sig(frame: &[u8]) -> Option<Vec<u8>> {
+    let sig = parse_input(frame).ok()?.signature?.to_vec();
+    let header_end = skip_cbor_value(frame, 0).ok()?;
+    let (pairs, first_key) = parse_cbor_len(frame, header_end).ok()?;
+    let mut out = frame[..header_end].to_vec();
+    head(5, pairs + 1, &mut out);
+    out.extend_from_slice(b"\x63sig");
+    head(2, sig.len(), &mut out);
+    out.extend_from_slice(&sig);
+    out.extend_from_slice(&frame[first_key..]);
+    Some(out)
+}
+
+fn print_breakdown(frames: &[Vec<u8>]) {
+    const ROUNDS: usize = 1000;
+    let mut total = ParseTimings::default();
+    for _ in 0..ROUNDS {
+        for frame in frames {
+            total.accumulate(&parse_input_timed(frame).1);
+        }
+    }
+    let n = (ROUNDS * frames.len()) as u64;
+    let share = |ns: u64| ns as f64 / total.total_ns.max(1) as f64 * 100.0;
+    eprintln!("parse_input phases, mean per frame over {} frames:", n);
+    for (name, ns) in [
+        ("header", total.header_ns),
+        ("payload", total.payload_ns),
+        ("ops", total.ops_ns),
+        ("car", total.car_ns),
+        ("signature", total.signature_ns),
+        ("total", total.total_ns),
+    ] {
+        eprintln!("  {:<10} {:>8} ns  {:>5.1}%", name, ns / n, share(ns));
+    }
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let frames = load_frames();
+    print_breakdown(&frames);
+
+    let mut group = c.benchmark_group("parse_input");
+    group.throughput(Throughput::Elements(frames.len() as u64));
+    group.bench_function("plain", |b| {
+        b.iter(|| {
+            for frame in &frames {
+                black_box(parse_input(black_box(frame)).ok());
+            }
+        })
+    });
+    group.bench_function("timed", |b| {
+        b.iter(|| {
+            for frame in &frames {
+                black_box(parse_input_timed(black_box(frame)));
+            }
+        })
+    });
+    group.finish();
+
+    let signed: Vec<Vec<u8>> = frames.iter().filter_map(|f| with_top_level_sig(f)).collect();
+    let mut group = c.benchmark_group("top_level_sig");
+    group.throughput(Throughput::Elements(signed.len() as u64));
+    group.bench_function("parse_input", |b| {
+        b.iter(|| {
+            for frame in &signed {
+                black_box(parse_input(black_box(frame)).ok());
+            }
+        })
+    });
+    group.bench_function("parse_for_verify", |b| {
+        b.iter(|| {
+            for frame in &signed {
+                black_box(parse_for_verify(black_box(frame)).ok());
+            }
+        })
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("peek");
+    group.throughput(Throughput::Elements(frames.len() as u64));
+    group.bench_function("peek_frame", |b| {
+        b.iter(|| {
+            for frame in &frames {
+                black_box(peek_frame(black_box(frame)));
+            }
+        })
+    });
+    group.bench_function("peek_then_parse_full", |b| {
+        b.iter(|| {
+            for frame in &frames {
+                black_box(peek_frame(black_box(frame)).map(|peek| parse_full(peek, frame).ok()));
+            }
+        })
+    });
+    group.bench_function("parse_input", |b| {
+        b.iter(|| {
+            for frame in &frames {
+                black_box(parse_input(black_box(frame)).ok());
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
@@ -0,0 +1,10 @@
+#![no_main]
+// cargo +nightly fuzz run mst_node
+
+use libfuzzer_sys::fuzz_target;
+use did_mmap_cache::mst::MstNode;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = MstNode::from_bytes(data);
+    let _ = MstNode::get_root_from_commit(data);
+});
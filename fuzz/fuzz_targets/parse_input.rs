@@ -0,0 +1,13 @@
+#![no_main]
+// cargo +nightly fuzz run parse_input
+
+use libfuzzer_sys::fuzz_target;
+use did_mmap_cache::parser::core::parse_input;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(envelope) = parse_input(data) {
+        // Touch the borrowed slices so out-of-range views would be caught by ASan
+        let _ = envelope.commit.map(|c| c.len());
+        let _ = envelope.signature.map(|s| s.len());
+    }
+});
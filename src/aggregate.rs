@@ -0,0 +1,291 @@
+//! Archive stage for `sovereign_aggregator`'s siege mode.
+//!
+//! A commit can reach the aggregator from more than one of its connections (a relay in the
+//! list, the same PDS under two URLs, ...), so `SiegeArchiver` keeps only the first copy of
+//! each frame. First sightings are queued to a dedicated writer thread, which parses them for
+//! the DID, record path and sequence and appends them to a `MultiShardArchive`. The async
+//! accept loop only hashes and enqueues, so it never waits on the archive's shard locks.
+//! Given a DID cache (`new_verifying`), the writer also checks each commit's signature and
+//! archives only commits that verify.
+
+use crate::archive::MultiShardArchive;
+use crate::mmap_did_cache::MmapDidCache;
+use crate::parser::core::parse_input;
+use crate::verify::verify_commit_detailed;
+use dashmap::DashMap;
+use fastbloom::BloomFilter;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+/// Hashes remembered exactly; older ones are only in the bloom filter.
+pub const DEDUP_WINDOW: usize = 500_000;
+
+const BLOOM_BITS: usize = 8 * 1024 * 1024; // 1MB Bloom Filter
+const BLOOM_HASHES: u32 = 4;
+const BLOOM_MAGIC: &[u8; 4] = b"SGBF";
+// magic, seed u128, hashes u32, then the filter's u64 words
+const BLOOM_HEADER_SIZE: usize = 24;
+/// A restored filter estimated to be wrong more often than this is kept as a fast "never
+/// seen" check only; trusting its hits would drop too many new frames.
+const MAX_RESTORED_FALSE_POS: f64 = 0.01;
+
+/// Frame hashes seen so far. The bloom filter answers "never seen" without touching the
+/// exact set, which settles bloom hits (false positives included) for the recent window.
+///
+/// Only the bloom filter survives a restart (`save_bloom`/`load_bloom`); the exact set starts
+/// empty. Until the window has turned over once, a hit in a restored filter is taken as a
+/// duplicate, so a false positive can drop a new frame in that stretch. After that, the
+/// filter goes back to being a fast path for the exact set. Persistence is best-effort:
+/// frames seen after the last save are counted again.
+pub struct FrameDedup {
+    bloom: BloomFilter,
+    // Keys the bloom hashes; saved with the bits so a reloaded filter hashes the same way
+    seed: u128,
+    recent: HashSet<[u8; 32]>,
+    order: VecDeque<[u8; 32]>,
+    window: usize,
+    // New hashes to take before the restored filter's window would have slid past
+    carried: usize,
+}
+
+impl FrameDedup {
+    pub fn new(window: usize) -> Self {
+        let seed = rand::random();
+        Self {
+            bloom: BloomFilter::with_num_bits(BLOOM_BITS).seed(&seed).hashes(BLOOM_HASHES),
+            seed,
+            recent: HashSet::new(),
+            order: VecDeque::new(),
+            window,
+            carried: 0,
+        }
+    }
+
+    /// Writes the bloom filter and its seed to `path`.
+    pub fn save_bloom(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let words = self.bloom.as_slice();
+        let mut buf = Vec::with_capacity(BLOOM_HEADER_SIZE + words.len() * 8);
+        buf.extend_from_slice(BLOOM_MAGIC);
+        buf.extend_from_slice(&self.seed.to_le_bytes());
+        buf.extend_from_slice(&self.bloom.num_hashes().to_le_bytes());
+        for word in words {
+            buf.extend_from_slice(&word.to_le_bytes());
+        }
+        // Written aside and renamed, so a crash mid-save leaves the previous filter
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, buf)?;
+        fs::rename(&tmp, path)
+    }
+
+    /// Replaces the bloom filter with one written by `save_bloom`. Returns false, changing
+    /// nothing, if the file doesn't exist.
+    pub fn load_bloom(&mut self, path: impl AsRef<Path>) -> io::Result<bool> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        if data.len() < BLOOM_HEADER_SIZE || &data[..4] != BLOOM_MAGIC {
+            return Err(invalid("not a saved bloom filter"));
+        }
+        let body = &data[BLOOM_HEADER_SIZE..];
+        if body.is_empty() || body.len() % 8 != 0 {
+            return Err(invalid("bloom filter bits truncated"));
+        }
+        let seed = u128::from_le_bytes(data[4..20].try_into().unwrap());
+        let hashes = u32::from_le_bytes(data[20..24].try_into().unwrap());
+        if hashes == 0 {
+            return Err(invalid("bloom filter has no hash functions"));
+        }
+        let words: Vec<u64> = body.chunks_exact(8).map(|w| u64::from_le_bytes(w.try_into().unwrap())).collect();
+
+        let set: u64 = words.iter().map(|w| w.count_ones() as u64).sum();
+        let false_pos = (set as f64 / (words.len() * 64) as f64).powi(hashes as i32);
+        self.bloom = BloomFilter::from_vec(words).seed(&seed).hashes(hashes);
+        self.seed = seed;
+        self.carried = if false_pos <= MAX_RESTORED_FALSE_POS { self.window } else { 0 };
+        Ok(true)
+    }
+
+    /// True the first time `hash` is offered within the window.
+    pub fn insert(&mut self, hash: &[u8; 32]) -> bool {
+        if self.bloom.contains(hash) && (self.carried > 0 || self.recent.contains(hash)) {
+            return false;
+        }
+        self.carried = self.carried.saturating_sub(1);
+        self.bloom.insert(hash);
+        self.recent.insert(*hash);
+        self.order.push_back(*hash);
+        if self.order.len() > self.window {
+            if let Some(old) = self.order.pop_front() {
+                self.recent.remove(&old);
+            }
+        }
+        true
+    }
+}
+
+/// Counters shared between the siege loop and the writer thread.
+#[derive(Default)]
+pub struct SiegeStats {
+    /// Frames written to the archive
+    pub archived: AtomicU64,
+    /// Frames queued for the writer but not yet handled
+    pub pending: AtomicU64,
+    /// First sightings that didn't parse or carry no DID
+    pub skipped: AtomicU64,
+    /// Commits not archived because their signature didn't verify or the cache has no key
+    pub unverified: AtomicU64,
+    /// Last sequence number archived from each endpoint URL
+    pub cursors: DashMap<String, u64>,
+}
+
+impl SiegeStats {
+    /// Seeds `cursors` from a file written by `save_cursors`. A missing file is an empty map.
+    pub fn load_cursors(&self, path: impl AsRef<Path>) -> io::Result<usize> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        let map: HashMap<String, u64> = serde_json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let count = map.len();
+        for (url, seq) in map {
+            self.cursors.insert(url, seq);
+        }
+        Ok(count)
+    }
+
+    /// Writes `cursors` as a JSON object of URL -> sequence, like `pds_cursors.json`.
+    pub fn save_cursors(&self, path: impl AsRef<Path>) -> io::Result<usize> {
+        let map: HashMap<String, u64> = self.cursors.iter().map(|e| (e.key().clone(), *e.value())).collect();
+        let json = serde_json::to_string_pretty(&map).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        fs::write(path, json)?;
+        Ok(map.len())
+    }
+}
+
+/// Deduplicates frames and hands first sightings to the archive writer thread.
+pub struct SiegeArchiver {
+    dedup: FrameDedup,
+    tx: Option<Sender<(Arc<String>, Vec<u8>)>>,
+    stats: Arc<SiegeStats>,
+    writer: Option<JoinHandle<()>>,
+    archive: Option<Arc<MultiShardArchive>>,
+}
+
+impl SiegeArchiver {
+    /// With `archive` set to `None` (a dry run) frames are still deduplicated and parsed, so
+    /// cursors keep advancing, but nothing is written.
+    pub fn new(archive: Option<Arc<MultiShardArchive>>, stats: Arc<SiegeStats>) -> Self {
+        Self::new_verifying(archive, stats, None)
+    }
+
+    /// `new` that, with a cache, archives a `#commit` only if its signature verifies against
+    /// the key cached for its repo. Other events are archived as before. Rejected commits
+    /// still advance their endpoint's cursor.
+    pub fn new_verifying(archive: Option<Arc<MultiShardArchive>>, stats: Arc<SiegeStats>, cache: Option<MmapDidCache>) -> Self {
+        let (tx, rx) = mpsc::channel::<(Arc<String>, Vec<u8>)>();
+        let writer_archive = archive.clone();
+        let writer_stats = Arc::clone(&stats);
+        let writer = thread::Builder::new()
+            .name("siege-archive".to_string())
+            .spawn(move || {
+                let mut next_seq = writer_archive.as_ref().and_then(|a| a.max_seq()).map_or(0, |s| s + 1);
+                for (url, frame) in rx {
+                    writer_stats.pending.fetch_sub(1, Ordering::Relaxed);
+                    let Ok(envelope) = parse_input(&frame) else {
+                        writer_stats.skipped.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    };
+                    let Some(did) = envelope.did.and_then(|d| std::str::from_utf8(d).ok()) else {
+                        writer_stats.skipped.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    };
+                    if let Some(seq) = envelope.sequence {
+                        writer_stats.cursors.insert(url.to_string(), seq);
+                    }
+                    if let Some(cache) = &cache {
+                        let verified = envelope.t != Some(b"#commit")
+                            || cache.get(did).is_some_and(|(key, key_type)| verify_commit_detailed(&envelope, &key, key_type).is_ok());
+                        if !verified {
+                            writer_stats.unverified.fetch_add(1, Ordering::Relaxed);
+                            continue;
+                        }
+                    }
+                    let Some(archive) = &writer_archive else { continue };
+
+                    let mut primary_path = String::new();
+                    for op in &envelope.ops {
+                        if op.action == "delete" {
+                            archive.delete_by_path(did, op.path);
+                        } else if primary_path.is_empty() {
+                            primary_path = op.path.to_string();
+                        }
+                    }
+                    // The envelope borrows the frame, which is moved into the archive
+                    let did = did.to_string();
+                    drop(envelope);
+                    archive.ingest(next_seq, &did, primary_path, frame);
+                    next_seq += 1;
+                    writer_stats.archived.fetch_add(1, Ordering::Relaxed);
+                }
+            })
+            .expect("failed to spawn archive writer");
+
+        Self {
+            dedup: FrameDedup::new(DEDUP_WINDOW),
+            tx: Some(tx),
+            stats,
+            writer: Some(writer),
+            archive,
+        }
+    }
+
+    pub fn stats(&self) -> &Arc<SiegeStats> {
+        &self.stats
+    }
+
+    /// `FrameDedup::save_bloom` for the archiver's dedup set.
+    pub fn save_bloom(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.dedup.save_bloom(path)
+    }
+
+    /// `FrameDedup::load_bloom` for the archiver's dedup set.
+    pub fn load_bloom(&mut self, path: impl AsRef<Path>) -> io::Result<bool> {
+        self.dedup.load_bloom(path)
+    }
+
+    /// Queues `frame` for the archive if it's the first copy seen. Never blocks.
+    pub fn offer(&mut self, url: &Arc<String>, hash: &[u8; 32], frame: &[u8]) -> bool {
+        if !self.dedup.insert(hash) {
+            return false;
+        }
+        if let Some(tx) = &self.tx {
+            self.stats.pending.fetch_add(1, Ordering::Relaxed);
+            if tx.send((Arc::clone(url), frame.to_vec())).is_err() {
+                self.stats.pending.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+        true
+    }
+
+    /// Lets the writer drain its queue, then flushes the archive.
+    pub fn finish(mut self) {
+        self.tx = None;
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+        if let Some(archive) = &self.archive {
+            archive.shutdown();
+        }
+    }
+}
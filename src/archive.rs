@@ -1,21 +1,70 @@
 use memmap2::Mmap;
-use std::collections::{BTreeMap, HashMap};
+use std::borrow::Cow;
+use std::collections::{hash_map, BTreeMap, HashMap, HashSet, VecDeque};
 use std::fs::{self, File};
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex, RwLock};
-use crossbeam_channel::{Sender, unbounded};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use crossbeam_channel::{Receiver, Sender, unbounded};
 use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use serde::{Deserialize, Serialize};
+
+pub mod checkout;
+pub mod cluster;
+pub mod cluster_cache;
+pub mod consistency;
+pub mod dataset;
+pub mod gaps;
+pub mod heads;
+pub mod iter;
+pub mod path_hash;
+pub mod pending;
+pub mod query;
+pub mod source;
+pub mod stats;
+pub mod sync;
+pub mod wal;
+
+pub use cluster::{ClusterEntry, ClusterReader, ClusterWriter};
+use cluster_cache::ClusterCache;
+use consistency::ConsistencyReport;
+pub use checkout::{checkout_did, CheckoutHead, RepoCheckout, RepoRecord};
+pub use dataset::{DatasetManifest, VerifyManifestReport};
+pub use gaps::{GapLog, GapRecord};
+pub use iter::{ShardIter, ShardRef};
+pub use path_hash::PathHash;
+pub use pending::PendingIndex;
+pub use source::{LocalMmapSource, SegmentSource};
+pub use stats::CollStat;
+pub use wal::WalSync;
+use heads::{HeadIndex, HeadInfo};
 
 pub struct SegmentPayload {
     pub start_seq: u64,
     pub max_seq: u64,
     pub count: u64,
-    pub pending: HashMap<String, Vec<(u64, String, Vec<u8>)>>,
+    /// Shared with `PendingIndex` while the segment is being written
+    pub pending: Arc<pending::Messages>,
     pub shard_dir: PathBuf,
     pub shard_id: usize,
+    pub hash_alg: MerkleAlgorithm,
+    pub path_hash: PathHash,
+    /// Write the `.mhash` sidecar for this segment
+    pub message_hashes: bool,
+    /// Parse each DID's newest message into `heads.bin`
+    pub track_heads: bool,
+    /// Write the `.stats` sidecar for this segment
+    pub collection_stats: bool,
+    pub compression: CompressionConfig,
+    /// Sealed journal holding these messages, removed once the segment is written
+    pub wal: Option<PathBuf>,
 }
 
+/// Size of `tombstones.bin`: 512MB = ~4 Billion messages support (Future-proof)
+const TOMBSTONE_FILE_SIZE: u64 = 512 * 1024 * 1024;
+
 /// Persistent bitset for deleted messages.
 pub struct TombstoneStore {
     mmap: memmap2::MmapMut,
@@ -31,10 +80,8 @@ impl TombstoneStore {
             .open(path)?;
         
         let metadata = file.metadata()?;
-        // 512MB = ~4 Billion messages support (Future-proof)
-        let size = 512 * 1024 * 1024;
-        if metadata.len() < size {
-            file.set_len(size)?;
+        if metadata.len() < TOMBSTONE_FILE_SIZE {
+            crate::platform::set_len_sparse(&file, TOMBSTONE_FILE_SIZE)?;
         }
         
         let mmap = unsafe { memmap2::MmapMut::map_mut(&file)? };
@@ -48,147 +95,958 @@ impl TombstoneStore {
         (self.mmap[byte_idx] & (1 << bit_idx)) != 0
     }
 
-    pub fn mark_deleted(&mut self, seq: u64) {
-        let byte_idx = (seq / 8) as usize;
-        let bit_idx = (seq % 8) as u8;
-        if byte_idx < self.mmap.len() {
-            self.mmap[byte_idx] |= 1 << bit_idx;
+    /// Seqs the store can hold; `mark_deleted` refuses anything at or past this.
+    pub fn capacity(&self) -> u64 {
+        self.mmap.len() as u64 * 8
+    }
+
+    fn check_seq(&self, seq: u64) -> Result<(), TombstoneError> {
+        if seq >= self.capacity() {
+            return Err(TombstoneError::OutOfRange { seq, capacity: self.capacity() });
+        }
+        Ok(())
+    }
+
+    /// Marks one seq deleted. The page is left for the OS to write back; the bulk
+    /// operations flush.
+    pub fn mark_deleted(&mut self, seq: u64) -> Result<(), TombstoneError> {
+        self.check_seq(seq)?;
+        self.mmap[(seq / 8) as usize] |= 1 << (seq % 8);
+        Ok(())
+    }
+
+    /// Marks every seq in `start..end` deleted and flushes the touched bytes. Nothing is
+    /// marked if `end` is past `capacity`. Returns how many of the seqs were new tombstones.
+    pub fn mark_deleted_range(&mut self, start: u64, end: u64) -> Result<u64, TombstoneError> {
+        if start >= end {
+            return Ok(0);
+        }
+        self.check_seq(end - 1)?;
+        let before = self.count_deleted_in(start, end);
+
+        let (first, last) = ((start / 8) as usize, ((end - 1) / 8) as usize);
+        let head = 0xFFu8 << (start % 8);
+        let tail = 0xFFu8 >> (7 - (end - 1) % 8);
+        if first == last {
+            self.mmap[first] |= head & tail;
+        } else {
+            self.mmap[first] |= head;
+            self.mmap[first + 1..last].fill(0xFF);
+            self.mmap[last] |= tail;
+        }
+        self.mmap.flush_range(first, last - first + 1)?;
+        Ok(end - start - before)
+    }
+
+    /// Marks each of `seqs` deleted and flushes once. Nothing is marked if any seq is past
+    /// `capacity`. Returns how many were new tombstones.
+    pub fn mark_deleted_many(&mut self, seqs: &[u64]) -> Result<u64, TombstoneError> {
+        let (Some(&min), Some(&max)) = (seqs.iter().min(), seqs.iter().max()) else { return Ok(0) };
+        self.check_seq(max)?;
+        let mut added = 0;
+        for &seq in seqs {
+            let (byte, bit) = ((seq / 8) as usize, 1u8 << (seq % 8));
+            added += u64::from(self.mmap[byte] & bit == 0);
+            self.mmap[byte] |= bit;
+        }
+        let (first, last) = ((min / 8) as usize, (max / 8) as usize);
+        self.mmap.flush_range(first, last - first + 1)?;
+        Ok(added)
+    }
+
+    /// Number of seqs in `start..end` marked deleted. Seqs past `capacity` count as live.
+    pub fn count_deleted_in(&self, start: u64, end: u64) -> u64 {
+        let end = end.min(self.capacity());
+        if start >= end {
+            return 0;
+        }
+        let (first, last) = ((start / 8) as usize, ((end - 1) / 8) as usize);
+        let head = 0xFFu8 << (start % 8);
+        let tail = 0xFFu8 >> (7 - (end - 1) % 8);
+        if first == last {
+            return (self.mmap[first] & head & tail).count_ones() as u64;
+        }
+        let middle = &self.mmap[first + 1..last];
+        let words = middle.chunks_exact(8);
+        let rest: u64 = words.remainder().iter().map(|b| b.count_ones() as u64).sum();
+        let whole: u64 = words.map(|w| u64::from_ne_bytes(w.try_into().unwrap()).count_ones() as u64).sum();
+        (self.mmap[first] & head).count_ones() as u64 + whole + rest + (self.mmap[last] & tail).count_ones() as u64
+    }
+
+    /// Highest seq marked deleted, if any.
+    pub fn highest_deleted(&self) -> Option<u64> {
+        let bits = trimmed_bitset(&self.mmap);
+        let byte_idx = bits.len().checked_sub(1)?;
+        Some(byte_idx as u64 * 8 + 7 - bits[byte_idx].leading_zeros() as u64)
+    }
+
+    /// Number of seqs marked deleted.
+    pub fn count(&self) -> u64 {
+        trimmed_bitset(&self.mmap).iter().map(|b| b.count_ones() as u64).sum()
+    }
+
+    /// ORs another store's bitset (or a prefix of it) into this one.
+    /// Returns how many tombstones were new here.
+    pub fn merge(&mut self, bits: &[u8]) -> u64 {
+        let mut added = 0;
+        for (dst, &src) in self.mmap.iter_mut().zip(bits) {
+            added += (src & !*dst).count_ones() as u64;
+            *dst |= src;
         }
+        added
     }
 }
 
+/// Why a seq couldn't be tombstoned.
+#[derive(Debug)]
+pub enum TombstoneError {
+    /// `seq` is past the `capacity` seqs `tombstones.bin` holds
+    OutOfRange { seq: u64, capacity: u64 },
+    /// Flushing the bitset failed, or the archive has no tombstone store
+    Io(io::Error),
+}
+
+impl std::fmt::Display for TombstoneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TombstoneError::OutOfRange { seq, capacity } => {
+                write!(f, "sequence {} is past the tombstone store's capacity of {} seqs", seq, capacity)
+            }
+            TombstoneError::Io(e) => write!(f, "tombstone write failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TombstoneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TombstoneError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for TombstoneError {
+    fn from(e: io::Error) -> Self {
+        TombstoneError::Io(e)
+    }
+}
+
+impl From<TombstoneError> for io::Error {
+    fn from(e: TombstoneError) -> Self {
+        match e {
+            TombstoneError::Io(e) => e,
+            e => io::Error::new(io::ErrorKind::InvalidInput, e),
+        }
+    }
+}
+
+/// The store behind an archive's `tombstones` field; an error if opening it failed.
+fn tombstone_store(ts: &Option<Arc<RwLock<TombstoneStore>>>) -> Result<&Arc<RwLock<TombstoneStore>>, TombstoneError> {
+    ts.as_ref().ok_or_else(|| TombstoneError::Io(io::Error::new(io::ErrorKind::NotFound, "archive has no tombstone store")))
+}
+
+/// A tombstone bitset up to its last set byte.
+pub(crate) fn trimmed_bitset(bits: &[u8]) -> &[u8] {
+    // The file is mostly zero pages; skip those a page at a time before looking at bytes
+    let zero_page = [0u8; 4096];
+    let mut end = bits.len();
+    while end > 0 {
+        let start = end.saturating_sub(zero_page.len());
+        if bits[start..end] != zero_page[..end - start] {
+            break;
+        }
+        end = start;
+    }
+    let end = bits[..end].iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+    &bits[..end]
+}
+
 use zstd;
-use crate::mst::builder::MerkleTree;
+use crate::mst::builder::MerkleAlgorithm;
+
+/// Default cap on how large a single cluster may decompress to. Real clusters are a few
+/// hundred KB; anything near this is corruption or a zstd bomb from an untrusted source.
+pub const DEFAULT_MAX_DECOMPRESSED_CLUSTER_BYTES: usize = 64 * 1024 * 1024;
+
+/// A cluster (or the message requested from it) exceeded the decompression cap.
+/// Surfaced as an `io::Error` of kind `InvalidData` wrapping this value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecompressionLimitExceeded {
+    pub limit: usize,
+}
+
+impl std::fmt::Display for DecompressionLimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cluster exceeds decompression limit of {} bytes", self.limit)
+    }
+}
+
+impl std::error::Error for DecompressionLimitExceeded {}
+
+impl From<DecompressionLimitExceeded> for io::Error {
+    fn from(e: DecompressionLimitExceeded) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// Why a read of one seq (`get_message_by_seq`, `get_message_streaming`,
+/// `get_raw_cluster_at_seq`) returned nothing.
+#[derive(Debug)]
+pub enum ArchiveError {
+    /// Before the first stored seq: pruned, or never archived here
+    NotFound,
+    /// Deleted with `mark_deleted`; later seqs may still be stored
+    Tombstoned { seq: u64 },
+    /// Between stored seqs, but nothing was ever written at `seq`
+    Gap { seq: u64 },
+    /// Past the last stored seq, i.e. at the head of the archive (or the archive is empty)
+    OutOfRange,
+    /// The index points outside the segment data, or a cluster doesn't decode into messages
+    Corrupt { detail: String },
+    /// Reading or decompressing the cluster failed
+    Io(io::Error),
+}
+
+impl ArchiveError {
+    /// Classifies a seq nothing is stored at against the stored range `min..=max`.
+    fn missing(seq: u64, min: Option<u64>, max: Option<u64>) -> Self {
+        match (min, max) {
+            (Some(min), _) if seq < min => ArchiveError::NotFound,
+            (_, Some(max)) if seq <= max => ArchiveError::Gap { seq },
+            _ => ArchiveError::OutOfRange,
+        }
+    }
+
+    fn corrupt(detail: impl Into<String>) -> Self {
+        ArchiveError::Corrupt { detail: detail.into() }
+    }
+}
+
+impl std::fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArchiveError::NotFound => f.write_str("sequence precedes the archive"),
+            ArchiveError::Tombstoned { seq } => write!(f, "sequence {} tombstoned", seq),
+            ArchiveError::Gap { seq } => write!(f, "sequence {} was never written", seq),
+            ArchiveError::OutOfRange => f.write_str("sequence past the end of the archive"),
+            ArchiveError::Corrupt { detail } => write!(f, "archive corrupt: {}", detail),
+            ArchiveError::Io(e) => write!(f, "archive read failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ArchiveError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ArchiveError {
+    fn from(e: io::Error) -> Self {
+        ArchiveError::Io(e)
+    }
+}
+
+impl From<DecompressionLimitExceeded> for ArchiveError {
+    fn from(e: DecompressionLimitExceeded) -> Self {
+        ArchiveError::Io(e.into())
+    }
+}
+
+/// Missing seqs become `NotFound` and corruption `InvalidData`, so `?` into `io::Result` keeps
+/// working; callers that care match on the `ArchiveError` instead.
+impl From<ArchiveError> for io::Error {
+    fn from(e: ArchiveError) -> Self {
+        match e {
+            ArchiveError::Io(e) => e,
+            e @ ArchiveError::Corrupt { .. } => io::Error::new(io::ErrorKind::InvalidData, e),
+            other => io::Error::new(io::ErrorKind::NotFound, other),
+        }
+    }
+}
+
+/// Decompresses a cluster, reading at most `limit` bytes of output.
+fn decompress_bounded(compressed: &[u8], dict: Option<&[u8]>, limit: usize) -> io::Result<Vec<u8>> {
+    use std::io::Read;
+    let mut out = Vec::new();
+    let cap = limit as u64 + 1;
+    if let Some(d) = dict {
+        zstd::stream::read::Decoder::with_dictionary(compressed, d)?.take(cap).read_to_end(&mut out)?;
+    } else {
+        zstd::stream::read::Decoder::new(compressed)?.take(cap).read_to_end(&mut out)?;
+    }
+    if out.len() > limit {
+        return Err(DecompressionLimitExceeded { limit }.into());
+    }
+    Ok(out)
+}
+
+/// Decodes only `range` of a cluster: skips up to `range.start`, reads `range.len()` bytes and
+/// stops, so the cluster is never held in full. `window_log_max` caps the decoder's window
+/// buffer, which is what such a read actually allocates.
+fn decompress_range(compressed: &[u8], dict: Option<&[u8]>, range: std::ops::Range<usize>, window_log_max: u32) -> io::Result<Vec<u8>> {
+    use std::io::Read;
+    fn read_range<R: Read>(mut decoder: R, range: std::ops::Range<usize>) -> io::Result<Vec<u8>> {
+        let skipped = io::copy(&mut (&mut decoder).take(range.start as u64), &mut io::sink())?;
+        let mut out = vec![0u8; range.len()];
+        if skipped < range.start as u64 || decoder.read_exact(&mut out).is_err() {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Decompression index error"));
+        }
+        Ok(out)
+    }
+    if let Some(d) = dict {
+        let mut decoder = zstd::stream::read::Decoder::with_dictionary(compressed, d)?;
+        decoder.window_log_max(window_log_max)?;
+        read_range(decoder, range)
+    } else {
+        let mut decoder = zstd::stream::read::Decoder::with_buffer(compressed)?;
+        decoder.window_log_max(window_log_max)?;
+        read_range(decoder, range)
+    }
+}
+
+/// Splits a decompressed cluster into its `(seq, record)` pairs. See `ClusterReader`.
+pub fn split_cluster(raw: &[u8]) -> io::Result<Vec<(u64, &[u8])>> {
+    Ok(ClusterReader::new(raw)?.iter().map(|e| (e.seq, e.data)).collect())
+}
+
+/// Decompresses a cluster as stored on disk or streamed by the relay and returns its
+/// records in order. `limit` caps the decompressed size.
+pub fn decode_cluster(compressed: &[u8], dict: Option<&[u8]>, limit: usize) -> io::Result<Vec<(u64, Vec<u8>)>> {
+    let raw = decompress_bounded(compressed, dict, limit)?;
+    Ok(ClusterReader::new(&raw)?.iter().map(|e| (e.seq, e.data.to_vec())).collect())
+}
+
+// .idx layout: a header, then one record per sequence:
+// bin_off(8), c_len(4), inner_off(4), i_len(4), path_hash(8)
+// The header is the 32-byte Merkle root, then IDX_ALG_MAGIC, the root's 1-byte
+// `MerkleAlgorithm` id, a byte that is 1 if the `CompressionConfig` follows, and that config
+// as level i32, long distance u8, window log u8 (0 = zstd's choice). Indexes written before
+// the id moved into the header are the bare root; those written before the config did have
+// zeros after the id. The magic's top byte is 0xff, so it can't be a first record's bin_off.
+const IDX_HEADER_SIZE: usize = 32;
+const IDX_ALG_HEADER_SIZE: usize = 48;
+const IDX_ALG_MAGIC: [u8; 8] = *b"STEidx\x02\xff";
+const IDX_COMPRESSION_AT: usize = IDX_HEADER_SIZE + IDX_ALG_MAGIC.len() + 1;
+const IDX_RECORD_SIZE: usize = 28;
+// .pidx sidecar: one (path_hash u64, seq u64) entry per .idx record, sorted by hash then seq.
+// Segments written before it existed have no sidecar and fall back to a linear scan.
+const PATH_INDEX_ENTRY_SIZE: usize = 16;
+// .dictid sidecar: `dict_hash` (hex) of the zstd dictionary the segment was compressed with.
+// Absent for segments compressed without one, or written before it existed.
+const DICT_ID_EXT: &str = "dictid";
+// .hashalg sidecar: the `MerkleAlgorithm` id of a bare-root .idx. Only read now, for segments
+// written before the id moved into the header; a bare root without one is blake3.
+const HASH_ALG_EXT: &str = "hashalg";
+// .mhash sidecar, written when message hashes are on: one (blake3 [u8; 32], seq u64) entry per
+// stored message, sorted by hash then seq. Gaps have no entry. Without one a segment can't
+// answer `contains_hash`.
+// Not an .idx column: records are in seq order, so a hash lookup would scan every one, and
+// the 32 bytes would more than double each record for archives that never turn hashes on.
+const MESSAGE_HASH_EXT: &str = "mhash";
+const MESSAGE_HASH_ENTRY_SIZE: usize = 40;
+// .didx sidecar: one (fxhash(did) u64, seq u64) entry per stored message, sorted by hash then
+// seq. Gaps have no entry. Segments without one are searched a cluster at a time.
+const DID_INDEX_EXT: &str = "didx";
+const DID_INDEX_ENTRY_SIZE: usize = 16;
+// .zcfg sidecar: the `CompressionConfig` of an .idx whose header doesn't carry one, encoded as
+// in the header. Only read now, for segments written before the config moved into the header;
+// without either a segment was written with the default.
+const COMPRESSION_EXT: &str = "zcfg";
+const COMPRESSION_CONFIG_SIZE: usize = 6;
+// A segment `recompress_segment` is swapping: the new pair is written as .bin.new and .idx.new,
+// and renaming the latter to .idx.swap commits the swap. The old .bin sits at .bin.old until
+// the new one and the .idx are in place. See `swap_segment_files`.
+const SWAP_BIN_EXT: &str = "bin.new";
+const SWAP_IDX_EXT: &str = "idx.new";
+const SWAP_COMMIT_EXT: &str = "idx.swap";
+const SWAP_OLD_BIN_EXT: &str = "bin.old";
+/// Gives every `Segment` its own `ClusterCache` key space.
+static NEXT_SEGMENT_CACHE_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A decoded `.idx` record.
+#[derive(Debug, Clone, Copy)]
+struct IdxRecord {
+    bin_off: usize,
+    c_len: usize,
+    inner_off: usize,
+    m_len: usize,
+    path_hash: u64,
+}
+
+impl IdxRecord {
+    fn parse(rec: &[u8]) -> Self {
+        let u64_at = |o: usize| u64::from_le_bytes(rec[o..o + 8].try_into().unwrap());
+        let u32_at = |o: usize| u32::from_le_bytes(rec[o..o + 4].try_into().unwrap()) as usize;
+        IdxRecord {
+            bin_off: u64_at(0) as usize,
+            c_len: u32_at(8),
+            inner_off: u32_at(12),
+            m_len: u32_at(16),
+            path_hash: u64_at(20),
+        }
+    }
+
+    /// Byte range of the compressed cluster, if it fits inside a `bin_len`-byte file.
+    fn cluster_range(&self, bin_len: usize) -> Option<std::ops::Range<usize>> {
+        let end = self.bin_off.checked_add(self.c_len)?;
+        (end <= bin_len).then_some(self.bin_off..end)
+    }
+
+    /// Byte range of the message inside a decompressed cluster of `cluster_len` bytes.
+    fn message_range(&self, cluster_len: usize) -> Option<std::ops::Range<usize>> {
+        let end = self.inner_off.checked_add(self.m_len)?;
+        (end <= cluster_len).then_some(self.inner_off..end)
+    }
+}
 
 /// A single immutable archive segment.
 /// Stores a contiguous range of firehose messages, clustered by DID for max compression.
 pub struct Segment {
     pub start_seq: u64,
-    pub bin_mmap: Mmap,
-    pub idx_mmap: Mmap,
+    // The .idx and clusters: mapped files, or with `remote-segments`, object storage
+    storage: Box<dyn SegmentSource>,
     pub root_hash: [u8; 32],
-    // Simple cache for the last decompressed cluster to avoid redundant work
-    cluster_cache: Mutex<HashMap<usize, Arc<Vec<u8>>>>,
+    // Keys this segment's clusters in a `ClusterCache`; unique per instance
+    cache_id: u64,
+    max_decompressed: usize,
+    path_index: Option<Mmap>,
+    message_hashes: Option<Mmap>,
+    did_index: Option<Mmap>,
+    /// `dict_hash` of the dictionary this segment was compressed with, from its `.dictid` sidecar
+    pub dict_hash: Option<String>,
+    /// `MerkleAlgorithm` id of `root_hash`, from the `.idx` header, or a legacy `.hashalg` sidecar
+    /// (0, blake3, without either)
+    pub hash_alg_id: u8,
+    // Where the .idx records start: past the root, and past the algorithm id if the header has one
+    records_at: usize,
+    /// What the clusters were compressed with, from the `.idx` header, or a legacy `.zcfg`
+    /// sidecar (the default without either)
+    pub compression: CompressionConfig,
+    /// `PathHash` id of its path hashes, from its `.phash` sidecar (0, FxHash, without one)
+    pub path_hash_id: u8,
+    collection_stats: Option<HashMap<String, CollStat>>,
+    // The .bin this was mapped from by `SegmentedArchive::refresh`
+    source: Option<PathBuf>,
+}
+
+/// Hex blake3 of a zstd dictionary; identifies it in `.dictid` sidecars and the relay protocol.
+pub fn dict_hash(dict: &[u8]) -> String {
+    hex::encode(blake3::hash(dict).as_bytes())
+}
+
+/// zstd settings clusters are compressed with. The default (level 3, no long-distance
+/// matching) is what every segment was written with before this was configurable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompressionConfig {
+    pub level: i32,
+    /// Long-distance matching: finds repeats further back than the level's match window
+    pub long_distance: bool,
+    /// Match window as a power of two; None leaves it to zstd. At most `MAX_WINDOW_LOG`.
+    pub window_log: Option<u32>,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig { level: 3, long_distance: false, window_log: None }
+    }
+}
+
+impl CompressionConfig {
+    /// Largest window a stock zstd decoder accepts without raising its limit. Readers decode
+    /// with plain decoders, so nothing is written with a larger one.
+    pub const MAX_WINDOW_LOG: u32 = 27;
+
+    /// InvalidInput for a level zstd doesn't have or a window past `MAX_WINDOW_LOG`.
+    pub fn validate(&self) -> io::Result<()> {
+        if !zstd::compression_level_range().contains(&self.level) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("zstd level {} is out of range", self.level)));
+        }
+        if let Some(log) = self.window_log {
+            if !(10..=Self::MAX_WINDOW_LOG).contains(&log) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("window log {} is outside 10..={}", log, Self::MAX_WINDOW_LOG),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn compressor(&self, dict: Option<&[u8]>) -> io::Result<zstd::bulk::Compressor<'static>> {
+        use zstd::stream::raw::CParameter;
+        self.validate()?;
+        let mut compressor = match dict {
+            Some(d) => zstd::bulk::Compressor::with_dictionary(self.level, d)?,
+            None => zstd::bulk::Compressor::new(self.level)?,
+        };
+        if self.long_distance {
+            compressor.set_parameter(CParameter::EnableLongDistanceMatching(true))?;
+        }
+        if let Some(log) = self.window_log {
+            compressor.set_parameter(CParameter::WindowLog(log))?;
+        }
+        Ok(compressor)
+    }
+
+    fn to_bytes(self) -> [u8; COMPRESSION_CONFIG_SIZE] {
+        let mut out = [0u8; COMPRESSION_CONFIG_SIZE];
+        out[..4].copy_from_slice(&self.level.to_le_bytes());
+        out[4] = self.long_distance as u8;
+        out[5] = self.window_log.unwrap_or(0) as u8;
+        out
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let bytes: [u8; COMPRESSION_CONFIG_SIZE] = bytes.try_into().ok()?;
+        Some(CompressionConfig {
+            level: i32::from_le_bytes(bytes[..4].try_into().unwrap()),
+            long_distance: bytes[4] != 0,
+            window_log: (bytes[5] != 0).then_some(bytes[5] as u32),
+        })
+    }
 }
 
 impl Segment {
     pub fn new(start_seq: u64, bin_mmap: Mmap, idx_mmap: Mmap) -> Self {
+        Self::from_source(start_seq, Box::new(LocalMmapSource::new(bin_mmap, idx_mmap)))
+    }
+
+    /// A segment read through `storage` rather than mapped files.
+    pub fn from_source(start_seq: u64, storage: Box<dyn SegmentSource>) -> Self {
         // Load root hash from the first 32 bytes of the index
+        // A truncated header leaves the root zeroed; msg_count() then reports no records.
         let mut root_hash = [0u8; 32];
-        if idx_mmap.len() >= 32 {
-            root_hash.copy_from_slice(&idx_mmap[0..32]);
+        let idx = storage.get_index_bytes();
+        let (records_at, header_alg) = idx_header(idx);
+        let compression = idx_compression(idx).unwrap_or_default();
+        match idx.get(..records_at) {
+            Some(header) => root_hash.copy_from_slice(&header[..IDX_HEADER_SIZE]),
+            None => tracing::warn!("Segment {} index is {} bytes, shorter than its header", start_seq, idx.len()),
         }
 
         Self {
             start_seq,
-            bin_mmap,
-            idx_mmap,
+            storage,
             root_hash,
-            cluster_cache: Mutex::new(HashMap::with_capacity(512)),
+            cache_id: NEXT_SEGMENT_CACHE_ID.fetch_add(1, Ordering::Relaxed),
+            max_decompressed: DEFAULT_MAX_DECOMPRESSED_CLUSTER_BYTES,
+            path_index: None,
+            message_hashes: None,
+            did_index: None,
+            dict_hash: None,
+            hash_alg_id: header_alg.unwrap_or_else(|| MerkleAlgorithm::default().id()),
+            records_at,
+            compression,
+            path_hash_id: PathHash::Fx.id(),
+            collection_stats: None,
+            source: None,
         }
     }
 
-    /// Verifies the integrity of the segment by checking the stored Merkle Root
-    /// against the actual message data.
-    pub fn verify_integrity(&self, dict: Option<&[u8]>) -> io::Result<bool> {
-        let msg_count = (self.idx_mmap.len() - 32) / 28;
-        let mut tree = MerkleTree::new();
-        
-        for i in 0..msg_count {
-            if let Ok(data) = self.get_decompressed_message_by_index(i as u64, dict) {
-                tree.push(&data);
-            }
+    /// The algorithm `root_hash` was built with. None for an id this build doesn't know.
+    pub fn hash_algorithm(&self) -> Option<MerkleAlgorithm> {
+        MerkleAlgorithm::from_id(self.hash_alg_id)
+    }
+
+    /// True if the `.idx` header names the algorithm, rather than leaving it to a legacy sidecar.
+    pub(crate) fn hash_alg_in_idx(&self) -> bool {
+        self.records_at == IDX_ALG_HEADER_SIZE
+    }
+
+    /// Attaches the segment's `.pidx` sidecar. One whose size doesn't match the index is ignored.
+    pub fn with_path_index(mut self, pidx_mmap: Mmap) -> Self {
+        if pidx_mmap.len() == self.msg_count() * PATH_INDEX_ENTRY_SIZE {
+            self.path_index = Some(pidx_mmap);
+        } else {
+            tracing::warn!("Segment {} path index is {} bytes for {} records, ignoring it", self.start_seq, pidx_mmap.len(), self.msg_count());
         }
-        
-        let calculated = tree.root();
-        Ok(calculated.as_bytes() == &self.root_hash)
+        self
     }
 
-    /// Finds a sequence by path hash in this segment.
-    pub fn find_seq_by_path_hash(&self, path_hash: u64) -> Option<u64> {
-        // Record size is now 28 bytes: bin_off(8), c_len(4), inner_off(4), i_len(4), path_hash(8)
-        let msg_count = (self.idx_mmap.len() - 32) / 28;
-        for i in 0..msg_count {
-            let idx_off = 32 + i * 28;
-            let hash = u64::from_le_bytes(self.idx_mmap[idx_off + 20..idx_off + 28].try_into().unwrap());
-            if hash == path_hash {
-                return Some(self.start_seq + i as u64);
-            }
+    pub fn has_path_index(&self) -> bool {
+        self.path_index.is_some()
+    }
+
+    /// Attaches the segment's `.mhash` sidecar. One with more entries than the index has
+    /// records, or a partial entry, is ignored.
+    pub fn with_message_hashes(mut self, mhash_mmap: Mmap) -> Self {
+        if mhash_mmap.len().is_multiple_of(MESSAGE_HASH_ENTRY_SIZE) && mhash_mmap.len() / MESSAGE_HASH_ENTRY_SIZE <= self.msg_count() {
+            self.message_hashes = Some(mhash_mmap);
+        } else {
+            tracing::warn!("Segment {} message hashes are {} bytes for {} records, ignoring them", self.start_seq, mhash_mmap.len(), self.msg_count());
         }
-        None
+        self
     }
 
-    /// Retrieves and decompresses a message by its relative index.
-    pub fn get_decompressed_message_by_index(
-        &self, 
-        index: u64, 
-        dict: Option<&[u8]>,
-    ) -> io::Result<Vec<u8>> {
-        // Record size is now 28 bytes: bin_off(8), c_len(4), inner_off(4), i_len(4), path_hash(8)
-        let idx_start = 32 + (index as usize) * 28;
-        let idx_end = idx_start + 28;
+    pub fn has_message_hashes(&self) -> bool {
+        self.message_hashes.is_some()
+    }
 
-        if idx_end > self.idx_mmap.len() {
-            return Err(io::Error::new(io::ErrorKind::NotFound, "Index out of bounds"));
+    /// Messages and bytes per collection, from the `.stats` sidecar. None without one.
+    pub fn collection_stats(&self) -> Option<&HashMap<String, CollStat>> {
+        self.collection_stats.as_ref()
+    }
+
+    /// Attaches the segment's `.didx` sidecar. One with more entries than the index has
+    /// records, or a partial entry, is ignored.
+    pub fn with_did_index(mut self, didx_mmap: Mmap) -> Self {
+        if didx_mmap.len().is_multiple_of(DID_INDEX_ENTRY_SIZE) && didx_mmap.len() / DID_INDEX_ENTRY_SIZE <= self.msg_count() {
+            self.did_index = Some(didx_mmap);
+        } else {
+            tracing::warn!("Segment {} DID index is {} bytes for {} records, ignoring it", self.start_seq, didx_mmap.len(), self.msg_count());
         }
+        self
+    }
 
-        let bin_off = u64::from_le_bytes(self.idx_mmap[idx_start..idx_start + 8].try_into().unwrap()) as usize;
-        let c_len = u32::from_le_bytes(self.idx_mmap[idx_start + 8..idx_start + 12].try_into().unwrap()) as usize;
-        let inner_off = u32::from_le_bytes(self.idx_mmap[idx_start + 12..idx_start + 16].try_into().unwrap()) as usize;
-        let m_len = u32::from_le_bytes(self.idx_mmap[idx_start + 16..idx_start + 20].try_into().unwrap()) as usize;
+    pub fn has_did_index(&self) -> bool {
+        self.did_index.is_some()
+    }
 
-        if m_len == 0 {
-            return Err(io::Error::new(io::ErrorKind::NotFound, "Message not found in sequence gap"));
+    /// Every stored sequence of `did`'s messages in the segment, ascending. Binary-searches
+    /// the `.didx` sidecar when there is one. Without it, one message of each cluster is
+    /// decoded for its DID, since a cluster only ever holds one DID's messages. The hash can
+    /// collide, so callers still check the DID of what they read.
+    pub fn seqs_for_did(&self, did: &str, dict: Option<&[u8]>) -> Vec<u64> {
+        let stored = |seq: u64| seq.checked_sub(self.start_seq).and_then(|i| self.record(i)).is_some_and(|r| r.m_len > 0);
+        let did_hash = heads::fx_hash(did);
+        if let Some(table) = &self.did_index {
+            let entry = |i: usize| {
+                let e = &table[i * DID_INDEX_ENTRY_SIZE..(i + 1) * DID_INDEX_ENTRY_SIZE];
+                (u64::from_le_bytes(e[..8].try_into().unwrap()), u64::from_le_bytes(e[8..].try_into().unwrap()))
+            };
+            let len = table.len() / DID_INDEX_ENTRY_SIZE;
+            let (mut lo, mut hi) = (0, len);
+            while lo < hi {
+                let mid = lo + (hi - lo) / 2;
+                if entry(mid).0 < did_hash { lo = mid + 1 } else { hi = mid }
+            }
+            return (lo..len).map(entry).take_while(|e| e.0 == did_hash).map(|e| e.1).filter(|&seq| stored(seq)).collect();
         }
 
-        // Cache check
-        {
-            let cache = self.cluster_cache.lock().unwrap();
-            if let Some(cluster) = cache.get(&bin_off) {
-                if inner_off + m_len <= cluster.len() {
-                    return Ok(cluster[inner_off..inner_off + m_len].to_vec());
+        // bin_off -> whether the cluster there is `did`'s
+        let cache = ClusterCache::default();
+        let mut clusters: HashMap<usize, bool> = HashMap::new();
+        let mut seqs = Vec::new();
+        for i in 0..self.msg_count() as u64 {
+            let Some(rec) = self.record(i).filter(|r| r.m_len > 0) else { continue };
+            let ours = match clusters.get(&rec.bin_off) {
+                Some(&ours) => ours,
+                None => {
+                    let found = self.get_decompressed_message_by_index(i, dict, &cache).ok()
+                        .and_then(|msg| crate::parser::core::peek_frame(&msg).and_then(|p| p.did).map(|d| d == did.as_bytes()));
+                    // A message that doesn't say leaves the question to the next one in the cluster
+                    match found {
+                        Some(ours) => {
+                            clusters.insert(rec.bin_off, ours);
+                            ours
+                        }
+                        None => false,
+                    }
                 }
+            };
+            if ours {
+                seqs.push(self.start_seq + i);
             }
         }
+        seqs
+    }
+
+    fn message_hash_entry(table: &[u8], i: usize) -> ([u8; 32], u64) {
+        let e = &table[i * MESSAGE_HASH_ENTRY_SIZE..(i + 1) * MESSAGE_HASH_ENTRY_SIZE];
+        (e[..32].try_into().unwrap(), u64::from_le_bytes(e[32..].try_into().unwrap()))
+    }
 
-        if bin_off + c_len > self.bin_mmap.len() {
-            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Binary mapping out of bounds"));
+    /// Lowest sequence in the segment whose message has this blake3, by binary search of the
+    /// `.mhash` sidecar. None without one.
+    pub fn contains_hash(&self, hash: &[u8; 32]) -> Option<u64> {
+        let table = self.message_hashes.as_ref()?;
+        let len = table.len() / MESSAGE_HASH_ENTRY_SIZE;
+        let (mut lo, mut hi) = (0, len);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if Self::message_hash_entry(table, mid).0 < *hash { lo = mid + 1 } else { hi = mid }
         }
+        // Entries for the hash run in seq order; take the first that points at a stored message
+        (lo..len)
+            .map(|i| Self::message_hash_entry(table, i))
+            .take_while(|(h, _)| h == hash)
+            .map(|(_, seq)| seq)
+            .find(|&seq| seq.checked_sub(self.start_seq).and_then(|i| self.record(i)).is_some_and(|r| r.m_len > 0))
+    }
 
-        let compressed_slice = &self.bin_mmap[bin_off..bin_off + c_len];
-        let mut decompressed = Vec::new();
-        if let Some(d) = dict {
-            let mut decoder = zstd::stream::read::Decoder::with_dictionary(compressed_slice, d)?;
-            std::io::copy(&mut decoder, &mut decompressed)?;
-        } else {
-            let mut decoder = zstd::stream::read::Decoder::new(compressed_slice)?;
-            std::io::copy(&mut decoder, &mut decompressed)?;
+    /// Every digest in the `.mhash` sidecar, in hash order. Empty without one.
+    pub fn message_hashes(&self) -> Vec<[u8; 32]> {
+        let Some(table) = &self.message_hashes else { return Vec::new() };
+        (0..table.len() / MESSAGE_HASH_ENTRY_SIZE).map(|i| Self::message_hash_entry(table, i).0).collect()
+    }
+
+    /// Number of index records (one per sequence slot, including gaps).
+    pub fn msg_count(&self) -> usize {
+        self.storage.get_index_bytes().len().saturating_sub(self.records_at) / IDX_RECORD_SIZE
+    }
+
+    /// Where the segment's bytes are read from.
+    pub fn storage(&self) -> &dyn SegmentSource {
+        &*self.storage
+    }
+
+    /// This segment's key in a `ClusterCache`.
+    pub fn cache_id(&self) -> u64 {
+        self.cache_id
+    }
+
+    /// Reads the index record for a relative index, rejecting anything past the end of the file.
+    fn record(&self, index: u64) -> Option<IdxRecord> {
+        let start = usize::try_from(index).ok()?
+            .checked_mul(IDX_RECORD_SIZE)?
+            .checked_add(self.records_at)?;
+        let end = start.checked_add(IDX_RECORD_SIZE)?;
+        self.storage.get_index_bytes().get(start..end).map(IdxRecord::parse)
+    }
+
+    /// Verifies the integrity of the segment by checking the stored Merkle Root
+    /// against the actual message data.
+    pub fn verify_integrity(&self, dict: Option<&[u8]>) -> io::Result<bool> {
+        let alg = self.hash_algorithm().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("segment {}: unknown merkle hash algorithm id {}", self.start_seq, self.hash_alg_id))
+        })?;
+        // A cache of its own, so walking the whole segment doesn't push out an archive's hot set
+        let cache = ClusterCache::default();
+        let leaves = (0..self.msg_count() as u64).filter_map(|i| self.get_decompressed_message_by_index(i, dict, &cache).ok());
+        Ok(alg.root_of(leaves) == self.root_hash)
+    }
+
+    /// Writes a fresh `.idx` for the segment at `bin_path`, holding seqs `start_seq..=max_seq`,
+    /// from the `.bin` alone: each cluster's header lists its seqs and lengths. For when the
+    /// `.idx` is lost or truncated, since a segment without one isn't opened.
+    ///
+    /// The `.bin` carries no paths, so path hashes come from the `.pidx` sidecar if it still
+    /// matches the seq range, and are 0 otherwise. The Merkle root is recomputed with the
+    /// algorithm the old header or a legacy `.hashalg` sidecar names. If neither survived, the
+    /// one whose root matches what's left of the old header is used, and the default without
+    /// that. The compression settings carry over the same way, from the old header or a
+    /// legacy `.zcfg` sidecar. A cluster that doesn't decode, or a seq outside
+    /// the range or stored twice, is an `InvalidData` error and nothing is written.
+    pub fn rebuild_index_from_bin(bin_path: &Path, start_seq: u64, max_seq: u64, dict: Option<&[u8]>) -> io::Result<()> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", bin_path.display(), msg));
+        if max_seq < start_seq {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("max seq {} is below start seq {}", max_seq, start_seq)));
+        }
+        if let Ok(hash) = fs::read_to_string(bin_path.with_extension(DICT_ID_EXT)) {
+            let hash = hash.trim().to_ascii_lowercase();
+            if !hash.is_empty() && dict.map(dict_hash) != Some(hash.clone()) {
+                return Err(invalid(format!("compressed with dictionary {}, not the one given", hash)));
+            }
+        }
+        let old_idx = fs::read(bin_path.with_extension("idx")).unwrap_or_default();
+        let alg_id = idx_header(&old_idx).1
+            .or_else(|| fs::read(bin_path.with_extension(HASH_ALG_EXT)).ok().and_then(|b| b.first().copied()));
+        let alg = match alg_id {
+            Some(id) => Some(MerkleAlgorithm::from_id(id).ok_or_else(|| invalid(format!("unknown merkle hash algorithm id {}", id)))?),
+            None => None,
+        };
+        let compression = idx_compression(&old_idx).unwrap_or_else(|| read_compression(&bin_path.with_extension(COMPRESSION_EXT)));
+
+        let count = (max_seq - start_seq + 1) as usize;
+        let path_hashes: HashMap<u64, u64> = fs::read(bin_path.with_extension("pidx"))
+            .ok()
+            .filter(|pidx| pidx.len() == count * PATH_INDEX_ENTRY_SIZE)
+            .map(|pidx| {
+                pidx.chunks_exact(PATH_INDEX_ENTRY_SIZE)
+                    .map(|e| (u64::from_le_bytes(e[8..].try_into().unwrap()), u64::from_le_bytes(e[..8].try_into().unwrap())))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let bin = fs::read(bin_path)?;
+        let mut records = BTreeMap::new();
+        let mut messages = HashMap::with_capacity(count);
+        let mut bin_off = 0;
+        while bin_off < bin.len() {
+            // Clusters are back-to-back zstd frames; each frame header says where it ends
+            let c_len = zstd::zstd_safe::find_frame_compressed_size(&bin[bin_off..])
+                .map_err(|_| invalid(format!("no zstd frame at offset {}", bin_off)))?;
+            let raw = decompress_bounded(&bin[bin_off..bin_off + c_len], dict, DEFAULT_MAX_DECOMPRESSED_CLUSTER_BYTES)?;
+            let cluster = ClusterReader::new(&raw).map_err(|e| invalid(format!("cluster at offset {}: {}", bin_off, e)))?;
+            for ClusterEntry { seq, offset, data } in cluster.iter() {
+                if !(start_seq..=max_seq).contains(&seq) {
+                    return Err(invalid(format!("seq {} outside {}..={}", seq, start_seq, max_seq)));
+                }
+                let path_hash = path_hashes.get(&seq).copied().unwrap_or(0);
+                if records.insert(seq, (bin_off as u64, c_len as u32, offset as u32, data.len() as u32, path_hash)).is_some() {
+                    return Err(invalid(format!("seq {} is stored twice", seq)));
+                }
+                messages.insert(seq, data.to_vec());
+            }
+            bin_off += c_len;
         }
 
-        if inner_off + m_len > decompressed.len() {
-            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Decompression index error"));
+        let root_with = |alg: MerkleAlgorithm| alg.root_of((start_seq..=max_seq).filter_map(|seq| messages.get(&seq)));
+        let (alg, root) = match alg {
+            Some(alg) => (alg, root_with(alg)),
+            None => [MerkleAlgorithm::Blake3, MerkleAlgorithm::Sha256].into_iter()
+                .map(|alg| (alg, root_with(alg)))
+                .find(|(_, root)| old_idx.get(..IDX_HEADER_SIZE) == Some(&root[..]))
+                .unwrap_or_else(|| (MerkleAlgorithm::default(), root_with(MerkleAlgorithm::default()))),
+        };
+        // Renamed into place, like a freshly persisted segment's
+        let idx_tmp = bin_path.with_extension("idx.tmp");
+        let mut idx_file = File::create(&idx_tmp)?;
+        idx_file.write_all(&encode_idx(&root, alg, compression, start_seq, max_seq, &records))?;
+        idx_file.sync_all()?;
+        fs::rename(&idx_tmp, bin_path.with_extension("idx"))
+    }
+
+    /// Finds the lowest sequence with this path hash in the segment. Binary-searches the
+    /// path index when there is one.
+    pub fn find_seq_by_path_hash(&self, path_hash: u64) -> Option<u64> {
+        let Some(table) = &self.path_index else {
+            return self.find_seq_by_path_hash_linear(path_hash);
+        };
+        let entry = |i: usize| {
+            let e = &table[i * PATH_INDEX_ENTRY_SIZE..(i + 1) * PATH_INDEX_ENTRY_SIZE];
+            (u64::from_le_bytes(e[..8].try_into().unwrap()), u64::from_le_bytes(e[8..].try_into().unwrap()))
+        };
+        // First entry whose hash is >= path_hash
+        let (mut lo, mut hi) = (0, table.len() / PATH_INDEX_ENTRY_SIZE);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if entry(mid).0 < path_hash { lo = mid + 1 } else { hi = mid }
+        }
+        if lo == table.len() / PATH_INDEX_ENTRY_SIZE || entry(lo).0 != path_hash {
+            return None;
+        }
+        // Double-check against the index so a damaged sidecar can't point deletes at the wrong message
+        let seq = entry(lo).1;
+        match seq.checked_sub(self.start_seq).and_then(|i| self.record(i)) {
+            Some(rec) if rec.path_hash == path_hash => Some(seq),
+            _ => self.find_seq_by_path_hash_linear(path_hash),
         }
+    }
 
-        let result = decompressed[inner_off..inner_off + m_len].to_vec();
-        {
-            let mut cache = self.cluster_cache.lock().unwrap();
-            if cache.len() >= 512 { cache.clear(); }
-            cache.insert(bin_off, Arc::new(decompressed));
+    /// `find_seq_by_path_hash` without the path index: scans every record.
+    pub fn find_seq_by_path_hash_linear(&self, path_hash: u64) -> Option<u64> {
+        for i in 0..self.msg_count() as u64 {
+            if self.record(i).is_some_and(|r| r.path_hash == path_hash) {
+                return Some(self.start_seq + i);
+            }
+        }
+        None
+    }
+
+    /// Every sequence in the segment with this path hash, ascending. Uses the path index
+    /// when there is one and it agrees with the `.idx`.
+    pub fn seqs_by_path_hash(&self, path_hash: u64) -> Vec<u64> {
+        let matches = |seq: u64| seq.checked_sub(self.start_seq).and_then(|i| self.record(i)).is_some_and(|r| r.path_hash == path_hash);
+        if let Some(table) = &self.path_index {
+            let entry = |i: usize| {
+                let e = &table[i * PATH_INDEX_ENTRY_SIZE..(i + 1) * PATH_INDEX_ENTRY_SIZE];
+                (u64::from_le_bytes(e[..8].try_into().unwrap()), u64::from_le_bytes(e[8..].try_into().unwrap()))
+            };
+            let len = table.len() / PATH_INDEX_ENTRY_SIZE;
+            let (mut lo, mut hi) = (0, len);
+            while lo < hi {
+                let mid = lo + (hi - lo) / 2;
+                if entry(mid).0 < path_hash { lo = mid + 1 } else { hi = mid }
+            }
+            let seqs: Vec<u64> = (lo..len).map(entry).take_while(|e| e.0 == path_hash).map(|e| e.1).collect();
+            if seqs.iter().all(|&seq| matches(seq)) {
+                return seqs;
+            }
+        }
+        (0..self.msg_count() as u64).map(|i| self.start_seq + i).filter(|&seq| matches(seq)).collect()
+    }
+
+    /// `path`'s hash as this segment stores it. None for a `.phash` id this build doesn't know.
+    pub fn path_hash(&self, path: &str) -> Option<u64> {
+        PathHash::from_id(self.path_hash_id).map(|alg| alg.hash(path))
+    }
+
+    /// `seqs_by_path_hash` with `path` hashed the way this segment was written. Other paths
+    /// can share the hash, so the messages still need checking.
+    pub fn seqs_by_path(&self, path: &str) -> Vec<u64> {
+        self.path_hash(path).map_or_else(Vec::new, |hash| self.seqs_by_path_hash(hash))
+    }
+
+    /// Index record of a stored message, rejecting gaps and messages over the decompression cap.
+    fn message_record(&self, index: u64) -> Result<IdxRecord, ArchiveError> {
+        let rec = self.record(index).ok_or(ArchiveError::OutOfRange)?;
+        if rec.m_len == 0 {
+            return Err(ArchiveError::Gap { seq: self.start_seq + index });
+        }
+        // Offsets within a cluster are u32, so no message ends past u32::MAX
+        if rec.inner_off.checked_add(rec.m_len).is_none_or(|end| end > u32::MAX as usize) {
+            return Err(ArchiveError::corrupt("message range overflows its cluster"));
         }
+        if rec.m_len > self.max_decompressed {
+            return Err(DecompressionLimitExceeded { limit: self.max_decompressed }.into());
+        }
+        Ok(rec)
+    }
+
+    fn cached_message(&self, rec: &IdxRecord, cache: &ClusterCache) -> Option<Vec<u8>> {
+        // The cache hands out an Arc, so its lock isn't held while copying out.
+        let cluster = cache.get(self.cache_id, rec.bin_off)?;
+        rec.message_range(cluster.len()).map(|range| cluster[range].to_vec())
+    }
+
+    fn compressed_cluster(&self, rec: &IdxRecord) -> Result<Cow<'_, [u8]>, ArchiveError> {
+        let cluster_range = rec.cluster_range(self.storage.bin_len())
+            .ok_or_else(|| ArchiveError::corrupt("cluster lies past the end of the .bin"))?;
+        Ok(self.storage.get_cluster(cluster_range.start, cluster_range.len())?)
+    }
+
+    /// Retrieves and decompresses a message by its relative index. The decompressed cluster
+    /// is looked up in and added to `cache`.
+    pub fn get_decompressed_message_by_index(
+        &self, 
+        index: u64, 
+        dict: Option<&[u8]>,
+        cache: &ClusterCache,
+    ) -> Result<Vec<u8>, ArchiveError> {
+        let rec = self.message_record(index)?;
+        if let Some(message) = self.cached_message(&rec, cache) {
+            return Ok(message);
+        }
+
+        let decompressed = decompress_bounded(&self.compressed_cluster(&rec)?, dict, self.max_decompressed)?;
+
+        let range = rec.message_range(decompressed.len())
+            .ok_or_else(|| ArchiveError::corrupt("message lies past the end of its cluster"))?;
+
+        let result = decompressed[range].to_vec();
+        cache.insert(self.cache_id, rec.bin_off, Arc::new(decompressed));
 
         Ok(result)
     }
 
-    /// Super-lean path: returns the raw compressed cluster for a message sequence index.
-    pub fn get_raw_cluster_by_index(&self, index: u64) -> io::Result<&[u8]> {
-        let idx_start = 32 + (index as usize) * 28;
-        let bin_off = u64::from_le_bytes(self.idx_mmap[idx_start..idx_start + 8].try_into().unwrap()) as usize;
-        let c_len = u32::from_le_bytes(self.idx_mmap[idx_start + 8..idx_start + 12].try_into().unwrap()) as usize;
-        
-        if bin_off + c_len > self.bin_mmap.len() {
-            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Bin OOB"));
+    /// Like `get_decompressed_message_by_index`, but on a cache miss decodes only up to the
+    /// end of the message and doesn't cache the cluster. Meant for one-off reads out of large
+    /// clusters, where the full path would allocate (and keep) the whole cluster.
+    pub fn get_message_streaming(&self, index: u64, dict: Option<&[u8]>, cache: &ClusterCache) -> Result<Vec<u8>, ArchiveError> {
+        let rec = self.message_record(index)?;
+        if let Some(message) = self.cached_message(&rec, cache) {
+            return Ok(message);
         }
-        Ok(&self.bin_mmap[bin_off..bin_off + c_len])
+        let end = rec.inner_off.checked_add(rec.m_len)
+            .filter(|&end| end <= self.max_decompressed)
+            .ok_or(DecompressionLimitExceeded { limit: self.max_decompressed })?;
+        let window_log_max = usize::BITS - (self.max_decompressed.max(1 << 10) - 1).leading_zeros();
+        Ok(decompress_range(&self.compressed_cluster(&rec)?, dict, rec.inner_off..end, window_log_max)?)
+    }
+
+    /// Super-lean path: returns the raw compressed cluster for a message sequence index.
+    pub fn get_raw_cluster_by_index(&self, index: u64) -> io::Result<Cow<'_, [u8]>> {
+        let rec = self.record(index)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "Index out of bounds"))?;
+        let range = rec.cluster_range(self.storage.bin_len())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Bin OOB"))?;
+        self.storage.get_cluster(range.start, range.len())
     }
 }
 
@@ -199,14 +1057,31 @@ pub struct SegmentedArchive {
     segments: RwLock<BTreeMap<u64, Vec<Segment>>>,
     tombstones: Option<Arc<RwLock<TombstoneStore>>>,
     dict_ref: Option<Arc<Vec<u8>>>,
+    max_decompressed: usize,
+    // Every segment `refresh` has mapped: .bin path -> (.idx length, .idx mtime)
+    mapped: Mutex<HashMap<PathBuf, IdxStamp>>,
+    cache: Arc<ClusterCache>,
+    heads: RwLock<HeadIndex>,
 }
 
+type IdxStamp = (u64, Option<SystemTime>);
+
 impl SegmentedArchive {
     /// Opens all segments in a directory.
     pub fn open_directory<P: AsRef<Path>>(
         dir: P,
         tombstones: Option<Arc<RwLock<TombstoneStore>>>,
         dict_ref: Option<Arc<Vec<u8>>>
+    ) -> io::Result<Self> {
+        Self::open_directory_with_limit(dir, tombstones, dict_ref, DEFAULT_MAX_DECOMPRESSED_CLUSTER_BYTES)
+    }
+
+    /// Same as `open_directory`, capping cluster decompression at `max_decompressed` bytes.
+    pub fn open_directory_with_limit<P: AsRef<Path>>(
+        dir: P,
+        tombstones: Option<Arc<RwLock<TombstoneStore>>>,
+        dict_ref: Option<Arc<Vec<u8>>>,
+        max_decompressed: usize,
     ) -> io::Result<Self> {
         let dir_path = dir.as_ref().to_path_buf();
         if !dir_path.exists() {
@@ -225,6 +1100,10 @@ impl SegmentedArchive {
             segments: RwLock::new(BTreeMap::new()),
             tombstones: effective_tombstones,
             dict_ref,
+            max_decompressed,
+            mapped: Mutex::new(HashMap::new()),
+            cache: Arc::new(ClusterCache::default()),
+            heads: RwLock::new(HeadIndex::default()),
         };
         
         // Use refresh to populate shards correctly
@@ -233,24 +1112,41 @@ impl SegmentedArchive {
         Ok(archive)
     }
 
-    fn scan_dir(dir: &Path, segments: &mut BTreeMap<u64, Vec<Segment>>) -> io::Result<()> {
-        for entry in fs::read_dir(dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            
-            if path.extension().and_then(|s| s.to_str()) == Some("bin") {
-                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
-                    // Filename is either "123" OR "shard_X_123"
-                    let start_seq = if let Some(stripped) = stem.find('_').and_then(|i| stem[i+1..].parse::<u64>().ok()) {
-                        stripped
-                    } else if let Ok(n) = stem.parse::<u64>() {
-                        n
-                    } else {
-                        continue;
-                    };
+    /// Maps the segments in `dir` that aren't in `mapped` or whose `.idx` has changed since,
+    /// then opens those its `remote.json` lists that aren't on disk.
+    fn scan_dir(dir: &Path, mapped: &HashMap<PathBuf, IdxStamp>, max_decompressed: usize) -> io::Result<Vec<(IdxStamp, Segment)>> {
+        let list = || fs::read_dir(dir)?.map(|e| e.map(|e| e.path())).collect::<io::Result<Vec<PathBuf>>>();
+        let mut paths = list()?;
+        // A swap a crash interrupted is finished before its files are paired up. One that can't
+        // be leaves a .bin and .idx that may not match, so that segment isn't mapped.
+        let swaps: Vec<PathBuf> = paths.iter()
+            .filter(|p| p.to_str().is_some_and(|p| p.ends_with(SWAP_COMMIT_EXT)))
+            .map(|p| p.with_extension("").with_extension("bin"))
+            .collect();
+        let mut unfinished = HashSet::new();
+        if !swaps.is_empty() {
+            for bin_path in swaps {
+                if let Err(e) = finish_swap(&bin_path) {
+                    tracing::warn!("{}: can't finish an interrupted recompression, not mapping it: {}", bin_path.display(), e);
+                    unfinished.insert(bin_path);
+                }
+            }
+            paths = list()?;
+        }
 
+        let mut found = Vec::new();
+        for path in paths {
+            if unfinished.contains(&path) {
+                continue;
+            }
+            if path.extension().and_then(|s| s.to_str()) == Some("bin") {
+                if let Some(start_seq) = path.file_stem().and_then(|s| s.to_str()).and_then(stem_start_seq) {
                     let idx_path = path.with_extension("idx");
-                    if idx_path.exists() {
+                    if let Ok(meta) = fs::metadata(&idx_path) {
+                        let stamp = (meta.len(), meta.modified().ok());
+                        if mapped.get(&path) == Some(&stamp) {
+                            continue;
+                        }
                         let bin_file = File::open(&path)?;
                         let idx_file = File::open(&idx_path)?;
                         
@@ -258,12 +1154,79 @@ impl SegmentedArchive {
                         let idx_mmap = unsafe { Mmap::map(&idx_file)? };
                         
                         let segment = Segment::new(start_seq, bin_mmap, idx_mmap);
-                        segments.entry(start_seq).or_default().push(segment);
+                        found.push((stamp, Self::attach_sidecars(segment, &path, max_decompressed, path.clone())));
                     }
                 }
             }
         }
-        Ok(())
+        found.extend(Self::scan_remote(dir, mapped, max_decompressed)?);
+        Ok(found)
+    }
+
+    /// Reads the sidecars next to `bin_path` into `segment`, which was mapped from `source`.
+    fn attach_sidecars(mut segment: Segment, bin_path: &Path, max_decompressed: usize, source: PathBuf) -> Segment {
+        // Optional: legacy segments have no path index
+        let pidx_mmap = File::open(bin_path.with_extension("pidx")).ok()
+            .filter(|f| f.metadata().is_ok_and(|m| m.len() > 0))
+            .and_then(|f| unsafe { Mmap::map(&f) }.ok());
+        if let Some(pidx_mmap) = pidx_mmap {
+            segment = segment.with_path_index(pidx_mmap);
+        }
+        let mhash_mmap = File::open(bin_path.with_extension(MESSAGE_HASH_EXT)).ok()
+            .filter(|f| f.metadata().is_ok_and(|m| m.len() > 0))
+            .and_then(|f| unsafe { Mmap::map(&f) }.ok());
+        if let Some(mhash_mmap) = mhash_mmap {
+            segment = segment.with_message_hashes(mhash_mmap);
+        }
+        let didx_mmap = File::open(bin_path.with_extension(DID_INDEX_EXT)).ok()
+            .filter(|f| f.metadata().is_ok_and(|m| m.len() > 0))
+            .and_then(|f| unsafe { Mmap::map(&f) }.ok());
+        if let Some(didx_mmap) = didx_mmap {
+            segment = segment.with_did_index(didx_mmap);
+        }
+        segment.max_decompressed = max_decompressed;
+        segment.dict_hash = fs::read_to_string(bin_path.with_extension(DICT_ID_EXT)).ok()
+            .map(|h| h.trim().to_ascii_lowercase())
+            .filter(|h| !h.is_empty());
+        if !segment.hash_alg_in_idx() {
+            segment.hash_alg_id = read_hash_alg_id(&bin_path.with_extension(HASH_ALG_EXT));
+        }
+        if idx_compression(segment.storage.get_index_bytes()).is_none() {
+            segment.compression = read_compression(&bin_path.with_extension(COMPRESSION_EXT));
+        }
+        segment.path_hash_id = path_hash::read_id(&bin_path.with_extension(path_hash::PATH_HASH_EXT));
+        segment.collection_stats = stats::read(&bin_path.with_extension(stats::STATS_EXT));
+        segment.source = Some(source);
+        segment
+    }
+
+    /// Opens the segments `dir/remote.json` lists, skipping those already mapped and those
+    /// with a `.bin` on disk. Remote segments don't change, so they're never re-read.
+    #[cfg(feature = "remote-segments")]
+    fn scan_remote(dir: &Path, mapped: &HashMap<PathBuf, IdxStamp>, max_decompressed: usize) -> io::Result<Vec<(IdxStamp, Segment)>> {
+        let Some(manifest) = source::RemoteManifest::load(dir)? else { return Ok(Vec::new()) };
+        let local_bin = |stem: &str| dir.join(format!("{}.bin", stem));
+        let exts = ["pidx", MESSAGE_HASH_EXT, DID_INDEX_EXT, DICT_ID_EXT, HASH_ALG_EXT, COMPRESSION_EXT, stats::STATS_EXT, path_hash::PATH_HASH_EXT];
+        let opened = source::open_remote(dir, &manifest, &exts, |stem| {
+            let path = local_bin(stem);
+            stem_start_seq(stem).is_some() && !mapped.contains_key(&path) && !path.exists()
+        })?;
+        let mut found = Vec::new();
+        for (stem, storage, sidecars) in opened {
+            let start_seq = stem_start_seq(&stem).unwrap();
+            let stamp = (storage.get_index_bytes().len() as u64, None);
+            let segment = Segment::from_source(start_seq, Box::new(storage));
+            found.push((stamp, Self::attach_sidecars(segment, &sidecars, max_decompressed, local_bin(&stem))));
+        }
+        Ok(found)
+    }
+
+    #[cfg(not(feature = "remote-segments"))]
+    fn scan_remote(dir: &Path, _mapped: &HashMap<PathBuf, IdxStamp>, _max_decompressed: usize) -> io::Result<Vec<(IdxStamp, Segment)>> {
+        if dir.join(source::REMOTE_MANIFEST).exists() {
+            tracing::warn!("{} lists remote segments, but this build has no remote-segments feature; ignoring them", dir.display());
+        }
+        Ok(Vec::new())
     }
 
     pub fn find_seq_by_path_hash(&self, path_hash: u64) -> Option<u64> {
@@ -279,57 +1242,189 @@ impl SegmentedArchive {
         None
     }
 
+    /// Sequence of a stored message with this blake3, newest segments first. Only segments
+    /// written with message hashes on can answer; tombstoned messages still count.
+    pub fn contains_hash(&self, hash: &[u8; 32]) -> Option<u64> {
+        let segments = self.segments.read().unwrap();
+        segments.values().rev().flatten().find_map(|segment| segment.contains_hash(hash))
+    }
+
+    /// Messages and bytes per collection over the segments starting in `seqs`, from their
+    /// `.stats` sidecars. Segments written without collection stats are skipped.
+    pub fn collection_stats(&self, seqs: std::ops::Range<u64>) -> HashMap<String, CollStat> {
+        let mut totals = HashMap::new();
+        if seqs.is_empty() {
+            return totals;
+        }
+        let segments = self.segments.read().unwrap();
+        for segment in segments.range(seqs).flat_map(|(_, list)| list) {
+            if let Some(segment_stats) = segment.collection_stats() {
+                stats::merge(&mut totals, segment_stats);
+            }
+        }
+        totals
+    }
+
+    /// Newest archived commit of `did`, from segments written with head tracking on and seen
+    /// by the last `refresh`.
+    pub fn latest_for_did(&self, did: &str) -> Option<HeadInfo> {
+        self.heads.read().unwrap().get(did)
+    }
+
+    /// Every DID with a tracked head, in no particular order.
+    pub fn head_dids(&self) -> Vec<String> {
+        self.heads.read().unwrap().dids()
+    }
+
+    /// Message digests of the newest `count` segments, one list per segment, oldest first.
+    pub fn recent_message_hashes(&self, count: usize) -> Vec<Vec<[u8; 32]>> {
+        let segments = self.segments.read().unwrap();
+        let mut recent: Vec<_> = segments.values().rev().flatten().take(count).map(Segment::message_hashes).collect();
+        recent.reverse();
+        recent
+    }
+
+    /// Picks up segments persisted since the last refresh. Segments already mapped stay as they
+    /// are, cluster caches included, unless their `.idx` changed on disk; removed files aren't
+    /// noticed (see `force_rescan`).
     pub fn refresh(&self) -> io::Result<()> {
+        self.heads.write().unwrap().catch_up(&self.data_dir)?;
+        let mut mapped = self.mapped.lock().unwrap();
+        let found = self.scan_all(&mapped)?;
+        if found.is_empty() {
+            return Ok(());
+        }
         let mut segments = self.segments.write().unwrap();
-        segments.clear(); // Re-scan clean
-        Self::scan_dir(&self.data_dir, &mut segments)?;
-        
+        self.install(found, &mut mapped, &mut segments);
+        Ok(())
+    }
+
+    /// Adds freshly scanned segments, each replacing an older mapping of the same files.
+    fn install(&self, found: Vec<(IdxStamp, Segment)>, mapped: &mut HashMap<PathBuf, IdxStamp>, segments: &mut BTreeMap<u64, Vec<Segment>>) {
+        for (stamp, segment) in found {
+            let list = segments.entry(segment.start_seq).or_default();
+            // A changed segment replaces its old mapping
+            list.retain(|s| {
+                let keep = s.source != segment.source;
+                if !keep {
+                    self.cache.remove_segment(s.cache_id);
+                }
+                keep
+            });
+            if let Some(source) = &segment.source {
+                mapped.insert(source.clone(), stamp);
+            }
+            list.push(segment);
+        }
+    }
+
+    /// Drops every mapped segment and re-reads the directories from scratch. For recovery
+    /// after segment files were removed or replaced behind the archive's back.
+    pub fn force_rescan(&self) -> io::Result<()> {
+        {
+            let mut heads = self.heads.write().unwrap();
+            *heads = HeadIndex::default();
+            heads.catch_up(&self.data_dir)?;
+        }
+        let mut mapped = self.mapped.lock().unwrap();
+        let found = self.scan_all(&HashMap::new())?;
+        let mut segments = self.segments.write().unwrap();
+        for segment in segments.values().flatten() {
+            self.cache.remove_segment(segment.cache_id);
+        }
+        segments.clear();
+        mapped.clear();
+        for (stamp, segment) in found {
+            if let Some(source) = &segment.source {
+                mapped.insert(source.clone(), stamp);
+            }
+            segments.entry(segment.start_seq).or_default().push(segment);
+        }
+        Ok(())
+    }
+
+    /// `scan_dir` over the data dir and its `shard_*` subdirectories.
+    fn scan_all(&self, mapped: &HashMap<PathBuf, IdxStamp>) -> io::Result<Vec<(IdxStamp, Segment)>> {
+        let mut found = Self::scan_dir(&self.data_dir, mapped, self.max_decompressed)?;
+
         // Also scan shard subdirectories if they exist
         if self.data_dir.exists() {
             for entry in fs::read_dir(&self.data_dir)? {
                 let entry = entry?;
                 let path = entry.path();
                 if path.is_dir() && path.file_name().and_then(|s| s.to_str()).map(|s| s.starts_with("shard_")).unwrap_or(false) {
-                    Self::scan_dir(&path, &mut segments).ok();
+                    if let Ok(more) = Self::scan_dir(&path, mapped, self.max_decompressed) {
+                        found.extend(more);
+                    }
                 }
             }
         }
-        Ok(())
+        Ok(found)
+    }
+
+    /// Shares `cache` with this archive in place of its own, e.g. across the shards of a
+    /// `MultiShardArchive`.
+    pub fn with_cluster_cache(mut self, cache: Arc<ClusterCache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    pub fn cluster_cache(&self) -> &Arc<ClusterCache> {
+        &self.cache
+    }
+
+    /// Decompressed clusters of this archive's segments in its cluster cache.
+    pub fn cached_clusters(&self) -> usize {
+        self.segments.read().unwrap().values().flatten().map(|s| self.cache.segment_entries(s.cache_id)).sum()
     }
 
     /// Finds and retrieves a message by its global sequence number.
     /// Returns decompressed data.
-    pub fn get_message_by_seq(&self, seq: u64, dict: Option<&[u8]>) -> io::Result<Vec<u8>> {
-        if let Some(ts) = &self.tombstones {
-            if ts.read().unwrap().is_deleted(seq) {
-                return Err(io::Error::new(io::ErrorKind::NotFound, "Sequence tombstoned"));
-            }
+    pub fn get_message_by_seq(&self, seq: u64, dict: Option<&[u8]>) -> Result<Vec<u8>, ArchiveError> {
+        self.read_message(seq, dict, false)
+    }
+
+    /// `get_message_by_seq` through `Segment::get_message_streaming`.
+    pub fn get_message_streaming(&self, seq: u64, dict: Option<&[u8]>) -> Result<Vec<u8>, ArchiveError> {
+        self.read_message(seq, dict, true)
+    }
+
+    fn is_tombstoned(&self, seq: u64) -> bool {
+        self.tombstones.as_ref().is_some_and(|ts| ts.read().unwrap().is_deleted(seq))
+    }
+
+    fn read_message(&self, seq: u64, dict: Option<&[u8]>, streaming: bool) -> Result<Vec<u8>, ArchiveError> {
+        if self.is_tombstoned(seq) {
+            return Err(ArchiveError::Tombstoned { seq });
         }
+        self.read_stored(seq, dict, streaming)
+    }
 
+    /// `read_message` without the tombstone check.
+    fn read_stored(&self, seq: u64, dict: Option<&[u8]>, streaming: bool) -> Result<Vec<u8>, ArchiveError> {
         let segments = self.segments.read().unwrap();
         let effective_dict = dict.or_else(|| self.dict_ref.as_ref().map(|d| &d[..]));
         
         for (_start, list) in segments.range(..=seq).rev() {
             for segment in list {
                 let rel_index = seq - segment.start_seq;
-                let idx_start = 32 + (rel_index as usize) * 28;
-                if idx_start + 20 <= segment.idx_mmap.len() {
-                    let m_len = u32::from_le_bytes(segment.idx_mmap[idx_start + 16..idx_start + 20].try_into().unwrap());
-                    if m_len != 0 {
-                        return segment.get_decompressed_message_by_index(rel_index, effective_dict);
+                if segment.record(rel_index).is_some_and(|r| r.m_len != 0) {
+                    if streaming {
+                        return segment.get_message_streaming(rel_index, effective_dict, &self.cache);
                     }
+                    return segment.get_decompressed_message_by_index(rel_index, effective_dict, &self.cache);
                 }
             }
         }
-        Err(io::Error::new(io::ErrorKind::NotFound, "Sequence not found in archive"))
+        drop(segments);
+        Err(ArchiveError::missing(seq, self.min_seq(), self.max_seq()))
     }
 
-    /// Returns the raw compressed cluster for a global sequence.
-    pub fn get_raw_cluster_at_seq(&self, seq: u64) -> io::Result<Vec<u8>> {
-        if let Some(ts) = &self.tombstones {
-            if ts.read().unwrap().is_deleted(seq) {
-                return Err(io::Error::new(io::ErrorKind::NotFound, "Sequence tombstoned"));
-            }
+    /// Returns the raw compressed cluster for a global sequence. Tombstoned seqs sharing
+    /// the cluster are filtered out of it.
+    pub fn get_raw_cluster_at_seq(&self, seq: u64) -> Result<Vec<u8>, ArchiveError> {
+        if self.is_tombstoned(seq) {
+            return Err(ArchiveError::Tombstoned { seq });
         }
 
         let segments = self.segments.read().unwrap();
@@ -337,102 +1432,92 @@ impl SegmentedArchive {
         for (_start, list) in segments.range(..=seq).rev() {
             for segment in list {
                 let rel_index = seq - segment.start_seq;
-                let idx_start = 32 + (rel_index as usize) * 28;
-                
-                if idx_start + 12 <= segment.idx_mmap.len() {
-                    let bin_off = u64::from_le_bytes(segment.idx_mmap[idx_start..idx_start + 8].try_into().unwrap()) as usize;
-                    if bin_off != 0 {
-                        let c_len = u32::from_le_bytes(segment.idx_mmap[idx_start + 8..idx_start + 12].try_into().unwrap()) as usize;
-                        if bin_off + c_len <= segment.bin_mmap.len() {
-                            let raw_cluster = &segment.bin_mmap[bin_off..bin_off + c_len];
-                            
-                            // Check if ANY sequence in this cluster is tombstoned
-                            if let Some(ts) = &self.tombstones {
-                                let mut cluster_seqs = Vec::new();
-                                // Record size 28
-                                let msg_count = (segment.idx_mmap.len() - 32) / 28;
-                                for i in 0..msg_count {
-                                    let off = 32 + i * 28;
-                                    let b_off = u64::from_le_bytes(segment.idx_mmap[off..off + 8].try_into().unwrap()) as usize;
-                                    if b_off == bin_off {
-                                        cluster_seqs.push(segment.start_seq + i as u64);
-                                    }
-                                }
 
-                                let ts_lock = ts.read().unwrap();
-                                let mut any_tombstoned = false;
-                                for s in &cluster_seqs {
-                                    if ts_lock.is_deleted(*s) {
-                                        any_tombstoned = true;
-                                        break;
-                                    }
+                if let Some(rec) = segment.record(rel_index) {
+                    // Gap records are all-zero; a real cluster always has c_len > 0 (bin_off may be 0)
+                    if rec.c_len != 0 {
+                        let bin_off = rec.bin_off;
+                        let raw_cluster = segment.compressed_cluster(&rec)?;
+                        
+                        // Check if ANY sequence in this cluster is tombstoned
+                        if let Some(ts) = &self.tombstones {
+                            let mut cluster_seqs = Vec::new();
+                            for i in 0..segment.msg_count() as u64 {
+                                if segment.record(i).is_some_and(|r| r.c_len != 0 && r.bin_off == bin_off) {
+                                    cluster_seqs.push(segment.start_seq + i);
                                 }
+                            }
 
-                                if any_tombstoned {
-                                    // Decompress, Filter, Re-compress (LEAN BUT COMPLIANT)
-                                    let mut decompressed = Vec::new();
-                                    use std::io::Read;
-                                    if let Some(dict) = self.dict_ref.as_ref() {
-                                        let mut decoder = zstd::Decoder::with_dictionary(raw_cluster, &dict[..])?;
-                                        decoder.read_to_end(&mut decompressed)?;
-                                    } else {
-                                        let mut decoder = zstd::Decoder::new(raw_cluster)?;
-                                        decoder.read_to_end(&mut decompressed)?;
-                                    }
-
-                                    // The cluster format: [u16 count][u32 len1][u32 len2]...[data1][data2]...
-                                    if decompressed.len() < 2 { return Ok(raw_cluster.to_vec()); }
-                                    let count = u16::from_le_bytes([decompressed[0], decompressed[1]]) as usize;
-                                    if count != cluster_seqs.len() { return Ok(raw_cluster.to_vec()); }
-
-                                    let mut offsets = Vec::new();
-                                    let mut curr = 2 + (count * 4);
-                                    for i in 0..count {
-                                        let len = u32::from_le_bytes(decompressed[2 + i*4..6 + i*4].try_into().unwrap()) as usize;
-                                        offsets.push((curr, len));
-                                        curr += len;
-                                    }
-
-                                    let mut filtered_payloads = Vec::new();
-                                    for (i, s) in cluster_seqs.iter().enumerate() {
-                                        if !ts_lock.is_deleted(*s) {
-                                            let (o, l) = offsets[i];
-                                            filtered_payloads.push(&decompressed[o..o+l]);
-                                        }
-                                    }
-
-                                    // Rebuild cluster
-                                    let mut rebuilt = Vec::new();
-                                    rebuilt.extend_from_slice(&(filtered_payloads.len() as u16).to_le_bytes());
-                                    for p in &filtered_payloads {
-                                        rebuilt.extend_from_slice(&(p.len() as u32).to_le_bytes());
-                                    }
-                                    for p in &filtered_payloads {
-                                        rebuilt.extend_from_slice(p);
-                                    }
-
-                                    let compressed;
-                                    use std::io::Write;
-                                    if let Some(dict) = self.dict_ref.as_ref() {
-                                        let mut encoder = zstd::Encoder::with_dictionary(Vec::new(), 3, &dict[..])?;
-                                        encoder.write_all(&rebuilt)?;
-                                        compressed = encoder.finish()?;
-                                    } else {
-                                        let mut encoder = zstd::Encoder::new(Vec::new(), 3)?;
-                                        encoder.write_all(&rebuilt)?;
-                                        compressed = encoder.finish()?;
-                                    }
-                                    return Ok(compressed);
+                            let ts_lock = ts.read().unwrap();
+                            let mut any_tombstoned = false;
+                            for s in &cluster_seqs {
+                                if ts_lock.is_deleted(*s) {
+                                    any_tombstoned = true;
+                                    break;
                                 }
                             }
 
-                            return Ok(raw_cluster.to_vec());
+                            if any_tombstoned {
+                                // Decompress, Filter, Re-compress (LEAN BUT COMPLIANT)
+                                let decompressed = decompress_bounded(
+                                    &raw_cluster,
+                                    self.dict_ref.as_ref().map(|d| &d[..]),
+                                    self.max_decompressed,
+                                )?;
+
+                                // Keep the on-disk layout so consumers decode both kinds of cluster alike
+                                let cluster = ClusterReader::new(&decompressed).map_err(|e| ArchiveError::corrupt(e.to_string()))?;
+                                let mut kept = ClusterWriter::new();
+                                for entry in cluster.iter().filter(|e| !ts_lock.is_deleted(e.seq)) {
+                                    kept.push(entry.seq, entry.data)?;
+                                }
+                                let rebuilt = kept.finish();
+
+                                // The segment's level, but never long-distance matching: this runs
+                                // per relay request, and the frame must open with a plain decoder
+                                let config = CompressionConfig { long_distance: false, window_log: None, ..segment.compression };
+                                let compressed = config
+                                    .compressor(self.dict_ref.as_ref().map(|d| &d[..]))?
+                                    .compress(&rebuilt)?;
+                                return Ok(compressed);
+                            }
                         }
+
+                        return Ok(raw_cluster.into_owned());
                     }
                 }
             }
         }
-        Err(io::Error::new(io::ErrorKind::NotFound, "Sequence not found in archive"))
+        drop(segments);
+        Err(ArchiveError::missing(seq, self.min_seq(), self.max_seq()))
+    }
+
+    /// `dict_hash` recorded for the segment holding `seq`. None if the seq isn't stored or
+    /// its segment has no `.dictid` sidecar.
+    pub fn dict_hash_at_seq(&self, seq: u64) -> Option<String> {
+        let segments = self.segments.read().unwrap();
+        for (_start, list) in segments.range(..=seq).rev() {
+            for segment in list {
+                if segment.record(seq - segment.start_seq).is_some_and(|r| r.c_len != 0) {
+                    return segment.dict_hash.clone();
+                }
+            }
+        }
+        None
+    }
+
+    /// `CompressionConfig` the segment holding `seq` was written with. None if the seq isn't
+    /// stored.
+    pub fn compression_at_seq(&self, seq: u64) -> Option<CompressionConfig> {
+        let segments = self.segments.read().unwrap();
+        for (_start, list) in segments.range(..=seq).rev() {
+            for segment in list {
+                if segment.record(seq - segment.start_seq).is_some_and(|r| r.c_len != 0) {
+                    return Some(segment.compression);
+                }
+            }
+        }
+        None
     }
 
     pub fn min_seq(&self) -> Option<u64> {
@@ -446,19 +1531,14 @@ impl SegmentedArchive {
         
         let mut max = *start_seq;
         for segment in list {
-            let msg_count = (segment.idx_mmap.len() - 32) / 28;
-            if msg_count > 0 {
-                // Find highest non-zero message length by scanning backwards
-                for i in (0..msg_count).rev() {
-                    let idx_off = 32 + i * 28;
-                    let m_len = u32::from_le_bytes(segment.idx_mmap[idx_off + 16..idx_off + 20].try_into().unwrap());
-                    if m_len != 0 {
-                        let current_max = *start_seq + (i as u64);
-                        if current_max > max {
-                            max = current_max;
-                        }
-                        break; 
+            // Find highest non-zero message length by scanning backwards
+            for i in (0..segment.msg_count() as u64).rev() {
+                if segment.record(i).is_some_and(|r| r.m_len != 0) {
+                    let current_max = *start_seq + i;
+                    if current_max > max {
+                        max = current_max;
                     }
+                    break; 
                 }
             }
         }
@@ -475,6 +1555,7 @@ impl SegmentedArchive {
     }
 
     pub fn merge(&self, other: SegmentedArchive) {
+        self.mapped.lock().unwrap().extend(other.mapped.into_inner().unwrap());
         let mut segments = self.segments.write().unwrap();
         let other_segments = other.segments.into_inner().unwrap();
         for (seq, segment_list) in other_segments {
@@ -482,6 +1563,284 @@ impl SegmentedArchive {
         }
     }
 
+    /// Copies every segment in `src_dir` (another node's `.bin`/`.idx` files and their
+    /// sidecars, not its `shard_N` subdirectories) into this archive with all seqs moved up
+    /// by `seq_offset`, then refreshes. Clusters are re-encoded with the new seqs; messages,
+    /// and so Merkle roots, are unchanged. Tombstones aren't carried over.
+    ///
+    /// Nothing is written if a moved segment would overlap one already here or another
+    /// imported one, or was compressed with a dictionary other than this archive's.
+    /// Returns how many segments were imported.
+    pub fn import_segments(&self, src_dir: &Path, seq_offset: u64) -> io::Result<usize> {
+        let mut sources = Vec::new();
+        for entry in fs::read_dir(src_dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|s| s.to_str()) != Some("bin") || !path.with_extension("idx").exists() {
+                continue;
+            }
+            let Some(stem) = path.file_stem().and_then(|s| s.to_str()).map(str::to_owned) else { continue };
+            // Same naming as scan_dir: "123" or "s0_123"; the prefix is kept
+            let (prefix, start_seq) = match stem.find('_').and_then(|i| Some((&stem[..=i], stem[i + 1..].parse::<u64>().ok()?))) {
+                Some(parsed) => parsed,
+                None => match stem.parse::<u64>() {
+                    Ok(n) => ("", n),
+                    Err(_) => continue,
+                },
+            };
+            let new_start = start_seq.checked_add(seq_offset)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("seq offset {} overflows segment {}", seq_offset, stem)))?;
+            let idx = fs::read(path.with_extension("idx"))?;
+            let records_at = idx_header(&idx).0;
+            if idx.len() < records_at || !(idx.len() - records_at).is_multiple_of(IDX_RECORD_SIZE) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{}: index is not a whole number of records", path.display())));
+            }
+            let count = ((idx.len() - records_at) / IDX_RECORD_SIZE) as u64;
+            sources.push((path, format!("{}{}", prefix, new_start), new_start, count, idx));
+        }
+
+        // Everything is checked before the first file is written
+        let dict = self.dict_ref.as_ref().map(|d| &d[..]);
+        let our_dict = dict.map(dict_hash);
+        {
+            let segments = self.segments.read().unwrap();
+            let mut taken: Vec<(u64, u64)> = segments.values().flatten().map(|s| (s.start_seq, s.msg_count() as u64)).collect();
+            for (path, name, new_start, count, _) in &sources {
+                if let Some(src_dict) = fs::read_to_string(path.with_extension(DICT_ID_EXT)).ok().map(|h| h.trim().to_ascii_lowercase()).filter(|h| !h.is_empty()) {
+                    if our_dict.as_ref() != Some(&src_dict) {
+                        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} was compressed with dictionary {}, not this archive's", path.display(), src_dict)));
+                    }
+                }
+                let end = new_start.saturating_add(*count);
+                if let Some((start, len)) = taken.iter().find(|(start, len)| *new_start < start + len && *start < end) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{} would cover seqs {}..{}, overlapping the segment at {}..{}", path.display(), new_start, end, start, start + len),
+                    ));
+                }
+                if self.data_dir.join(format!("{}.bin", name)).exists() || self.data_dir.join(format!("{}.idx", name)).exists() {
+                    return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("segment {} already exists in {}", name, self.data_dir.display())));
+                }
+                taken.push((*new_start, *count));
+            }
+        }
+
+        for (path, name, _, _, idx) in &sources {
+            let src_dict = if path.with_extension(DICT_ID_EXT).exists() { dict } else { None };
+            let bin = fs::read(path)?;
+            // A bare-root .idx gets the full header, with the algorithm its sidecar named
+            let alg_id = segment_hash_alg_id(idx, path);
+            let (new_bin, new_idx) = Self::remap_segment(&bin, idx, seq_offset, src_dict, self.max_decompressed, alg_id, CompressionConfig::default())?;
+            let dst = |ext: &str| self.data_dir.join(format!("{}.{}", name, ext));
+
+            fs::write(dst("bin"), new_bin)?;
+            for ext in [DICT_ID_EXT, stats::STATS_EXT, path_hash::PATH_HASH_EXT] {
+                if let Ok(bytes) = fs::read(path.with_extension(ext)) {
+                    fs::write(dst(ext), bytes)?;
+                }
+            }
+            // (hash, seq) tables keep their order when every seq moves by the same amount
+            for (ext, key_len) in [
+                ("pidx", PATH_INDEX_ENTRY_SIZE - 8),
+                (MESSAGE_HASH_EXT, MESSAGE_HASH_ENTRY_SIZE - 8),
+                (DID_INDEX_EXT, DID_INDEX_ENTRY_SIZE - 8),
+            ] {
+                let Ok(mut table) = fs::read(path.with_extension(ext)) else { continue };
+                if table.len() % (key_len + 8) != 0 {
+                    tracing::warn!("{}: {} sidecar has a partial entry, not importing it", path.display(), ext);
+                    continue;
+                }
+                for entry in table.chunks_exact_mut(key_len + 8) {
+                    let seq = u64::from_le_bytes(entry[key_len..].try_into().unwrap()).saturating_add(seq_offset);
+                    entry[key_len..].copy_from_slice(&seq.to_le_bytes());
+                }
+                fs::write(dst(ext), table)?;
+            }
+            // The .idx goes last: a segment is visible once it exists
+            fs::write(dst("idx"), new_idx)?;
+        }
+
+        self.refresh()?;
+        Ok(sources.len())
+    }
+
+    /// Rewrites the segment starting at `start_seq` with `config`, e.g. to move a segment that
+    /// has gone cold to a slower, denser level. Messages, and so the Merkle root, are
+    /// unchanged, as are its sidecars. Readers keep being served throughout: the segment is
+    /// swapped under the archive's lock. Returns the new `.bin` size.
+    ///
+    /// The new pair is written next to the old one and swapped in by `swap_segment_files`: a
+    /// failed swap puts the old pair back, and one a crash interrupted is finished by the next
+    /// scan of the directory, so no reader maps the new `.bin` with the old `.idx`.
+    pub fn recompress_segment(&self, start_seq: u64, config: CompressionConfig) -> io::Result<u64> {
+        config.validate()?;
+        let dict = self.dict_ref.as_ref().map(|d| &d[..]);
+        let (bin_path, alg_id) = {
+            let segments = self.segments.read().unwrap();
+            let mut matching = segments.get(&start_seq).into_iter().flatten();
+            let segment = match (matching.next(), matching.next()) {
+                (Some(segment), None) => segment,
+                (None, _) => return Err(io::Error::new(io::ErrorKind::NotFound, format!("no segment starts at {}", start_seq))),
+                (Some(_), Some(_)) => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("more than one segment starts at {}", start_seq)))
+                }
+            };
+            if let Some(hash) = &segment.dict_hash {
+                if dict.map(dict_hash).as_ref() != Some(hash) {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("segment {} was compressed with dictionary {}, not this archive's", start_seq, hash)));
+                }
+            }
+            let source = segment.source.clone().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("segment {} wasn't mapped from a file", start_seq)))?;
+            (source, segment.hash_alg_id)
+        };
+
+        let idx_path = bin_path.with_extension("idx");
+        let (new_bin, new_idx) = Self::remap_segment(&fs::read(&bin_path)?, &fs::read(&idx_path)?, 0, dict, self.max_decompressed, alg_id, config)?;
+        for (path, bytes) in [(bin_path.with_extension(SWAP_BIN_EXT), &new_bin), (bin_path.with_extension(SWAP_IDX_EXT), &new_idx)] {
+            let mut file = File::create(path)?;
+            file.write_all(bytes)?;
+            file.sync_all()?;
+        }
+
+        let mut mapped = self.mapped.lock().unwrap();
+        let mut segments = self.segments.write().unwrap();
+        // Unmapped before the renames, which Windows refuses over a mapped file
+        if let Some(list) = segments.get_mut(&start_seq) {
+            list.retain(|s| {
+                let keep = s.source.as_ref() != Some(&bin_path);
+                if !keep {
+                    self.cache.remove_segment(s.cache_id);
+                }
+                keep
+            });
+            if list.is_empty() {
+                segments.remove(&start_seq);
+            }
+        }
+        mapped.remove(&bin_path);
+        let swapped = swap_segment_files(&bin_path);
+        if swapped.is_ok() {
+            // The header names the settings now; a legacy sidecar would only be stale
+            let _ = fs::remove_file(bin_path.with_extension(COMPRESSION_EXT));
+        }
+        // Mapped again whether or not the swap went through
+        let found = self.scan_all(&mapped)?;
+        self.install(found, &mut mapped, &mut segments);
+        swapped?;
+        Ok(new_bin.len() as u64)
+    }
+
+    /// Re-encodes a segment's clusters with `compression` and seqs moved by `seq_offset`,
+    /// returning the new `.bin` and an `.idx` pointing into it, whose header names `alg_id`
+    /// and `compression`. Gap records stay zeroed.
+    fn remap_segment(
+        bin: &[u8],
+        idx: &[u8],
+        seq_offset: u64,
+        dict: Option<&[u8]>,
+        limit: usize,
+        alg_id: u8,
+        compression: CompressionConfig,
+    ) -> io::Result<(Vec<u8>, Vec<u8>)> {
+        let mut compressor = compression.compressor(dict)?;
+        let mut new_bin = Vec::with_capacity(bin.len());
+        let records_at = idx_header(idx).0;
+        let root: [u8; 32] = idx.get(..IDX_HEADER_SIZE).and_then(|r| r.try_into().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "index is shorter than its header"))?;
+        let mut new_idx = encode_idx_header(&root, alg_id, compression, idx.len().saturating_sub(records_at));
+        // Old bin_off -> (new bin_off, new c_len), since a cluster is shared by its DID's records
+        let mut moved: HashMap<usize, (u64, u32)> = HashMap::new();
+        for rec_bytes in idx[records_at..].chunks_exact(IDX_RECORD_SIZE) {
+            let rec = IdxRecord::parse(rec_bytes);
+            let mut out: [u8; IDX_RECORD_SIZE] = rec_bytes.try_into().unwrap();
+            if rec.c_len > 0 {
+                let (off, len) = match moved.get(&rec.bin_off) {
+                    Some(&placed) => placed,
+                    None => {
+                        let range = rec.cluster_range(bin.len())
+                            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "Binary mapping out of bounds"))?;
+                        let mut raw = decompress_bounded(&bin[range], dict, limit)?;
+                        cluster::shift_seqs(&mut raw, seq_offset)?;
+                        let compressed = compressor.compress(&raw)?;
+                        let placed = (new_bin.len() as u64, compressed.len() as u32);
+                        new_bin.extend_from_slice(&compressed);
+                        moved.insert(rec.bin_off, placed);
+                        placed
+                    }
+                };
+                out[..8].copy_from_slice(&off.to_le_bytes());
+                out[8..12].copy_from_slice(&len.to_le_bytes());
+            }
+            new_idx.extend_from_slice(&out);
+        }
+        Ok((new_bin, new_idx))
+    }
+
+    /// Newest stored message for `path` in `did`'s repo, or None if there is none. The path
+    /// hash doesn't cover the DID, so candidates that parse as another repo's event are
+    /// stepped over. If the newest one is tombstoned, that is the answer: older versions
+    /// aren't brought back.
+    pub fn latest_by_path(&self, did: &str, path: &str, dict: Option<&[u8]>) -> Result<Option<Vec<u8>>, ArchiveError> {
+        Ok(self.latest_entry_by_path(did, path, dict)?.map(|(_, message)| message))
+    }
+
+    /// `latest_by_path` with the seq the message is stored under.
+    pub fn latest_entry_by_path(&self, did: &str, path: &str, dict: Option<&[u8]>) -> Result<Option<(u64, Vec<u8>)>, ArchiveError> {
+        for seq in self.seqs_by_path(path) {
+            let message = match self.read_stored(seq, dict, false) {
+                Ok(message) => message,
+                Err(ArchiveError::Gap { .. } | ArchiveError::NotFound | ArchiveError::OutOfRange) => continue,
+                Err(e) => return Err(e),
+            };
+            // Frames that don't parse can't be attributed, so they're taken at their path hash
+            if crate::parser::core::parse_input(&message).ok().and_then(|env| env.did).is_some_and(|d| d != did.as_bytes()) {
+                continue;
+            }
+            if self.is_tombstoned(seq) {
+                return Err(ArchiveError::Tombstoned { seq });
+            }
+            return Ok(Some((seq, message)));
+        }
+        Ok(None)
+    }
+
+    /// Seqs whose path hash matches `path` in any segment, each segment hashing it its own
+    /// way; newest first.
+    pub fn seqs_by_path(&self, path: &str) -> Vec<u64> {
+        let mut seqs: Vec<u64> = {
+            let segments = self.segments.read().unwrap();
+            segments.values().flatten().flat_map(|segment| segment.seqs_by_path(path)).collect()
+        };
+        seqs.sort_unstable_by(|a, b| b.cmp(a));
+        seqs.dedup();
+        seqs
+    }
+
+    /// Newest stored seq that really is `did`'s message at `path`: each candidate with the
+    /// path's hash is decompressed and parsed, and taken only if its repo is `did` and one of
+    /// its ops is at `path`. Frames that don't parse are never taken, so a colliding path
+    /// hash can't point a delete at someone else's message. Tombstoned seqs count.
+    pub fn find_verified_by_path(&self, did: &str, path: &str) -> Option<u64> {
+        self.seqs_by_path(path).into_iter().find(|&seq| {
+            let Ok(message) = self.read_stored(seq, None, false) else { return false };
+            crate::parser::core::parse_input(&message).ok().is_some_and(|env| {
+                env.did == Some(did.as_bytes()) && env.ops.iter().any(|op| op.path == path)
+            })
+        })
+    }
+
+    /// Every stored seq of `did`'s messages across the segments, ascending, tombstoned ones
+    /// included. See `Segment::seqs_for_did`.
+    pub fn seqs_for_did(&self, did: &str, dict: Option<&[u8]>) -> Vec<u64> {
+        let effective_dict = dict.or_else(|| self.dict_ref.as_ref().map(|d| &d[..]));
+        let mut seqs: Vec<u64> = {
+            let segments = self.segments.read().unwrap();
+            segments.values().flatten().flat_map(|segment| segment.seqs_for_did(did, effective_dict)).collect()
+        };
+        seqs.sort_unstable();
+        seqs.dedup();
+        seqs
+    }
+
     /// Finds a sequence number by its path hash. 
     /// Note: This performs a linear scan of segments and is intended to be called 
     /// on a specific shard's archive to stay "lean".
@@ -498,17 +1857,30 @@ impl SegmentedArchive {
         None
     }
 
-    pub fn mark_deleted(&self, seq: u64) {
-        if let Some(ts) = &self.tombstones {
-            ts.write().unwrap().mark_deleted(seq);
-        }
+    pub fn mark_deleted(&self, seq: u64) -> Result<(), TombstoneError> {
+        tombstone_store(&self.tombstones)?.write().unwrap().mark_deleted(seq)
+    }
+
+    /// Tombstones `start..end` under one lock; see `TombstoneStore::mark_deleted_range`.
+    pub fn mark_deleted_range(&self, start: u64, end: u64) -> Result<u64, TombstoneError> {
+        tombstone_store(&self.tombstones)?.write().unwrap().mark_deleted_range(start, end)
+    }
+
+    /// Tombstones each of `seqs` under one lock; see `TombstoneStore::mark_deleted_many`.
+    pub fn mark_deleted_many(&self, seqs: &[u64]) -> Result<u64, TombstoneError> {
+        tombstone_store(&self.tombstones)?.write().unwrap().mark_deleted_many(seqs)
+    }
+
+    /// Tombstoned seqs in `start..end`; 0 without a tombstone store.
+    pub fn count_deleted_in(&self, start: u64, end: u64) -> u64 {
+        self.tombstones.as_ref().map_or(0, |ts| ts.read().unwrap().count_deleted_in(start, end))
     }
 
     pub fn verify_integrity_at_seq(&self, seq: u64, dict: Option<&[u8]>) -> io::Result<bool> {
         let segments = self.segments.read().unwrap();
         for (_start, list) in segments.range(..=seq).rev() {
             for segment in list {
-                let msg_count = (segment.idx_mmap.len() - 32) / 28;
+                let msg_count = segment.msg_count();
                 if seq >= segment.start_seq && seq < segment.start_seq + msg_count as u64 {
                     return segment.verify_integrity(dict);
                 }
@@ -517,9 +1889,14 @@ impl SegmentedArchive {
         Err(io::Error::new(io::ErrorKind::NotFound, "Sequence not found"))
     }
 
+    /// Decompression cap applied to every cluster read through this archive.
+    pub fn max_decompressed_cluster_bytes(&self) -> usize {
+        self.max_decompressed
+    }
+
     pub fn get_segment(&self, _start_seq: u64) -> Option<Segment> {
         // Note: Returning Segment by value copies the Mmaps (cheap) but we should be careful.
-        // Actually, Segment doesn't implement Clone easily because of Mutex.
+        // Actually, Segment doesn't implement Clone easily because of the cluster cache.
         // Let's just not provide this or return a reference if needed.
         None
     }
@@ -533,6 +1910,8 @@ pub struct ArchiveWriter {
     current_count: u64,
     max_segment_messages: u64,
     dict: Option<Box<[u8]>>,
+    hash_alg: MerkleAlgorithm,
+    path_hash: PathHash,
     
     // Stats for benchmarking
     pub total_compressed_bytes: u64,
@@ -540,6 +1919,75 @@ pub struct ArchiveWriter {
     // Clustering buffer: DID -> Vec<(Sequence, Path, Data)>
     pending: HashMap<String, Vec<(u64, String, Vec<u8>)>>,
     shard_id: usize,
+    // When the oldest message in `pending` arrived (None while empty)
+    pending_since: Option<Instant>,
+    message_hashes: bool,
+    track_heads: bool,
+    collection_stats: bool,
+    compression: CompressionConfig,
+    dedup: Option<HashWindow>,
+    /// Messages `append_message` dropped as duplicates (see `enable_dedup`)
+    pub duplicates_skipped: u64,
+    wal: Option<wal::Wal>,
+    // `wal.log` holds the replayed pending buffer, but nothing is journaling to it
+    replayed_log: bool,
+    /// Messages `new` recovered from the shard's journal
+    pub wal_replayed: u64,
+}
+
+/// blake3 digests of a shard's pending buffer and its last `segments` segments.
+struct HashWindow {
+    segments: usize,
+    // A digest can sit in several older segments written before dedup was on
+    counts: HashMap<[u8; 32], u32>,
+    /// One list per segment, oldest first; the last one is the pending buffer's
+    lists: VecDeque<Vec<[u8; 32]>>,
+}
+
+impl HashWindow {
+    fn new(segments: usize, history: Vec<Vec<[u8; 32]>>) -> Self {
+        let mut window = HashWindow { segments, counts: HashMap::new(), lists: VecDeque::new() };
+        for list in history {
+            for hash in &list {
+                *window.counts.entry(*hash).or_default() += 1;
+            }
+            window.lists.push_back(list);
+        }
+        window.lists.push_back(Vec::new());
+        window.evict();
+        window
+    }
+
+    /// Adds `hash` to the pending buffer's list. False if it is already in the window.
+    fn insert(&mut self, hash: [u8; 32]) -> bool {
+        if self.counts.contains_key(&hash) {
+            return false;
+        }
+        self.counts.insert(hash, 1);
+        self.lists.back_mut().expect("pending list").push(hash);
+        true
+    }
+
+    /// The pending buffer was taken as a segment.
+    fn seal(&mut self) {
+        if self.lists.back().is_some_and(|l| !l.is_empty()) {
+            self.lists.push_back(Vec::new());
+            self.evict();
+        }
+    }
+
+    fn evict(&mut self) {
+        while self.lists.len() > self.segments + 1 {
+            for hash in self.lists.pop_front().unwrap_or_default() {
+                if let hash_map::Entry::Occupied(mut e) = self.counts.entry(hash) {
+                    *e.get_mut() -= 1;
+                    if *e.get() == 0 {
+                        e.remove();
+                    }
+                }
+            }
+        }
+    }
 }
 
 impl ArchiveWriter {
@@ -550,36 +1998,181 @@ impl ArchiveWriter {
         max_messages: u64,
         dict: Option<Vec<u8>>
     ) -> io::Result<Self> {
+        Self::new_with_compression(dir, shard_id, start_seq, max_messages, dict, CompressionConfig::default())
+    }
+
+    /// Like `new`, but segments, journaled ones replayed on open included, are compressed with
+    /// `compression`. Fails if it doesn't validate.
+    pub fn new_with_compression<P: AsRef<Path>>(
+        dir: P,
+        shard_id: u64,
+        start_seq: u64,
+        max_messages: u64,
+        dict: Option<Vec<u8>>,
+        compression: CompressionConfig,
+    ) -> io::Result<Self> {
+        compression.validate()?;
         if !dir.as_ref().exists() {
             fs::create_dir_all(&dir)?;
         }
 
-        Ok(ArchiveWriter {
+        let mut writer = ArchiveWriter {
             data_dir: dir.as_ref().to_path_buf(),
             current_start_seq: start_seq,
             current_max_seq: 0,
             current_count: 0,
             max_segment_messages: max_messages,
             dict: dict.map(|d| d.into_boxed_slice()),
+            hash_alg: MerkleAlgorithm::default(),
+            path_hash: PathHash::default(),
             total_compressed_bytes: 0,
             pending: HashMap::with_capacity(10000),
             shard_id: shard_id as usize,
-        })
+            pending_since: None,
+            message_hashes: false,
+            track_heads: false,
+            collection_stats: false,
+            compression,
+            dedup: None,
+            duplicates_skipped: 0,
+            wal: None,
+            replayed_log: false,
+            wal_replayed: 0,
+        };
+        writer.replay_wal()?;
+        Ok(writer)
+    }
+
+    /// Takes back the messages a previous writer journaled but never persisted. Each sealed
+    /// journal is written as its own segment under its own start seq, with the writer's
+    /// settings at this point, and removed. `wal.log` becomes the pending buffer, less any
+    /// full segments' worth, which are written the same way. Seqs an existing segment
+    /// already covers are skipped. Journaling only resumes with `enable_wal`.
+    fn replay_wal(&mut self) -> io::Result<()> {
+        let (sealed, log) = wal::replay(&self.data_dir, self.shard_id)?;
+        if sealed.is_empty() && log.is_empty() {
+            return Ok(());
+        }
+        let stored = segment_ranges(&self.data_dir, self.shard_id)?;
+        let unstored = |r: &wal::WalRecord| !stored.iter().any(|(start, max)| (*start..=*max).contains(&r.seq));
+
+        for journal in sealed {
+            let mut records = journal.records.into_iter().filter(unstored).peekable();
+            let mut first = true;
+            while records.peek().is_some() {
+                for r in records.by_ref().take(self.max_segment_messages.max(1) as usize) {
+                    self.wal_replayed += 1;
+                    self.push_pending(r.seq, &r.did, r.path, r.data);
+                }
+                if std::mem::take(&mut first) {
+                    self.current_start_seq = self.current_start_seq.min(journal.start_seq);
+                }
+                let mut payload = self.take_payload();
+                if records.peek().is_none() {
+                    payload.wal = Some(journal.path.clone());
+                }
+                Self::persist_payload(payload, self.dict.as_deref())?;
+            }
+            if first {
+                // Everything in it is already stored
+                fs::remove_file(&journal.path)?;
+            }
+        }
+
+        let had_log = !log.is_empty();
+        let mut log_persisted = false;
+        for r in log.into_iter().filter(unstored) {
+            self.wal_replayed += 1;
+            self.push_pending(r.seq, &r.did, r.path, r.data);
+            if self.current_count >= self.max_segment_messages {
+                let payload = self.take_payload();
+                Self::persist_payload(payload, self.dict.as_deref())?;
+                log_persisted = true;
+            }
+        }
+        if log_persisted || (had_log && self.pending.is_empty()) {
+            // wal.log is cut back to what is still pending
+            wal::Wal::create(&self.data_dir, WalSync::default(), self.pending_records())?;
+        }
+        self.replayed_log = !self.pending.is_empty();
+        tracing::info!("Shard {}: replayed {} journaled messages", self.shard_id, self.wal_replayed);
+        Ok(())
+    }
+
+    /// The pending buffer's messages in seq order, as journal records.
+    fn pending_records(&self) -> Vec<(u64, &str, &str, &[u8])> {
+        let mut pending: Vec<_> = self.pending.iter()
+            .flat_map(|(did, messages)| messages.iter().map(move |(seq, path, data)| (*seq, did.as_str(), path.as_str(), &data[..])))
+            .collect();
+        pending.sort_unstable_by_key(|m| m.0);
+        pending
+    }
+
+    /// Journals every message to `wal.log` before buffering it, so a crash doesn't lose the
+    /// pending buffer. Messages already pending are written first. Calling it again only
+    /// changes `sync`.
+    pub fn enable_wal(&mut self, sync: WalSync) -> io::Result<()> {
+        if let Some(journal) = &mut self.wal {
+            journal.set_sync(sync);
+            return Ok(());
+        }
+        self.wal = Some(wal::Wal::create(&self.data_dir, sync, self.pending_records())?);
+        self.replayed_log = false;
+        Ok(())
+    }
+
+    /// Size of the journal for the messages pending now; 0 without one.
+    pub fn wal_bytes(&self) -> u64 {
+        self.wal.as_ref().map_or(0, wal::Wal::bytes)
+    }
+
+    /// Merkle hash used for segments taken from now on.
+    pub fn set_hash_algorithm(&mut self, alg: MerkleAlgorithm) {
+        self.hash_alg = alg;
+    }
+
+    /// Path hash used for segments taken from now on.
+    pub fn set_path_hash(&mut self, alg: PathHash) {
+        self.path_hash = alg;
+    }
+
+    /// Write a `.mhash` sidecar (the blake3 of every message) with segments taken from now on.
+    pub fn set_message_hashes(&mut self, on: bool) {
+        self.message_hashes = on;
+    }
+
+    /// Record each DID's newest commit in `heads.bin` as segments taken from now on are
+    /// persisted. Costs a parse per DID per segment.
+    pub fn set_track_heads(&mut self, on: bool) {
+        self.track_heads = on;
+    }
+
+    /// Write a `.stats` sidecar (messages and bytes per collection) with segments taken from
+    /// now on.
+    pub fn set_collection_stats(&mut self, on: bool) {
+        self.collection_stats = on;
+    }
+
+    /// Drops messages whose blake3 matches one already in the pending buffer or the last
+    /// `segments` segments. `history` holds those segments' digests, oldest first, as
+    /// `SegmentedArchive::recent_message_hashes` returns them. Turns message hashes on.
+    pub fn enable_dedup(&mut self, segments: usize, history: Vec<Vec<[u8; 32]>>) {
+        self.message_hashes = true;
+        self.dedup = Some(HashWindow::new(segments, history));
     }
 
     /// Appends a message. If full, returns the payload to be persisted in background.
     pub fn append_message(&mut self, seq: u64, did: &str, path: &str, data: &[u8]) -> io::Result<Option<SegmentPayload>> {
-        if self.pending.is_empty() {
-            self.current_start_seq = seq;
-            self.current_max_seq = seq;
-        } else {
-            if seq > self.current_max_seq {
-                self.current_max_seq = seq;
+        if let Some(window) = &mut self.dedup {
+            if !window.insert(*blake3::hash(data).as_bytes()) {
+                self.duplicates_skipped += 1;
+                return Ok(None);
             }
         }
-        
-        self.pending.entry(did.to_string()).or_default().push((seq, path.to_string(), data.to_vec()));
-        self.current_count += 1;
+        if let Some(journal) = &mut self.wal {
+            journal.append(seq, did, path, data)?;
+        }
+        self.push_pending(seq, did, path.to_string(), data.to_vec());
 
         if self.current_count >= self.max_segment_messages {
             let payload = self.take_payload();
@@ -589,6 +2182,18 @@ impl ArchiveWriter {
         Ok(None)
     }
 
+    fn push_pending(&mut self, seq: u64, did: &str, path: String, data: Vec<u8>) {
+        if self.pending.is_empty() {
+            self.current_start_seq = seq;
+            self.current_max_seq = seq;
+            self.pending_since = Some(Instant::now());
+        } else if seq > self.current_max_seq {
+            self.current_max_seq = seq;
+        }
+        self.pending.entry(did.to_string()).or_default().push((seq, path, data));
+        self.current_count += 1;
+    }
+
     /// Manually finalize and persist the current segment (useful for tests/shutdown).
     pub fn finalize_segment(&mut self) -> io::Result<()> {
         let payload = self.take_payload();
@@ -597,230 +2202,890 @@ impl ArchiveWriter {
     }
 
     pub fn take_payload(&mut self) -> SegmentPayload {
+        let wal = match &mut self.wal {
+            Some(journal) if !self.pending.is_empty() => journal.seal(self.current_start_seq)
+                .inspect_err(|e| tracing::warn!("Shard {}: couldn't seal the journal: {}", self.shard_id, e))
+                .ok(),
+            None if std::mem::take(&mut self.replayed_log) => wal::seal_log(&self.data_dir, self.current_start_seq)
+                .inspect_err(|e| tracing::warn!("Shard {}: couldn't seal the replayed journal: {}", self.shard_id, e))
+                .ok(),
+            _ => None,
+        };
         let payload = SegmentPayload {
             start_seq: self.current_start_seq,
             max_seq: self.current_max_seq,
             count: self.current_count,
-            pending: std::mem::take(&mut self.pending),
+            pending: Arc::new(std::mem::take(&mut self.pending)),
             shard_dir: self.data_dir.clone(),
             shard_id: self.shard_id,
+            hash_alg: self.hash_alg,
+            path_hash: self.path_hash,
+            message_hashes: self.message_hashes,
+            track_heads: self.track_heads,
+            collection_stats: self.collection_stats,
+            compression: self.compression,
+            wal,
         };
+        if let Some(window) = &mut self.dedup {
+            window.seal();
+        }
         self.current_count = 0;
         self.current_max_seq = 0;
+        self.pending_since = None;
         payload
     }
 
+    /// True if this writer has buffered messages older than `max_age`.
+    pub fn has_stale_pending(&self, max_age: Duration) -> bool {
+        self.pending_since.is_some_and(|t| t.elapsed() >= max_age)
+    }
+
     /// Flushes a frozen payload to disk. This is STATIC and doesn't hold Writer locks.
     pub fn persist_payload(payload: SegmentPayload, dict: Option<&[u8]>) -> io::Result<u64> {
         if payload.pending.is_empty() { return Ok(0); }
-        use fxhash::FxHasher;
-        use std::hash::{Hasher, Hash};
 
         let base_name = format!("s{}_{}", payload.shard_id, payload.start_seq);
         let bin_path = payload.shard_dir.join(format!("{}.bin", base_name));
         let idx_path = payload.shard_dir.join(format!("{}.idx", base_name));
+        let pidx_path = payload.shard_dir.join(format!("{}.pidx", base_name));
+        if let Some(d) = dict {
+            fs::write(payload.shard_dir.join(format!("{}.{}", base_name, DICT_ID_EXT)), dict_hash(d))?;
+        }
         
         let mut bin_file = File::create(&bin_path)?;
         let mut idx_map = BTreeMap::new(); 
         let mut seq_to_data = HashMap::with_capacity(payload.count as usize);
+        let mut did_index = Vec::with_capacity(payload.count as usize);
 
         let mut current_bin_offset = 0u64;
-        let mut compressor = if let Some(d) = dict {
-            zstd::bulk::Compressor::with_dictionary(3, d)?
-        } else {
-            zstd::bulk::Compressor::new(3)?
-        };
+        let mut compressor = payload.compression.compressor(dict)?;
 
         let mut dids: Vec<_> = payload.pending.keys().collect();
         dids.sort();
+        // Keyed by slices of the paths, so the tally allocates per collection, not per message
+        let mut collection_totals = payload.collection_stats.then(HashMap::<&str, CollStat>::new);
 
         for did in dids {
-            let messages = payload.pending.get(did).unwrap();
-            let mut cluster_raw = Vec::new();
-            let mut header = Vec::with_capacity(2 + messages.len() * 12);
-            header.extend_from_slice(&(messages.len() as u16).to_le_bytes());
+            let did_hash = heads::fx_hash(did.as_str());
+            // A DID with more messages than a cluster holds gets several clusters
+            for messages in payload.pending.get(did).unwrap().chunks(cluster::MAX_CLUSTER_RECORDS) {
+                let mut cluster = ClusterWriter::new();
+                for (seq, path, data) in messages {
+                    if let Some(totals) = &mut collection_totals {
+                        totals.entry(stats::collection_of(path)).or_default().record(data.len());
+                    }
+                    did_index.push((did_hash, *seq));
+                    cluster.push(*seq, data)?;
+                    seq_to_data.insert(*seq, data.clone());
+                }
+                let final_raw = cluster.finish();
 
-            for (seq, _path, data) in messages {
-                header.extend_from_slice(&seq.to_le_bytes());
-                header.extend_from_slice(&(data.len() as u32).to_le_bytes());
-                cluster_raw.extend_from_slice(data);
-                seq_to_data.insert(*seq, data.clone());
-            }
+                let compressed = compressor.compress(&final_raw)?;
+                let compressed_len = compressed.len() as u32;
+                bin_file.write_all(&compressed)?;
 
-            let mut final_raw = header;
-            final_raw.extend_from_slice(&cluster_raw);
+                for (entry, (_seq, path, _data)) in ClusterReader::new(&final_raw)?.iter().zip(messages) {
+                    let path_hash = payload.path_hash.hash(path);
+                    idx_map.insert(entry.seq, (current_bin_offset, compressed_len, entry.offset as u32, entry.data.len() as u32, path_hash));
+                }
 
-            let compressed = compressor.compress(&final_raw)?;
-            let compressed_len = compressed.len() as u32;
-            bin_file.write_all(&compressed)?;
+                current_bin_offset += compressed_len as u64;
+            }
+        }
 
-            let mut current_inner_off = 2 + (messages.len() as u32 * 12);
-            for (seq, path, data) in messages {
-                let mut hasher = FxHasher::default();
-                path.hash(&mut hasher);
-                let path_hash = hasher.finish();
+        let root = payload.hash_alg.root_of((payload.start_seq..=payload.max_seq).filter_map(|seq| seq_to_data.get(&seq)));
 
-                idx_map.insert(*seq, (current_bin_offset, compressed_len, current_inner_off, data.len() as u32, path_hash));
-                current_inner_off += data.len() as u32;
+        // The .idx header carries the algorithm id and compression; sidecars left by an older
+        // build go
+        for ext in [HASH_ALG_EXT, COMPRESSION_EXT] {
+            match fs::remove_file(payload.shard_dir.join(format!("{}.{}", base_name, ext))) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
             }
-
-            current_bin_offset += compressed_len as u64;
         }
 
-        let mut tree = MerkleTree::new();
-        for seq in payload.start_seq..=payload.max_seq {
-            if let Some(data) = seq_to_data.get(&seq) { 
-                tree.push(data); 
+        path_hash::write(&payload.shard_dir.join(format!("{}.{}", base_name, path_hash::PATH_HASH_EXT)), payload.path_hash)?;
+
+        // Like the id, the hashes go down before the .idx makes the segment visible
+        let mhash_path = payload.shard_dir.join(format!("{}.{}", base_name, MESSAGE_HASH_EXT));
+        if payload.message_hashes {
+            let mut hashes: Vec<([u8; 32], u64)> = seq_to_data.iter().map(|(seq, data)| (*blake3::hash(data).as_bytes(), *seq)).collect();
+            hashes.sort_unstable();
+            let mut mhash_buf = Vec::with_capacity(hashes.len() * MESSAGE_HASH_ENTRY_SIZE);
+            for (hash, seq) in hashes {
+                mhash_buf.extend_from_slice(&hash);
+                mhash_buf.extend_from_slice(&seq.to_le_bytes());
+            }
+            let mut mhash_file = File::create(&mhash_path)?;
+            mhash_file.write_all(&mhash_buf)?;
+            mhash_file.sync_all()?;
+        } else {
+            match fs::remove_file(&mhash_path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
             }
         }
-        let root = tree.root();
 
-        let mut idx_file = File::create(&idx_path)?;
-        idx_file.write_all(root.as_bytes())?;
-        for seq in payload.start_seq..=payload.max_seq {
-            let (bin_off, c_len, inner_off, i_len, path_hash) = idx_map.get(&seq).cloned().unwrap_or((0,0,0,0,0));
-            idx_file.write_all(&bin_off.to_le_bytes())?;
-            idx_file.write_all(&c_len.to_le_bytes())?;
-            idx_file.write_all(&inner_off.to_le_bytes())?;
-            idx_file.write_all(&i_len.to_le_bytes())?;
-            idx_file.write_all(&path_hash.to_le_bytes())?;
+        let stats_path = payload.shard_dir.join(format!("{}.{}", base_name, stats::STATS_EXT));
+        match &collection_totals {
+            Some(totals) => stats::write(&stats_path, totals)?,
+            None => match fs::remove_file(&stats_path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            },
         }
 
+        did_index.sort_unstable();
+        let mut didx_buf = Vec::with_capacity(did_index.len() * DID_INDEX_ENTRY_SIZE);
+        for (did_hash, seq) in did_index {
+            didx_buf.extend_from_slice(&did_hash.to_le_bytes());
+            didx_buf.extend_from_slice(&seq.to_le_bytes());
+        }
+        let mut didx_file = File::create(payload.shard_dir.join(format!("{}.{}", base_name, DID_INDEX_EXT)))?;
+        didx_file.write_all(&didx_buf)?;
+        didx_file.sync_all()?;
+
+        let idx_buf = encode_idx(&root, payload.hash_alg, payload.compression, payload.start_seq, payload.max_seq, &idx_map);
+        let mut path_index: Vec<(u64, u64)> = (payload.start_seq..=payload.max_seq)
+            .map(|seq| (idx_map.get(&seq).map_or(0, |r| r.4), seq))
+            .collect();
+
+        // Gaps are included (hash 0) so a lookup answers exactly what a linear scan would
+        path_index.sort_unstable();
+        let mut pidx_buf = Vec::with_capacity(path_index.len() * PATH_INDEX_ENTRY_SIZE);
+        for (path_hash, seq) in path_index {
+            pidx_buf.extend_from_slice(&path_hash.to_le_bytes());
+            pidx_buf.extend_from_slice(&seq.to_le_bytes());
+        }
+        let mut pidx_file = File::create(&pidx_path)?;
+        pidx_file.write_all(&pidx_buf)?;
+
         bin_file.sync_all()?;
+        pidx_file.sync_all()?;
+
+        // The .idx is renamed into place last: `refresh` keeps a segment mapped until its .idx
+        // changes, so it must never pick up a half-written one.
+        let idx_tmp = payload.shard_dir.join(format!("{}.idx.tmp", base_name));
+        let mut idx_file = File::create(&idx_tmp)?;
+        idx_file.write_all(&idx_buf)?;
         idx_file.sync_all()?;
+        fs::rename(&idx_tmp, &idx_path)?;
+
+        // After the rename, so a head never names a seq readers can't find
+        if payload.track_heads {
+            heads::append(&payload.shard_dir, &heads::payload_heads(&payload.pending))?;
+        }
+        if let Some(journal) = &payload.wal {
+            fs::remove_file(journal)?;
+        }
         Ok(current_bin_offset)
     }
 
 
 }
 
+const META_FILE: &str = "archive_meta.json";
+/// One `seek_by_time` sample is kept per this many seqs.
+const TIME_INDEX_STRIDE: u64 = 4096;
+
+/// Archive topology, persisted at the archive root so later runs route DIDs the same way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveMeta {
+    pub num_shards: usize,
+}
+
+impl ArchiveMeta {
+    pub fn load(root: &Path) -> io::Result<Option<Self>> {
+        let path = root.join(META_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let data = fs::read(&path)?;
+        serde_json::from_slice(&data)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn save(&self, root: &Path) -> io::Result<()> {
+        let json = serde_json::to_vec_pretty(self).map_err(io::Error::other)?;
+        fs::write(root.join(META_FILE), json)
+    }
+}
+
+/// Algorithm id from a `.hashalg` sidecar; 0 (blake3) when there isn't one.
+fn read_hash_alg_id(path: &Path) -> u8 {
+    fs::read(path).ok().and_then(|b| b.first().copied()).unwrap_or_else(|| MerkleAlgorithm::default().id())
+}
+
+/// Where an `.idx`'s records start, and the `MerkleAlgorithm` id if its header carries one.
+/// Takes the whole index or just its first `IDX_ALG_HEADER_SIZE` bytes.
+fn idx_header(idx: &[u8]) -> (usize, Option<u8>) {
+    match idx.get(IDX_HEADER_SIZE..=IDX_HEADER_SIZE + IDX_ALG_MAGIC.len()) {
+        Some(tail) if tail[..IDX_ALG_MAGIC.len()] == IDX_ALG_MAGIC => (IDX_ALG_HEADER_SIZE, Some(tail[IDX_ALG_MAGIC.len()])),
+        _ => (IDX_HEADER_SIZE, None),
+    }
+}
+
+/// Byte offset of the first record in an `.idx`, for tools that walk the records themselves.
+pub fn idx_records_offset(idx: &[u8]) -> usize {
+    idx_header(idx).0
+}
+
+/// Up to the first `IDX_ALG_HEADER_SIZE` bytes of the `.idx` at `path`, enough for `idx_header`.
+fn read_idx_prefix(path: &Path) -> io::Result<Vec<u8>> {
+    use std::io::Read;
+    let mut prefix = Vec::with_capacity(IDX_ALG_HEADER_SIZE);
+    File::open(path)?.take(IDX_ALG_HEADER_SIZE as u64).read_to_end(&mut prefix)?;
+    Ok(prefix)
+}
+
+/// `MerkleAlgorithm` id of the segment at `bin_path` whose `.idx` starts with `prefix`: from the
+/// header, else a legacy `.hashalg` sidecar, else blake3.
+fn segment_hash_alg_id(prefix: &[u8], bin_path: &Path) -> u8 {
+    idx_header(prefix).1.unwrap_or_else(|| read_hash_alg_id(&bin_path.with_extension(HASH_ALG_EXT)))
+}
+
+/// `CompressionConfig` an `.idx` header records, if it has one. Takes the whole index or
+/// just its first `IDX_ALG_HEADER_SIZE` bytes.
+fn idx_compression(idx: &[u8]) -> Option<CompressionConfig> {
+    if idx_header(idx).0 != IDX_ALG_HEADER_SIZE {
+        return None;
+    }
+    let tail = idx.get(IDX_COMPRESSION_AT..IDX_ALG_HEADER_SIZE)?;
+    if tail[0] != 1 {
+        return None;
+    }
+    CompressionConfig::from_bytes(&tail[1..])
+}
+
+/// An `.idx` header: `root`, the `MerkleAlgorithm` id and `compression`.
+fn encode_idx_header(root: &[u8; 32], alg_id: u8, compression: CompressionConfig, capacity: usize) -> Vec<u8> {
+    let mut idx_buf = Vec::with_capacity(IDX_ALG_HEADER_SIZE + capacity);
+    idx_buf.extend_from_slice(root);
+    idx_buf.extend_from_slice(&IDX_ALG_MAGIC);
+    idx_buf.push(alg_id);
+    idx_buf.push(1);
+    idx_buf.extend_from_slice(&compression.to_bytes());
+    idx_buf
+}
+
+/// An `.idx` for seqs `start_seq..=max_seq`: the root, `alg`'s id and `compression`, then each
+/// seq's (bin_off, c_len, inner_off, len, path_hash) from `records`, zeroed for gaps.
+fn encode_idx(
+    root: &[u8; 32],
+    alg: MerkleAlgorithm,
+    compression: CompressionConfig,
+    start_seq: u64,
+    max_seq: u64,
+    records: &BTreeMap<u64, (u64, u32, u32, u32, u64)>,
+) -> Vec<u8> {
+    let count = (max_seq - start_seq + 1) as usize;
+    let mut idx_buf = encode_idx_header(root, alg.id(), compression, count * IDX_RECORD_SIZE);
+    for seq in start_seq..=max_seq {
+        let (bin_off, c_len, inner_off, i_len, path_hash) = records.get(&seq).cloned().unwrap_or((0, 0, 0, 0, 0));
+        idx_buf.extend_from_slice(&bin_off.to_le_bytes());
+        idx_buf.extend_from_slice(&c_len.to_le_bytes());
+        idx_buf.extend_from_slice(&inner_off.to_le_bytes());
+        idx_buf.extend_from_slice(&i_len.to_le_bytes());
+        idx_buf.extend_from_slice(&path_hash.to_le_bytes());
+    }
+    idx_buf
+}
+
+/// `(start_seq, max_seq)` of each segment shard `shard_id` has written to `dir`, from the
+/// `.idx` names and sizes.
+fn segment_ranges(dir: &Path, shard_id: usize) -> io::Result<Vec<(u64, u64)>> {
+    let prefix = format!("s{}_", shard_id);
+    let mut ranges = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(start) = name.to_str().and_then(|n| n.strip_prefix(&prefix)?.strip_suffix(".idx")?.parse::<u64>().ok()) else { continue };
+        let records_at = idx_header(&read_idx_prefix(&entry.path())?).0;
+        let records = (entry.metadata()?.len() as usize).saturating_sub(records_at) / IDX_RECORD_SIZE;
+        if records > 0 {
+            ranges.push((start, start + records as u64 - 1));
+        }
+    }
+    Ok(ranges)
+}
+
+/// Start seq of a segment file stem: either "123" or "shard_X_123".
+fn stem_start_seq(stem: &str) -> Option<u64> {
+    stem.find('_').and_then(|i| stem[i + 1..].parse::<u64>().ok()).or_else(|| stem.parse::<u64>().ok())
+}
+
+/// Swaps the `.bin.new`/`.idx.new` pair `recompress_segment` wrote in for the segment at
+/// `bin_path`. A step that fails puts the old pair back; if that fails too, the commit marker
+/// stays and the next scan finishes the swap with `finish_swap`.
+fn swap_segment_files(bin_path: &Path) -> io::Result<()> {
+    let path = |ext: &str| bin_path.with_extension(ext);
+    fs::rename(path(SWAP_IDX_EXT), path(SWAP_COMMIT_EXT))?;
+    let uncommit = || fs::rename(path(SWAP_COMMIT_EXT), path(SWAP_IDX_EXT));
+    let restore_old = || fs::rename(path(SWAP_OLD_BIN_EXT), bin_path).and_then(|_| uncommit());
+    let (failed, rolled_back) = if let Err(e) = fs::rename(bin_path, path(SWAP_OLD_BIN_EXT)) {
+        (e, uncommit())
+    } else if let Err(e) = fs::rename(path(SWAP_BIN_EXT), bin_path) {
+        (e, restore_old())
+    } else if let Err(e) = fs::rename(path(SWAP_COMMIT_EXT), path("idx")) {
+        (e, fs::rename(bin_path, path(SWAP_BIN_EXT)).and_then(|_| restore_old()))
+    } else {
+        return match fs::remove_file(path(SWAP_OLD_BIN_EXT)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        };
+    };
+    if let Err(e) = rolled_back {
+        tracing::warn!("{}: couldn't roll back a failed recompression, the next scan finishes it: {}", bin_path.display(), e);
+    }
+    Err(failed)
+}
+
+/// Completes a swap `swap_segment_files` committed but didn't finish: the new `.bin` and
+/// `.idx` go in place, whichever renames already happened, and the old `.bin` goes.
+fn finish_swap(bin_path: &Path) -> io::Result<()> {
+    let path = |ext: &str| bin_path.with_extension(ext);
+    let done_if_missing = |result: io::Result<()>| match result {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    };
+    done_if_missing(fs::rename(path(SWAP_BIN_EXT), bin_path))?;
+    done_if_missing(fs::rename(path(SWAP_COMMIT_EXT), path("idx")))?;
+    done_if_missing(fs::remove_file(path(SWAP_OLD_BIN_EXT)))
+}
+
+/// Settings from a legacy `.zcfg` sidecar; the default when there isn't one or it doesn't parse.
+fn read_compression(path: &Path) -> CompressionConfig {
+    fs::read(path).ok().and_then(|b| CompressionConfig::from_bytes(&b)).unwrap_or_default()
+}
+
+/// Number of consecutive `shard_N` directories under `root` (archives written before the meta file).
+fn count_shard_dirs(root: &Path) -> usize {
+    (0..).take_while(|i| root.join(format!("shard_{}", i)).is_dir()).count()
+}
+
+/// Routes a DID to a shard. Must stay stable for an archive's lifetime.
+fn shard_for_did(did: &str, num_shards: usize) -> usize {
+    use fxhash::FxHasher;
+    use std::hash::{Hasher, Hash};
+
+    let mut hasher = FxHasher::default();
+    did.hash(&mut hasher);
+    hasher.finish() as usize % num_shards
+}
+
+/// Persister threads `MultiShardArchive::new` starts: one per shard, up to the CPU count.
+fn default_persist_threads(num_shards: usize) -> usize {
+    num_shards.min(num_cpus::get()).max(1)
+}
+
+/// A background persister: writes payloads off `rx` until it takes a poison pill. Each
+/// payload leaves `pending` once it is on disk; one that fails stays readable from there.
+/// Holds `gate` for reading while writing, so `pause_persistence` can stop it in between.
+fn spawn_persister(rx: Receiver<Option<SegmentPayload>>, dict: Option<Arc<Vec<u8>>>, pending: Arc<PendingIndex>, gate: Arc<RwLock<()>>) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        while let Ok(Some(payload)) = rx.recv() {
+            let _open = gate.read().unwrap();
+            // An empty payload was never registered, and may share its start with one that was
+            let (shard_id, start_seq, registered) = (payload.shard_id, payload.start_seq, !payload.pending.is_empty());
+            match ArchiveWriter::persist_payload(payload, dict.as_ref().map(|d| &d[..])) {
+                Ok(_) if registered => pending.unregister(shard_id, start_seq),
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Shard {}: segment at seq {} not persisted, keeping it in memory: {}", shard_id, start_seq, e),
+            }
+        }
+    })
+}
+
+/// Registers `payload` with `pending` and queues it for a persister.
+fn hand_off(pending: &PendingIndex, tx: &Sender<Option<SegmentPayload>>, payload: SegmentPayload) {
+    pending.register(&payload);
+    let _ = tx.send(Some(payload));
+}
+
 pub struct MultiShardArchive {
-    writers: Vec<Mutex<ArchiveWriter>>,
+    writers: Arc<Vec<Mutex<ArchiveWriter>>>,
     readers: Vec<SegmentedArchive>,
     persist_tx: Sender<Option<SegmentPayload>>, // Option for Poison Pill
+    persist_rx: Receiver<Option<SegmentPayload>>,
     dict_ref: Option<Arc<Vec<u8>>>,
-    persist_thread: Mutex<Option<thread::JoinHandle<()>>>,
+    // One pill per thread on shutdown
+    persist_threads: Mutex<Vec<thread::JoinHandle<()>>>,
     tombstones: Option<Arc<RwLock<TombstoneStore>>>,
+    flush_running: Arc<AtomicBool>,
+    flush_thread: Mutex<Option<thread::JoinHandle<()>>>,
+    // Sparse seq -> record TID timestamp (µs) samples collected by `seek_by_time`
+    time_index: RwLock<BTreeMap<u64, u64>>,
+    open_report: ConsistencyReport,
+    // Shared by every reader
+    cluster_cache: Arc<ClusterCache>,
+    root: PathBuf,
+    // Payloads queued or being written, readable until their segment is on disk
+    pending: Arc<PendingIndex>,
+    // `pending.persisted()` as of the last `refresh`
+    refreshed_at: AtomicU64,
+    persist_gate: Arc<RwLock<()>>,
+}
+
+/// Holds the background persisters between segments until dropped; see
+/// `MultiShardArchive::pause_persistence`.
+pub struct PersistPause<'a> {
+    _gate: std::sync::RwLockWriteGuard<'a, ()>,
 }
 
 impl MultiShardArchive {
     pub fn open_readonly(path: impl AsRef<Path>, dict: Option<Vec<u8>>) -> io::Result<Self> {
-        let path = path.as_ref();
+        Self::open_readonly_with_limit(path, dict, DEFAULT_MAX_DECOMPRESSED_CLUSTER_BYTES)
+    }
+
+    /// Like `open_readonly`, but fails with `InvalidData` if `consistency_check` finds anything.
+    pub fn open_readonly_strict(path: impl AsRef<Path>, dict: Option<Vec<u8>>) -> io::Result<Self> {
+        Self::open_readonly_inner(path.as_ref(), dict, DEFAULT_MAX_DECOMPRESSED_CLUSTER_BYTES, true)
+    }
+
+    /// Read-only open with an explicit cap on cluster decompression, for serving
+    /// archives whose contents weren't produced locally.
+    pub fn open_readonly_with_limit(path: impl AsRef<Path>, dict: Option<Vec<u8>>, max_decompressed: usize) -> io::Result<Self> {
+        Self::open_readonly_inner(path.as_ref(), dict, max_decompressed, false)
+    }
+
+    fn open_readonly_inner(path: &Path, dict: Option<Vec<u8>>, max_decompressed: usize, strict: bool) -> io::Result<Self> {
         let ts_path = path.join("tombstones.bin");
         let tombstones = TombstoneStore::open_or_create(&ts_path).ok().map(|ts| Arc::new(RwLock::new(ts)));
         let dict_arc = dict.map(Arc::new);
+        let cluster_cache = Arc::new(ClusterCache::default());
         
         let mut readers = Vec::new();
+        let expected_shards = ArchiveMeta::load(path)?.map(|m| m.num_shards);
         // Scan for shard_N directories
         let mut shard_idx = 0;
         loop {
             let shard_dir = path.join(format!("shard_{}", shard_idx));
+            if expected_shards.is_some_and(|n| shard_idx >= n) { break; }
             if !shard_dir.exists() { break; }
-            readers.push(SegmentedArchive::open_directory(shard_dir, tombstones.clone(), dict_arc.clone())?);
+            readers.push(SegmentedArchive::open_directory_with_limit(shard_dir, tombstones.clone(), dict_arc.clone(), max_decompressed)?
+                .with_cluster_cache(Arc::clone(&cluster_cache)));
             shard_idx += 1;
         }
 
         if readers.is_empty() {
             // Try opening the root as a single shard if no shard_N found
-            readers.push(SegmentedArchive::open_directory(path, tombstones.clone(), dict_arc.clone())?);
+            readers.push(SegmentedArchive::open_directory_with_limit(path, tombstones.clone(), dict_arc.clone(), max_decompressed)?
+                .with_cluster_cache(Arc::clone(&cluster_cache)));
         }
 
-        let (tx, _) = unbounded::<Option<SegmentPayload>>();
+        let open_report = Self::check_on_open(path, &readers, tombstones.as_ref(), strict)?;
+        let (tx, rx) = unbounded::<Option<SegmentPayload>>();
         
         Ok(Self {
-            writers: Vec::new(),
+            writers: Arc::new(Vec::new()),
             readers,
             persist_tx: tx,
+            persist_rx: rx,
             dict_ref: dict_arc,
-            persist_thread: Mutex::new(None),
+            persist_threads: Mutex::new(Vec::new()),
             tombstones,
+            flush_running: Arc::new(AtomicBool::new(false)),
+            flush_thread: Mutex::new(None),
+            time_index: RwLock::new(BTreeMap::new()),
+            open_report,
+            cluster_cache,
+            root: path.to_path_buf(),
+            pending: Arc::new(PendingIndex::default()),
+            refreshed_at: AtomicU64::new(0),
+            persist_gate: Arc::new(RwLock::new(())),
         })
     }
 
     pub fn new(path: impl AsRef<Path>, num_shards: usize, segment_size: u64, dict: Option<Vec<u8>>) -> io::Result<Self> {
-        let path = path.as_ref();
+        Self::new_with_force(path, num_shards, segment_size, dict, false)
+    }
+
+    /// Like `new`, but with `force` an existing archive may be reopened with a different
+    /// shard count. Historical data then sits in the wrong shards for path lookups; use
+    /// `reshard` to move it instead unless that's acceptable.
+    pub fn new_with_force(path: impl AsRef<Path>, num_shards: usize, segment_size: u64, dict: Option<Vec<u8>>, force: bool) -> io::Result<Self> {
+        Self::new_inner(path.as_ref(), num_shards, segment_size, dict, CompressionConfig::default(), force, false)
+    }
+
+    /// Like `new_with_force`, but every shard compresses its segments with `compression`.
+    /// Existing segments keep theirs; see `SegmentedArchive::recompress_segment` to change them.
+    pub fn new_with_compression(
+        path: impl AsRef<Path>,
+        num_shards: usize,
+        segment_size: u64,
+        dict: Option<Vec<u8>>,
+        compression: CompressionConfig,
+        force: bool,
+    ) -> io::Result<Self> {
+        Self::new_inner(path.as_ref(), num_shards, segment_size, dict, compression, force, false)
+    }
+
+    /// Like `new`, but fails with `InvalidData` if `consistency_check` finds anything.
+    pub fn new_strict(path: impl AsRef<Path>, num_shards: usize, segment_size: u64, dict: Option<Vec<u8>>) -> io::Result<Self> {
+        Self::new_inner(path.as_ref(), num_shards, segment_size, dict, CompressionConfig::default(), false, true)
+    }
+
+    fn new_inner(
+        path: &Path,
+        num_shards: usize,
+        segment_size: u64,
+        dict: Option<Vec<u8>>,
+        compression: CompressionConfig,
+        force: bool,
+        strict: bool,
+    ) -> io::Result<Self> {
+        compression.validate()?;
+        if num_shards == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "num_shards must be > 0"));
+        }
         if !path.exists() {
             fs::create_dir_all(path)?;
         }
 
+        let existing = match ArchiveMeta::load(path)? {
+            Some(meta) => meta.num_shards,
+            None => count_shard_dirs(path),
+        };
+        if existing != 0 && existing != num_shards && !force {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("archive at {} has {} shards, requested {} (reshard it or pass force)", path.display(), existing, num_shards),
+            ));
+        }
+        ArchiveMeta { num_shards }.save(path)?;
+
         let ts_path = path.join("tombstones.bin");
         let tombstones = TombstoneStore::open_or_create(&ts_path).ok().map(|ts| Arc::new(RwLock::new(ts)));
 
         let dict_arc = dict.map(Arc::new);
+        let cluster_cache = Arc::new(ClusterCache::default());
         let mut writers = Vec::new();
         let mut readers = Vec::new();
         for i in 0..num_shards {
             let shard_dir = path.join(format!("shard_{}", i));
             let start_seq = 0; 
-            writers.push(Mutex::new(ArchiveWriter::new(shard_dir.clone(), i as u64, start_seq, segment_size, dict_arc.as_ref().map(|d| d.to_vec()))?));
-            readers.push(SegmentedArchive::open_directory(shard_dir, tombstones.clone(), dict_arc.clone())?);
+            writers.push(Mutex::new(ArchiveWriter::new_with_compression(
+                shard_dir.clone(),
+                i as u64,
+                start_seq,
+                segment_size,
+                dict_arc.as_ref().map(|d| d.to_vec()),
+                compression,
+            )?));
+            readers.push(SegmentedArchive::open_directory(shard_dir, tombstones.clone(), dict_arc.clone())?
+                .with_cluster_cache(Arc::clone(&cluster_cache)));
         }
+        let open_report = Self::check_on_open(path, &readers, tombstones.as_ref(), strict)?;
 
         let (tx, rx) = unbounded::<Option<SegmentPayload>>();
-        let dict_for_thread = dict_arc.clone();
-        
-        // Background Persister Thread
-        let handle = thread::spawn(move || {
-            while let Ok(maybe_payload) = rx.recv() {
-                if let Some(payload) = maybe_payload {
-                    let _ = ArchiveWriter::persist_payload(payload, dict_for_thread.as_ref().map(|d| &d[..]));
-                } else {
-                    break; // Poison Pill received
-                }
-            }
-        });
+        let pending = Arc::new(PendingIndex::default());
+        let persist_gate = Arc::new(RwLock::new(()));
+        let persist_threads = (0..default_persist_threads(num_shards))
+            .map(|_| spawn_persister(rx.clone(), dict_arc.clone(), pending.clone(), persist_gate.clone()))
+            .collect();
 
         Ok(Self {
-            writers,
+            writers: Arc::new(writers),
             readers,
             persist_tx: tx,
+            persist_rx: rx,
             dict_ref: dict_arc,
-            persist_thread: Mutex::new(Some(handle)),
+            persist_threads: Mutex::new(persist_threads),
             tombstones,
+            flush_running: Arc::new(AtomicBool::new(false)),
+            flush_thread: Mutex::new(None),
+            time_index: RwLock::new(BTreeMap::new()),
+            open_report,
+            cluster_cache,
+            root: path.to_path_buf(),
+            pending,
+            refreshed_at: AtomicU64::new(0),
+            persist_gate,
         })
     }
 
-    pub fn ingest(&self, seq: u64, did: &str, path: String, msg: Vec<u8>) {
-        use fxhash::FxHasher;
-        use std::hash::{Hasher, Hash};
+    fn check_on_open(
+        path: &Path,
+        readers: &[SegmentedArchive],
+        tombstones: Option<&Arc<RwLock<TombstoneStore>>>,
+        strict: bool,
+    ) -> io::Result<ConsistencyReport> {
+        let report = consistency::check(readers, tombstones);
+        if !report.is_consistent() {
+            if strict {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("archive at {} is inconsistent: {}", path.display(), report)));
+            }
+            tracing::warn!("Archive at {}: {}", path.display(), report);
+        }
+        Ok(report)
+    }
+
+    /// Re-runs the startup cross-check of segments and tombstones against the files as they are now.
+    /// Add cursors with `ConsistencyReport::check_cursors`.
+    pub fn consistency_check(&self) -> ConsistencyReport {
+        consistency::check(&self.readers, self.tombstones.as_ref())
+    }
+
+    /// What the check run while opening the archive found.
+    pub fn consistency_report(&self) -> &ConsistencyReport {
+        &self.open_report
+    }
+
+    /// Starts a background timer that hands any shard's buffer to the persister once its
+    /// oldest message has waited `max_age`, so quiet shards still reach disk promptly.
+    /// Calling it again replaces the previous timer.
+    pub fn start_idle_flush(&self, max_age: Duration) {
+        self.stop_idle_flush();
+        if self.writers.is_empty() { return; }
+
+        self.flush_running.store(true, Ordering::Relaxed);
+        let running = self.flush_running.clone();
+        let writers = self.writers.clone();
+        let tx = self.persist_tx.clone();
+        let pending = self.pending.clone();
+        let tick = (max_age / 4).clamp(Duration::from_millis(10), Duration::from_secs(1));
+
+        let handle = thread::spawn(move || {
+            while running.load(Ordering::Relaxed) {
+                thread::sleep(tick);
+                for writer in writers.iter() {
+                    let mut w = writer.lock().unwrap();
+                    if w.has_stale_pending(max_age) {
+                        hand_off(&pending, &tx, w.take_payload());
+                    }
+                }
+            }
+        });
+
+        *self.flush_thread.lock().unwrap() = Some(handle);
+    }
+
+    fn stop_idle_flush(&self) {
+        self.flush_running.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.flush_thread.lock().unwrap().take() {
+            let _ = handle.join();
+        }
+    }
+
+    /// Merkle hash for the segments every shard writes from now on. Existing segments keep
+    /// the algorithm they were written with.
+    pub fn set_hash_algorithm(&self, alg: MerkleAlgorithm) {
+        for writer in self.writers.iter() {
+            writer.lock().unwrap().set_hash_algorithm(alg);
+        }
+    }
+
+    /// Path hash for the segments every shard writes from now on (blake3 by default).
+    /// Existing segments keep the one they were written with, and lookups by path hash each
+    /// segment with its own.
+    pub fn set_path_hash(&self, alg: PathHash) {
+        for writer in self.writers.iter() {
+            writer.lock().unwrap().set_path_hash(alg);
+        }
+    }
+
+    /// Number of background threads persisting finished segments; `new` starts
+    /// `min(shards, cpus)`. Waits for the segments already queued to be written by the
+    /// current threads, then starts `threads` (at least one) new ones.
+    pub fn set_persist_threads(&self, threads: usize) {
+        if self.writers.is_empty() { return; }
+        let mut handles = self.persist_threads.lock().unwrap();
+        for _ in 0..handles.len() {
+            let _ = self.persist_tx.send(None);
+        }
+        for handle in handles.drain(..) {
+            let _ = handle.join();
+        }
+        handles.extend((0..threads.max(1)).map(|_| spawn_persister(self.persist_rx.clone(), self.dict_ref.clone(), self.pending.clone(), self.persist_gate.clone())));
+    }
+
+    pub fn persist_threads(&self) -> usize {
+        self.persist_threads.lock().unwrap().len()
+    }
+
+    /// Write `.mhash` sidecars with the segments every shard writes from now on.
+    pub fn set_message_hashes(&self, on: bool) {
+        for writer in self.writers.iter() {
+            writer.lock().unwrap().set_message_hashes(on);
+        }
+    }
+
+    /// Track each DID's newest commit (`latest_for_did`) in the segments every shard writes
+    /// from now on.
+    pub fn set_track_heads(&self, on: bool) {
+        for writer in self.writers.iter() {
+            writer.lock().unwrap().set_track_heads(on);
+        }
+    }
+
+    /// Write `.stats` sidecars with the segments every shard writes from now on.
+    pub fn set_collection_stats(&self, on: bool) {
+        for writer in self.writers.iter() {
+            writer.lock().unwrap().set_collection_stats(on);
+        }
+    }
+
+    /// `SegmentedArchive::collection_stats` over every shard.
+    pub fn collection_stats(&self, seqs: std::ops::Range<u64>) -> HashMap<String, CollStat> {
+        let mut totals = HashMap::new();
+        for reader in &self.readers {
+            stats::merge(&mut totals, &reader.collection_stats(seqs.clone()));
+        }
+        totals
+    }
+
+    /// Skips ingested messages whose blake3 is already in their shard's pending buffer or its
+    /// last `segments` segments, and turns message hashes on. The window starts from the
+    /// `.mhash` sidecars on disk, so a restarted ingester still recognizes frames archived
+    /// before it stopped (if they were written with hashes on).
+    pub fn enable_dedup(&self, segments: usize) -> io::Result<()> {
+        for (writer, reader) in self.writers.iter().zip(&self.readers) {
+            reader.refresh()?;
+            let history = reader.recent_message_hashes(segments);
+            writer.lock().unwrap().enable_dedup(segments, history);
+        }
+        Ok(())
+    }
+
+    /// Journals every shard's pending buffer (see `ArchiveWriter::enable_wal`).
+    pub fn enable_wal(&self, sync: WalSync) -> io::Result<()> {
+        for writer in self.writers.iter() {
+            writer.lock().unwrap().enable_wal(sync)?;
+        }
+        Ok(())
+    }
+
+    /// Bytes in the shards' journals.
+    pub fn wal_bytes(&self) -> u64 {
+        self.writers.iter().map(|w| w.lock().unwrap().wal_bytes()).sum()
+    }
+
+    /// Messages recovered from the shards' journals when the archive was opened.
+    pub fn wal_replayed(&self) -> u64 {
+        self.writers.iter().map(|w| w.lock().unwrap().wal_replayed).sum()
+    }
+
+    /// Messages dropped as duplicates since `enable_dedup`.
+    pub fn duplicates_skipped(&self) -> u64 {
+        self.writers.iter().map(|w| w.lock().unwrap().duplicates_skipped).sum()
+    }
+
+    /// Sequence of an archived message with this blake3, from segments written with message
+    /// hashes on. Call `refresh` first to see recently persisted segments.
+    pub fn contains_hash(&self, hash: &[u8; 32]) -> Option<u64> {
+        self.readers.iter().find_map(|r| r.contains_hash(hash))
+    }
 
-        let mut hasher = FxHasher::default();
-        did.hash(&mut hasher);
-        let shard_idx = hasher.finish() as usize % self.writers.len(); 
+    pub fn ingest(&self, seq: u64, did: &str, path: String, msg: Vec<u8>) {
+        let shard_idx = shard_for_did(did, self.writers.len());
 
         let mut writer = self.writers[shard_idx].lock().unwrap();
-        if let Ok(Some(payload)) = writer.append_message(seq, did, &path, &msg) {
-            let _ = self.persist_tx.send(Some(payload));
+        match writer.append_message(seq, did, &path, &msg) {
+            Ok(Some(payload)) => hand_off(&self.pending, &self.persist_tx, payload),
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Dropped seq {}: {}", seq, e),
         }
     }
 
-    pub fn mark_deleted(&self, seq: u64) {
-        if let Some(ts) = &self.tombstones {
-            ts.write().unwrap().mark_deleted(seq);
+    /// Appends many messages, taking each shard's lock once. Every shard the batch touches is
+    /// locked before anything is appended, so a concurrent idle flush or `shutdown` sees either
+    /// all of the batch or none of it. Produces the same segments as calling `ingest` per message.
+    pub fn ingest_batch(&self, msgs: &[(u64, &str, String, Vec<u8>)]) {
+        let num_shards = self.writers.len();
+        let mut groups: Vec<Vec<usize>> = vec![Vec::new(); num_shards];
+        for (i, (_, did, _, _)) in msgs.iter().enumerate() {
+            groups[shard_for_did(did, num_shards)].push(i);
+        }
+
+        // Locks are always taken in ascending shard order, so concurrent batches can't deadlock
+        let mut locked: Vec<_> = groups.iter().enumerate()
+            .filter(|(_, group)| !group.is_empty())
+            .map(|(shard_idx, group)| (self.writers[shard_idx].lock().unwrap(), group))
+            .collect();
+        for (writer, group) in locked.iter_mut() {
+            for &i in group.iter() {
+                let (seq, did, path, msg) = &msgs[i];
+                match writer.append_message(*seq, did, path, msg) {
+                    Ok(Some(payload)) => hand_off(&self.pending, &self.persist_tx, payload),
+                    Ok(None) => {}
+                    Err(e) => tracing::warn!("Dropped seq {}: {}", seq, e),
+                }
+            }
         }
     }
 
-    pub fn delete_by_path(&self, did: &str, path: &str) {
-        use fxhash::FxHasher;
-        use std::hash::{Hasher, Hash};
+    /// `ingest` for async callers. The append runs on tokio's blocking pool, so a contended
+    /// shard lock or a segment rollover never stalls a runtime worker. Must be called from
+    /// within a tokio runtime; fails only if the blocking task panicked.
+    pub async fn ingest_async(self: Arc<Self>, seq: u64, did: String, path: String, msg: Vec<u8>) -> io::Result<()> {
+        tokio::task::spawn_blocking(move || self.ingest(seq, &did, path, msg)).await.map_err(io::Error::other)
+    }
 
-        let mut hasher = FxHasher::default();
-        did.hash(&mut hasher);
-        let shard_idx = hasher.finish() as usize % self.readers.len();
-        
-        let path_hasher = {
-            let mut h = FxHasher::default();
-            path.hash(&mut h);
-            h.finish()
-        };
+    /// `ingest_batch` on tokio's blocking pool; see `ingest_async`.
+    pub async fn ingest_batch_async(self: Arc<Self>, msgs: Vec<(u64, String, String, Vec<u8>)>) -> io::Result<()> {
+        tokio::task::spawn_blocking(move || {
+            let (dids, rest): (Vec<String>, Vec<_>) = msgs.into_iter().map(|(seq, did, path, msg)| (did, (seq, path, msg))).unzip();
+            let batch: Vec<_> = dids.iter().zip(rest).map(|(did, (seq, path, msg))| (seq, did.as_str(), path, msg)).collect();
+            self.ingest_batch(&batch)
+        })
+        .await
+        .map_err(io::Error::other)
+    }
+
+    pub fn mark_deleted(&self, seq: u64) -> Result<(), TombstoneError> {
+        tombstone_store(&self.tombstones)?.write().unwrap().mark_deleted(seq)
+    }
+
+    /// Tombstones `start..end` under one lock; see `TombstoneStore::mark_deleted_range`.
+    pub fn mark_deleted_range(&self, start: u64, end: u64) -> Result<u64, TombstoneError> {
+        tombstone_store(&self.tombstones)?.write().unwrap().mark_deleted_range(start, end)
+    }
+
+    /// Tombstones each of `seqs` under one lock; see `TombstoneStore::mark_deleted_many`.
+    pub fn mark_deleted_many(&self, seqs: &[u64]) -> Result<u64, TombstoneError> {
+        tombstone_store(&self.tombstones)?.write().unwrap().mark_deleted_many(seqs)
+    }
+
+    /// Tombstoned seqs in `start..end`; 0 without a tombstone store.
+    pub fn count_deleted_in(&self, start: u64, end: u64) -> u64 {
+        self.tombstones.as_ref().map_or(0, |ts| ts.read().unwrap().count_deleted_in(start, end))
+    }
 
-        let reader = &self.readers[shard_idx];
+    /// Tombstones the newest archived message of `did` at `path`, once it has checked that
+    /// the message really is that (see `SegmentedArchive::find_verified_by_path`). Returns the
+    /// seq tombstoned; None if no stored message matched or the tombstone couldn't be written.
+    pub fn delete_by_path(&self, did: &str, path: &str) -> Option<u64> {
+        let reader = &self.readers[shard_for_did(did, self.readers.len())];
         // 1. Refresh reader to see most recent segments
-        let _ = reader.refresh(); 
+        let _ = reader.refresh();
+
+        // 2. Find the message, and make sure it is this DID's record at this path
+        let seq = reader.find_verified_by_path(did, path)?;
+        match self.mark_deleted(seq) {
+            Ok(()) => Some(seq),
+            Err(e) => {
+                tracing::warn!("Delete of {}/{} (seq {}) not recorded: {}", did, path, seq, e);
+                None
+            }
+        }
+    }
 
-        // 2. Find sequence
-        if let Some(seq) = reader.find_sequence_by_path(path_hasher) {
-            self.mark_deleted(seq);
+    /// Archived record at `at://did/collection/rkey`: the newest message written under that
+    /// path in the DID's shard, decompressed. `NotFound` if nothing was; an error wrapping
+    /// `ArchiveError::Tombstoned` if the newest version was deleted. Only persisted segments
+    /// are searched; call `refresh` first to see recent ones.
+    pub fn get_record(&self, did: &str, collection: &str, rkey: &str) -> io::Result<Vec<u8>> {
+        self.get_record_entry(did, collection, rkey).map(|(_, message)| message)
+    }
+
+    /// `get_record` with the seq the message is stored under.
+    pub fn get_record_entry(&self, did: &str, collection: &str, rkey: &str) -> io::Result<(u64, Vec<u8>)> {
+        let path = format!("{}/{}", collection, rkey);
+        let reader = &self.readers[shard_for_did(did, self.readers.len())];
+        match reader.latest_entry_by_path(did, &path, self.dict_ref.as_ref().map(|d| &d[..]))? {
+            Some(entry) => Ok(entry),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, format!("no record at at://{}/{}", did, path))),
         }
     }
 
@@ -828,22 +3093,28 @@ impl MultiShardArchive {
         self.readers.len()
     }
 
+    pub fn max_decompressed_cluster_bytes(&self) -> usize {
+        self.readers.first().map_or(DEFAULT_MAX_DECOMPRESSED_CLUSTER_BYTES, |r| r.max_decompressed_cluster_bytes())
+    }
+
     pub fn shutdown(&self) {
+        self.stop_idle_flush();
         println!("[Archive] Finalizing shards for shutdown...");
-        for writer in &self.writers {
+        for writer in self.writers.iter() {
             let mut w = writer.lock().unwrap();
-            let payload = w.take_payload();
-            let _ = self.persist_tx.send(Some(payload));
+            hand_off(&self.pending, &self.persist_tx, w.take_payload());
         }
         
-        // Send poison pill
-        let _ = self.persist_tx.send(None);
-        
-        // Wait for thread to finish
-        if let Ok(mut lock) = self.persist_thread.lock() {
-            if let Some(handle) = lock.take() {
+        // One poison pill per persister, queued behind the payloads
+        if let Ok(mut handles) = self.persist_threads.lock() {
+            if !handles.is_empty() {
+                for _ in 0..handles.len() {
+                    let _ = self.persist_tx.send(None);
+                }
                 println!("[Archive] Waiting for background persistence to finish...");
-                let _ = handle.join();
+                for handle in handles.drain(..) {
+                    let _ = handle.join();
+                }
                 println!("[Archive] Persistence finished.");
             }
         }
@@ -860,28 +3131,265 @@ impl MultiShardArchive {
     }
 
     pub fn refresh(&self) -> io::Result<()> {
+        let persisted = self.pending.persisted();
         for r in &self.readers {
             r.refresh()?;
         }
+        self.refreshed_at.store(persisted, Ordering::Relaxed);
         Ok(())
     }
 
-    pub fn get_message_by_seq(&self, seq: u64) -> io::Result<Vec<u8>> {
+    /// Segments handed to the persisters whose files aren't written yet. Reads fall back
+    /// to them, so a seq doesn't go missing between leaving the writer and reaching disk.
+    pub fn pending_index(&self) -> &PendingIndex {
+        &self.pending
+    }
+
+    /// Stops the background persisters before their next segment until the guard drops;
+    /// a segment already being written is finished first. Handed-off segments stay
+    /// readable from memory meanwhile. Don't `shutdown` while holding it.
+    pub fn pause_persistence(&self) -> PersistPause<'_> {
+        PersistPause { _gate: self.persist_gate.write().unwrap() }
+    }
+
+    /// The cluster cache all shards share. Its budget can be changed with `set_budget`.
+    pub fn cluster_cache(&self) -> &Arc<ClusterCache> {
+        &self.cluster_cache
+    }
+
+    /// `SegmentedArchive::force_rescan` on every shard.
+    pub fn force_rescan(&self) -> io::Result<()> {
         for r in &self.readers {
-            if let Ok(data) = r.get_message_by_seq(seq, self.dict_ref.as_ref().map(|d| &d[..])) {
-                return Ok(data);
-            }
+            r.force_rescan()?;
+        }
+        Ok(())
+    }
+
+    pub fn get_message_by_seq(&self, seq: u64) -> Result<Vec<u8>, ArchiveError> {
+        self.read_persisted_or_pending(
+            seq,
+            |r| r.get_message_by_seq(seq, self.dict_ref.as_ref().map(|d| &d[..])),
+            |p| p.get_message(seq).map(Ok),
+        )
+    }
+
+    /// Single-message read that doesn't decompress or cache the whole cluster on a cache
+    /// miss; see `Segment::get_message_streaming`.
+    pub fn get_message_streaming(&self, seq: u64) -> Result<Vec<u8>, ArchiveError> {
+        self.read_persisted_or_pending(
+            seq,
+            |r| r.get_message_streaming(seq, self.dict_ref.as_ref().map(|d| &d[..])),
+            |p| p.get_message(seq).map(Ok),
+        )
+    }
+
+    /// Newest archived commit of `did`, for `getLatestCommit`-style answers. Only segments
+    /// written with `set_track_heads` on count; call `refresh` first to see recent ones.
+    pub fn latest_for_did(&self, did: &str) -> Option<HeadInfo> {
+        self.readers[shard_for_did(did, self.readers.len())].latest_for_did(did)
+    }
+
+    /// Every DID `latest_for_did` knows, shard by shard.
+    pub fn iter_dids(&self) -> impl Iterator<Item = String> + '_ {
+        self.readers.iter().flat_map(|r| r.head_dids())
+    }
+
+    /// See `SegmentedArchive::dict_hash_at_seq`.
+    pub fn dict_hash_at_seq(&self, seq: u64) -> Option<String> {
+        self.readers.iter().find_map(|r| r.dict_hash_at_seq(seq))
+    }
+
+    /// Raw cluster holding `seq`, from whichever shard stores it. For a segment still
+    /// being persisted it is compressed on the spot, byte for byte as it will be stored.
+    pub fn get_raw_cluster_at_seq(&self, seq: u64) -> Result<Vec<u8>, ArchiveError> {
+        self.read_persisted_or_pending(
+            seq,
+            |r| r.get_raw_cluster_at_seq(seq),
+            |p| p.get_raw_cluster(seq, self.dict_ref.as_ref().map(|d| &d[..])),
+        )
+    }
+
+    /// `read_any_shard`, then the pending segments if no shard has `seq` (a tombstone or
+    /// read error is final). If one was persisted since the readers last refreshed, they
+    /// refresh and try once more: the seq may have moved to disk while it was looked for.
+    fn read_persisted_or_pending<T>(
+        &self,
+        seq: u64,
+        read: impl Fn(&SegmentedArchive) -> Result<T, ArchiveError>,
+        pending: impl Fn(&PendingIndex) -> Option<io::Result<T>>,
+    ) -> Result<T, ArchiveError> {
+        let missing = match self.read_any_shard(seq, &read) {
+            Err(e @ (ArchiveError::NotFound | ArchiveError::Gap { .. } | ArchiveError::OutOfRange)) => e,
+            result => return result,
+        };
+        if let Some(found) = pending(&self.pending) {
+            return found.map_err(ArchiveError::Io);
+        }
+        if self.pending.persisted() != self.refreshed_at.load(Ordering::Relaxed) && self.refresh().is_ok() {
+            return self.read_any_shard(seq, read);
         }
-        Err(io::Error::new(io::ErrorKind::NotFound, "Sequence not found in any shard"))
+        Err(missing)
     }
 
-    pub fn get_raw_cluster_at_seq(&self, seq: u64) -> io::Result<Vec<u8>> {
+    /// `read` on each shard until one has `seq`. Otherwise a read error in the shard that has
+    /// it wins over `Tombstoned`; if no shard has it at all, it's classified against the range
+    /// of all shards together, since one shard's gap is usually another shard's message.
+    fn read_any_shard<T>(&self, seq: u64, read: impl Fn(&SegmentedArchive) -> Result<T, ArchiveError>) -> Result<T, ArchiveError> {
+        let mut failure = None;
         for r in &self.readers {
-            // SegmentedArchive::get_raw_cluster_at_seq already handles tombstones
-            if let Ok(data) = r.get_raw_cluster_at_seq(seq) {
-                return Ok(data);
+            match read(r) {
+                Ok(data) => return Ok(data),
+                Err(e @ (ArchiveError::Io(_) | ArchiveError::Corrupt { .. })) => failure = Some(e),
+                Err(e @ ArchiveError::Tombstoned { .. }) => {
+                    failure.get_or_insert(e);
+                }
+                Err(_) => {}
+            }
+        }
+        Err(failure.unwrap_or_else(|| ArchiveError::missing(seq, self.min_seq(), self.max_seq())))
+    }
+
+    /// Finds the first seq, scanning forward from `min_seq`, whose record TID timestamp is
+    /// at or after `since_micros` (µs since the epoch). Returns None if nothing stored qualifies yet.
+    ///
+    /// Best effort: the time comes from the rkey of the message's first op, which is only a
+    /// TID for TID-keyed collections and is chosen by the client, so skewed clocks can put a
+    /// message slightly out of order. Messages without a TID rkey are stepped over.
+    /// Every `TIME_INDEX_STRIDE` seqs a sample is cached, so later seeks skip the part
+    /// already known to be too old.
+    pub fn seek_by_time(&self, since_micros: u64) -> Option<u64> {
+        let min = self.min_seq()?;
+        let max = self.max_seq()?;
+        let mut seq = {
+            let index = self.time_index.read().unwrap();
+            index.iter().rev()
+                .find(|(_, &ts)| ts < since_micros)
+                .map_or(min, |(&s, _)| s.saturating_add(1).max(min))
+        };
+
+        let mut last_bucket = None;
+        while seq <= max {
+            if let Some(ts) = self.message_timestamp(seq) {
+                let bucket = seq / TIME_INDEX_STRIDE;
+                if last_bucket != Some(bucket) {
+                    self.time_index.write().unwrap().entry(seq).or_insert(ts);
+                    last_bucket = Some(bucket);
+                }
+                if ts >= since_micros {
+                    return Some(seq);
+                }
+            }
+            seq += 1;
+        }
+        None
+    }
+
+    /// TID timestamp of the first op's rkey, if the stored frame has one.
+    fn message_timestamp(&self, seq: u64) -> Option<u64> {
+        let data = self.get_message_by_seq(seq).ok()?;
+        let envelope = crate::parser::core::parse_input_opt(&data)?;
+        envelope.ops.first()?.tid_timestamp()
+    }
+}
+
+impl Drop for MultiShardArchive {
+    fn drop(&mut self) {
+        // Don't leave the idle-flush timer spinning if the caller never called shutdown().
+        self.flush_running.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Outcome of a `reshard` run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReshardReport {
+    pub copied: u64,
+    pub tombstoned: u64,
+    /// Stored frames that no longer parse, so their DID (and thus shard) is unknown. Only
+    /// nonzero with `drop_unparseable`.
+    pub skipped: u64,
+}
+
+/// Copies every message of the archive at `src_dir` into a new archive at `dst_dir` with
+/// `new_count` shards. Sequence numbers and tombstones carry over; each message is re-routed
+/// by the repo DID parsed from its frame, with the same primary path the ingester uses.
+///
+/// A stored frame that doesn't parse can't be routed. It fails the reshard with
+/// `InvalidData`, removing what was written to `dst_dir`, unless `drop_unparseable` is set;
+/// then it is left out and counted in `skipped`.
+pub fn reshard(
+    src_dir: impl AsRef<Path>,
+    dst_dir: impl AsRef<Path>,
+    new_count: usize,
+    segment_size: u64,
+    dict: Option<Vec<u8>>,
+    drop_unparseable: bool,
+) -> io::Result<ReshardReport> {
+    use crate::parser::core::parse_input_opt;
+
+    let dst_dir = dst_dir.as_ref();
+    if ArchiveMeta::load(dst_dir)?.is_some() || count_shard_dirs(dst_dir) > 0 {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} already holds an archive", dst_dir.display())));
+    }
+
+    let src = MultiShardArchive::open_readonly(src_dir, dict.clone())?;
+    let dst = MultiShardArchive::new(dst_dir, new_count, segment_size, dict)?;
+    let mut report = ReshardReport::default();
+
+    if let (Some(min), Some(max)) = (src.min_seq(), src.max_seq()) {
+        if let Some(ts) = &src.tombstones {
+            let ts = ts.read().unwrap();
+            let deleted: Vec<u64> = (min..=max).filter(|&seq| ts.is_deleted(seq)).collect();
+            report.tombstoned += dst.mark_deleted_many(&deleted)?;
+        }
+        for (seq, at) in src.iter_range(min, max + 1) {
+            let msg = match src.read_at(seq, &at) {
+                Ok(m) => m,
+                Err(_) => continue, // tombstoned
+            };
+
+            let routing = parse_input_opt(&msg).and_then(|env| {
+                let did = std::str::from_utf8(env.did?).ok()?.to_string();
+                let path = env.ops.iter()
+                    .find(|op| op.action != "delete")
+                    .map_or_else(String::new, |op| op.path.to_string());
+                Some((did, path))
+            });
+            match routing {
+                Some((did, path)) => {
+                    dst.ingest(seq, &did, path, msg);
+                    report.copied += 1;
+                }
+                None if drop_unparseable => report.skipped += 1,
+                None => {
+                    dst.shutdown();
+                    drop(dst);
+                    remove_archive(dst_dir, new_count)?;
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("seq {} doesn't parse, so its shard is unknown (drop_unparseable leaves such frames out)", seq),
+                    ));
+                }
             }
         }
-        Err(io::Error::new(io::ErrorKind::NotFound, "Sequence not found in any shard"))
     }
+
+    dst.shutdown();
+    Ok(report)
+}
+
+/// Removes the files `MultiShardArchive::new` creates under `root` for `num_shards` shards.
+fn remove_archive(root: &Path, num_shards: usize) -> io::Result<()> {
+    for i in 0..num_shards {
+        match fs::remove_dir_all(root.join(format!("shard_{}", i))) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    for file in [META_FILE, "tombstones.bin"] {
+        match fs::remove_file(root.join(file)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    Ok(())
 }
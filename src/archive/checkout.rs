@@ -0,0 +1,165 @@
+//! A DID's repo as its archived commits leave it.
+//!
+//! `checkout_did` replays every stored message of the DID in seq order: a create or update
+//! puts the op's record block, looked up in the commit's CAR by the op's CID, at its path,
+//! and a delete takes the path out. What's left is each live path with its CID and record,
+//! plus the last commit seen. Ops whose block isn't in the CAR keep their CID and are listed
+//! by `RepoCheckout::missing_blocks` instead of failing the checkout; tombstoned messages are
+//! skipped, so their ops are not applied.
+
+use super::heads::commit_rev;
+use super::{shard_for_did, ArchiveError, MultiShardArchive};
+use crate::mst::car::CarStore;
+use crate::parser::cid_utils::normalize_cid_bytes;
+use crate::parser::core::parse_input;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// A record at a path, as the last op on it left it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoRecord {
+    /// Binary CID, without the multibase prefix
+    pub cid: Vec<u8>,
+    /// Seq of the message whose op wrote it
+    pub seq: u64,
+    /// DAG-CBOR record; None if the block wasn't in that commit's CAR
+    pub bytes: Option<Vec<u8>>,
+}
+
+/// The last commit applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckoutHead {
+    pub seq: u64,
+    /// Binary CID, without the multibase prefix; empty if the frame carried none
+    pub commit_cid: Vec<u8>,
+    pub rev: Option<String>,
+    /// The commit block, when the CAR had it
+    pub commit: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepoCheckout {
+    pub did: String,
+    /// `collection/rkey` -> record, for every path not deleted since
+    pub records: BTreeMap<String, RepoRecord>,
+    /// None if no commit of the DID is archived
+    pub head: Option<CheckoutHead>,
+    /// Commits applied
+    pub commits: u64,
+    /// Messages skipped for being tombstoned
+    pub tombstoned: u64,
+    /// Messages of the DID that didn't parse
+    pub unparsed: u64,
+}
+
+impl RepoCheckout {
+    /// Paths whose record block was missing from the commit that wrote them.
+    pub fn missing_blocks(&self) -> impl Iterator<Item = (&str, &RepoRecord)> + '_ {
+        self.records.iter().filter(|(_, r)| r.bytes.is_none()).map(|(path, r)| (path.as_str(), r))
+    }
+
+    /// Writes the head commit and the records as a CARv1 rooted at the commit. The MST nodes
+    /// aren't archived, so they're not in it; records without a block are left out too.
+    pub fn write_car<W: Write>(&self, mut out: W) -> io::Result<()> {
+        let head = self.head.as_ref().filter(|h| !h.commit_cid.is_empty())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no commit archived for {}", self.did)))?;
+
+        let mut header = vec![0xa2, 0x65];
+        header.extend_from_slice(b"roots");
+        header.extend_from_slice(&[0x81, 0xd8, 0x2a]);
+        cbor_head(2, head.commit_cid.len() as u64 + 1, &mut header);
+        header.push(0x00);
+        header.extend_from_slice(&head.commit_cid);
+        header.push(0x67);
+        header.extend_from_slice(b"version");
+        header.push(0x01);
+
+        let mut buf = Vec::new();
+        write_varint(header.len() as u64, &mut buf);
+        buf.extend_from_slice(&header);
+        let mut written: Vec<&[u8]> = Vec::new();
+        let blocks = head.commit.as_deref().map(|c| (&head.commit_cid[..], c)).into_iter()
+            .chain(self.records.values().filter_map(|r| r.bytes.as_deref().map(|b| (&r.cid[..], b))));
+        for (cid, block) in blocks {
+            // Identical records share a block
+            if written.contains(&cid) {
+                continue;
+            }
+            written.push(cid);
+            write_varint((cid.len() + block.len()) as u64, &mut buf);
+            buf.extend_from_slice(cid);
+            buf.extend_from_slice(block);
+        }
+        out.write_all(&buf)
+    }
+}
+
+/// Replays `did`'s archived messages onto an empty repo. Reads go to the DID's shard, with
+/// the archive's dictionary.
+pub fn checkout_did(archive: &MultiShardArchive, did: &str) -> io::Result<RepoCheckout> {
+    let reader = &archive.readers[shard_for_did(did, archive.readers.len())];
+    let dict = archive.dict_ref.as_ref().map(|d| &d[..]);
+    let mut checkout = RepoCheckout { did: did.to_string(), ..Default::default() };
+
+    for seq in reader.seqs_for_did(did, dict) {
+        if reader.is_tombstoned(seq) {
+            checkout.tombstoned += 1;
+            continue;
+        }
+        let message = match reader.read_stored(seq, dict, false) {
+            Ok(message) => message,
+            Err(ArchiveError::Gap { .. } | ArchiveError::NotFound | ArchiveError::OutOfRange) => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let Ok(envelope) = parse_input(&message) else {
+            checkout.unparsed += 1;
+            continue;
+        };
+        // The DID index is by hash, so another repo's message can turn up
+        if envelope.did != Some(did.as_bytes()) || !matches!(envelope.t, Some(b"#commit") | Some(b"commit")) {
+            continue;
+        }
+
+        let store = CarStore::new(envelope.blocks.unwrap_or_default());
+        for op in &envelope.ops {
+            match (op.action, op.cid) {
+                ("create" | "update", Some(cid)) => {
+                    let bytes = store.get_block(cid).map(<[u8]>::to_vec);
+                    checkout.records.insert(op.path.to_string(), RepoRecord { cid: normalize_cid_bytes(cid).to_vec(), seq, bytes });
+                }
+                ("delete", _) => {
+                    checkout.records.remove(op.path);
+                }
+                _ => {}
+            }
+        }
+        checkout.commits += 1;
+        checkout.head = Some(CheckoutHead {
+            seq,
+            commit_cid: envelope.cid.map_or_else(Vec::new, |c| normalize_cid_bytes(c).to_vec()),
+            rev: envelope.commit.and_then(commit_rev).map(str::to_string),
+            commit: envelope.commit.map(<[u8]>::to_vec),
+        });
+    }
+    Ok(checkout)
+}
+
+fn cbor_head(major: u8, len: u64, out: &mut Vec<u8>) {
+    let major = major << 5;
+    match len {
+        0..=23 => out.push(major | len as u8),
+        24..=0xff => out.extend_from_slice(&[major | 24, len as u8]),
+        _ => {
+            out.push(major | 25);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+    }
+}
+
+fn write_varint(mut value: u64, out: &mut Vec<u8>) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
@@ -0,0 +1,141 @@
+//! The cluster payload format, before compression:
+//! `[u16 count][(u64 seq, u32 len) * count][record data...]`, little-endian, the records
+//! back to back in header order. Segments store one cluster per zstd frame, and the relay
+//! streams the same layout, tombstoned records taken out. `ClusterWriter` builds one and
+//! `ClusterReader` takes one apart; nothing else should touch the header.
+
+use std::io;
+
+/// `(u64 seq, u32 len)` per record.
+pub const CLUSTER_ENTRY_HEADER_SIZE: usize = 12;
+/// The count is a u16.
+pub const MAX_CLUSTER_RECORDS: usize = u16::MAX as usize;
+
+fn corrupt() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Corrupt cluster header")
+}
+
+/// One record of a cluster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClusterEntry<'a> {
+    pub seq: u64,
+    /// Where the record starts in the cluster, as an `.idx` record's `inner_off`
+    pub offset: usize,
+    pub data: &'a [u8],
+}
+
+/// A decompressed cluster whose header has been checked against its length.
+#[derive(Debug, Clone, Copy)]
+pub struct ClusterReader<'a> {
+    raw: &'a [u8],
+    count: usize,
+}
+
+impl<'a> ClusterReader<'a> {
+    /// InvalidData if the header is cut short or a record runs past the end.
+    pub fn new(raw: &'a [u8]) -> io::Result<Self> {
+        let count = u16::from_le_bytes(raw.get(..2).ok_or_else(corrupt)?.try_into().unwrap()) as usize;
+        let mut end = 2 + count * CLUSTER_ENTRY_HEADER_SIZE;
+        if end > raw.len() {
+            return Err(corrupt());
+        }
+        let reader = ClusterReader { raw, count };
+        for i in 0..count {
+            end = end.checked_add(reader.len_at(i)).filter(|&e| e <= raw.len()).ok_or_else(corrupt)?;
+        }
+        Ok(reader)
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Where the record data starts.
+    pub fn data_offset(&self) -> usize {
+        2 + self.count * CLUSTER_ENTRY_HEADER_SIZE
+    }
+
+    fn entry_header(&self, i: usize) -> &'a [u8] {
+        &self.raw[2 + i * CLUSTER_ENTRY_HEADER_SIZE..2 + (i + 1) * CLUSTER_ENTRY_HEADER_SIZE]
+    }
+
+    fn len_at(&self, i: usize) -> usize {
+        u32::from_le_bytes(self.entry_header(i)[8..].try_into().unwrap()) as usize
+    }
+
+    /// The records in header order.
+    pub fn iter(&self) -> impl Iterator<Item = ClusterEntry<'a>> + '_ {
+        let mut offset = self.data_offset();
+        (0..self.count).map(move |i| {
+            let len = self.len_at(i);
+            let entry = ClusterEntry {
+                seq: u64::from_le_bytes(self.entry_header(i)[..8].try_into().unwrap()),
+                offset,
+                data: &self.raw[offset..offset + len],
+            };
+            offset += len;
+            entry
+        })
+    }
+}
+
+/// Builds a cluster record by record.
+#[derive(Debug, Default)]
+pub struct ClusterWriter {
+    entries: Vec<(u64, u32)>,
+    data: Vec<u8>,
+}
+
+impl ClusterWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends a record. InvalidInput once the cluster holds `MAX_CLUSTER_RECORDS`, or for
+    /// a record of 4GB or more.
+    pub fn push(&mut self, seq: u64, record: &[u8]) -> io::Result<()> {
+        if self.entries.len() >= MAX_CLUSTER_RECORDS {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("a cluster holds at most {} records", MAX_CLUSTER_RECORDS)));
+        }
+        let len = u32::try_from(record.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("record of {} bytes is too large for a cluster", record.len())))?;
+        self.entries.push((seq, len));
+        self.data.extend_from_slice(record);
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The cluster bytes, ready to compress.
+    pub fn finish(self) -> Vec<u8> {
+        let mut raw = Vec::with_capacity(2 + self.entries.len() * CLUSTER_ENTRY_HEADER_SIZE + self.data.len());
+        raw.extend_from_slice(&(self.entries.len() as u16).to_le_bytes());
+        for (seq, len) in &self.entries {
+            raw.extend_from_slice(&seq.to_le_bytes());
+            raw.extend_from_slice(&len.to_le_bytes());
+        }
+        raw.extend_from_slice(&self.data);
+        raw
+    }
+}
+
+/// Adds `offset` to every seq in a cluster's header, in place.
+pub(crate) fn shift_seqs(raw: &mut [u8], offset: u64) -> io::Result<()> {
+    let count = ClusterReader::new(raw)?.len();
+    for i in 0..count {
+        let field = &mut raw[2 + i * CLUSTER_ENTRY_HEADER_SIZE..2 + i * CLUSTER_ENTRY_HEADER_SIZE + 8];
+        let seq = u64::from_le_bytes((&*field).try_into().unwrap()).checked_add(offset).ok_or_else(corrupt)?;
+        field.copy_from_slice(&seq.to_le_bytes());
+    }
+    Ok(())
+}
@@ -0,0 +1,203 @@
+//! Decompressed clusters shared by every segment of an archive.
+//!
+//! Each `SegmentedArchive` holds an `Arc<ClusterCache>`, and `MultiShardArchive` hands one
+//! to all of its shards, so the hot set is bounded by one byte budget however many segments
+//! are mapped. Entries are keyed by the segment's `cache_id` and the cluster's offset in its
+//! `.bin`; start seqs aren't used because one archive can map two segments starting at the
+//! same seq. Once the decompressed bytes pass the budget, the least recently used clusters
+//! go first.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Budget of `ClusterCache::default()`: 1GB of decompressed clusters.
+pub const DEFAULT_CLUSTER_CACHE_BYTES: usize = 1024 * 1024 * 1024;
+
+// Independent LRUs, each with an equal share of the budget, so concurrent readers rarely
+// wait on one another. A cluster larger than one share isn't cached.
+const CACHE_SHARDS: usize = 16;
+
+/// (segment cache_id, bin_off)
+type ClusterKey = (u64, usize);
+
+#[derive(Default)]
+struct LruShard {
+    // Cluster and the tick it was last used at
+    entries: HashMap<ClusterKey, (Arc<Vec<u8>>, u64)>,
+    // tick -> key, oldest first
+    order: BTreeMap<u64, ClusterKey>,
+    bytes: usize,
+    tick: u64,
+}
+
+impl LruShard {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+
+    fn get(&mut self, key: ClusterKey) -> Option<Arc<Vec<u8>>> {
+        let tick = self.next_tick();
+        let (cluster, used) = self.entries.get_mut(&key)?;
+        self.order.remove(used);
+        *used = tick;
+        self.order.insert(tick, key);
+        Some(Arc::clone(cluster))
+    }
+
+    fn remove(&mut self, key: &ClusterKey) {
+        if let Some((cluster, used)) = self.entries.remove(key) {
+            self.order.remove(&used);
+            self.bytes -= cluster.capacity();
+        }
+    }
+
+    /// Drops the least recently used clusters until `bytes` fits `budget`. Returns how many went.
+    fn evict_to(&mut self, budget: usize) -> u64 {
+        let mut evicted = 0;
+        while self.bytes > budget {
+            let Some((_, key)) = self.order.pop_first() else { break };
+            if let Some((cluster, _)) = self.entries.remove(&key) {
+                self.bytes -= cluster.capacity();
+                evicted += 1;
+            }
+        }
+        evicted
+    }
+}
+
+/// Counters of a `ClusterCache`, for the monitor and `--report`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ClusterCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Clusters dropped to stay within the budget
+    pub evictions: u64,
+    pub entries: u64,
+    /// Bytes allocated for the cached clusters
+    pub bytes: u64,
+    pub budget: u64,
+}
+
+impl ClusterCacheStats {
+    /// Share of lookups answered from the cache, 0.0 before the first one.
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 { 0.0 } else { self.hits as f64 / lookups as f64 }
+    }
+}
+
+/// Byte-budgeted LRU of decompressed clusters. See the module docs.
+pub struct ClusterCache {
+    shards: Vec<Mutex<LruShard>>,
+    budget: AtomicUsize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl Default for ClusterCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CLUSTER_CACHE_BYTES)
+    }
+}
+
+impl ClusterCache {
+    /// A cache holding at most `budget` bytes of decompressed clusters.
+    pub fn new(budget: usize) -> Self {
+        Self {
+            shards: (0..CACHE_SHARDS).map(|_| Mutex::new(LruShard::default())).collect(),
+            budget: AtomicUsize::new(budget),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    fn shard(&self, key: &ClusterKey) -> &Mutex<LruShard> {
+        let mut h = fxhash::FxHasher::default();
+        key.hash(&mut h);
+        &self.shards[h.finish() as usize % CACHE_SHARDS]
+    }
+
+    fn shard_budget(&self) -> usize {
+        self.budget.load(Ordering::Relaxed) / CACHE_SHARDS
+    }
+
+    /// The cluster at `bin_off` of segment `cache_id`, marking it recently used.
+    pub fn get(&self, cache_id: u64, bin_off: usize) -> Option<Arc<Vec<u8>>> {
+        let key = (cache_id, bin_off);
+        let found = self.shard(&key).lock().unwrap().get(key);
+        let counter = if found.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        found
+    }
+
+    /// Caches a decompressed cluster, evicting older ones past the budget. Its allocated
+    /// size is what counts against the budget.
+    pub fn insert(&self, cache_id: u64, bin_off: usize, cluster: Arc<Vec<u8>>) {
+        let budget = self.shard_budget();
+        if cluster.capacity() > budget {
+            return;
+        }
+        let key = (cache_id, bin_off);
+        let mut shard = self.shard(&key).lock().unwrap();
+        shard.remove(&key);
+        let tick = shard.next_tick();
+        shard.bytes += cluster.capacity();
+        shard.entries.insert(key, (cluster, tick));
+        shard.order.insert(tick, key);
+        let evicted = shard.evict_to(budget);
+        if evicted > 0 {
+            self.evictions.fetch_add(evicted, Ordering::Relaxed);
+        }
+    }
+
+    /// Drops every cluster of segment `cache_id`, e.g. once it's no longer mapped.
+    pub fn remove_segment(&self, cache_id: u64) {
+        for shard in &self.shards {
+            let mut shard = shard.lock().unwrap();
+            let keys: Vec<ClusterKey> = shard.entries.keys().filter(|k| k.0 == cache_id).copied().collect();
+            for key in keys {
+                shard.remove(&key);
+            }
+        }
+    }
+
+    /// Clusters currently cached for segment `cache_id`.
+    pub fn segment_entries(&self, cache_id: u64) -> usize {
+        self.shards.iter().map(|s| s.lock().unwrap().entries.keys().filter(|k| k.0 == cache_id).count()).sum()
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget.load(Ordering::Relaxed)
+    }
+
+    /// Changes the budget, evicting right away if the cache is now over it.
+    pub fn set_budget(&self, budget: usize) {
+        self.budget.store(budget, Ordering::Relaxed);
+        let share = budget / CACHE_SHARDS;
+        let evicted: u64 = self.shards.iter().map(|s| s.lock().unwrap().evict_to(share)).sum();
+        self.evictions.fetch_add(evicted, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> ClusterCacheStats {
+        let (mut entries, mut bytes) = (0, 0);
+        for shard in &self.shards {
+            let shard = shard.lock().unwrap();
+            entries += shard.entries.len() as u64;
+            bytes += shard.bytes as u64;
+        }
+        ClusterCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            entries,
+            bytes,
+            budget: self.budget() as u64,
+        }
+    }
+}
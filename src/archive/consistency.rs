@@ -0,0 +1,172 @@
+//! Startup cross-check of an archive's persisted state.
+//!
+//! Segments, `tombstones.bin` and the callers' cursor files are written independently, so
+//! a partial restore can leave them disagreeing: two segments of one shard claiming the
+//! same seqs, an `.idx` pointing past the end of its `.bin`, tombstones for seqs the
+//! archive never reached (which would hide content written there later), or cursors that
+//! resume far beyond what is stored. `MultiShardArchive::new` and `open_readonly` run
+//! `check` and log what it finds; the `_strict` constructors refuse to open instead.
+
+use super::{idx_header, SegmentedArchive, TombstoneStore, IDX_RECORD_SIZE};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+/// One disagreement between an archive's files.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ConsistencyIssue {
+    /// Two segments of one shard cover some of the same seqs
+    OverlappingSegments { shard: usize, first_start: u64, second_start: u64, overlap: u64 },
+    /// The `.idx` doesn't describe the `.bin` next to it
+    IndexMismatch { shard: usize, start_seq: u64, reason: String },
+    /// `tombstones.bin` marks seqs past the newest stored one (all of them when `max_seq` is None)
+    TombstonesBeyondMax { max_seq: Option<u64>, highest_deleted: u64 },
+    /// A saved cursor resumes more than the allowed slack past the newest stored seq
+    CursorAhead { name: String, cursor: u64, max_seq: Option<u64> },
+}
+
+impl ConsistencyIssue {
+    /// Overlaps make the next write clobber or shadow stored seqs; the ingester won't run on them unforced.
+    pub fn is_overlap(&self) -> bool {
+        matches!(self, ConsistencyIssue::OverlappingSegments { .. })
+    }
+}
+
+impl fmt::Display for ConsistencyIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConsistencyIssue::OverlappingSegments { shard, first_start, second_start, overlap } => write!(
+                f,
+                "shard {}: segments starting at {} and {} share {} seqs",
+                shard, first_start, second_start, overlap
+            ),
+            ConsistencyIssue::IndexMismatch { shard, start_seq, reason } => {
+                write!(f, "shard {}: segment {}: {}", shard, start_seq, reason)
+            }
+            ConsistencyIssue::TombstonesBeyondMax { max_seq: Some(max), highest_deleted } => {
+                write!(f, "tombstones reach seq {}, past the newest stored seq {}", highest_deleted, max)
+            }
+            ConsistencyIssue::TombstonesBeyondMax { max_seq: None, highest_deleted } => {
+                write!(f, "tombstones reach seq {}, but the archive is empty", highest_deleted)
+            }
+            ConsistencyIssue::CursorAhead { name, cursor, max_seq } => match max_seq {
+                Some(max) => write!(f, "cursor {} = {} is far past the newest stored seq {}", name, cursor, max),
+                None => write!(f, "cursor {} = {}, but the archive is empty", name, cursor),
+            },
+        }
+    }
+}
+
+/// What `MultiShardArchive::consistency_check` found.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsistencyReport {
+    pub shards: usize,
+    pub segments: usize,
+    pub min_seq: Option<u64>,
+    pub max_seq: Option<u64>,
+    pub issues: Vec<ConsistencyIssue>,
+}
+
+impl ConsistencyReport {
+    pub fn is_consistent(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    pub fn has_overlaps(&self) -> bool {
+        self.issues.iter().any(ConsistencyIssue::is_overlap)
+    }
+
+    /// Adds a `CursorAhead` issue for every cursor more than `slack` past `max_seq`.
+    /// Against an empty archive, any non-zero cursor is ahead. Pass `u64::MAX` as `slack`
+    /// for cursors that count some other seq space (like a PDS's own) to catch only that case.
+    pub fn check_cursors<'a>(&mut self, cursors: impl IntoIterator<Item = (&'a str, u64)>, slack: u64) {
+        for (name, cursor) in cursors {
+            let ahead = match self.max_seq {
+                Some(max) => cursor > max.saturating_add(slack),
+                None => cursor > 0,
+            };
+            if ahead {
+                self.issues.push(ConsistencyIssue::CursorAhead { name: name.to_string(), cursor, max_seq: self.max_seq });
+            }
+        }
+    }
+}
+
+impl fmt::Display for ConsistencyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let range = match (self.min_seq, self.max_seq) {
+            (Some(min), Some(max)) => format!("seqs {}..={}", min, max),
+            _ => "no seqs".to_string(),
+        };
+        write!(f, "{} shards, {} segments, {}: ", self.shards, self.segments, range)?;
+        if self.issues.is_empty() {
+            return write!(f, "consistent");
+        }
+        write!(f, "{} issue(s)", self.issues.len())?;
+        for issue in &self.issues {
+            write!(f, "\n  - {}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks every shard's segments and the shared tombstones. Cursors are left to `check_cursors`.
+pub(super) fn check(readers: &[SegmentedArchive], tombstones: Option<&Arc<RwLock<TombstoneStore>>>) -> ConsistencyReport {
+    let mut report = ConsistencyReport {
+        shards: readers.len(),
+        min_seq: readers.iter().filter_map(|r| r.min_seq()).min(),
+        max_seq: readers.iter().filter_map(|r| r.max_seq()).max(),
+        ..ConsistencyReport::default()
+    };
+    for (shard, reader) in readers.iter().enumerate() {
+        report.segments += check_shard(shard, reader, &mut report.issues);
+    }
+    if let Some(highest) = tombstones.and_then(|ts| ts.read().unwrap().highest_deleted()) {
+        if report.max_seq.is_none_or(|max| highest > max) {
+            report.issues.push(ConsistencyIssue::TombstonesBeyondMax { max_seq: report.max_seq, highest_deleted: highest });
+        }
+    }
+    report
+}
+
+/// Returns the shard's segment count.
+fn check_shard(shard: usize, reader: &SegmentedArchive, issues: &mut Vec<ConsistencyIssue>) -> usize {
+    let segments = reader.segments.read().unwrap();
+    let mut count = 0;
+    // (start, end) of the segment reaching furthest so far; segments are visited by start seq
+    let mut furthest: Option<(u64, u64)> = None;
+    for segment in segments.values().flatten() {
+        count += 1;
+        let start = segment.start_seq;
+        let end = start + segment.msg_count() as u64;
+        if let Some((prev_start, prev_end)) = furthest {
+            if start < prev_end {
+                let overlap = prev_end.min(end) - start;
+                issues.push(ConsistencyIssue::OverlappingSegments { shard, first_start: prev_start, second_start: start, overlap });
+            }
+        }
+        if furthest.is_none_or(|(_, prev_end)| end > prev_end) {
+            furthest = Some((start, end));
+        }
+
+        let mismatch = |reason: String| ConsistencyIssue::IndexMismatch { shard, start_seq: start, reason };
+        let idx_len = segment.storage().get_index_bytes().len();
+        let records_at = idx_header(segment.storage().get_index_bytes()).0;
+        if idx_len < records_at {
+            issues.push(mismatch(format!(".idx is {} bytes, shorter than its header", idx_len)));
+            continue;
+        }
+        if !(idx_len - records_at).is_multiple_of(IDX_RECORD_SIZE) {
+            issues.push(mismatch(format!(".idx ends in a partial record ({} bytes)", idx_len)));
+        }
+        let bin_len = segment.storage().bin_len();
+        let past_end = (0..segment.msg_count() as u64)
+            .filter_map(|i| segment.record(i))
+            .find(|r| r.c_len != 0 && r.cluster_range(bin_len).is_none());
+        if let Some(r) = past_end {
+            issues.push(mismatch(format!("cluster at {}+{} runs past the {}-byte .bin", r.bin_off, r.c_len, bin_len)));
+        }
+    }
+    count
+}
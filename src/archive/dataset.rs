@@ -0,0 +1,243 @@
+//! Hash-addressed snapshot of an archive's finished segments, for publishing a dataset.
+//!
+//! `MultiShardArchive::export_manifest` writes one JSON file naming every finished segment
+//! with the blake3 of its `.bin` and `.idx`, its Merkle root and dictionary, plus the seq
+//! range and tombstones. The same archive always gives byte-identical output, so the
+//! manifest's own hash can be cited. `verify_manifest` rebuilds it from disk and lists
+//! every difference, down to the segment.
+
+use super::sync::{build_manifest, segment_file_name};
+use super::{trimmed_bitset, MultiShardArchive, DICT_ID_EXT};
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+pub const DATASET_MANIFEST_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DatasetSegment {
+    pub shard: usize,
+    pub start_seq: u64,
+    /// Index records, gaps included
+    pub message_count: u64,
+    /// Hex blake3 of the whole `.bin`
+    pub bin_blake3: String,
+    /// Hex blake3 of the whole `.idx`
+    pub idx_blake3: String,
+    /// Hex Merkle root from the `.idx` header
+    pub root_hash: String,
+    /// `MerkleAlgorithm` id of `root_hash`
+    pub hash_alg: u8,
+    /// `dict_hash` from the `.dictid` sidecar; None for segments written without a dictionary
+    pub dict_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DatasetManifest {
+    pub version: u32,
+    pub num_shards: usize,
+    /// First seq of the oldest segment
+    pub min_seq: Option<u64>,
+    /// Last index record of the newest segment
+    pub max_seq: Option<u64>,
+    pub tombstone_count: u64,
+    /// Hex blake3 of `tombstones.bin` up to its last set byte
+    pub tombstones_blake3: String,
+    /// Sorted by shard, then start seq
+    pub segments: Vec<DatasetSegment>,
+}
+
+impl DatasetManifest {
+    /// Hashes every finished segment of the archive at `root`. Segments still being written
+    /// are left out, as in `sync::build_manifest`.
+    pub fn build(root: &Path) -> io::Result<Self> {
+        let listing = build_manifest(root)?;
+        let mut segments = Vec::with_capacity(listing.segments.len());
+        for info in listing.segments {
+            let dir = root.join(format!("shard_{}", info.shard));
+            let bin = dir.join(segment_file_name(info.shard, info.start_seq, "bin"));
+            segments.push(DatasetSegment {
+                shard: info.shard,
+                start_seq: info.start_seq,
+                message_count: info.message_count,
+                bin_blake3: hash_file(&bin)?,
+                idx_blake3: hash_file(&bin.with_extension("idx"))?,
+                root_hash: info.root_hash,
+                hash_alg: info.hash_alg,
+                dict_hash: fs::read_to_string(bin.with_extension(DICT_ID_EXT))
+                    .ok()
+                    .map(|h| h.trim().to_ascii_lowercase())
+                    .filter(|h| !h.is_empty()),
+            });
+        }
+        let (tombstone_count, tombstones_blake3) = hash_tombstones(&root.join("tombstones.bin"))?;
+        Ok(DatasetManifest {
+            version: DATASET_MANIFEST_VERSION,
+            num_shards: listing.num_shards,
+            min_seq: segments.iter().map(|s| s.start_seq).min(),
+            max_seq: segments.iter().filter(|s| s.message_count > 0).map(|s| s.start_seq + s.message_count - 1).max(),
+            tombstone_count,
+            tombstones_blake3,
+            segments,
+        })
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let bytes = fs::read(path)?;
+        serde_json::from_slice(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Writes the manifest to `path` through a temp file, so a reader never sees half of it.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut json = serde_json::to_vec_pretty(self).map_err(io::Error::other)?;
+        json.push(b'\n');
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, path)
+    }
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Count and hash of the tombstone bitset. A missing file is the same as an empty one.
+fn hash_tombstones(path: &Path) -> io::Result<(u64, String)> {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok((0, blake3::hash(&[]).to_hex().to_string())),
+        Err(e) => return Err(e),
+    };
+    if file.metadata()?.len() == 0 {
+        return Ok((0, blake3::hash(&[]).to_hex().to_string()));
+    }
+    let map = unsafe { Mmap::map(&file)? };
+    let bits = trimmed_bitset(&map);
+    let count = bits.iter().map(|b| b.count_ones() as u64).sum();
+    Ok((count, blake3::hash(bits).to_hex().to_string()))
+}
+
+impl MultiShardArchive {
+    /// Writes a `DatasetManifest` of the segments on disk to `out` and returns it. Messages
+    /// not yet persisted aren't in it; `shutdown` first for a complete snapshot.
+    pub fn export_manifest(&self, out: &Path) -> io::Result<DatasetManifest> {
+        let manifest = DatasetManifest::build(&self.root)?;
+        manifest.save(out)?;
+        Ok(manifest)
+    }
+}
+
+/// One way the archive no longer matches its manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ManifestDrift {
+    /// Listed in the manifest, gone from disk
+    MissingSegment { shard: usize, start_seq: u64 },
+    /// On disk, not in the manifest
+    ExtraSegment { shard: usize, start_seq: u64 },
+    /// Present in both, but these fields differ (`bin`, `idx`, `message_count`, `root_hash`, `hash_alg`, `dict_hash`)
+    ChangedSegment { shard: usize, start_seq: u64, fields: Vec<&'static str> },
+    Tombstones { expected_count: u64, found_count: u64 },
+    ShardCount { expected: usize, found: usize },
+}
+
+impl fmt::Display for ManifestDrift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManifestDrift::MissingSegment { shard, start_seq } => write!(f, "shard {} segment {}: missing", shard, start_seq),
+            ManifestDrift::ExtraSegment { shard, start_seq } => write!(f, "shard {} segment {}: not in manifest", shard, start_seq),
+            ManifestDrift::ChangedSegment { shard, start_seq, fields } => {
+                write!(f, "shard {} segment {}: {} changed", shard, start_seq, fields.join(", "))
+            }
+            ManifestDrift::Tombstones { expected_count, found_count } => {
+                write!(f, "tombstones changed: {} in manifest, {} now", expected_count, found_count)
+            }
+            ManifestDrift::ShardCount { expected, found } => write!(f, "shard count changed: {} in manifest, {} now", expected, found),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct VerifyManifestReport {
+    pub segments_checked: usize,
+    pub drift: Vec<ManifestDrift>,
+}
+
+impl VerifyManifestReport {
+    pub fn is_clean(&self) -> bool {
+        self.drift.is_empty()
+    }
+}
+
+impl fmt::Display for VerifyManifestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_clean() {
+            return write!(f, "{} segments match the manifest", self.segments_checked);
+        }
+        write!(f, "{} segments checked, {} differences:", self.segments_checked, self.drift.len())?;
+        for drift in &self.drift {
+            write!(f, "\n  {}", drift)?;
+        }
+        Ok(())
+    }
+}
+
+/// Re-hashes the archive at `archive_dir` and compares it with the manifest at `manifest_path`.
+/// Errors only if the manifest can't be read or the archive can't be listed.
+pub fn verify_manifest(archive_dir: &Path, manifest_path: &Path) -> io::Result<VerifyManifestReport> {
+    let expected = DatasetManifest::load(manifest_path)?;
+    let found = DatasetManifest::build(archive_dir)?;
+    let mut report = VerifyManifestReport { segments_checked: expected.segments.len(), drift: Vec::new() };
+
+    if expected.num_shards != found.num_shards {
+        report.drift.push(ManifestDrift::ShardCount { expected: expected.num_shards, found: found.num_shards });
+    }
+    let mut on_disk: BTreeMap<(usize, u64), &DatasetSegment> =
+        found.segments.iter().map(|s| ((s.shard, s.start_seq), s)).collect();
+    for want in &expected.segments {
+        let (shard, start_seq) = (want.shard, want.start_seq);
+        let Some(have) = on_disk.remove(&(shard, start_seq)) else {
+            report.drift.push(ManifestDrift::MissingSegment { shard, start_seq });
+            continue;
+        };
+        let mut fields = Vec::new();
+        if have.bin_blake3 != want.bin_blake3 {
+            fields.push("bin");
+        }
+        if have.idx_blake3 != want.idx_blake3 {
+            fields.push("idx");
+        }
+        if have.message_count != want.message_count {
+            fields.push("message_count");
+        }
+        if have.root_hash != want.root_hash {
+            fields.push("root_hash");
+        }
+        if have.hash_alg != want.hash_alg {
+            fields.push("hash_alg");
+        }
+        if have.dict_hash != want.dict_hash {
+            fields.push("dict_hash");
+        }
+        if !fields.is_empty() {
+            report.drift.push(ManifestDrift::ChangedSegment { shard, start_seq, fields });
+        }
+    }
+    for (shard, start_seq) in on_disk.into_keys() {
+        report.drift.push(ManifestDrift::ExtraSegment { shard, start_seq });
+    }
+    if expected.tombstones_blake3 != found.tombstones_blake3 {
+        report.drift.push(ManifestDrift::Tombstones {
+            expected_count: expected.tombstone_count,
+            found_count: found.tombstone_count,
+        });
+    }
+    Ok(report)
+}
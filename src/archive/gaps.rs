@@ -0,0 +1,118 @@
+//! Record of firehose history the archive never received, kept in `gaps.jsonl`.
+//!
+//! When a PDS has pruned past a saved cursor it answers `#info` `OutdatedCursor`, and the
+//! stream picks up again at some later seq (see `ingest::FirehoseEvent::Gap`). Everything
+//! between is lost unless backfilled, so each such jump is appended as one JSON line:
+//! `{"host", "saved_cursor", "new_start_seq", "time"}`, with `time` in unix seconds. The
+//! file is read back on open, so the totals cover every run that wrote to the archive.
+
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const GAPS_FILE: &str = "gaps.jsonl";
+
+/// One jump in a host's sequence.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GapRecord {
+    pub host: String,
+    /// Last seq received before the host reported the cursor outdated
+    pub saved_cursor: u64,
+    /// First seq received afterwards
+    pub new_start_seq: u64,
+    /// Unix seconds
+    pub time: u64,
+}
+
+impl GapRecord {
+    /// A gap observed now.
+    pub fn now(host: impl Into<String>, saved_cursor: u64, new_start_seq: u64) -> Self {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        GapRecord { host: host.into(), saved_cursor, new_start_seq, time }
+    }
+
+    /// Seqs skipped: those after `saved_cursor` and before `new_start_seq`.
+    pub fn span(&self) -> u64 {
+        self.new_start_seq.saturating_sub(self.saved_cursor.saturating_add(1))
+    }
+}
+
+/// Called with every gap as it is recorded, e.g. to request a backfill.
+pub type GapHook = Box<dyn Fn(&GapRecord) + Send + Sync>;
+
+/// Append-only `gaps.jsonl` with its records also held in memory for queries.
+pub struct GapLog {
+    path: PathBuf,
+    records: Mutex<Vec<GapRecord>>,
+    hook: Option<GapHook>,
+}
+
+impl GapLog {
+    /// Opens `dir/gaps.jsonl`, creating `dir` if needed. Lines that don't parse (a torn last
+    /// write) are skipped with a warning.
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        fs::create_dir_all(dir.as_ref())?;
+        let path = dir.as_ref().join(GAPS_FILE);
+        let mut records = Vec::new();
+        match File::open(&path) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    let line = line?;
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    match serde_json::from_str(&line) {
+                        Ok(record) => records.push(record),
+                        Err(e) => tracing::warn!("{}: skipping unreadable gap record: {}", path.display(), e),
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Ok(GapLog { path, records: Mutex::new(records), hook: None })
+    }
+
+    /// Runs `hook` after each gap is written.
+    pub fn with_hook(mut self, hook: GapHook) -> Self {
+        self.hook = Some(hook);
+        self
+    }
+
+    /// Appends `record` to the file, then runs the hook.
+    pub fn record(&self, record: GapRecord) -> io::Result<()> {
+        let mut line = serde_json::to_string(&record).map_err(io::Error::other)?;
+        line.push('\n');
+        {
+            let mut records = self.records.lock().unwrap();
+            let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+            file.write_all(line.as_bytes())?;
+            records.push(record.clone());
+        }
+        if let Some(hook) = &self.hook {
+            hook(&record);
+        }
+        Ok(())
+    }
+
+    /// Every gap recorded for `host`, oldest first.
+    pub fn gaps_for_host(&self, host: &str) -> Vec<GapRecord> {
+        self.records.lock().unwrap().iter().filter(|r| r.host == host).cloned().collect()
+    }
+
+    /// Seqs skipped across all hosts.
+    pub fn total_gap_span(&self) -> u64 {
+        self.records.lock().unwrap().iter().map(GapRecord::span).sum()
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,175 @@
+//! Per-shard index of each DID's newest archived commit, kept in `heads.bin`.
+//!
+//! With head tracking on (`MultiShardArchive::set_track_heads`), persisting a segment parses
+//! the newest message of every DID in it and appends one record per DID that had a commit:
+//! `fxhash(did) u64 | seq u64 | commit CID 36 bytes | fxhash(rev) u64 | did length u16 | did`,
+//! little-endian. Readers replay the file into memory when they open and read what was
+//! appended since on `refresh`. The highest seq per DID wins, so the order of records
+//! doesn't matter. Records go down only once their segment is visible: after a crash a head
+//! can lag the archive by a segment, but never names a seq that isn't stored.
+
+use crate::parser::cid_utils::normalize_cid_bytes;
+use crate::parser::core::{parse_cbor_len, parse_cbor_text, parse_for_verify, skip_cbor_value};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+pub const HEADS_FILE: &str = "heads.bin";
+/// Binary CIDv1 of a dag-cbor sha2-256 block, as commits are addressed
+pub const COMMIT_CID_LEN: usize = 36;
+const RECORD_FIXED_SIZE: usize = 8 + 8 + COMMIT_CID_LEN + 8 + 2;
+
+/// A DID's newest archived commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeadInfo {
+    pub seq: u64,
+    pub commit_cid: [u8; COMMIT_CID_LEN],
+    /// fxhash of the commit's `rev`; 0 if it had none
+    pub rev_hash: u64,
+}
+
+pub(crate) fn fx_hash<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = fxhash::FxHasher::default();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// What one shard's `heads.bin` says so far.
+#[derive(Default)]
+pub(crate) struct HeadIndex {
+    // Keyed by the DID itself: did:web names are free to pick, so an fxhash collision must
+    // not let one repo shadow another's head
+    entries: HashMap<Box<str>, HeadInfo>,
+    // Bytes of heads.bin replayed, always a whole number of records
+    read_len: u64,
+}
+
+impl HeadIndex {
+    /// Replays the records appended to `dir/heads.bin` since the last call. A record still
+    /// being written is left for the next one.
+    pub(crate) fn catch_up(&mut self, dir: &Path) -> io::Result<()> {
+        let mut file = match File::open(dir.join(HEADS_FILE)) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let len = file.metadata()?.len();
+        if len < self.read_len {
+            // Replaced behind our back: start over
+            *self = HeadIndex::default();
+        }
+        file.seek(SeekFrom::Start(self.read_len))?;
+        let mut buf = Vec::new();
+        file.take(len - self.read_len).read_to_end(&mut buf)?;
+
+        let mut off = 0;
+        while let Some((did, head, next)) = decode_record(&buf, off) {
+            self.insert(did, head);
+            off = next;
+        }
+        self.read_len += off as u64;
+        Ok(())
+    }
+
+    fn insert(&mut self, did: &str, head: HeadInfo) {
+        match self.entries.get_mut(did) {
+            Some(current) if current.seq >= head.seq => {}
+            Some(current) => *current = head,
+            None => {
+                self.entries.insert(did.into(), head);
+            }
+        }
+    }
+
+    pub(crate) fn get(&self, did: &str) -> Option<HeadInfo> {
+        self.entries.get(did).copied()
+    }
+
+    pub(crate) fn dids(&self) -> Vec<String> {
+        self.entries.keys().map(|did| did.to_string()).collect()
+    }
+}
+
+fn decode_record(buf: &[u8], off: usize) -> Option<(&str, HeadInfo, usize)> {
+    let fixed = buf.get(off..off + RECORD_FIXED_SIZE)?;
+    let u64_at = |i: usize| u64::from_le_bytes(fixed[i..i + 8].try_into().unwrap());
+    let did_len = u16::from_le_bytes(fixed[RECORD_FIXED_SIZE - 2..].try_into().unwrap()) as usize;
+    let end = off + RECORD_FIXED_SIZE + did_len;
+    let did = std::str::from_utf8(buf.get(off + RECORD_FIXED_SIZE..end)?).ok()?;
+    let head = HeadInfo {
+        seq: u64_at(8),
+        commit_cid: fixed[16..16 + COMMIT_CID_LEN].try_into().unwrap(),
+        rev_hash: u64_at(16 + COMMIT_CID_LEN),
+    };
+    Some((did, head, end))
+}
+
+fn encode_record(did: &str, head: &HeadInfo, out: &mut Vec<u8>) {
+    out.extend_from_slice(&fx_hash(did).to_le_bytes());
+    out.extend_from_slice(&head.seq.to_le_bytes());
+    out.extend_from_slice(&head.commit_cid);
+    out.extend_from_slice(&head.rev_hash.to_le_bytes());
+    out.extend_from_slice(&(did.len() as u16).to_le_bytes());
+    out.extend_from_slice(did.as_bytes());
+}
+
+/// Newest `#commit` of each DID among a segment's pending messages. Messages that don't
+/// parse, or carry another event, are stepped over in favour of older ones.
+pub(crate) fn payload_heads(pending: &super::pending::Messages) -> Vec<(&str, HeadInfo)> {
+    let mut heads = Vec::new();
+    for (did, messages) in pending {
+        let mut newest_first: Vec<_> = messages.iter().map(|(seq, _, data)| (*seq, data)).collect();
+        newest_first.sort_unstable_by_key(|&(seq, _)| std::cmp::Reverse(seq));
+        if let Some(head) = newest_first.into_iter().find_map(|(seq, data)| commit_head(seq, data)) {
+            heads.push((did.as_str(), head));
+        }
+    }
+    heads
+}
+
+fn commit_head(seq: u64, frame: &[u8]) -> Option<HeadInfo> {
+    let envelope = parse_for_verify(frame).ok()?;
+    if !matches!(envelope.t, Some(b"#commit") | Some(b"commit")) {
+        return None;
+    }
+    // The CID comes out of tag 42 with its multibase prefix
+    let cid = envelope.cid?;
+    let cid = normalize_cid_bytes(cid);
+    Some(HeadInfo {
+        seq,
+        commit_cid: cid.try_into().ok()?,
+        rev_hash: envelope.commit.and_then(commit_rev).map_or(0, fx_hash),
+    })
+}
+
+/// `rev` of a commit block.
+pub(crate) fn commit_rev(commit: &[u8]) -> Option<&str> {
+    if commit.first()? >> 5 != 5 {
+        return None;
+    }
+    let (pairs, mut off) = parse_cbor_len(commit, 0).ok()?;
+    for _ in 0..pairs {
+        let (key, next) = parse_cbor_text(commit, off).ok()?;
+        if key == b"rev" {
+            return parse_cbor_text(commit, next).ok().and_then(|(v, _)| std::str::from_utf8(v).ok());
+        }
+        off = skip_cbor_value(commit, next).ok()?;
+    }
+    None
+}
+
+/// Appends `heads` to `dir/heads.bin` in one write.
+pub(crate) fn append(dir: &Path, heads: &[(&str, HeadInfo)]) -> io::Result<()> {
+    if heads.is_empty() {
+        return Ok(());
+    }
+    let mut buf = Vec::with_capacity(heads.len() * (RECORD_FIXED_SIZE + 32));
+    for (did, head) in heads {
+        encode_record(did, head, &mut buf);
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(dir.join(HEADS_FILE))?;
+    file.write_all(&buf)?;
+    file.sync_data()
+}
@@ -0,0 +1,160 @@
+//! Ordered iteration over the stored seqs of every shard.
+//!
+//! A shard's segments cover disjoint seq ranges, but shards interleave: consecutive seqs
+//! usually live in different shards, and probing each shard per seq (as
+//! `get_message_by_seq` does) costs O(shards) per message. `iter_range` instead plans each
+//! shard's segments once, walks each shard's `.idx` records in order a batch at a time,
+//! and merges the shards with a heap keyed by seq.
+
+use super::{ArchiveError, MultiShardArchive, SegmentedArchive};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+/// Records a shard cursor reads per lock of its segment map.
+const BATCH: usize = 1024;
+
+/// Where `iter_range` found a seq: the shard, the start of the segment holding it and
+/// the record's index in that segment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShardRef {
+    pub shard: usize,
+    pub segment_start: u64,
+    pub index: u64,
+}
+
+/// One shard's stored seqs in order: segments still to walk as (start, next index, end
+/// index), and records already read from them.
+struct ShardCursor {
+    shard: usize,
+    spans: VecDeque<(u64, u64, u64)>,
+    buffered: VecDeque<(u64, ShardRef)>,
+}
+
+impl ShardCursor {
+    /// Segments of `reader` overlapping `start..end`, from one look at its segment map.
+    /// A segment overlapping its predecessor is clipped to start after it, so the cursor
+    /// never goes backward.
+    fn plan(shard: usize, reader: &SegmentedArchive, start: u64, end: u64) -> Self {
+        let mut spans: VecDeque<(u64, u64, u64)> = VecDeque::new();
+        let segments = reader.segments.read().unwrap();
+        // Segments are disjoint, so only the last one starting at or before `start` reaches it
+        let first = segments.range(..=start).next_back().map_or(start, |(&s, _)| s);
+        for (&segment_start, list) in segments.range(first..end.max(first)) {
+            let count = list.iter().map(|s| s.msg_count() as u64).max().unwrap_or(0);
+            let from = spans.back().map_or(start, |&(s, _, e)| start.max(s + e)).max(segment_start);
+            let to = end.min(segment_start + count);
+            if from < to {
+                spans.push_back((segment_start, from - segment_start, to - segment_start));
+            }
+        }
+        ShardCursor { shard, spans, buffered: VecDeque::new() }
+    }
+
+    /// Reads up to `BATCH` stored records into `buffered`. Gap records are skipped; a
+    /// segment that went away since `plan` is dropped.
+    fn fill(&mut self, reader: &SegmentedArchive) {
+        let segments = reader.segments.read().unwrap();
+        while self.buffered.len() < BATCH {
+            let Some(&(segment_start, mut next, end)) = self.spans.front() else { break };
+            if let Some(list) = segments.get(&segment_start) {
+                while next < end && self.buffered.len() < BATCH {
+                    if list.iter().any(|s| s.record(next).is_some_and(|r| r.m_len != 0)) {
+                        let at = ShardRef { shard: self.shard, segment_start, index: next };
+                        self.buffered.push_back((segment_start + next, at));
+                    }
+                    next += 1;
+                }
+            }
+            if next < end && segments.contains_key(&segment_start) {
+                self.spans[0].1 = next;
+            } else {
+                self.spans.pop_front();
+            }
+        }
+    }
+
+    fn peek(&mut self, reader: &SegmentedArchive) -> Option<u64> {
+        if self.buffered.is_empty() {
+            self.fill(reader);
+        }
+        self.buffered.front().map(|&(seq, _)| seq)
+    }
+}
+
+/// Iterator returned by `MultiShardArchive::iter_range`.
+pub struct ShardIter<'a> {
+    archive: &'a MultiShardArchive,
+    cursors: Vec<ShardCursor>,
+    heap: BinaryHeap<Reverse<(u64, usize)>>,
+    last: Option<u64>,
+}
+
+impl Iterator for ShardIter<'_> {
+    type Item = (u64, ShardRef);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Reverse((seq, shard)) = self.heap.pop()?;
+            let cursor = &mut self.cursors[shard];
+            let item = cursor.buffered.pop_front();
+            if let Some(next) = cursor.peek(&self.archive.readers[shard]) {
+                self.heap.push(Reverse((next, shard)));
+            }
+            // A seq stored by two shards (left over from an interrupted reshard) comes once
+            if self.last.is_some_and(|last| seq <= last) {
+                continue;
+            }
+            self.last = Some(seq);
+            return item;
+        }
+    }
+}
+
+impl MultiShardArchive {
+    /// Every seq in `start..end` stored in a persisted segment of any shard, in order,
+    /// with where it is stored. Tombstoned seqs are included (`read_at` refuses them);
+    /// gaps and segments still pending aren't. Each step is O(log shards).
+    pub fn iter_range(&self, start: u64, end: u64) -> ShardIter<'_> {
+        let mut cursors: Vec<ShardCursor> = self.readers.iter().enumerate()
+            .map(|(shard, reader)| ShardCursor::plan(shard, reader, start, end))
+            .collect();
+        let mut heap = BinaryHeap::with_capacity(cursors.len());
+        for (shard, cursor) in cursors.iter_mut().enumerate() {
+            if let Some(seq) = cursor.peek(&self.readers[shard]) {
+                heap.push(Reverse((seq, shard)));
+            }
+        }
+        ShardIter { archive: self, cursors, heap, last: None }
+    }
+
+    /// The message `iter_range` yielded as `seq` at `at`. The cluster is decompressed into
+    /// the shared cluster cache, so the cluster's other messages are copied out of it.
+    /// Falls back to `get_message_by_seq` if the segment changed since.
+    pub fn read_at(&self, seq: u64, at: &ShardRef) -> Result<Vec<u8>, ArchiveError> {
+        let Some(reader) = self.readers.get(at.shard) else { return self.get_message_by_seq(seq) };
+        if reader.is_tombstoned(seq) {
+            return Err(ArchiveError::Tombstoned { seq });
+        }
+        let dict = self.dict_ref.as_ref().map(|d| &d[..]);
+        let found = {
+            let segments = reader.segments.read().unwrap();
+            segments.get(&at.segment_start)
+                .filter(|_| at.segment_start + at.index == seq)
+                .and_then(|list| list.iter().find(|s| s.record(at.index).is_some_and(|r| r.m_len != 0)))
+                .map(|segment| segment.get_decompressed_message_by_index(at.index, dict, &reader.cache))
+        };
+        found.unwrap_or_else(|| self.get_message_by_seq(seq))
+    }
+
+    /// Lowest of the shards' newest persisted seqs (shards with nothing stored don't count),
+    /// kept below any segment still pending. Up to here no shard will fill in a gap any
+    /// more, so `iter_range` sees everything that will be stored.
+    pub fn persisted_through(&self) -> Option<u64> {
+        let through = self.readers.iter().filter_map(|r| r.max_seq()).min()?;
+        match self.pending.oldest_start() {
+            Some(0) => None,
+            Some(pending) => Some(through.min(pending - 1)),
+            None => Some(through),
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! The hash of a repo path kept in each `.idx` record and `.pidx` entry, recorded per segment
+//! in an `sN_M.phash` sidecar.
+//!
+//! Segments written before the sidecar existed hash with FxHash. That is fast but easy to
+//! collide on purpose: anyone who can publish records can pick a path sharing its hash with
+//! someone else's. New segments use the first 8 bytes of the path's blake3 instead. Either
+//! way a path hash only narrows the search; `delete_by_path` checks the message it found
+//! before tombstoning it.
+
+use super::heads;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub(crate) const PATH_HASH_EXT: &str = "phash";
+
+/// How a segment's path hashes were computed, as stored in its 1-byte `.phash` sidecar.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum PathHash {
+    /// FxHash of the path; segments without a sidecar
+    Fx,
+    /// blake3 of the path, truncated to 64 bits
+    #[default]
+    Blake3,
+}
+
+impl PathHash {
+    pub fn id(self) -> u8 {
+        match self {
+            PathHash::Fx => 0,
+            PathHash::Blake3 => 1,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(PathHash::Fx),
+            1 => Some(PathHash::Blake3),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            PathHash::Fx => "fxhash",
+            PathHash::Blake3 => "blake3",
+        }
+    }
+
+    pub fn hash(self, path: &str) -> u64 {
+        match self {
+            PathHash::Fx => heads::fx_hash(path),
+            PathHash::Blake3 => {
+                let digest = blake3::hash(path.as_bytes());
+                u64::from_le_bytes(digest.as_bytes()[..8].try_into().unwrap())
+            }
+        }
+    }
+}
+
+impl std::str::FromStr for PathHash {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fx" | "fxhash" => Ok(PathHash::Fx),
+            "blake3" => Ok(PathHash::Blake3),
+            other => Err(format!("unknown path hash '{}' (expected blake3 or fxhash)", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for PathHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Id from a `.phash` sidecar; 0 (FxHash) when there isn't one.
+pub(crate) fn read_id(path: &Path) -> u8 {
+    fs::read(path).ok().and_then(|b| b.first().copied()).unwrap_or_else(|| PathHash::Fx.id())
+}
+
+/// Writes `alg`'s sidecar at `path`, or removes it for FxHash.
+pub(crate) fn write(path: &Path, alg: PathHash) -> io::Result<()> {
+    if alg == PathHash::Fx {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    } else {
+        fs::write(path, [alg.id()])
+    }
+}
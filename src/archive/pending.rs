@@ -0,0 +1,127 @@
+//! Segments handed to the background persister whose files aren't on disk yet.
+//!
+//! `MultiShardArchive` registers every payload here before queueing it and the persister
+//! unregisters it once `persist_payload` returns, so a seq is always readable from one
+//! place or the other. Readers look here only after missing in the segments. The payload's
+//! message map is shared, not copied: memory is what the persist queue holds anyway,
+//! normally at most one payload per shard.
+
+use super::{cluster, ClusterWriter, CompressionConfig, SegmentPayload};
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// One buffered message: seq, repo path and frame.
+type Message = (u64, String, Vec<u8>);
+/// Messages of one payload, by DID as the writer buffered them.
+pub(crate) type Messages = HashMap<String, Vec<Message>>;
+
+struct PendingSegment {
+    shard_id: usize,
+    start_seq: u64,
+    max_seq: u64,
+    compression: CompressionConfig,
+    messages: Arc<Messages>,
+    // seq -> (DID, position in its list)
+    by_seq: HashMap<u64, (String, usize)>,
+}
+
+impl PendingSegment {
+    fn find(&self, seq: u64) -> Option<(&[Message], usize)> {
+        if seq < self.start_seq || seq > self.max_seq {
+            return None;
+        }
+        let (did, i) = self.by_seq.get(&seq)?;
+        Some((self.messages.get(did)?.as_slice(), *i))
+    }
+}
+
+#[derive(Default)]
+pub struct PendingIndex {
+    // Copy-on-write: a reader clones the current list and searches it without the lock
+    segments: RwLock<Arc<Vec<Arc<PendingSegment>>>>,
+    persisted: AtomicU64,
+}
+
+impl PendingIndex {
+    /// Makes `payload`'s messages readable until `unregister`. Empty payloads are ignored.
+    pub fn register(&self, payload: &SegmentPayload) {
+        if payload.pending.is_empty() {
+            return;
+        }
+        let by_seq = payload.pending.iter()
+            .flat_map(|(did, messages)| messages.iter().enumerate().map(move |(i, m)| (m.0, (did.clone(), i))))
+            .collect();
+        let segment = Arc::new(PendingSegment {
+            shard_id: payload.shard_id,
+            start_seq: payload.start_seq,
+            max_seq: payload.max_seq,
+            compression: payload.compression,
+            messages: Arc::clone(&payload.pending),
+            by_seq,
+        });
+        let mut segments = self.segments.write().unwrap();
+        let mut next = Vec::clone(&segments);
+        next.retain(|s| (s.shard_id, s.start_seq) != (segment.shard_id, segment.start_seq));
+        next.push(segment);
+        *segments = Arc::new(next);
+    }
+
+    /// Forgets the payload of `shard_id` starting at `start_seq`, now that its segment is on disk.
+    pub fn unregister(&self, shard_id: usize, start_seq: u64) {
+        let mut segments = self.segments.write().unwrap();
+        let next: Vec<_> = segments.iter().filter(|s| (s.shard_id, s.start_seq) != (shard_id, start_seq)).cloned().collect();
+        *segments = Arc::new(next);
+        self.persisted.fetch_add(1, Ordering::Release);
+    }
+
+    /// Payloads registered and not yet unregistered.
+    pub fn len(&self) -> usize {
+        self.snapshot().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Lowest `start_seq` of the payloads still pending.
+    pub fn oldest_start(&self) -> Option<u64> {
+        self.snapshot().iter().map(|s| s.start_seq).min()
+    }
+
+    /// Counts `unregister` calls, so a reader can tell whether segments appeared on disk
+    /// since it last refreshed.
+    pub fn persisted(&self) -> u64 {
+        self.persisted.load(Ordering::Acquire)
+    }
+
+    fn snapshot(&self) -> Arc<Vec<Arc<PendingSegment>>> {
+        Arc::clone(&self.segments.read().unwrap())
+    }
+
+    /// The message stored under `seq` in a pending payload.
+    pub fn get_message(&self, seq: u64) -> Option<Vec<u8>> {
+        let segments = self.snapshot();
+        let (messages, i) = segments.iter().find_map(|s| s.find(seq))?;
+        Some(messages[i].2.clone())
+    }
+
+    /// The compressed cluster `seq` will be stored in: the same messages, settings and
+    /// dictionary `persist_payload` uses, so it matches the cluster later read from disk.
+    pub fn get_raw_cluster(&self, seq: u64, dict: Option<&[u8]>) -> Option<io::Result<Vec<u8>>> {
+        let segments = self.snapshot();
+        let (segment, (messages, i)) = segments.iter().find_map(|s| Some((s, s.find(seq)?)))?;
+        let chunk = i / cluster::MAX_CLUSTER_RECORDS * cluster::MAX_CLUSTER_RECORDS;
+        let end = (chunk + cluster::MAX_CLUSTER_RECORDS).min(messages.len());
+        Some(compress_cluster(&messages[chunk..end], segment.compression, dict))
+    }
+}
+
+fn compress_cluster(messages: &[(u64, String, Vec<u8>)], compression: CompressionConfig, dict: Option<&[u8]>) -> io::Result<Vec<u8>> {
+    let mut cluster = ClusterWriter::new();
+    for (seq, _path, data) in messages {
+        cluster.push(*seq, data)?;
+    }
+    compression.compressor(dict)?.compress(&cluster.finish())
+}
@@ -0,0 +1,138 @@
+//! Lookups behind `sovereign_query`: a stored message decoded for printing, a DID's stored
+//! messages, a record by `at://` URI, and a scan of a seq window by record time.
+
+use super::{shard_for_did, ArchiveError, MultiShardArchive};
+use crate::parser::core::parse_input;
+use crate::parser::records::{decode_record_from_car, RecordView};
+use serde::Serialize;
+use std::io;
+use std::ops::Range;
+
+/// One op of a stored commit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StoredOp {
+    pub action: String,
+    pub path: String,
+}
+
+/// A stored message, decoded as far as it goes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct StoredMessage {
+    pub seq: u64,
+    /// Frame type, e.g. `#commit`; None if the frame doesn't parse
+    pub event: Option<String>,
+    pub did: Option<String>,
+    pub ops: Vec<StoredOp>,
+    /// `RecordView::kind` of the first op's record
+    pub record_kind: Option<String>,
+    /// One-line summary of that record
+    pub record: Option<String>,
+    /// The record's `createdAt`, if it has one
+    pub created_at: Option<String>,
+    /// µs since the epoch: `createdAt` if it parses, else the first op's TID rkey
+    pub time_micros: Option<u64>,
+    /// Size of the stored frame
+    pub bytes: usize,
+}
+
+impl StoredMessage {
+    /// Decodes `message`, stored under `seq`. A frame that doesn't parse still gets its
+    /// seq and size.
+    pub fn describe(seq: u64, message: &[u8]) -> Self {
+        let mut described = StoredMessage {
+            seq,
+            event: None,
+            did: None,
+            ops: Vec::new(),
+            record_kind: None,
+            record: None,
+            created_at: None,
+            time_micros: None,
+            bytes: message.len(),
+        };
+        let Ok(envelope) = parse_input(message) else { return described };
+        let text = |b: &[u8]| String::from_utf8_lossy(b).into_owned();
+        described.event = envelope.t.map(text);
+        described.did = envelope.did.map(text);
+        described.ops = envelope.ops.iter().map(|op| StoredOp { action: op.action.to_string(), path: op.path.to_string() }).collect();
+
+        let first = envelope.ops.first();
+        if let Some(view) = envelope.blocks.and_then(|blocks| decode_record_from_car(blocks, first.and_then(|op| op.cid))) {
+            if let RecordView::Post { created_at, .. } = &view {
+                described.created_at = created_at.clone();
+            }
+            described.record_kind = Some(view.kind().to_string());
+            described.record = Some(view.to_string());
+        }
+        described.time_micros = described.created_at.as_deref().and_then(rfc3339_micros).or_else(|| first?.tid_timestamp());
+        described
+    }
+
+    /// Paths of the ops, comma separated, for one-line output.
+    pub fn paths(&self) -> String {
+        self.ops.iter().map(|op| op.path.as_str()).collect::<Vec<_>>().join(",")
+    }
+}
+
+/// µs since the epoch of an RFC 3339 time such as a record's `createdAt`. None if it doesn't
+/// parse or is before 1970.
+pub fn rfc3339_micros(time: &str) -> Option<u64> {
+    let time = chrono::DateTime::parse_from_rfc3339(time).ok()?;
+    u64::try_from(time.timestamp_micros()).ok()
+}
+
+/// Splits `at://did/collection/rkey` into its parts.
+pub fn parse_at_uri(uri: &str) -> Option<(&str, &str, &str)> {
+    let mut parts = uri.strip_prefix("at://")?.splitn(3, '/');
+    let did = parts.next().filter(|p| !p.is_empty())?;
+    let collection = parts.next().filter(|p| !p.is_empty())?;
+    let rkey = parts.next().filter(|p| !p.is_empty() && !p.contains('/'))?;
+    Some((did, collection, rkey))
+}
+
+impl MultiShardArchive {
+    /// Every stored seq of `did`'s messages, ascending, tombstoned ones included. Only the
+    /// DID's shard is searched, through its `.didx` sidecars; segments without one cost a
+    /// decode per cluster. The index is by hash, so other repos' seqs can turn up.
+    pub fn seqs_for_did(&self, did: &str) -> Vec<u64> {
+        let reader = &self.readers[shard_for_did(did, self.readers.len())];
+        reader.seqs_for_did(did, self.dict_ref.as_ref().map(|d| &d[..]))
+    }
+
+    /// `did`'s stored messages, decoded, ascending by seq. Tombstoned messages and other
+    /// repos' messages sharing the DID's hash are left out.
+    pub fn messages_for_did(&self, did: &str) -> Result<Vec<StoredMessage>, ArchiveError> {
+        let mut messages = Vec::new();
+        for seq in self.seqs_for_did(did) {
+            let message = match self.get_message_by_seq(seq) {
+                Ok(message) => StoredMessage::describe(seq, &message),
+                Err(ArchiveError::Tombstoned { .. } | ArchiveError::Gap { .. } | ArchiveError::NotFound | ArchiveError::OutOfRange) => continue,
+                Err(e) => return Err(e),
+            };
+            if message.did.as_deref().is_none_or(|d| d == did) {
+                messages.push(message);
+            }
+        }
+        Ok(messages)
+    }
+
+    /// The stored message at `at://did/collection/rkey`, decoded; see `get_record_entry`.
+    pub fn find_record(&self, uri: &str) -> io::Result<StoredMessage> {
+        let (did, collection, rkey) = parse_at_uri(uri)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("not an at://did/collection/rkey URI: {}", uri)))?;
+        let (seq, message) = self.get_record_entry(did, collection, rkey)?;
+        Ok(StoredMessage::describe(seq, &message))
+    }
+
+    /// The stored messages in `seqs` whose time (`StoredMessage::time_micros`) is in
+    /// `since..until`, µs since the epoch, either end open. Messages without a time and
+    /// seqs that can't be read are skipped.
+    pub fn scan_time_window(&self, seqs: Range<u64>, since: Option<u64>, until: Option<u64>) -> Vec<StoredMessage> {
+        seqs.filter_map(|seq| {
+            let message = StoredMessage::describe(seq, &self.get_message_by_seq(seq).ok()?);
+            let time = message.time_micros?;
+            (since.is_none_or(|s| time >= s) && until.is_none_or(|u| time < u)).then_some(message)
+        })
+        .collect()
+    }
+}
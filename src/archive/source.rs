@@ -0,0 +1,417 @@
+//! Where a `Segment`'s bytes come from.
+//!
+//! Segments read their `.idx` and compressed clusters through a `SegmentSource`. Local
+//! segments are memory-mapped (`LocalMmapSource`). With the `remote-segments` feature,
+//! sealed segments can instead be left in object storage and read with HTTP range requests
+//! (`HttpRangeSource`): a directory's `remote.json` lists them, and `SegmentedArchive`
+//! opens those alongside its local files. A remote segment's `.idx` and sidecars are
+//! downloaded once into a local cache directory; its clusters are fetched as they're read
+//! and kept there too, least recently used going first once past the manifest's budget.
+//!
+//! Everything that reads a segment works the same on a remote one, tombstone filtering and
+//! `verify_integrity` included, but anything that walks a whole segment fetches every
+//! cluster in it: one request each, until the cache holds them.
+
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A directory's remote segments, read from its `remote.json`.
+pub const REMOTE_MANIFEST: &str = "remote.json";
+
+/// Bytes of one segment: its whole `.idx`, and ranges of its `.bin`.
+pub trait SegmentSource: Send + Sync {
+    /// The `.idx`: Merkle root header, then one record per seq.
+    fn get_index_bytes(&self) -> &[u8];
+    /// `len` bytes of the `.bin` starting at `bin_off`. Callers check the range against
+    /// `bin_len` first.
+    fn get_cluster(&self, bin_off: usize, len: usize) -> io::Result<Cow<'_, [u8]>>;
+    /// Whether the `.bin` can still be read.
+    fn exists(&self) -> bool;
+    /// Size of the `.bin`.
+    fn bin_len(&self) -> usize;
+}
+
+/// A segment's `.bin` and `.idx`, memory-mapped.
+pub struct LocalMmapSource {
+    bin: Mmap,
+    idx: Mmap,
+}
+
+impl LocalMmapSource {
+    pub fn new(bin: Mmap, idx: Mmap) -> Self {
+        LocalMmapSource { bin, idx }
+    }
+}
+
+impl SegmentSource for LocalMmapSource {
+    fn get_index_bytes(&self) -> &[u8] {
+        &self.idx
+    }
+
+    fn get_cluster(&self, bin_off: usize, len: usize) -> io::Result<Cow<'_, [u8]>> {
+        bin_off.checked_add(len)
+            .and_then(|end| self.bin.get(bin_off..end))
+            .map(Cow::Borrowed)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "cluster lies past the end of the .bin"))
+    }
+
+    // A mapping stays readable after its file is unlinked
+    fn exists(&self) -> bool {
+        true
+    }
+
+    fn bin_len(&self) -> usize {
+        self.bin.len()
+    }
+}
+
+/// `remote.json`: segments of a directory that live under `base_url` rather than on disk.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteManifest {
+    /// Prefix the segment file names are appended to: a bucket URL, a static host, or the
+    /// same with a `{file}` placeholder for URLs that don't end in the file name.
+    pub base_url: String,
+    /// File stems as they'd be on disk, e.g. `shard_0_1000` for `shard_0_1000.bin`
+    pub segments: Vec<String>,
+    /// Fetched indexes, sidecars and clusters; relative paths are under the directory
+    #[serde(default = "default_cache_dir")]
+    pub cache_dir: PathBuf,
+    /// Budget for cached clusters; indexes and sidecars aren't counted or evicted
+    #[serde(default = "default_cache_bytes")]
+    pub cache_bytes: u64,
+}
+
+fn default_cache_dir() -> PathBuf {
+    PathBuf::from("remote_cache")
+}
+
+fn default_cache_bytes() -> u64 {
+    1024 * 1024 * 1024
+}
+
+impl RemoteManifest {
+    /// The manifest in `dir`, if it has one.
+    pub fn load(dir: &Path) -> io::Result<Option<Self>> {
+        match std::fs::read(dir.join(REMOTE_MANIFEST)) {
+            Ok(bytes) => serde_json::from_slice(&bytes).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn save(&self, dir: &Path) -> io::Result<()> {
+        std::fs::write(dir.join(REMOTE_MANIFEST), serde_json::to_vec_pretty(self).map_err(io::Error::other)?)
+    }
+
+    /// URL of `file` (a segment file name such as `shard_0_1000.bin`).
+    pub fn url_for(&self, file: &str) -> String {
+        if self.base_url.contains("{file}") {
+            self.base_url.replace("{file}", file)
+        } else if self.base_url.ends_with('/') {
+            format!("{}{}", self.base_url, file)
+        } else {
+            format!("{}/{}", self.base_url, file)
+        }
+    }
+
+    pub fn cache_path(&self, dir: &Path) -> PathBuf {
+        dir.join(&self.cache_dir)
+    }
+}
+
+#[cfg(feature = "remote-segments")]
+pub use remote::{HttpRangeSource, RemoteClusterCache};
+
+#[cfg(feature = "remote-segments")]
+mod remote {
+    use super::{RemoteManifest, SegmentSource};
+    use memmap2::Mmap;
+    use std::borrow::Cow;
+    use std::collections::{BTreeMap, HashMap};
+    use std::fs::{self, File};
+    use std::io::{self, Write};
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    // Cached clusters sit under the cache dir as `clusters/<stem>.<bin_off>.<len>`
+    const CLUSTER_DIR: &str = "clusters";
+    const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+    #[derive(Default)]
+    struct LruState {
+        // File name -> (bytes, tick it was last used at)
+        entries: HashMap<String, (u64, u64)>,
+        // tick -> file name, oldest first
+        order: BTreeMap<u64, String>,
+        bytes: u64,
+        tick: u64,
+    }
+
+    impl LruState {
+        fn touch(&mut self, name: &str, len: u64) {
+            self.tick += 1;
+            let tick = self.tick;
+            match self.entries.get_mut(name) {
+                Some((_, used)) => {
+                    self.order.remove(used);
+                    *used = tick;
+                }
+                None => {
+                    self.entries.insert(name.to_string(), (len, tick));
+                    self.bytes += len;
+                }
+            }
+            self.order.insert(tick, name.to_string());
+        }
+
+        fn remove(&mut self, name: &str) {
+            if let Some((len, used)) = self.entries.remove(name) {
+                self.order.remove(&used);
+                self.bytes -= len;
+            }
+        }
+    }
+
+    /// Compressed clusters fetched from remote segments, kept as files and evicted least
+    /// recently used first. Clusters already on disk from an earlier run are picked up,
+    /// oldest modification first.
+    pub struct RemoteClusterCache {
+        dir: PathBuf,
+        budget: u64,
+        state: Mutex<LruState>,
+    }
+
+    impl RemoteClusterCache {
+        pub fn open(cache_dir: &Path, budget: u64) -> io::Result<Self> {
+            let dir = cache_dir.join(CLUSTER_DIR);
+            fs::create_dir_all(&dir)?;
+            let mut existing = Vec::new();
+            for entry in fs::read_dir(&dir)? {
+                let entry = entry?;
+                let meta = entry.metadata()?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if name.ends_with(".tmp") {
+                    let _ = fs::remove_file(entry.path());
+                } else if meta.is_file() {
+                    existing.push((meta.modified().ok(), name, meta.len()));
+                }
+            }
+            existing.sort();
+            let mut state = LruState::default();
+            for (_, name, len) in existing {
+                state.touch(&name, len);
+            }
+            let cache = RemoteClusterCache { dir, budget, state: Mutex::new(state) };
+            cache.evict(&mut cache.state.lock().unwrap());
+            Ok(cache)
+        }
+
+        fn get(&self, name: &str) -> Option<Vec<u8>> {
+            let mut state = self.state.lock().unwrap();
+            let len = state.entries.get(name)?.0;
+            match fs::read(self.dir.join(name)) {
+                Ok(bytes) => {
+                    state.touch(name, len);
+                    Some(bytes)
+                }
+                Err(_) => {
+                    state.remove(name);
+                    None
+                }
+            }
+        }
+
+        fn insert(&self, name: &str, bytes: &[u8]) -> io::Result<()> {
+            let tmp = self.dir.join(format!("{}.tmp", name));
+            File::create(&tmp)?.write_all(bytes)?;
+            fs::rename(&tmp, self.dir.join(name))?;
+            let mut state = self.state.lock().unwrap();
+            state.touch(name, bytes.len() as u64);
+            self.evict(&mut state);
+            Ok(())
+        }
+
+        fn evict(&self, state: &mut LruState) {
+            while state.bytes > self.budget {
+                let Some((_, name)) = state.order.pop_first() else { break };
+                if let Some((len, _)) = state.entries.remove(&name) {
+                    state.bytes -= len;
+                }
+                let _ = fs::remove_file(self.dir.join(&name));
+            }
+        }
+
+        /// Bytes of clusters on disk.
+        pub fn bytes(&self) -> u64 {
+            self.state.lock().unwrap().bytes
+        }
+
+        pub fn len(&self) -> usize {
+            self.state.lock().unwrap().entries.len()
+        }
+
+        pub fn is_empty(&self) -> bool {
+            self.len() == 0
+        }
+    }
+
+    /// A segment in object storage. Its `.idx` is downloaded when opened and mapped from
+    /// the cache dir; clusters are fetched with `Range` requests through a
+    /// `RemoteClusterCache`.
+    pub struct HttpRangeSource {
+        http: reqwest::blocking::Client,
+        bin_url: String,
+        stem: String,
+        bin_len: usize,
+        idx: Mmap,
+        clusters: Arc<RemoteClusterCache>,
+    }
+
+    impl HttpRangeSource {
+        /// Opens segment `stem` of `manifest`, downloading its `.idx` unless the cache dir
+        /// already has it.
+        pub fn open(http: reqwest::blocking::Client, manifest: &RemoteManifest, dir: &Path, stem: &str, clusters: Arc<RemoteClusterCache>) -> io::Result<Self> {
+            let bin_url = manifest.url_for(&format!("{}.bin", stem));
+            let bin_len = head_len(&http, &bin_url)?
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{} is not there", bin_url)))?;
+            let idx_path = manifest.cache_path(dir).join(format!("{}.idx", stem));
+            if !idx_path.exists() {
+                let fetched = fetch_file(&http, &manifest.url_for(&format!("{}.idx", stem)), &idx_path)?;
+                if !fetched {
+                    return Err(io::Error::new(io::ErrorKind::NotFound, format!("{}.idx is not there", stem)));
+                }
+            }
+            let idx = unsafe { Mmap::map(&File::open(&idx_path)?)? };
+            Ok(HttpRangeSource { http, bin_url, stem: stem.to_string(), bin_len: bin_len as usize, idx, clusters })
+        }
+
+        fn fetch_range(&self, bin_off: usize, len: usize) -> io::Result<Vec<u8>> {
+            let resp = self.http
+                .get(&self.bin_url)
+                .header(reqwest::header::RANGE, format!("bytes={}-{}", bin_off, bin_off + len - 1))
+                .send()
+                .map_err(io::Error::other)?;
+            // A 200 would be the whole .bin: the server doesn't do ranges
+            if resp.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+                return Err(io::Error::other(format!("GET {} bytes {}+{}: HTTP {}", self.bin_url, bin_off, len, resp.status())));
+            }
+            let bytes = resp.bytes().map_err(io::Error::other)?;
+            if bytes.len() != len {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("{}: {} of {} bytes at {}", self.bin_url, bytes.len(), len, bin_off)));
+            }
+            Ok(bytes.to_vec())
+        }
+    }
+
+    impl SegmentSource for HttpRangeSource {
+        fn get_index_bytes(&self) -> &[u8] {
+            &self.idx
+        }
+
+        fn get_cluster(&self, bin_off: usize, len: usize) -> io::Result<Cow<'_, [u8]>> {
+            if len == 0 {
+                return Ok(Cow::Borrowed(&[]));
+            }
+            let name = format!("{}.{}.{}", self.stem, bin_off, len);
+            if let Some(bytes) = self.clusters.get(&name) {
+                return Ok(Cow::Owned(bytes));
+            }
+            let bytes = self.fetch_range(bin_off, len)?;
+            if let Err(e) = self.clusters.insert(&name, &bytes) {
+                tracing::warn!("Could not cache cluster {}: {}", name, e);
+            }
+            Ok(Cow::Owned(bytes))
+        }
+
+        fn exists(&self) -> bool {
+            head_len(&self.http, &self.bin_url).is_ok_and(|len| len.is_some())
+        }
+
+        fn bin_len(&self) -> usize {
+            self.bin_len
+        }
+    }
+
+    /// Content-Length of `url`, or None if the server says it isn't there.
+    fn head_len(http: &reqwest::blocking::Client, url: &str) -> io::Result<Option<u64>> {
+        let resp = http.head(url).send().map_err(io::Error::other)?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !resp.status().is_success() {
+            return Err(io::Error::other(format!("HEAD {}: HTTP {}", url, resp.status())));
+        }
+        resp.headers()
+            .get(reqwest::header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok())
+            .map(Some)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("HEAD {}: no Content-Length", url)))
+    }
+
+    /// Opens the segments in `dir`'s manifest for which `wanted` returns true, fetching their
+    /// sidecars (`exts`) into the cache dir as well. Returns each segment's stem, its source,
+    /// and where its sidecars now are, as the path of a `.bin` beside them.
+    pub(crate) fn open_remote(
+        dir: &Path,
+        manifest: &RemoteManifest,
+        exts: &[&str],
+        wanted: impl Fn(&str) -> bool,
+    ) -> io::Result<Vec<(String, HttpRangeSource, PathBuf)>> {
+        let stems: Vec<&String> = manifest.segments.iter().filter(|s| wanted(s)).collect();
+        if stems.is_empty() {
+            return Ok(Vec::new());
+        }
+        let cache_dir = manifest.cache_path(dir);
+        let clusters = Arc::new(RemoteClusterCache::open(&cache_dir, manifest.cache_bytes)?);
+        let http = reqwest::blocking::Client::builder().timeout(REQUEST_TIMEOUT).build().map_err(io::Error::other)?;
+        let mut opened = Vec::new();
+        for stem in stems {
+            let open = || -> io::Result<HttpRangeSource> {
+                let source = HttpRangeSource::open(http.clone(), manifest, dir, stem, Arc::clone(&clusters))?;
+                for ext in exts {
+                    let name = format!("{}.{}", stem, ext);
+                    if !cache_dir.join(&name).exists() {
+                        fetch_file(&http, &manifest.url_for(&name), &cache_dir.join(&name))?;
+                    }
+                }
+                Ok(source)
+            };
+            // The local tier stays readable while the remote one is down; the next refresh retries
+            match open() {
+                Ok(source) => opened.push((stem.clone(), source, cache_dir.join(format!("{}.bin", stem)))),
+                Err(e) => tracing::warn!("Remote segment {} unavailable: {}", stem, e),
+            }
+        }
+        Ok(opened)
+    }
+
+    /// Downloads `url` to `dest`, through a `.tmp` file. False if the server says it isn't there.
+    fn fetch_file(http: &reqwest::blocking::Client, url: &str, dest: &Path) -> io::Result<bool> {
+        let mut resp = http.get(url).send().map_err(io::Error::other)?;
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(false);
+        }
+        if !resp.status().is_success() {
+            return Err(io::Error::other(format!("GET {}: HTTP {}", url, resp.status())));
+        }
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut tmp = dest.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let mut file = File::create(&tmp)?;
+        resp.copy_to(&mut file).map_err(io::Error::other)?;
+        file.sync_all()?;
+        fs::rename(&tmp, dest)?;
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "remote-segments")]
+pub(crate) use remote::open_remote;
@@ -0,0 +1,74 @@
+//! Per-collection message counts and sizes, kept per segment in an `sN_M.stats` sidecar.
+//!
+//! With collection stats on (`MultiShardArchive::set_collection_stats`), persisting a segment
+//! counts each message under the collection of its path (the part before the first `/`) and
+//! writes the totals as JSON: `{"app.bsky.feed.post":{"messages":812,"bytes":402113}}`.
+//! Messages stored without a path, such as identity events, count under `NO_COLLECTION`.
+//! Bytes are uncompressed frame bytes: clusters are compressed per DID, so compressed size
+//! can't be split by collection.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+pub(crate) const STATS_EXT: &str = "stats";
+/// The collection messages stored without a path are counted under.
+pub const NO_COLLECTION: &str = "(none)";
+
+/// Messages of one collection and their uncompressed size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CollStat {
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+impl CollStat {
+    pub(crate) fn record(&mut self, bytes: usize) {
+        self.messages += 1;
+        self.bytes += bytes as u64;
+    }
+
+    pub fn add(&mut self, other: CollStat) {
+        self.messages += other.messages;
+        self.bytes += other.bytes;
+    }
+}
+
+/// The collection of a repo path: `app.bsky.feed.post` for `app.bsky.feed.post/3k2a`.
+pub fn collection_of(path: &str) -> &str {
+    match path.split('/').next() {
+        Some(collection) if !collection.is_empty() => collection,
+        _ => NO_COLLECTION,
+    }
+}
+
+/// Adds every collection of `from` into `into`.
+pub fn merge(into: &mut HashMap<String, CollStat>, from: &HashMap<String, CollStat>) {
+    for (collection, stat) in from {
+        match into.get_mut(collection) {
+            Some(total) => total.add(*stat),
+            None => {
+                into.insert(collection.clone(), *stat);
+            }
+        }
+    }
+}
+
+/// Writes a segment's totals, keyed by collection names borrowed from its paths.
+pub(crate) fn write(path: &Path, totals: &HashMap<&str, CollStat>) -> io::Result<()> {
+    // Sorted, so the same segment always gets the same bytes
+    let sorted: BTreeMap<&str, CollStat> = totals.iter().map(|(c, s)| (*c, *s)).collect();
+    let mut file = File::create(path)?;
+    file.write_all(&serde_json::to_vec(&sorted)?)?;
+    file.sync_all()
+}
+
+/// A segment's sidecar. None without one, or if it doesn't parse.
+pub(crate) fn read(path: &Path) -> Option<HashMap<String, CollStat>> {
+    let data = fs::read(path).ok()?;
+    serde_json::from_slice(&data)
+        .inspect_err(|e| tracing::warn!("{}: unreadable collection stats: {}", path.display(), e))
+        .ok()
+}
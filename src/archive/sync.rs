@@ -0,0 +1,466 @@
+//! Archive-to-archive mirroring over plain HTTP.
+//!
+//! `SyncServer` exposes an archive directory read-only:
+//!
+//! - `GET /sync/manifest`: JSON `SyncManifest` of every finished segment
+//! - `GET /sync/file/<shard>/<name>`: a segment's `.bin`, `.idx` or `.pidx`, honouring `Range: bytes=<from>-`
+//! - `GET /sync/tombstones`: the tombstone bitset, trailing zeros trimmed, zstd-compressed
+//!
+//! `SyncClient::pull` compares the manifest with a local archive directory and downloads
+//! only the segments it lacks. Files land as `.partial` first and are resumed with range
+//! requests after an interruption; each segment's Merkle root is checked before it's moved
+//! into place. Source tombstones are OR-ed into the mirror's, never cleared.
+
+use super::path_hash::{self, PATH_HASH_EXT};
+use super::{
+    count_shard_dirs, decompress_bounded, idx_header, read_idx_prefix, segment_hash_alg_id, trimmed_bitset, ArchiveMeta, Segment,
+    TombstoneStore, HASH_ALG_EXT, IDX_HEADER_SIZE, IDX_RECORD_SIZE, PATH_INDEX_ENTRY_SIZE, TOMBSTONE_FILE_SIZE,
+};
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Longest request head the server reads before giving up on a client.
+const MAX_REQUEST_HEAD: usize = 8 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SegmentInfo {
+    pub shard: usize,
+    pub start_seq: u64,
+    /// Index records, gaps included
+    pub message_count: u64,
+    /// Hex Merkle root from the `.idx` header
+    pub root_hash: String,
+    /// `MerkleAlgorithm` id the root was built with. Manifests from before it existed mean blake3.
+    #[serde(default)]
+    pub hash_alg: u8,
+    /// `PathHash` id of its path hashes. Manifests from before it existed mean FxHash.
+    #[serde(default)]
+    pub path_hash: u8,
+    pub bin_size: u64,
+    pub idx_size: u64,
+    pub has_path_index: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncManifest {
+    pub num_shards: usize,
+    pub segments: Vec<SegmentInfo>,
+}
+
+/// Outcome of `SyncClient::pull`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SyncReport {
+    pub downloaded: usize,
+    pub up_to_date: usize,
+    /// One line per segment that couldn't be fetched or failed verification
+    pub failed: Vec<String>,
+    /// Bytes transferred for segment files
+    pub bytes: u64,
+    pub tombstones_added: u64,
+}
+
+pub(super) fn segment_file_name(shard: usize, start_seq: u64, ext: &str) -> String {
+    format!("s{}_{}.{}", shard, start_seq, ext)
+}
+
+/// Start seq of a `s<shard>_<start>.<ext>` file name. Anything else (including paths) is rejected.
+fn segment_start(shard: usize, name: &str, ext: &str) -> Option<u64> {
+    let rest = name.strip_prefix(&format!("s{}_", shard))?.strip_suffix(&format!(".{}", ext))?;
+    if rest.is_empty() || !rest.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    rest.parse().ok()
+}
+
+/// Lists the finished segments of the archive at `root`. Segments whose index is still
+/// being written (size not a whole number of records) are left out.
+pub fn build_manifest(root: &Path) -> io::Result<SyncManifest> {
+    let num_shards = match ArchiveMeta::load(root)? {
+        Some(meta) => meta.num_shards,
+        None => count_shard_dirs(root),
+    };
+    let mut segments = Vec::new();
+    for shard in 0..num_shards {
+        let entries = match fs::read_dir(root.join(format!("shard_{}", shard))) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            let Some(start_seq) = path.file_name().and_then(|n| n.to_str()).and_then(|n| segment_start(shard, n, "bin")) else {
+                continue;
+            };
+            let Ok(bin_size) = fs::metadata(&path).map(|m| m.len()) else { continue };
+            let Ok(idx_size) = fs::metadata(path.with_extension("idx")).map(|m| m.len()) else { continue };
+            let header = read_idx_prefix(&path.with_extension("idx"))?;
+            let records_at = idx_header(&header).0;
+            let records = (idx_size as usize).saturating_sub(records_at);
+            if (idx_size as usize) < records_at || !records.is_multiple_of(IDX_RECORD_SIZE) {
+                continue;
+            }
+            let root_hash = &header[..IDX_HEADER_SIZE];
+            let message_count = (records / IDX_RECORD_SIZE) as u64;
+            let has_path_index = fs::metadata(path.with_extension("pidx"))
+                .is_ok_and(|m| m.len() == message_count * PATH_INDEX_ENTRY_SIZE as u64);
+            segments.push(SegmentInfo {
+                shard,
+                start_seq,
+                message_count,
+                root_hash: hex::encode(root_hash),
+                hash_alg: segment_hash_alg_id(&header, &path),
+                path_hash: path_hash::read_id(&path.with_extension(PATH_HASH_EXT)),
+                bin_size,
+                idx_size,
+                has_path_index,
+            });
+        }
+    }
+    segments.sort_by_key(|s| (s.shard, s.start_seq));
+    Ok(SyncManifest { num_shards, segments })
+}
+
+/// Read-only HTTP view of an archive directory for `SyncClient`.
+pub struct SyncServer {
+    root: PathBuf,
+    listener: TcpListener,
+}
+
+impl SyncServer {
+    pub fn bind(root: impl AsRef<Path>, addr: impl ToSocketAddrs) -> io::Result<Self> {
+        Ok(Self { root: root.as_ref().to_path_buf(), listener: TcpListener::bind(addr)? })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serves forever, one thread per connection.
+    pub fn serve(self) {
+        for stream in self.listener.incoming() {
+            let Ok(stream) = stream else { continue };
+            let root = self.root.clone();
+            thread::spawn(move || {
+                let peer = stream.peer_addr().ok();
+                if let Err(e) = handle_request(&root, stream) {
+                    tracing::debug!("sync request from {:?} failed: {}", peer, e);
+                }
+            });
+        }
+    }
+
+    pub fn spawn(self) -> JoinHandle<()> {
+        thread::spawn(move || self.serve())
+    }
+}
+
+fn handle_request(root: &Path, mut stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+    let head = read_head(&mut stream)?;
+    let mut lines = head.lines();
+    let mut request_line = lines.next().unwrap_or_default().split_whitespace();
+    let (method, target) = (request_line.next().unwrap_or_default(), request_line.next().unwrap_or_default());
+    if method != "GET" {
+        return respond(&mut stream, "405 Method Not Allowed", &[], b"");
+    }
+    // Only the open-ended form the client sends; other forms are ignored and get the whole file
+    let range_from = lines
+        .filter_map(|l| l.split_once(':'))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("range"))
+        .and_then(|(_, v)| v.trim().strip_prefix("bytes=")?.strip_suffix('-')?.parse::<u64>().ok());
+
+    let path = target.split('?').next().unwrap_or_default();
+    match path {
+        "/sync/manifest" => {
+            let body = serde_json::to_vec(&build_manifest(root)?).map_err(io::Error::other)?;
+            respond(&mut stream, "200 OK", &[("Content-Type", "application/json".to_string())], &body)
+        }
+        "/sync/tombstones" => {
+            let body = compressed_tombstones(&root.join("tombstones.bin"))?;
+            respond(&mut stream, "200 OK", &[("Content-Type", "application/zstd".to_string())], &body)
+        }
+        _ => match path.strip_prefix("/sync/file/").and_then(|rest| segment_file_path(root, rest)) {
+            Some(file) => serve_file(&mut stream, &file, range_from),
+            None => respond(&mut stream, "404 Not Found", &[], b""),
+        },
+    }
+}
+
+/// Reads up to the blank line ending the request head.
+fn read_head(stream: &mut TcpStream) -> io::Result<String> {
+    let mut head = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !head.ends_with(b"\r\n\r\n") {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "connection closed mid-request"));
+        }
+        head.extend_from_slice(&buf[..n]);
+        if head.len() > MAX_REQUEST_HEAD {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "request head too large"));
+        }
+    }
+    String::from_utf8(head).map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "request head is not UTF-8"))
+}
+
+/// `<shard>/<segment file name>` to a path under `root`, if it names a segment file.
+fn segment_file_path(root: &Path, rest: &str) -> Option<PathBuf> {
+    let (shard, name) = rest.split_once('/')?;
+    let shard: usize = shard.parse().ok()?;
+    ["bin", "idx", "pidx"].iter().find_map(|ext| segment_start(shard, name, ext))?;
+    Some(root.join(format!("shard_{}", shard)).join(name))
+}
+
+fn serve_file(stream: &mut TcpStream, path: &Path, range_from: Option<u64>) -> io::Result<()> {
+    let mut file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return respond(stream, "404 Not Found", &[], b""),
+        Err(e) => return Err(e),
+    };
+    let len = file.metadata()?.len();
+    let (status, from, mut headers) = match range_from {
+        Some(from) if from >= len => {
+            return respond(stream, "416 Range Not Satisfiable", &[("Content-Range", format!("bytes */{}", len))], b"");
+        }
+        Some(from) => ("206 Partial Content", from, vec![("Content-Range", format!("bytes {}-{}/{}", from, len - 1, len))]),
+        None => ("200 OK", 0, Vec::new()),
+    };
+    headers.push(("Content-Type", "application/octet-stream".to_string()));
+    write_head(stream, status, &headers, len - from)?;
+    file.seek(SeekFrom::Start(from))?;
+    io::copy(&mut file.take(len - from), stream)?;
+    stream.flush()
+}
+
+/// The bitset up to its last set byte, compressed. An archive without tombstones sends an empty frame.
+fn compressed_tombstones(path: &Path) -> io::Result<Vec<u8>> {
+    let file = match File::open(path) {
+        Ok(f) => f,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return zstd::bulk::compress(&[], 3),
+        Err(e) => return Err(e),
+    };
+    if file.metadata()?.len() == 0 {
+        return zstd::bulk::compress(&[], 3);
+    }
+    let bits = unsafe { Mmap::map(&file)? };
+    zstd::bulk::compress(trimmed_bitset(&bits), 3)
+}
+
+fn write_head(stream: &mut TcpStream, status: &str, headers: &[(&str, String)], content_length: u64) -> io::Result<()> {
+    let mut head = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n", status, content_length);
+    for (name, value) in headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+    stream.write_all(head.as_bytes())
+}
+
+fn respond(stream: &mut TcpStream, status: &str, headers: &[(&str, String)], body: &[u8]) -> io::Result<()> {
+    write_head(stream, status, headers, body.len() as u64)?;
+    stream.write_all(body)?;
+    stream.flush()
+}
+
+/// Pulls segments and tombstones from a `SyncServer` into a local archive directory.
+pub struct SyncClient {
+    base_url: String,
+    http: reqwest::blocking::Client,
+    dict: Option<Vec<u8>>,
+}
+
+impl SyncClient {
+    /// `base_url` is the server root, e.g. `http://home.example:8081`.
+    pub fn new(base_url: &str) -> io::Result<Self> {
+        // No overall timeout: segment files can take a while, and a stalled transfer resumes next pull
+        let http = reqwest::blocking::Client::builder()
+            .connect_timeout(Duration::from_secs(10))
+            .timeout(None)
+            .build()
+            .map_err(io::Error::other)?;
+        Ok(Self { base_url: base_url.trim_end_matches('/').to_string(), http, dict: None })
+    }
+
+    /// Dictionary the source archive was compressed with, needed to verify segments.
+    pub fn with_dict(mut self, dict: Option<Vec<u8>>) -> Self {
+        self.dict = dict;
+        self
+    }
+
+    pub fn manifest(&self) -> io::Result<SyncManifest> {
+        let resp = self.get("/sync/manifest", None)?;
+        let body = resp.bytes().map_err(io::Error::other)?;
+        serde_json::from_slice(&body).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Brings the archive at `dest` up to date with the source. Segment failures are
+    /// collected in the report rather than aborting the pull.
+    pub fn pull(&self, dest: impl AsRef<Path>) -> io::Result<SyncReport> {
+        let dest = dest.as_ref();
+        let manifest = self.manifest()?;
+        match ArchiveMeta::load(dest)? {
+            Some(meta) if meta.num_shards != manifest.num_shards => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("mirror at {} has {} shards, source has {}", dest.display(), meta.num_shards, manifest.num_shards),
+                ));
+            }
+            Some(_) => {}
+            None => {
+                fs::create_dir_all(dest)?;
+                ArchiveMeta { num_shards: manifest.num_shards }.save(dest)?;
+            }
+        }
+
+        let mut report = SyncReport::default();
+        for seg in &manifest.segments {
+            let dir = dest.join(format!("shard_{}", seg.shard));
+            fs::create_dir_all(&dir)?;
+            if is_installed(&dir, seg) {
+                report.up_to_date += 1;
+                continue;
+            }
+            match self.fetch_segment(&dir, seg, &mut report.bytes) {
+                Ok(()) => report.downloaded += 1,
+                Err(e) => {
+                    tracing::warn!("sync: shard {} segment {} failed: {}", seg.shard, seg.start_seq, e);
+                    report.failed.push(format!("shard {} segment {}: {}", seg.shard, seg.start_seq, e));
+                }
+            }
+        }
+
+        report.tombstones_added = self.merge_tombstones(dest)?;
+        Ok(report)
+    }
+
+    fn fetch_segment(&self, dir: &Path, seg: &SegmentInfo, bytes: &mut u64) -> io::Result<()> {
+        let mut files = vec![("bin", seg.bin_size), ("idx", seg.idx_size)];
+        if seg.has_path_index {
+            files.push(("pidx", seg.message_count * PATH_INDEX_ENTRY_SIZE as u64));
+        }
+        let partial = |ext: &str| dir.join(format!("{}.partial", segment_file_name(seg.shard, seg.start_seq, ext)));
+        for (ext, size) in &files {
+            let name = segment_file_name(seg.shard, seg.start_seq, ext);
+            self.download(&format!("/sync/file/{}/{}", seg.shard, name), &partial(ext), *size, bytes)?;
+        }
+
+        if let Err(e) = self.verify(seg, &partial("bin"), &partial("idx")) {
+            // Start from scratch next time rather than resuming onto bad bytes
+            for (ext, _) in &files {
+                let _ = fs::remove_file(partial(ext));
+            }
+            return Err(e);
+        }
+
+        // The algorithm ids ride in the manifest rather than as files of their own. A bare-root
+        // .idx from an older source still needs the hash id written next to it.
+        let hash_alg_path = dir.join(segment_file_name(seg.shard, seg.start_seq, HASH_ALG_EXT));
+        if seg.hash_alg != 0 && idx_header(&read_idx_prefix(&partial("idx"))?).1.is_none() {
+            fs::write(&hash_alg_path, [seg.hash_alg])?;
+        } else if hash_alg_path.exists() {
+            fs::remove_file(&hash_alg_path)?;
+        }
+        let path_hash_path = dir.join(segment_file_name(seg.shard, seg.start_seq, PATH_HASH_EXT));
+        if seg.path_hash != 0 {
+            fs::write(&path_hash_path, [seg.path_hash])?;
+        } else if path_hash_path.exists() {
+            fs::remove_file(&path_hash_path)?;
+        }
+
+        // Index last: readers only pick up a segment once its .idx exists
+        for ext in ["pidx", "bin", "idx"] {
+            if files.iter().any(|(e, _)| *e == ext) {
+                fs::rename(partial(ext), dir.join(segment_file_name(seg.shard, seg.start_seq, ext)))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn verify(&self, seg: &SegmentInfo, bin_path: &Path, idx_path: &Path) -> io::Result<()> {
+        let bin = unsafe { Mmap::map(&File::open(bin_path)?)? };
+        let idx = unsafe { Mmap::map(&File::open(idx_path)?)? };
+        let mut segment = Segment::new(seg.start_seq, bin, idx);
+        if !segment.hash_alg_in_idx() {
+            segment.hash_alg_id = seg.hash_alg;
+        } else if segment.hash_alg_id != seg.hash_alg {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Merkle hash algorithm differs from the manifest's"));
+        }
+        if hex::encode(segment.root_hash) != seg.root_hash || !segment.verify_integrity(self.dict.as_deref())? {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Merkle root mismatch"));
+        }
+        Ok(())
+    }
+
+    /// Fetches `url_path` into `partial` until it holds `expected` bytes, resuming what's there.
+    fn download(&self, url_path: &str, partial: &Path, expected: u64, bytes: &mut u64) -> io::Result<()> {
+        let mut have = fs::metadata(partial).map(|m| m.len()).unwrap_or(0);
+        if have > expected {
+            fs::remove_file(partial)?;
+            have = 0;
+        }
+        if have == expected && partial.exists() {
+            return Ok(());
+        }
+
+        let mut resp = self.get(url_path, (have > 0).then_some(have))?;
+        let resumed = resp.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+        if resumed {
+            let content_range = resp.headers().get(reqwest::header::CONTENT_RANGE).and_then(|v| v.to_str().ok());
+            if !content_range.is_some_and(|r| r.starts_with(&format!("bytes {}-", have))) {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, format!("unexpected Content-Range {:?}", content_range)));
+            }
+        }
+        // A 200 means the server ignored the range: start over
+        let mut file = if resumed { OpenOptions::new().append(true).open(partial)? } else { File::create(partial)? };
+        *bytes += resp.copy_to(&mut file).map_err(io::Error::other)?;
+        file.sync_all()?;
+
+        let got = file.metadata()?.len();
+        if got != expected {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("{}: {} of {} bytes", url_path, got, expected)));
+        }
+        Ok(())
+    }
+
+    fn merge_tombstones(&self, dest: &Path) -> io::Result<u64> {
+        let compressed = self.get("/sync/tombstones", None)?.bytes().map_err(io::Error::other)?;
+        let bits = decompress_bounded(&compressed, None, TOMBSTONE_FILE_SIZE as usize)?;
+        if bits.is_empty() {
+            return Ok(0);
+        }
+        let mut store = TombstoneStore::open_or_create(dest.join("tombstones.bin"))?;
+        Ok(store.merge(&bits))
+    }
+
+    fn get(&self, url_path: &str, range_from: Option<u64>) -> io::Result<reqwest::blocking::Response> {
+        let mut req = self.http.get(format!("{}{}", self.base_url, url_path));
+        if let Some(from) = range_from {
+            req = req.header(reqwest::header::RANGE, format!("bytes={}-", from));
+        }
+        let resp = req.send().map_err(io::Error::other)?;
+        if !resp.status().is_success() {
+            return Err(io::Error::other(format!("GET {}: HTTP {}", url_path, resp.status())));
+        }
+        Ok(resp)
+    }
+}
+
+/// True if `dir` already holds this exact segment.
+fn is_installed(dir: &Path, seg: &SegmentInfo) -> bool {
+    let bin = dir.join(segment_file_name(seg.shard, seg.start_seq, "bin"));
+    let idx = dir.join(segment_file_name(seg.shard, seg.start_seq, "idx"));
+    if fs::metadata(&bin).map(|m| m.len()).ok() != Some(seg.bin_size)
+        || fs::metadata(&idx).map(|m| m.len()).ok() != Some(seg.idx_size)
+    {
+        return false;
+    }
+    let Ok(header) = read_idx_prefix(&idx) else { return false };
+    header.get(..IDX_HEADER_SIZE).is_some_and(|root| hex::encode(root) == seg.root_hash)
+        && segment_hash_alg_id(&header, &bin) == seg.hash_alg
+        && path_hash::read_id(&dir.join(segment_file_name(seg.shard, seg.start_seq, PATH_HASH_EXT))) == seg.path_hash
+}
@@ -0,0 +1,209 @@
+//! Write-ahead journal for a shard writer's pending buffer, kept in `shard_dir/wal.log`.
+//!
+//! Each appended message is one record: `[u32 len][u64 seq][u16 did len][did][u16 path len]
+//! [path][data]`, little-endian, `len` counting everything after itself. When the writer takes
+//! its pending buffer as a segment, the journal is renamed to `wal.<start_seq>.log` beside
+//! the segment it will become, and a fresh `wal.log` is started. Persisting the segment
+//! removes the sealed journal. On open, each sealed journal whose `.idx` never appeared is
+//! written as its own segment, and `wal.log` becomes the new writer's pending buffer.
+
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+pub const WAL_FILE: &str = "wal.log";
+// seq + did len + path len
+const RECORD_HEADER_SIZE: usize = 8 + 2 + 2;
+
+/// When the journal is fsynced: after this many records or this long since the last sync,
+/// whichever comes first. A process crash loses nothing the journal took; a power loss
+/// loses at most what wasn't synced yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WalSync {
+    pub every_records: u64,
+    pub every: Duration,
+}
+
+impl Default for WalSync {
+    fn default() -> Self {
+        Self { every_records: 1000, every: Duration::from_millis(100) }
+    }
+}
+
+/// One replayed message.
+pub(crate) struct WalRecord {
+    pub seq: u64,
+    pub did: String,
+    pub path: String,
+    pub data: Vec<u8>,
+}
+
+pub(crate) struct Wal {
+    dir: PathBuf,
+    file: File,
+    sync: WalSync,
+    unsynced: u64,
+    last_sync: Instant,
+    bytes: u64,
+}
+
+fn sealed_path(dir: &Path, start_seq: u64) -> PathBuf {
+    dir.join(format!("wal.{}.log", start_seq))
+}
+
+fn encode(seq: u64, did: &str, path: &str, data: &[u8]) -> io::Result<Vec<u8>> {
+    let too_long = |what| io::Error::new(io::ErrorKind::InvalidInput, format!("{} too long for the journal", what));
+    let did_len = u16::try_from(did.len()).map_err(|_| too_long("DID"))?;
+    let path_len = u16::try_from(path.len()).map_err(|_| too_long("path"))?;
+    let len = u32::try_from(RECORD_HEADER_SIZE + did.len() + path.len() + data.len()).map_err(|_| too_long("message"))?;
+    let mut record = Vec::with_capacity(4 + len as usize);
+    record.extend_from_slice(&len.to_le_bytes());
+    record.extend_from_slice(&seq.to_le_bytes());
+    record.extend_from_slice(&did_len.to_le_bytes());
+    record.extend_from_slice(did.as_bytes());
+    record.extend_from_slice(&path_len.to_le_bytes());
+    record.extend_from_slice(path.as_bytes());
+    record.extend_from_slice(data);
+    Ok(record)
+}
+
+/// The records of one journal, up to the first that is cut short or doesn't decode (a torn
+/// last write).
+fn decode_all(buf: &[u8], source: &Path) -> Vec<WalRecord> {
+    fn take<'a>(buf: &'a [u8], at: &mut usize, n: usize) -> Option<&'a [u8]> {
+        let bytes = buf.get(*at..at.checked_add(n)?)?;
+        *at += n;
+        Some(bytes)
+    }
+    fn record(buf: &[u8], at: &mut usize) -> Option<WalRecord> {
+        let len = u32::from_le_bytes(take(buf, at, 4)?.try_into().ok()?) as usize;
+        let body = take(buf, at, len)?;
+        let mut off = 0;
+        let seq = u64::from_le_bytes(take(body, &mut off, 8)?.try_into().ok()?);
+        let did_len = u16::from_le_bytes(take(body, &mut off, 2)?.try_into().ok()?) as usize;
+        let did = std::str::from_utf8(take(body, &mut off, did_len)?).ok()?.to_string();
+        let path_len = u16::from_le_bytes(take(body, &mut off, 2)?.try_into().ok()?) as usize;
+        let path = std::str::from_utf8(take(body, &mut off, path_len)?).ok()?.to_string();
+        Some(WalRecord { seq, did, path, data: body[off..].to_vec() })
+    }
+
+    let mut records = Vec::new();
+    let mut at = 0;
+    while at < buf.len() {
+        match record(buf, &mut at) {
+            Some(r) => records.push(r),
+            None => {
+                tracing::warn!("{}: dropping a torn record at byte {} of {}", source.display(), at, buf.len());
+                break;
+            }
+        }
+    }
+    records
+}
+
+/// A sealed journal whose segment was never written.
+pub(crate) struct SealedJournal {
+    pub start_seq: u64,
+    pub path: PathBuf,
+    pub records: Vec<WalRecord>,
+}
+
+/// Messages journaled in `dir` that never made it into a segment: the sealed journals without
+/// an `.idx` for `s<shard_id>_<start_seq>`, oldest first, and the records in `wal.log`.
+/// Sealed journals whose segment did get written are removed. A seq seen twice is kept the
+/// first time.
+pub(crate) fn replay(dir: &Path, shard_id: usize) -> io::Result<(Vec<SealedJournal>, Vec<WalRecord>)> {
+    let mut sealed = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let start = path.file_name().and_then(|n| n.to_str())
+            .and_then(|n| n.strip_prefix("wal.")?.strip_suffix(".log")?.parse::<u64>().ok());
+        if let Some(start) = start {
+            if dir.join(format!("s{}_{}.idx", shard_id, start)).exists() {
+                fs::remove_file(&path)?;
+            } else {
+                sealed.push((start, path));
+            }
+        }
+    }
+    sealed.sort();
+
+    let mut seen = HashSet::new();
+    let mut read = |path: &Path| -> io::Result<Vec<WalRecord>> {
+        match fs::read(path) {
+            Ok(buf) => Ok(decode_all(&buf, path).into_iter().filter(|r| seen.insert(r.seq)).collect()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    };
+    let mut journals = Vec::with_capacity(sealed.len());
+    for (start_seq, path) in sealed {
+        let records = read(&path)?;
+        journals.push(SealedJournal { start_seq, path, records });
+    }
+    let log = read(&dir.join(WAL_FILE))?;
+    Ok((journals, log))
+}
+
+/// Renames `wal.log` to `wal.<start_seq>.log`, for a pending buffer that was replayed from
+/// it but isn't being journaled.
+pub(crate) fn seal_log(dir: &Path, start_seq: u64) -> io::Result<PathBuf> {
+    let sealed = sealed_path(dir, start_seq);
+    fs::rename(dir.join(WAL_FILE), &sealed)?;
+    Ok(sealed)
+}
+
+impl Wal {
+    /// Starts `dir/wal.log` over with `records`, the messages pending at this point.
+    pub(crate) fn create<'a>(dir: &Path, sync: WalSync, records: impl IntoIterator<Item = (u64, &'a str, &'a str, &'a [u8])>) -> io::Result<Self> {
+        let tmp = dir.join(format!("{}.tmp", WAL_FILE));
+        let mut file = File::create(&tmp)?;
+        let mut bytes = 0;
+        for (seq, did, path, data) in records {
+            let record = encode(seq, did, path, data)?;
+            file.write_all(&record)?;
+            bytes += record.len() as u64;
+        }
+        file.sync_all()?;
+        fs::rename(&tmp, dir.join(WAL_FILE))?;
+        let file = OpenOptions::new().append(true).open(dir.join(WAL_FILE))?;
+        Ok(Wal { dir: dir.to_path_buf(), file, sync, unsynced: 0, last_sync: Instant::now(), bytes })
+    }
+
+    pub(crate) fn set_sync(&mut self, sync: WalSync) {
+        self.sync = sync;
+    }
+
+    /// Bytes in `wal.log`.
+    pub(crate) fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    pub(crate) fn append(&mut self, seq: u64, did: &str, path: &str, data: &[u8]) -> io::Result<()> {
+        let record = encode(seq, did, path, data)?;
+        self.file.write_all(&record)?;
+        self.bytes += record.len() as u64;
+        self.unsynced += 1;
+        if self.unsynced >= self.sync.every_records.max(1) || self.last_sync.elapsed() >= self.sync.every {
+            self.file.sync_data()?;
+            self.unsynced = 0;
+            self.last_sync = Instant::now();
+        }
+        Ok(())
+    }
+
+    /// Renames `wal.log` to `wal.<start_seq>.log` for the segment starting at `start_seq`
+    /// and starts an empty one. Returns the sealed journal's path.
+    pub(crate) fn seal(&mut self, start_seq: u64) -> io::Result<PathBuf> {
+        self.file.sync_data()?;
+        let sealed = sealed_path(&self.dir, start_seq);
+        fs::rename(self.dir.join(WAL_FILE), &sealed)?;
+        self.file = OpenOptions::new().create(true).append(true).open(self.dir.join(WAL_FILE))?;
+        self.bytes = 0;
+        self.unsynced = 0;
+        self.last_sync = Instant::now();
+        Ok(sealed)
+    }
+}
@@ -0,0 +1,68 @@
+//! Checkout: rebuilds a DID's current records from the archive, as a directory of files or a CAR.
+
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Component, Path, PathBuf};
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use did_mmap_cache::archive::{checkout_did, MultiShardArchive};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Args {
+    /// Archive directory
+    archive: PathBuf,
+
+    /// DID whose repo to check out
+    did: String,
+
+    /// Write each record to <dir>/<collection>/<rkey>.cbor
+    #[arg(long, required_unless_present = "car")]
+    out: Option<PathBuf>,
+
+    /// Write the head commit and the records as a CAR file
+    #[arg(long, conflicts_with = "out")]
+    car: Option<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let archive = MultiShardArchive::open_readonly(&args.archive, None)?;
+    let checkout = checkout_did(&archive, &args.did)?;
+
+    match &checkout.head {
+        Some(head) => println!("[Checkout] {} records after {} commits, head at seq {} (rev {})",
+            checkout.records.len(), checkout.commits, head.seq, head.rev.as_deref().unwrap_or("-")),
+        None => bail!("no commits archived for {}", args.did),
+    }
+    for (path, record) in checkout.missing_blocks() {
+        println!("[Checkout] Missing block for {} (seq {})", path, record.seq);
+    }
+    if checkout.tombstoned > 0 || checkout.unparsed > 0 {
+        println!("[Checkout] Skipped {} tombstoned and {} unparseable messages", checkout.tombstoned, checkout.unparsed);
+    }
+
+    if let Some(car) = &args.car {
+        checkout.write_car(BufWriter::new(File::create(car)?))?;
+        println!("[Checkout] CAR written to {}", car.display());
+    } else if let Some(out) = &args.out {
+        let mut written = 0;
+        for (path, record) in &checkout.records {
+            let Some(bytes) = &record.bytes else { continue };
+            // Paths come off the wire: only plain `collection/rkey` components are written
+            if !Path::new(path).components().all(|c| matches!(c, Component::Normal(_))) {
+                println!("[Checkout] Skipping unsafe path {:?}", path);
+                continue;
+            }
+            let file = out.join(format!("{}.cbor", path));
+            if let Some(parent) = file.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&file, bytes)?;
+            written += 1;
+        }
+        println!("[Checkout] {} records written to {}", written, out.display());
+    }
+    Ok(())
+}
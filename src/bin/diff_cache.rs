@@ -0,0 +1,54 @@
+//! Diff Cache: lists the DIDs added, dropped or re-keyed between two mmap DID caches.
+
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use clap::Parser;
+use did_mmap_cache::mmap_did_cache::MmapDidCache;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Args {
+    /// The earlier cache
+    old: PathBuf,
+
+    /// The later cache
+    new: PathBuf,
+
+    /// File with one DID per line, to name the hashes in the output
+    #[arg(long)]
+    dids: Option<PathBuf>,
+
+    /// Entries printed per category
+    #[arg(long, default_value_t = 20)]
+    show: usize,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let old = MmapDidCache::open(&args.old)?;
+    let new = MmapDidCache::open(&args.new)?;
+    let diff = old.diff(&new);
+
+    let dids = match &args.dids {
+        Some(path) => fs::read_to_string(path)?,
+        None => String::new(),
+    };
+    let labels = diff.label(dids.lines().map(str::trim).filter(|l| !l.is_empty()));
+    let name = |hash: &[u8; 32]| labels.get(hash).map_or_else(|| hex::encode(hash), |did| did.to_string());
+
+    println!("[Diff] {} only in {}, {} only in {}, {} with a different key",
+        diff.only_in_self.len(), args.old.display(), diff.only_in_other.len(), args.new.display(), diff.changed.len());
+    for change in diff.changed.iter().take(args.show) {
+        println!("  changed  {}  type {} {} -> type {} {}", name(&change.did_hash),
+            change.old.1, hex::encode(change.old.0), change.new.1, hex::encode(change.new.0));
+    }
+    for hash in diff.only_in_self.iter().take(args.show) {
+        println!("  dropped  {}", name(hash));
+    }
+    for hash in diff.only_in_other.iter().take(args.show) {
+        println!("  added    {}", name(hash));
+    }
+    Ok(())
+}
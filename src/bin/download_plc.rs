@@ -1,4 +1,5 @@
 use std::{fs::OpenOptions, io::{Write, BufRead, BufReader}, thread, time::Duration, fs::File};
+use did_mmap_cache::resolver::plc_directory_base;
 use reqwest::blocking::Client;
 use serde_json::Value;
 
@@ -31,13 +32,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         total = std::fs::read_to_string(output_file)?.lines().count();
     }
 
-    println!("Starting export...");
+    let directory = plc_directory_base();
+    println!("Starting export from {}...", directory);
 
     const MAX_RETRIES: u32 = 8;
     const BASE_DELAY: u64 = 2; // seconds
 
     loop {
-        let url = format!("https://plc.directory/export?count=1000&after={}", after);
+        let url = format!("{}/export?count=1000&after={}", directory, after);
         let mut retries = 0;
         let text = loop {
             match client.get(&url).send() {
@@ -4,13 +4,18 @@
 //!   cargo run --release -p did_mmap_cache --bin firehose_tap
 //!   cargo run --release -p did_mmap_cache --bin firehose_tap -- --endpoint wss://some-pds.example.com
 //!   cargo run --release -p did_mmap_cache --bin firehose_tap -- --limit 100
+//!   cargo run --release -p did_mmap_cache --bin firehose_tap -- --compressed -e ws://localhost:8080
+//!   cargo run --release -p did_mmap_cache --bin firehose_tap -- --max-reconnects 0 -e ws://localhost:8080
 //!
 //! Connects to the firehose, parses commits, and outputs JSON to stdout.
+//! With `--compressed` it reads from a `sovereign_relay` instead, unpacking its zstd clusters.
+//! Dropped connections are resumed from the last seq, until `--max-reconnects` runs out.
 
+use did_mmap_cache::ingest::{ConnectorConfig, DisconnectReason, Endpoint, EndpointKind, FirehoseConnector, FirehoseEvent, Flow};
 use did_mmap_cache::parser::core::parse_input;
-use tungstenite::Message;
-use url::Url;
 use std::io::{self, Write};
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
 
 fn main() {
     let args: Vec<String> = std::env::args().collect();
@@ -19,6 +24,8 @@ fn main() {
     let mut endpoint = "wss://bsky.network/xrpc/com.atproto.sync.subscribeRepos".to_string();
     let mut limit: Option<u64> = None;
     let mut raw_mode = false;
+    let mut compressed = false;
+    let mut max_reconnects: Option<u32> = None;
     
     let mut i = 1;
     while i < args.len() {
@@ -42,9 +49,18 @@ fn main() {
                     i += 1;
                 }
             }
+            "--max-reconnects" => {
+                if i + 1 < args.len() {
+                    max_reconnects = args[i + 1].parse().ok();
+                    i += 1;
+                }
+            }
             "--raw" | "-r" => {
                 raw_mode = true;
             }
+            "--compressed" | "-c" => {
+                compressed = true;
+            }
             "--help" | "-h" => {
                 eprintln!("Firehose Tap - Minimal ATProto Firehose Consumer");
                 eprintln!();
@@ -54,6 +70,8 @@ fn main() {
                 eprintln!("  -e, --endpoint <URL>   WebSocket endpoint (default: bsky.network relay)");
                 eprintln!("  -n, --limit <N>        Stop after N messages");
                 eprintln!("  -r, --raw              Output raw hex instead of parsed JSON");
+                eprintln!("  -c, --compressed       Endpoint is a sovereign_relay (zstd clusters)");
+                eprintln!("  --max-reconnects <N>   Exit after N reconnects (default: retry forever)");
                 eprintln!("  -h, --help             Show this help");
                 eprintln!();
                 eprintln!("Examples:");
@@ -61,6 +79,7 @@ fn main() {
                 eprintln!("  firehose_tap -n 10                    # First 10 messages");
                 eprintln!("  firehose_tap -e wss://pds.example.com # Direct PDS connection");
                 eprintln!("  firehose_tap | jq .did                # Pipe to jq for filtering");
+                eprintln!("  firehose_tap -c -e ws://localhost:8080 # Check your own relay's output");
                 return;
             }
             _ => {}
@@ -68,74 +87,85 @@ fn main() {
         i += 1;
     }
 
-    let url = match Url::parse(&endpoint) {
-        Ok(u) => u,
-        Err(e) => {
-            eprintln!("{{\"error\":\"invalid_url\",\"message\":\"{}\"}}", e);
-            return;
-        }
-    };
-
-    let mut socket = match tungstenite::connect(url.as_str()) {
-        Ok((s, _)) => s,
-        Err(e) => {
-            eprintln!("{{\"error\":\"connection_failed\",\"message\":\"{}\"}}", e);
-            return;
-        }
+    let Some(endpoint) = Endpoint::new(EndpointKind::Relay, &endpoint) else {
+        report_error("invalid_url", &endpoint);
+        return;
     };
+    let config = ConnectorConfig { compressed, max_reconnects, ..ConnectorConfig::default() };
+    let mut connector = FirehoseConnector::new(vec![endpoint], config, Arc::new(AtomicBool::new(true)));
 
     let stdout = io::stdout();
-    let mut out = stdout.lock();
+    let count = tap(&mut connector, &mut stdout.lock(), raw_mode, limit);
+    if connector.out_of_reconnects() && limit.is_none_or(|n| count < n) {
+        report_error("reconnect_limit", &format!("gave up after {} reconnects", max_reconnects.unwrap_or_default()));
+    }
+}
+
+/// Writes every record `connector` delivers to `out` until `limit` is reached or the
+/// connector gives up. Returns the number written.
+fn tap(connector: &mut FirehoseConnector, out: &mut impl Write, raw_mode: bool, limit: Option<u64>) -> u64 {
     let mut count: u64 = 0;
+    connector.run(|event| match event {
+        FirehoseEvent::Frame { data, .. } => {
+            write_record(out, &data, raw_mode);
+            count += 1;
+            if limit.is_some_and(|n| count >= n) { Flow::Stop } else { Flow::Continue }
+        }
+        FirehoseEvent::ConnectFailed { error, .. } => {
+            report_error("connection_failed", &error.to_string());
+            Flow::Continue
+        }
+        FirehoseEvent::Disconnected { reason, .. } => {
+            if !matches!(reason, DisconnectReason::Shutdown | DisconnectReason::OutdatedCursor) {
+                report_error("disconnected", &reason.to_string());
+            }
+            Flow::Continue
+        }
+        _ => Flow::Continue,
+    });
+    out.flush().ok();
+    count
+}
 
-    loop {
-        match socket.read() {
-            Ok(Message::Binary(bin)) => {
-                if raw_mode {
-                    // Output raw hex for debugging
-                    writeln!(out, "{}", hex::encode(&bin)).ok();
-                } else {
-                    // Parse and output as JSON
-                    match parse_input(&bin) {
-                        Some(envelope) => {
-                            let did_str = envelope.did
-                                .and_then(|d| std::str::from_utf8(d).ok())
-                                .unwrap_or("unknown");
-                            let seq = envelope.sequence.unwrap_or(0);
-                            let sig_hex = envelope.signature
-                                .map(|s| hex::encode(s))
-                                .unwrap_or_default();
-                            let event_type = envelope.t
-                                .and_then(|t| std::str::from_utf8(t).ok())
-                                .unwrap_or("unknown");
-                            
-                            let json_out = serde_json::json!({
-                                "seq": seq,
-                                "did": did_str,
-                                "type": event_type,
-                                "signature_hex": sig_hex,
-                                "raw_bytes": bin.len(),
-                            });
-                            writeln!(out, "{}", json_out).ok();
-                        }
-                        None => {
-                            // Non-commit message (identity, handle, etc.) - output minimal info
-                            writeln!(out, "{{\"raw_bytes\":{},\"parse\":\"non-commit\"}}", bin.len()).ok();
-                        }
-                    }
-                }
+fn report_error(error: &str, message: &str) {
+    eprintln!("{}", serde_json::json!({ "error": error, "message": message }));
+}
 
-                count += 1;
-                if let Some(n) = limit {
-                    if count >= n {
-                        break;
-                    }
-                }
-            }
-            Ok(_) => {} // Ignore non-binary messages
-            Err(_) => {
-                break;
-            }
+fn write_record(out: &mut impl Write, bin: &[u8], raw_mode: bool) {
+    if raw_mode {
+        // Output raw hex for debugging
+        writeln!(out, "{}", hex::encode(bin)).ok();
+        return;
+    }
+    // Parse and output as JSON
+    match parse_input(bin) {
+        Ok(envelope) => {
+            let did_str = envelope.did
+                .and_then(|d| std::str::from_utf8(d).ok())
+                .unwrap_or("unknown");
+            let seq = envelope.sequence.unwrap_or(0);
+            let sig_hex = envelope.signature
+                .map(hex::encode)
+                .unwrap_or_default();
+            let event_type = envelope.t
+                .and_then(|t| std::str::from_utf8(t).ok())
+                .unwrap_or("unknown");
+            
+            let json_out = serde_json::json!({
+                "seq": seq,
+                "did": did_str,
+                "type": event_type,
+                "signature_hex": sig_hex,
+                "raw_bytes": bin.len(),
+            });
+            writeln!(out, "{}", json_out).ok();
+        }
+        Err(e) => {
+            let json_out = serde_json::json!({
+                "raw_bytes": bin.len(),
+                "parse_error": e.to_string(),
+            });
+            writeln!(out, "{}", json_out).ok();
         }
     }
 }
@@ -0,0 +1,77 @@
+//! Inspect Archive: summarises a stored archive, and exports or checks its dataset manifest.
+//! `--stats` adds messages and bytes per collection, from segments' `.stats` sidecars.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use did_mmap_cache::archive::dataset::{verify_manifest, DatasetManifest};
+use did_mmap_cache::archive::MultiShardArchive;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Args {
+    /// Archive directory
+    archive: PathBuf,
+
+    /// Write a dataset manifest of the finished segments to this file
+    #[arg(long)]
+    manifest: Option<PathBuf>,
+
+    /// Check the archive against a previously exported manifest; exits non-zero on drift
+    #[arg(long, conflicts_with = "manifest")]
+    verify: Option<PathBuf>,
+
+    /// Print messages and bytes per collection, from segments written with collection stats
+    #[arg(long, conflicts_with = "verify")]
+    stats: bool,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    if let Some(manifest) = &args.verify {
+        let report = verify_manifest(&args.archive, manifest)?;
+        println!("[Inspect] {}", report);
+        if !report.is_clean() {
+            bail!("archive differs from {}", manifest.display());
+        }
+        return Ok(());
+    }
+
+    let summary = match &args.manifest {
+        Some(out) => {
+            let archive = MultiShardArchive::open_readonly(&args.archive, None)?;
+            let manifest = archive.export_manifest(out)?;
+            println!("[Inspect] Manifest written to {}", out.display());
+            manifest
+        }
+        None => DatasetManifest::build(Path::new(&args.archive))?,
+    };
+    println!("[Inspect] {} shards, {} segments", summary.num_shards, summary.segments.len());
+    match (summary.min_seq, summary.max_seq) {
+        (Some(min), Some(max)) => println!("[Inspect] Seqs {}..={}", min, max),
+        _ => println!("[Inspect] No finished segments"),
+    }
+    println!("[Inspect] {} tombstones", summary.tombstone_count);
+
+    if args.stats {
+        let archive = MultiShardArchive::open_readonly(&args.archive, None)?;
+        println!("[Inspect] Clusters are read up to {} bytes decompressed", archive.max_decompressed_cluster_bytes());
+        let mut stats: Vec<_> = archive.collection_stats(0..u64::MAX).into_iter().collect();
+        if stats.is_empty() {
+            println!("[Inspect] No segment has collection stats; write them with the ingester's --collection-stats");
+            return Ok(());
+        }
+        stats.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then_with(|| a.0.cmp(&b.0)));
+        let total_bytes: u64 = stats.iter().map(|(_, s)| s.bytes).sum();
+        println!("[Inspect] {:<40} {:>14} {:>16} {:>7}", "COLLECTION", "MESSAGES", "BYTES", "SHARE");
+        for (collection, stat) in &stats {
+            let share = stat.bytes as f64 * 100.0 / total_bytes.max(1) as f64;
+            println!("[Inspect] {:<40} {:>14} {:>16} {:>6.1}%", collection, stat.messages, stat.bytes, share);
+        }
+        let total_messages: u64 = stats.iter().map(|(_, s)| s.messages).sum();
+        println!("[Inspect] {:<40} {:>14} {:>16}", "total", total_messages, total_bytes);
+    }
+    Ok(())
+}
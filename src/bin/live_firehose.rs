@@ -1,31 +1,58 @@
 //! Live Firehose Consumer for ATProto (High Performance Multithreaded Edition)
-//! Connects to the Bluesky firehose and verifies commit frames using mmap cache
+//! Connects to the Bluesky firehose (or a failover list of endpoints) and verifies commit frames using mmap cache
 
 use did_mmap_cache::mmap_did_cache::MmapDidCache;
-use did_mmap_cache::parser::core::{parse_input, CommitEnvelope};
-use did_mmap_cache::resolver::resolve_did;
+use did_mmap_cache::parser::core::{parse_for_verify, CommitEnvelope, OUTDATED_CURSOR};
+use did_mmap_cache::resolver::ResolverCache;
 use did_mmap_cache::monitor::{SovereignMonitor, ErrorType};
 use did_mmap_cache::mst::{MstNode, visualize::draw_mst_visual};
 use did_mmap_cache::mst::car::CarStore;
-use tungstenite::Message;
-use url::Url;
+use did_mmap_cache::ingest::{ConnectorConfig, DisconnectReason, Endpoint, FirehoseConnector, FirehoseEvent, Flow, PipelineShutdown};
+use did_mmap_cache::verify::{verify_commit_parsed, ParsedKey, VerifyError};
+use clap::Parser;
 use std::sync::atomic::{AtomicU64, AtomicBool, Ordering};
 use std::sync::{Arc, Mutex, RwLock};
 use std::collections::HashMap;
 use std::thread;
 use std::fs;
 use std::cell::RefCell;
+use std::time::Duration;
 use crossbeam_channel::unbounded;
 
-use k256::ecdsa::{VerifyingKey as K256VerifyingKey, Signature as K256Signature};
-use p256::ecdsa::{VerifyingKey as P256VerifyingKey, Signature as P256Signature};
-use k256::ecdsa::signature::hazmat::PrehashVerifier as _;
-use sha2::{Digest, Sha256};
+const DEFAULT_RELAY: &str = "wss://bsky.network/xrpc/com.atproto.sync.subscribeRepos";
 
-#[derive(Clone, Debug)]
-enum ParsedKey {
-    Secp256k1(K256VerifyingKey),
-    P256(P256VerifyingKey),
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Args {
+    /// Path to the mmap DID cache
+    cache_path: String,
+
+    /// Draw the MST for commits from this DID
+    target_did: Option<String>,
+
+    /// Firehose endpoint, highest priority first. Prefix with `pds=` for a PDS (default: relay). Repeatable.
+    #[arg(long = "endpoint", default_value = DEFAULT_RELAY)]
+    endpoints: Vec<String>,
+
+    /// Rotate to the next endpoint on repeated errors or a stall instead of retrying the same one
+    #[arg(long, default_value_t = false)]
+    failover: bool,
+
+    /// Seconds without a frame before a connection counts as stalled
+    #[arg(long, default_value_t = 30)]
+    stall_secs: u64,
+
+    /// On Ctrl-C, give already-queued frames this many seconds to be verified
+    #[arg(long, default_value_t = 10)]
+    drain_secs: u64,
+
+    /// Endpoints are sovereign_relay instances streaming zstd clusters (cursor.txt is not used)
+    #[arg(long, default_value_t = false)]
+    compressed: bool,
+
+    /// Shut down after this many reconnects instead of retrying forever
+    #[arg(long)]
+    max_reconnects: Option<u32>,
 }
 
 thread_local! {
@@ -44,11 +71,7 @@ fn resolve_did_cached(did: &str, cache: &Arc<RwLock<MmapDidCache>>) -> Option<(P
         lock.get(did)
     }?;
 
-    let parsed = match key_type {
-        1 => K256VerifyingKey::from_sec1_bytes(&pubkey_bytes).ok().map(ParsedKey::Secp256k1),
-        2 => P256VerifyingKey::from_sec1_bytes(&pubkey_bytes).ok().map(ParsedKey::P256),
-        _ => None,
-    }?;
+    let parsed = ParsedKey::from_bytes(key_type, &pubkey_bytes)?;
 
     let entry = (parsed, pubkey_bytes);
     KEY_CACHE.with(|c| {
@@ -60,18 +83,33 @@ fn resolve_did_cached(did: &str, cache: &Arc<RwLock<MmapDidCache>>) -> Option<(P
 }
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() < 2 {
-        eprintln!("Usage: {} <mmap_cache_file> [target_did]", args[0]);
-        return;
+    let args = Args::parse();
+    let cache_path = &args.cache_path;
+    let target_did_filter = args.target_did.clone();
+
+    let mut endpoints = Vec::with_capacity(args.endpoints.len());
+    for spec in &args.endpoints {
+        match Endpoint::parse(spec) {
+            Some(e) => endpoints.push(e),
+            None => {
+                eprintln!("[Error] Invalid --endpoint '{}': expected [relay=|pds=]<ws(s) url or host>", spec);
+                std::process::exit(1);
+            }
+        }
+    }
+    if args.failover && endpoints.len() < 2 {
+        eprintln!("[Warn] --failover has nothing to fail over to with a single --endpoint");
     }
-    let cache_path = &args[1];
-    let target_did_filter = args.get(2).map(|s| s.to_string());
 
-    // Zero-Stop: Load cursor from file
-    let initial_cursor = fs::read_to_string("cursor.txt")
-        .ok()
-        .and_then(|s| s.trim().parse::<u64>().ok());
+    // Zero-Stop: Load cursor from file. A relay's clusters arrive out of seq order, so no
+    // single received seq is a safe place to resume from and compressed runs don't keep one.
+    let initial_cursor = if args.compressed {
+        None
+    } else {
+        fs::read_to_string("cursor.txt")
+            .ok()
+            .and_then(|s| s.trim().parse::<u64>().ok())
+    };
 
     if let Some(c) = initial_cursor {
         println!("[Info] Resuming from sequence: {}", c);
@@ -87,76 +125,93 @@ fn main() {
     let pending_resolutions = Arc::new(Mutex::new(HashMap::<String, Vec<Vec<u8>>>::new()));
 
     let monitor = Arc::new(SovereignMonitor::new());
+    // DIDs that just failed to resolve aren't looked up again on every commit
+    let resolver = Arc::new(ResolverCache::default());
     let last_seq = Arc::new(AtomicU64::new(initial_cursor.unwrap_or(0)));
     let running = Arc::new(AtomicBool::new(true));
 
-    // Zero-Stop: Set up Graceful Shutdown
-    let last_seq_ctrlc = Arc::clone(&last_seq);
+    // Zero-Stop: Set up Graceful Shutdown (the cursor is saved once the queue has drained)
     let running_ctrlc = Arc::clone(&running);
-    ctrlc::set_handler(move || {
+    let request_stop = move || {
         println!("\n[Shutdown] Control-C detected. Finishing work and saving cursor...");
         running_ctrlc.store(false, Ordering::SeqCst);
-        let final_seq = last_seq_ctrlc.load(Ordering::SeqCst);
-        if final_seq > 0 {
-            fs::write("cursor.txt", final_seq.to_string()).expect("Failed to save cursor.txt");
-            println!("[Shutdown] Saved cursor: {}", final_seq);
-        }
-        std::process::exit(0);
-    }).expect("Error setting Ctrl-C handler");
+    };
+    ctrlc::set_handler(request_stop.clone()).expect("Error setting Ctrl-C handler");
+    // 'q' in the interactive dashboard takes the same path
+    monitor.start_interactive(request_stop);
 
     // Channel for frames: Producer pushes, Workers pull
     let (tx, rx) = unbounded::<Vec<u8>>();
+    let shutdown = Arc::new(PipelineShutdown::new(Arc::clone(&running), Duration::from_secs(args.drain_secs)));
 
     // 1. Ingestion Thread (The Producer)
     // This thread does NOTHING but read from the socket and push to the queue.
     let running_ingest = Arc::clone(&running);
     let last_seq_ingest = Arc::clone(&last_seq);
-
-    thread::spawn(move || {
-        while running_ingest.load(Ordering::SeqCst) {
-            let current_cursor = last_seq_ingest.load(Ordering::Relaxed);
-            let mut firehose_url = "wss://bsky.network/xrpc/com.atproto.sync.subscribeRepos".to_string();
-            if current_cursor > 0 {
-                firehose_url.push_str(&format!("?cursor={}", current_cursor));
+    let track_cursor = !args.compressed;
+
+    let connector_config = ConnectorConfig {
+        failover: args.failover,
+        stall_timeout: Some(Duration::from_secs(args.stall_secs)),
+        compressed: args.compressed,
+        // A relay serving this same dictionary then skips sending it
+        relay_dict: if args.compressed { fs::read("atproto_firehose.dict").ok() } else { None },
+        max_reconnects: args.max_reconnects,
+        ..ConnectorConfig::default()
+    };
+
+    let running_budget = Arc::clone(&running);
+    let producer = thread::spawn(move || {
+        let mut connector = FirehoseConnector::new(endpoints, connector_config, running_ingest)
+            .with_cursor(initial_cursor);
+        connector.run(|event| match event {
+            FirehoseEvent::Connected { endpoint, cursor } => {
+                println!("[Info] Connected to {} (cursor={:?})", endpoint, cursor);
+                Flow::Continue
             }
-            
-            let url = Url::parse(&firehose_url).unwrap();
-            let host = url.host_str().unwrap();
-            let port = url.port_or_known_default().unwrap();
-            let addr = format!("{}:{}", host, port);
-            
-            println!("[Info] Connecting to {}... (cursor={})", addr, current_cursor);
-            
-            println!("[Info] Connecting to {}...", firehose_url);
-            
-            let mut socket = match tungstenite::connect(url.as_str()) {
-                Ok((s, _)) => s,
-                Err(e) => {
-                    eprintln!("[Error] Websocket connect failed: {}. Retrying in 5s...", e);
-                    thread::sleep(std::time::Duration::from_secs(5));
-                    continue;
+            FirehoseEvent::Frame { seq, data, .. } => {
+                // Track the cursor as received, so a reset on failover isn't undone by in-flight work
+                if let Some(seq) = seq.filter(|_| track_cursor) {
+                    last_seq_ingest.store(seq, Ordering::Relaxed);
                 }
-            };
-            
-            println!("[Info] Connected to firehose.");
-
-            while running_ingest.load(Ordering::SeqCst) {
-                match socket.read() {
-                    Ok(msg) => {
-                        if let Message::Binary(bin) = msg {
-                            if tx.send(bin).is_err() { return; } // Channel closed
-                        }
-                    }
-                    Err(e) => {
-                        eprintln!("[Error] Websocket error: {}. Reconnecting...", e);
-                        break; // Break inner loop to trigger reconnect
-                    }
+                if tx.send(data).is_err() { Flow::Stop } else { Flow::Continue } // Channel closed
+            }
+            FirehoseEvent::Info { endpoint, name, message } => {
+                println!("[Info] {} says {}: {}", endpoint, name, message.unwrap_or_default());
+                if name == OUTDATED_CURSOR {
+                    // The connector reconnects from the live head; don't save the stale cursor
+                    eprintln!("[Warn] Cursor {} is older than {} keeps, resuming from the live head", last_seq_ingest.load(Ordering::Relaxed), endpoint);
+                    last_seq_ingest.store(0, Ordering::Relaxed);
                 }
+                Flow::Continue
+            }
+            FirehoseEvent::Gap { endpoint, saved_cursor, resumed_at } => {
+                eprintln!("[Warn] {} resumed at seq {}; seqs {}..{} were never received", endpoint, resumed_at, saved_cursor + 1, resumed_at);
+                Flow::Continue
+            }
+            FirehoseEvent::Disconnected { endpoint, reason } => {
+                if !matches!(reason, DisconnectReason::Shutdown | DisconnectReason::OutdatedCursor) {
+                    eprintln!("[Error] {} dropped: {}. Reconnecting...", endpoint, reason);
+                }
+                Flow::Continue
+            }
+            FirehoseEvent::ConnectFailed { endpoint, error } => {
+                eprintln!("[Error] Websocket connect to {} failed: {}. Retrying...", endpoint, error);
+                Flow::Continue
             }
-            
-            if running_ingest.load(Ordering::SeqCst) {
-                thread::sleep(std::time::Duration::from_secs(2)); // Small breathing room
+            FirehoseEvent::Switched { from, to, cursor_reset } => {
+                if cursor_reset {
+                    eprintln!("[Warn] Failing over {} -> {}: cursor does not carry over, resuming from the live head", from, to);
+                    last_seq_ingest.store(0, Ordering::Relaxed);
+                } else {
+                    println!("[Info] Failing over {} -> {}", from, to);
+                }
+                Flow::Continue
             }
+        });
+        if connector.out_of_reconnects() {
+            println!("\n[Shutdown] Reconnect limit reached. Finishing work and saving cursor...");
+            running_budget.store(false, Ordering::SeqCst);
         }
     });
 
@@ -165,26 +220,37 @@ fn main() {
     let num_workers = logical_cpus; // 1:1 ratio for pure CPU tasks
     println!("[Info] Detected {} hardware threads. Spawning {} verification workers...", logical_cpus, num_workers);
     
+    let mut workers = Vec::with_capacity(num_workers);
     for _ in 0..num_workers {
         let rx = rx.clone();
+        let shutdown = Arc::clone(&shutdown);
         let cache = Arc::clone(&cache);
         let pending_resolutions = Arc::clone(&pending_resolutions);
         let monitor = Arc::clone(&monitor);
-        let last_seq = Arc::clone(&last_seq);
+        let resolver = Arc::clone(&resolver);
         let filter_did = target_did_filter.clone();
 
-        thread::spawn(move || {
-            while let Ok(msg) = rx.recv() {
-                process_message(msg, &cache, &pending_resolutions, &monitor, &last_seq, filter_did.as_deref());
+        workers.push(thread::spawn(move || {
+            while let Some(msg) = shutdown.recv(&rx) {
+                process_message(msg, &cache, &pending_resolutions, &monitor, &resolver, filter_did.as_deref());
             }
-        });
+        }));
     }
 
     // 3. Monitor Thread (The UI Dashboard)
+    // Keeps drawing while the shutdown thread drains the queue
     let mut last_total = 0;
     let mut last_time = std::time::Instant::now();
+    let mut pipeline = Some((producer, workers));
+    let mut drain_thread = None;
     
-    while running.load(Ordering::SeqCst) {
+    while !monitor.stopped.load(Ordering::Relaxed) {
+        if !running.load(Ordering::SeqCst) {
+            if let Some((producer, workers)) = pipeline.take() {
+                let (shutdown, rx, monitor) = (Arc::clone(&shutdown), rx.clone(), Arc::clone(&monitor));
+                drain_thread = Some(thread::spawn(move || shutdown.run(&rx, vec![producer], workers, None, Some(&monitor))));
+            }
+        }
         thread::sleep(std::time::Duration::from_millis(500)); // Update dashboard twice per second
         let total = monitor.total.load(Ordering::Relaxed);
         
@@ -198,6 +264,17 @@ fn main() {
         last_total = total;
         last_time = now;
     }
+    monitor.stop_interactive();
+
+    if let Some(report) = drain_thread.and_then(|h| h.join().ok()) {
+        println!("[Shutdown] Drained {} frames, dropped {}{}.", report.drained, report.dropped,
+            if report.timed_out { " (drain timed out)" } else { "" });
+    }
+    let final_seq = last_seq.load(Ordering::SeqCst);
+    if final_seq > 0 {
+        fs::write("cursor.txt", final_seq.to_string()).expect("Failed to save cursor.txt");
+        println!("[Shutdown] Saved cursor: {}", final_seq);
+    }
 }
 
 fn process_message(
@@ -205,18 +282,14 @@ fn process_message(
     cache: &Arc<RwLock<MmapDidCache>>, 
     pending_resolutions: &Arc<Mutex<HashMap<String, Vec<Vec<u8>>>>>,
     monitor: &Arc<SovereignMonitor>,
-    last_seq: &AtomicU64,
+    resolver: &ResolverCache,
     filter_did: Option<&str>
 ) {
-    if let Some(envelope) = parse_input(&msg) {
-        // Track the cursor
-        if let Some(seq) = envelope.sequence {
-            let current = last_seq.load(Ordering::Relaxed);
-            if seq > current {
-                last_seq.store(seq, Ordering::Relaxed);
-            }
-        }
-
+    let parsed = parse_for_verify(&msg).inspect_err(|e| {
+        monitor.record_malformed();
+        tracing::debug!("malformed frame: {}", e);
+    });
+    if let Ok(envelope) = parsed {
         if let Some(t_bytes) = envelope.t {
             if t_bytes == b"#commit" || t_bytes == b"commit" {
                 if let Some(did_bytes) = envelope.did {
@@ -238,17 +311,12 @@ fn process_message(
                                 // Release lock before network call
                                 drop(pending);
 
-                                if let Some((pk, kt)) = resolve_did(did) {
+                                if let Some((pk, kt)) = resolver.resolve(did) {
                                     monitor.healed.fetch_add(1, Ordering::Relaxed);
                                     let mut lock = cache.write().unwrap();
                                     lock.atomic_update_or_tombstone(did, Some(kt), Some(&pk));
                                     
-                                    let parsed = match kt {
-                                        1 => K256VerifyingKey::from_sec1_bytes(&pk).ok().map(ParsedKey::Secp256k1),
-                                        2 => P256VerifyingKey::from_sec1_bytes(&pk).ok().map(ParsedKey::P256),
-                                        _ => None,
-                                    };
-                                    if let Some(p) = parsed {
+                                    if let Some(p) = ParsedKey::from_bytes(kt, &pk) {
                                         key_entry = Some((p, pk));
                                     }
                                 }
@@ -261,8 +329,8 @@ fn process_message(
                                 // Process all messages in the backlog now that we have the key
                                 if let Some((parsed, pk)) = &key_entry {
                                     for b_msg in backlog {
-                                        if let Some(env) = parse_input(&b_msg) {
-                                            verify_envelope(&env, parsed, pk, did, monitor, cache, filter_did);
+                                        if let Ok(env) = parse_for_verify(&b_msg) {
+                                            verify_envelope(&env, parsed, pk, did, monitor, cache, resolver, filter_did);
                                         }
                                     }
                                     return; // Already processed this message as part of the backlog
@@ -271,7 +339,7 @@ fn process_message(
                         }
 
                         if let Some((parsed, pk)) = key_entry {
-                            verify_envelope(&envelope, &parsed, &pk, did, monitor, cache, filter_did);
+                            verify_envelope(&envelope, &parsed, &pk, did, monitor, cache, resolver, filter_did);
                         } else { 
                             monitor.record_event(did, false, Some(ErrorType::MissingKey), None);
                         }
@@ -282,6 +350,7 @@ fn process_message(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn verify_envelope(
     envelope: &CommitEnvelope,
     pubkey: &ParsedKey,
@@ -289,87 +358,60 @@ fn verify_envelope(
     did: &str,
     monitor: &Arc<SovereignMonitor>,
     cache: &Arc<RwLock<MmapDidCache>>,
+    resolver: &ResolverCache,
     filter_did: Option<&str>
 ) {
-    let kt_val = match pubkey { ParsedKey::Secp256k1(_) => 1, ParsedKey::P256(_) => 2 };
-
-    if verify_commit(envelope, pubkey) {
-        monitor.record_event(did, true, None, Some(kt_val));
-
-        // MST VISUALIZER: Trigger ONLY if it's our specific target DID
-        let is_target = filter_did.map_or(false, |f| f == did);
-
-        if is_target {
-            println!("\n[MST VISUALIZER] Update for {}", did);
-            if let Some(commit_data) = envelope.commit {
-                if let Some(root_cid) = MstNode::get_root_from_commit(commit_data) {
-                    println!("  [*] Root CID: {}", root_cid);
-                    if let Some(blocks) = envelope.blocks {
-                        let store = CarStore::new(blocks);
-                        let root_cid_bytes = root_cid.to_bytes();
-                        if let Some(root_block) = store.get_block(&root_cid_bytes) {
-                            if let Ok(root_node) = MstNode::from_bytes(root_block) {
-                                draw_mst_visual(&root_node, &store, 0, Vec::new());
+    let kt_val = pubkey.key_type();
+
+    match verify_commit_parsed(envelope, pubkey) {
+        Ok(()) => {
+            monitor.record_event(did, true, None, Some(kt_val));
+
+            // MST VISUALIZER: Trigger ONLY if it's our specific target DID
+            let is_target = filter_did == Some(did);
+
+            if is_target {
+                println!("\n[MST VISUALIZER] Update for {}", did);
+                if let Some(commit_data) = envelope.commit {
+                    if let Some(root_cid) = MstNode::get_root_from_commit(commit_data) {
+                        println!("  [*] Root CID: {}", root_cid);
+                        if let Some(blocks) = envelope.blocks {
+                            let store = CarStore::new(blocks);
+                            let root_cid_bytes = root_cid.to_bytes();
+                            if let Some(root_block) = store.get_block(&root_cid_bytes) {
+                                if let Ok(root_node) = MstNode::from_bytes(root_block) {
+                                    draw_mst_visual(&root_node, &store, 0, Vec::new());
+                                }
                             }
                         }
                     }
                 }
+                println!("[MST VISUALIZER - END]\n");
             }
-            println!("[MST VISUALIZER - END]\n");
         }
-    } else {
-        // Phase 3: STALE CACHE RECOVERY
-        // Key might have rotated? 
-        if let Some((fresh_pk, fresh_kt)) = resolve_did(did) {
-            if fresh_pk != *pubkey_bytes {
-                monitor.healed.fetch_add(1, Ordering::Relaxed);
-                let mut lock = cache.write().unwrap();
-                lock.atomic_update_or_tombstone(did, Some(fresh_kt), Some(&fresh_pk));
-                
-                let fresh_key = match fresh_kt {
-                    1 => K256VerifyingKey::from_sec1_bytes(&fresh_pk).ok().map(ParsedKey::Secp256k1),
-                    2 => P256VerifyingKey::from_sec1_bytes(&fresh_pk).ok().map(ParsedKey::P256),
-                    _ => None,
-                };
-
-                if let Some(fk) = fresh_key {
-                    if verify_commit(envelope, &fk) {
-                        monitor.record_event(did, true, None, Some(fresh_kt));
-                    } else {
-                        monitor.record_event(did, false, Some(ErrorType::InvalidSignature), Some(fresh_kt));
+        Err(VerifyError::VerifyFailed) => {
+            // Phase 3: STALE CACHE RECOVERY
+            // Key might have rotated? 
+            if let Some((fresh_pk, fresh_kt)) = resolver.resolve(did) {
+                if fresh_pk != *pubkey_bytes {
+                    monitor.healed.fetch_add(1, Ordering::Relaxed);
+                    let mut lock = cache.write().unwrap();
+                    lock.atomic_update_or_tombstone(did, Some(fresh_kt), Some(&fresh_pk));
+
+                    if let Some(fk) = ParsedKey::from_bytes(fresh_kt, &fresh_pk) {
+                        match verify_commit_parsed(envelope, &fk) {
+                            Ok(()) => monitor.record_event(did, true, None, Some(fresh_kt)),
+                            Err(e) => monitor.record_event(did, false, Some(e.error_type()), Some(fresh_kt)),
+                        }
                     }
+                } else {
+                    monitor.record_event(did, false, Some(ErrorType::InvalidSignature), Some(kt_val));
                 }
             } else {
-                monitor.record_event(did, false, Some(ErrorType::InvalidSignature), Some(kt_val));
+                monitor.record_event(did, false, Some(ErrorType::MissingKey), Some(kt_val));
             }
-        } else {
-            monitor.record_event(did, false, Some(ErrorType::MissingKey), Some(kt_val));
         }
-    }
-}
-
-fn verify_commit(envelope: &CommitEnvelope, pubkey: &ParsedKey) -> bool {
-    let sig_bytes = match envelope.signature { Some(b) => b, None => return false };
-    let commit_raw = match envelope.commit { Some(b) => b, None => return false };
-    
-    // Zero-Copy Hash (Updates hasher directly from raw buffer slices)
-    let mut hasher = Sha256::new();
-    if did_mmap_cache::parser::canonical::hash_canonical_commit(commit_raw, &mut hasher) {
-        let hash = hasher.finalize();
-
-        match pubkey {
-            ParsedKey::Secp256k1(vk) => {
-                if let Ok(sig) = K256Signature::from_slice(sig_bytes) {
-                    vk.verify_prehash(&hash, &sig).is_ok()
-                } else { false }
-            },
-            ParsedKey::P256(vk) => {
-                if let Ok(sig) = P256Signature::from_slice(sig_bytes) {
-                    vk.verify_prehash(&hash, &sig).is_ok()
-                } else { false }
-            }
-        }
-    } else {
-        false
+        // The frame itself is broken; a fresh key wouldn't help
+        Err(e) => monitor.record_event(did, false, Some(e.error_type()), Some(kt_val)),
     }
 }
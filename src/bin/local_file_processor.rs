@@ -1,14 +1,14 @@
 //! ATProto Firehose Processor: Parses and verifies commit frames using mmap cache
 
 use did_mmap_cache::mmap_did_cache::MmapDidCache;
-use did_mmap_cache::parser::core::{parse_input, skip_cbor_value};
+use did_mmap_cache::parser::core::{parse_input_opt, skip_cbor_value_opt};
 use did_mmap_cache::verify::verify_commit;
 use std::env;
 use std::fs::File;
 use std::io::Read;
 
 fn process_message(data: &[u8], cache: &MmapDidCache) {
-    if let Some(envelope) = parse_input(data) {
+    if let Some(envelope) = parse_input_opt(data) {
         // Only process #commit messages
         if let Some(t) = envelope.t {
             if t == b"#commit" {
@@ -65,9 +65,9 @@ fn main() {
     let total = buf.len();
     while offset < total {
         // Find end of header
-        let Some(header_end) = skip_cbor_value(&buf, offset) else { break };
+        let Some(header_end) = skip_cbor_value_opt(&buf, offset) else { break };
         // Find end of payload
-        let Some(payload_end) = skip_cbor_value(&buf, header_end) else { break };
+        let Some(payload_end) = skip_cbor_value_opt(&buf, header_end) else { break };
         let frame = &buf[offset..payload_end];
         process_message(frame, &cache);
         offset = payload_end;
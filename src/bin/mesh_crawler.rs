@@ -17,6 +17,7 @@ use serde::{Deserialize, Serialize};
 use serde_json;
 use sonic_rs::{from_str, Value, JsonValueTrait, JsonContainerTrait};
 use url::Url;
+use did_mmap_cache::net::{probe_firehose, FirehoseProbe};
 use did_mmap_cache::pds_ledger::PdsLedger;
 
 #[derive(Parser, Debug)]
@@ -37,18 +38,31 @@ struct Args {
     /// Save results to this file
     #[arg(short, long, default_value = "mesh_map.json")]
     output: String,
+
+    /// After the HTTP probe, open each reachable PDS's firehose and wait for a first frame
+    #[arg(long)]
+    deep_probe: bool,
+
+    /// Seconds a deep probe waits for the upgrade and for the first frame
+    #[arg(long, default_value_t = 10)]
+    deep_timeout: u64,
+
+    /// Deep probes in flight at once, independent of --threads
+    #[arg(long, default_value_t = 32)]
+    deep_concurrency: usize,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
 enum HealthGrade {
     A, // Fast, responsive, modern
     B, // Responsive, but slower or lacks features
     C, // Unreliable / Slow
     D, // Barely responsive
+    #[default]
     F, // Dead / Refused
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 struct PdsReport {
     url: String,
     hostname: String,
@@ -58,6 +72,16 @@ struct PdsReport {
     app_version: Option<String>,
     error: Option<String>,
     last_seen: String,
+    /// subscribeRepos accepted a websocket and delivered a frame (only set by --deep-probe)
+    #[serde(default)]
+    ws_ok: bool,
+    #[serde(default)]
+    first_frame_ms: Option<u128>,
+    /// Seq of the first firehose frame, if it carried one
+    #[serde(default)]
+    latest_seq: Option<u64>,
+    #[serde(default)]
+    ws_error: Option<String>,
 }
 
 fn main() -> Result<()> {
@@ -68,7 +92,12 @@ fn main() -> Result<()> {
     println!("[Mesh Crawler] Found {} unique PDS candidates.", endpoints.len());
 
     println!("[Mesh Crawler] Phase 2: Probing health using {} threads...", args.threads);
-    let results = probe_endpoints(endpoints, &args);
+    let mut results = probe_endpoints(endpoints, &args);
+
+    if args.deep_probe {
+        println!("[Mesh Crawler] Phase 2b: Deep-probing firehoses, {} at a time...", args.deep_concurrency);
+        deep_probe(&mut results, &args);
+    }
 
     println!("[Mesh Crawler] Phase 3: Generating Mesh Map...");
     save_results(&results, &args.output)?;
@@ -214,6 +243,7 @@ fn probe_pds(client: &Client, url_str: String) -> PdsReport {
                         app_version: None,
                         error: None,
                         last_seen: chrono::Utc::now().to_rfc3339(),
+                        ..PdsReport::default()
                     }
                 } else {
                     PdsReport {
@@ -225,6 +255,7 @@ fn probe_pds(client: &Client, url_str: String) -> PdsReport {
                         app_version: None,
                         error: Some("Invalid JSON response".to_string()),
                         last_seen: chrono::Utc::now().to_rfc3339(),
+                        ..PdsReport::default()
                     }
                 }
             } else {
@@ -237,6 +268,7 @@ fn probe_pds(client: &Client, url_str: String) -> PdsReport {
                     app_version: None,
                     error: Some(format!("HTTP {}", status)),
                     last_seen: chrono::Utc::now().to_rfc3339(),
+                    ..PdsReport::default()
                 }
             }
         }
@@ -250,11 +282,51 @@ fn probe_pds(client: &Client, url_str: String) -> PdsReport {
                 app_version: None,
                 error: Some(e.to_string()),
                 last_seen: chrono::Utc::now().to_rfc3339(),
+                ..PdsReport::default()
             }
         }
     }
 }
 
+/// Opens the firehose of every host the HTTP phase reached, `--deep-concurrency` at a time.
+fn deep_probe(results: &mut [PdsReport], args: &Args) {
+    let wait = Duration::from_secs(args.deep_timeout);
+    let candidates: Vec<usize> = (0..results.len()).filter(|&i| results[i].grade != HealthGrade::F).collect();
+    let next = AtomicUsize::new(0);
+    let probed = Mutex::new(Vec::with_capacity(candidates.len()));
+    let reports: &[PdsReport] = results;
+    thread::scope(|scope| {
+        for _ in 0..args.deep_concurrency.max(1) {
+            scope.spawn(|| {
+                while let Some(&i) = candidates.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let probe = probe_firehose(&reports[i].url, wait);
+                    let mut done = probed.lock().unwrap();
+                    done.push((i, probe));
+                    if done.len() % 100 == 0 {
+                        eprint!("\r[Crawler] {}/{} firehoses probed...", done.len(), candidates.len());
+                    }
+                }
+            });
+        }
+    });
+    println!("\r[Crawler] {}/{} firehoses probed. Done.", candidates.len(), candidates.len());
+
+    for (i, probe) in probed.into_inner().unwrap() {
+        apply_probe(&mut results[i], probe);
+    }
+}
+
+fn apply_probe(report: &mut PdsReport, probe: FirehoseProbe) {
+    report.ws_ok = probe.ws_ok;
+    report.first_frame_ms = probe.first_frame_ms;
+    report.latest_seq = probe.latest_seq;
+    report.ws_error = probe.error;
+    // A fast describeServer is no use to the ingester if subscribeRepos doesn't stream
+    if !report.ws_ok {
+        report.grade = report.grade.max(HealthGrade::C);
+    }
+}
+
 fn save_results(results: &[PdsReport], path: &str) -> Result<()> {
     let file = File::create(path)?;
     serde_json::to_writer_pretty(file, results)?;
@@ -269,6 +341,10 @@ fn print_summary(results: &[PdsReport]) {
 
     println!("\n--- Mesh Health Summary ---");
     println!("Total PDS Nodes: {}", results.len());
+    let ws_ok = results.iter().filter(|r| r.ws_ok).count();
+    if ws_ok > 0 {
+        println!("Firehose OK: {}", ws_ok);
+    }
     for (grade, count) in grades {
         println!("Grade {}: {}", grade, count);
     }
@@ -1,7 +1,8 @@
 use memmap2::MmapOptions;
 use std::fs::File;
 use std::io::Read;
-use did_mmap_cache::parser::core::parse_input;
+use did_mmap_cache::archive::ClusterReader;
+use did_mmap_cache::parser::core::parse_input_opt;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     // 1. Load Dictionary and Files
@@ -12,57 +13,27 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let bin_file = File::open("firehose_test.bin")?;
     let bin_mmap = unsafe { MmapOptions::new().map(&bin_file)? };
 
-    let idx_file = File::open("firehose_test.idx")?;
-    let idx_mmap = unsafe { MmapOptions::new().map(&idx_file)? };
-
     let mut total_decompressed_bytes = 0;
     let mut did_bytes = 0;
     let mut sig_bytes = 0;
     let mut block_data_bytes = 0;
     let mut metadata_misc_bytes = 0;
 
-    println!("[Info] Parsing 16-byte index records...");
-    
-    // Each record is 16 bytes: bin_off (8), inner_off (4), m_len (4)
-    let num_messages = idx_mmap.len() / 16;
-    
-    // To avoid redundant decompression of the same cluster, we'll track seen offsets
-    let mut seen_clusters = std::collections::HashSet::new();
-
-    for i in 0..num_messages {
-        let chunk = &idx_mmap[i*16..(i+1)*16];
-        let bin_off = u64::from_le_bytes(chunk[0..8].try_into().unwrap()) as usize;
-        let _inner_off = u32::from_le_bytes(chunk[8..12].try_into().unwrap()) as usize;
-        let _m_len = u32::from_le_bytes(chunk[12..16].try_into().unwrap()) as usize;
-
-        if seen_clusters.insert(bin_off) {
-            let compressed_chunk = &bin_mmap[bin_off..];
-            // Use decode_all because we don't know cluster size
-            if let Ok(decompressed) = zstd::stream::decode_all_with_dictionary(compressed_chunk, &dict_data) {
-                // Since this is a research tool, we'll parse the entire cluster's messages
-                // but for simplicity here, we'll just handle the fact that parse_input
-                // might need to be called on individual messages. 
-                // Actually, the easiest way to "audit" is to parse every message one by one.
-                // We'll skip the "seen_clusters" optimization for parsing, but use it for raw stats.
-            }
-        }
-    }
+    println!("[Info] Walking the clusters of firehose_test.bin...");
 
-    // REWRITING LOOP FOR ACCURACY: Iterate through every message, parse it.
-    for i in 0..num_messages {
-        let chunk = &idx_mmap[i*16..(i+1)*16];
-        let bin_off = u64::from_le_bytes(chunk[0..8].try_into().unwrap()) as usize;
-        let inner_off = u32::from_le_bytes(chunk[8..12].try_into().unwrap()) as usize;
-        let m_len = u32::from_le_bytes(chunk[12..16].try_into().unwrap()) as usize;
+    // Clusters are back-to-back zstd frames, so the .idx isn't needed to find them
+    let mut bin_off = 0;
+    while bin_off < bin_mmap.len() {
+        let c_len = zstd::zstd_safe::find_frame_compressed_size(&bin_mmap[bin_off..])
+            .map_err(|_| format!("no zstd frame at offset {}", bin_off))?;
+        let raw = zstd::stream::decode_all_with_dictionary(&bin_mmap[bin_off..bin_off + c_len], &dict_data)?;
+        bin_off += c_len;
 
-        // Note: This is slow because it decompresses the cluster for EVERY message.
-        // In research, that's fine for small samples.
-        let compressed_chunk = &bin_mmap[bin_off..];
-        if let Ok(cluster) = zstd::stream::decode_all_with_dictionary(compressed_chunk, &dict_data) {
-            let decompressed = &cluster[inner_off..inner_off + m_len];
+        for entry in ClusterReader::new(&raw)?.iter() {
+            let decompressed = entry.data;
             total_decompressed_bytes += decompressed.len();
 
-            if let Some(parsed) = parse_input(decompressed) {
+            if let Some(parsed) = parse_input_opt(decompressed) {
                 // DID bytes
                 if let Some(did) = parsed.did {
                     did_bytes += did.len();
@@ -0,0 +1,70 @@
+// bench_stream_read.rs
+// Peak RSS of reading one message out of a cold ~4MB cluster: full decompression vs streaming.
+// Each read runs in its own child process, since peak RSS only ever grows.
+// Usage: cargo run --release --bin bench_stream_read  (Linux: reads VmHWM from /proc/self/status)
+
+use std::env;
+use std::process::Command;
+use std::time::Instant;
+use did_mmap_cache::archive::MultiShardArchive;
+
+const MESSAGES: u64 = 400;
+const MESSAGE_BYTES: usize = 10 * 1024;
+
+/// Peak resident set size in KB.
+fn peak_rss_kb() -> u64 {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|s| s.lines().find(|l| l.starts_with("VmHWM:")).map(|l| l.to_string()))
+        .and_then(|l| l.split_whitespace().nth(1).and_then(|v| v.parse().ok()))
+        .unwrap_or(0)
+}
+
+fn message(seq: u64) -> Vec<u8> {
+    // Barely compressible, so the cluster is large on disk too
+    let mut state = seq.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    (0..MESSAGE_BYTES).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state as u8
+    }).collect()
+}
+
+fn read_one(dir: &str, mode: &str) {
+    let archive = MultiShardArchive::open_readonly(dir, None).expect("Failed to open archive");
+    let before = peak_rss_kb();
+    let start = Instant::now();
+    let data = match mode {
+        "streaming" => archive.get_message_streaming(0),
+        _ => archive.get_message_by_seq(0),
+    }.expect("Read failed");
+    let elapsed = start.elapsed();
+    assert_eq!(data, message(0));
+    println!("{:<10} read {} bytes in {:>10?}, peak RSS +{} KB", mode, data.len(), elapsed, peak_rss_kb().saturating_sub(before));
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() == 3 {
+        read_one(&args[1], &args[2]);
+        return;
+    }
+
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    let archive = MultiShardArchive::new(dir.path(), 1, MESSAGES + 1, None).expect("Failed to create archive");
+    for seq in 0..MESSAGES {
+        archive.ingest(seq, "did:plc:bench", format!("app.bsky.feed.post/{}", seq), message(seq));
+    }
+    archive.shutdown();
+    println!("[Info] One cluster of {} messages ({:.1} MB decompressed)", MESSAGES, (MESSAGES as usize * MESSAGE_BYTES) as f64 / 1024.0 / 1024.0);
+
+    let exe = env::current_exe().expect("No current exe");
+    let dir_arg = dir.path().to_str().expect("Non-UTF-8 temp dir");
+    for mode in ["full", "streaming"] {
+        let status = Command::new(&exe).args([dir_arg, mode]).status().expect("Failed to spawn reader");
+        if !status.success() {
+            eprintln!("[ERROR] {} reader failed", mode);
+        }
+    }
+}
@@ -1,5 +1,5 @@
 use did_mmap_cache::archive::SegmentedArchive;
-use did_mmap_cache::parser::core::parse_input;
+use did_mmap_cache::parser::core::parse_input_opt;
 use std::fs;
 use std::sync::Arc;
 
@@ -24,7 +24,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     for i in 0..100 {
         let seq = start_seq + i;
         if let Ok(data) = archive.get_message_by_seq(seq, None) {
-            if let Some(parsed) = parse_input(&data) {
+            if let Some(parsed) = parse_input_opt(&data) {
                 if let Some(did_bytes) = parsed.did {
                     let did = std::str::from_utf8(did_bytes)?;
                     
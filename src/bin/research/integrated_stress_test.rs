@@ -3,17 +3,35 @@
 //! Reports: Per-stage throughput and queue depths to identify bottlenecks.
 
 use did_mmap_cache::mmap_did_cache::MmapDidCache;
-use did_mmap_cache::parser::core::parse_input;
-use did_mmap_cache::archive::ArchiveWriter;
+use did_mmap_cache::parser::core::parse_input_opt;
+use did_mmap_cache::archive::MultiShardArchive;
 use tungstenite::Message;
 use url::Url;
+use did_mmap_cache::net::cursor_url;
 use std::sync::atomic::{AtomicU64, AtomicBool, Ordering};
-use std::sync::{Arc, Mutex, RwLock};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
 use std::thread;
 use std::fs;
 use std::time::{Instant, Duration};
 use crossbeam_channel::unbounded;
 
+const ARCHIVE_SHARDS: usize = 4;
+/// Upper bound on messages handed to `ingest_batch` at once.
+const ARCHIVE_BATCH: usize = 1024;
+
+/// Bytes in persisted segment `.bin` files under the archive root.
+fn bin_bytes(root: &Path) -> u64 {
+    let Ok(shards) = fs::read_dir(root) else { return 0 };
+    shards.flatten()
+        .filter_map(|shard| fs::read_dir(shard.path()).ok())
+        .flat_map(|files| files.flatten())
+        .filter(|f| f.path().extension().is_some_and(|x| x == "bin"))
+        .filter_map(|f| f.metadata().ok())
+        .map(|m| m.len())
+        .sum()
+}
+
 fn main() {
     let args: Vec<String> = std::env::args().collect();
     if args.len() < 3 {
@@ -36,15 +54,15 @@ fn main() {
     let dict_data = fs::read(dict_path).expect("Missing atproto_firehose.dict - run capture_and_train first");
     println!("[Info] Loaded Zstd dictionary ({} bytes)", dict_data.len());
 
-    // Setup ArchiveWriter (starts at seq 0 for test, or we could load from cursor)
+    // Resume the firehose from the saved cursor, if any
     let initial_cursor = fs::read_to_string("cursor.txt")
         .ok()
         .and_then(|s| s.trim().parse::<u64>().ok())
         .unwrap_or(0);
         
-    let archive_writer = Arc::new(Mutex::new(
-        ArchiveWriter::new(archive_dir, 0, initial_cursor, 50_000, Some(dict_data.clone())).expect("Failed to init ArchiveWriter")
-    ));
+    let archive = Arc::new(
+        MultiShardArchive::new(archive_dir, ARCHIVE_SHARDS, 50_000, Some(dict_data.clone())).expect("Failed to init archive")
+    );
 
     // Stats
     let ingested = Arc::new(AtomicU64::new(0));
@@ -64,13 +82,12 @@ fn main() {
     let last_seq_ingest = Arc::clone(&last_seq);
     thread::spawn(move || {
         while running_ingest.load(Ordering::SeqCst) {
-            let mut firehose_url = "wss://bsky.network/xrpc/com.atproto.sync.subscribeRepos".to_string();
+            let firehose_url = Url::parse("wss://bsky.network/xrpc/com.atproto.sync.subscribeRepos").unwrap();
             let cur = last_seq_ingest.load(Ordering::Relaxed);
-            if cur > 0 { firehose_url.push_str(&format!("?cursor={}", cur)); }
             
             println!("[Stage 1] Connecting to firehose (cursor={})...", cur);
             
-            let url = Url::parse(&firehose_url).unwrap();
+            let url = cursor_url(&firehose_url, (cur > 0).then_some(cur));
             let host = url.host_str().unwrap();
             let port = url.port_or_known_default().unwrap();
             let addr = format!("{}:{}", host, port);
@@ -135,19 +152,19 @@ fn main() {
             while running_ref.load(Ordering::SeqCst) {
                 if let Ok(bin) = rx.recv() {
                     // Extract seq and verify
-                    if let Some(envelope) = parse_input(&bin) {
-                        if let Some(seq) = envelope.sequence {
-                            last_seq_ref.fetch_max(seq, Ordering::Relaxed);
-                            
-                            let did_str = envelope.did
-                                .and_then(|b| std::str::from_utf8(b).ok())
-                                .unwrap_or("unknown")
-                                .to_string();
-
-                            raw_bytes_ref.fetch_add(bin.len() as u64, Ordering::Relaxed);
-                            verified_ref.fetch_add(1, Ordering::Relaxed);
-                            let _ = tx.send((seq, did_str, bin));
-                        }
+                    let parsed = parse_input_opt(&bin).and_then(|envelope| {
+                        let did_str = envelope.did
+                            .and_then(|b| std::str::from_utf8(b).ok())
+                            .unwrap_or("unknown")
+                            .to_string();
+                        Some((envelope.sequence?, did_str))
+                    });
+                    if let Some((seq, did_str)) = parsed {
+                        last_seq_ref.fetch_max(seq, Ordering::Relaxed);
+
+                        raw_bytes_ref.fetch_add(bin.len() as u64, Ordering::Relaxed);
+                        verified_ref.fetch_add(1, Ordering::Relaxed);
+                        let _ = tx.send((seq, did_str, bin));
                     }
                 }
             }
@@ -155,7 +172,7 @@ fn main() {
     }
 
     // 3. Compression & Archive Worker (Single Threaded - the bottleneck we fear)
-    let archive_ref = Arc::clone(&archive_writer);
+    let archive_ref = Arc::clone(&archive);
     let compressed_ref = Arc::clone(&compressed);
     let running_comp = Arc::clone(&running);
     let rx_comp_worker = rx_compress.clone();
@@ -164,18 +181,26 @@ fn main() {
         println!("[Stage 3] Archive Worker Started (Clustered Batching Enabled)");
         
         while running_comp.load(Ordering::SeqCst) || !rx_comp_worker.is_empty() {
-            if let Ok((seq, did, raw_data)) = rx_comp_worker.recv() {
-                // Write - ArchiveWriter now handles compression & clustering internally
-                let mut writer = archive_ref.lock().unwrap();
-                writer.append_message(seq, &did, "test/path", &raw_data).expect("Archive write failed");
+            if let Ok(first) = rx_comp_worker.recv() {
+                // Take whatever else is queued, so each shard lock is taken once per batch
+                let (mut dids, mut rest) = (Vec::with_capacity(ARCHIVE_BATCH), Vec::with_capacity(ARCHIVE_BATCH));
+                for (seq, did, raw_data) in std::iter::once(first).chain(rx_comp_worker.try_iter().take(ARCHIVE_BATCH - 1)) {
+                    dids.push(did);
+                    rest.push((seq, raw_data));
+                }
+                let batch: Vec<_> = dids.iter()
+                    .zip(rest)
+                    .map(|(did, (seq, raw_data))| (seq, did.as_str(), "test/path".to_string(), raw_data))
+                    .collect();
+                // The archive handles compression & clustering internally
+                archive_ref.ingest_batch(&batch);
                 
-                compressed_ref.fetch_add(1, Ordering::Relaxed);
+                compressed_ref.fetch_add(batch.len() as u64, Ordering::Relaxed);
             }
         }
 
-        // Finalize the last segment before exiting
-        let mut writer = archive_ref.lock().unwrap();
-        writer.finalize_segment().ok();
+        // Finalize the last segments before exiting
+        archive_ref.shutdown();
         println!("[Stage 3] Finalized segments.");
     });
 
@@ -190,10 +215,7 @@ fn main() {
         let c = compressed.load(Ordering::Relaxed);
         let r_b = raw_bytes.load(Ordering::Relaxed);
 
-        let compressed_bytes = {
-            let writer = archive_writer.lock().unwrap();
-            writer.total_compressed_bytes
-        };
+        let compressed_bytes = bin_bytes(Path::new(archive_dir));
         
         let q_v = rx_verify.len();
         let q_c = rx_compress.len();
@@ -2,7 +2,7 @@ use memmap2::MmapOptions;
 use std::fs::File;
 use std::io::Read;
 use std::time::Instant;
-use did_mmap_cache::parser::core::parse_input;
+use did_mmap_cache::parser::core::parse_input_opt;
 use zstd::dict::DecoderDictionary;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -63,7 +63,7 @@ fn fetch_and_parse(
     let mut decompressed = Vec::with_capacity(compressed_chunk.len() * 3);
     decoder.read_to_end(&mut decompressed)?;
 
-    let _ = parse_input(&decompressed);
+    let _ = parse_input_opt(&decompressed);
     
     Ok(())
 }
@@ -87,7 +87,7 @@ fn peek_at(bin: &[u8], idx: &[u8], dict: &DecoderDictionary, msg_id: usize) -> R
     println!("[Success] Decompressed message #{} ({} bytes raw -> {} bytes decompressed)", 
         msg_id, compressed_chunk.len(), decompressed.len());
 
-    if let Some(parsed) = parse_input(&decompressed) {
+    if let Some(parsed) = parse_input_opt(&decompressed) {
         println!("\n[MESSAGE DETAILS]");
         println!("===========================================");
         println!("Type:       {}", std::str::from_utf8(parsed.t.unwrap_or(b"unknown")).unwrap_or("err"));
@@ -1,7 +1,7 @@
 //! Pipeline Benchmark: Verification + Compression + Archive IO
 //! This test floods the pipeline as fast as possible to find the physical breakdown point.
 
-use did_mmap_cache::parser::core::parse_input;
+use did_mmap_cache::parser::core::parse_input_opt;
 use did_mmap_cache::archive::ArchiveWriter;
 use std::sync::atomic::{AtomicU64, AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
@@ -2,7 +2,7 @@ use memmap2::MmapOptions;
 use std::fs::File;
 use std::io::Read;
 use std::collections::HashSet;
-use did_mmap_cache::parser::core::parse_input;
+use did_mmap_cache::parser::core::parse_input_opt;
 use did_mmap_cache::mst::car::CarStore;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -39,7 +39,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         
         let decompressed = decompressor.decompress(compressed_chunk, 1024 * 1024).unwrap();
         
-        if let Some(parsed) = parse_input(&decompressed) {
+        if let Some(parsed) = parse_input_opt(&decompressed) {
             if let Some(car_data) = parsed.blocks {
                 total_car_bytes_received += car_data.len();
                 
@@ -30,13 +30,13 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         
         // Use our parser to verify the signature
         match parse_input(&data) {
-            Some(_) => {
+            Ok(_) => {
                 println!("[Success] Seq {}: Parsed Successfully", seq);
             },
-            None => {
-                println!("[Failure] Seq {}: Parsing Error", seq);
+            Err(e) => {
+                println!("[Failure] Seq {}: Parsing Error ({})", seq, e);
             }
-        }
+        };
     }
 
     Ok(())
@@ -1,4 +1,4 @@
-use did_mmap_cache::archive::SegmentedArchive;
+use did_mmap_cache::archive::{idx_records_offset, SegmentedArchive};
 use std::fs;
 use std::path::Path;
 use std::sync::Arc;
@@ -45,7 +45,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     for i in 0..5 {
         // Updated for 28-byte index format
-        let idx_off = 32 + i * 28;
+        let idx_off = idx_records_offset(&idx_bytes) + i * 28;
         if idx_off + 28 > idx_bytes.len() { break; }
         
         let chunk = &idx_bytes[idx_off..idx_off + 28];
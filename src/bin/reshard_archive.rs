@@ -0,0 +1,53 @@
+// reshard_archive.rs
+// Copy an archive into a new directory with a different shard count, keeping seqs and tombstones.
+// Usage: cargo run --release --bin reshard_archive -- [--drop-unparseable] <src_archive> <dst_archive> <new_shard_count> [dict]
+
+use std::env;
+use std::fs;
+use std::time::Instant;
+use did_mmap_cache::archive::{reshard, ArchiveMeta};
+
+fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    // Frames that no longer parse can't be routed; by default they stop the reshard
+    let drop_unparseable = args.iter().any(|a| a == "--drop-unparseable");
+    args.retain(|a| a != "--drop-unparseable");
+    if args.len() < 4 || args.len() > 5 {
+        eprintln!("Usage: {} [--drop-unparseable] <src_archive> <dst_archive> <new_shard_count> [dict]", args[0]);
+        std::process::exit(1);
+    }
+    let src_path = &args[1];
+    let dst_path = &args[2];
+    let new_count: usize = match args[3].parse() {
+        Ok(n) if n > 0 => n,
+        _ => {
+            eprintln!("[ERROR] <new_shard_count> must be a positive integer");
+            std::process::exit(1);
+        }
+    };
+    let dict = args.get(4).map(|p| fs::read(p).expect("Failed to read dictionary"));
+
+    match ArchiveMeta::load(src_path.as_ref()) {
+        Ok(Some(meta)) => println!("Source: {} shards", meta.num_shards),
+        Ok(None) => println!("Source: no archive_meta.json, using shard_N directories as found"),
+        Err(e) => {
+            eprintln!("[ERROR] {}", e);
+            std::process::exit(1);
+        }
+    }
+    println!("Target: {} shards at {}", new_count, dst_path);
+
+    let start = Instant::now();
+    match reshard(src_path, dst_path, new_count, 50_000, dict, drop_unparseable) {
+        Ok(report) => {
+            println!("Done in {:?}: {} messages copied, {} tombstones carried over", start.elapsed(), report.copied, report.tombstoned);
+            if report.skipped > 0 {
+                println!("[WARN] {} stored frames could not be parsed and were dropped", report.skipped);
+            }
+        }
+        Err(e) => {
+            eprintln!("[ERROR] Reshard failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
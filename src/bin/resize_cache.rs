@@ -0,0 +1,46 @@
+// resize_cache.rs
+// Rehash an existing mmap DID cache into a new file with a different slot count.
+// Usage: cargo run --release --bin resize_cache -- <old_cache.bin> <new_cache.bin> <new_num_slots>
+
+use std::env;
+use std::time::Instant;
+use did_mmap_cache::mmap_did_cache::MmapDidCache;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 4 {
+        eprintln!("Usage: {} <old_cache.bin> <new_cache.bin> <new_num_slots>", args[0]);
+        std::process::exit(1);
+    }
+    let src_path = &args[1];
+    let dst_path = &args[2];
+    let new_slots: usize = match args[3].replace('_', "").parse() {
+        Ok(n) if n > 0 => n,
+        _ => {
+            eprintln!("[ERROR] <new_num_slots> must be a positive integer");
+            std::process::exit(1);
+        }
+    };
+    if src_path == dst_path {
+        eprintln!("[ERROR] Refusing to resize in place; write to a new file and swap it in.");
+        std::process::exit(1);
+    }
+
+    let src = MmapDidCache::open(src_path).expect("Failed to open source cache");
+    let live = src.iter_entries().count();
+    println!("Source: {} slots, {} live entries ({:.2}% load)", src.num_slots(), live, live as f64 / src.num_slots() as f64 * 100.0);
+    println!("Target: {} slots ({:.2}% load, {:.1} GB)", new_slots, live as f64 / new_slots as f64 * 100.0, (new_slots as f64 * 99.0) / 1e9);
+    if live >= new_slots {
+        eprintln!("[ERROR] Target table is too small for {} entries", live);
+        std::process::exit(1);
+    }
+
+    let start = Instant::now();
+    match src.resize_into(dst_path, new_slots) {
+        Ok(copied) => println!("Done! Rehashed {} entries in {:?}", copied, start.elapsed()),
+        Err(e) => {
+            eprintln!("[ERROR] Resize failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
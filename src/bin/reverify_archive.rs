@@ -0,0 +1,95 @@
+//! Reverify Archive: re-runs signature verification over a stored archive.
+//! After a canonicalizer or key parsing fix, diff the failure CSVs of two runs to see which
+//! commits changed verdict.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::Result;
+use clap::Parser;
+use did_mmap_cache::archive::MultiShardArchive;
+use did_mmap_cache::mmap_did_cache::MmapDidCache;
+use did_mmap_cache::resolver::ResolverCache;
+use did_mmap_cache::verify::{reverify, reverify_with};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Args {
+    /// Archive directory
+    archive: String,
+
+    /// Mmap DID cache to take keys from
+    cache: String,
+
+    /// Zstd dictionary the archive was written with
+    #[arg(long, default_value = "atproto_firehose.dict")]
+    dict: String,
+
+    /// First seq to check (default: the archive's first)
+    #[arg(long)]
+    from: Option<u64>,
+
+    /// Last seq to check, inclusive (default: the archive's last)
+    #[arg(long)]
+    to: Option<u64>,
+
+    /// Verification threads (default: one per CPU)
+    #[arg(long)]
+    workers: Option<usize>,
+
+    /// Resolve DIDs missing from the cache over the network instead of reporting NoKey
+    #[arg(long)]
+    allow_network: bool,
+
+    /// Where to write one row per failing commit
+    #[arg(short, long, default_value = "reverify_failures.csv")]
+    out: String,
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let dict = if Path::new(&args.dict).exists() {
+        Some(std::fs::read(&args.dict)?)
+    } else {
+        println!("[Reverify] No dictionary at {}; assuming the archive was written without one.", args.dict);
+        None
+    };
+    let archive = MultiShardArchive::open_readonly(&args.archive, dict)?;
+    let cache = MmapDidCache::open(&args.cache)?;
+
+    let range = match (args.from.or(archive.min_seq()), args.to.or(archive.max_seq())) {
+        (Some(from), Some(to)) if from <= to => from..to + 1,
+        _ => {
+            println!("[Reverify] Nothing to check.");
+            return Ok(());
+        }
+    };
+    let workers = args.workers.unwrap_or_else(num_cpus::get);
+    println!("[Reverify] Checking seqs {}..={} on {} workers...", range.start, range.end - 1, workers);
+
+    let start = Instant::now();
+    let report = if args.allow_network {
+        let resolver = ResolverCache::default();
+        reverify_with(&archive, Some(range), workers, |did| cache.get(did).or_else(|| resolver.resolve(did)))
+    } else {
+        reverify(&archive, &cache, Some(range), workers)
+    };
+
+    println!(
+        "[Reverify] Done in {:?}: {} commits checked, {} verified, {} failed, {} non-commit frames skipped",
+        start.elapsed(), report.checked, report.verified, report.failures.len(), report.skipped
+    );
+    for (error, n) in &report.by_error {
+        println!("  {:<18} {}", error, n);
+    }
+    for (key_type, counts) in &report.by_key_type {
+        println!("  {:<18} {} verified, {} failed", key_type, counts.verified, counts.failed);
+    }
+
+    report.write_csv(BufWriter::new(File::create(&args.out)?))?;
+    println!("[Reverify] Failures written to {}", args.out);
+    Ok(())
+}
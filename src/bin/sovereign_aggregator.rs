@@ -4,23 +4,25 @@
 //! This tool proves that a single home computer can manage 10,000+ persistent
 //! WebSocket connections to aggregate the global ATProto firehose.
 
+use did_mmap_cache::aggregate::{SiegeArchiver, SiegeStats};
+use did_mmap_cache::archive::MultiShardArchive;
+use did_mmap_cache::mmap_did_cache::MmapDidCache;
+use did_mmap_cache::net::{BackoffPolicy, FailureKind};
 use did_mmap_cache::pds_ledger::{PdsEntry, PdsLedger};
+use did_mmap_cache::plc::ExportStream;
+use did_mmap_cache::resolver::plc_directory_base;
 use futures::StreamExt;
 use std::sync::{Arc, Mutex};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::{Duration, Instant};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use dashmap::{DashMap, DashSet};
-use fastbloom::BloomFilter;
 use tokio::sync::mpsc;
 use tokio::task::JoinSet;
 use bytes::Bytes;
 use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
 use url::Url;
 use tracing::{info, warn, error};
-use sonic_rs::JsonValueTrait;
-
-use std::io::Write;
 
 /// The Sovereign Registry: Tracks all known PDS endpoints
 struct PdsRegistry {
@@ -28,16 +30,63 @@ struct PdsRegistry {
     active_workers: DashSet<String>,
     ledger: Mutex<Option<PdsLedger>>,
     url_to_idx: DashMap<String, usize>,
-    bloom: Mutex<BloomFilter>, 
+    /// Archive counters and the per-endpoint cursors workers resume from
+    archive_stats: Arc<SiegeStats>,
 }
 
 enum WorkerResponse {
     Connected(String),
     Success(Arc<String>, [u8; 32], Bytes),
-    Failure(String),
+    Failure(String, FailureKind),
     Closed,
 }
 
+/// Penalties double from a minute up to an hour.
+fn backoff_policy() -> BackoffPolicy {
+    BackoffPolicy {
+        drop_base: Duration::from_secs(60),
+        error_base: Duration::from_secs(60),
+        cap: Duration::from_secs(3600),
+        ..BackoffPolicy::default()
+    }
+}
+
+/// Cursors and the dedup bloom filter are rewritten this often while the siege runs, and once
+/// more on shutdown.
+const CURSOR_SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+struct SiegeOptions {
+    archive: String,
+    cursors: String,
+    bloom: String,
+    cache: Option<String>,
+    dry_run: bool,
+}
+
+impl SiegeOptions {
+    fn parse(args: &[String]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut opts = SiegeOptions {
+            archive: "./siege_archive".to_string(),
+            cursors: "siege_cursors.json".to_string(),
+            bloom: "siege_bloom.bin".to_string(),
+            cache: None,
+            dry_run: false,
+        };
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--archive" => opts.archive = iter.next().ok_or("--archive needs a directory")?.clone(),
+                "--cursors" => opts.cursors = iter.next().ok_or("--cursors needs a file")?.clone(),
+                "--bloom" => opts.bloom = iter.next().ok_or("--bloom needs a file")?.clone(),
+                "--cache" => opts.cache = Some(iter.next().ok_or("--cache needs a file")?.clone()),
+                "--dry-run" => opts.dry_run = true,
+                other => return Err(format!("Unknown siege option: {}", other).into()),
+            }
+        }
+        Ok(opts)
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt::init();
@@ -46,7 +95,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     if args.len() < 3 {
         eprintln!("Usage:");
         eprintln!("  {} discover <pds_list_file>   - Crawl PLC to find PDS nodes", args[0]);
-        eprintln!("  {} siege <pds_list_file>      - Connect to all nodes in the list", args[0]);
+        eprintln!("  {} siege <pds_list_file> [--archive <dir>] [--cursors <file>] [--bloom <file>] [--cache <file>] [--dry-run]", args[0]);
+        eprintln!("                                 - Connect to all nodes in the list and archive unique frames");
         eprintln!("  {} migrate <pds_list_file>    - Convert .txt list to .bin ledger", args[0]);
         eprintln!("  {} inspect <pds_ledger_file>  - Display statistics from binary ledger", args[0]);
         return Ok(());
@@ -58,7 +108,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     if mode == "discover" {
         run_discovery(list_path).await
     } else if mode == "siege" {
-        run_siege(list_path).await
+        run_siege(list_path, SiegeOptions::parse(&args[3..])?).await
     } else if mode == "migrate" {
         run_migration(list_path).await
     } else if mode == "inspect" {
@@ -79,6 +129,8 @@ async fn run_discovery(list_path: &str) -> Result<(), Box<dyn std::error::Error>
     };
     
     let mut ledger = PdsLedger::open_or_create(&bin_path)?;
+    // A crash mid-append can leave a torn last entry
+    ledger.repair()?;
     let (new_pds_tx, mut new_pds_rx) = mpsc::channel::<String>(1000);
     let endpoints = Arc::new(DashSet::new());
     let total_scanned = Arc::new(AtomicU64::new(0));
@@ -96,142 +148,47 @@ async fn run_discovery(list_path: &str) -> Result<(), Box<dyn std::error::Error>
         info!("Loaded {} existing PDS endpoints from binary ledger.", endpoints.len());
     }
 
+    // Resumes from the cursor file; the first run crawls from the start of 2023
     let cursor_path = format!("{}.cursor", list_path);
-    let after = if let Ok(ts) = std::fs::read_to_string(&cursor_path) {
-        let ts = ts.trim().to_string();
-        info!("Resuming discovery from cursor: {}", ts);
-        ts
-    } else {
-        "2023-01-01T00:00:00.000Z".to_string()
-    };
-
-    // 1. Health Check & Interactive Prompt
-    let client = reqwest::Client::new();
-    info!("Checking PLC Directory rate limits...");
-    let probe_url = format!("https://plc.directory/export?count=1&after={}", after);
-    let resp = client.get(&probe_url).send().await?;
-    
-    if resp.status().as_u16() == 429 {
-        error!("PLC Directory is currently rate-limiting this IP (429). Please wait a few minutes.");
-        return Ok(());
-    } else if resp.status().is_success() {
-        print!("PLC is reachable. Resume discovery from {}? (y/n): ", after);
-        std::io::stdout().flush()?;
-        let mut input = String::new();
-        std::io::stdin().read_line(&mut input)?;
-        if input.trim().to_lowercase() != "y" {
-            info!("Discovery aborted by user.");
-            return Ok(());
-        }
-    } else {
-        error!("Unexpected status from PLC: {}", resp.status());
-        return Ok(());
-    }
+    let mut stream = ExportStream::new(&plc_directory_base(), "2023-01-01T00:00:00.000Z")
+        .with_cursor_file(&cursor_path)?
+        .follow(true);
+    info!("Discovering PDS nodes from PLC operations after {}", stream.cursor());
 
-    // Spawn Discovery Module (Polite Single-Threaded Crawl)
+    // Spawn Discovery Module (Polite Single-Threaded Crawl). The stream blocks, so it gets
+    // its own thread.
     let discovery_tx = new_pds_tx.clone();
     let registry_discovery = Arc::clone(&endpoints);
     let total_scanned_spawn = Arc::clone(&total_scanned);
-    let cursor_path_clone = cursor_path.clone();
-    let mut after_loop = after.clone();
-    
-    tokio::spawn(async move {
-        let client = reqwest::Client::new();
+    std::thread::spawn(move || {
         let mut last_cursor_save = Instant::now();
-        let mut target_delay = Duration::from_millis(500);
-        let mut floor_delay = Duration::from_millis(150);
-        let mut success_streak = 0;
-        
-        loop {
-            let url = format!("https://plc.directory/export?count=1000&after={}", after_loop);
-            match client.get(&url).send().await {
-                Ok(resp) => {
-                    let status = resp.status();
-                    if status.as_u16() == 429 {
-                        success_streak = 0;
-                        // The "Sweet Spot" Logic: If we hit a 429, the previous delay was our "Failure Point".
-                        // We set a new Floor 50ms higher than that failure point.
-                        let failure_point = target_delay;
-                        floor_delay = (failure_point + Duration::from_millis(50)).min(Duration::from_secs(2));
-                        target_delay = (floor_delay + Duration::from_millis(250)).min(Duration::from_secs(5));
-
-                        warn!("SWEET SPOT SEARCH: {}ms was too fast. Setting new safe floor to {}ms and cooling down...", 
-                            failure_point.as_millis(), floor_delay.as_millis());
-                        
-                        tokio::time::sleep(Duration::from_secs(120)).await;
-                        continue;
-                    }
-                    if !status.is_success() {
-                        success_streak = 0;
-                        error!("Discovery: Request failed with status: {}. Backing off...", status);
-                        tokio::time::sleep(Duration::from_secs(30)).await;
-                        continue;
-                    }
-
-                    // Success: Try to optimize toward the floor
-                    success_streak += 1;
-                    if success_streak >= 8 && target_delay > floor_delay {
-                        target_delay -= Duration::from_millis(25);
-                        success_streak = 0;
-                        info!("Discovery Strategy: Optimizing toward sweet spot (Target: {}ms, Floor: {}ms)", 
-                            target_delay.as_millis(), floor_delay.as_millis());
-                    }
-
-                    if let Ok(text) = resp.text().await {
-                        if text.trim().is_empty() { 
-                            // Reached the current tip
-                            tokio::time::sleep(Duration::from_secs(60)).await;
-                            continue;
-                        }
-                        
-                        let mut current_page_max_ts = None;
-                        for line in text.lines() {
-                            total_scanned_spawn.fetch_add(1, Ordering::Relaxed);
-                            
-                            // POWERHOUSE: Using sonic_rs for SIMD-accelerated JSON scanning
-                            if let Ok(v) = sonic_rs::from_str::<sonic_rs::Value>(line) {
-                                if let Some(endpoint) = v.pointer(["operation", "services", "atproto_pds", "endpoint"])
-                                    .as_str() {
-                                    
-                                    let mut pds_url = endpoint.to_string();
-                                    if pds_url.starts_with("https://") {
-                                        pds_url = pds_url.replace("https://", "wss://");
-                                    }
-                                    if !pds_url.ends_with("/xrpc/com.atproto.sync.subscribeRepos") {
-                                        pds_url = format!("{}/xrpc/com.atproto.sync.subscribeRepos", pds_url.trim_end_matches('/'));
-                                    }
-
-                                    if !registry_discovery.contains(&pds_url) {
-                                        if registry_discovery.insert(pds_url.clone()) {
-                                            let _ = discovery_tx.send(pds_url).await;
-                                        }
-                                    }
-                                }
-                                if let Some(ts) = v.get("createdAt").as_str() {
-                                    current_page_max_ts = Some(ts.to_string());
-                                }
-                            }
-                        }
-
-                        if let Some(ts) = current_page_max_ts {
-                            after_loop = ts;
-                            if last_cursor_save.elapsed() >= Duration::from_secs(10) {
-                                let _ = std::fs::write(&cursor_path_clone, &after_loop);
-                                last_cursor_save = Instant::now();
-                            }
-                        }
-                    }
-                    // Adaptive Throughput: Uses the dynamically adjusted target_delay
-                    tokio::time::sleep(target_delay).await;
-                }
+        while let Some(next) = stream.next() {
+            let op = match next {
+                Ok(op) => op,
                 Err(e) => {
-                    success_streak = 0;
-                    target_delay = (target_delay + Duration::from_millis(200)).min(Duration::from_secs(5));
-                    error!("Discovery: Network error: {}. Increasing delay to {}ms", e, target_delay.as_millis());
-                    // Exponential backoff on network errors (10s -> 30s)
-                    tokio::time::sleep(Duration::from_secs(15)).await;
+                    error!("Discovery stopped: {}", e);
+                    break;
+                }
+            };
+            total_scanned_spawn.fetch_add(1, Ordering::Relaxed);
+            if let Some(endpoint) = op.pds_endpoint {
+                let mut pds_url = endpoint;
+                if pds_url.starts_with("https://") {
+                    pds_url = pds_url.replace("https://", "wss://");
+                }
+                if !pds_url.ends_with("/xrpc/com.atproto.sync.subscribeRepos") {
+                    pds_url = format!("{}/xrpc/com.atproto.sync.subscribeRepos", pds_url.trim_end_matches('/'));
+                }
+                if !registry_discovery.contains(&pds_url) && registry_discovery.insert(pds_url.clone()) {
+                    let _ = discovery_tx.blocking_send(pds_url);
                 }
             }
+            if last_cursor_save.elapsed() >= Duration::from_secs(10) {
+                if let Err(e) = stream.checkpoint() {
+                    warn!("Discovery: failed to save cursor: {}", e);
+                }
+                last_cursor_save = Instant::now();
+            }
         }
     });
 
@@ -264,22 +221,49 @@ async fn run_discovery(list_path: &str) -> Result<(), Box<dyn std::error::Error>
     }
 }
 
-async fn run_siege(list_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+async fn run_siege(list_path: &str, opts: SiegeOptions) -> Result<(), Box<dyn std::error::Error>> {
     info!("Starting Sovereign Siege (Phase 2: Stress Test)");
     
     // 1. Connection Pooling setup - (Arc<String> source, Hash, Bytes)
     let (tx, mut rx) = mpsc::channel::<WorkerResponse>(500_000);
+    let archive_stats = Arc::new(SiegeStats::default());
+    let resumed = archive_stats.load_cursors(&opts.cursors)?;
+    info!("Loaded {} cursors from {}", resumed, opts.cursors);
     let registry = Arc::new(PdsRegistry {
         endpoints: DashSet::new(),
         active_workers: DashSet::new(),
         ledger: Mutex::new(None),
         url_to_idx: DashMap::new(),
-        bloom: Mutex::new(BloomFilter::with_num_bits(8 * 1024 * 1024).hashes(4)), // 1MB Bloom Filter
+        archive_stats: Arc::clone(&archive_stats),
     });
 
+    // Unique frames go to a writer thread, so a slow shard lock never stalls this loop
+    let archive = if opts.dry_run {
+        info!("Dry run: frames are deduplicated but not archived");
+        None
+    } else {
+        let dict = std::fs::read("atproto_firehose.dict").ok();
+        let archive = Arc::new(MultiShardArchive::new(&opts.archive, 16, 50_000, dict)?);
+        archive.start_idle_flush(Duration::from_secs(30));
+        Some(archive)
+    };
+    // With a DID cache, only commits whose signature verifies are archived
+    let cache = match &opts.cache {
+        Some(path) => {
+            info!("Verifying commits against {}", path);
+            Some(MmapDidCache::open(path)?)
+        }
+        None => None,
+    };
+    let mut archiver = SiegeArchiver::new_verifying(archive, Arc::clone(&archive_stats), cache);
+    if archiver.load_bloom(&opts.bloom)? {
+        info!("Loaded dedup bloom filter from {}", opts.bloom);
+    }
+
     // 2. Load the PDS list (Prefer binary ledger)
     if list_path.ends_with(".bin") || std::path::Path::new(list_path).exists() && !list_path.ends_with(".txt") {
-        let ledger = PdsLedger::open_or_create(list_path)?;
+        let mut ledger = PdsLedger::open_or_create(list_path)?;
+        ledger.repair()?;
         for i in 0..ledger.entry_count() {
             if let Some(entry) = ledger.get_entry(i) {
                 let url = entry.get_url();
@@ -310,12 +294,15 @@ async fn run_siege(list_path: &str) -> Result<(), Box<dyn std::error::Error>> {
     let mut total_bytes = 0u64;
     let mut unique_count = 0u64;
     let mut last_report = Instant::now();
+    let mut last_archived = 0u64;
+    let mut last_cursor_save = Instant::now();
 
     let mut success_count = 0u64;
     let mut fail_count = 0u64;
     
-    let mut seen_hashes: std::collections::VecDeque<[u8; 32]> = std::collections::VecDeque::new();
-    let mut hash_set: std::collections::HashSet<[u8; 32]> = std::collections::HashSet::new();
+    let policy = backoff_policy();
+    // Messages per worker since the last report, folded into each node's rolling rate
+    let mut host_counts: HashMap<Arc<String>, u64> = HashMap::new();
 
     // 4. Ingestion & Storage Loop
     let mut join_set = JoinSet::new();
@@ -331,6 +318,9 @@ async fn run_siege(list_path: &str) -> Result<(), Box<dyn std::error::Error>> {
     let mut spawn_interval = tokio::time::interval(Duration::from_millis(50));
     spawn_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
 
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
     loop {
         tokio::select! {
             _ = spawn_interval.tick() => {
@@ -346,7 +336,7 @@ async fn run_siege(list_path: &str) -> Result<(), Box<dyn std::error::Error>> {
                             if let Some(idx) = registry.url_to_idx.get(e.as_str()) {
                                 if let Some(l) = registry.ledger.lock().unwrap().as_ref() {
                                     if let Some(entry) = l.get_entry(*idx) {
-                                        if entry.penalty_until > now { return false; }
+                                        if !entry.health().is_available(now) { return false; }
                                     }
                                 }
                             }
@@ -368,15 +358,20 @@ async fn run_siege(list_path: &str) -> Result<(), Box<dyn std::error::Error>> {
                     let rate = msg_count as f64 / dur;
                     let u_rate = unique_count as f64 / dur;
                     let mbps = (total_bytes as f64 * 8.0) / (dur * 1024.0 * 1024.0);
+                    let archived = archive_stats.archived.load(Ordering::Relaxed);
+                    let a_rate = (archived - last_archived) as f64 / dur;
+                    last_archived = archived;
                     
                     // CLEAN MONITOR: Using \r to overwrite line for a "dashboard" feel
                     print!(
-                        "\r[SIEGE] Active: {:>5} | OK={:<5} ERR={:<5} | {:>4.1}k msg/s (U:{:>4.1}k) | {:>5.1} Mbps | Seq: {:<8}", 
+                        "\r[SIEGE] Active: {:>5} | OK={:<5} ERR={:<5} | {:>4.1}k msg/s (U:{:>4.1}k A:{:>4.1}k) | Pending: {:<6} | {:>5.1} Mbps | Seq: {:<8}", 
                         registry_agg.active_workers.len(),
                         success_count,
                         fail_count,
                         rate / 1000.0,
                         u_rate / 1000.0,
+                        a_rate / 1000.0,
+                        archive_stats.pending.load(Ordering::Relaxed),
                         mbps,
                         global_seq
                     );
@@ -389,8 +384,26 @@ async fn run_siege(list_path: &str) -> Result<(), Box<dyn std::error::Error>> {
                     last_report = Instant::now();
                     
                     if let Some(l) = registry.ledger.lock().unwrap().as_mut() {
+                        for url in registry.active_workers.iter() {
+                            let count = host_counts.get(url.key()).copied().unwrap_or(0);
+                            if let Some(mut entry) = registry.url_to_idx.get(url.key()).and_then(|idx| l.get_entry_mut(*idx)) {
+                                let mut health = entry.health();
+                                health.observe_rate(count, Duration::from_secs_f64(dur));
+                                entry.set_health(&health);
+                            }
+                        }
                         let _ = l.flush();
                     }
+                    host_counts.clear();
+                }
+                if last_cursor_save.elapsed() >= CURSOR_SAVE_INTERVAL {
+                    if let Err(e) = archive_stats.save_cursors(&opts.cursors) {
+                        warn!("Failed to save cursors to {}: {}", opts.cursors, e);
+                    }
+                    if let Err(e) = archiver.save_bloom(&opts.bloom) {
+                        warn!("Failed to save bloom filter to {}: {}", opts.bloom, e);
+                    }
+                    last_cursor_save = Instant::now();
                 }
             }
             Some(msg) = rx.recv() => {
@@ -399,53 +412,35 @@ async fn run_siege(list_path: &str) -> Result<(), Box<dyn std::error::Error>> {
                         success_count += 1;
                         if let Some(idx) = registry.url_to_idx.get(&url) {
                             if let Some(l) = registry.ledger.lock().unwrap().as_mut() {
-                                if let Some(entry) = l.get_entry_mut(*idx) {
-                                    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
-                                    entry.last_success = now;
-                                    entry.last_attempt = now;
-                                    
-                                    if entry.fail_count > 0 {
-                                        entry.fail_count = 0;
-                                        entry.penalty_until = 0;
-                                    }
+                                if let Some(mut entry) = l.get_entry_mut(*idx) {
+                                    let mut health = entry.health();
+                                    policy.on_success(&mut health, std::time::SystemTime::now());
+                                    entry.set_health(&health);
                                 }
                             }
                         }
                     },
-                    WorkerResponse::Success(_url_origin, hash, data) => {
+                    WorkerResponse::Success(url_origin, hash, data) => {
                         msg_count += 1;
+                        *host_counts.entry(Arc::clone(&url_origin)).or_default() += 1;
                         total_bytes += data.len() as u64;
 
-                        // POWERHOUSE: Bloom Filter First Defense
-                        let mut bloom = registry.bloom.lock().unwrap();
-                        if !bloom.contains(&hash) {
-                            bloom.insert(&hash);
-                            
-                            // Secondary HashSet for 100% collision safety
-                            if !hash_set.contains(&hash) {
-                                unique_count += 1;
-                                hash_set.insert(hash);
-                                seen_hashes.push_back(hash);
-                                global_seq += 1;
-
-                                if seen_hashes.len() > 500_000 {
-                                    if let Some(old) = seen_hashes.pop_front() {
-                                        hash_set.remove(&old);
-                                    }
-                                }
-                            }
+                        // POWERHOUSE: Bloom Filter First Defense, HashSet for 100% collision safety
+                        if archiver.offer(&url_origin, &hash, &data) {
+                            unique_count += 1;
+                            global_seq += 1;
                         }
                     },
-                    WorkerResponse::Failure(url) => {
+                    WorkerResponse::Failure(url, kind) => {
                         fail_count += 1;
                         if let Some(idx) = registry.url_to_idx.get(&url) {
                             if let Some(l) = registry.ledger.lock().unwrap().as_mut() {
-                                if let Some(entry) = l.get_entry_mut(*idx) {
-                                    entry.fail_count += 1;
-                                    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
-                                    entry.last_attempt = now;
-                                    let penalty_secs = (30 * 2u64.pow(entry.fail_count.min(7))).min(3600);
-                                    entry.penalty_until = now + penalty_secs;
+                                if let Some(mut entry) = l.get_entry_mut(*idx) {
+                                    let mut health = entry.health();
+                                    if policy.on_failure(&mut health, kind, std::time::SystemTime::now()).is_none() {
+                                        warn!("Blacklisting {} after {:?}", url, kind);
+                                    }
+                                    entry.set_health(&health);
                                 }
                             }
                         }
@@ -458,8 +453,26 @@ async fn run_siege(list_path: &str) -> Result<(), Box<dyn std::error::Error>> {
                     error!("Worker task panicked: {:?}", e);
                 }
             }
+            _ = &mut ctrl_c => break,
         }
     }
+
+    println!();
+    info!("Shutting down: draining {} queued frames", archive_stats.pending.load(Ordering::Relaxed));
+    join_set.abort_all();
+    if let Err(e) = archiver.save_bloom(&opts.bloom) {
+        warn!("Failed to save bloom filter to {}: {}", opts.bloom, e);
+    }
+    tokio::task::spawn_blocking(move || archiver.finish()).await?;
+    // Cursors only after the flush, so none points past what's on disk
+    let saved = archive_stats.save_cursors(&opts.cursors)?;
+    info!(
+        "Archived {} frames ({} commits failed verification), saved {} cursors",
+        archive_stats.archived.load(Ordering::Relaxed),
+        archive_stats.unverified.load(Ordering::Relaxed),
+        saved
+    );
+    Ok(())
 }
 
 
@@ -477,14 +490,15 @@ fn spawn_worker(
     
     join_set.spawn(async move {
         loop {
-            match connect_to_pds(Arc::clone(&url), &worker_tx).await {
+            let cursor = reg.archive_stats.cursors.get(url.as_str()).map(|c| *c);
+            match connect_to_pds(Arc::clone(&url), cursor, &worker_tx).await {
                 Ok(_) => {
                     let _ = worker_tx.send(WorkerResponse::Closed).await;
                     reg.active_workers.remove(url.as_ref());
                     break;
                 }
-                Err(_e) => {
-                    let _ = worker_tx.send(WorkerResponse::Failure(url.to_string())).await;
+                Err(kind) => {
+                    let _ = worker_tx.send(WorkerResponse::Failure(url.to_string(), kind)).await;
                     reg.active_workers.remove(url.as_ref());
                     break; // Exit worker on failure to obey central penalty logic
                 }
@@ -494,9 +508,13 @@ fn spawn_worker(
     });
 }
 
-async fn connect_to_pds(url_arc: Arc<String>, tx: &mpsc::Sender<WorkerResponse>) -> Result<(), String> {
-    let url = Url::parse(&url_arc).map_err(|e| e.to_string())?;
-    let (ws_stream, _) = connect_async(url).await.map_err(|e| e.to_string())?;
+/// Resumes after `cursor`, the last sequence archived from this endpoint, when there is one.
+async fn connect_to_pds(url_arc: Arc<String>, cursor: Option<u64>, tx: &mpsc::Sender<WorkerResponse>) -> Result<(), FailureKind> {
+    let mut url = Url::parse(&url_arc).map_err(|_| FailureKind::BadUrl)?;
+    if let Some(cursor) = cursor {
+        url.query_pairs_mut().append_pair("cursor", &cursor.to_string());
+    }
+    let (ws_stream, _) = connect_async(url).await.map_err(|e| FailureKind::from_connect_error(&e))?;
     
     // Notify aggregator we connected successfully (Recovery point)
     let _ = tx.send(WorkerResponse::Connected(url_arc.to_string())).await;
@@ -513,9 +531,7 @@ async fn connect_to_pds(url_arc: Arc<String>, tx: &mpsc::Sender<WorkerResponse>)
                 }
             }
             Ok(Message::Close(_)) => break,
-            Err(e) => {
-                return Err(format!("Stream error on {}: {}", url_arc, e));
-            }
+            Err(_) => return Err(FailureKind::Dropped),
             _ => {}
         }
     }
@@ -557,7 +573,7 @@ async fn run_migration(txt_path: &str) -> Result<(), Box<dyn std::error::Error>>
     }
 
     ledger.flush()?;
-    info!("Migration complete. Added {} nodes. Total in ledger: {}.", added, ledger.entry_count());
+    info!("Migration complete. Added {} nodes. Total in ledger: {}.", added, ledger.valid_entry_count());
     info!("Source of truth is now: {}", bin_path);
     Ok(())
 }
@@ -568,16 +584,15 @@ async fn run_inspection(bin_path: &str) -> Result<(), Box<dyn std::error::Error>
     
     let mut active = 0;
     let mut penalized = 0;
+    let mut blacklisted = 0;
     let mut total_fails = 0u64;
     let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs();
 
     println!("--- Sovereign Ledger Inspection: {} ---", bin_path);
-    println!("Total Entries: {}", count);
+    println!("Total Entries: {} ({} slots)", ledger.valid_entry_count(), count);
     
     for i in 0..count {
         if let Some(entry) = ledger.get_entry(i) {
-            if entry.url[0] == 0 { continue; }
-            
             total_fails += entry.fail_count as u64;
             if entry.last_success > 0 {
                 active += 1;
@@ -585,22 +600,26 @@ async fn run_inspection(bin_path: &str) -> Result<(), Box<dyn std::error::Error>
             if entry.penalty_until > now {
                 penalized += 1;
             }
+            if entry.health().blacklisted {
+                blacklisted += 1;
+            }
         }
     }
 
     println!("Nodes with some success: {}", active);
     println!("Nodes currently penalized: {}", penalized);
+    println!("Nodes blacklisted: {}", blacklisted);
     println!("Total failures across mesh: {}", total_fails);
     
     if count > 0 {
         println!("\nSample Entries:");
         for i in 0..count.min(10) {
             if let Some(entry) = ledger.get_entry(i) {
-                if entry.url[0] == 0 { continue; }
                 let url = entry.get_url();
                 let fails = entry.fail_count;
-                let status = if entry.penalty_until > now { "Penalized" } else if entry.last_success > 0 { "Healthy" } else { "Fresh" };
-                println!("  - {}: Fails={}, Status={}", url, fails, status);
+                let health = entry.health();
+                let status = if health.blacklisted { "Blacklisted" } else if entry.penalty_until > now { "Penalized" } else if entry.last_success > 0 { "Healthy" } else { "Fresh" };
+                println!("  - {}: Fails={}, Status={}, Rate={:.1} msg/s", url, fails, status, health.msg_rate);
             }
         }
     }
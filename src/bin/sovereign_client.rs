@@ -7,6 +7,9 @@ use futures::StreamExt;
 use clap::Parser;
 use zstd::bulk::Decompressor;
 use serde_json::Value;
+use url::Url;
+use did_mmap_cache::archive::ClusterReader;
+use did_mmap_cache::net::cursor_url;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
@@ -24,13 +27,7 @@ struct Args {
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
     
-    let mut url = args.url;
-    if !url.ends_with('/') && args.cursor.is_some() {
-        url.push('/');
-    }
-    if let Some(c) = args.cursor {
-        url.push_str(&format!("?cursor={}", c));
-    }
+    let url = cursor_url(&Url::parse(&args.url)?, args.cursor);
 
     println!("[Client] Connecting to {}...", url);
     let (ws_stream, _) = connect_async(url).await?;
@@ -67,28 +64,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 // Decompress the entire cluster burst
                 match decompressor.decompress_to_buffer(&compressed_cluster, &mut output_buffer) {
                     Ok(size) => {
-                        let cluster_raw = &output_buffer[..size];
-                        if size < 2 { continue; }
-
-                        // Parse Sovereign Cluster Header
-                        let num_records = u16::from_le_bytes(cluster_raw[0..2].try_into().unwrap());
-                        let mut offset = 2 + (num_records as usize * 12);
-                        
-                        for i in 0..num_records as usize {
-                            let head_ptr = 2 + i * 12;
-                            let seq = u64::from_le_bytes(cluster_raw[head_ptr..head_ptr + 8].try_into().unwrap());
-                            let len = u32::from_le_bytes(cluster_raw[head_ptr + 8..head_ptr + 12].try_into().unwrap()) as usize;
-                            
-                            if offset + len <= size {
-                                let record = &cluster_raw[offset..offset + len];
-                                println!("[Seq {}] Recv {} bytes (cluster burst)", seq, len);
-                                
-                                // Peek at record
-                                let peek_len = len.min(32);
-                                println!("  Peek: {}", hex::encode(&record[..peek_len]));
-                                
-                                offset += len;
+                        let cluster = match ClusterReader::new(&output_buffer[..size]) {
+                            Ok(cluster) => cluster,
+                            Err(e) => {
+                                eprintln!("[Error] {}", e);
+                                continue;
                             }
+                        };
+
+                        for entry in cluster.iter() {
+                            println!("[Seq {}] Recv {} bytes (cluster burst)", entry.seq, entry.data.len());
+
+                            // Peek at record
+                            let peek_len = entry.data.len().min(32);
+                            println!("  Peek: {}", hex::encode(&entry.data[..peek_len]));
                         }
                     }
                     Err(e) => {
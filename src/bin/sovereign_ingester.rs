@@ -7,22 +7,32 @@ use std::io::Write;
 use std::sync::{Arc, RwLock};
 use std::sync::atomic::{AtomicU64, AtomicBool, Ordering};
 use std::thread;
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::Result;
 use clap::Parser;
 use crossbeam_channel::{unbounded, Sender};
-use url::Url;
-use tungstenite::{connect, Message};
 use serde::{Deserialize, Serialize};
 
 use did_mmap_cache::mmap_did_cache::MmapDidCache;
-use did_mmap_cache::archive::MultiShardArchive;
-use did_mmap_cache::monitor::{SovereignMonitor, ErrorType};
-use did_mmap_cache::parser::core::parse_input;
-use did_mmap_cache::resolver::{resolve_did, resolve_handle};
-use did_mmap_cache::verify::verify_commit;
-use did_mmap_cache::mst::car::CarStore;
+use did_mmap_cache::archive::{CompressionConfig, GapLog, GapRecord, MultiShardArchive, WalSync};
+use did_mmap_cache::mst::builder::MerkleAlgorithm;
+use did_mmap_cache::monitor::{ArrivalOutcome, ArrivalTracker, DropEvidenceStore, SovereignMonitor, ErrorType, SeqStep, ARRIVAL_TICK};
+use did_mmap_cache::parser::cid_utils::normalize_cid_bytes;
+use did_mmap_cache::parser::core::{parse_full, parse_input, peek_frame, CommitEnvelope, OUTDATED_CURSOR};
+use did_mmap_cache::parser::records::decode_record_from_car;
+use did_mmap_cache::resolver::{resolve_handle_verified_with, ResolverCache, ResolverConfig};
+use did_mmap_cache::verify::{verify_commit_detailed_with, VerifyOptions, VerifyOutcome};
+use did_mmap_cache::filter::{DidAllowlist, FilterSpec, MixedCommitPolicy};
+use did_mmap_cache::ingest::{ConnectorConfig, DisconnectReason, Endpoint, EndpointKind, FirehoseConnector, FirehoseEvent, Flow, PipelineShutdown};
+use did_mmap_cache::net::{BackoffPolicy, FailureKind, HostHealth};
+use did_mmap_cache::plc::{ExportStream, RotationWatcher};
+use did_mmap_cache::resolver::plc_directory_base;
+
+/// How often a worker folds its message count into the host's rolling rate.
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+/// A CID the mesh saw that the relay hasn't delivered within this long counts as dropped.
+const RELAY_WINDOW: Duration = Duration::from_secs(3);
 
 #[derive(Parser, Debug)]
 #[command(author, version, about)]
@@ -51,6 +61,19 @@ struct Args {
     #[arg(long)]
     dry_run: bool,
 
+    /// Write sovereign_errors.log as one JSON object per line (ts, event, host, did, detail)
+    #[arg(long)]
+    log_json: bool,
+
+    /// Run this command as `<cmd> <host> <saved_cursor> <new_start_seq>` for every cursor gap
+    /// recorded in gaps.jsonl, e.g. to queue a backfill
+    #[arg(long)]
+    gap_hook: Option<String>,
+
+    /// On shutdown, write a JSON verification report (counters + sample of failing DIDs) here
+    #[arg(long)]
+    report: Option<String>,
+
     /// Live mode: Ignore saved pds_cursors.json and start from current head
     #[arg(long)]
     live: bool,
@@ -62,6 +85,128 @@ struct Args {
     /// Relay URL to compare against (can be specified multiple times)
     #[arg(long)]
     relay: Vec<String>,
+
+    /// Persist a shard's buffered messages once the oldest has waited this many seconds (0 = only when full)
+    #[arg(long, default_value_t = 30)]
+    flush_secs: u64,
+
+    /// Number of archive shards (must match an existing archive; see reshard_archive)
+    #[arg(long, default_value_t = 16)]
+    shards: usize,
+
+    /// Messages per segment in each shard [default: 500 with --live, 50000 otherwise]
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    segment_size: Option<u64>,
+
+    /// Open an existing archive even if --shards differs from the count it was created with,
+    /// or the startup check finds overlapping segments
+    #[arg(long)]
+    force: bool,
+
+    /// On shutdown, give already-queued frames this many seconds to be verified and archived
+    #[arg(long, default_value_t = 30)]
+    drain_secs: u64,
+
+    /// Only archive commits touching these collection prefixes; `-` excludes (e.g. "app.bsky.feed,-app.bsky.feed.like")
+    #[arg(long, alias = "collections")]
+    filter_collections: Option<String>,
+
+    /// Only archive commits from DIDs listed in this file (one per line)
+    #[arg(long)]
+    filter_dids_file: Option<String>,
+
+    /// Archive only this fraction of commits that pass the other filters
+    #[arg(long, default_value_t = 1.0)]
+    filter_sample: f64,
+
+    /// Strip ops outside --filter-collections (and their records) from archived commits
+    /// instead of archiving mixed commits whole
+    #[arg(long)]
+    filter_trim: bool,
+
+    /// Keep the full frame of every relay drop here, one file per day (see verify_evidence)
+    #[arg(long, default_value = "drop_evidence")]
+    evidence_dir: String,
+
+    /// Cap on the evidence directory; the oldest days are deleted first
+    #[arg(long, default_value_t = 1024)]
+    evidence_max_mb: u64,
+
+    /// Memory for mesh frames awaiting the relay; past it the oldest lose their drop evidence
+    #[arg(long, default_value_t = 256)]
+    ghost_budget_mb: usize,
+
+    /// Merkle hash for new segments' roots: blake3 or sha256 (existing segments keep theirs)
+    #[arg(long, default_value = "blake3")]
+    merkle_hash: MerkleAlgorithm,
+
+    /// Store each message's blake3 with new segments (.mhash), so archived frames can be looked up by hash
+    #[arg(long)]
+    message_hashes: bool,
+
+    /// Record each DID's newest archived commit (heads.bin) as segments are written
+    #[arg(long)]
+    track_heads: bool,
+
+    /// Count messages and bytes per collection with new segments (.stats), for inspect_archive --stats
+    #[arg(long)]
+    collection_stats: bool,
+
+    /// zstd level for new segments
+    #[arg(long, default_value_t = 3)]
+    zstd_level: i32,
+
+    /// Compress new segments with zstd long-distance matching
+    #[arg(long)]
+    zstd_long: bool,
+
+    /// Journal each shard's unpersisted messages to wal.log, so a crash doesn't lose them
+    #[arg(long)]
+    wal: bool,
+
+    /// Skip frames already archived in the last N segments of their shard (0 = off; implies --message-hashes)
+    #[arg(long, default_value_t = 0)]
+    dedup_segments: usize,
+
+    /// Read the DID cache in ahead of the first lookups and turn off readahead around faults
+    #[arg(long)]
+    warm_cache: bool,
+
+    /// With --warm-cache, also pin the first N cache slots in RAM (bounded by RLIMIT_MEMLOCK)
+    #[arg(long, default_value_t = 0)]
+    mlock_slots: usize,
+
+    /// Memory budget for decompressed archive clusters, shared by all shards (MB)
+    #[arg(long, default_value_t = 1024)]
+    cluster_cache_mb: usize,
+
+    /// Accept high-S signatures (after normalizing them) and count them as High-S on the dashboard
+    #[arg(long)]
+    normalize_high_s: bool,
+
+    /// Accept high-S signatures without counting them
+    #[arg(long)]
+    allow_high_s: bool,
+
+    /// PLC directory to resolve did:plc through (default $PLC_DIRECTORY_BASE, else https://plc.directory)
+    #[arg(long)]
+    plc_directory: Option<String>,
+
+    /// Gateway to fetch did:web documents from as <gateway>/<did>, instead of each DID's host
+    #[arg(long)]
+    did_web_gateway: Option<String>,
+
+    /// DNS-over-HTTPS JSON endpoint for handle TXT lookups (e.g. https://cloudflare-dns.com/dns-query)
+    #[arg(long)]
+    doh: Option<String>,
+
+    /// Follow the PLC directory's /export in the background and apply key rotations to the cache as they happen
+    #[arg(long)]
+    watch_plc: bool,
+
+    /// Where --watch-plc keeps its export cursor; without one it starts from now
+    #[arg(long, default_value = "plc_watch.cursor")]
+    plc_cursor: String,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -69,6 +214,9 @@ struct PdsReport {
     url: String,
     hostname: String,
     grade: String,
+    /// Set by `mesh_crawler --deep-probe` when the firehose delivered a frame
+    #[serde(default)]
+    ws_ok: bool,
 }
 
 struct SharedState {
@@ -78,11 +226,51 @@ struct SharedState {
     cache: Arc<RwLock<MmapDidCache>>,
     running: Arc<AtomicBool>,
     dry_run: bool,
+    filter: FilterSpec,
     pds_cursors: Arc<DashMap<String, u64>>,
     blocked_pds: Arc<DashMap<String, bool>>,
-    arrival_log: Arc<DashMap<Vec<u8>, (Instant, bool, bool)>>, // CID -> (Time, IsRelay, WasMatched)
-    ghost_content: Arc<DashMap<Vec<u8>, (String, Vec<u8>)>>, // CID -> (SourceHost, Raw Message)
+    arrivals: ArrivalTracker,
     relay_hosts: Arc<DashMap<String, bool>>,
+    /// Reconnect state per host, saved to pds_health.json on shutdown
+    host_health: Arc<DashMap<String, HostHealth>>,
+    backoff: BackoffPolicy,
+    /// Skips re-resolving DIDs that just failed
+    resolver: ResolverCache,
+    resolver_config: ResolverConfig,
+    verify_opts: VerifyOptions,
+    errors: ErrorLog,
+    /// Seqs hosts pruned before we read them, kept in the archive's gaps.jsonl
+    gaps: GapLog,
+}
+
+const ERROR_LOG: &str = "sovereign_errors.log";
+/// Counters and leaderboard carried across restarts
+const MONITOR_SNAPSHOT: &str = "monitor_snapshot.json";
+/// Each PDS's last seq and gap totals, kept with `pds_cursors.json`
+const PDS_CONTINUITY: &str = "pds_continuity.json";
+
+/// Appends to `sovereign_errors.log`, as `[time] event host: detail` lines or, with
+/// `--log-json`, JSON objects. Every error site goes through here so the two stay alike.
+struct ErrorLog {
+    json: bool,
+}
+
+impl ErrorLog {
+    fn record(&self, event: &str, host: &str, did: Option<&str>, detail: &str) {
+        let now = chrono::Local::now();
+        let line = if self.json {
+            serde_json::json!({ "ts": now.to_rfc3339(), "event": event, "host": host, "did": did, "detail": detail }).to_string()
+        } else {
+            match did {
+                Some(did) => format!("[{}] {} {} for DID {}: {}", now, event, host, did, detail),
+                None => format!("[{}] {} {}: {}", now, event, host, detail),
+            }
+        };
+        // One write per line, so lines from concurrent workers don't interleave
+        if let Ok(mut file) = fs::OpenOptions::new().create(true).append(true).open(ERROR_LOG) {
+            let _ = file.write_all(format!("{}\n", line).as_bytes());
+        }
+    }
 }
 
 use dashmap::DashMap;
@@ -103,6 +291,13 @@ fn main() -> Result<()> {
         }
     }
 
+    let host_health = Arc::new(DashMap::new());
+    if let Ok(health_data) = fs::read_to_string("pds_health.json") {
+        if let Ok(map) = serde_json::from_str::<HashMap<String, HostHealth>>(&health_data) {
+            for (k, v) in map { host_health.insert(k, v); }
+        }
+    }
+
     let blocked_pds = Arc::new(DashMap::new());
     if let Ok(block_data) = fs::read_to_string("pds_blocked.json") {
         if let Ok(list) = serde_json::from_str::<Vec<String>>(&block_data) {
@@ -111,9 +306,10 @@ fn main() -> Result<()> {
         }
     }
     
-    let targets: Vec<PdsReport> = all_nodes.into_iter()
+    let mut targets: Vec<PdsReport> = all_nodes.into_iter()
         .filter(|n| {
             if blocked_pds.contains_key(&n.hostname) { return false; }
+            if host_health.get(&n.hostname).is_some_and(|h| h.blacklisted) { return false; }
             
             let grade = n.grade.to_uppercase();
             let min_grade = args.min_grade.to_uppercase();
@@ -144,23 +340,88 @@ fn main() -> Result<()> {
             
             grade_val <= min_val
         })
-        .take(args.max_conns)
         .collect();
+    // Hosts whose firehose was seen streaming go first when --max-conns cuts the list
+    targets.sort_by_key(|n| !n.ws_ok);
+    targets.truncate(args.max_conns);
 
     println!("[Sovereign] Initializing with {} PDS targets...", targets.len());
 
     // 2. Initialize Infrastructure
-    let cache = Arc::new(RwLock::new(MmapDidCache::open_mut(&args.cache)?));
+    let cache = MmapDidCache::try_open_mut(&args.cache)?;
+    if args.warm_cache {
+        if let Err(e) = cache.advise_random().and_then(|_| cache.prefetch()) {
+            eprintln!("[Warn] Cache warm-up failed: {}", e);
+        }
+        if args.mlock_slots > 0 {
+            if let Err(e) = cache.lock_slots(0..args.mlock_slots) {
+                eprintln!("[Warn] Could not lock {} cache slots: {}", args.mlock_slots, e);
+            }
+        }
+    }
+    let cache = Arc::new(RwLock::new(cache));
     let dict = fs::read("atproto_firehose.dict").ok();
-    // Balanced configuration: 16 shards for faster testing/visibility.
     // Segment size tuned to 500 for live head to see files quickly.
-    let segment_size = if args.live { 500 } else { 50_000 };
-    let archive = Arc::new(MultiShardArchive::new(&args.archive, 16, segment_size, dict)?);
+    let segment_size = args.segment_size.unwrap_or(if args.live { 500 } else { 50_000 });
+    let compression = CompressionConfig { level: args.zstd_level, long_distance: args.zstd_long, window_log: None };
+    let archive = Arc::new(MultiShardArchive::new_with_compression(&args.archive, args.shards, segment_size, dict, compression, args.force)?);
+    let mut consistency = archive.consistency_report().clone();
+    // pds_cursors count each PDS's own seqs, so only saved cursors over an empty archive are caught
+    let cursors: Vec<(String, u64)> = pds_cursors.iter().map(|e| (e.key().clone(), *e.value())).collect();
+    consistency.check_cursors(cursors.iter().map(|(host, c)| (host.as_str(), *c)), u64::MAX);
+    println!("[Sovereign] Archive check: {}", consistency);
+    if consistency.has_overlaps() && !args.force {
+        anyhow::bail!("archive {} has overlapping segments; restore it or pass --force to write anyway", args.archive);
+    }
+    archive.set_hash_algorithm(args.merkle_hash);
+    archive.set_message_hashes(args.message_hashes);
+    archive.set_track_heads(args.track_heads);
+    archive.set_collection_stats(args.collection_stats);
+    if args.dedup_segments > 0 {
+        archive.enable_dedup(args.dedup_segments)?;
+    }
+    if archive.wal_replayed() > 0 {
+        println!("[Sovereign] Recovered {} unpersisted messages from the shard journals.", archive.wal_replayed());
+    }
+    if args.wal {
+        archive.enable_wal(WalSync::default())?;
+    }
+    if args.flush_secs > 0 {
+        archive.start_idle_flush(Duration::from_secs(args.flush_secs));
+    }
+    archive.cluster_cache().set_budget(args.cluster_cache_mb * 1024 * 1024);
+    let mut gaps = GapLog::open(&args.archive)?;
+    if !gaps.is_empty() {
+        println!("[Sovereign] {} cursor gaps on record, {} seqs never received.", gaps.len(), gaps.total_gap_span());
+    }
+    if let Some(cmd) = args.gap_hook.clone() {
+        gaps = gaps.with_hook(Box::new(move |gap: &GapRecord| {
+            let spawned = std::process::Command::new(&cmd)
+                .arg(&gap.host)
+                .arg(gap.saved_cursor.to_string())
+                .arg(gap.new_start_seq.to_string())
+                .spawn();
+            if let Err(e) = spawned {
+                eprintln!("[Warn] Gap hook {} failed to start: {}", cmd, e);
+            }
+        }));
+    }
     let monitor = Arc::new(SovereignMonitor::new());
+    monitor.attach_cluster_cache(Arc::clone(archive.cluster_cache()));
+    match monitor.load_snapshot(MONITOR_SNAPSHOT) {
+        Ok(snapshot) => println!("[Sovereign] Resumed monitor totals: {} frames, {} DIDs on the leaderboard.", snapshot.counters.get("total").copied().unwrap_or(0), snapshot.leaderboard.len()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => eprintln!("[Warn] Ignoring {}: {}", MONITOR_SNAPSHOT, e),
+    }
+    match monitor.continuity.load(PDS_CONTINUITY) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => eprintln!("[Warn] Ignoring {}: {}", PDS_CONTINUITY, e),
+        // Without the saved cursors the hosts start from their live heads, which isn't a gap
+        _ if args.live => monitor.continuity.rebase(),
+        _ => {}
+    }
     let global_seq = AtomicU64::new(0);
     let running = Arc::new(AtomicBool::new(true));
-    let arrival_log = Arc::new(DashMap::new());
-    let ghost_content = Arc::new(DashMap::new());
+    let arrivals = ArrivalTracker::with_budget(RELAY_WINDOW, args.ghost_budget_mb * 1024 * 1024);
     let relay_hosts = Arc::new(DashMap::new());
     for r in &args.relay {
         if let Ok(u) = url::Url::parse(r) {
@@ -171,6 +432,22 @@ fn main() -> Result<()> {
         }
     }
 
+    let policy = if args.filter_trim { MixedCommitPolicy::Trim } else { MixedCommitPolicy::Whole };
+    let mut filter = FilterSpec::default().with_sample_rate(args.filter_sample).with_policy(policy);
+    if let Some(collections) = &args.filter_collections {
+        filter = filter.with_collections(collections);
+    }
+    if let Some(path) = &args.filter_dids_file {
+        let dids = DidAllowlist::from_file(path)?;
+        println!("[Sovereign] Archiving only {} allowlisted DIDs.", dids.len());
+        filter = filter.with_dids(dids);
+    }
+
+    let resolver_config = ResolverConfig {
+        plc_directory: args.plc_directory.clone(),
+        did_web_gateway: args.did_web_gateway.clone(),
+        doh_endpoint: args.doh.clone(),
+    };
     let state = Arc::new(SharedState {
         monitor,
         global_seq,
@@ -178,21 +455,50 @@ fn main() -> Result<()> {
         cache,
         running: Arc::clone(&running),
         dry_run: args.dry_run,
+        filter,
         pds_cursors: Arc::clone(&pds_cursors),
         blocked_pds: Arc::clone(&blocked_pds),
-        arrival_log,
-        ghost_content,
+        arrivals,
         relay_hosts,
+        host_health,
+        backoff: BackoffPolicy::default(),
+        resolver: ResolverCache::default().with_config(resolver_config.clone()),
+        resolver_config,
+        verify_opts: VerifyOptions { allow_high_s: args.allow_high_s, normalize: args.normalize_high_s },
+        errors: ErrorLog { json: args.log_json },
+        gaps,
     });
 
     // Handle Shutdown
     let running_ctrlc = Arc::clone(&running);
-    ctrlc::set_handler(move || {
+    let request_stop = move || {
         println!("\n[Shutdown] Stop signal received. Finishing loops...");
         running_ctrlc.store(false, Ordering::SeqCst);
-    })?;
+    };
+    ctrlc::set_handler(request_stop.clone())?;
+    // 'q' in the interactive dashboard takes the same path
+    state.monitor.start_interactive(request_stop);
+
+    let plc_watcher = if args.watch_plc {
+        let directory = args.plc_directory.clone().unwrap_or_else(plc_directory_base);
+        let now = chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true);
+        let stream = ExportStream::new(&directory, &now).with_cursor_file(&args.plc_cursor)?.follow(true);
+        println!("[Sovereign] Watching {} for key rotations after {}.", directory, stream.cursor());
+        let (watcher, rotations) = RotationWatcher::new(stream, Arc::clone(&state.cache));
+        let handle = watcher.with_running_flag(Arc::clone(&running)).spawn()?;
+        let monitor = Arc::clone(&state.monitor);
+        thread::spawn(move || {
+            for event in rotations {
+                monitor.record_rotation(event.to_string());
+            }
+        });
+        Some(handle)
+    } else {
+        None
+    };
 
     let (tx, rx) = unbounded::<(String, Vec<u8>)>();
+    let shutdown = Arc::new(PipelineShutdown::new(Arc::clone(&running), Duration::from_secs(args.drain_secs)));
 
     // 3. Thread Spawner Helper
     // We limit the stack size to 256KB per thread (vs 2-8MB default)
@@ -209,7 +515,6 @@ fn main() -> Result<()> {
     let state_h = Arc::clone(&state);
     let running_h = Arc::clone(&running);
     spawn_optimized("handle-resolver".to_string(), Box::new(move || {
-        use did_mmap_cache::resolver::resolve_handle;
         while running_h.load(Ordering::SeqCst) {
             let mut to_resolve = Vec::new();
             {
@@ -224,8 +529,9 @@ fn main() -> Result<()> {
             }
 
             for did in to_resolve {
-                if let Some(handle) = resolve_handle(&did) {
-                    state_h.monitor.handle_cache.insert(did, handle);
+                // Unverified or spoofed handles keep a suffix so the TUI never shows them bare
+                if let Some(handle) = resolve_handle_verified_with(&did, &state_h.resolver_config) {
+                    state_h.monitor.handle_cache.insert(did, handle.display_name());
                 } else {
                     state_h.monitor.handle_cache.insert(did, "unresolved".to_string());
                 }
@@ -239,24 +545,26 @@ fn main() -> Result<()> {
     // Start these BEFORE connections so they are ready to catch messages immediately
     // Increased to 4x CPUs to handle threads blocked on DID resolution network I/O.
     let num_verifiers = num_cpus::get() * 4;
+    let mut verifiers = Vec::with_capacity(num_verifiers);
     for i in 0..num_verifiers {
         let rx = rx.clone();
         let state = Arc::clone(&state);
-        spawn_optimized(format!("verifier-{}", i), Box::new(move || {
-            while let Ok((pds_host, msg)) = rx.recv() {
+        let shutdown = Arc::clone(&shutdown);
+        verifiers.push(spawn_optimized(format!("verifier-{}", i), Box::new(move || {
+            while let Some((pds_host, msg)) = shutdown.recv(&rx) {
                 process_sovereign_message(msg, pds_host, &state);
             }
-        }));
+        })));
     }
 
     // 5. Start Monitor Dashboard in a background thread
     let state_monitor = Arc::clone(&state);
     let rx_monitor = rx.clone();
-    let running_monitor = Arc::clone(&running);
     spawn_optimized("monitor-ui".to_string(), Box::new(move || {
         let mut last_total = 0;
         let mut last_time = Instant::now();
-        while running_monitor.load(Ordering::SeqCst) {
+        // Keeps drawing through the drain phase of shutdown
+        while !state_monitor.monitor.stopped.load(Ordering::Relaxed) {
             thread::sleep(Duration::from_millis(500));
             let total = state_monitor.monitor.total.load(Ordering::Relaxed);
             let now = Instant::now();
@@ -273,85 +581,87 @@ fn main() -> Result<()> {
     // Cleanup & Ghost Detection Thread
     let state_ghosts = Arc::clone(&state);
     let running_ghosts = Arc::clone(&running);
+    let mut evidence = DropEvidenceStore::open(&args.evidence_dir, args.evidence_max_mb * 1024 * 1024)?;
     spawn_optimized("ghost-detector".to_string(), Box::new(move || {
         println!("[Sovereign] Ghost Detection Thread started.");
+        let mut evidence_full_logged = false;
         while running_ghosts.load(Ordering::SeqCst) {
-            state_ghosts.monitor.ghost_hunter_loops.fetch_add(1, Ordering::Relaxed);
-            thread::sleep(Duration::from_millis(500));
-            let now = Instant::now();
-            let mut drops_count = 0;
-            let mut to_remove = Vec::new();
-            
-            let log_len = state_ghosts.arrival_log.len();
-            if log_len > 0 && state_ghosts.monitor.ghost_hunter_loops.load(Ordering::Relaxed) % 10 == 0 {
+            let loops = state_ghosts.monitor.ghost_hunter_loops.fetch_add(1, Ordering::Relaxed) + 1;
+            thread::sleep(ARRIVAL_TICK);
+            let ghosts = state_ghosts.arrivals.tick();
+
+            if loops.is_multiple_of(10) && !state_ghosts.arrivals.is_empty() {
                 if let Ok(mut f) = fs::OpenOptions::new().create(true).append(true).open("ghost_hunter.log") {
-                    let _ = writeln!(f, "Scanning {} entries...", log_len);
+                    let _ = writeln!(f, "Tracking {} entries, {} frame bytes held, {} frames evicted",
+                        state_ghosts.arrivals.len(), state_ghosts.arrivals.frame_bytes(), state_ghosts.arrivals.evicted_frames());
                 }
             }
 
-            for entry in state_ghosts.arrival_log.iter() {
-                let (time, is_relay, was_matched) = *entry.value();
-                let age = now.duration_since(time);
-
-                if !was_matched && !is_relay && age > Duration::from_secs(3) {
-                    // MESH saw it, RELAY didn't in window.
-                    drops_count += 1;
-                    
-                    // Log to relay_drops.log
-                    if let Some(content_val) = state_ghosts.ghost_content.get(entry.key()) {
-                        let (source_host, msg_bytes) = content_val.value();
-                        let cid_hex = hex::encode(entry.key());
-                        let mut snippet = String::from("No block content");
-                        let mut info = String::from("?");
-                        
-                        if let Some(envelope) = parse_input(msg_bytes) {
-                            if let Some(did_bytes) = envelope.did {
-                                let did_str = std::str::from_utf8(did_bytes).unwrap_or("?");
-                                
-                                let handle = if let Some(h) = state_ghosts.monitor.handle_cache.get(did_str) {
-                                    h.value().clone()
-                                } else if let Some(h) = resolve_handle(did_str) {
-                                    state_ghosts.monitor.handle_cache.insert(did_str.to_string(), h.clone());
-                                    h
-                                } else {
-                                    did_str.to_string()
-                                };
-
-                                if let Some(blocks) = envelope.blocks {
-                                    if let Some(s) = extract_better_snippet(blocks) {
-                                        snippet = s;
-                                    }
-                                }
-                                info = format!("Handle: {} (Source: {})", handle, source_host);
-                            }
-                        }
-                        
-                        if let Ok(mut f) = fs::OpenOptions::new().create(true).append(true).open("relay_drops.log") {
-                            use std::io::Write;
-                            let _ = writeln!(f, "[DROP] CID: {} | {} | Sample: {}", cid_hex, info, snippet);
+            // MESH saw it, RELAY didn't in window.
+            let drops_count = ghosts.len() as u64;
+            for ghost in ghosts {
+                let cid_hex = hex::encode(&ghost.cid);
+                let Some(msg_bytes) = ghost.frame else {
+                    // Evicted by --ghost-budget-mb: counted, but there's nothing to show or keep
+                    state_ghosts.monitor.push_drop(format!("{} dropped {} (frame evicted)", ghost.source_host, cid_hex));
+                    continue;
+                };
+                let source_host = &ghost.source_host;
+                let mut snippet = String::from("No block content");
+                let mut info = String::from("?");
+
+                if let Ok(envelope) = parse_input(&msg_bytes) {
+                    if let Some(did_bytes) = envelope.did {
+                        let did_str = std::str::from_utf8(did_bytes).unwrap_or("?");
+
+                        let handle = if let Some(h) = state_ghosts.monitor.handle_cache.get(did_str) {
+                            h.value().clone()
+                        } else if let Some(h) = resolve_handle_verified_with(did_str, &state_ghosts.resolver_config) {
+                            let h = h.display_name();
+                            state_ghosts.monitor.handle_cache.insert(did_str.to_string(), h.clone());
+                            h
+                        } else {
+                            did_str.to_string()
+                        };
+
+                        if let Some(s) = record_snippet(&envelope) {
+                            snippet = s;
                         }
-                        
-                        // Push to Monitor TUI
-                        state_ghosts.monitor.push_drop(format!("{} dropped {}", info, cid_hex));
+                        info = format!("Handle: {} (Source: {})", handle, source_host);
                     }
+                }
+
+                if let Ok(mut f) = fs::OpenOptions::new().create(true).append(true).open("relay_drops.log") {
+                    use std::io::Write;
+                    let _ = writeln!(f, "[DROP] CID: {} | {} | Sample: {}", cid_hex, info, snippet);
+                }
 
-                    // Mark as 'matched' (handled) so we dont count again
-                    to_remove.push(entry.key().clone());
-                } else if age > Duration::from_secs(60) {
-                    // Old entries (matched or relay-first) - safe to purge from RAM
-                    to_remove.push(entry.key().clone());
+                // Keep the whole frame so the drop can be re-verified later
+                let first_seen = SystemTime::now() - ghost.first_seen.elapsed();
+                match evidence.record(&ghost.cid, source_host, &msg_bytes, first_seen, RELAY_WINDOW) {
+                    Ok(true) => evidence_full_logged = false,
+                    Ok(false) if !evidence_full_logged => {
+                        evidence_full_logged = true;
+                        if let Ok(mut f) = fs::OpenOptions::new().create(true).append(true).open("ghost_hunter.log") {
+                            let _ = writeln!(f, "Drop evidence store is at its cap; frames are not being kept");
+                        }
+                    }
+                    Ok(false) => {}
+                    Err(e) => {
+                        if let Ok(mut f) = fs::OpenOptions::new().create(true).append(true).open("ghost_hunter.log") {
+                            let _ = writeln!(f, "Failed to store drop evidence for {}: {}", cid_hex, e);
+                        }
+                    }
                 }
+
+                // Push to Monitor TUI
+                state_ghosts.monitor.push_drop(format!("{} dropped {}", info, cid_hex));
             }
 
             if drops_count > 0 {
                 state_ghosts.monitor.dropped_by_relay.fetch_add(drops_count, Ordering::Relaxed);
                 state_ghosts.monitor.healed.fetch_add(drops_count, Ordering::Relaxed);
             }
-
-            for key in to_remove {
-                state_ghosts.arrival_log.remove(&key);
-                state_ghosts.ghost_content.remove(&key);
-            }
         }
     }));
 
@@ -394,11 +704,21 @@ fn main() -> Result<()> {
         thread::sleep(Duration::from_secs(1));
     }
 
-    println!("[Shutdown] Saving final cursors and closing archive...");
-    
-    // 1. Signal Archive to flush
-    state.archive.shutdown();
-    
+    // 1. Stop connections, drain the queue, then flush the archive
+    println!("[Shutdown] Draining queued frames (up to {}s)...", args.drain_secs);
+    let drain = shutdown.run(&rx, workers, verifiers, Some(&state.archive), Some(&state.monitor));
+    state.monitor.stop_interactive();
+    println!("[Shutdown] Drained {} frames, dropped {}{}.", drain.drained, drain.dropped,
+        if drain.timed_out { " (drain timed out)" } else { "" });
+
+    if let Some(handle) = plc_watcher {
+        match handle.join() {
+            Ok(Ok(stats)) => println!("[Shutdown] PLC watcher stopped: {} operations, {} rotations.", stats.operations + stats.nullified, stats.rotations),
+            Ok(Err(e)) => eprintln!("[Shutdown] PLC watcher failed: {}", e),
+            Err(_) => eprintln!("[Shutdown] PLC watcher panicked."),
+        }
+    }
+
     // 2. Save Cursors
     let mut final_map = HashMap::new();
     for entry in state.pds_cursors.iter() {
@@ -410,8 +730,32 @@ fn main() -> Result<()> {
             Err(e) => eprintln!("[Shutdown] Failed to save cursors: {}", e),
         }
     }
+    match state.monitor.save_snapshot(MONITOR_SNAPSHOT) {
+        Ok(_) => println!("[Shutdown] Saved monitor totals."),
+        Err(e) => eprintln!("[Shutdown] Failed to save monitor totals: {}", e),
+    }
+    if let Err(e) = state.monitor.continuity.save(PDS_CONTINUITY) {
+        eprintln!("[Shutdown] Failed to save seq continuity: {}", e);
+    }
+    let health_map: HashMap<String, HostHealth> = state.host_health.iter().map(|e| (e.key().clone(), e.value().clone())).collect();
+    if let Ok(json) = serde_json::to_string_pretty(&health_map) {
+        if let Err(e) = fs::write("pds_health.json", json) {
+            eprintln!("[Shutdown] Failed to save host health: {}", e);
+        }
+    }
+
+    // 3. Verification report
+    if let Some(path) = &args.report {
+        match serde_json::to_string_pretty(&state.monitor.report()) {
+            Ok(json) => match fs::write(path, json) {
+                Ok(_) => println!("[Shutdown] Wrote verification report to {}.", path),
+                Err(e) => eprintln!("[Shutdown] Failed to write report: {}", e),
+            },
+            Err(e) => eprintln!("[Shutdown] Failed to serialize report: {}", e),
+        }
+    }
 
-    // 3. Save Blocked PDS (Blacklist)
+    // 4. Save Blocked PDS (Blacklist)
     let blocked_list: Vec<String> = state.blocked_pds.iter().map(|e| e.key().clone()).collect();
     if let Ok(json) = serde_json::to_string_pretty(&blocked_list) {
         match fs::write("pds_blocked.json", json) {
@@ -420,360 +764,296 @@ fn main() -> Result<()> {
         }
     }
 
-    // Give it a second to clean up network threads
-    thread::sleep(Duration::from_millis(500));
-    
-    println!("[Shutdown] Finalizing archive segments...");
-    state.archive.shutdown();
-
     println!("[Shutdown] Complete.");
 
     Ok(())
 }
 
 fn worker_loop(pds_url: String, state: Arc<SharedState>, tx: Sender<(String, Vec<u8>)>, start_live: bool) {
-    let hostname = match Url::parse(&pds_url) {
-        Ok(u) => {
-            let host = u.host_str().unwrap_or("unknown").trim().to_string();
-            if let Some(port) = u.port() {
-                format!("{}:{}", host, port)
-            } else {
-                host
-            }
-        }
-        Err(_) => pds_url.trim()
-            .trim_start_matches("wss://")
-            .trim_start_matches("ws://")
-            .trim_start_matches("https://")
-            .trim_start_matches("http://")
-            .split('/')
-            .next()
-            .unwrap_or("")
-            .to_string(),
-    };
-
-    if hostname.is_empty() || hostname.contains(' ') || hostname.contains('\n') || hostname.len() > 128 {
+    let Some(mut endpoint) = Endpoint::from_address(EndpointKind::Pds, &pds_url) else {
+        state.errors.record("bad_address", pds_url.trim(), None, "not a URL, host or host:port");
         return;
+    };
+    let hostname = endpoint.host();
+    if state.relay_hosts.contains_key(&hostname) {
+        endpoint.kind = EndpointKind::Relay;
     }
-    
-    let mut session_started = false;
-    while state.running.load(Ordering::SeqCst) {
-        let cursor = if start_live && !session_started { 
-            None 
-        } else { 
-            state.pds_cursors.get(&hostname).map(|e| *e.value()) 
-        };
-        
-        let mut ws_url = format!("wss://{}/xrpc/com.atproto.sync.subscribeRepos", hostname);
-        if let Some(c) = cursor {
-            ws_url.push_str(&format!("?cursor={}", c));
-        }
+    let cursor = if start_live { None } else { state.pds_cursors.get(&hostname).map(|e| *e.value()) };
+    let config = ConnectorConfig {
+        failover: false,
+        // Small PDSes can be quiet for hours; idle connections are kept alive with pings instead
+        stall_timeout: None,
+        read_timeout: Duration::from_secs(20),
+        ..ConnectorConfig::default()
+    };
 
-        match connect(&ws_url) {
-            Ok((mut socket, _)) => {
-                session_started = true;
-                // Set a read timeout so we can send Pings if the connection is idle
-                let stream = socket.get_mut();
-                let _ = match stream {
-                    tungstenite::stream::MaybeTlsStream::Plain(s) => s.set_read_timeout(Some(Duration::from_secs(20))),
-                    tungstenite::stream::MaybeTlsStream::Rustls(s) => s.get_mut().set_read_timeout(Some(Duration::from_secs(20))),
-                    _ => Ok(()),
-                };
+    // Flapping hosts back off exponentially; the first frame after a reconnect clears it
+    let policy = &state.backoff;
+    let mut health = state.host_health.get(&hostname).map(|h| h.clone()).unwrap_or_default();
+    let mut backing_off = health.failures > 0;
+    let save = |health: &HostHealth| { state.host_health.insert(hostname.clone(), health.clone()); };
 
-                state.monitor.active_conns.fetch_add(1, Ordering::Relaxed);
-                while state.running.load(Ordering::SeqCst) {
-                    match socket.read() {
-                        Ok(msg) => {
-                            if let Message::Binary(bin) = msg {
-                                if tx.send((hostname.clone(), bin)).is_err() { 
-                                    state.monitor.active_conns.fetch_sub(1, Ordering::Relaxed);
-                                    return; 
-                                }
-                            }
-                        }
-                        Err(tungstenite::Error::Io(e)) if e.kind() == std::io::ErrorKind::WouldBlock || e.kind() == std::io::ErrorKind::TimedOut => {
-                            // idle timeout: send a Ping to keep the connection alive
-                            if socket.send(Message::Ping(Vec::new())).is_err() {
-                                break;
-                            }
-                        }
-                        Err(e) => {
-                            state.monitor.conn_errors.fetch_add(1, Ordering::Relaxed);
-                            
-                            // Log unexpected drops
-                            if let Ok(mut file) = fs::OpenOptions::new().create(true).append(true).open("sovereign_errors.log") {
-                                use std::io::Write;
-                                let _ = writeln!(file, "[{}] Drop on {}: {:?}", chrono::Local::now(), hostname, e);
-                            }
+    // Blacklisted by an earlier run: nothing this worker does would clear it
+    if health.blacklisted {
+        state.blocked_pds.insert(hostname.clone(), true);
+        return;
+    }
+    // A host still penalized by the previous run waits out the rest of its delay
+    while state.running.load(Ordering::SeqCst) && !health.is_available(unix_now()) {
+        thread::sleep(Duration::from_millis(500));
+    }
 
-                            if !state.running.load(Ordering::SeqCst) { return; }
-                            break; 
-                        }
-                    }
+    let mut window = (Instant::now(), 0u64);
+    let mut connector = FirehoseConnector::new(vec![endpoint], config, Arc::clone(&state.running)).with_cursor(cursor);
+    connector.run(|event| match event {
+        FirehoseEvent::Connected { .. } => {
+            state.monitor.active_conns.fetch_add(1, Ordering::Relaxed);
+            window = (Instant::now(), 0);
+            Flow::Continue
+        }
+        FirehoseEvent::Frame { seq, data, .. } => {
+            window.1 += 1;
+            // Here rather than on the verifiers, which take a host's frames in no set order
+            if let Some(seq) = seq {
+                match state.monitor.continuity.observe(&hostname, seq) {
+                    SeqStep::Gap { start, end } => state.errors.record("seq_gap", &hostname, None, &format!("seqs {}..={} skipped", start, end)),
+                    SeqStep::Reset { from } => state.errors.record("seq_reset", &hostname, None, &format!("seq went back from {} to {}", from, seq)),
+                    _ => {}
                 }
-                // If we exit the inner while loop
-                state.monitor.active_conns.fetch_sub(1, Ordering::SeqCst);
-                thread::sleep(Duration::from_secs(5)); // Back off after connection drop to avoid spinning
             }
-            Err(e) => {
-                state.monitor.conn_errors.fetch_add(1, Ordering::Relaxed);
-                
-                // CRITICAL: Handle Authentication Required (401), Not Found (404), Forbidden (403), etc.
-                // If the PDS is private or misconfigured, stop retrying it to save resources.
-                // We expand this to any 4xx or 5xx that indicates it's not a public valid firehose,
-                // or a 200 OK which means it's returning a webpage instead of upgrading to WS.
-                let is_unrecoverable = match &e {
-                    tungstenite::Error::Http(resp) => {
-                        let s = resp.status().as_u16();
-                        s == 400 || s == 401 || s == 403 || s == 404 || s >= 500 || s == 200
-                    },
-                    tungstenite::Error::Url(tungstenite::error::UrlError::UnsupportedUrlScheme) => true,
-                    _ => false,
-                };
-
-                if let Ok(mut file) = fs::OpenOptions::new().create(true).append(true).open("sovereign_errors.log") {
-                    use std::io::Write;
-                    let _ = writeln!(file, "[{}] Failed to connect to {} (via {}): {:?}", chrono::Local::now(), hostname, ws_url, e);
+            if backing_off || window.0.elapsed() >= RATE_WINDOW {
+                if !backing_off {
+                    health.observe_rate(window.1, window.0.elapsed());
+                    window = (Instant::now(), 0);
                 }
+                policy.on_success(&mut health, SystemTime::now());
+                save(&health);
+                backing_off = false;
+            }
+            if tx.send((hostname.clone(), data)).is_err() { Flow::Stop } else { Flow::Continue }
+        }
+        FirehoseEvent::Disconnected { reason, .. } => {
+            state.monitor.active_conns.fetch_sub(1, Ordering::Relaxed);
+            let kind = match reason {
+                DisconnectReason::Shutdown | DisconnectReason::OutdatedCursor => {
+                    save(&health);
+                    return Flow::Continue;
+                }
+                DisconnectReason::Error(e) => {
+                    state.monitor.conn_errors.fetch_add(1, Ordering::Relaxed);
 
-                if is_unrecoverable {
-                    let reason = if let tungstenite::Error::Http(resp) = &e {
-                        format!("HTTP {}", resp.status())
-                    } else if matches!(e, tungstenite::Error::Url(_)) {
-                        "Unsupported URL Scheme".to_string()
-                    } else {
-                        "Unrecoverable".to_string()
-                    };
-                    
-                    if let Ok(mut file) = fs::OpenOptions::new().create(true).append(true).open("sovereign_errors.log") {
-                        use std::io::Write;
-                        let _ = writeln!(file, "[{}] BLACKLISTED {} status: {}", chrono::Local::now(), hostname, reason);
-                    }
-
-                    state.blocked_pds.insert(hostname, true);
-                    return; // EXIT WORKER THREAD
+                    // Log unexpected drops
+                    state.errors.record("drop", &hostname, None, &format!("{:?}", e));
+                    FailureKind::Dropped
                 }
+                DisconnectReason::Stalled(_) => FailureKind::Stalled,
+            };
+            backing_off = true;
+            // Drops are never permanent, so there's always a delay
+            let delay = policy.on_failure(&mut health, kind, SystemTime::now()).unwrap_or(policy.cap);
+            save(&health);
+            Flow::RetryAfter(delay)
+        }
+        FirehoseEvent::ConnectFailed { endpoint, error: e } => {
+            state.monitor.conn_errors.fetch_add(1, Ordering::Relaxed);
+
+            state.errors.record("connect_failed", &hostname, None, &format!("via {}: {:?}", endpoint.subscribe_url(None), e));
+
+            // A private or misconfigured PDS (401/403/404, a web page instead of an upgrade, ...)
+            // is blacklisted rather than retried; see BackoffPolicy::should_blacklist.
+            let delay = policy.on_failure(&mut health, FailureKind::from_connect_error(e), SystemTime::now());
+            save(&health);
+            if let Some(delay) = delay {
+                backing_off = true;
+                return Flow::RetryAfter(delay);
+            }
+            let reason = if let tungstenite::Error::Http(resp) = e {
+                format!("HTTP {}", resp.status())
+            } else if matches!(e, tungstenite::Error::Url(_)) {
+                "Unsupported URL Scheme".to_string()
+            } else {
+                "Unrecoverable".to_string()
+            };
+
+            state.errors.record("blacklisted", &hostname, None, &reason);
 
-                if !state.running.load(Ordering::SeqCst) { return; }
-                thread::sleep(Duration::from_secs(30)); // Back off longer for errors
+            state.blocked_pds.insert(hostname.clone(), true);
+            Flow::DropEndpoint // Last endpoint gone: EXIT WORKER THREAD
+        }
+        FirehoseEvent::Info { name, message, .. } => {
+            tracing::info!("{} says {}: {}", hostname, name, message.unwrap_or_default());
+            if name == OUTDATED_CURSOR {
+                // The connector reconnects from the live head; a restart shouldn't bring the old cursor back
+                state.pds_cursors.remove(&hostname);
             }
+            Flow::Continue
         }
+        FirehoseEvent::Gap { saved_cursor, resumed_at, .. } => {
+            state.monitor.cursor_gaps.fetch_add(1, Ordering::Relaxed);
+            let gap = GapRecord::now(hostname.clone(), saved_cursor, resumed_at);
+            state.errors.record("cursor_gap", &hostname, None, &format!("resumed at {} after cursor {}, {} seqs skipped", resumed_at, saved_cursor, gap.span()));
+            if let Err(e) = state.gaps.record(gap) {
+                eprintln!("[Warn] Could not record gap for {}: {}", hostname, e);
+            }
+            Flow::Continue
+        }
+        FirehoseEvent::Switched { .. } => Flow::Continue,
+    });
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Human-readable summary of the record carried by a commit, for drop logs and the TUI tap.
+fn record_snippet(envelope: &CommitEnvelope) -> Option<String> {
+    let blocks = envelope.blocks?;
+    let op_cid = envelope.ops.iter().find_map(|op| op.cid);
+    decode_record_from_car(blocks, op_cid).map(|view| view.to_string())
+}
+
+/// Applies a verified commit's deletes, then stores the commit unless the ingest filter
+/// leaves it out. Deletes don't go through the filter: a record archived before the filter
+/// or sampling changed must not outlive its deletion.
+fn archive_commit(state: &SharedState, seq: u64, did: &str, envelope: &CommitEnvelope, msg: Vec<u8>) {
+    if !state.dry_run {
+        for op in envelope.ops.iter().filter(|op| op.action == "delete") {
+            state.archive.delete_by_path(did, op.path);
+        }
+    }
+    if !state.filter.matches(envelope).is_keep() {
+        state.monitor.filtered.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    if state.dry_run {
+        return;
     }
+    let msg = state.filter.trim(envelope).unwrap_or(msg);
+    let primary_path = state.filter.kept_ops(envelope)
+        .find(|op| op.action != "delete")
+        .map_or_else(String::new, |op| op.path.to_string());
+    let started = Instant::now();
+    state.archive.ingest(seq, did, primary_path, msg);
+    state.monitor.ingest_time.record_duration(started.elapsed());
 }
 
-fn extract_better_snippet(blocks: &[u8]) -> Option<String> {
-    let store = CarStore::new(blocks);
-    
-    // Higher priority fields for social posts
-    let targets = [
-        "\"text\":", 
-        "\"displayName\":", 
-        "\"description\":", 
-        "\"subject\":", 
-        "\"uri\":", 
-        "\"val\":",  // Used in some labels/custom lexicons
-        "\"$type\":" // To at least see what it is
-    ];
-
-    for block in store.blocks.values() {
-        for target in targets {
-            if let Some(pos) = block.windows(target.len()).position(|w| w == target.as_bytes()) {
-                let start = pos + target.len();
-                let mut end = start;
-                while end < block.len() && end < start + 120 {
-                    // Stop at quote (if not escaped) or comma/brace
-                    if block[end] == b'\"' && (end == 0 || block[end-1] != b'\\') {
-                        break;
-                    }
-                    if block[end] == b',' || block[end] == b'}' || block[end] == b']' {
-                        break;
-                    }
-                    end += 1;
-                }
-                if end > start {
-                    let text = String::from_utf8_lossy(&block[start..end])
-                        .to_string()
-                        .trim_matches(|c| c == '\"' || c == ' ')
-                        .to_string();
-                    
-                    // Prefix with the field type to make it clearer
-                    let field = target.trim_matches(|c| c == '\"' || c == ':');
-                    return Some(format!("{}: {}", field, text));
-                }
-            }
+fn process_sovereign_message(msg: Vec<u8>, pds_host: String, state: &SharedState) {
+    let frame = msg.clone();
+    // Cursors and the relay race only need the header and top-level fields; the full parse
+    // is left for the commits that get verified
+    let Some(peek) = peek_frame(&frame) else {
+        // A bare CAR is no error, but there is nothing to do with it either
+        if let Err(e) = parse_input(&frame) {
+            state.monitor.record_malformed();
+            tracing::debug!("malformed frame from {}: {}", pds_host, e);
         }
+        state.monitor.record_pds_message(&pds_host, None);
+        return;
+    };
+    state.monitor.record_pds_message(&pds_host, peek.seq);
+    // Track per-PDS cursor
+    if let Some(pds_seq) = peek.seq {
+        state.pds_cursors.insert(pds_host.clone(), pds_seq);
     }
-    
-    // Last resort: printable filter for anything that looks like content
-    for block in store.blocks.values() {
-        let readable: String = block.iter()
-            .filter(|&&b| b >= 32 && b <= 126)
-            .map(|&b| b as char)
-            .collect();
-        
-        if readable.len() > 10 {
-            // If it looks like a URI or has common structure
-            if readable.contains("at://") {
-                if let Some(idx) = readable.find("at://") {
-                    return Some(readable[idx..].chars().take(80).collect());
-                }
+
+    // --- RELAY SHADOW LOGIC ---
+    let is_relay = state.relay_hosts.contains_key(&pds_host);
+
+    if let Some(cid) = peek.cid {
+        // Commit CIDs come from links, so carry the 0x00 prefix; the relay's may not
+        let cid = normalize_cid_bytes(cid);
+
+        // If Mesh saw it first, the tracker keeps the content for potential Drop Inspection
+        match state.arrivals.observe(cid, is_relay, &pds_host, &msg) {
+            ArrivalOutcome::RelayWon => {
+                state.monitor.relay_wins.fetch_add(1, Ordering::Relaxed);
             }
-            // Just return a chunk of printable text
-            let chunk: String = readable.chars().take(50).collect();
-            if !chunk.is_empty() {
-                return Some(format!("Raw: {}...", chunk));
+            ArrivalOutcome::MeshWon { lead } => {
+                state.monitor.mesh_wins.fetch_add(1, Ordering::Relaxed);
+                state.monitor.total_lat_gain_ms.fetch_add(lead.as_millis() as u64, Ordering::Relaxed);
+                state.monitor.lat_gain.record_duration(lead);
             }
+            ArrivalOutcome::First | ArrivalOutcome::Repeat => {}
         }
     }
-    None
-}
+    // --------------------------
 
-fn process_sovereign_message(msg: Vec<u8>, pds_host: String, state: &SharedState) {
-    if let Some(envelope) = parse_input(&msg.clone()) {
-        // Track per-PDS cursor
-        if let Some(pds_seq) = envelope.sequence {
-            state.pds_cursors.insert(pds_host.clone(), pds_seq);
+    // In Sovereign mode, we use a global monotonic sequence for the archive,
+    let seq = state.global_seq.fetch_add(1, Ordering::Relaxed);
+
+    if !peek.is_commit() {
+        return;
+    }
+    let envelope = match parse_full(peek, &frame) {
+        Ok(envelope) => envelope,
+        Err(e) => {
+            state.monitor.record_malformed();
+            tracing::debug!("malformed commit from {}: {}", pds_host, e);
+            return;
         }
+    };
+    // Proof of Decoding: Every 50 commits, push a snippet to the TUI
+    if !is_relay && seq.is_multiple_of(50) {
+        if let Some(snippet) = record_snippet(&envelope) {
+            state.monitor.push_tap(snippet);
+        }
+    }
 
-        // --- RELAY SHADOW LOGIC ---
-        let is_relay = state.relay_hosts.contains_key(&pds_host);
-        
-        // Prefer record_cid for matching posts/likes, fallback to commit cid
-        let target_cid = envelope.record_cid.or(envelope.cid);
+    if let Some(did_bytes) = envelope.did {
+        if let Ok(did) = std::str::from_utf8(did_bytes) {
 
-        if let Some(mut cid) = target_cid {
-            // Normalize CID: Remove leading 0x00 common in binary CID encoding
-            if cid.first() == Some(&0x00) {
-                cid = &cid[1..];
-            }
+            let key_entry = {
+                let lock = state.cache.read().unwrap();
+                lock.get(did)
+            };
 
-            let now = Instant::now();
-            let entry = state.arrival_log.get(cid);
-            
-            if let Some(prev) = entry {
-                let (first_time, first_was_relay, already_matched) = *prev.value();
-                if !already_matched && first_was_relay != is_relay {
-                    if first_was_relay && !is_relay {
-                        // Relay arrived first, Mesh just arrived.
-                        state.monitor.relay_wins.fetch_add(1, Ordering::Relaxed);
-                    } else if !first_was_relay && is_relay {
-                        // Mesh arrived first, Relay just arrived.
-                        state.monitor.mesh_wins.fetch_add(1, Ordering::Relaxed);
-                        let diff = now.duration_since(first_time).as_millis() as u64;
-                        state.monitor.total_lat_gain_ms.fetch_add(diff, Ordering::Relaxed);
-                    }
-                    
-                    // Mark as matched so we don't count it again for other mesh nodes
-                    drop(prev);
-                    state.arrival_log.insert(cid.to_vec(), (first_time, first_was_relay, true));
-                    
-                    // If matched, we don't need to keep the content for drop inspection
-                    state.ghost_content.remove(cid);
+            let key_entry = if key_entry.is_none() {
+                // Resolve missing keys via network (Slow Path)
+                if let Some((pk, kt)) = state.resolver.resolve(did) {
+                    let mut lock = state.cache.write().unwrap();
+                    lock.atomic_update_or_tombstone(did, Some(kt), Some(&pk));
+                    Some((pk, kt))
+                } else {
+                    None
                 }
             } else {
-                // First time seeing this CID
-                state.arrival_log.insert(cid.to_vec(), (now, is_relay, false));
-                
-                // If Mesh saw it first, store content for potential Drop Inspection
-                if !is_relay {
-                    state.ghost_content.insert(cid.to_vec(), (pds_host.clone(), msg.clone()));
-                }
-            }
-        }
-        // --------------------------
-
-        // In Sovereign mode, we use a global monotonic sequence for the archive,
-        let seq = state.global_seq.fetch_add(1, Ordering::Relaxed);
-
-        if let Some(t) = envelope.t {
-            if t == b"#commit" || t == b"commit" {
-                // Proof of Decoding: Every 50 commits, push a snippet to the TUI
-                if !is_relay && seq % 50 == 0 {
-                    if let Some(blocks) = envelope.blocks.clone() {
-                        if let Some(snippet) = extract_better_snippet(blocks) {
-                            state.monitor.push_tap(snippet);
-                        }
-                    }
-                }
-
-                if let Some(did_bytes) = envelope.did {
-                    if let Ok(did) = std::str::from_utf8(did_bytes) {
-                        
-                        let key_entry = {
-                            let lock = state.cache.read().unwrap();
-                            lock.get(did)
-                        };
+                key_entry
+            };
 
-                        let key_entry = if key_entry.is_none() {
-                            // Resolve missing keys via network (Slow Path)
-                            if let Some((pk, kt)) = resolve_did(did) {
+            if let Some((mut pk, mut kt)) = key_entry {
+                // Verify and Archive
+                let started = Instant::now();
+                let mut outcome = verify_commit_detailed_with(&envelope, &pk, kt, &state.verify_opts);
+                state.monitor.verify_time.record_duration(started.elapsed());
+                // Potential key rotation - try re-resolving (Slow Path). Malformed
+                // input fails the same way with any key, so it isn't worth a lookup.
+                if matches!(outcome, VerifyOutcome::Mismatch | VerifyOutcome::BadKey) {
+                    if let Some((new_pk, new_kt)) = state.resolver.resolve(did) {
+                        if new_pk != pk || new_kt != kt {
+                            {
                                 let mut lock = state.cache.write().unwrap();
-                                lock.atomic_update_or_tombstone(did, Some(kt), Some(&pk));
-                                Some((pk, kt))
-                            } else {
-                                None
-                            }
-                        } else {
-                            key_entry
-                        };
-
-                        if let Some((mut pk, mut kt)) = key_entry {
-                            // Verify and Archive
-                            if verify_commit(&envelope, &pk, kt) {
-                                state.monitor.record_event(did, true, None, Some(kt));
-                                if !state.dry_run {
-                                    // Handle operations (create/update/delete)
-                                    let mut primary_path = "".to_string();
-                                    for op in &envelope.ops {
-                                        if op.action == "delete" {
-                                            state.archive.delete_by_path(did, &op.path);
-                                        } else if primary_path.is_empty() {
-                                            primary_path = op.path.clone();
-                                        }
-                                    }
-                                    state.archive.ingest(seq, did, primary_path, msg);
-                                }
-                            } else {
-                                // Potential key rotation - try re-resolving (Slow Path)
-                                let mut resolved_again = false;
-                                if let Some((new_pk, new_kt)) = resolve_did(did) {
-                                    if new_pk != pk || new_kt != kt {
-                                        {
-                                            let mut lock = state.cache.write().unwrap();
-                                            lock.atomic_update_or_tombstone(did, Some(new_kt), Some(&new_pk));
-                                        }
-                                        pk = new_pk;
-                                        kt = new_kt;
-                                        if verify_commit(&envelope, &pk, kt) {
-                                            resolved_again = true;
-                                        }
-                                    }
-                                }
-
-                                if resolved_again {
-                                    state.monitor.record_event(did, true, None, Some(kt));
-                                    if !state.dry_run {
-                                        let mut primary_path = "".to_string();
-                                        for op in &envelope.ops {
-                                            if op.action == "delete" {
-                                                state.archive.delete_by_path(did, &op.path);
-                                            } else if primary_path.is_empty() {
-                                                primary_path = op.path.clone();
-                                            }
-                                        }
-                                        state.archive.ingest(seq, did, primary_path, msg);
-                                    }
-                                } else {
-                                    state.monitor.record_event(did, false, Some(ErrorType::InvalidSignature), Some(kt));
-                                    if let Ok(mut file) = fs::OpenOptions::new().create(true).append(true).open("sovereign_errors.log") {
-                                        use std::io::Write;
-                                        let _ = writeln!(file, "[{}] INVALID SIG from {} for DID {}", chrono::Local::now(), pds_host, did);
-                                    }
-                                }
+                                lock.atomic_update_or_tombstone(did, Some(new_kt), Some(&new_pk));
                             }
+                            pk = new_pk;
+                            kt = new_kt;
+                            outcome = verify_commit_detailed_with(&envelope, &pk, kt, &state.verify_opts);
                         }
                     }
                 }
+
+                if outcome.is_ok() {
+                    state.monitor.record_event(did, true, None, Some(kt));
+                    if outcome == VerifyOutcome::VerifiedHighS {
+                        state.monitor.record_high_s();
+                    }
+                    archive_commit(state, seq, did, &envelope, msg);
+                } else {
+                    state.monitor.record_event(did, false, outcome.error_type(), Some(kt));
+                    let event = if outcome == VerifyOutcome::Mismatch { "invalid_sig" } else { "verify_failed" };
+                    state.errors.record(event, &pds_host, Some(did), &format!("{:?}", outcome));
+                }
+            } else {
+                state.monitor.record_event(did, false, Some(ErrorType::MissingKey), None);
             }
         }
     }
@@ -0,0 +1,68 @@
+//! Sovereign Mirror: keeps an offsite copy of an archive served by `sovereign_relay --sync-port`.
+//! Only segments the mirror doesn't already hold are transferred; interrupted downloads resume.
+
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use clap::Parser;
+use did_mmap_cache::archive::sync::SyncClient;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+struct Args {
+    /// Sync endpoint of the source relay, e.g. http://home.example:8081
+    source: String,
+
+    /// Local mirror directory
+    #[arg(short, long, default_value = "sovereign_mirror")]
+    dest: String,
+
+    /// Zstd dictionary the source archive was written with (needed to verify segments)
+    #[arg(long, default_value = "atproto_firehose.dict")]
+    dict: String,
+
+    /// Pull again every this many seconds (0 = pull once and exit)
+    #[arg(long, default_value_t = 0)]
+    interval_secs: u64,
+}
+
+fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+    let args = Args::parse();
+
+    let dict = if Path::new(&args.dict).exists() {
+        Some(std::fs::read(&args.dict)?)
+    } else {
+        println!("[Mirror] No dictionary at {}; assuming the source archive was written without one.", args.dict);
+        None
+    };
+    let client = SyncClient::new(&args.source)?.with_dict(dict);
+
+    loop {
+        let start = Instant::now();
+        match client.pull(&args.dest) {
+            Ok(report) => {
+                println!(
+                    "[Mirror] {} segments downloaded ({:.2} MB), {} up to date, {} new tombstones in {:?}",
+                    report.downloaded,
+                    report.bytes as f64 / 1024.0 / 1024.0,
+                    report.up_to_date,
+                    report.tombstones_added,
+                    start.elapsed()
+                );
+                for failure in &report.failed {
+                    println!("[Mirror] [WARN] {}", failure);
+                }
+            }
+            Err(e) => eprintln!("[Mirror] [ERROR] Pull from {} failed: {}", args.source, e),
+        }
+
+        if args.interval_secs == 0 {
+            break;
+        }
+        thread::sleep(Duration::from_secs(args.interval_secs));
+    }
+    Ok(())
+}
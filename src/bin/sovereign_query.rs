@@ -0,0 +1,133 @@
+//! Sovereign Query: looks up what the archive stored, by seq, DID, record URI or time window.
+
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use anyhow::{bail, Context, Result};
+use clap::{ArgGroup, Parser, ValueEnum};
+use did_mmap_cache::archive::query::{rfc3339_micros, StoredMessage};
+use did_mmap_cache::archive::MultiShardArchive;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// One aligned row per message
+    Table,
+    /// One JSON object per line
+    Jsonl,
+}
+
+#[derive(Parser, Debug)]
+#[command(author, version, about)]
+#[command(group(ArgGroup::new("query").required(true).multiple(true).args(["seq", "did", "path", "since", "until"])))]
+struct Args {
+    /// Archive directory
+    archive: PathBuf,
+
+    /// Print the message stored under this seq
+    #[arg(long)]
+    seq: Option<u64>,
+
+    /// With --seq, print the raw frame as hex instead of decoding it
+    #[arg(long, requires = "seq")]
+    hex: bool,
+
+    /// List this DID's stored messages
+    #[arg(long, conflicts_with_all = ["seq", "path"])]
+    did: Option<String>,
+
+    /// Print the newest stored message at at://did/collection/rkey
+    #[arg(long, conflicts_with_all = ["seq", "did"])]
+    path: Option<String>,
+
+    /// Messages whose record time is at or after this (RFC 3339 or Unix seconds)
+    #[arg(long, conflicts_with_all = ["seq", "did", "path"])]
+    since: Option<String>,
+
+    /// Messages whose record time is before this (RFC 3339 or Unix seconds)
+    #[arg(long, conflicts_with_all = ["seq", "did", "path"])]
+    until: Option<String>,
+
+    /// First seq of the time-window scan (default: the first at or after --since, by TID)
+    #[arg(long)]
+    from: Option<u64>,
+
+    /// Seqs the time-window scan reads at most
+    #[arg(long, default_value_t = 100_000)]
+    max_scan: u64,
+
+    #[arg(long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+}
+
+/// µs since the epoch of an RFC 3339 time or a count of Unix seconds.
+fn parse_time(arg: &str) -> Result<u64> {
+    if let Ok(secs) = arg.parse::<u64>() {
+        return secs.checked_mul(1_000_000).context("time out of range");
+    }
+    rfc3339_micros(arg).with_context(|| format!("{:?} is neither RFC 3339 nor Unix seconds", arg))
+}
+
+fn print(out: &mut impl Write, format: Format, messages: &[StoredMessage]) -> Result<()> {
+    match format {
+        Format::Jsonl => {
+            for message in messages {
+                serde_json::to_writer(&mut *out, message)?;
+                writeln!(out)?;
+            }
+        }
+        Format::Table => {
+            writeln!(out, "{:>12}  {:<10}  {:<32}  {:<24}  {:<50}  RECORD", "SEQ", "EVENT", "DID", "CREATED", "PATH")?;
+            for m in messages {
+                writeln!(
+                    out,
+                    "{:>12}  {:<10}  {:<32}  {:<24}  {:<50}  {}",
+                    m.seq,
+                    m.event.as_deref().unwrap_or("?"),
+                    m.did.as_deref().unwrap_or("-"),
+                    m.created_at.as_deref().unwrap_or("-"),
+                    m.paths(),
+                    m.record.as_deref().unwrap_or("-"),
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+    let archive = MultiShardArchive::open_readonly(&args.archive, None)?;
+    let mut out = BufWriter::new(io::stdout().lock());
+
+    let messages = if let Some(seq) = args.seq {
+        let message = archive.get_message_by_seq(seq).with_context(|| format!("seq {}", seq))?;
+        if args.hex {
+            writeln!(out, "{}", hex::encode(&message))?;
+            return Ok(out.flush()?);
+        }
+        vec![StoredMessage::describe(seq, &message)]
+    } else if let Some(did) = &args.did {
+        archive.messages_for_did(did)?
+    } else if let Some(uri) = &args.path {
+        vec![archive.find_record(uri)?]
+    } else {
+        let since = args.since.as_deref().map(parse_time).transpose()?;
+        let until = args.until.as_deref().map(parse_time).transpose()?;
+        let start = match args.from {
+            Some(from) => from,
+            None => match since {
+                Some(since) => match archive.seek_by_time(since) {
+                    Some(seq) => seq,
+                    None => bail!("nothing stored at or after --since"),
+                },
+                None => archive.min_seq().context("the archive is empty")?,
+            },
+        };
+        let end = archive.max_seq().map_or(start, |max| max + 1).min(start.saturating_add(args.max_scan));
+        eprintln!("[Query] Scanning seqs {}..{}", start, end);
+        archive.scan_time_window(start..end, since, until)
+    };
+
+    print(&mut out, args.format, &messages)?;
+    Ok(out.flush()?)
+}
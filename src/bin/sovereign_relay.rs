@@ -1,14 +1,31 @@
 //! Sovereign Relay - Unfiltered firehose provider.
 //! Serves historical and live ATProto records from high-efficiency archival storage.
 //! Supports Zstd-compressed framing for 70% egress reduction.
+//!
+//! Framing 1 streams bare zstd clusters. Clients that ask for `framing=2` get each cluster
+//! behind a header naming its dictionary, and a `dict_change` notice plus the new dictionary
+//! whenever the stream crosses into segments compressed with a different one.
+//!
+//! A client that sends a `SubscriptionFilter` as a Text frame right after connecting gets
+//! message mode instead: every stored frame is decompressed and checked against the filter,
+//! and only the matching ones are sent, uncompressed, one Binary message each.
+//!
+//! `order=desc` walks the archive backward instead, from `cursor` (or the newest stored
+//! seq) down to the oldest, and closes the connection once it gets there.
 
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::net::{TcpListener, TcpStream};
-use tokio_tungstenite::tungstenite::protocol::Message;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Message};
+use futures::stream::SplitSink;
 use futures::{StreamExt, SinkExt};
 use clap::Parser;
-use did_mmap_cache::archive::MultiShardArchive;
+use did_mmap_cache::archive::{self, ArchiveError, MultiShardArchive};
+use did_mmap_cache::archive::sync::SyncServer;
+use did_mmap_cache::filter::{FilterDecision, FilterSpec, SubscriptionFilter};
+use did_mmap_cache::ingest::{encode_relay_frame, relay_dict_prefix, RELAY_FRAMING};
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 use tracing::{info, warn, error};
 
@@ -27,20 +44,79 @@ struct Args {
     #[arg(short, long, default_value = "atproto_firehose.dict")]
     dict: String,
 
+    /// Directory of other dictionaries segments may be compressed with (every file is loaded)
+    #[arg(long)]
+    dict_dir: Option<String>,
+
     /// Compression level (1-22)
     #[arg(long, default_value_t = 3)]
     compression_level: i32,
+
+    /// Refuse to decompress archive clusters larger than this (MB)
+    #[arg(long, default_value_t = 64)]
+    max_cluster_mb: usize,
+
+    /// Also serve the archive to `sovereign_mirror` over HTTP on this port
+    #[arg(long)]
+    sync_port: Option<u16>,
+
+    /// Stream as fast as the socket takes it while a client is behind the archive head
+    #[arg(long)]
+    catchup_fast: bool,
+}
+
+/// While catching up, the loop only yields this often so other connections still get a turn.
+const CATCHUP_YIELD_EVERY: u64 = 4096;
+const CATCHUP_LOG_INTERVAL: Duration = Duration::from_secs(10);
+/// How long after connecting a client has to send its subscription filter.
+const FILTER_WAIT: Duration = Duration::from_millis(250);
+
+type WsSink = SplitSink<tokio_tungstenite::WebSocketStream<TcpStream>, Message>;
+
+/// Progress of a `--catchup-fast` client towards the archive head.
+struct Catchup {
+    head: u64,
+    from: u64,
+    started: Instant,
+    last_log: Instant,
+    logged_seq: u64,
+}
+
+impl Catchup {
+    fn start(from: u64, head: u64) -> Self {
+        let now = Instant::now();
+        Catchup { head, from, started: now, last_log: now, logged_seq: from }
+    }
+
+    /// Logs the rate since the last report once `CATCHUP_LOG_INTERVAL` has passed.
+    fn maybe_log(&mut self, addr: std::net::SocketAddr, current_seq: u64) {
+        let elapsed = self.last_log.elapsed();
+        if elapsed < CATCHUP_LOG_INTERVAL {
+            return;
+        }
+        let rate = (current_seq - self.logged_seq) as f64 / elapsed.as_secs_f64();
+        info!("  {} catching up: seq {} of {} ({:.0} seq/s)", addr, current_seq, self.head, rate);
+        self.last_log = Instant::now();
+        self.logged_seq = current_seq;
+    }
 }
 
 use std::sync::atomic::{AtomicU64, Ordering};
 
 struct RelayState {
     archive: MultiShardArchive,
-    dict: Vec<u8>,
+    /// Every dictionary this relay can hand out, by hex blake3
+    dicts: HashMap<String, Vec<u8>>,
+    /// Hash of `--dict`, used for segments that don't record their dictionary
+    dict_hash: String,
     _compression_level: i32,
+    catchup_fast: bool,
     sent_clusters: AtomicU64,
     sent_bytes: AtomicU64,
     filtered_msgs: AtomicU64,
+    /// Message mode: frames sent, and frames a client's filter turned away
+    sent_msgs: AtomicU64,
+    skipped_msgs: AtomicU64,
 }
 
 #[tokio::main]
@@ -53,22 +129,44 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // 1. Load Dictionary
     let dict = std::fs::read(&args.dict).expect("Failed to read dictionary");
     info!("Loaded Zstd dictionary ({} bytes)", dict.len());
+    let dict_hash = archive::dict_hash(&dict);
+    let mut dicts = HashMap::new();
+    if let Some(dir) = &args.dict_dir {
+        for entry in std::fs::read_dir(dir)?.flatten() {
+            if entry.path().is_file() {
+                let other = std::fs::read(entry.path())?;
+                info!("Loaded dictionary {} ({})", entry.path().display(), &archive::dict_hash(&other)[..8]);
+                dicts.insert(archive::dict_hash(&other), other);
+            }
+        }
+    }
 
     // 2. Load Archive (Multi-shard aware)
     let archive_path = PathBuf::from(&args.archive);
-    let combined_archive = MultiShardArchive::open_readonly(&archive_path, Some(dict.clone()))?;
+    let combined_archive = MultiShardArchive::open_readonly_with_limit(&archive_path, Some(dict.clone()), args.max_cluster_mb * 1024 * 1024)?;
     
-    info!("Archive ready with {} shards", combined_archive.reader_count());
+    info!("Archive ready with {} shards (cluster decompression cap {} bytes)", combined_archive.reader_count(), combined_archive.max_decompressed_cluster_bytes());
 
+    dicts.insert(dict_hash.clone(), dict);
     let state = Arc::new(RelayState {
         archive: combined_archive,
-        dict,
+        dicts,
+        dict_hash,
         _compression_level: args.compression_level,
+        catchup_fast: args.catchup_fast,
         sent_clusters: AtomicU64::new(0),
         sent_bytes: AtomicU64::new(0),
         filtered_msgs: AtomicU64::new(0),
+        sent_msgs: AtomicU64::new(0),
+        skipped_msgs: AtomicU64::new(0),
     });
 
+    if let Some(sync_port) = args.sync_port {
+        let server = SyncServer::bind(&archive_path, ("0.0.0.0", sync_port))?;
+        info!("Serving archive sync on port {}", sync_port);
+        server.spawn();
+    }
+
     let listener = TcpListener::bind(format!("0.0.0.0:{}", args.port)).await?;
     info!("Listening for connections...");
 
@@ -91,6 +189,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let sent_c = state.sent_clusters.load(Ordering::Relaxed);
     let sent_b = state.sent_bytes.load(Ordering::Relaxed);
     let filtered = state.filtered_msgs.load(Ordering::Relaxed);
+    let sent_m = state.sent_msgs.load(Ordering::Relaxed);
+    let skipped_m = state.skipped_msgs.load(Ordering::Relaxed);
 
     println!("\n╔═══════════════════════════════════════════════════════════════════════╗");
     println!("║                   SOVEREIGN RELAY SHUTDOWN SUMMARY                  ║");
@@ -98,6 +198,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("  Total Clusters Served:   {}", sent_c);
     println!("  Total Egress Data:       {:.2} MB", sent_b as f64 / 1024.0 / 1024.0);
     println!("  Tombstones Filtered:     {} messages", filtered);
+    println!("  Filtered Clients:        {} messages sent, {} skipped", sent_m, skipped_m);
+    println!("  Cluster Size Cap:        {} MB decompressed", args.max_cluster_mb);
     println!("-------------------------------------------------------------------------");
     println!("  Archive Location:        {}", args.archive);
     println!("  Status:                  Clean Exit\n");
@@ -110,6 +212,18 @@ async fn handle_connection(stream: TcpStream, state: Arc<RelayState>, addr: std:
     
     let cursor_atomic = Arc::new(AtomicU64::new(u64::MAX));
     let cursor_clone = Arc::clone(&cursor_atomic);
+    // `since=<rfc3339>` as µs since the epoch
+    let since_atomic = Arc::new(AtomicU64::new(u64::MAX));
+    let since_clone = Arc::clone(&since_atomic);
+    // `have_dict=<hash>`: the dictionary the client already holds
+    let have_dict = Arc::new(std::sync::Mutex::new(None::<String>));
+    let have_dict_clone = Arc::clone(&have_dict);
+    // `framing=2`: dictionary-tagged clusters and in-band dictionary changes
+    let framing_atomic = Arc::new(AtomicU64::new(1));
+    let framing_clone = Arc::clone(&framing_atomic);
+    // `order=desc`: newest seq first, down to the oldest stored one
+    let desc_atomic = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let desc_clone = Arc::clone(&desc_atomic);
 
     info!("New connection from: {}", addr);
 
@@ -117,11 +231,42 @@ async fn handle_connection(stream: TcpStream, state: Arc<RelayState>, addr: std:
         let uri = request.uri();
         info!("  WS Request URI: {}", uri);
         if let Some(query) = uri.query() {
-            for part in query.split('&') {
-                if part.starts_with("cursor=") {
-                    if let Ok(val) = part[7..].parse::<u64>() {
-                        cursor_clone.store(val, Ordering::SeqCst);
+            // Decoded, so an offset like `+02:00` survives as `%2B02:00`
+            for (key, value) in url::form_urlencoded::parse(query.as_bytes()) {
+                match key.as_ref() {
+                    "cursor" => {
+                        if let Ok(val) = value.parse::<u64>() {
+                            cursor_clone.store(val, Ordering::SeqCst);
+                        }
+                    }
+                    "have_dict" => *have_dict_clone.lock().unwrap() = Some(value.to_ascii_lowercase()),
+                    "framing" => {
+                        if let Ok(val) = value.parse::<u64>() {
+                            framing_clone.store(val.clamp(1, RELAY_FRAMING), Ordering::SeqCst);
+                        }
                     }
+                    "order" => match value.as_ref() {
+                        "asc" => desc_clone.store(false, Ordering::SeqCst),
+                        "desc" => desc_clone.store(true, Ordering::SeqCst),
+                        _ => {
+                            let mut err = tokio_tungstenite::tungstenite::handshake::server::ErrorResponse::new(
+                                Some(format!("invalid order={}: expected asc or desc", value)),
+                            );
+                            *err.status_mut() = tokio_tungstenite::tungstenite::http::StatusCode::BAD_REQUEST;
+                            return Err(err);
+                        }
+                    },
+                    "since" => match chrono::DateTime::parse_from_rfc3339(&value) {
+                        Ok(t) if t.timestamp_micros() >= 0 => since_clone.store(t.timestamp_micros() as u64, Ordering::SeqCst),
+                        _ => {
+                            let mut err = tokio_tungstenite::tungstenite::handshake::server::ErrorResponse::new(
+                                Some(format!("invalid since={}: expected an RFC 3339 time", value)),
+                            );
+                            *err.status_mut() = tokio_tungstenite::tungstenite::http::StatusCode::BAD_REQUEST;
+                            return Err(err);
+                        }
+                    },
+                    _ => {}
                 }
             }
         }
@@ -135,30 +280,92 @@ async fn handle_connection(stream: TcpStream, state: Arc<RelayState>, addr: std:
     };
 
     let cursor_val = cursor_atomic.load(Ordering::SeqCst);
-    let cursor = if cursor_val == u64::MAX { None } else { Some(cursor_val) };
+    let mut cursor = if cursor_val == u64::MAX { None } else { Some(cursor_val) };
+    let since_val = since_atomic.load(Ordering::SeqCst);
+    let framing = framing_atomic.load(Ordering::SeqCst);
+    let desc = desc_atomic.load(Ordering::SeqCst);
+
+    let (mut ws_sink, mut ws_source) = ws_stream.split();
+
+    // 0. A filter sent straight after connecting switches the connection to message mode
+    let subscription = match tokio::time::timeout(FILTER_WAIT, ws_source.next()).await {
+        Ok(Some(Ok(Message::Text(text)))) => match SubscriptionFilter::from_message(&text) {
+            Ok(filter) => Some(filter),
+            Err(e) => {
+                warn!("  Rejecting filter from {}: {}", addr, e);
+                let _ = ws_sink.send(Message::Close(Some(CloseFrame {
+                    code: CloseCode::Policy,
+                    reason: format!("invalid filter: {}", e).into(),
+                }))).await;
+                return Ok(());
+            }
+        },
+        _ => None,
+    };
+
+    // 1. Negotiation (Start from cursor, since, or min_seq). An explicit cursor wins over since.
+    // Walking backward starts at the cursor or the newest seq, and ignores since.
+    if desc {
+        cursor = state.archive.max_seq().map(|max| cursor.map_or(max, |c| c.min(max)));
+    } else if cursor.is_none() && since_val != u64::MAX {
+        let seek_state = Arc::clone(&state);
+        let found = tokio::task::spawn_blocking(move || seek_state.archive.seek_by_time(since_val)).await?;
+        // Nothing that recent is stored yet: start at the live head
+        cursor = found.or_else(|| state.archive.max_seq().map(|m| m + 1));
+        info!("  since={}µs resolved to seq {:?} for {}", since_val, cursor, addr);
+    }
 
-    let (mut ws_sink, mut _ws_source) = ws_stream.split();
+    if let Some(filter) = subscription {
+        stream_messages(ws_sink, &state, addr, cursor, filter, desc).await;
+        info!("Closing connection");
+        return Ok(());
+    }
 
-    // 1. Handshake: Send protocol metadata and dictionary
-    let dict_hash = hex::encode(blake3::hash(&state.dict).as_bytes());
-    let handshake = serde_json::json!({
+    // 2. Handshake: Send protocol metadata, then the dictionary of the first segment streamed
+    // unless the client already has it
+    let first_seq = if desc { cursor } else { cursor.or_else(|| state.archive.min_seq()) };
+    let mut dict_hash = first_seq
+        .map_or_else(|| state.dict_hash.clone(), |seq| segment_dict_hash(&state, seq));
+    if !state.dicts.contains_key(&dict_hash) {
+        warn!("  Segment dictionary {} is not loaded; see --dict-dir", &dict_hash[..8.min(dict_hash.len())]);
+        dict_hash = state.dict_hash.clone();
+    }
+    let dict_sent = have_dict.lock().unwrap().as_deref() != Some(dict_hash.as_str());
+    let mut handshake = serde_json::json!({
         "version": 1,
         "compression": "zstd",
         "dict_hash": dict_hash,
+        "dict_sent": dict_sent,
+        "framing": framing,
         "info": "Sovereign Relay v0.1.0 - Unfiltered Firehose"
     });
+    if desc {
+        handshake["order"] = "desc".into();
+    }
 
     if let Err(e) = ws_sink.send(Message::Text(handshake.to_string())).await {
         warn!("  Failed to send handshake JSON to {}: {}", addr, e);
         return Ok(());
     }
-    if let Err(e) = ws_sink.send(Message::Binary(state.dict.clone())).await {
-        warn!("  Failed to send dictionary to {}: {}", addr, e);
+    if dict_sent {
+        if let Err(e) = ws_sink.send(Message::Binary(state.dicts[&dict_hash].clone())).await {
+            warn!("  Failed to send dictionary to {}: {}", addr, e);
+            return Ok(());
+        }
+        info!("  Handshake complete for {}. Dictionary sent (hash: {}, framing {})", addr, &dict_hash[..8], framing);
+    } else {
+        info!("  Handshake complete for {}. Client already holds dictionary {} (framing {})", addr, &dict_hash[..8], framing);
+    }
+
+    if desc {
+        if let Some(newest) = cursor {
+            stream_clusters_desc(&mut ws_sink, &state, addr, framing, &mut dict_hash, newest).await;
+        }
+        close_at_oldest(&mut ws_sink, addr).await;
+        info!("Closing connection");
         return Ok(());
     }
-    info!("  Handshake complete for {}. Dictionary sent (hash: {})", addr, &dict_hash[..8]);
 
-    // 2. Negotiation (Start from cursor or min_seq)
     // If no segments exist yet, wait until some appear
     let mut start_seq = cursor.or_else(|| state.archive.min_seq());
     
@@ -177,51 +384,342 @@ async fn handle_connection(stream: TcpStream, state: Arc<RelayState>, addr: std:
     // This allows the server to act as a pure byte-streamer with minimal CPU.
     
     let mut last_cluster_hash = [0u8; 32];
+    // A gap is only skipped once a refresh hasn't filled it, in case another shard's
+    // segment holding it was persisted after ours
+    let mut gap_retried = None;
+    let mut catchup = if state.catchup_fast {
+        state.archive.max_seq().filter(|&head| head > current_seq).map(|head| Catchup::start(current_seq, head))
+    } else {
+        None
+    };
+    if let Some(c) = &catchup {
+        info!("  {} is {} seqs behind the head; streaming without throttling until it catches up", addr, c.head - current_seq);
+    }
 
     loop {
         // 1. Fetch the raw compressed cluster from the archive
-        // NOTE: If the sequence is tombstoned, this currently returns NotFound.
-        // We should distinguish between "Tombstoned" and "End of Archive".
         match state.archive.get_raw_cluster_at_seq(current_seq) {
             Ok(cluster_data) => {
                 let current_hash = blake3::hash(&cluster_data).into();
                 
                 // Only send the cluster if it's new (multiple sequences share one cluster)
                 if current_hash != last_cluster_hash {
-                    let len = cluster_data.len();
-                    if let Err(e) = ws_sink.send(Message::Binary(cluster_data)).await {
-                        warn!("  Failed to send cluster to {}: {}", addr, e);
+                    if !send_cluster(&mut ws_sink, &state, addr, framing, &mut dict_hash, current_seq, cluster_data).await {
                         break;
                     }
-                    state.sent_clusters.fetch_add(1, Ordering::Relaxed);
-                    state.sent_bytes.fetch_add(len as u64, Ordering::Relaxed);
                     last_cluster_hash = current_hash;
                 }
                 
                 // Track current progress
                 current_seq += 1;
             }
-            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-                let err_msg = e.to_string();
-                if err_msg.contains("tombstoned") {
-                    // Skip this message but continue to next
-                    state.filtered_msgs.fetch_add(1, Ordering::Relaxed);
-                    current_seq += 1;
-                } else {
-                    // End of current archive data. Refresh and wait.
-                    state.archive.refresh().ok();
-                    tokio::time::sleep(Duration::from_millis(100)).await;
+            Err(e) => {
+                if !step_past_miss(&state, addr, e, &mut current_seq, &mut gap_retried).await {
+                    break;
+                }
+            }
+        }
+
+        if let Some(c) = &mut catchup {
+            if current_seq > c.head {
+                // The head may have moved on while we streamed
+                match state.archive.max_seq() {
+                    Some(head) if head >= current_seq => c.head = head,
+                    _ => {
+                        let secs = c.started.elapsed().as_secs_f64();
+                        info!(
+                            "  {} caught up at seq {}: {} seqs in {:.1}s ({:.0} seq/s)",
+                            addr, current_seq, current_seq - c.from, secs, (current_seq - c.from) as f64 / secs.max(1e-3)
+                        );
+                        catchup = None;
+                    }
                 }
+            } else {
+                c.maybe_log(addr, current_seq);
             }
+        }
+        if catchup.is_none() || current_seq % CATCHUP_YIELD_EVERY == 0 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    info!("Closing connection");
+    Ok(())
+}
+
+/// Sends the cluster holding `seq`, preceded by a `dict_change` notice and the new dictionary
+/// if `seq`'s segment was compressed with another one than `dict_hash`. False if the stream
+/// has to end.
+async fn send_cluster(
+    ws_sink: &mut WsSink,
+    state: &RelayState,
+    addr: std::net::SocketAddr,
+    framing: u64,
+    dict_hash: &mut String,
+    seq: u64,
+    cluster_data: Vec<u8>,
+) -> bool {
+    let segment_hash = segment_dict_hash(state, seq);
+    if segment_hash != *dict_hash {
+        let Some(new_dict) = state.dicts.get(&segment_hash) else {
+            error!("  Seq {} needs dictionary {}, which is not loaded; see --dict-dir", seq, &segment_hash[..8.min(segment_hash.len())]);
+            return false;
+        };
+        if framing < 2 {
+            // A framing-1 client can't be told; reconnecting gets it the right dictionary
+            info!("  Dictionary changes at seq {}; closing framing-1 client {}", seq, addr);
+            let _ = ws_sink.send(Message::Close(Some(CloseFrame {
+                code: CloseCode::Again,
+                reason: format!("dictionary changed at seq {}; reconnect with cursor", seq).into(),
+            }))).await;
+            return false;
+        }
+        let notice = serde_json::json!({ "event": "dict_change", "dict_hash": segment_hash, "seq": seq });
+        if let Err(e) = ws_sink.send(Message::Text(notice.to_string())).await {
+            warn!("  Failed to send dict_change to {}: {}", addr, e);
+            return false;
+        }
+        if let Err(e) = ws_sink.send(Message::Binary(new_dict.clone())).await {
+            warn!("  Failed to send dictionary to {}: {}", addr, e);
+            return false;
+        }
+        info!("  Dictionary {} -> {} for {} at seq {}", &dict_hash[..8], &segment_hash[..8], addr, seq);
+        *dict_hash = segment_hash;
+    }
+    let frame = if framing >= 2 {
+        encode_relay_frame(relay_dict_prefix(&state.dicts[&*dict_hash]), &cluster_data)
+    } else {
+        cluster_data
+    };
+    let len = frame.len();
+    if let Err(e) = ws_sink.send(Message::Binary(frame)).await {
+        warn!("  Failed to send cluster to {}: {}", addr, e);
+        return false;
+    }
+    state.sent_clusters.fetch_add(1, Ordering::Relaxed);
+    state.sent_bytes.fetch_add(len as u64, Ordering::Relaxed);
+    true
+}
+
+/// `order=desc`: every cluster holding a seq in `min_seq..=newest`, newest seq first. A
+/// cluster is sent at the first (newest) of its seqs the walk reaches, and not again.
+async fn stream_clusters_desc(ws_sink: &mut WsSink, state: &RelayState, addr: std::net::SocketAddr, framing: u64, dict_hash: &mut String, newest: u64) {
+    let Some(oldest) = state.archive.min_seq() else { return };
+    info!("  Streaming to {} backward from seq {} to {}", addr, newest, oldest);
+    let mut sent = HashSet::new();
+    let mut current_seq = newest;
+    loop {
+        match state.archive.get_raw_cluster_at_seq(current_seq) {
+            Ok(cluster_data) => {
+                let hash: [u8; 32] = blake3::hash(&cluster_data).into();
+                if sent.insert(hash) && !send_cluster(ws_sink, state, addr, framing, dict_hash, current_seq, cluster_data).await {
+                    return;
+                }
+            }
+            Err(ArchiveError::Tombstoned { .. }) => {
+                state.filtered_msgs.fetch_add(1, Ordering::Relaxed);
+            }
+            // A bounded scan of what's stored: gaps are not waited on
+            Err(ArchiveError::Gap { .. }) => {}
+            Err(ArchiveError::NotFound | ArchiveError::OutOfRange) => return,
             Err(e) => {
                 error!("  Archive read error for {}: {}", addr, e);
-                break;
+                return;
+            }
+        }
+        if current_seq <= oldest {
+            return;
+        }
+        current_seq -= 1;
+        tokio::task::yield_now().await;
+    }
+}
+
+/// Ends an `order=desc` stream.
+async fn close_at_oldest(ws_sink: &mut WsSink, addr: std::net::SocketAddr) {
+    info!("  Backward stream to {} reached the oldest stored seq", addr);
+    let _ = ws_sink.send(Message::Close(Some(CloseFrame {
+        code: CloseCode::Normal,
+        reason: "reached the oldest stored seq".into(),
+    }))).await;
+}
+
+/// Hash of the dictionary `seq`'s segment was compressed with; `--dict` unless it says otherwise.
+fn segment_dict_hash(state: &RelayState, seq: u64) -> String {
+    state.archive.dict_hash_at_seq(seq).unwrap_or_else(|| state.dict_hash.clone())
+}
+
+/// Moves `current_seq` past a seq the archive couldn't return, or waits for it to be
+/// written. False if the stream has to end.
+async fn step_past_miss(state: &RelayState, addr: std::net::SocketAddr, err: ArchiveError, current_seq: &mut u64, gap_retried: &mut Option<u64>) -> bool {
+    match err {
+        ArchiveError::Tombstoned { .. } => {
+            // Skip this message but continue to next
+            state.filtered_msgs.fetch_add(1, Ordering::Relaxed);
+            *current_seq += 1;
+        }
+        ArchiveError::Gap { seq } if *gap_retried == Some(seq) => {
+            *current_seq += 1;
+        }
+        ArchiveError::Gap { seq } => {
+            *gap_retried = Some(seq);
+            state.archive.refresh().ok();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        ArchiveError::NotFound => {
+            // Cursor older than the archive: resume at the oldest seq still stored
+            match state.archive.min_seq() {
+                Some(min) if min > *current_seq => {
+                    info!("  Seq {} is no longer archived; {} resumes at {}", current_seq, addr, min);
+                    *current_seq = min;
+                }
+                _ => *current_seq += 1,
+            }
+        }
+        ArchiveError::OutOfRange => {
+            // End of current archive data. Refresh and wait.
+            state.archive.refresh().ok();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        e => {
+            error!("  Archive read error for {}: {}", addr, e);
+            return false;
+        }
+    }
+    true
+}
+
+/// Message mode: each stored frame `filter` keeps, as a Binary message of its own. Frames
+/// that don't parse are skipped along with those the filter turns away. With `desc`, the
+/// walk goes backward from `cursor` and ends at the oldest stored seq.
+async fn stream_messages(mut ws_sink: WsSink, state: &RelayState, addr: std::net::SocketAddr, cursor: Option<u64>, filter: SubscriptionFilter, desc: bool) {
+    let mut handshake = serde_json::json!({
+        "version": 1,
+        "mode": "messages",
+        "compression": "none",
+        "filter": { "collections": filter.collections, "dids": filter.dids },
+        "info": "Sovereign Relay v0.1.0 - Filtered Firehose"
+    });
+    if desc {
+        handshake["order"] = "desc".into();
+    }
+    if let Err(e) = ws_sink.send(Message::Text(handshake.to_string())).await {
+        warn!("  Failed to send handshake JSON to {}: {}", addr, e);
+        return;
+    }
+    info!("  Handshake complete for {}. Message mode: {} collections, {} DIDs", addr, filter.collections.len(), filter.dids.len());
+    let spec = filter.spec();
+    let (mut sent, mut skipped) = (0u64, 0u64);
+
+    if desc {
+        if let (Some(newest), Some(oldest)) = (cursor, state.archive.min_seq()) {
+            info!("  Streaming filtered messages to {} backward from seq {} to {}", addr, newest, oldest);
+            let mut current_seq = newest;
+            loop {
+                match state.archive.get_message_by_seq(current_seq) {
+                    Ok(frame) => {
+                        if !send_if_kept(&mut ws_sink, state, addr, &spec, frame, &mut sent, &mut skipped).await {
+                            return;
+                        }
+                    }
+                    Err(ArchiveError::Tombstoned { .. }) => {
+                        state.filtered_msgs.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(ArchiveError::Gap { .. }) => {}
+                    Err(ArchiveError::NotFound | ArchiveError::OutOfRange) => break,
+                    Err(e) => {
+                        error!("  Archive read error for {}: {}", addr, e);
+                        return;
+                    }
+                }
+                if current_seq <= oldest {
+                    break;
+                }
+                current_seq -= 1;
+                tokio::task::yield_now().await;
             }
         }
+        info!("  {} was sent {} messages; its filter skipped {}", addr, sent, skipped);
+        close_at_oldest(&mut ws_sink, addr).await;
+        return;
+    }
+
+    let mut current_seq = loop {
+        if let Some(seq) = cursor.or_else(|| state.archive.min_seq()) {
+            break seq;
+        }
+        info!("  No segments found in archive. Waiting...");
+        state.archive.refresh().ok();
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    };
+    info!("  Streaming filtered messages to {} starting from seq {}", addr, current_seq);
 
+    let mut gap_retried = None;
+    loop {
+        // Seqs every shard has persisted past are final: walk them in one merged pass
+        if let Some(settled) = state.archive.persisted_through().filter(|&settled| settled >= current_seq) {
+            for (seq, at) in state.archive.iter_range(current_seq, settled + 1) {
+                match state.archive.read_at(seq, &at) {
+                    Ok(frame) => {
+                        if !send_if_kept(&mut ws_sink, state, addr, &spec, frame, &mut sent, &mut skipped).await {
+                            info!("  {} was sent {} messages; its filter skipped {}", addr, sent, skipped);
+                            return;
+                        }
+                    }
+                    Err(ArchiveError::Tombstoned { .. }) => {
+                        state.filtered_msgs.fetch_add(1, Ordering::Relaxed);
+                    }
+                    Err(e @ (ArchiveError::Io(_) | ArchiveError::Corrupt { .. })) => {
+                        error!("  Archive read error for {}: {}", addr, e);
+                        return;
+                    }
+                    Err(_) => {}
+                }
+                tokio::task::yield_now().await;
+            }
+            current_seq = settled + 1;
+            continue;
+        }
+        match state.archive.get_message_by_seq(current_seq) {
+            Ok(frame) => {
+                if !send_if_kept(&mut ws_sink, state, addr, &spec, frame, &mut sent, &mut skipped).await {
+                    break;
+                }
+                current_seq += 1;
+            }
+            Err(e) => {
+                if !step_past_miss(state, addr, e, &mut current_seq, &mut gap_retried).await {
+                    break;
+                }
+            }
+        }
         tokio::task::yield_now().await;
     }
+    info!("  {} was sent {} messages; its filter skipped {}", addr, sent, skipped);
+}
 
-    info!("Closing connection");
-    Ok(())
+/// Sends `frame` if `spec` keeps it, counting it as sent or skipped. False if the send failed.
+async fn send_if_kept(
+    ws_sink: &mut WsSink,
+    state: &RelayState,
+    addr: std::net::SocketAddr,
+    spec: &FilterSpec,
+    frame: Vec<u8>,
+    sent: &mut u64,
+    skipped: &mut u64,
+) -> bool {
+    if !spec.matches_frame(&frame).is_some_and(FilterDecision::is_keep) {
+        *skipped += 1;
+        state.skipped_msgs.fetch_add(1, Ordering::Relaxed);
+        return true;
+    }
+    let len = frame.len();
+    if let Err(e) = ws_sink.send(Message::Binary(frame)).await {
+        warn!("  Failed to send message to {}: {}", addr, e);
+        return false;
+    }
+    *sent += 1;
+    state.sent_msgs.fetch_add(1, Ordering::Relaxed);
+    state.sent_bytes.fetch_add(len as u64, Ordering::Relaxed);
+    true
 }
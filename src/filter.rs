@@ -0,0 +1,263 @@
+//! Record-level filtering for focused captures.
+//!
+//! A `FilterSpec` decides per commit whether it is worth archiving. It is applied after
+//! verification, so filtered commits still count towards the verification stats.
+//!
+//! A commit is kept if *any* of its ops matches the collection filter. Commits batch ops
+//! atomically, so by default a multi-op commit that touches a wanted collection is archived
+//! whole, other ops included. With `MixedCommitPolicy::Trim` it is archived as a rebuilt
+//! frame holding only the matching ops and their records instead (see `retain_ops`).
+//!
+//! The relay builds the same spec from a client's `SubscriptionFilter` and forwards only the
+//! frames it keeps (`FilterSpec::matches_frame`).
+
+use crate::parser::core::{parse_full, parse_input, peek_frame, retain_ops, CommitEnvelope, RepoOp};
+use fastbloom::BloomFilter;
+use fxhash::FxHasher;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::hash::Hasher;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterDecision {
+    Keep,
+    /// No op is in an included collection, or every one is excluded
+    ExcludedCollection,
+    /// The DID isn't on the allowlist
+    NotAllowlisted,
+    /// Matched, but left out by the sampling rate
+    Sampled,
+}
+
+impl FilterDecision {
+    pub fn is_keep(self) -> bool {
+        self == FilterDecision::Keep
+    }
+}
+
+/// What to archive of a commit whose ops only partly match the collection filter.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MixedCommitPolicy {
+    /// The frame as it arrived
+    #[default]
+    Whole,
+    /// A copy with only the matching ops and their record blocks
+    Trim,
+}
+
+/// Set of DIDs to keep. The bloom filter answers the common "not listed" case without
+/// touching the exact set.
+pub struct DidAllowlist {
+    bloom: BloomFilter,
+    exact: HashSet<Vec<u8>>,
+}
+
+impl DidAllowlist {
+    pub fn new<I, S>(dids: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let exact: HashSet<Vec<u8>> = dids.into_iter().map(|d| d.as_ref().as_bytes().to_vec()).collect();
+        let mut bloom = BloomFilter::with_false_pos(0.001).expected_items(exact.len().max(1));
+        for did in &exact {
+            bloom.insert(did.as_slice());
+        }
+        Self { bloom, exact }
+    }
+
+    /// One DID per line. Blank lines and `#` comments are skipped; anything else that
+    /// isn't a DID is an error rather than a silently empty capture.
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let mut dids = Vec::new();
+        for (n, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if !line.starts_with("did:") {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: expected a DID, found {:?}", n + 1, line),
+                ));
+            }
+            dids.push(line);
+        }
+        Ok(Self::new(dids))
+    }
+
+    pub fn contains(&self, did: &[u8]) -> bool {
+        self.bloom.contains(did) && self.exact.contains(did)
+    }
+
+    pub fn len(&self) -> usize {
+        self.exact.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.exact.is_empty()
+    }
+}
+
+/// Which commits to archive. The default keeps everything.
+pub struct FilterSpec {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    dids: Option<DidAllowlist>,
+    sample_rate: f64,
+    policy: MixedCommitPolicy,
+}
+
+impl Default for FilterSpec {
+    fn default() -> Self {
+        Self { include: Vec::new(), exclude: Vec::new(), dids: None, sample_rate: 1.0, policy: MixedCommitPolicy::Whole }
+    }
+}
+
+impl FilterSpec {
+    /// Comma-separated collection prefixes, `-` marking an exclusion:
+    /// `app.bsky.feed,-app.bsky.feed.like`. A prefix matches whole NSID segments, so
+    /// `app.bsky.feed.post` doesn't match `app.bsky.feed.postgate`; a trailing `.*` is allowed.
+    pub fn with_collections(mut self, spec: &str) -> Self {
+        for item in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (list, prefix) = match item.strip_prefix('-') {
+                Some(p) => (&mut self.exclude, p),
+                None => (&mut self.include, item),
+            };
+            let prefix = prefix.trim_end_matches('*').trim_end_matches('.');
+            if !prefix.is_empty() {
+                list.push(prefix.to_string());
+            }
+        }
+        self
+    }
+
+    pub fn with_dids(mut self, dids: DidAllowlist) -> Self {
+        self.dids = Some(dids);
+        self
+    }
+
+    /// Keep roughly this fraction of otherwise matching commits (clamped to 0..=1).
+    pub fn with_sample_rate(mut self, rate: f64) -> Self {
+        self.sample_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_policy(mut self, policy: MixedCommitPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// True if the spec can reject anything.
+    pub fn is_active(&self) -> bool {
+        !self.include.is_empty() || !self.exclude.is_empty() || self.dids.is_some() || self.sample_rate < 1.0
+    }
+
+    pub fn matches(&self, envelope: &CommitEnvelope) -> FilterDecision {
+        if let Some(dids) = &self.dids {
+            if !envelope.did.is_some_and(|did| dids.contains(did)) {
+                return FilterDecision::NotAllowlisted;
+            }
+        }
+        if (!self.include.is_empty() || !self.exclude.is_empty())
+            && !envelope.collections().any(|c| self.collection_matches(c))
+        {
+            return FilterDecision::ExcludedCollection;
+        }
+        if self.sample_rate < 1.0 && !self.sampled_in(envelope) {
+            return FilterDecision::Sampled;
+        }
+        FilterDecision::Keep
+    }
+
+    /// Whether `op` is in a collection the spec keeps. True for every op without a collection filter.
+    pub fn op_matches(&self, op: &RepoOp) -> bool {
+        self.collection_matches(op.collection())
+    }
+
+    /// The ops of a kept commit to archive: the matching ones under `MixedCommitPolicy::Trim`,
+    /// all of them otherwise.
+    pub fn kept_ops<'e, 'a>(&'e self, envelope: &'e CommitEnvelope<'a>) -> impl Iterator<Item = &'e RepoOp<'a>> + 'e {
+        let trim = self.policy == MixedCommitPolicy::Trim;
+        envelope.ops.iter().filter(move |op| !trim || self.op_matches(op))
+    }
+
+    /// The frame to archive for a kept commit, if it isn't `envelope.raw` as is: under
+    /// `MixedCommitPolicy::Trim`, a commit with ops that don't match is rebuilt without them.
+    /// None if nothing needs trimming, or the frame can't be rebuilt; it is archived whole then.
+    pub fn trim(&self, envelope: &CommitEnvelope) -> Option<Vec<u8>> {
+        if self.policy != MixedCommitPolicy::Trim || envelope.ops.iter().all(|op| self.op_matches(op)) {
+            return None;
+        }
+        retain_ops(envelope.raw, |op| self.op_matches(op)).ok()
+    }
+
+    /// `matches` for a raw frame. A DID off the allowlist is turned away on the header peek,
+    /// before the ops are parsed. None if the frame doesn't parse.
+    pub fn matches_frame(&self, frame: &[u8]) -> Option<FilterDecision> {
+        let Some(peek) = peek_frame(frame) else {
+            return parse_input(frame).ok().map(|envelope| self.matches(&envelope));
+        };
+        if let Some(dids) = &self.dids {
+            if !peek.did.is_some_and(|did| dids.contains(did)) {
+                return Some(FilterDecision::NotAllowlisted);
+            }
+        }
+        parse_full(peek, frame).ok().map(|envelope| self.matches(&envelope))
+    }
+
+    fn collection_matches(&self, collection: &str) -> bool {
+        let under = |prefix: &String| {
+            collection.strip_prefix(prefix.as_str()).is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+        };
+        (self.include.is_empty() || self.include.iter().any(under)) && !self.exclude.iter().any(under)
+    }
+
+    /// Sampling hashes the commit CID, so a commit seen from a relay and from its PDS
+    /// gets the same answer.
+    fn sampled_in(&self, envelope: &CommitEnvelope) -> bool {
+        let mut h = FxHasher::default();
+        h.write(envelope.cid.unwrap_or(envelope.raw));
+        (h.finish() as f64) < self.sample_rate * u64::MAX as f64
+    }
+}
+
+/// What a relay client subscribes to, sent as a Text frame right after connecting:
+/// `{"filter":{"collections":["app.bsky.feed.post"],"dids":["did:plc:..."]}}`. Either list may
+/// be left out; collections take the `with_collections` syntax, exclusions included.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct SubscriptionFilter {
+    pub collections: Vec<String>,
+    pub dids: Vec<String>,
+}
+
+impl SubscriptionFilter {
+    /// Parses the client's message. InvalidData if it isn't a `filter` object or names
+    /// something that isn't a DID.
+    pub fn from_message(text: &str) -> io::Result<Self> {
+        #[derive(Deserialize)]
+        struct Message {
+            filter: SubscriptionFilter,
+        }
+        let filter = serde_json::from_str::<Message>(text)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+            .filter;
+        if let Some(bad) = filter.dids.iter().find(|d| !d.starts_with("did:")) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("expected a DID, found {:?}", bad)));
+        }
+        Ok(filter)
+    }
+
+    pub fn spec(&self) -> FilterSpec {
+        let spec = FilterSpec::default().with_collections(&self.collections.join(","));
+        if self.dids.is_empty() {
+            return spec;
+        }
+        spec.with_dids(DidAllowlist::new(&self.dids))
+    }
+}
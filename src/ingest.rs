@@ -0,0 +1,786 @@
+//! Firehose connection loop shared by the live consumers.
+//!
+//! `FirehoseConnector` owns the websocket: it connects to the first endpoint of a priority
+//! list, keeps idle connections alive with pings, tracks the last sequence number it has
+//! seen and, with failover enabled, rotates to the next endpoint on repeated errors or
+//! when no frame arrives for too long. Callers see everything through `FirehoseEvent`s.
+//! An `#info` `OutdatedCursor` from the endpoint drops the cursor and reconnects from the
+//! live head, instead of retrying the stale cursor forever. The first frame after that
+//! reconnect is preceded by a `Gap` event naming the seqs that were skipped.
+//!
+//! With `ConnectorConfig::compressed` the endpoints are `sovereign_relay` instances: the
+//! connector performs the relay handshake and unpacks each zstd cluster into one `Frame`
+//! per record. Once it holds the relay's dictionary it offers it back with `have_dict=<hash>`,
+//! so reconnects skip the download. It asks for framing 2 (`framing=2`), where every cluster
+//! names its dictionary and the relay announces a new one before the first cluster using it.
+//!
+//! `ConnectorConfig::max_reconnects` bounds how many times the connector reconnects before
+//! `run` returns, for smoke tests against a local server. `with_connect` replaces the
+//! websocket connect itself, so a test can hand out sockets of its own.
+//!
+//! `PipelineShutdown` stops such a pipeline in two phases: producers are closed first,
+//! then the frames already queued are given a bounded window to reach the verifiers
+//! before the archive is flushed.
+
+use crate::archive::{decode_cluster, dict_hash, MultiShardArchive, DEFAULT_MAX_DECOMPRESSED_CLUSTER_BYTES};
+use crate::monitor::SovereignMonitor;
+use crate::net;
+use crate::parser::core::parse_input_opt;
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{Message, WebSocket};
+use url::Url;
+
+pub const SUBSCRIBE_REPOS_PATH: &str = "/xrpc/com.atproto.sync.subscribeRepos";
+
+/// Relays and PDSes number their events independently, so a cursor only carries over
+/// between endpoints of the same kind.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EndpointKind {
+    Relay,
+    Pds,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Endpoint {
+    pub kind: EndpointKind,
+    /// subscribeRepos URL without a query string
+    pub url: Url,
+}
+
+impl Endpoint {
+    /// Accepts a full URL or a bare host (`wss://` is assumed). An empty path becomes subscribeRepos.
+    pub fn new(kind: EndpointKind, url: &str) -> Option<Self> {
+        let url = url.trim();
+        let mut url = if url.contains("://") {
+            Url::parse(url).ok()?
+        } else {
+            Url::parse(&format!("wss://{}", url)).ok()?
+        };
+        if !matches!(url.scheme(), "ws" | "wss") || url.host_str().is_none() {
+            return None;
+        }
+        if url.path().is_empty() || url.path() == "/" {
+            url.set_path(SUBSCRIBE_REPOS_PATH);
+        }
+        url.set_query(None);
+        Some(Self { kind, url })
+    }
+
+    /// Endpoint for a host listed by address: a URL of any scheme, `host`, `host:port` or
+    /// `[v6]:port`. The result is always `wss://` with the address's port, if it named one
+    /// other than its scheme's default; any path is replaced by subscribeRepos.
+    pub fn from_address(kind: EndpointKind, addr: &str) -> Option<Self> {
+        let addr = addr.trim();
+        // Without a scheme, `host:8443` would parse as scheme `host`
+        let url = if addr.contains("://") {
+            Url::parse(addr).ok()?
+        } else {
+            Url::parse(&format!("wss://{}", addr)).ok()?
+        };
+        // Bracketed for IPv6, so it can take a port
+        let host = url.host_str().filter(|h| !h.is_empty())?;
+        match url.port() {
+            Some(port) => Self::new(kind, &format!("{}:{}", host, port)),
+            None => Self::new(kind, host),
+        }
+    }
+
+    /// Parses a command-line endpoint: `[relay=|pds=]<url or host>`, defaulting to a relay.
+    pub fn parse(spec: &str) -> Option<Self> {
+        if let Some(rest) = spec.strip_prefix("pds=") {
+            Self::new(EndpointKind::Pds, rest)
+        } else {
+            Self::new(EndpointKind::Relay, spec.strip_prefix("relay=").unwrap_or(spec))
+        }
+    }
+
+    /// `host[:port]`, as used for per-host bookkeeping.
+    pub fn host(&self) -> String {
+        let host = self.url.host_str().unwrap_or_default();
+        match self.url.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        }
+    }
+
+    pub fn subscribe_url(&self, cursor: Option<u64>) -> String {
+        net::cursor_url(&self.url, cursor).to_string()
+    }
+
+    /// Relays mirror the same network-wide sequence; every PDS has its own.
+    fn shares_cursor_with(&self, other: &Endpoint) -> bool {
+        self == other || (self.kind == EndpointKind::Relay && other.kind == EndpointKind::Relay)
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            EndpointKind::Relay => "relay",
+            EndpointKind::Pds => "pds",
+        };
+        write!(f, "{} {}", kind, self.url)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ConnectorConfig {
+    /// Rotate through the endpoint list instead of retrying the current one forever
+    pub failover: bool,
+    /// Consecutive connect/read errors on one endpoint before rotating
+    pub max_failures: u32,
+    /// No frame for this long counts as a failure and rotates immediately. `None` disables it.
+    pub stall_timeout: Option<Duration>,
+    /// Socket read timeout; an idle connection gets a ping at this interval
+    pub read_timeout: Duration,
+    /// Pause after a dropped connection
+    pub reconnect_delay: Duration,
+    /// Pause after a failed connect
+    pub connect_error_delay: Duration,
+    /// Endpoints speak the `sovereign_relay` protocol (dictionary handshake, zstd clusters)
+    pub compressed: bool,
+    /// Relay dictionary already on hand. Replaced by whatever a relay sends instead.
+    pub relay_dict: Option<Vec<u8>>,
+    /// Connects after the first one before `run` gives up, failed or not. `None` retries forever.
+    pub max_reconnects: Option<u32>,
+}
+
+impl Default for ConnectorConfig {
+    fn default() -> Self {
+        Self {
+            failover: false,
+            max_failures: 3,
+            stall_timeout: Some(Duration::from_secs(30)),
+            read_timeout: Duration::from_secs(20),
+            reconnect_delay: Duration::from_secs(2),
+            connect_error_delay: Duration::from_secs(5),
+            compressed: false,
+            relay_dict: None,
+            max_reconnects: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DisconnectReason {
+    Error(tungstenite::Error),
+    Stalled(Duration),
+    /// The endpoint sent `#info` `OutdatedCursor`; the connector reconnects from the live head
+    OutdatedCursor,
+    /// The shutdown flag was cleared or the handler asked to stop
+    Shutdown,
+}
+
+impl fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DisconnectReason::Error(e) => write!(f, "{}", e),
+            DisconnectReason::Stalled(d) => write!(f, "no frames for {:?}", d),
+            DisconnectReason::OutdatedCursor => write!(f, "cursor is older than the endpoint keeps"),
+            DisconnectReason::Shutdown => write!(f, "shutdown"),
+        }
+    }
+}
+
+/// Every `Connected` is followed by exactly one `Disconnected`.
+pub enum FirehoseEvent<'a> {
+    Connected { endpoint: &'a Endpoint, cursor: Option<u64> },
+    Frame { endpoint: &'a Endpoint, seq: Option<u64>, data: Vec<u8> },
+    /// An `#info` frame. These aren't passed on as `Frame`s.
+    Info { endpoint: &'a Endpoint, name: &'a str, message: Option<&'a str> },
+    Disconnected { endpoint: &'a Endpoint, reason: &'a DisconnectReason },
+    ConnectFailed { endpoint: &'a Endpoint, error: &'a tungstenite::Error },
+    /// Failover moved to another endpoint; `cursor_reset` means the old cursor was dropped.
+    Switched { from: &'a Endpoint, to: &'a Endpoint, cursor_reset: bool },
+    /// Sent just before the first frame after an `OutdatedCursor` reconnect: the seqs after
+    /// `saved_cursor` and before `resumed_at` were never received.
+    Gap { endpoint: &'a Endpoint, saved_cursor: u64, resumed_at: u64 },
+}
+
+/// What the event handler wants the connector to do next.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Stop,
+    /// Remove the current endpoint from the list (e.g. it answered 401/404)
+    DropEndpoint,
+    /// Retry after this long instead of the configured delay. Only meaningful as the
+    /// answer to `ConnectFailed` or `Disconnected`; elsewhere it's treated as `Continue`.
+    RetryAfter(Duration),
+}
+
+pub use crate::net::backoff_delay;
+
+/// The websocket a `FirehoseConnector` reads from.
+pub type FirehoseSocket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+type ConnectFn = Box<dyn FnMut(&Url, Option<u64>) -> tungstenite::Result<FirehoseSocket> + Send>;
+
+pub struct FirehoseConnector {
+    endpoints: Vec<Endpoint>,
+    current: usize,
+    cursor: Option<u64>,
+    // The cursor an `OutdatedCursor` dropped, until a frame shows where the stream resumed
+    outdated_cursor: Option<u64>,
+    failures: u32,
+    // Connects made so far, against `max_reconnects`
+    connects: u32,
+    config: ConnectorConfig,
+    running: Arc<AtomicBool>,
+    connect: ConnectFn,
+}
+
+impl FirehoseConnector {
+    /// `endpoints` is in priority order. The loop exits once `running` is cleared.
+    pub fn new(endpoints: Vec<Endpoint>, config: ConnectorConfig, running: Arc<AtomicBool>) -> Self {
+        Self {
+            endpoints,
+            current: 0,
+            cursor: None,
+            outdated_cursor: None,
+            failures: 0,
+            connects: 0,
+            config,
+            running,
+            connect: Box::new(net::reconnect_with_cursor),
+        }
+    }
+
+    /// Resume from `cursor` on the first endpoint.
+    pub fn with_cursor(mut self, cursor: Option<u64>) -> Self {
+        self.cursor = cursor;
+        self
+    }
+
+    /// Opens the websocket with `connect` instead of `net::reconnect_with_cursor`. It gets the
+    /// endpoint URL, with the relay query parameters in compressed mode, and the cursor.
+    pub fn with_connect<C>(mut self, connect: C) -> Self
+    where
+        C: FnMut(&Url, Option<u64>) -> tungstenite::Result<FirehoseSocket> + Send + 'static,
+    {
+        self.connect = Box::new(connect);
+        self
+    }
+
+    /// True once `run` has made the last connect `max_reconnects` allows. When that
+    /// connection drops, `run` returns instead of connecting again.
+    pub fn out_of_reconnects(&self) -> bool {
+        self.config.max_reconnects.is_some_and(|max| self.connects > max)
+    }
+
+    /// Last sequence number seen on the current endpoint (or the one it was started with).
+    pub fn cursor(&self) -> Option<u64> {
+        self.cursor
+    }
+
+    pub fn current_endpoint(&self) -> Option<&Endpoint> {
+        self.endpoints.get(self.current)
+    }
+
+    /// Runs until the handler returns `Flow::Stop`, `running` is cleared, no endpoints are
+    /// left or `max_reconnects` is used up.
+    pub fn run<F>(&mut self, mut handler: F)
+    where
+        F: FnMut(FirehoseEvent<'_>) -> Flow,
+    {
+        while self.running.load(Ordering::SeqCst) && !self.endpoints.is_empty() {
+            if self.out_of_reconnects() {
+                tracing::warn!("Giving up on the firehose after {} reconnects", self.connects - 1);
+                return;
+            }
+            self.connects += 1;
+            let endpoint = &self.endpoints[self.current];
+            let mut url = endpoint.url.clone();
+            if self.config.compressed {
+                url.query_pairs_mut().append_pair("framing", &RELAY_FRAMING.to_string());
+                if let Some(dict) = &self.config.relay_dict {
+                    url.query_pairs_mut().append_pair("have_dict", &dict_hash(dict));
+                }
+            }
+            let mut socket = match (self.connect)(&url, self.cursor) {
+                Ok(socket) => socket,
+                Err(error) => {
+                    let delay = match handler(FirehoseEvent::ConnectFailed { endpoint, error: &error }) {
+                        Flow::Stop => return,
+                        Flow::DropEndpoint => {
+                            self.drop_current(&mut handler);
+                            continue;
+                        }
+                        Flow::RetryAfter(delay) => delay,
+                        Flow::Continue => self.config.connect_error_delay,
+                    };
+                    self.pause(delay);
+                    self.record_failure(false, &mut handler);
+                    continue;
+                }
+            };
+            let read_timeout = match self.config.stall_timeout {
+                Some(stall) => self.config.read_timeout.min(stall),
+                None => self.config.read_timeout,
+            };
+            net::set_read_timeout(&mut socket, read_timeout);
+
+            let (mut dict, framing) = if self.config.compressed {
+                match read_relay_handshake(&mut socket, self.config.relay_dict.as_deref()) {
+                    Ok(handshake) => {
+                        self.config.relay_dict = Some(handshake.dict.clone());
+                        (Some(handshake.dict), handshake.framing)
+                    }
+                    Err(e) => {
+                        let error = tungstenite::Error::Io(e);
+                        let delay = match handler(FirehoseEvent::ConnectFailed { endpoint, error: &error }) {
+                            Flow::Stop => return,
+                            Flow::DropEndpoint => {
+                                self.drop_current(&mut handler);
+                                continue;
+                            }
+                            Flow::RetryAfter(delay) => delay,
+                            Flow::Continue => self.config.connect_error_delay,
+                        };
+                        self.pause(delay);
+                        self.record_failure(false, &mut handler);
+                        continue;
+                    }
+                }
+            } else {
+                (None, 1)
+            };
+            // Set by a `dict_change` notice: the next binary frame is that dictionary
+            let mut incoming_dict: Option<String> = None;
+
+            let mut flow = handler(FirehoseEvent::Connected { endpoint, cursor: self.cursor });
+            let mut last_frame = Instant::now();
+            let reason = loop {
+                if matches!(flow, Flow::Stop | Flow::DropEndpoint) || !self.running.load(Ordering::SeqCst) {
+                    break DisconnectReason::Shutdown;
+                }
+                match socket.read() {
+                    Ok(Message::Text(text)) if framing >= 2 => match parse_dict_change(&text) {
+                        Ok(hash) => incoming_dict = hash,
+                        Err(e) => break DisconnectReason::Error(tungstenite::Error::Io(e)),
+                    },
+                    Ok(Message::Binary(data)) if incoming_dict.is_some() => {
+                        last_frame = Instant::now();
+                        if incoming_dict.take().as_deref() != Some(dict_hash(&data).as_str()) {
+                            let e = io::Error::new(io::ErrorKind::InvalidData, "relay dictionary does not match its dict_change hash");
+                            break DisconnectReason::Error(tungstenite::Error::Io(e));
+                        }
+                        tracing::info!("Relay {} switched dictionary to {}", endpoint, &dict_hash(&data)[..8]);
+                        self.config.relay_dict = Some(data.clone());
+                        dict = Some(data);
+                    }
+                    Ok(Message::Binary(data)) if dict.is_some() => {
+                        last_frame = Instant::now();
+                        self.failures = 0;
+                        let cluster = if framing >= 2 {
+                            match decode_relay_frame(&data, dict.as_deref().unwrap_or_default()) {
+                                Ok(cluster) => cluster,
+                                Err(e) => break DisconnectReason::Error(tungstenite::Error::Io(e)),
+                            }
+                        } else {
+                            &data[..]
+                        };
+                        let records = match decode_cluster(cluster, dict.as_deref(), DEFAULT_MAX_DECOMPRESSED_CLUSTER_BYTES) {
+                            Ok(records) => records,
+                            Err(e) => break DisconnectReason::Error(tungstenite::Error::Io(e)),
+                        };
+                        // A cluster holds one DID's records from across a segment, so seqs arrive out
+                        // of order. The relay streamed this cluster for a seq no lower than its
+                        // smallest one, which makes that a resume point that can repeat but never skip.
+                        if let Some(min) = records.iter().map(|(seq, _)| *seq).min() {
+                            self.cursor = Some(min);
+                        }
+                        for (seq, data) in records {
+                            flow = handler(FirehoseEvent::Frame { endpoint, seq: Some(seq), data });
+                            if matches!(flow, Flow::Stop | Flow::DropEndpoint) {
+                                break;
+                            }
+                        }
+                    }
+                    Ok(Message::Binary(data)) => {
+                        last_frame = Instant::now();
+                        self.failures = 0;
+                        let envelope = parse_input_opt(&data);
+                        if let Some(info) = envelope.as_ref().and_then(|e| e.info()) {
+                            flow = handler(FirehoseEvent::Info { endpoint, name: info.name, message: info.message });
+                            if info.is_outdated_cursor() {
+                                tracing::warn!("{} no longer has cursor {:?}; reconnecting from the live head", endpoint, self.cursor);
+                                self.outdated_cursor = self.cursor.take();
+                                break DisconnectReason::OutdatedCursor;
+                            }
+                            continue;
+                        }
+                        let seq = envelope.and_then(|e| e.sequence);
+                        if seq.is_some() {
+                            self.cursor = seq;
+                        }
+                        if let (Some(resumed_at), Some(saved_cursor)) = (seq, self.outdated_cursor) {
+                            self.outdated_cursor = None;
+                            flow = handler(FirehoseEvent::Gap { endpoint, saved_cursor, resumed_at });
+                            if matches!(flow, Flow::Stop | Flow::DropEndpoint) {
+                                continue;
+                            }
+                        }
+                        flow = handler(FirehoseEvent::Frame { endpoint, seq, data });
+                    }
+                    Ok(_) => {}
+                    Err(tungstenite::Error::Io(e))
+                        if e.kind() == std::io::ErrorKind::WouldBlock || e.kind() == std::io::ErrorKind::TimedOut =>
+                    {
+                        let idle = last_frame.elapsed();
+                        if self.config.stall_timeout.is_some_and(|stall| idle >= stall) {
+                            break DisconnectReason::Stalled(idle);
+                        }
+                        // Idle but not stalled: keep the connection alive
+                        if let Err(e) = socket.send(Message::Ping(Vec::new())) {
+                            break DisconnectReason::Error(e);
+                        }
+                    }
+                    Err(e) => break DisconnectReason::Error(e),
+                }
+            };
+            if matches!(reason, DisconnectReason::Shutdown | DisconnectReason::OutdatedCursor) {
+                let _ = socket.close(None);
+            }
+            let after = handler(FirehoseEvent::Disconnected { endpoint, reason: &reason });
+
+            match (flow, after) {
+                (Flow::Stop, _) | (_, Flow::Stop) => return,
+                (Flow::DropEndpoint, _) | (_, Flow::DropEndpoint) => {
+                    self.drop_current(&mut handler);
+                    continue;
+                }
+                _ => {}
+            }
+            if !self.running.load(Ordering::SeqCst) {
+                return;
+            }
+            if matches!(reason, DisconnectReason::OutdatedCursor) {
+                // Not a failure: the endpoint is fine, only the cursor was too old
+                if let Flow::RetryAfter(delay) = after {
+                    self.pause(delay);
+                }
+                continue;
+            }
+            match after {
+                Flow::RetryAfter(delay) => self.pause(delay),
+                _ => self.pause(self.config.reconnect_delay),
+            }
+            let stalled = matches!(reason, DisconnectReason::Stalled(_));
+            self.record_failure(stalled, &mut handler);
+        }
+    }
+
+    fn record_failure<F>(&mut self, immediate: bool, handler: &mut F)
+    where
+        F: FnMut(FirehoseEvent<'_>) -> Flow,
+    {
+        self.failures += 1;
+        if !self.config.failover || self.endpoints.len() < 2 {
+            return;
+        }
+        if immediate || self.failures >= self.config.max_failures.max(1) {
+            let from = self.current;
+            self.current = (self.current + 1) % self.endpoints.len();
+            self.switch_from(&self.endpoints[from].clone(), handler);
+        }
+    }
+
+    fn drop_current<F>(&mut self, handler: &mut F)
+    where
+        F: FnMut(FirehoseEvent<'_>) -> Flow,
+    {
+        let removed = self.endpoints.remove(self.current);
+        tracing::warn!("Dropping firehose endpoint {}", removed);
+        if self.endpoints.is_empty() {
+            return;
+        }
+        if self.current >= self.endpoints.len() {
+            self.current = 0;
+        }
+        self.switch_from(&removed, handler);
+    }
+
+    /// Moves bookkeeping over to `self.current`, resetting the cursor if it means nothing there.
+    fn switch_from<F>(&mut self, from: &Endpoint, handler: &mut F)
+    where
+        F: FnMut(FirehoseEvent<'_>) -> Flow,
+    {
+        self.failures = 0;
+        // The new endpoint's first seq says nothing about what the old one skipped
+        self.outdated_cursor = None;
+        let to = &self.endpoints[self.current];
+        let cursor_reset = self.cursor.is_some() && !from.shares_cursor_with(to);
+        if cursor_reset {
+            tracing::warn!(
+                "Switching {} -> {}: cursor {} belongs to a different sequence, starting from the live head",
+                from, to, self.cursor.unwrap_or_default()
+            );
+            self.cursor = None;
+        } else {
+            tracing::info!("Switching firehose endpoint {} -> {}", from, to);
+        }
+        // Informational only; the returned flow is ignored
+        let _ = handler(FirehoseEvent::Switched { from, to, cursor_reset });
+    }
+
+    /// Sleeps in short slices so a shutdown isn't held up by a long backoff. Returns at once
+    /// if no reconnect will follow.
+    fn pause(&self, total: Duration) {
+        if self.out_of_reconnects() {
+            return;
+        }
+        let deadline = Instant::now() + total;
+        while self.running.load(Ordering::SeqCst) {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            thread::sleep((deadline - now).min(Duration::from_millis(100)));
+        }
+    }
+}
+
+/// Relay framing the connector asks for with `framing=2`. Framing 1 is bare zstd clusters.
+pub const RELAY_FRAMING: u64 = 2;
+/// First byte of a framing-2 cluster frame. A zstd frame starts with 0x28, so the two
+/// framings can't be mistaken for each other.
+pub const RELAY_FRAME_MAGIC: u8 = 0xd7;
+/// Magic, the first 8 bytes of the dictionary's blake3, then the cluster length (u32 LE).
+pub const RELAY_FRAME_HEADER_LEN: usize = 13;
+
+/// First 8 bytes of a dictionary's blake3, as carried by framing-2 cluster frames.
+pub fn relay_dict_prefix(dict: &[u8]) -> [u8; 8] {
+    blake3::hash(dict).as_bytes()[..8].try_into().unwrap()
+}
+
+/// Wraps a compressed cluster in the framing-2 header.
+pub fn encode_relay_frame(dict_prefix: [u8; 8], cluster: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(RELAY_FRAME_HEADER_LEN + cluster.len());
+    frame.push(RELAY_FRAME_MAGIC);
+    frame.extend_from_slice(&dict_prefix);
+    frame.extend_from_slice(&(cluster.len() as u32).to_le_bytes());
+    frame.extend_from_slice(cluster);
+    frame
+}
+
+/// Unwraps a framing-2 cluster frame, checking it was compressed with `dict`.
+pub fn decode_relay_frame<'a>(frame: &'a [u8], dict: &[u8]) -> io::Result<&'a [u8]> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    if frame.len() < RELAY_FRAME_HEADER_LEN || frame[0] != RELAY_FRAME_MAGIC {
+        return Err(invalid("not a framing-2 relay frame".into()));
+    }
+    if frame[1..9] != relay_dict_prefix(dict) {
+        return Err(invalid(format!("cluster compressed with dictionary {}, not the one held", hex::encode(&frame[1..9]))));
+    }
+    let len = u32::from_le_bytes(frame[9..13].try_into().unwrap()) as usize;
+    if frame.len() - RELAY_FRAME_HEADER_LEN != len {
+        return Err(invalid(format!("relay frame says {} cluster bytes, carries {}", len, frame.len() - RELAY_FRAME_HEADER_LEN)));
+    }
+    Ok(&frame[RELAY_FRAME_HEADER_LEN..])
+}
+
+/// Reads a framing-2 control message. `Some(hash)` for `dict_change`; other events are ignored.
+pub fn parse_dict_change(text: &str) -> io::Result<Option<String>> {
+    let msg: serde_json::Value = serde_json::from_str(text)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("relay control message: {}", e)))?;
+    if msg["event"] != "dict_change" {
+        return Ok(None);
+    }
+    match msg["dict_hash"].as_str() {
+        Some(hash) => Ok(Some(hash.to_ascii_lowercase())),
+        None => Err(io::Error::new(io::ErrorKind::InvalidData, "dict_change without a dict_hash")),
+    }
+}
+
+/// What a `sovereign_relay` handshake settled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayHandshake {
+    /// Dictionary the first clusters are compressed with
+    pub dict: Vec<u8>,
+    /// 1 (bare clusters) unless the relay confirmed a `framing=2` request
+    pub framing: u64,
+}
+
+/// Reads the `sovereign_relay` handshake (a JSON description, then the zstd dictionary)
+/// and returns the dictionary its clusters are compressed with.
+///
+/// A relay told `have_dict=<hash>` skips the dictionary and says so with `dict_sent: false`;
+/// `known` must then be that dictionary.
+pub fn read_relay_handshake<S: Read + Write>(socket: &mut WebSocket<S>, known: Option<&[u8]>) -> io::Result<RelayHandshake> {
+    let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+    let mut next = || loop {
+        match socket.read() {
+            Ok(Message::Ping(_) | Message::Pong(_)) => continue,
+            Ok(msg) => return Ok(msg),
+            Err(tungstenite::Error::Io(e)) => return Err(e),
+            Err(e) => return Err(io::Error::other(e)),
+        }
+    };
+
+    let info: serde_json::Value = match next()? {
+        Message::Text(text) => serde_json::from_str(&text).map_err(|e| invalid(format!("relay handshake: {}", e)))?,
+        _ => return Err(invalid("expected the relay's JSON handshake; is this a plain firehose?".into())),
+    };
+    if info["compression"] != "zstd" {
+        return Err(invalid(format!("unsupported relay compression: {}", info["compression"])));
+    }
+    // Relays from before `have_dict` always send it
+    let dict = if info["dict_sent"].as_bool().unwrap_or(true) {
+        match next()? {
+            Message::Binary(dict) => dict,
+            _ => return Err(invalid("expected the relay's dictionary after its handshake".into())),
+        }
+    } else {
+        known.ok_or_else(|| invalid("relay skipped its dictionary but none is held locally".into()))?.to_vec()
+    };
+    if let Some(expected) = info["dict_hash"].as_str() {
+        if dict_hash(&dict) != expected {
+            return Err(invalid("relay dictionary does not match its advertised dict_hash".into()));
+        }
+    }
+    // Relays from before framing 2 don't send the field
+    let framing = info["framing"].as_u64().unwrap_or(1);
+    Ok(RelayHandshake { dict, framing })
+}
+
+/// How long consumers block in `PipelineShutdown::recv` before rechecking for shutdown.
+const RECV_POLL: Duration = Duration::from_millis(100);
+
+/// Outcome of `PipelineShutdown::run`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Frames consumers picked up after producers were closed
+    pub drained: u64,
+    /// Frames still queued when the drain window ran out, discarded unprocessed
+    pub dropped: u64,
+    pub timed_out: bool,
+}
+
+/// Two-phase shutdown for a producer -> channel -> consumer pipeline.
+///
+/// Consumers pull frames with `recv` instead of `Receiver::recv`, which lets them exit
+/// once the queue is drained even while a straggling producer still holds a sender.
+pub struct PipelineShutdown {
+    running: Arc<AtomicBool>,
+    drain_timeout: Duration,
+    producer_grace: Duration,
+    draining: AtomicBool,
+    closed: AtomicBool,
+    drained: AtomicU64,
+}
+
+impl PipelineShutdown {
+    /// `running` is the flag producers watch; queued frames get up to `drain_timeout`.
+    pub fn new(running: Arc<AtomicBool>, drain_timeout: Duration) -> Self {
+        Self {
+            running,
+            drain_timeout,
+            producer_grace: Duration::from_secs(5),
+            draining: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            drained: AtomicU64::new(0),
+        }
+    }
+
+    /// How long to wait for producer threads to notice `running` before draining anyway.
+    pub fn with_producer_grace(mut self, grace: Duration) -> Self {
+        self.producer_grace = grace;
+        self
+    }
+
+    /// Consumer side: the next frame, or None once the pipeline has been drained and closed.
+    pub fn recv<T>(&self, rx: &Receiver<T>) -> Option<T> {
+        loop {
+            match rx.recv_timeout(RECV_POLL) {
+                Ok(frame) => {
+                    if self.draining.load(Ordering::SeqCst) {
+                        self.drained.fetch_add(1, Ordering::Relaxed);
+                    }
+                    return Some(frame);
+                }
+                Err(RecvTimeoutError::Timeout) if !self.closed.load(Ordering::SeqCst) => {}
+                Err(_) => return None,
+            }
+        }
+    }
+
+    /// Runs the shutdown: (1) clears `running` and waits up to the grace period for
+    /// `producers`, (2) waits up to `drain_timeout` for `queue` to empty, discarding what's
+    /// left, then joins `consumers`, (3) flushes `archive` and (4) returns the counts.
+    /// `monitor` shows "DRAINING (n remaining)" during phase 2 and is marked stopped at the end.
+    pub fn run<T>(
+        &self,
+        queue: &Receiver<T>,
+        producers: Vec<JoinHandle<()>>,
+        consumers: Vec<JoinHandle<()>>,
+        archive: Option<&MultiShardArchive>,
+        monitor: Option<&SovereignMonitor>,
+    ) -> ShutdownReport {
+        // 1. Close producers
+        self.running.store(false, Ordering::SeqCst);
+        let grace_end = Instant::now() + self.producer_grace;
+        while producers.iter().any(|h| !h.is_finished()) && Instant::now() < grace_end {
+            thread::sleep(Duration::from_millis(20));
+        }
+        let stragglers = producers.iter().filter(|h| !h.is_finished()).count();
+        if stragglers > 0 {
+            tracing::warn!("{} producer(s) still running after {:?}; their late frames will be dropped", stragglers, self.producer_grace);
+        }
+
+        // 2. Drain
+        let mut report = ShutdownReport::default();
+        if let Some(m) = monitor {
+            m.drain_remaining.store(queue.len() as u64, Ordering::Relaxed);
+            m.draining.store(true, Ordering::Relaxed);
+        }
+        self.draining.store(true, Ordering::SeqCst);
+        let deadline = Instant::now() + self.drain_timeout;
+        loop {
+            let remaining = queue.len();
+            if let Some(m) = monitor {
+                m.drain_remaining.store(remaining as u64, Ordering::Relaxed);
+            }
+            if remaining == 0 {
+                break;
+            }
+            if Instant::now() >= deadline {
+                report.timed_out = true;
+                break;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        while queue.try_recv().is_ok() {
+            report.dropped += 1;
+        }
+        self.closed.store(true, Ordering::SeqCst);
+        for h in consumers {
+            let _ = h.join();
+        }
+        // A straggling producer may have pushed after the sweep
+        while queue.try_recv().is_ok() {
+            report.dropped += 1;
+        }
+        report.drained = self.drained.load(Ordering::Relaxed);
+
+        // 3. Flush writers
+        if let Some(a) = archive {
+            a.shutdown();
+        }
+
+        // 4. Report
+        if let Some(m) = monitor {
+            m.draining.store(false, Ordering::Relaxed);
+            m.stopped.store(true, Ordering::Relaxed);
+        }
+        report
+    }
+}
@@ -8,10 +8,20 @@ pub mod mmap_cache_entry;
 pub mod resolver;
 pub mod parser {
 	pub mod core;
+	pub mod cid_utils;
 	pub mod canonical;
+	pub mod records;
 }
 pub mod verify;
 pub mod mst;
 pub mod archive;
 pub mod pds_ledger;
 pub mod monitor;
+pub mod platform;
+pub mod ingest;
+pub mod net;
+pub mod filter;
+pub mod aggregate;
+pub mod plc;
+#[cfg(feature = "test-fixtures")]
+pub mod testutil;
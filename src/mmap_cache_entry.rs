@@ -81,10 +81,10 @@ pub fn parse_commit_block(bytes: &[u8]) -> ParsedCommit {
         } else {
             // Unknown key, skip value
             // eprintln!("[commit parser] Unknown key: {}", key_str);
-            i = skip_cbor_value(bytes, i).unwrap_or(i+1);
-        // Minimal CBOR value skipper for unknown values
-        fn skip_cbor_value(buf: &[u8], i: usize) -> Option<usize> {
-            if i >= buf.len() { return None; }
+            i = skip_cbor_value(bytes, i, 0).unwrap_or(i+1);
+        // Minimal CBOR value skipper for unknown values (depth-capped like parser::core)
+        fn skip_cbor_value(buf: &[u8], i: usize, depth: usize) -> Option<usize> {
+            if i >= buf.len() || depth > crate::parser::core::MAX_CBOR_DEPTH { return None; }
             let major = buf[i] >> 5;
             let addl = buf[i] & 0x1f;
             let len: usize;
@@ -105,18 +105,18 @@ pub fn parse_commit_block(bytes: &[u8]) -> ParsedCommit {
             }
             match major {
                 0 | 1 | 7 => Some(i+hdr), // int, negint, simple
-                2 | 3 => Some(i+hdr+len), // bytes, text
+                2 | 3 => (i+hdr).checked_add(len), // bytes, text
                 4 => { // array
                     let mut idx = i+hdr;
-                    for _ in 0..len { idx = skip_cbor_value(buf, idx)?; }
+                    for _ in 0..len { idx = skip_cbor_value(buf, idx, depth + 1)?; }
                     Some(idx)
                 },
                 5 => { // map
                     let mut idx = i+hdr;
-                    for _ in 0..len { idx = skip_cbor_value(buf, idx)?; idx = skip_cbor_value(buf, idx)?; }
+                    for _ in 0..len { idx = skip_cbor_value(buf, idx, depth + 1)?; idx = skip_cbor_value(buf, idx, depth + 1)?; }
                     Some(idx)
                 },
-                6 => skip_cbor_value(buf, i+hdr), // tag, skip tag then value
+                6 => skip_cbor_value(buf, i+hdr, depth + 1), // tag, skip tag then value
                 _ => None,
             }
         }
@@ -198,7 +198,7 @@ fn parse_cbor_text_key<'a>(buf: &'a [u8], i: usize) -> (Option<&'a [u8]>, usize)
         } else { return (None, i+8); } },
         _ => return (None, i+1),
     }
-    if i+hdr+len > buf.len() { return (None, i+hdr+len); }
+    if len > buf.len() - (i+hdr) { return (None, buf.len()); }
     (Some(&buf[i+hdr..i+hdr+len]), i+hdr+len)
 }
 
@@ -221,7 +221,7 @@ fn parse_cbor_text<'a>(buf: &'a [u8], i: usize) -> Option<(&'a [u8], usize)> {
         } else { return None; } },
         _ => return None,
     }
-    if i+hdr+len > buf.len() { return None; }
+    if len > buf.len() - (i+hdr) { return None; }
     Some((&buf[i+hdr..i+hdr+len], i+hdr+len))
 }
 
@@ -244,7 +244,7 @@ fn parse_cbor_bytes<'a>(buf: &'a [u8], i: usize) -> Option<(&'a [u8], usize)> {
         } else { return None; } },
         _ => return None,
     }
-    if i+hdr+len > buf.len() { return None; }
+    if len > buf.len() - (i+hdr) { return None; }
     Some((&buf[i+hdr..i+hdr+len], i+hdr+len))
 }
 
@@ -1,133 +1,261 @@
-impl MmapDidCache {
-    /// Atomically insert or update a slot for a DID (valid=1), or tombstone/delete (valid=2).
-    /// For tombstone, pass None for key_type/pubkey. Returns true if written, false if not found.
-    /// SAFETY: Caller must ensure exclusive access to the mmap for mutation.
-    pub fn atomic_update_or_tombstone(&mut self, did: &str, key_type: Option<u8>, pubkey: Option<&[u8;33]>) -> bool {
-        use std::sync::atomic::{fence, Ordering};
-        let mut hasher = Sha256::new();
-        hasher.update(did.as_bytes());
-        let did_hash: [u8; 32] = hasher.finalize().into();
-        let mmap_mut = self.mmap_mut.as_mut().expect("MmapDidCache must be opened with open_mut() for mutation");
-        let mmap_len = mmap_mut.len();
-        let mut slot = (fxhash::hash64(&did_hash) % NUM_SLOTS as u64) as usize;
-        for _ in 0..NUM_SLOTS {
-            let start = slot * SLOT_SIZE;
-            let end = start + SLOT_SIZE;
-            if end > mmap_len {
-                slot = 0;
-                continue;
-            }
-            let entry_bytes = &mut mmap_mut[start..end];
-            let entry_did_hash = &entry_bytes[0..32];
-            let valid = entry_bytes[98];
-            if valid == 0 || entry_did_hash == did_hash {
-                // Write all fields except valid
-                entry_bytes[0..32].copy_from_slice(&did_hash);
-                if let (Some(kt), Some(pk)) = (key_type, pubkey) {
-                    entry_bytes[32] = kt;
-                    entry_bytes[33..66].copy_from_slice(pk);
-                    entry_bytes[66..98].fill(0);
-                    // Release fence before setting valid
-                    fence(Ordering::Release);
-                    entry_bytes[98] = 1; // valid
-                } else {
-                    // Tombstone: zero key_type/pubkey/reserved
-                    entry_bytes[32] = 0;
-                    entry_bytes[33..98].fill(0);
-                    fence(Ordering::Release);
-                    entry_bytes[98] = 2; // tombstone
-                }
-                return true;
-            }
-            slot = (slot + 1) % NUM_SLOTS;
-        }
-        false
-    }
-
-    /// Remove a DID from the cache by clearing its slot (valid=0)
-    pub fn remove_did(&mut self, did: &str) -> bool {
-        use sha2::{Sha256, Digest};
-        let mut hasher = Sha256::new();
-        hasher.update(did.as_bytes());
-        let did_hash: [u8; 32] = hasher.finalize().into();
-        let mmap_mut = self.mmap_mut.as_mut().expect("MmapDidCache must be opened with open_mut() for mutation");
-        let mmap_len = mmap_mut.len();
-        let mut slot = (fxhash::hash64(&did_hash) % NUM_SLOTS as u64) as usize;
-        for _ in 0..NUM_SLOTS {
-            let start = slot * SLOT_SIZE;
-            let end = start + SLOT_SIZE;
-            if end > mmap_len {
-                slot = 0;
-                continue;
-            }
-            let entry_bytes = &mut mmap_mut[start..end];
-            let entry_did_hash = &entry_bytes[0..32];
-            let valid = entry_bytes[98];
-            if valid != 0 && entry_did_hash == did_hash {
-                // DON'T zero the slot - that breaks linear probing chains!
-                // Instead, set valid to 2 (Tombstone).
-                entry_bytes[98] = 2; 
-                return true;
-            }
-            slot = (slot + 1) % NUM_SLOTS;
-        }
-        false
-    }
-}
 use memmap2::{Mmap, MmapMut};
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{fence, AtomicU8, Ordering};
 
 pub struct MmapDidCache {
     mmap: Option<Mmap>,
     mmap_mut: Option<MmapMut>,
+    num_slots: usize,
+    data_offset: usize,
+    // Holds the advisory writer lock for as long as the cache is open for mutation
+    _writer_lock: Option<File>,
 }
 use fxhash;
 use sha2::{Sha256, Digest};
+use crate::platform::{self, Advice};
 // Slot size: 99 bytes (32 DID hash + 1 key type + 33 pubkey + 32 reserved + 1 valid/version)
 const SLOT_SIZE: usize = 99;
-const NUM_SLOTS: usize = 150_000_001;
+/// Slot count of caches built before the header existed (and the default for new ones).
+pub const DEFAULT_NUM_SLOTS: usize = 150_000_001;
+
+// File header: magic(8) + version u32 + slot_size u32 + num_slots u64, zero padded.
+// Legacy files have no header and start directly with slot 0.
+const HEADER_SIZE: usize = 64;
+const MAGIC: &[u8; 8] = b"DIDCACHE";
+const FORMAT_VERSION: u32 = 1;
+
+// The first reserved byte of each slot is its seqlock counter, odd while a writer is changing
+// the slot. Readers retry until they copy the slot with the same even count on both sides.
+// Slots written before the counter existed hold 0 there, which reads as stable.
+const SEQ_OFFSET: usize = 66;
+// Retries before a reader gives up on a slot; only a writer that died mid-write leaves it odd
+const SEQ_RETRIES: usize = 10_000;
+
+/// Takes the exclusive advisory lock (flock / LockFileEx) that marks `file`'s cache as having
+/// a writer. With `wait` false, fails with `WouldBlock` instead of waiting for it.
+fn lock_writer(file: &File, wait: bool) -> io::Result<()> {
+    if wait {
+        return file.lock();
+    }
+    file.try_lock().map_err(|e| match e {
+        TryLockError::WouldBlock => {
+            io::Error::new(io::ErrorKind::WouldBlock, "cache is already open for writing by another process")
+        }
+        TryLockError::Error(e) => e,
+    })
+}
+
+/// The seqlock counter of the slot `entry`.
+fn seq_byte(entry: &mut [u8]) -> &AtomicU8 {
+    // SAFETY: the byte is inside `entry`, and u8 needs no alignment
+    unsafe { AtomicU8::from_ptr(entry.as_mut_ptr().add(SEQ_OFFSET)) }
+}
+
+/// Applies `write` to one slot with its seqlock counter odd, so readers never use a half-written slot.
+/// Writers are already exclusive (`&mut self` and the writer lock), so only readers race with this.
+fn write_slot(entry: &mut [u8], write: impl FnOnce(&mut [u8])) {
+    // A counter left odd by a writer that died mid-write stays odd until this write is done
+    let begin = seq_byte(entry).load(Ordering::Relaxed) | 1;
+    seq_byte(entry).store(begin, Ordering::Relaxed);
+    fence(Ordering::Release);
+    write(entry);
+    seq_byte(entry).store(begin.wrapping_add(1), Ordering::Release);
+}
+
+/// Copies the slot at `start` of `data` under its seqlock. None if it stayed busy through
+/// every retry.
+fn read_slot(data: &[u8], start: usize) -> Option<[u8; SLOT_SIZE]> {
+    // SAFETY: the byte is inside `data` and is only ever loaded through this reference, which
+    // the atomics memory model allows even on a read-only mapping
+    let seq = unsafe { AtomicU8::from_ptr(data.as_ptr().add(start + SEQ_OFFSET) as *mut u8) };
+    for attempt in 0..SEQ_RETRIES {
+        let before = seq.load(Ordering::Acquire);
+        if before & 1 == 0 {
+            let mut slot = [0u8; SLOT_SIZE];
+            slot.copy_from_slice(&data[start..start + SLOT_SIZE]);
+            fence(Ordering::Acquire);
+            if seq.load(Ordering::Relaxed) == before {
+                return Some(slot);
+            }
+        }
+        if attempt < 64 {
+            std::hint::spin_loop();
+        } else {
+            std::thread::yield_now();
+        }
+    }
+    None
+}
+
+fn read_header(data: &[u8]) -> (usize, usize) {
+    if data.len() >= HEADER_SIZE && &data[0..8] == MAGIC {
+        let num_slots = u64::from_le_bytes(data[16..24].try_into().unwrap()) as usize;
+        if num_slots > 0 {
+            return (num_slots, HEADER_SIZE);
+        }
+    }
+    (DEFAULT_NUM_SLOTS, 0)
+}
+
+/// SHA-256 of `did`, the key the cache stores it under.
+pub fn hash_did(did: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(did.as_bytes());
+    hasher.finalize().into()
+}
+
+/// A DID live in both caches of a `diff` with a different key, as `(pubkey, key_type)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyChange {
+    pub did_hash: [u8; 32],
+    pub old: ([u8; 33], u8),
+    pub new: ([u8; 33], u8),
+}
+
+/// What changed from one cache to another, by DID hash (the cache doesn't store DIDs).
+/// Each list is sorted by hash.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CacheDiff {
+    /// Live in the first cache only: gone or tombstoned in the second
+    pub only_in_self: Vec<[u8; 32]>,
+    /// Live in the second cache only
+    pub only_in_other: Vec<[u8; 32]>,
+    pub changed: Vec<KeyChange>,
+}
+
+impl CacheDiff {
+    pub fn is_empty(&self) -> bool {
+        self.only_in_self.is_empty() && self.only_in_other.is_empty() && self.changed.is_empty()
+    }
+
+    /// Names the hashes in this diff that belong to one of `dids`.
+    pub fn label<'a>(&self, dids: impl IntoIterator<Item = &'a str>) -> HashMap<[u8; 32], &'a str> {
+        let mut hashes: HashSet<&[u8; 32]> = self.only_in_self.iter().chain(&self.only_in_other).collect();
+        hashes.extend(self.changed.iter().map(|c| &c.did_hash));
+        dids.into_iter()
+            .map(|did| (hash_did(did), did))
+            .filter(|(hash, _)| hashes.contains(hash))
+            .collect()
+    }
+}
 
 impl MmapDidCache {
     /// Open the cache file for read-only access
-    pub fn open<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<Self> {
-        let file = std::fs::OpenOptions::new().read(true).open(path)?;
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).open(path)?;
         let mmap = unsafe { Mmap::map(&file)? };
-        Ok(MmapDidCache { mmap: Some(mmap), mmap_mut: None })
+        let (num_slots, data_offset) = read_header(&mmap);
+        Ok(MmapDidCache { mmap: Some(mmap), mmap_mut: None, num_slots, data_offset, _writer_lock: None })
+    }
+
+    /// Open the cache file for mutable access, waiting for any other writer to close it first.
+    /// Readers (`open`) never take the lock.
+    pub fn open_mut<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::open_mut_locking(path.as_ref(), true)
+    }
+
+    /// `open_mut` that fails right away, with `ErrorKind::WouldBlock`, if another writer
+    /// (in this or another process) has the file open.
+    pub fn try_open_mut<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::open_mut_locking(path.as_ref(), false)
     }
 
-    /// Open the cache file for mutable access
-    pub fn open_mut<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<Self> {
-        let file = std::fs::OpenOptions::new().read(true).write(true).open(path)?;
+    fn open_mut_locking(path: &Path, wait: bool) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        lock_writer(&file, wait)?;
         let mmap_mut = unsafe { MmapMut::map_mut(&file)? };
-        Ok(MmapDidCache { mmap: None, mmap_mut: Some(mmap_mut) })
+        let (num_slots, data_offset) = read_header(&mmap_mut);
+        Ok(MmapDidCache { mmap: None, mmap_mut: Some(mmap_mut), num_slots, data_offset, _writer_lock: Some(file) })
     }
-    /// Linear probing hash map lookup, matching plc_file_enricher.rs
-    pub fn get(&self, did: &str) -> Option<([u8; 33], u8)> {
-        // 1. Hash the DID to get a 32-byte did_hash
-        let mut hasher = Sha256::new();
-        hasher.update(did.as_bytes());
-        let did_hash: [u8; 32] = hasher.finalize().into();
 
-        // 2. Access the data (from either read-only or mutable mmap)
-        let mmap_data: &[u8] = if let Some(m) = self.mmap.as_ref() {
+    /// Create (or truncate) a cache file with a header recording `num_slots`, opened for mutation.
+    /// Fails with `WouldBlock` rather than truncate a file another writer has open.
+    pub fn create<P: AsRef<Path>>(path: P, num_slots: usize) -> io::Result<Self> {
+        if num_slots == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "num_slots must be > 0"));
+        }
+        // Truncated only once locked
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        lock_writer(&file, false)?;
+        file.set_len(0)?;
+        crate::platform::set_len_sparse(&file, (HEADER_SIZE + SLOT_SIZE * num_slots) as u64)?;
+        let mut mmap_mut = unsafe { MmapMut::map_mut(&file)? };
+        mmap_mut[0..8].copy_from_slice(MAGIC);
+        mmap_mut[8..12].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+        mmap_mut[12..16].copy_from_slice(&(SLOT_SIZE as u32).to_le_bytes());
+        mmap_mut[16..24].copy_from_slice(&(num_slots as u64).to_le_bytes());
+        Ok(MmapDidCache { mmap: None, mmap_mut: Some(mmap_mut), num_slots, data_offset: HEADER_SIZE, _writer_lock: Some(file) })
+    }
+
+    /// Number of hash slots, as recorded in the header (or the legacy default).
+    pub fn num_slots(&self) -> usize {
+        self.num_slots
+    }
+
+    /// Asks the kernel to start reading the whole file in (`madvise(MADV_WILLNEED)`), so the
+    /// first lookups after a start don't each wait on a page fault. Returns straight away;
+    /// the reads happen in the background.
+    pub fn prefetch(&self) -> io::Result<()> {
+        platform::advise(self.mapping(), Advice::WillNeed)
+    }
+
+    /// Turns off readahead around page faults (`madvise(MADV_RANDOM)`). Lookups hash to
+    /// unrelated slots, so pages read ahead of a fault are rarely the next ones wanted.
+    pub fn advise_random(&self) -> io::Result<()> {
+        platform::advise(self.mapping(), Advice::Random)
+    }
+
+    /// Pins the pages holding `slots` in RAM (`mlock`) for as long as the cache stays open.
+    /// The range is clamped to the table; fails with the OS error once `RLIMIT_MEMLOCK`
+    /// won't stretch that far.
+    pub fn lock_slots(&self, slots: Range<usize>) -> io::Result<()> {
+        let end = slots.end.min(self.num_slots);
+        let start = slots.start.min(end);
+        platform::lock_region(&self.data()[start * SLOT_SIZE..end * SLOT_SIZE])
+    }
+
+    fn mapping(&self) -> &[u8] {
+        if let Some(m) = self.mmap.as_ref() {
             m
         } else if let Some(m) = self.mmap_mut.as_ref() {
             m
         } else {
             panic!("MmapDidCache must be opened before use");
-        };
+        }
+    }
 
+    fn data(&self) -> &[u8] {
+        &self.mapping()[self.data_offset..]
+    }
+
+    /// Linear probing hash map lookup, matching plc_file_enricher.rs
+    pub fn get(&self, did: &str) -> Option<([u8; 33], u8)> {
+        // 1. Hash the DID to get a 32-byte did_hash
+        self.get_hashed(&hash_did(did))
+    }
+
+    /// Same as `get`, for callers that already hold the SHA-256 DID hash.
+    pub fn get_hashed(&self, did_hash: &[u8; 32]) -> Option<([u8; 33], u8)> {
+        // 2. Access the data (from either read-only or mutable mmap)
+        let mmap_data = self.data();
         let mmap_len = mmap_data.len();
-        let mut slot = (fxhash::hash64(&did_hash) % NUM_SLOTS as u64) as usize;
+        let num_slots = self.num_slots;
+        let mut slot = (fxhash::hash64(did_hash) % num_slots as u64) as usize;
 
         // 3. Linear probe
-        for _ in 0..NUM_SLOTS {
+        for _ in 0..num_slots {
             let start = slot * SLOT_SIZE;
             let end = start + SLOT_SIZE;
             if end > mmap_len {
                 slot = 0;
                 continue;
             }
-            let entry_bytes = &mmap_data[start..end];
+            // A slot a writer keeps busy can't be trusted, and the probe can't go past it
+            let entry_bytes = read_slot(mmap_data, start)?;
             let entry_did_hash = &entry_bytes[0..32];
             let key_type = entry_bytes[32];
             let valid = entry_bytes[98]; // last byte
@@ -153,8 +281,196 @@ impl MmapDidCache {
                     }
                 }
             }
-            slot = (slot + 1) % NUM_SLOTS;
+            slot = (slot + 1) % num_slots;
         }
         None
     }
+
+    /// Atomically insert or update a slot for a DID (valid=1), or tombstone/delete (valid=2).
+    /// For tombstone, pass None for key_type/pubkey. Returns true if written, false if not found.
+    /// Other processes are kept out by the writer lock `open_mut` takes, and readers see the
+    /// slot either before or after the write, never half of it.
+    pub fn atomic_update_or_tombstone(&mut self, did: &str, key_type: Option<u8>, pubkey: Option<&[u8;33]>) -> bool {
+        let did_hash = hash_did(did);
+        self.update_hashed(&did_hash, key_type, pubkey)
+    }
+
+    /// Same as `atomic_update_or_tombstone`, for callers that already hold the SHA-256 DID hash.
+    pub fn update_hashed(&mut self, did_hash: &[u8; 32], key_type: Option<u8>, pubkey: Option<&[u8;33]>) -> bool {
+        let num_slots = self.num_slots;
+        let data_offset = self.data_offset;
+        let mmap_mut = &mut self.mmap_mut.as_mut().expect("MmapDidCache must be opened with open_mut() for mutation")[data_offset..];
+        let mmap_len = mmap_mut.len();
+        let mut slot = (fxhash::hash64(did_hash) % num_slots as u64) as usize;
+        for _ in 0..num_slots {
+            let start = slot * SLOT_SIZE;
+            let end = start + SLOT_SIZE;
+            if end > mmap_len {
+                slot = 0;
+                continue;
+            }
+            let entry_bytes = &mut mmap_mut[start..end];
+            let entry_did_hash = &entry_bytes[0..32];
+            let valid = entry_bytes[98];
+            if valid == 0 || entry_did_hash == did_hash {
+                write_slot(entry_bytes, |entry_bytes| {
+                    // Write all fields except valid
+                    entry_bytes[0..32].copy_from_slice(did_hash);
+                    if let (Some(kt), Some(pk)) = (key_type, pubkey) {
+                        entry_bytes[32] = kt;
+                        entry_bytes[33..66].copy_from_slice(pk);
+                        entry_bytes[SEQ_OFFSET + 1..98].fill(0);
+                        // Release fence before setting valid
+                        fence(Ordering::Release);
+                        entry_bytes[98] = 1; // valid
+                    } else {
+                        // Tombstone: zero key_type/pubkey/reserved
+                        entry_bytes[32..SEQ_OFFSET].fill(0);
+                        entry_bytes[SEQ_OFFSET + 1..98].fill(0);
+                        fence(Ordering::Release);
+                        entry_bytes[98] = 2; // tombstone
+                    }
+                });
+                return true;
+            }
+            slot = (slot + 1) % num_slots;
+        }
+        false
+    }
+
+    /// Remove a DID from the cache by clearing its slot (valid=0)
+    pub fn remove_did(&mut self, did: &str) -> bool {
+        let did_hash = hash_did(did);
+        let num_slots = self.num_slots;
+        let data_offset = self.data_offset;
+        let mmap_mut = &mut self.mmap_mut.as_mut().expect("MmapDidCache must be opened with open_mut() for mutation")[data_offset..];
+        let mmap_len = mmap_mut.len();
+        let mut slot = (fxhash::hash64(&did_hash) % num_slots as u64) as usize;
+        for _ in 0..num_slots {
+            let start = slot * SLOT_SIZE;
+            let end = start + SLOT_SIZE;
+            if end > mmap_len {
+                slot = 0;
+                continue;
+            }
+            let entry_bytes = &mut mmap_mut[start..end];
+            let entry_did_hash = &entry_bytes[0..32];
+            let valid = entry_bytes[98];
+            if valid != 0 && entry_did_hash == did_hash {
+                // DON'T zero the slot - that breaks linear probing chains!
+                // Instead, set valid to 2 (Tombstone).
+                write_slot(entry_bytes, |entry_bytes| entry_bytes[98] = 2);
+                return true;
+            }
+            slot = (slot + 1) % num_slots;
+        }
+        false
+    }
+
+    /// Slot `did` occupies, live or tombstoned. None if it was never written.
+    pub fn slot_of(&self, did: &str) -> Option<usize> {
+        self.slot_of_hashed(&hash_did(did))
+    }
+
+    /// Same as `slot_of`, for callers that already hold the SHA-256 DID hash.
+    pub fn slot_of_hashed(&self, did_hash: &[u8; 32]) -> Option<usize> {
+        let mmap_data = self.data();
+        let num_slots = self.num_slots;
+        let mut slot = (fxhash::hash64(did_hash) % num_slots as u64) as usize;
+        for _ in 0..num_slots {
+            let start = slot * SLOT_SIZE;
+            let end = start + SLOT_SIZE;
+            if end > mmap_data.len() {
+                slot = 0;
+                continue;
+            }
+            match mmap_data[start + 98] {
+                0 => return None,
+                _ if &mmap_data[start..start + 32] == did_hash => return Some(slot),
+                _ => {}
+            }
+            slot = (slot + 1) % num_slots;
+        }
+        None
+    }
+
+    /// Whether `did_hash` holds a tombstone, as opposed to a live entry or no slot at all.
+    pub fn is_tombstoned_hashed(&self, did_hash: &[u8; 32]) -> bool {
+        self.slot_of_hashed(did_hash)
+            .is_some_and(|slot| self.data()[slot * SLOT_SIZE + 98] == 2)
+    }
+
+    /// Writes every modified page back to the file and waits for it (msync).
+    ///
+    /// Updates are visible to other mappings of the file (e.g. a verifier that opened it
+    /// read-only) as soon as they're made; the release fence only orders them. They survive a
+    /// process crash, but a kernel crash or power loss can drop any update since the last
+    /// flush. A no-op for caches opened read-only.
+    pub fn flush(&self) -> io::Result<()> {
+        match self.mmap_mut.as_ref() {
+            Some(m) => m.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// `flush` for one slot (see `slot_of`), so a single update can be made durable without
+    /// syncing the whole table. Only the pages holding that slot are written.
+    pub fn flush_range(&self, slot: usize) -> io::Result<()> {
+        let Some(m) = self.mmap_mut.as_ref() else { return Ok(()) };
+        let offset = self.data_offset + slot * SLOT_SIZE;
+        if slot >= self.num_slots || offset + SLOT_SIZE > m.len() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "slot out of range"));
+        }
+        m.flush_range(offset, SLOT_SIZE)
+    }
+
+    /// Iterates live entries as (did_hash, key_type, pubkey). Tombstones are skipped.
+    pub fn iter_entries(&self) -> impl Iterator<Item = ([u8; 32], u8, [u8; 33])> + '_ {
+        self.data().chunks_exact(SLOT_SIZE).filter_map(|entry| {
+            if entry[98] == 0 || entry[98] == 2 {
+                return None;
+            }
+            let mut did_hash = [0u8; 32];
+            did_hash.copy_from_slice(&entry[0..32]);
+            let mut pubkey = [0u8; 33];
+            pubkey.copy_from_slice(&entry[33..66]);
+            Some((did_hash, entry[32], pubkey))
+        })
+    }
+
+    /// Compares the live entries of this cache (the older one, say) with `other`'s. Tombstoned
+    /// DIDs count as absent. Reads every slot of both tables.
+    pub fn diff(&self, other: &MmapDidCache) -> CacheDiff {
+        let mut diff = CacheDiff::default();
+        for (did_hash, key_type, pubkey) in self.iter_entries() {
+            match other.get_hashed(&did_hash) {
+                None => diff.only_in_self.push(did_hash),
+                Some(new) if new != (pubkey, key_type) => diff.changed.push(KeyChange { did_hash, old: (pubkey, key_type), new }),
+                Some(_) => {}
+            }
+        }
+        diff.only_in_other = other.iter_entries()
+            .map(|(did_hash, _, _)| did_hash)
+            .filter(|did_hash| self.get_hashed(did_hash).is_none())
+            .collect();
+        diff.only_in_self.sort_unstable();
+        diff.only_in_other.sort_unstable();
+        diff.changed.sort_unstable_by_key(|c| c.did_hash);
+        diff
+    }
+
+    /// Rehashes every live entry into a new cache at `dst` with `new_num_slots` slots.
+    /// Tombstones are dropped, so this doubles as a compaction. Returns the entry count.
+    pub fn resize_into<P: AsRef<Path>>(&self, dst: P, new_num_slots: usize) -> io::Result<u64> {
+        let mut out = MmapDidCache::create(dst, new_num_slots)?;
+        let mut copied = 0u64;
+        for (did_hash, key_type, pubkey) in self.iter_entries() {
+            if !out.update_hashed(&did_hash, Some(key_type), Some(&pubkey)) {
+                return Err(io::Error::other("destination cache is full"));
+            }
+            copied += 1;
+        }
+        out.flush()?;
+        Ok(copied)
+    }
 }
@@ -1,8 +1,35 @@
+use crate::archive::cluster_cache::{ClusterCache, ClusterCacheStats};
+use crate::mmap_did_cache::MmapDidCache;
+use crate::parser::cid_utils::cid_eq;
+use crate::parser::core::parse_input;
+use crate::resolver::resolve_did;
+use crate::verify::{verify_commit_detailed, VerifyOutcome};
 use dashmap::DashMap;
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Mutex;
-use std::time::Instant;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::fs;
+#[cfg(feature = "tui")]
+use std::io::IsTerminal;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+pub mod continuity;
+
+pub use continuity::{ContinuityReport, SeqContinuity, SeqGap, SeqStep};
+
+/// Hosts silent for longer than this are dropped from the per-PDS table.
+pub const PDS_IDLE_EVICT: Duration = Duration::from_secs(600);
+/// Hosts silent for longer than this are flagged in the PDS panel.
+const PDS_IDLE_WARN: Duration = Duration::from_secs(60);
+/// Tap/drop lines shown below the PDS table in interactive mode.
+const LOWER_PANEL_LINES: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ErrorType {
     InvalidSignature,
     MissingKey,
@@ -10,13 +37,262 @@ pub enum ErrorType {
     MalformedCbor,
 }
 
+/// How many distinct failing DIDs are kept per error kind for the end-of-run report.
+pub const FAILURE_SAMPLE_CAP: usize = 100;
+
+/// End-of-run summary, written by `sovereign_ingester --report`.
+#[derive(Debug, Serialize)]
+pub struct MonitorReport {
+    pub uptime_secs: u64,
+    pub total: u64,
+    pub verified: u64,
+    /// Verified but left out of the archive by the ingest filter
+    pub filtered: u64,
+    pub healed: u64,
+    pub invalid_sig: u64,
+    pub missing_key: u64,
+    pub malformed: u64,
+    pub other_failures: u64,
+    pub secp256k1: u64,
+    pub p256: u64,
+    /// Verified only after normalizing a high-S signature (counted in `verified` too)
+    pub high_s: u64,
+    /// The archive's decompressed-cluster cache, when one was attached
+    pub cluster_cache: Option<ClusterCacheStats>,
+    pub lat_gain: LatencySummary,
+    pub verify_time: LatencySummary,
+    pub ingest_time: LatencySummary,
+    pub cursor_gaps: u64,
+    /// Cached keys replaced or tombstoned by the PLC watcher
+    pub plc_rotations: u64,
+    /// Holes and steps back in each PDS's own seqs
+    pub continuity: ContinuityReport,
+    /// Up to `FAILURE_SAMPLE_CAP` DIDs, in first-seen order
+    pub invalid_sig_dids: Vec<String>,
+    pub missing_key_dids: Vec<String>,
+}
+
+/// Leaderboard entries kept in a `MonitorSnapshot`, busiest first.
+pub const SNAPSHOT_LEADERBOARD_CAP: usize = 1000;
+
+/// Cumulative monitor state carried across restarts (`save_snapshot` / `load_snapshot`).
+/// Counters are keyed by field name so snapshots from builds with more or fewer of them
+/// still load. Live gauges (connections, drain progress), histograms and per-PDS rates
+/// start over each run.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MonitorSnapshot {
+    /// Unix seconds
+    pub saved_at: u64,
+    pub counters: BTreeMap<String, u64>,
+    /// (DID, frames), by count descending then DID
+    pub leaderboard: Vec<(String, u64)>,
+    pub invalid_sig_dids: Vec<String>,
+    pub missing_key_dids: Vec<String>,
+}
+
+/// Per-PDS counters, updated for every frame a host delivers.
+pub struct PdsStats {
+    pub messages: u64,
+    pub last_message: Instant,
+    pub cursor: Option<u64>,
+    /// msg/s over the last render interval
+    pub rate: f64,
+    rate_mark: (Instant, u64),
+}
+
+// Histogram buckets: values below 4 get one each, then every power of two is split into
+// four equal buckets, so a bucket is at most a quarter of its lower bound wide
+const HISTOGRAM_SUB_BITS: u32 = 2;
+const HISTOGRAM_SUB_BUCKETS: usize = 1 << HISTOGRAM_SUB_BITS;
+const HISTOGRAM_BUCKETS: usize = (64 - HISTOGRAM_SUB_BITS as usize + 1) * HISTOGRAM_SUB_BUCKETS;
+
+/// Lock-free histogram of nanosecond durations over fixed log-scaled buckets. Recording is
+/// one relaxed atomic add; quantiles are exact to within a bucket (about 25%).
+pub struct Histogram {
+    counts: Box<[AtomicU64]>,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram { counts: (0..HISTOGRAM_BUCKETS).map(|_| AtomicU64::new(0)).collect() }
+    }
+}
+
+impl Histogram {
+    fn bucket_of(ns: u64) -> usize {
+        if ns < HISTOGRAM_SUB_BUCKETS as u64 {
+            return ns as usize;
+        }
+        let exp = 63 - ns.leading_zeros();
+        let sub = (ns >> (exp - HISTOGRAM_SUB_BITS)) as usize & (HISTOGRAM_SUB_BUCKETS - 1);
+        (exp - HISTOGRAM_SUB_BITS + 1) as usize * HISTOGRAM_SUB_BUCKETS + sub
+    }
+
+    /// Largest value that lands in `bucket`.
+    fn bucket_max(bucket: usize) -> u64 {
+        if bucket < HISTOGRAM_SUB_BUCKETS {
+            return bucket as u64;
+        }
+        let shift = (bucket / HISTOGRAM_SUB_BUCKETS) as u32 - 1;
+        let sub = (bucket % HISTOGRAM_SUB_BUCKETS) as u64;
+        // The top bucket's edge shifts out to 0 and wraps to u64::MAX
+        ((HISTOGRAM_SUB_BUCKETS as u64 + sub + 1) << shift).wrapping_sub(1)
+    }
+
+    pub fn record(&self, ns: u64) {
+        self.counts[Self::bucket_of(ns)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_duration(&self, d: Duration) {
+        self.record(d.as_nanos().min(u64::MAX as u128) as u64);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.counts.iter().map(|c| c.load(Ordering::Relaxed)).sum()
+    }
+
+    /// Upper edge of the bucket holding the `q` quantile (0.0..=1.0), so the true value is
+    /// at most this and within a bucket width of it. 0 while empty.
+    pub fn quantile(&self, q: f64) -> u64 {
+        let counts: Vec<u64> = self.counts.iter().map(|c| c.load(Ordering::Relaxed)).collect();
+        let total: u64 = counts.iter().sum();
+        if total == 0 {
+            return 0;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, count) in counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Self::bucket_max(bucket);
+            }
+        }
+        Self::bucket_max(HISTOGRAM_BUCKETS - 1)
+    }
+
+    pub fn summary(&self) -> LatencySummary {
+        LatencySummary { count: self.count(), p50_ns: self.quantile(0.50), p95_ns: self.quantile(0.95), p99_ns: self.quantile(0.99) }
+    }
+}
+
+/// p50/p95/p99 of a `Histogram`, in nanoseconds.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct LatencySummary {
+    pub count: u64,
+    pub p50_ns: u64,
+    pub p95_ns: u64,
+    pub p99_ns: u64,
+}
+
+impl std::fmt::Display for LatencySummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ms = |ns: u64| ns as f64 / 1e6;
+        if self.count == 0 {
+            return write!(f, "-");
+        }
+        write!(f, "{:.2}/{:.2}/{:.2}ms", ms(self.p50_ns), ms(self.p95_ns), ms(self.p99_ns))
+    }
+}
+
+/// Which buffer the interactive dashboard shows below the PDS table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LowerPanel {
+    #[default]
+    Tap,
+    Drops,
+}
+
+/// A key press, independent of the terminal backend reading it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UiKey {
+    Char(char),
+    Enter,
+    Backspace,
+    Esc,
+    CtrlC,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UiAction {
+    /// Nothing visible changed
+    None,
+    Redraw,
+    /// Run the same graceful shutdown as Ctrl-C
+    Quit,
+}
+
+/// State of the interactive dashboard, driven by key presses.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UiState {
+    /// An input thread owns the terminal; off means the plain, render-only dashboard
+    pub interactive: bool,
+    /// Stop redrawing, so a burst can be read before it scrolls away
+    pub paused: bool,
+    pub panel: LowerPanel,
+    /// Case-insensitive substring applied to the leaderboard (DID or handle) and tap/drop lines
+    pub filter: Option<String>,
+    /// Text typed at the filter prompt; `Some` while the prompt is open
+    pub prompt: Option<String>,
+}
+
+impl UiState {
+    pub fn handle_key(&mut self, key: UiKey) -> UiAction {
+        if key == UiKey::CtrlC {
+            return UiAction::Quit;
+        }
+        // While the prompt is open every character is input, including 'q'
+        if let Some(prompt) = &mut self.prompt {
+            match key {
+                UiKey::Char(c) => prompt.push(c),
+                UiKey::Backspace => {
+                    prompt.pop();
+                }
+                UiKey::Enter => {
+                    let text = prompt.trim().to_string();
+                    self.filter = (!text.is_empty()).then_some(text);
+                    self.prompt = None;
+                }
+                UiKey::Esc => self.prompt = None,
+                UiKey::CtrlC => unreachable!(),
+            }
+            return UiAction::Redraw;
+        }
+        match key {
+            UiKey::Char('p') | UiKey::Char('P') => self.paused = !self.paused,
+            UiKey::Char('d') | UiKey::Char('D') => {
+                self.panel = match self.panel {
+                    LowerPanel::Tap => LowerPanel::Drops,
+                    LowerPanel::Drops => LowerPanel::Tap,
+                }
+            }
+            UiKey::Char('f') | UiKey::Char('F') => self.prompt = Some(self.filter.clone().unwrap_or_default()),
+            UiKey::Char('q') | UiKey::Char('Q') => return UiAction::Quit,
+            _ => return UiAction::None,
+        }
+        UiAction::Redraw
+    }
+
+    /// Whether `text` passes the current filter.
+    pub fn matches(&self, text: &str) -> bool {
+        match &self.filter {
+            Some(filter) => text.to_lowercase().contains(&filter.to_lowercase()),
+            None => true,
+        }
+    }
+}
+
 pub struct SovereignMonitor {
     pub total: AtomicU64,
     pub verified: AtomicU64,
+    pub filtered: AtomicU64,
     pub healed: AtomicU64,
     pub failed_sig: AtomicU64,
     pub failed_missing: AtomicU64,
+    pub failed_malformed: AtomicU64,
     pub failed_other: AtomicU64,
+    /// Spec violations: commits that verified only after high-S normalization
+    pub high_s: AtomicU64,
     
     // Ghost Hunter Specifics
     pub ghost_hunter_loops: AtomicU64,
@@ -24,10 +300,20 @@ pub struct SovereignMonitor {
     pub relay_wins: AtomicU64,
     pub mesh_wins: AtomicU64,
     pub total_lat_gain_ms: AtomicU64,
+    /// How far ahead of the relay the mesh delivered each frame it won
+    pub lat_gain: Histogram,
+
+    // Per-frame timings
+    pub verify_time: Histogram,
+    pub ingest_time: Histogram,
 
     // Networking
     pub active_conns: AtomicU64,
     pub conn_errors: AtomicU64,
+    /// Resumes where the host had pruned past our cursor (`#info` `OutdatedCursor`)
+    pub cursor_gaps: AtomicU64,
+    /// Keys the PLC watcher replaced or tombstoned (see `plc::RotationWatcher`)
+    pub plc_rotations: AtomicU64,
     
     // Key Mix
     pub k256_count: AtomicU64,
@@ -36,10 +322,33 @@ pub struct SovereignMonitor {
     // Leaderboard (DID -> Count)
     pub leaderboard: DashMap<String, u64>,
     pub handle_cache: DashMap<String, String>,
+
+    // Per-PDS throughput (host -> stats)
+    pub pds_stats: DashMap<String, PdsStats>,
+    // Per-PDS seq gaps, persisted by the ingester next to its cursors
+    pub continuity: SeqContinuity,
     
     // Recent Bursts for Tap
     pub tap_buffer: Mutex<Vec<String>>,
     pub drop_buffer: Mutex<Vec<String>>,
+    pub rotation_buffer: Mutex<Vec<String>>,
+
+    // Sample of failing DIDs for the audit report
+    pub invalid_sig_dids: Mutex<Vec<String>>,
+    pub missing_key_dids: Mutex<Vec<String>>,
+
+    // Archive read cache, shown next to the error counters (see `attach_cluster_cache`)
+    pub cluster_cache: Mutex<Option<Arc<ClusterCache>>>,
+
+    // Shutdown progress, driven by `ingest::PipelineShutdown`
+    pub draining: AtomicBool,
+    pub drain_remaining: AtomicU64,
+    pub stopped: AtomicBool,
+
+    // Interactive dashboard (see `start_interactive`)
+    pub ui: Mutex<UiState>,
+    ui_dirty: AtomicBool,
+    input_thread: Mutex<Option<JoinHandle<()>>>,
 
     pub start_time: Instant,
 }
@@ -49,26 +358,46 @@ impl SovereignMonitor {
         Self {
             total: AtomicU64::new(0),
             verified: AtomicU64::new(0),
+            filtered: AtomicU64::new(0),
             healed: AtomicU64::new(0),
             failed_sig: AtomicU64::new(0),
             failed_missing: AtomicU64::new(0),
+            failed_malformed: AtomicU64::new(0),
             failed_other: AtomicU64::new(0),
+            high_s: AtomicU64::new(0),
             
             ghost_hunter_loops: AtomicU64::new(0),
             dropped_by_relay: AtomicU64::new(0),
             relay_wins: AtomicU64::new(0),
             mesh_wins: AtomicU64::new(0),
             total_lat_gain_ms: AtomicU64::new(0),
+            lat_gain: Histogram::default(),
+            verify_time: Histogram::default(),
+            ingest_time: Histogram::default(),
 
             active_conns: AtomicU64::new(0),
             conn_errors: AtomicU64::new(0),
+            cursor_gaps: AtomicU64::new(0),
+            plc_rotations: AtomicU64::new(0),
 
             k256_count: AtomicU64::new(0),
             p256_count: AtomicU64::new(0),
             leaderboard: DashMap::with_capacity(10000),
             handle_cache: DashMap::with_capacity(1000),
+            pds_stats: DashMap::new(),
+            continuity: SeqContinuity::new(),
             tap_buffer: Mutex::new(Vec::with_capacity(100)),
             drop_buffer: Mutex::new(Vec::with_capacity(100)),
+            rotation_buffer: Mutex::new(Vec::with_capacity(100)),
+            invalid_sig_dids: Mutex::new(Vec::new()),
+            missing_key_dids: Mutex::new(Vec::new()),
+            cluster_cache: Mutex::new(None),
+            draining: AtomicBool::new(false),
+            drain_remaining: AtomicU64::new(0),
+            stopped: AtomicBool::new(false),
+            ui: Mutex::new(UiState::default()),
+            ui_dirty: AtomicBool::new(false),
+            input_thread: Mutex::new(None),
             start_time: Instant::now(),
         }
     }
@@ -85,6 +414,14 @@ impl SovereignMonitor {
         buf.push(msg);
     }
 
+    /// Counts a key rotation and keeps `line` among the recent ones.
+    pub fn record_rotation(&self, line: String) {
+        self.plc_rotations.fetch_add(1, Ordering::Relaxed);
+        let mut buf = self.rotation_buffer.lock().unwrap();
+        if buf.len() >= 50 { buf.remove(0); }
+        buf.push(line);
+    }
+
     pub fn record_event(&self, did: &str, success: bool, error: Option<ErrorType>, key_type: Option<u8>) {
         self.total.fetch_add(1, Ordering::Relaxed);
         
@@ -102,22 +439,249 @@ impl SovereignMonitor {
             }
         } else {
             match error {
-                Some(ErrorType::InvalidSignature) => { self.failed_sig.fetch_add(1, Ordering::Relaxed); },
-                Some(ErrorType::MissingKey) => { self.failed_missing.fetch_add(1, Ordering::Relaxed); },
+                Some(ErrorType::InvalidSignature) => {
+                    self.failed_sig.fetch_add(1, Ordering::Relaxed);
+                    Self::sample_did(&self.invalid_sig_dids, did);
+                },
+                Some(ErrorType::MissingKey) => {
+                    self.failed_missing.fetch_add(1, Ordering::Relaxed);
+                    Self::sample_did(&self.missing_key_dids, did);
+                },
+                Some(ErrorType::MalformedCbor) => { self.failed_malformed.fetch_add(1, Ordering::Relaxed); },
                 _ => { self.failed_other.fetch_add(1, Ordering::Relaxed); },
             };
         }
     }
 
+    fn sample_did(sample: &Mutex<Vec<String>>, did: &str) {
+        let mut dids = sample.lock().unwrap();
+        if dids.len() < FAILURE_SAMPLE_CAP && !dids.iter().any(|d| d == did) {
+            dids.push(did.to_string());
+        }
+    }
+
+    /// The counters a `MonitorSnapshot` carries over, by name.
+    fn cumulative_counters(&self) -> [(&'static str, &AtomicU64); 19] {
+        [
+            ("total", &self.total),
+            ("verified", &self.verified),
+            ("filtered", &self.filtered),
+            ("healed", &self.healed),
+            ("failed_sig", &self.failed_sig),
+            ("failed_missing", &self.failed_missing),
+            ("failed_malformed", &self.failed_malformed),
+            ("failed_other", &self.failed_other),
+            ("high_s", &self.high_s),
+            ("ghost_hunter_loops", &self.ghost_hunter_loops),
+            ("dropped_by_relay", &self.dropped_by_relay),
+            ("relay_wins", &self.relay_wins),
+            ("mesh_wins", &self.mesh_wins),
+            ("total_lat_gain_ms", &self.total_lat_gain_ms),
+            ("conn_errors", &self.conn_errors),
+            ("cursor_gaps", &self.cursor_gaps),
+            ("plc_rotations", &self.plc_rotations),
+            ("k256_count", &self.k256_count),
+            ("p256_count", &self.p256_count),
+        ]
+    }
+
+    /// Cumulative counters, the top `SNAPSHOT_LEADERBOARD_CAP` of the leaderboard and the
+    /// failing-DID samples. Each counter is read on its own, so take it once frames have
+    /// stopped (as the ingester does after draining) for totals that agree with each other.
+    pub fn snapshot(&self) -> MonitorSnapshot {
+        let mut leaderboard: Vec<(String, u64)> = self.leaderboard.iter().map(|e| (e.key().clone(), *e.value())).collect();
+        leaderboard.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        leaderboard.truncate(SNAPSHOT_LEADERBOARD_CAP);
+        MonitorSnapshot {
+            saved_at: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+            counters: self.cumulative_counters().iter().map(|(name, c)| (name.to_string(), c.load(Ordering::Relaxed))).collect(),
+            leaderboard,
+            invalid_sig_dids: self.invalid_sig_dids.lock().unwrap().clone(),
+            missing_key_dids: self.missing_key_dids.lock().unwrap().clone(),
+        }
+    }
+
+    /// Replaces the cumulative state with `snapshot`'s. Counters it doesn't name are left alone.
+    pub fn restore(&self, snapshot: &MonitorSnapshot) {
+        for (name, counter) in self.cumulative_counters() {
+            if let Some(value) = snapshot.counters.get(name) {
+                counter.store(*value, Ordering::Relaxed);
+            }
+        }
+        self.leaderboard.clear();
+        for (did, count) in &snapshot.leaderboard {
+            self.leaderboard.insert(did.clone(), *count);
+        }
+        *self.invalid_sig_dids.lock().unwrap() = snapshot.invalid_sig_dids.iter().take(FAILURE_SAMPLE_CAP).cloned().collect();
+        *self.missing_key_dids.lock().unwrap() = snapshot.missing_key_dids.iter().take(FAILURE_SAMPLE_CAP).cloned().collect();
+    }
+
+    /// Writes `snapshot()` to `path` as JSON, through a temporary file so a crash mid-write
+    /// leaves the previous snapshot in place.
+    pub fn save_snapshot(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let json = serde_json::to_vec_pretty(&self.snapshot()).map_err(io::Error::other)?;
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, path)
+    }
+
+    /// `restore`s the snapshot at `path`. NotFound if there is none, InvalidData if it doesn't parse.
+    pub fn load_snapshot(&self, path: impl AsRef<Path>) -> io::Result<MonitorSnapshot> {
+        let snapshot: MonitorSnapshot = serde_json::from_slice(&fs::read(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.restore(&snapshot);
+        Ok(snapshot)
+    }
+
+    /// Snapshot of the counters and failing-DID samples.
+    pub fn report(&self) -> MonitorReport {
+        MonitorReport {
+            uptime_secs: self.start_time.elapsed().as_secs(),
+            total: self.total.load(Ordering::Relaxed),
+            verified: self.verified.load(Ordering::Relaxed),
+            filtered: self.filtered.load(Ordering::Relaxed),
+            healed: self.healed.load(Ordering::Relaxed),
+            invalid_sig: self.failed_sig.load(Ordering::Relaxed),
+            missing_key: self.failed_missing.load(Ordering::Relaxed),
+            malformed: self.failed_malformed.load(Ordering::Relaxed),
+            other_failures: self.failed_other.load(Ordering::Relaxed),
+            secp256k1: self.k256_count.load(Ordering::Relaxed),
+            p256: self.p256_count.load(Ordering::Relaxed),
+            high_s: self.high_s.load(Ordering::Relaxed),
+            cluster_cache: self.cluster_cache_stats(),
+            lat_gain: self.lat_gain.summary(),
+            verify_time: self.verify_time.summary(),
+            ingest_time: self.ingest_time.summary(),
+            cursor_gaps: self.cursor_gaps.load(Ordering::Relaxed),
+            plc_rotations: self.plc_rotations.load(Ordering::Relaxed),
+            continuity: self.continuity.report(),
+            invalid_sig_dids: self.invalid_sig_dids.lock().unwrap().clone(),
+            missing_key_dids: self.missing_key_dids.lock().unwrap().clone(),
+        }
+    }
+
+    /// Reports `cache`'s hit, miss and eviction counters on the dashboard and in `report`.
+    pub fn attach_cluster_cache(&self, cache: Arc<ClusterCache>) {
+        *self.cluster_cache.lock().unwrap() = Some(cache);
+    }
+
+    pub fn cluster_cache_stats(&self) -> Option<ClusterCacheStats> {
+        self.cluster_cache.lock().unwrap().as_ref().map(|c| c.stats())
+    }
+
+    /// Counts a commit that verified with a high-S signature, on top of its `record_event`.
+    pub fn record_high_s(&self) {
+        self.high_s.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a frame that could not be parsed at all (no DID to attribute it to).
+    pub fn record_malformed(&self) {
+        self.total.fetch_add(1, Ordering::Relaxed);
+        self.failed_malformed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a frame from `host`, advancing its cursor when the frame carried a seq.
+    pub fn record_pds_message(&self, host: &str, cursor: Option<u64>) {
+        let now = Instant::now();
+        let bump = |stats: &mut PdsStats| {
+            stats.messages += 1;
+            stats.last_message = now;
+            if cursor.is_some() {
+                stats.cursor = cursor;
+            }
+        };
+        // Known hosts skip allocating the key; a new one goes through `entry`, so two
+        // connections seeing it at once both count into the same stats
+        if let Some(mut stats) = self.pds_stats.get_mut(host) {
+            bump(&mut stats);
+            return;
+        }
+        let mut stats = self.pds_stats.entry(host.to_string()).or_insert(PdsStats {
+            messages: 0,
+            last_message: now,
+            cursor: None,
+            rate: 0.0,
+            rate_mark: (now, 0),
+        });
+        bump(&mut stats);
+    }
+
+    /// Recomputes each host's rate since the previous call and drops hosts idle for
+    /// longer than `max_idle`.
+    pub fn refresh_pds_stats(&self, max_idle: Duration) {
+        let now = Instant::now();
+        self.pds_stats.retain(|_, stats| now.duration_since(stats.last_message) <= max_idle);
+        for mut entry in self.pds_stats.iter_mut() {
+            let stats = entry.value_mut();
+            let (mark_time, mark_count) = stats.rate_mark;
+            let elapsed = now.duration_since(mark_time).as_secs_f64();
+            if elapsed > 0.0 {
+                stats.rate = (stats.messages - mark_count) as f64 / elapsed;
+            }
+            stats.rate_mark = (now, stats.messages);
+        }
+    }
+
+    /// Applies a key press from the input thread; any visible change forces the next render.
+    pub fn handle_key(&self, key: UiKey) -> UiAction {
+        let action = self.ui.lock().unwrap().handle_key(key);
+        if action != UiAction::None {
+            self.ui_dirty.store(true, Ordering::Relaxed);
+        }
+        action
+    }
+
+    /// Switches `render` to the interactive dashboard, if built with the `tui` feature and stdin
+    /// is a terminal. A small input thread then feeds key presses to `handle_key` and calls
+    /// `on_quit` on 'q' (or Ctrl-C, which raw mode no longer turns into a signal).
+    /// Returns false, leaving the plain dashboard in place, otherwise.
+    pub fn start_interactive<F: Fn() + Send + 'static>(self: &Arc<Self>, on_quit: F) -> bool {
+        #[cfg(feature = "tui")]
+        if io::stdin().is_terminal() {
+            return match tui::spawn_input(Arc::clone(self), Box::new(on_quit)) {
+                Ok(handle) => {
+                    *self.input_thread.lock().unwrap() = Some(handle);
+                    true
+                }
+                Err(e) => {
+                    eprintln!("[Monitor] Interactive mode unavailable: {}", e);
+                    false
+                }
+            };
+        }
+        let _ = on_quit;
+        false
+    }
+
+    /// Waits for the input thread to restore the terminal. Call once `stopped` is set.
+    pub fn stop_interactive(&self) {
+        if let Some(handle) = self.input_thread.lock().unwrap().take() {
+            let _ = handle.join();
+        }
+    }
+
     pub fn render(&self, queue_len: usize, rate: f64) {
+        let ui = self.ui.lock().unwrap().clone();
+        let dirty = self.ui_dirty.swap(false, Ordering::Relaxed);
+        if ui.paused && !dirty {
+            return;
+        }
+        let mut out = String::with_capacity(8 * 1024);
+        // Writing to a String can't fail
+        macro_rules! emit {
+            ($($arg:tt)*) => {{ let _ = writeln!(out, $($arg)*); }};
+        }
         // Clear screen and move cursor to top-left
-        print!("\x1B[2J\x1B[H");
+        out.push_str("\x1B[2J\x1B[H");
 
         let total = self.total.load(Ordering::Relaxed);
         let verified = self.verified.load(Ordering::Relaxed);
         let f_sig = self.failed_sig.load(Ordering::Relaxed);
         let f_miss = self.failed_missing.load(Ordering::Relaxed);
+        let f_cbor = self.failed_malformed.load(Ordering::Relaxed);
         let healed = self.healed.load(Ordering::Relaxed);
+        let filtered = self.filtered.load(Ordering::Relaxed);
         let k256 = self.k256_count.load(Ordering::Relaxed);
         let p256 = self.p256_count.load(Ordering::Relaxed);
         let active = self.active_conns.load(Ordering::Relaxed);
@@ -127,15 +691,21 @@ impl SovereignMonitor {
         let p_pct = if verified > 0 { (p256 as f64 / verified as f64) * 100.0 } else { 0.0 };
 
         // 1. Header
-        println!("\x1B[1;36m╔═══════════════════════════════════════════════════════════════════════╗\x1B[0m");
-        println!("\x1B[1;36m║           SOVEREIGN TRUTH ENGINE - LIVE FIREHOSE MONITOR            ║\x1B[0m");
-        println!("\x1B[1;36m╚═══════════════════════════════════════════════════════════════════════╝\x1B[0m");
+        emit!("\x1B[1;36m╔═══════════════════════════════════════════════════════════════════════╗\x1B[0m");
+        emit!("\x1B[1;36m║           SOVEREIGN TRUTH ENGINE - LIVE FIREHOSE MONITOR            ║\x1B[0m");
+        emit!("\x1B[1;36m╚═══════════════════════════════════════════════════════════════════════╝\x1B[0m");
+        if self.draining.load(Ordering::Relaxed) {
+            emit!("\x1B[1;33mDRAINING ({} remaining)\x1B[0m", self.drain_remaining.load(Ordering::Relaxed));
+        }
+        if ui.paused {
+            emit!("\x1B[1;33mPAUSED\x1B[0m (press p to resume)");
+        }
 
         // 2. Throughput & Connections
         let queue_bar = self.make_bar(queue_len, 5000); // Assume 5k is 'Full'
-        println!("\x1B[1;37mRate:\x1B[0m \x1B[1;32m{:.2} msg/s\x1B[0m | \x1B[1;37mTotal:\x1B[0m {} | \x1B[1;37mHealed:\x1B[0m {}", rate, total, healed);
-        println!("\x1B[1;37mConns:\x1B[0m \x1B[1;32m{}\x1B[0m | \x1B[1;37mConn Errs:\x1B[0m \x1B[1;31m{}\x1B[0m | \x1B[1;37mQueue Saturation:\x1B[0m [{}] {:5} msgs", active, c_errs, queue_bar, queue_len);
-        println!();
+        emit!("\x1B[1;37mRate:\x1B[0m \x1B[1;32m{:.2} msg/s\x1B[0m | \x1B[1;37mTotal:\x1B[0m {} | \x1B[1;37mHealed:\x1B[0m {} | \x1B[1;37mFiltered:\x1B[0m {}", rate, total, healed, filtered);
+        emit!("\x1B[1;37mConns:\x1B[0m \x1B[1;32m{}\x1B[0m | \x1B[1;37mConn Errs:\x1B[0m \x1B[1;31m{}\x1B[0m | \x1B[1;37mCursor Gaps:\x1B[0m \x1B[1;33m{}\x1B[0m | \x1B[1;37mQueue Saturation:\x1B[0m [{}] {:5} msgs", active, c_errs, self.cursor_gaps.load(Ordering::Relaxed), queue_bar, queue_len);
+        emit!();
 
         // 3. Ghost Hunter Status (Mesh vs Relay)
         let m_wins = self.mesh_wins.load(Ordering::Relaxed);
@@ -144,29 +714,110 @@ impl SovereignMonitor {
         let win_pct = if total_wins > 0 { (m_wins as f64 / total_wins as f64) * 100.0 } else { 0.0 };
         let avg_gain = if m_wins > 0 { self.total_lat_gain_ms.load(Ordering::Relaxed) as f64 / m_wins as f64 } else { 0.0 };
 
-        println!("\x1B[1;37m[ Ghost Hunter Status ]\x1B[0m                 \x1B[1;37m[ Network Efficiency ]\x1B[0m");
-        println!("  Mesh Win Rate: \x1B[1;32m{:>3.1}%\x1B[0m ({:>8})            Relay Wins: \x1B[1;31m{}\x1B[0m", win_pct, m_wins, r_wins);
-        println!("  Avg Mesh Gain: \x1B[1;32m{:.1}ms\x1B[0m                    Relay Drops: \x1B[1;31m{}\x1B[0m", avg_gain, self.dropped_by_relay.load(Ordering::Relaxed));
-        println!();
+        emit!("\x1B[1;37m[ Ghost Hunter Status ]\x1B[0m                 \x1B[1;37m[ Network Efficiency ]\x1B[0m");
+        emit!("  Mesh Win Rate: \x1B[1;32m{:>3.1}%\x1B[0m ({:>8})            Relay Wins: \x1B[1;31m{}\x1B[0m", win_pct, m_wins, r_wins);
+        emit!("  Avg Mesh Gain: \x1B[1;32m{:.1}ms\x1B[0m                    Relay Drops: \x1B[1;31m{}\x1B[0m", avg_gain, self.dropped_by_relay.load(Ordering::Relaxed));
+        emit!(
+            "  p50/p95/p99  Gain: {}  Verify: {}  Ingest: {}",
+            self.lat_gain.summary(), self.verify_time.summary(), self.ingest_time.summary()
+        );
+        emit!();
 
         // 4. Stats Grid
-        println!("\x1B[1;37m[ Crypto Breakdown ]\x1B[0m                     \x1B[1;37m[ Error Diagnostics ]\x1B[0m");
-        println!("  Secp256k1: \x1B[1;34m{:>3.1}%\x1B[0m ({:>8})            Invalid Sig: \x1B[1;31m{}\x1B[0m", k_pct, k256, f_sig);
-        println!("  P-256:     \x1B[1;35m{:>3.1}%\x1B[0m ({:>8})            Missing Key: \x1B[1;33m{}\x1B[0m", p_pct, p256, f_miss);
-        println!();
+        emit!("\x1B[1;37m[ Crypto Breakdown ]\x1B[0m                     \x1B[1;37m[ Error Diagnostics ]\x1B[0m");
+        emit!("  Secp256k1: \x1B[1;34m{:>3.1}%\x1B[0m ({:>8})            Invalid Sig: \x1B[1;31m{}\x1B[0m", k_pct, k256, f_sig);
+        emit!("  P-256:     \x1B[1;35m{:>3.1}%\x1B[0m ({:>8})            Missing Key: \x1B[1;33m{}\x1B[0m", p_pct, p256, f_miss);
+        emit!("                                           Malformed:   \x1B[1;31m{}\x1B[0m", f_cbor);
+        emit!("                                           High-S:      \x1B[1;33m{}\x1B[0m", self.high_s.load(Ordering::Relaxed));
+        emit!("                                           Rotations:   \x1B[1;36m{}\x1B[0m", self.plc_rotations.load(Ordering::Relaxed));
+        if let Some(cache) = self.cluster_cache_stats() {
+            emit!("  Cluster Cache: \x1B[1;32m{:>3.1}%\x1B[0m hits ({} MB)       Evictions:   {}", cache.hit_rate() * 100.0, cache.bytes / (1024 * 1024), cache.evictions);
+        }
+        emit!();
 
         // 4. Leaderboard
-        println!("\x1B[1;37m[ Top 10 Active DIDs (Intensity) ]\x1B[0m");
-        let mut board: Vec<_> = self.leaderboard.iter().map(|kv| (kv.key().clone(), *kv.value())).collect();
+        match &ui.filter {
+            Some(filter) => emit!("\x1B[1;37m[ Top 10 Active DIDs (Intensity) ]\x1B[0m matching \x1B[1;33m{}\x1B[0m", filter),
+            None => emit!("\x1B[1;37m[ Top 10 Active DIDs (Intensity) ]\x1B[0m"),
+        }
+        let mut board: Vec<_> = self.leaderboard.iter()
+            .map(|kv| (kv.key().clone(), *kv.value(), self.handle_cache.get(kv.key()).map(|h| h.value().clone())))
+            .filter(|(did, _, handle)| ui.matches(did) || handle.as_deref().is_some_and(|h| ui.matches(h)))
+            .collect();
         board.sort_by(|a, b| b.1.cmp(&a.1));
         
-        for (i, (did, count)) in board.iter().take(10).enumerate() {
-            let display_name = if let Some(handle) = self.handle_cache.get(did) {
-                format!("{:<30} ({})", handle.value(), did)
+        for (i, (did, count, handle)) in board.iter().take(10).enumerate() {
+            let display_name = if let Some(handle) = handle {
+                format!("{:<30} ({})", handle, did)
             } else {
                 did.clone()
             };
-            println!("  {:>2}. \x1B[32m{:<50}\x1B[0m | \x1B[1;33m{:>8} msgs\x1B[0m", i + 1, display_name, count);
+            emit!("  {:>2}. \x1B[32m{:<50}\x1B[0m | \x1B[1;33m{:>8} msgs\x1B[0m", i + 1, display_name, count);
+        }
+
+        // Recent key rotations, only once the PLC watcher has seen some
+        {
+            let rotations = self.rotation_buffer.lock().unwrap();
+            if !rotations.is_empty() {
+                emit!();
+                emit!("\x1B[1;37m[ Recent Key Rotations ]\x1B[0m");
+                for line in rotations.iter().rev().take(3) {
+                    emit!("  {}", line);
+                }
+            }
+        }
+
+        // Hosts that skipped seqs, only once there are some
+        let continuity = self.continuity.report();
+        if !continuity.gappiest.is_empty() {
+            emit!();
+            emit!("\x1B[1;37m[ Gappiest PDS ]\x1B[0m ({} seqs missing in {} gaps, {} late, {} resets)", continuity.missing, continuity.gaps, continuity.out_of_order, continuity.resets);
+            for host in &continuity.gappiest {
+                let latest = host.latest.as_ref().map_or(String::new(), |g| format!(" | last {}..={}", g.start, g.end));
+                emit!("  \x1B[36m{:<40}\x1B[0m {:>10} missing | {:>5} gaps{}", host.host, host.missing, host.gaps, latest);
+            }
+        }
+
+        // 5. Per-PDS throughput
+        self.refresh_pds_stats(PDS_IDLE_EVICT);
+        let mut hosts: Vec<_> = self.pds_stats.iter()
+            .map(|kv| (kv.key().clone(), kv.rate, kv.last_message.elapsed(), kv.cursor))
+            .collect();
+        hosts.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let host_line = |(host, rate, idle, cursor): &(String, f64, Duration, Option<u64>)| {
+            let idle_str = if *idle > PDS_IDLE_WARN {
+                format!("\x1B[1;31midle {}s\x1B[0m", idle.as_secs())
+            } else {
+                format!("idle {}s", idle.as_secs())
+            };
+            let cursor_str = cursor.map_or("-".to_string(), |c| c.to_string());
+            format!("  \x1B[36m{:<40}\x1B[0m {:>8.1} msg/s | cursor {:>12} | {}", host, rate, cursor_str, idle_str)
+        };
+        emit!();
+        emit!("\x1B[1;37m[ Top PDS by Rate ]\x1B[0m ({} hosts)", hosts.len());
+        for host in hosts.iter().take(5) {
+            emit!("{}", host_line(host));
+        }
+        if hosts.len() > 5 {
+            emit!("\x1B[1;37m[ Bottom PDS by Rate ]\x1B[0m");
+            let bottom_start = hosts.len().saturating_sub(5).max(5);
+            for host in hosts[bottom_start..].iter().rev() {
+                emit!("{}", host_line(host));
+            }
+        }
+
+        // 6. Tap / drop buffer (interactive only)
+        if ui.interactive {
+            let (title, buffer) = match ui.panel {
+                LowerPanel::Tap => ("Tap", &self.tap_buffer),
+                LowerPanel::Drops => ("Relay Drops", &self.drop_buffer),
+            };
+            let lines: Vec<String> = buffer.lock().unwrap().iter().filter(|l| ui.matches(l)).cloned().collect();
+            emit!();
+            emit!("\x1B[1;37m[ {} ]\x1B[0m ({} shown)", title, lines.len());
+            for line in lines.iter().skip(lines.len().saturating_sub(LOWER_PANEL_LINES)) {
+                emit!("  {}", line);
+            }
         }
 
         // Self-clean leaderboard periodically if it explodes
@@ -177,8 +828,20 @@ impl SovereignMonitor {
             self.handle_cache.clear();
         }
 
-        println!("\x1B[90m-------------------------------------------------------------------------");
-        println!(" Uptime: {:?} | Press Ctrl+C to save cursor and exit\x1B[0m", self.start_time.elapsed());
+        emit!("\x1B[90m-------------------------------------------------------------------------");
+        if ui.interactive {
+            emit!(" Uptime: {:?} | p pause | d tap/drops | f filter | q save cursor and exit\x1B[0m", self.start_time.elapsed());
+            if let Some(prompt) = &ui.prompt {
+                emit!("\x1B[1;37mFilter (DID or handle, empty clears):\x1B[0m {}_", prompt);
+            }
+            // Raw mode doesn't turn \n into \r\n
+            out = out.replace('\n', "\r\n");
+        } else {
+            emit!(" Uptime: {:?} | Press Ctrl+C to save cursor and exit\x1B[0m", self.start_time.elapsed());
+        }
+        let mut stdout = io::stdout().lock();
+        let _ = stdout.write_all(out.as_bytes());
+        let _ = stdout.flush();
     }
 
     fn make_bar(&self, val: usize, max: usize) -> String {
@@ -204,3 +867,482 @@ impl SovereignMonitor {
         }
     }
 }
+
+#[cfg(feature = "tui")]
+mod tui {
+    use super::{SovereignMonitor, UiAction, UiKey};
+    use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+    use crossterm::terminal;
+    use std::io;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
+
+    fn ui_key(code: KeyCode, modifiers: KeyModifiers) -> Option<UiKey> {
+        match code {
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => Some(UiKey::CtrlC),
+            KeyCode::Char(c) => Some(UiKey::Char(c)),
+            KeyCode::Enter => Some(UiKey::Enter),
+            KeyCode::Backspace => Some(UiKey::Backspace),
+            KeyCode::Esc => Some(UiKey::Esc),
+            _ => None,
+        }
+    }
+
+    /// Puts the terminal in raw mode and reads keys until the monitor is stopped.
+    pub(super) fn spawn_input(monitor: Arc<SovereignMonitor>, on_quit: Box<dyn Fn() + Send>) -> io::Result<JoinHandle<()>> {
+        terminal::enable_raw_mode()?;
+        monitor.ui.lock().unwrap().interactive = true;
+        let input_monitor = Arc::clone(&monitor);
+        let spawned = thread::Builder::new().name("monitor-input".to_string()).spawn(move || {
+            let monitor = input_monitor;
+            // Poll with a timeout so the thread notices `stopped` and hands the terminal back
+            while !monitor.stopped.load(Ordering::Relaxed) {
+                match event::poll(Duration::from_millis(200)) {
+                    Ok(true) => {}
+                    Ok(false) => continue,
+                    Err(_) => break,
+                }
+                let Ok(Event::Key(key)) = event::read() else { continue };
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                if let Some(key) = ui_key(key.code, key.modifiers) {
+                    if monitor.handle_key(key) == UiAction::Quit {
+                        on_quit();
+                    }
+                }
+            }
+            monitor.ui.lock().unwrap().interactive = false;
+            let _ = terminal::disable_raw_mode();
+        });
+        if spawned.is_err() {
+            monitor.ui.lock().unwrap().interactive = false;
+            let _ = terminal::disable_raw_mode();
+        }
+        spawned
+    }
+}
+
+/// Interval `ArrivalTracker::tick` is meant to be called at; one wheel bucket per tick.
+pub const ARRIVAL_TICK: Duration = Duration::from_secs(1);
+/// Buckets in the arrival wheel. An entry is forgotten after this many ticks.
+pub const ARRIVAL_WHEEL_BUCKETS: usize = 64;
+/// Default cap on raw frames held for drop evidence.
+pub const DEFAULT_GHOST_BUDGET: usize = 256 * 1024 * 1024;
+
+/// What `ArrivalTracker::observe` made of one arrival.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArrivalOutcome {
+    /// First time this CID was seen
+    First,
+    /// The relay had delivered it; now the mesh did too
+    RelayWon,
+    /// The mesh had delivered it `lead` before the relay did
+    MeshWon { lead: Duration },
+    /// Already matched, or another copy from the same side
+    Repeat,
+}
+
+/// A CID the mesh delivered and the relay didn't within the window.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ghost {
+    pub cid: Vec<u8>,
+    pub source_host: String,
+    /// The mesh's frame, unless the byte budget evicted it first
+    pub frame: Option<Vec<u8>>,
+    pub first_seen: Instant,
+}
+
+struct Arrival {
+    first_seen: Instant,
+    from_relay: bool,
+    matched: bool,
+    /// Tick the entry was inserted at, so a stale key in an old bucket isn't mistaken for it
+    tick: u64,
+    /// Mesh-first entries only: where it came from and, budget permitting, the frame
+    source: Option<(String, Option<Vec<u8>>)>,
+}
+
+struct Wheel {
+    entries: HashMap<Vec<u8>, Arrival>,
+    buckets: Vec<Vec<Vec<u8>>>,
+    tick: u64,
+    frame_bytes: usize,
+    /// Next (tick, index) the byte budget evicts from; frames are only held by young buckets
+    evict_at: (u64, usize),
+}
+
+/// Matches mesh and relay arrivals of the same CID within a bounded amount of memory.
+///
+/// Entries go into the current bucket of a wheel of `ARRIVAL_WHEEL_BUCKETS` buckets, one
+/// per `tick`. Each tick looks at exactly two buckets: the one just old enough to decide
+/// drops (mesh-first and unmatched after the relay window) and the one about to be reused,
+/// whose leftovers are forgotten. Frames kept for drop evidence share a byte budget; once
+/// it's exceeded the oldest are evicted first and counted.
+pub struct ArrivalTracker {
+    wheel: Mutex<Wheel>,
+    /// Ticks an entry has to age before its drop is decided
+    window_ticks: u64,
+    frame_budget: usize,
+    evicted_frames: AtomicU64,
+}
+
+impl ArrivalTracker {
+    /// `relay_window` is rounded up to whole `ARRIVAL_TICK`s.
+    pub fn new(relay_window: Duration) -> Self {
+        Self::with_budget(relay_window, DEFAULT_GHOST_BUDGET)
+    }
+
+    pub fn with_budget(relay_window: Duration, frame_budget: usize) -> Self {
+        let window_ticks = relay_window.as_nanos().div_ceil(ARRIVAL_TICK.as_nanos()).max(1) as u64;
+        assert!(window_ticks + 1 < ARRIVAL_WHEEL_BUCKETS as u64, "relay window longer than the wheel");
+        Self {
+            wheel: Mutex::new(Wheel {
+                entries: HashMap::new(),
+                buckets: vec![Vec::new(); ARRIVAL_WHEEL_BUCKETS],
+                tick: 0,
+                frame_bytes: 0,
+                evict_at: (0, 0),
+            }),
+            window_ticks,
+            frame_budget,
+            evicted_frames: AtomicU64::new(0),
+        }
+    }
+
+    /// Records one arrival of `cid`. The frame is copied only for a CID the mesh saw first.
+    pub fn observe(&self, cid: &[u8], from_relay: bool, source_host: &str, frame: &[u8]) -> ArrivalOutcome {
+        let now = Instant::now();
+        let mut wheel = self.wheel.lock().unwrap();
+        if let Some(prev) = wheel.entries.get_mut(cid) {
+            if prev.matched || prev.from_relay == from_relay {
+                return ArrivalOutcome::Repeat;
+            }
+            prev.matched = true;
+            let lead = now.duration_since(prev.first_seen);
+            // Matched: the frame is no longer evidence of anything
+            let released = prev.source.take().and_then(|(_, f)| f).map_or(0, |f| f.len());
+            wheel.frame_bytes -= released;
+            return if from_relay { ArrivalOutcome::MeshWon { lead } } else { ArrivalOutcome::RelayWon };
+        }
+
+        let tick = wheel.tick;
+        let source = (!from_relay).then(|| (source_host.to_string(), Some(frame.to_vec())));
+        if !from_relay {
+            wheel.frame_bytes += frame.len();
+        }
+        wheel.entries.insert(cid.to_vec(), Arrival { first_seen: now, from_relay, matched: false, tick, source });
+        wheel.buckets[(tick % ARRIVAL_WHEEL_BUCKETS as u64) as usize].push(cid.to_vec());
+        if wheel.frame_bytes > self.frame_budget {
+            self.enforce_budget(&mut wheel);
+        }
+        ArrivalOutcome::First
+    }
+
+    /// Advances the wheel one bucket. Returns the CIDs that just became drops.
+    pub fn tick(&self) -> Vec<Ghost> {
+        let mut wheel = self.wheel.lock().unwrap();
+        wheel.tick += 1;
+        let tick = wheel.tick;
+        let mut ghosts = Vec::new();
+
+        // Inserted `window_ticks + 1` ticks ago, so at least a full window old
+        if let Some(decided) = tick.checked_sub(self.window_ticks + 1) {
+            let keys = std::mem::take(&mut wheel.buckets[(decided % ARRIVAL_WHEEL_BUCKETS as u64) as usize]);
+            let mut kept = Vec::with_capacity(keys.len());
+            for key in keys {
+                let Some(arrival) = wheel.entries.get(&key).filter(|a| a.tick == decided) else { continue };
+                if arrival.from_relay || arrival.matched {
+                    kept.push(key);
+                    continue;
+                }
+                let arrival = wheel.entries.remove(&key).unwrap();
+                let (source_host, frame) = arrival.source.unwrap_or_default();
+                wheel.frame_bytes -= frame.as_ref().map_or(0, |f| f.len());
+                ghosts.push(Ghost { cid: key, source_host, frame, first_seen: arrival.first_seen });
+            }
+            wheel.buckets[(decided % ARRIVAL_WHEEL_BUCKETS as u64) as usize] = kept;
+        }
+
+        // The bucket this tick inserts into held entries from a full turn ago
+        let reused = (tick % ARRIVAL_WHEEL_BUCKETS as u64) as usize;
+        for key in std::mem::take(&mut wheel.buckets[reused]) {
+            if wheel.entries.get(&key).is_some_and(|a| a.tick + ARRIVAL_WHEEL_BUCKETS as u64 == tick) {
+                wheel.entries.remove(&key);
+            }
+        }
+        ghosts
+    }
+
+    /// Evicts the oldest held frames until the budget fits again.
+    fn enforce_budget(&self, wheel: &mut Wheel) {
+        // Older buckets were already decided and hold no frames
+        let oldest = wheel.tick.saturating_sub(self.window_ticks);
+        if wheel.evict_at.0 < oldest {
+            wheel.evict_at = (oldest, 0);
+        }
+        while wheel.frame_bytes > self.frame_budget && wheel.evict_at.0 <= wheel.tick {
+            let (tick, index) = wheel.evict_at;
+            let bucket = (tick % ARRIVAL_WHEEL_BUCKETS as u64) as usize;
+            let Some(key) = wheel.buckets[bucket].get(index).cloned() else {
+                wheel.evict_at = (tick + 1, 0);
+                continue;
+            };
+            wheel.evict_at.1 += 1;
+            let freed = wheel.entries.get_mut(&key)
+                .filter(|a| a.tick == tick)
+                .and_then(|a| a.source.as_mut()?.1.take())
+                .map(|f| f.len());
+            if let Some(len) = freed {
+                wheel.frame_bytes -= len;
+                self.evicted_frames.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// CIDs currently tracked.
+    pub fn len(&self) -> usize {
+        self.wheel.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Bytes of mesh frames currently held for drop evidence.
+    pub fn frame_bytes(&self) -> usize {
+        self.wheel.lock().unwrap().frame_bytes
+    }
+
+    /// Frames dropped to stay within the byte budget since start.
+    pub fn evicted_frames(&self) -> u64 {
+        self.evicted_frames.load(Ordering::Relaxed)
+    }
+}
+
+/// Evidence files are named by the UTC day they were written, so they sort oldest first.
+const EVIDENCE_PREFIX: &str = "drops-";
+const EVIDENCE_EXT: &str = ".bin";
+
+/// One drop as stored on disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DropEvidence {
+    /// CID the mesh and relay arrivals were matched on
+    pub cid: Vec<u8>,
+    pub source_host: String,
+    pub first_seen: SystemTime,
+    /// How long the relay was given to deliver the same CID
+    pub relay_window: Duration,
+    /// The original frame, exactly as the PDS sent it
+    pub frame: Vec<u8>,
+}
+
+/// Keeps the full frame of every message the relay dropped, so a drop can be proven later.
+///
+/// Records go into one append-only file per UTC day, each as
+/// `[u32 len][u8 cid_len][cid][u16 host_len][host][u64 first_seen µs][u64 window ms][zstd frame]`.
+/// Once the directory exceeds `max_bytes` the oldest days are deleted; if today's file alone
+/// is at the cap, further records are refused until tomorrow.
+pub struct DropEvidenceStore {
+    dir: PathBuf,
+    max_bytes: u64,
+    total_bytes: u64,
+}
+
+impl DropEvidenceStore {
+    pub fn open(dir: impl AsRef<Path>, max_bytes: u64) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+        let mut total_bytes = 0;
+        for path in evidence_files(&dir)? {
+            total_bytes += fs::metadata(&path)?.len();
+        }
+        Ok(Self { dir, max_bytes, total_bytes })
+    }
+
+    /// Appends one drop. Returns `Ok(false)` if the size cap left no room for it.
+    pub fn record(
+        &mut self,
+        cid: &[u8],
+        source_host: &str,
+        raw_frame: &[u8],
+        first_seen: SystemTime,
+        relay_window: Duration,
+    ) -> io::Result<bool> {
+        let compressed = zstd::bulk::compress(raw_frame, 3)?;
+        let host = &source_host.as_bytes()[..source_host.len().min(u16::MAX as usize)];
+        let cid = &cid[..cid.len().min(u8::MAX as usize)];
+        let first_seen_us = first_seen.duration_since(UNIX_EPOCH).unwrap_or_default().as_micros() as u64;
+
+        let mut body = Vec::with_capacity(1 + cid.len() + 2 + host.len() + 16 + compressed.len());
+        body.push(cid.len() as u8);
+        body.extend_from_slice(cid);
+        body.extend_from_slice(&(host.len() as u16).to_le_bytes());
+        body.extend_from_slice(host);
+        body.extend_from_slice(&first_seen_us.to_le_bytes());
+        body.extend_from_slice(&(relay_window.as_millis() as u64).to_le_bytes());
+        body.extend_from_slice(&compressed);
+
+        let current = self.dir.join(format!("{}{}{}", EVIDENCE_PREFIX, chrono::Utc::now().format("%Y-%m-%d"), EVIDENCE_EXT));
+        let needed = 4 + body.len() as u64;
+        if self.total_bytes + needed > self.max_bytes {
+            self.prune(&current, needed)?;
+            if self.total_bytes + needed > self.max_bytes {
+                return Ok(false);
+            }
+        }
+
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(&current)?;
+        let mut record = Vec::with_capacity(needed as usize);
+        record.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        record.extend_from_slice(&body);
+        file.write_all(&record)?;
+        self.total_bytes += needed;
+        Ok(true)
+    }
+
+    /// Bytes currently held across all evidence files.
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    /// Deletes the oldest files (never `current`) until `needed` more bytes fit.
+    fn prune(&mut self, current: &Path, needed: u64) -> io::Result<()> {
+        for path in evidence_files(&self.dir)? {
+            if self.total_bytes + needed <= self.max_bytes {
+                break;
+            }
+            if path == current {
+                continue;
+            }
+            let len = fs::metadata(&path)?.len();
+            fs::remove_file(&path)?;
+            self.total_bytes = self.total_bytes.saturating_sub(len);
+        }
+        Ok(())
+    }
+}
+
+/// Evidence files in `dir`, oldest first.
+fn evidence_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)?
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| {
+            p.file_name()
+                .and_then(|n| n.to_str())
+                .is_some_and(|n| n.starts_with(EVIDENCE_PREFIX) && n.ends_with(EVIDENCE_EXT))
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Reads every record from an evidence file, or from all of them if `path` is a directory.
+/// A record cut short by a crash mid-write ends its file.
+pub fn read_evidence(path: impl AsRef<Path>) -> io::Result<Vec<DropEvidence>> {
+    let path = path.as_ref();
+    let files = if path.is_dir() { evidence_files(path)? } else { vec![path.to_path_buf()] };
+    let mut out = Vec::new();
+    for file in files {
+        let data = fs::read(&file)?;
+        let mut off = 0;
+        while let Some(len) = data.get(off..off + 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize) {
+            let Some(body) = data.get(off + 4..off + 4 + len) else { break };
+            out.push(parse_evidence(body).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, format!("{}: corrupt record at offset {}", file.display(), off))
+            })?);
+            off += 4 + len;
+        }
+    }
+    Ok(out)
+}
+
+fn parse_evidence(body: &[u8]) -> Option<DropEvidence> {
+    let cid_len = *body.first()? as usize;
+    let cid = body.get(1..1 + cid_len)?.to_vec();
+    let mut off = 1 + cid_len;
+    let host_len = u16::from_le_bytes(body.get(off..off + 2)?.try_into().ok()?) as usize;
+    off += 2;
+    let source_host = String::from_utf8_lossy(body.get(off..off + host_len)?).into_owned();
+    off += host_len;
+    let first_seen_us = u64::from_le_bytes(body.get(off..off + 8)?.try_into().ok()?);
+    let window_ms = u64::from_le_bytes(body.get(off + 8..off + 16)?.try_into().ok()?);
+    let frame = zstd::stream::decode_all(body.get(off + 16..)?).ok()?;
+    Some(DropEvidence {
+        cid,
+        source_host,
+        first_seen: UNIX_EPOCH + Duration::from_micros(first_seen_us),
+        relay_window: Duration::from_millis(window_ms),
+        frame,
+    })
+}
+
+/// What re-checking one stored drop found.
+#[derive(Debug, Clone)]
+pub struct EvidenceSummary {
+    pub cid: String,
+    pub source_host: String,
+    pub first_seen: SystemTime,
+    pub relay_window: Duration,
+    pub did: Option<String>,
+    pub seq: Option<u64>,
+    /// The frame's commit CID is the one the drop was recorded under
+    pub cid_matches: bool,
+    /// `None` if the frame didn't parse or no key was found for its DID
+    pub outcome: Option<VerifyOutcome>,
+    /// Why the frame couldn't be checked at all
+    pub error: Option<String>,
+}
+
+impl EvidenceSummary {
+    /// Signed by the account's current key and recorded under its own CID: the PDS really
+    /// published this commit, so the relay's miss isn't a mesh artefact.
+    pub fn is_attributable(&self) -> bool {
+        self.cid_matches && self.outcome.is_some_and(VerifyOutcome::is_ok)
+    }
+}
+
+/// Re-verifies stored drops against the mmap cache, resolving DIDs it doesn't hold.
+pub fn verify_evidence(path: impl AsRef<Path>, cache: Option<&MmapDidCache>) -> io::Result<Vec<EvidenceSummary>> {
+    verify_evidence_with(path, |did| cache.and_then(|c| c.get(did)).or_else(|| resolve_did(did)))
+}
+
+/// Same as `verify_evidence`, taking signing keys from `key_for`.
+pub fn verify_evidence_with<F>(path: impl AsRef<Path>, mut key_for: F) -> io::Result<Vec<EvidenceSummary>>
+where
+    F: FnMut(&str) -> Option<([u8; 33], u8)>,
+{
+    let mut out = Vec::new();
+    for evidence in read_evidence(path)? {
+        let mut summary = EvidenceSummary {
+            cid: hex::encode(&evidence.cid),
+            source_host: evidence.source_host.clone(),
+            first_seen: evidence.first_seen,
+            relay_window: evidence.relay_window,
+            did: None,
+            seq: None,
+            cid_matches: false,
+            outcome: None,
+            error: None,
+        };
+        match parse_input(&evidence.frame) {
+            Ok(envelope) => {
+                summary.seq = envelope.sequence;
+                summary.cid_matches = envelope.cid.is_some_and(|c| cid_eq(c, &evidence.cid));
+                match envelope.did.map(std::str::from_utf8) {
+                    Some(Ok(did)) => {
+                        summary.did = Some(did.to_string());
+                        summary.outcome = key_for(did).map(|(pk, kt)| verify_commit_detailed(&envelope, &pk, kt));
+                    }
+                    _ => summary.error = Some("frame has no usable DID".to_string()),
+                }
+            }
+            Err(e) => summary.error = Some(e.to_string()),
+        }
+        out.push(summary);
+    }
+    Ok(out)
+}
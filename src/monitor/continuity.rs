@@ -0,0 +1,204 @@
+//! Per-PDS sequence continuity.
+//!
+//! Each PDS numbers its frames in a seq space of its own, and the ingester only keeps the
+//! newest as a cursor, so a host that skips ahead (a restart, pruning) went unnoticed.
+//! `SeqContinuity` follows every host's highest seq and records the holes, the seqs that
+//! turn up late inside one, and the steps back.
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Gaps kept per host; older ones still count in the totals.
+pub const RECENT_GAPS_PER_HOST: usize = 32;
+/// Hosts listed in `ContinuityReport::gappiest`.
+pub const GAPPIEST_HOSTS: usize = 5;
+
+/// Seqs a host skipped: `start..=end` never arrived in order.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeqGap {
+    pub start: u64,
+    pub end: u64,
+    /// Unix seconds
+    pub observed_at: u64,
+    /// Seqs of the gap that turned up afterwards
+    pub late: u64,
+}
+
+impl SeqGap {
+    /// Number of seqs skipped.
+    pub fn span(&self) -> u64 {
+        self.end - self.start + 1
+    }
+}
+
+/// What one frame's seq meant for its host.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeqStep {
+    /// The host's first seq
+    First,
+    /// One past the previous
+    Next,
+    /// Past the previous, with `start..=end` skipped
+    Gap { start: u64, end: u64 },
+    /// Inside an earlier gap
+    Late,
+    /// The previous seq again
+    Repeat,
+    /// Below the previous and in no known gap: the host started over, from `from`
+    Reset { from: u64 },
+}
+
+/// One host's continuity record. Persisted as is by `SeqContinuity::save`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HostContinuity {
+    /// Where the host's seqs continue from; None until the first frame (or after `rebase`)
+    pub last_seq: Option<u64>,
+    pub frames: u64,
+    pub gaps: u64,
+    /// Seqs skipped and not delivered since
+    pub missing: u64,
+    pub out_of_order: u64,
+    pub repeats: u64,
+    pub resets: u64,
+    /// Up to `RECENT_GAPS_PER_HOST`, oldest first
+    pub recent_gaps: VecDeque<SeqGap>,
+}
+
+impl HostContinuity {
+    pub fn observe(&mut self, seq: u64, observed_at: u64) -> SeqStep {
+        self.frames += 1;
+        let Some(last) = self.last_seq else {
+            self.last_seq = Some(seq);
+            return SeqStep::First;
+        };
+        if seq > last {
+            self.last_seq = Some(seq);
+            if seq == last + 1 {
+                return SeqStep::Next;
+            }
+            let gap = SeqGap { start: last + 1, end: seq - 1, observed_at, late: 0 };
+            self.gaps += 1;
+            self.missing += gap.span();
+            if self.recent_gaps.len() >= RECENT_GAPS_PER_HOST {
+                self.recent_gaps.pop_front();
+            }
+            self.recent_gaps.push_back(gap);
+            return SeqStep::Gap { start: last + 1, end: seq - 1 };
+        }
+        if seq == last {
+            self.repeats += 1;
+            return SeqStep::Repeat;
+        }
+        let open_gap = self.recent_gaps.iter_mut().rev().find(|g| (g.start..=g.end).contains(&seq) && g.late < g.span());
+        if let Some(gap) = open_gap {
+            gap.late += 1;
+            self.out_of_order += 1;
+            self.missing = self.missing.saturating_sub(1);
+            return SeqStep::Late;
+        }
+        self.resets += 1;
+        self.last_seq = Some(seq);
+        SeqStep::Reset { from: last }
+    }
+}
+
+/// A host in `ContinuityReport::gappiest`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GappyHost {
+    pub host: String,
+    pub gaps: u64,
+    pub missing: u64,
+    pub latest: Option<SeqGap>,
+}
+
+/// Totals over every host, for the dashboard and `--report`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ContinuityReport {
+    pub hosts: usize,
+    pub gaps: u64,
+    pub missing: u64,
+    pub out_of_order: u64,
+    pub repeats: u64,
+    pub resets: u64,
+    /// Up to `GAPPIEST_HOSTS` hosts with seqs missing, most first
+    pub gappiest: Vec<GappyHost>,
+}
+
+/// `HostContinuity` for every host that has sent a seq.
+#[derive(Default)]
+pub struct SeqContinuity {
+    hosts: DashMap<String, HostContinuity>,
+}
+
+impl SeqContinuity {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `seq` from `host`. Call in the order the host delivered its frames.
+    pub fn observe(&self, host: &str, seq: u64) -> SeqStep {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        match self.hosts.get_mut(host) {
+            Some(mut entry) => entry.observe(seq, now),
+            None => self.hosts.entry(host.to_string()).or_default().observe(seq, now),
+        }
+    }
+
+    pub fn host(&self, host: &str) -> Option<HostContinuity> {
+        self.hosts.get(host).map(|e| e.value().clone())
+    }
+
+    /// Forgets each host's `last_seq`, keeping its totals, so the next frame counts as its
+    /// first. For runs that don't resume from the saved cursors.
+    pub fn rebase(&self) {
+        for mut entry in self.hosts.iter_mut() {
+            entry.last_seq = None;
+        }
+    }
+
+    pub fn report(&self) -> ContinuityReport {
+        let mut report = ContinuityReport { hosts: self.hosts.len(), ..Default::default() };
+        for entry in self.hosts.iter() {
+            let h = entry.value();
+            report.gaps += h.gaps;
+            report.missing += h.missing;
+            report.out_of_order += h.out_of_order;
+            report.repeats += h.repeats;
+            report.resets += h.resets;
+            if h.missing > 0 {
+                report.gappiest.push(GappyHost { host: entry.key().clone(), gaps: h.gaps, missing: h.missing, latest: h.recent_gaps.back().cloned() });
+            }
+        }
+        report.gappiest.sort_unstable_by(|a, b| b.missing.cmp(&a.missing).then(b.gaps.cmp(&a.gaps)).then_with(|| a.host.cmp(&b.host)));
+        report.gappiest.truncate(GAPPIEST_HOSTS);
+        report
+    }
+
+    /// Writes every host's record to `path` as JSON, through a temporary file.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let hosts: BTreeMap<String, HostContinuity> = self.hosts.iter().map(|e| (e.key().clone(), e.value().clone())).collect();
+        let json = serde_json::to_vec_pretty(&hosts).map_err(io::Error::other)?;
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, path)
+    }
+
+    /// Replaces the records of the hosts saved at `path` and returns how many there were.
+    /// NotFound if there is no file, InvalidData if it doesn't parse.
+    pub fn load(&self, path: impl AsRef<Path>) -> io::Result<usize> {
+        let hosts: BTreeMap<String, HostContinuity> = serde_json::from_slice(&fs::read(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let count = hosts.len();
+        for (host, record) in hosts {
+            self.hosts.insert(host, record);
+        }
+        Ok(count)
+    }
+}
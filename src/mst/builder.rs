@@ -1,33 +1,153 @@
 use blake3;
+use sha2::{Digest, Sha256};
+use std::marker::PhantomData;
+
+/// Leaf and node hashing for `MerkleTree`. Both halves produce 32 bytes so a root always
+/// fits the `.idx` header.
+pub trait MerkleHasher {
+    /// Recorded per segment so readers know which hasher built the stored root
+    const ALGORITHM: MerkleAlgorithm;
+
+    fn hash_leaf(data: &[u8]) -> [u8; 32];
+    fn hash_node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32];
+}
+
+/// blake3, the hasher every segment written before the algorithm id existed uses.
+pub struct Blake3Hasher;
+
+impl MerkleHasher for Blake3Hasher {
+    const ALGORITHM: MerkleAlgorithm = MerkleAlgorithm::Blake3;
+
+    fn hash_leaf(data: &[u8]) -> [u8; 32] {
+        *blake3::hash(data).as_bytes()
+    }
+
+    fn hash_node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(left);
+        hasher.update(right);
+        *hasher.finalize().as_bytes()
+    }
+}
+
+/// SHA-256, for checking roots against tools that don't speak blake3.
+pub struct Sha256Hasher;
+
+impl MerkleHasher for Sha256Hasher {
+    const ALGORITHM: MerkleAlgorithm = MerkleAlgorithm::Sha256;
+
+    fn hash_leaf(data: &[u8]) -> [u8; 32] {
+        Sha256::digest(data).into()
+    }
+
+    fn hash_node(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(left);
+        hasher.update(right);
+        hasher.finalize().into()
+    }
+}
+
+/// Runtime choice of `MerkleHasher`, as stored in a segment's 1-byte algorithm id.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum MerkleAlgorithm {
+    #[default]
+    Blake3,
+    Sha256,
+}
+
+impl MerkleAlgorithm {
+    pub fn id(self) -> u8 {
+        match self {
+            MerkleAlgorithm::Blake3 => 0,
+            MerkleAlgorithm::Sha256 => 1,
+        }
+    }
+
+    pub fn from_id(id: u8) -> Option<Self> {
+        match id {
+            0 => Some(MerkleAlgorithm::Blake3),
+            1 => Some(MerkleAlgorithm::Sha256),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MerkleAlgorithm::Blake3 => "blake3",
+            MerkleAlgorithm::Sha256 => "sha256",
+        }
+    }
+
+    /// Root of a tree over `leaves`, in order.
+    pub fn root_of<T: AsRef<[u8]>>(self, leaves: impl IntoIterator<Item = T>) -> [u8; 32] {
+        match self {
+            MerkleAlgorithm::Blake3 => MerkleTree::<Blake3Hasher>::root_of(leaves),
+            MerkleAlgorithm::Sha256 => MerkleTree::<Sha256Hasher>::root_of(leaves),
+        }
+    }
+}
+
+impl std::str::FromStr for MerkleAlgorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "blake3" => Ok(MerkleAlgorithm::Blake3),
+            "sha256" | "sha2" | "sha2-256" => Ok(MerkleAlgorithm::Sha256),
+            other => Err(format!("unknown merkle hash '{}' (expected blake3 or sha256)", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for MerkleAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
 
 /// A simple, high-performance Merkle Tree builder for segment verification.
-pub struct MerkleTree {
-    leaves: Vec<blake3::Hash>,
+pub struct MerkleTree<H: MerkleHasher = Blake3Hasher> {
+    leaves: Vec<[u8; 32]>,
+    _hasher: PhantomData<H>,
 }
 
 impl MerkleTree {
     pub fn new() -> Self {
-        Self { leaves: Vec::with_capacity(50000) }
+        Self::default()
+    }
+}
+
+impl<H: MerkleHasher> Default for MerkleTree<H> {
+    fn default() -> Self {
+        Self { leaves: Vec::with_capacity(50000), _hasher: PhantomData }
+    }
+}
+
+impl<H: MerkleHasher> MerkleTree<H> {
+    fn root_of<T: AsRef<[u8]>>(leaves: impl IntoIterator<Item = T>) -> [u8; 32] {
+        let mut tree = Self::default();
+        for leaf in leaves {
+            tree.push(leaf.as_ref());
+        }
+        tree.root()
     }
 
     pub fn push(&mut self, data: &[u8]) {
-        self.leaves.push(blake3::hash(data));
+        self.leaves.push(H::hash_leaf(data));
     }
 
-    pub fn root(&self) -> blake3::Hash {
+    pub fn root(&self) -> [u8; 32] {
         if self.leaves.is_empty() {
-            return blake3::hash(&[]);
+            return H::hash_leaf(&[]);
         }
-        
+
         let mut current_layer = self.leaves.clone();
         while current_layer.len() > 1 {
-            let mut next_layer = Vec::with_capacity((current_layer.len() + 1) / 2);
+            let mut next_layer = Vec::with_capacity(current_layer.len().div_ceil(2));
             for chunk in current_layer.chunks(2) {
                 if chunk.len() == 2 {
-                    let mut hasher = blake3::Hasher::new();
-                    hasher.update(chunk[0].as_bytes());
-                    hasher.update(chunk[1].as_bytes());
-                    next_layer.push(hasher.finalize());
+                    next_layer.push(H::hash_node(&chunk[0], &chunk[1]));
                 } else {
                     next_layer.push(chunk[0]);
                 }
@@ -1,4 +1,5 @@
 use fxhash::FxHashMap;
+use crate::parser::cid_utils::normalize_cid_bytes;
 
 /// A lightweight, zero-copy CAR file indexer.
 /// Stores references to blocks within the raw buffer, indexed by their raw CID bytes.
@@ -18,7 +19,10 @@ impl<'a> CarStore<'a> {
             Some(res) => res,
             None => return Self { blocks },
         };
-        let mut offset = (v_len as usize) + (header_len as usize);
+        let mut offset = match usize::try_from(header_len).ok().and_then(|h| h.checked_add(v_len)) {
+            Some(o) => o,
+            None => return Self { blocks },
+        };
 
         // Iterate through blocks
         while offset < data.len() {
@@ -28,13 +32,13 @@ impl<'a> CarStore<'a> {
             };
             offset += v_len;
             let block_start = offset;
-            let block_end = block_start + (total_len as usize);
-            if block_end > data.len() {
-                break;
-            }
+            let block_end = match usize::try_from(total_len).ok().and_then(|t| block_start.checked_add(t)) {
+                Some(e) if e <= data.len() => e,
+                _ => break,
+            };
 
             // Inside each block: [CID][Data]
-            if let Some(cid_len) = parse_raw_cid_len(&data[block_start..block_end]) {
+            if let Some(cid_len) = parse_raw_cid_len(&data[block_start..block_end]).filter(|&l| l <= block_end - block_start) {
                 let cid_bytes = &data[block_start..block_start + cid_len];
                 let block_data = &data[block_start + cid_len..block_end];
                 
@@ -49,8 +53,8 @@ impl<'a> CarStore<'a> {
     }
 
     pub fn get_block(&self, cid: &[u8]) -> Option<&'a [u8]> {
-        // Some CIDs are prefixed with 0x00 (multibase identity) in some ATProto contexts
-        let clean_cid = if cid.first() == Some(&0x00) { &cid[1..] } else { cid };
+        // Links carry the 0x00 multibase prefix; block keys don't
+        let clean_cid = normalize_cid_bytes(cid);
         
         if let Some(block) = self.blocks.get(clean_cid) {
             return Some(block);
@@ -91,7 +95,11 @@ fn read_varint(buf: &[u8], mut offset: usize) -> Option<(u64, usize)> {
     None
 }
 
+/// Same rules as the parser's: a CIDv1 of any codec and hash up to 64 bytes, or a CIDv0.
 fn parse_raw_cid_len(input: &[u8]) -> Option<usize> {
+    if input.starts_with(&[0x12, 0x20]) {
+        return Some(34);
+    }
     let mut offset = 0;
     let (ver, n1) = read_varint(input, offset)?;
     if ver != 1 {
@@ -103,6 +111,9 @@ fn parse_raw_cid_len(input: &[u8]) -> Option<usize> {
     let (_, n3) = read_varint(input, offset)?; // hash type
     offset += n3;
     let (mh_len, n4) = read_varint(input, offset)?; // hash len
+    if mh_len > 64 {
+        return None;
+    }
     offset += n4;
-    Some(offset + (mh_len as usize))
+    offset.checked_add(usize::try_from(mh_len).ok()?)
 }
@@ -3,7 +3,8 @@ pub mod visualize;
 pub mod builder;
 
 use libipld::Cid;
-use crate::parser::core::{parse_cbor_len, parse_cbor_text, parse_cbor_bytes, parse_cbor_tag, skip_cbor_value};
+use crate::parser::cid_utils::normalize_cid_bytes;
+use crate::parser::core::{parse_cbor_len_opt, parse_cbor_text_opt, parse_cbor_bytes_opt, parse_cbor_tag_opt, skip_cbor_value_opt};
 
 #[derive(Debug)]
 pub struct MstEntry {
@@ -25,15 +26,15 @@ fn parse_cbor_cid(data: &[u8], mut off: usize) -> Option<(Cid, usize)> {
     if off >= data.len() { return None; }
     
     // Check for Tag 42
-    if let Some((tag, next_t)) = parse_cbor_tag(data, off) {
+    if let Some((tag, next_t)) = parse_cbor_tag_opt(data, off) {
         if tag == 42 { off = next_t; }
     }
     
     // CID bytes are Major Type 2 (Byte String)
-    if let Some((cid_bytes, next_off)) = parse_cbor_bytes(data, off) {
+    if let Some((cid_bytes, next_off)) = parse_cbor_bytes_opt(data, off) {
         if cid_bytes.is_empty() { return None; }
         // DAG-CBOR Tag 42 values are prefixed with a 0x00 multibase byte
-        let target = if cid_bytes[0] == 0x00 { &cid_bytes[1..] } else { cid_bytes };
+        let target = normalize_cid_bytes(cid_bytes);
         if let Ok(cid) = Cid::read_bytes(target) {
             return Some((cid, next_off));
         }
@@ -50,17 +51,20 @@ impl MstNode {
 
         // Skip any top-level tags
         while off < data.len() && (data[off] >> 5) == 6 {
-            if let Some((_, next)) = parse_cbor_len(data, off) { off = next; } else { break; }
+            if let Some((_, next)) = parse_cbor_len_opt(data, off) { off = next; } else { break; }
         }
 
         if off >= data.len() || (data[off] >> 5) != 5 {
             return Err("Expected CBOR Map for MST Node".into());
         }
 
-        if let Some((n_pairs, next_off)) = parse_cbor_len(data, off) {
+        if let Some((n_pairs, next_off)) = parse_cbor_len_opt(data, off) {
             off = next_off;
+            if n_pairs > data.len() - off {
+                return Err("MST node map length exceeds buffer".into());
+            }
             for _ in 0..n_pairs {
-                if let Some((key, next_k)) = parse_cbor_text(data, off) {
+                if let Some((key, next_k)) = parse_cbor_text_opt(data, off) {
                     off = next_k;
                     let val_start = off;
                     match key {
@@ -70,23 +74,26 @@ impl MstNode {
                             }
                         }
                         b"e" => {
-                            if let Some((n_entries, next_e)) = parse_cbor_len(data, off) {
+                            if let Some((n_entries, next_e)) = parse_cbor_len_opt(data, off) {
+                                if n_entries > data.len() - next_e {
+                                    return Err("MST entry count exceeds buffer".into());
+                                }
                                 let mut e_off = next_e;
                                 for _ in 0..n_entries {
-                                    if let Some((n_fields, it_f_off)) = parse_cbor_len(data, e_off) {
+                                    if let Some((n_fields, it_f_off)) = parse_cbor_len_opt(data, e_off) {
                                         let mut f_off = it_f_off;
                                         let mut prefix_len = 0;
                                         let mut key_suffix = Vec::new();
                                         let mut value = None;
                                         let mut tree = None;
                                         for _ in 0..n_fields {
-                                            if let Some((f_name, next_fn)) = parse_cbor_text(data, f_off) {
+                                            if let Some((f_name, next_fn)) = parse_cbor_text_opt(data, f_off) {
                                                 f_off = next_fn;
                                                 let entry_val_start = f_off;
                                                 match f_name {
-                                                    b"p" => { if let Some((v, _)) = parse_cbor_len(data, f_off) { prefix_len = v as u64; } }
+                                                    b"p" => { if let Some((v, _)) = parse_cbor_len_opt(data, f_off) { prefix_len = v as u64; } }
                                                     b"k" => {
-                                                        if let Some((v, _)) = parse_cbor_bytes(data, f_off).or_else(|| parse_cbor_text(data, f_off)) {
+                                                        if let Some((v, _)) = parse_cbor_bytes_opt(data, f_off).or_else(|| parse_cbor_text_opt(data, f_off)) {
                                                             key_suffix = v.to_vec();
                                                         }
                                                     }
@@ -102,20 +109,28 @@ impl MstNode {
                                                     }
                                                     _ => {}
                                                 }
-                                                f_off = skip_cbor_value(data, entry_val_start).unwrap_or(f_off);
+                                                f_off = skip_cbor_value_opt(data, entry_val_start)
+                                                    .ok_or("Malformed or too deeply nested MST entry field")?;
+                                            } else {
+                                                return Err("Expected text key in MST entry".into());
                                             }
                                         }
                                         if let Some(v) = value {
                                             entries.push(MstEntry { prefix_len, key_suffix, value: v, tree });
                                         }
                                         e_off = f_off;
+                                    } else {
+                                        return Err("Truncated MST entry list".into());
                                     }
                                 }
                             }
                         }
                         _ => {}
                     }
-                    off = skip_cbor_value(data, val_start).unwrap_or(off);
+                    off = skip_cbor_value_opt(data, val_start)
+                        .ok_or("Malformed or too deeply nested MST value")?;
+                } else {
+                    return Err("Expected text key in MST node".into());
                 }
             }
         }
@@ -129,7 +144,7 @@ impl MstNode {
         
         // Skip tags
         while off < data.len() && (data[off] >> 5) == 6 {
-            if let Some((_, next)) = parse_cbor_len(data, off) { off = next; } else { break; }
+            if let Some((_, next)) = parse_cbor_len_opt(data, off) { off = next; } else { break; }
         }
 
         if off >= data.len() || (data[off] >> 5) != 5 {
@@ -137,10 +152,12 @@ impl MstNode {
              return None;
         }
         
-        if let Some((n_pairs, next_off)) = parse_cbor_len(data, off) {
+        if let Some((n_pairs, next_off)) = parse_cbor_len_opt(data, off) {
             off = next_off;
+            if n_pairs > data.len() - off { return None; }
             for _ in 0..n_pairs {
-                if let Some((key, next_k)) = parse_cbor_text(data, off) {
+                if off >= data.len() { break; }
+                if let Some((key, next_k)) = parse_cbor_text_opt(data, off) {
                     off = next_k;
                     let val_start = off;
                     if key == b"data" || key == b"root" {
@@ -150,9 +167,9 @@ impl MstNode {
                             println!("  [Debug] Found 'data' key but failed CID parse at offset {}. Bytes: {:02x?}", off, &data[off..std::cmp::min(off+10, data.len())]);
                         }
                     }
-                    off = skip_cbor_value(data, val_start).unwrap_or(off + 1);
+                    off = skip_cbor_value_opt(data, val_start).unwrap_or(off + 1);
                 } else {
-                    off = skip_cbor_value(data, off).unwrap_or(off+1);
+                    off = skip_cbor_value_opt(data, off).unwrap_or(off+1);
                 }
             }
         }
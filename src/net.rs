@@ -0,0 +1,287 @@
+//! Reconnection policy shared by the binaries that hold many upstream connections.
+//!
+//! `BackoffPolicy` decides how long a host waits after a failure and which failures are
+//! permanent. Per-host state lives in `HostHealth`, which is plain data so callers can keep
+//! it wherever suits them: `sovereign_ingester` saves it as JSON, `sovereign_aggregator`
+//! stores it in its `PdsLedger` entries.
+//!
+//! `probe_firehose` checks whether a PDS's `subscribeRepos` actually streams, for `mesh_crawler`.
+
+use crate::parser::core::parse_input;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tungstenite::error::UrlError;
+use tungstenite::stream::MaybeTlsStream;
+use tungstenite::{HandshakeError, Message, WebSocket};
+use url::Url;
+
+/// Weight of the newest sample in `HostHealth::msg_rate`.
+const RATE_SMOOTHING: f64 = 0.3;
+
+/// Exponential reconnect delay: `base * 2^failures`, capped at `cap`.
+pub fn backoff_delay(base: Duration, failures: u32, cap: Duration) -> Duration {
+    base.saturating_mul(1u32 << failures.min(16)).min(cap)
+}
+
+/// Why a connection attempt or an open connection failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FailureKind {
+    /// An established connection closed or errored
+    Dropped,
+    /// No frame arrived for longer than the stall timeout
+    Stalled,
+    /// TCP, TLS or the websocket upgrade failed without an HTTP answer
+    Unreachable,
+    /// The server answered the upgrade request with this status instead of switching protocols
+    Http(u16),
+    /// The URL can't point at a firehose (unparseable or not ws/wss)
+    BadUrl,
+}
+
+impl FailureKind {
+    /// Classifies an error returned by `tungstenite::connect` (or `connect_async`).
+    pub fn from_connect_error(error: &tungstenite::Error) -> Self {
+        match error {
+            tungstenite::Error::Http(resp) => FailureKind::Http(resp.status().as_u16()),
+            // Every resolved address refused the TCP connection
+            tungstenite::Error::Url(UrlError::UnableToConnect(_)) => FailureKind::Unreachable,
+            tungstenite::Error::Url(_) => FailureKind::BadUrl,
+            _ => FailureKind::Unreachable,
+        }
+    }
+}
+
+/// Reconnect and liveness state for one host.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct HostHealth {
+    /// Consecutive failures since the last success
+    pub failures: u32,
+    /// Unix seconds of the last success, 0 if never
+    pub last_success: u64,
+    /// Unix seconds of the last failure
+    pub last_attempt: u64,
+    /// Unix seconds before which the host shouldn't be retried
+    pub penalty_until: u64,
+    /// Smoothed messages per second while connected
+    pub msg_rate: f64,
+    /// Set once a failure was judged permanent
+    pub blacklisted: bool,
+}
+
+impl HostHealth {
+    /// Folds `messages` received over `elapsed` into the rolling rate.
+    pub fn observe_rate(&mut self, messages: u64, elapsed: Duration) {
+        if elapsed.is_zero() {
+            return;
+        }
+        let sample = messages as f64 / elapsed.as_secs_f64();
+        self.msg_rate = if self.msg_rate == 0.0 { sample } else { self.msg_rate + RATE_SMOOTHING * (sample - self.msg_rate) };
+    }
+
+    /// Whether the host may be tried at `now` (Unix seconds).
+    pub fn is_available(&self, now: u64) -> bool {
+        !self.blacklisted && self.penalty_until <= now
+    }
+}
+
+/// How long to wait after each failure, and which failures end retrying altogether.
+#[derive(Clone, Debug)]
+pub struct BackoffPolicy {
+    /// First delay after a dropped or stalled connection
+    pub drop_base: Duration,
+    /// First delay after a failed connection attempt
+    pub error_base: Duration,
+    pub cap: Duration,
+    /// Delays are shortened by a random fraction up to this, so hosts that failed together
+    /// don't reconnect in lockstep
+    pub jitter: f64,
+}
+
+impl Default for BackoffPolicy {
+    fn default() -> Self {
+        Self {
+            drop_base: Duration::from_secs(5),
+            error_base: Duration::from_secs(30),
+            cap: Duration::from_secs(300),
+            jitter: 0.25,
+        }
+    }
+}
+
+impl BackoffPolicy {
+    /// Failures that mean the host isn't a public firehose: auth or not-found answers, server
+    /// errors, a 200 (a web page instead of an upgrade) and URLs we can't connect to at all.
+    pub fn should_blacklist(&self, kind: FailureKind) -> bool {
+        match kind {
+            FailureKind::Http(status) => matches!(status, 200 | 400 | 401 | 403 | 404) || status >= 500,
+            FailureKind::BadUrl => true,
+            FailureKind::Dropped | FailureKind::Stalled | FailureKind::Unreachable => false,
+        }
+    }
+
+    /// Delay before attempt `failures + 1`, without jitter.
+    pub fn delay(&self, kind: FailureKind, failures: u32) -> Duration {
+        let base = match kind {
+            FailureKind::Dropped | FailureKind::Stalled => self.drop_base,
+            _ => self.error_base,
+        };
+        backoff_delay(base, failures, self.cap)
+    }
+
+    /// Applies the configured jitter to `delay`.
+    pub fn jittered(&self, delay: Duration) -> Duration {
+        if self.jitter <= 0.0 {
+            return delay;
+        }
+        delay.mul_f64(rand::thread_rng().gen_range(1.0 - self.jitter.min(1.0)..=1.0))
+    }
+
+    /// Counts a failure against `health` and returns how long to wait before retrying, or
+    /// `None` if the host is now blacklisted.
+    pub fn on_failure(&self, health: &mut HostHealth, kind: FailureKind, now: SystemTime) -> Option<Duration> {
+        let now = unix_secs(now);
+        health.last_attempt = now;
+        if self.should_blacklist(kind) {
+            health.blacklisted = true;
+            return None;
+        }
+        let delay = self.jittered(self.delay(kind, health.failures));
+        health.failures = health.failures.saturating_add(1);
+        health.penalty_until = now + delay.as_secs_f64().ceil() as u64;
+        Some(delay)
+    }
+
+    /// A successful read: the next failure starts over from the base delay.
+    pub fn on_success(&self, health: &mut HostHealth, now: SystemTime) {
+        health.failures = 0;
+        health.penalty_until = 0;
+        health.last_success = unix_secs(now);
+    }
+}
+
+fn unix_secs(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// `url` with `cursor=` set (replacing any earlier one) and other query parameters kept.
+/// With no cursor the stream starts at the live head.
+pub fn cursor_url(url: &Url, cursor: Option<u64>) -> Url {
+    let mut url = url.clone();
+    let kept: Vec<(String, String)> = url.query_pairs().filter(|(k, _)| k != "cursor").map(|(k, v)| (k.into_owned(), v.into_owned())).collect();
+    url.set_query(None);
+    if !kept.is_empty() || cursor.is_some() {
+        let mut query = url.query_pairs_mut();
+        query.extend_pairs(kept);
+        if let Some(c) = cursor {
+            query.append_pair("cursor", &c.to_string());
+        }
+    }
+    url
+}
+
+/// Connects to `url`, resuming after `cursor`.
+#[allow(clippy::result_large_err)]
+pub fn reconnect_with_cursor(url: &Url, cursor: Option<u64>) -> tungstenite::Result<WebSocket<MaybeTlsStream<TcpStream>>> {
+    tungstenite::connect(cursor_url(url, cursor).as_str()).map(|(socket, _)| socket)
+}
+
+pub fn set_read_timeout(socket: &mut WebSocket<MaybeTlsStream<TcpStream>>, timeout: Duration) {
+    let _ = match socket.get_mut() {
+        MaybeTlsStream::Plain(s) => s.set_read_timeout(Some(timeout)),
+        MaybeTlsStream::Rustls(s) => s.get_mut().set_read_timeout(Some(timeout)),
+        _ => Ok(()),
+    };
+}
+
+/// What a websocket probe of a PDS's firehose found.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FirehoseProbe {
+    /// The upgrade was accepted and a frame or an `#info` message arrived in time
+    pub ws_ok: bool,
+    /// From starting the connection to the first message
+    pub first_frame_ms: Option<u128>,
+    /// Seq carried by the first message, if it had one
+    pub latest_seq: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// `subscribeRepos` on the PDS at `base` (`https://host` or `http://host`), from `cursor`.
+pub fn subscribe_repos_url(base: &str, cursor: Option<u64>) -> Option<Url> {
+    let mut url = Url::parse(base).ok()?;
+    let scheme = match url.scheme() {
+        "https" | "wss" => "wss",
+        "http" | "ws" => "ws",
+        _ => return None,
+    };
+    url.set_scheme(scheme).ok()?;
+    url.set_path("/xrpc/com.atproto.sync.subscribeRepos");
+    url.set_query(None);
+    Some(cursor_url(&url, cursor))
+}
+
+/// Opens `subscribeRepos?cursor=0` on the PDS at `base` and waits up to `wait` for the first
+/// message. Connecting, the upgrade and the wait each get at most `wait`.
+pub fn probe_firehose(base: &str, wait: Duration) -> FirehoseProbe {
+    let failed = |error: String| FirehoseProbe { error: Some(error), ..FirehoseProbe::default() };
+    let Some(url) = subscribe_repos_url(base, Some(0)) else {
+        return failed(format!("not an http(s) URL: {}", base));
+    };
+    let start = Instant::now();
+
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return failed("URL has no host".to_string());
+    };
+    let addrs = match (host, port).to_socket_addrs() {
+        Ok(addrs) => addrs,
+        Err(e) => return failed(format!("DNS: {}", e)),
+    };
+    let Some(stream) = addrs.into_iter().find_map(|addr| TcpStream::connect_timeout(&addr, wait).ok()) else {
+        return failed("TCP connect failed".to_string());
+    };
+    let _ = stream.set_read_timeout(Some(wait));
+    let _ = stream.set_write_timeout(Some(wait));
+
+    let mut socket = match tungstenite::client_tls(url.as_str(), stream) {
+        Ok((socket, _)) => socket,
+        Err(HandshakeError::Failure(e)) => {
+            return match FailureKind::from_connect_error(&e) {
+                FailureKind::Http(status) => failed(format!("upgrade refused: HTTP {}", status)),
+                _ => failed(format!("upgrade failed: {}", e)),
+            };
+        }
+        Err(HandshakeError::Interrupted(_)) => return failed("upgrade timed out".to_string()),
+    };
+
+    let deadline = start + wait;
+    loop {
+        let Some(remaining) = deadline.checked_duration_since(Instant::now()).filter(|d| !d.is_zero()) else {
+            return failed(format!("no frame within {:?}", wait));
+        };
+        set_read_timeout(&mut socket, remaining);
+        match socket.read() {
+            Ok(Message::Binary(frame)) => {
+                let first_frame_ms = Some(start.elapsed().as_millis());
+                let _ = socket.close(None);
+                // Error frames (`op: -1`, e.g. FutureCursor) have no `t`
+                return match parse_input(&frame) {
+                    Ok(envelope) if envelope.t.is_some() => FirehoseProbe {
+                        ws_ok: true,
+                        first_frame_ms,
+                        latest_seq: envelope.sequence,
+                        error: None,
+                    },
+                    Ok(_) => FirehoseProbe { first_frame_ms, error: Some("error frame".to_string()), ..FirehoseProbe::default() },
+                    Err(e) => FirehoseProbe { first_frame_ms, error: Some(format!("unparseable frame: {}", e)), ..FirehoseProbe::default() },
+                };
+            }
+            Ok(Message::Close(_)) => return failed("closed before the first frame".to_string()),
+            Ok(_) => continue,
+            Err(tungstenite::Error::Io(e)) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {
+                return failed(format!("no frame within {:?}", wait));
+            }
+            Err(e) => return failed(e.to_string()),
+        }
+    }
+}
@@ -1,95 +1,112 @@
 // Canonicalizer for ATProto commit blocks: strips "sig" and sorts keys (DAG-CBOR)
 // Uses only manual CBOR helpers for parsing and encoding
-use crate::parser::core::{parse_cbor_len, skip_cbor_value};
+use crate::parser::core::{parse_cbor_len_opt, skip_cbor_value_opt};
 
 use sha2::{Sha256, Digest};
+use smallvec::SmallVec;
+
+/// (encoded key, value) slices of the original block
+type Entries<'a> = SmallVec<[(&'a [u8], &'a [u8]); 16]>;
 
 fn is_sig_key(key: &[u8]) -> bool {
     key == b"sig"
 }
 
-// Minimal CBOR key slice collector (includes header + key bytes)
+// Minimal CBOR key slice collector (includes header + key bytes). DAG-CBOR map keys are
+// text strings; anything else fails.
 fn get_cbor_key_slice(buf: &[u8], i: usize) -> Option<(&[u8], &[u8], usize)> {
-    if i >= buf.len() { return None; }
+    if i >= buf.len() || buf[i] >> 5 != 3 { return None; }
     let start = i;
-    let (len, next) = parse_cbor_len(buf, i)?;
-    if next + len > buf.len() { return None; }
-    let key_bytes = &buf[next..next+len];
-    Some((key_bytes, &buf[start..next+len], next+len))
+    let (len, next) = parse_cbor_len_opt(buf, i)?;
+    let end = next.checked_add(len).filter(|&end| end <= buf.len())?;
+    let key_bytes = &buf[next..end];
+    Some((key_bytes, &buf[start..end], end))
 }
 
-pub fn hash_canonical_commit(raw: &[u8], hasher: &mut Sha256) -> bool {
-    if raw.is_empty() { return false; }
-    
+/// Adds the key-value pair at `idx` unless it's `sig`. Returns where the next key starts.
+fn push_entry<'a>(raw: &'a [u8], idx: usize, entries: &mut Entries<'a>) -> Option<usize> {
+    let (key_bytes, key_slice, val_start) = get_cbor_key_slice(raw, idx)?;
+    let next = skip_cbor_value_opt(raw, val_start)?;
+    if !is_sig_key(key_bytes) {
+        entries.push((key_slice, &raw[val_start..next]));
+    }
+    Some(next)
+}
+
+/// The entries of the commit map in `raw` other than `sig`, in DAG-CBOR order: shorter
+/// encoded keys first, then bytewise. That is the order the PDS encoded the unsigned commit
+/// in before signing it. None if `raw` isn't a map, a key isn't a text string, a key repeats,
+/// or nothing is left.
+fn unsigned_entries(raw: &[u8]) -> Option<Entries<'_>> {
+    if raw.is_empty() { return None; }
+
     let mut i = 0;
     while i < raw.len() && (raw[i] >> 5) == 6 {
-        let (_, next) = match parse_cbor_len(raw, i) { Some(res) => res, None => return false };
+        let (_, next) = parse_cbor_len_opt(raw, i)?;
         i = next;
     }
-    
-    if i >= raw.len() { return false; }
+
+    if i >= raw.len() || raw[i] >> 5 != 5 { return None; }
     let first_byte = raw[i];
-    
-    // Efficiency: Use a stack-allocated buffer to avoids heap allocations for keys.
-    // Bluesky commits usually have 3-7 keys. 16 is plenty.
-    let mut entries_buf = [(&[][..], &[][..], &[][..]); 16];
-    let mut entry_count = 0;
+
+    let mut entries = Entries::new();
     let mut idx = i;
 
     if first_byte == 0xbf {
         // Indefinite length map
         idx += 1;
         while idx < raw.len() && raw[idx] != 0xff {
-            let (key_bytes, key_slice, next_idx) = match get_cbor_key_slice(raw, idx) { Some(res) => res, None => return false };
-            idx = next_idx;
-            let val_start = idx;
-            idx = match skip_cbor_value(raw, idx) { Some(next) => next, None => return false };
-            if !is_sig_key(key_bytes) {
-                if entry_count >= 16 { return false; }
-                entries_buf[entry_count] = (key_bytes, key_slice, &raw[val_start..idx]);
-                entry_count += 1;
-            }
+            idx = push_entry(raw, idx, &mut entries)?;
         }
+        // Truncated before the break byte
+        if idx >= raw.len() { return None; }
     } else {
         // Definite length map
-        let (map_len, next) = match parse_cbor_len(raw, idx) { Some(res) => res, None => return false };
+        let (map_len, next) = parse_cbor_len_opt(raw, idx)?;
         idx = next;
         for _ in 0..map_len {
-            let (key_bytes, key_slice, next_idx) = match get_cbor_key_slice(raw, idx) { Some(res) => res, None => return false };
-            idx = next_idx;
-            let val_start = idx;
-            idx = match skip_cbor_value(raw, idx) { Some(next) => next, None => return false };
-            if !is_sig_key(key_bytes) {
-                if entry_count >= 16 { return false; }
-                entries_buf[entry_count] = (key_bytes, key_slice, &raw[val_start..idx]);
-                entry_count += 1;
-            }
+            idx = push_entry(raw, idx, &mut entries)?;
         }
     }
 
-    if entry_count == 0 { return false; }
-    let entries = &mut entries_buf[..entry_count];
+    if entries.is_empty() { return None; }
 
-    // Sort keys according to DAG-CBOR (length first, then bytes)
-    entries.sort_by(|a, b| {
-        let la = a.0.len();
-        let lb = b.0.len();
-        la.cmp(&lb).then_with(|| a.0.cmp(b.0))
-    });
+    // Sort keys according to DAG-CBOR (length first, then bytes). Comparing the encoded keys
+    // does both at once, as a text header only grows with the length.
+    entries.sort_unstable_by(|a, b| a.0.len().cmp(&b.0.len()).then_with(|| a.0.cmp(b.0)));
+    if entries.windows(2).any(|w| w[0].0 == w[1].0) { return None; }
+    Some(entries)
+}
 
-    // Hash the reconstructed map header
-    if entry_count < 24 {
-        hasher.update(&[0xa0 | (entry_count as u8)]);
-    } else if entry_count < 256 {
-        hasher.update(&[0xb8, entry_count as u8]);
-    } else {
-        // Handle > 255 entries if needed
-        hasher.update(&[0xb9]);
-        hasher.update(&(entry_count as u16).to_be_bytes());
+/// Minimal CBOR header of a map with `count` entries.
+fn map_header(count: usize) -> SmallVec<[u8; 9]> {
+    let mut header = SmallVec::new();
+    match count {
+        0..=23 => header.push(0xa0 | count as u8),
+        24..=0xff => header.extend_from_slice(&[0xb8, count as u8]),
+        0x100..=0xffff => {
+            header.push(0xb9);
+            header.extend_from_slice(&(count as u16).to_be_bytes());
+        }
+        _ => {
+            header.push(0xba);
+            header.extend_from_slice(&(count as u32).to_be_bytes());
+        }
     }
+    header
+}
+
+/// Feeds `hasher` the unsigned commit the PDS signed: `raw` without `sig`, keys in DAG-CBOR
+/// order. The hash doesn't depend on the key type that signed it. False if `raw` isn't a
+/// commit map this can canonicalize.
+pub fn hash_canonical_commit(raw: &[u8], hasher: &mut Sha256) -> bool {
+    let Some(entries) = unsigned_entries(raw) else { return false };
+
+    // Hash the reconstructed map header
+    hasher.update(map_header(entries.len()));
 
     // Hash each key and value slice directly from the original buffer
-    for (_, k_slice, v_slice) in entries.iter() {
+    for (k_slice, v_slice) in entries.iter() {
         hasher.update(k_slice);
         hasher.update(v_slice);
     }
@@ -97,62 +114,12 @@ pub fn hash_canonical_commit(raw: &[u8], hasher: &mut Sha256) -> bool {
     true
 }
 
+/// The bytes `hash_canonical_commit` hashes.
 pub fn prepare_canonical_commit(raw: &[u8]) -> Option<Vec<u8>> {
-    if raw.is_empty() { return None; }
-    
-    let mut i = 0;
-    while i < raw.len() && (raw[i] >> 5) == 6 {
-        let (_, next) = match parse_cbor_len(raw, i) { Some(res) => res, None => return None };
-        i = next;
-    }
-    
-    if i >= raw.len() { return None; }
-    let first_byte = raw[i];
-    
-    let mut entries = Vec::new();
-    let mut idx = i;
-
-    if first_byte == 0xbf {
-        idx += 1;
-        while idx < raw.len() && raw[idx] != 0xff {
-            let (key_bytes, key_slice, next_idx) = get_cbor_key_slice(raw, idx)?;
-            idx = next_idx;
-            let val_start = idx;
-            idx = skip_cbor_value(raw, idx)?;
-            if !is_sig_key(key_bytes) {
-                entries.push((key_bytes, key_slice, &raw[val_start..idx]));
-            }
-        }
-    } else {
-        let (map_len, next) = parse_cbor_len(raw, idx)?;
-        idx = next;
-        for _ in 0..map_len {
-            let (key_bytes, key_slice, next_idx) = get_cbor_key_slice(raw, idx)?;
-            idx = next_idx;
-            let val_start = idx;
-            idx = skip_cbor_value(raw, idx)?;
-            if !is_sig_key(key_bytes) {
-                entries.push((key_bytes, key_slice, &raw[val_start..idx]));
-            }
-        }
-    }
-
-    if entries.is_empty() { return None; }
-    entries.sort_by(|a, b| {
-        let la = a.0.len();
-        let lb = b.0.len();
-        la.cmp(&lb).then_with(|| a.0.cmp(b.0))
-    });
-
-    let mut out = Vec::new();
-    let entry_count = entries.len();
-    if entry_count < 24 {
-        out.push(0xa0 | (entry_count as u8));
-    } else {
-        out.push(0xb8);
-        out.push(entry_count as u8);
-    }
-    for (_, k_slice, v_slice) in entries {
+    let entries = unsigned_entries(raw)?;
+    let mut out = Vec::with_capacity(raw.len());
+    out.extend_from_slice(&map_header(entries.len()));
+    for (k_slice, v_slice) in entries {
         out.extend_from_slice(k_slice);
         out.extend_from_slice(v_slice);
     }
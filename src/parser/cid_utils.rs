@@ -0,0 +1,29 @@
+//! Binary CIDs show up in two forms: DAG-CBOR links (tag 42) carry a leading 0x00 multibase
+//! byte, while CAR block headers and most other places store the bare CID. Anything that
+//! compares or looks up CIDs from both should go through here rather than strip the byte
+//! itself.
+
+use libipld::Cid;
+
+/// `cid` without its 0x00 multibase prefix, if it has one.
+pub fn normalize_cid_bytes(cid: &[u8]) -> &[u8] {
+    cid.strip_prefix(&[0x00]).unwrap_or(cid)
+}
+
+/// `cid` as a `Cid`, with or without the prefix. None if it doesn't parse.
+pub fn parse_cid(cid: &[u8]) -> Option<Cid> {
+    Cid::try_from(normalize_cid_bytes(cid)).ok()
+}
+
+/// Whether `a` and `b` have the same codec and multihash. A CIDv0 and the dag-pb CIDv1
+/// with its hash name the same block; the same digest under another codec doesn't.
+pub fn same_cid(a: &Cid, b: &Cid) -> bool {
+    a.codec() == b.codec() && a.hash() == b.hash()
+}
+
+/// Whether `a` and `b` name the same CID, whichever of them carries the prefix. Equal bytes
+/// match without parsing; anything else is compared with `same_cid`.
+pub fn cid_eq(a: &[u8], b: &[u8]) -> bool {
+    let (a, b) = (normalize_cid_bytes(a), normalize_cid_bytes(b));
+    a == b || matches!((parse_cid(a), parse_cid(b)), (Some(a), Some(b)) if same_cid(&a, &b))
+}
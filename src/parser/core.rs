@@ -1,10 +1,58 @@
 use std::str;
 
-#[derive(Debug, Clone)]
-pub struct RepoOp {
-    pub action: String,
-    pub path: String,
-    pub cid: Option<Vec<u8>>,
+use smallvec::SmallVec;
+
+use super::cid_utils::{cid_eq, normalize_cid_bytes, parse_cid, same_cid};
+
+/// One entry of a commit's `ops`, borrowed from the frame. Copy the fields out
+/// (`path.to_string()`, `cid.map(<[u8]>::to_vec)`) to keep them past it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepoOp<'a> {
+    pub action: &'a str,
+    pub path: &'a str,
+    pub cid: Option<&'a [u8]>,
+}
+
+/// A commit's ops. Commits rarely carry more than a few, so these stay off the heap.
+pub type RepoOps<'a> = SmallVec<[RepoOp<'a>; 4]>;
+
+impl<'a> RepoOp<'a> {
+    /// NSID half of `collection/rkey` (the whole path if there is no `/`).
+    pub fn collection(&self) -> &'a str {
+        self.path.split_once('/').map_or(self.path, |(c, _)| c)
+    }
+
+    /// Record key half of `collection/rkey`, empty if there is none.
+    pub fn rkey(&self) -> &'a str {
+        self.path.split_once('/').map_or("", |(_, r)| r)
+    }
+
+    /// Creation time (microseconds since the Unix epoch) embedded in a TID rkey.
+    /// `None` for non-TID keys such as `self` on profiles.
+    pub fn tid_timestamp(&self) -> Option<u64> {
+        decode_tid(self.rkey())
+    }
+}
+
+const TID_ALPHABET: &[u8; 32] = b"234567abcdefghijklmnopqrstuvwxyz";
+
+/// Decodes a 13-char base32-sortable TID into its 53-bit microsecond timestamp,
+/// dropping the low 10 clock-id bits.
+pub fn decode_tid(tid: &str) -> Option<u64> {
+    let bytes = tid.as_bytes();
+    if bytes.len() != 13 {
+        return None;
+    }
+    let mut value = 0u64;
+    for (i, &b) in bytes.iter().enumerate() {
+        let digit = TID_ALPHABET.iter().position(|&c| c == b)? as u64;
+        // 13 chars carry 65 bits; the top one must be zero, so the first char is at most 'j'
+        if i == 0 && digit >= 16 {
+            return None;
+        }
+        value = (value << 5) | digit;
+    }
+    Some(value >> 10)
 }
 
 #[derive(Debug)]
@@ -19,102 +67,264 @@ pub struct CommitEnvelope<'a> {
     pub commit: Option<&'a [u8]>,
     pub cid: Option<&'a [u8]>,
     pub record_cid: Option<&'a [u8]>,
-    pub ops: Vec<RepoOp>,
+    pub ops: RepoOps<'a>,
     pub source_type: &'static str,
 }
 
+impl<'a> CommitEnvelope<'a> {
+    /// Number of repo ops; 0 for non-commit events and empty commits.
+    pub fn ops_len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Size of the CAR `blocks` slice in bytes, 0 without one.
+    pub fn blocks_len(&self) -> usize {
+        self.blocks.map_or(0, <[u8]>::len)
+    }
+
+    /// Distinct collection NSIDs of the ops, in first-seen order.
+    pub fn collections(&self) -> impl Iterator<Item = &'a str> + '_ {
+        self.ops.iter().enumerate().filter_map(|(i, op)| {
+            let collection = op.collection();
+            // Commits carry a handful of ops, so a scan back beats a set
+            (!self.ops[..i].iter().any(|prev| prev.collection() == collection)).then_some(collection)
+        })
+    }
+
+    /// True if any op is in exactly the collection `nsid`.
+    pub fn touches_collection(&self, nsid: &str) -> bool {
+        self.ops.iter().any(|op| op.collection() == nsid)
+    }
+
+    /// The `name` and `message` of an `#info` frame; None for every other event.
+    pub fn info(&self) -> Option<InfoMessage<'a>> {
+        if self.t != Some(&b"#info"[..]) {
+            return None;
+        }
+        let raw = self.raw;
+        let (pairs, mut off) = parse_map_header(raw, skip_tags(raw, skip_cbor_value(raw, 0).ok()?).ok()?).ok()?;
+        let mut name = None;
+        let mut message = None;
+        for _ in 0..pairs {
+            let (key, next) = parse_cbor_text(raw, off).ok()?;
+            let value = parse_cbor_text(raw, next).ok().and_then(|(v, _)| str::from_utf8(v).ok());
+            match key {
+                b"name" => name = value,
+                b"message" => message = value,
+                _ => {}
+            }
+            off = skip_cbor_value(raw, next).ok()?;
+        }
+        Some(InfoMessage { name: name?, message })
+    }
+}
+
+/// `name` of the `#info` a relay sends when the requested cursor is older than it keeps.
+pub const OUTDATED_CURSOR: &str = "OutdatedCursor";
+
+/// Body of an `#info` frame: relay notices that aren't repo events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InfoMessage<'a> {
+    pub name: &'a str,
+    pub message: Option<&'a str>,
+}
+
+impl InfoMessage<'_> {
+    /// The cursor asked for is gone; the stream should be restarted from the live head.
+    pub fn is_outdated_cursor(&self) -> bool {
+        self.name == OUTDATED_CURSOR
+    }
+}
+
+/// Why a frame failed to parse. Offsets are byte positions in the buffer handed to the parser.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    UnexpectedEof { offset: usize },
+    WrongMajorType { offset: usize, expected: u8, found: u8 },
+    /// Reserved additional-info values (28..=30) or indefinite length where not allowed.
+    InvalidLength { offset: usize },
+    MissingField(&'static str),
+    InvalidCar { reason: &'static str },
+    /// Arrays/maps/tags nested deeper than `MAX_CBOR_DEPTH`.
+    DepthExceeded { offset: usize },
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::UnexpectedEof { offset } => write!(f, "unexpected end of input at offset {}", offset),
+            ParseError::WrongMajorType { offset, expected, found } => {
+                write!(f, "expected CBOR major type {} at offset {}, found {}", expected, offset, found)
+            }
+            ParseError::InvalidLength { offset } => write!(f, "invalid CBOR length encoding at offset {}", offset),
+            ParseError::MissingField(name) => write!(f, "missing required field '{}'", name),
+            ParseError::InvalidCar { reason } => write!(f, "invalid CAR: {}", reason),
+            ParseError::DepthExceeded { offset } => write!(f, "CBOR nesting too deep at offset {}", offset),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 // --- CBOR LOW-LEVEL HELPERS ---
 
-pub fn parse_cbor_len(buf: &[u8], i: usize) -> Option<(usize, usize)> {
-    if i >= buf.len() { return None; }
-    let addl = buf[i] & 0x1f;
-    let mut idx = i + 1;
-    let len = match addl {
-        n @ 0..=23 => n as usize,
-        24 => { let n = *buf.get(idx)? as usize; idx += 1; n },
-        25 => { let n = u16::from_be_bytes([*buf.get(idx)?, *buf.get(idx+1)?]) as usize; idx += 2; n },
-        26 => { let n = u32::from_be_bytes([*buf.get(idx)?, *buf.get(idx+1)?, *buf.get(idx+2)?, *buf.get(idx+3)?]) as usize; idx += 4; n },
-        27 => { let n = u64::from_be_bytes([
-            *buf.get(idx)?, *buf.get(idx+1)?, *buf.get(idx+2)?, *buf.get(idx+3)?,
-            *buf.get(idx+4)?, *buf.get(idx+5)?, *buf.get(idx+6)?, *buf.get(idx+7)?]) as usize; idx += 8; n },
-        _ => return None,
+fn byte_at(buf: &[u8], i: usize) -> Result<u8, ParseError> {
+    buf.get(i).copied().ok_or(ParseError::UnexpectedEof { offset: i })
+}
+
+fn expect_major(buf: &[u8], i: usize, expected: u8) -> Result<(), ParseError> {
+    let found = byte_at(buf, i)? >> 5;
+    if found != expected {
+        return Err(ParseError::WrongMajorType { offset: i, expected, found });
+    }
+    Ok(())
+}
+
+/// Decodes the argument of the head at `i` as a raw u64 (uint value, tag number or length).
+fn parse_cbor_arg(buf: &[u8], i: usize) -> Result<(u64, usize), ParseError> {
+    let addl = byte_at(buf, i)? & 0x1f;
+    let idx = i + 1;
+    let width = match addl {
+        0..=23 => return Ok((addl as u64, idx)),
+        24 => 1,
+        25 => 2,
+        26 => 4,
+        27 => 8,
+        _ => return Err(ParseError::InvalidLength { offset: i }),
     };
-    Some((len, idx))
+    if idx + width > buf.len() {
+        return Err(ParseError::UnexpectedEof { offset: buf.len() });
+    }
+    let val = buf[idx..idx + width].iter().fold(0u64, |acc, &b| (acc << 8) | b as u64);
+    Ok((val, idx + width))
 }
 
-pub fn parse_cbor_uint(buf: &[u8], i: usize) -> Option<(u64, usize)> {
-    if i >= buf.len() || (buf[i] >> 5) != 0 { return None; }
-    let (val, next) = parse_cbor_len(buf, i)?;
-    Some((val as u64, next))
+pub fn parse_cbor_len(buf: &[u8], i: usize) -> Result<(usize, usize), ParseError> {
+    let (len, next) = parse_cbor_arg(buf, i)?;
+    // 64-bit lengths must still fit the platform's address space (32-bit targets)
+    let len = usize::try_from(len).map_err(|_| ParseError::InvalidLength { offset: i })?;
+    Ok((len, next))
 }
 
-pub fn parse_cbor_bytes(buf: &[u8], i: usize) -> Option<(&[u8], usize)> {
-    if i >= buf.len() || (buf[i] >> 5) != 2 { return None; }
-    let (len, header_end) = parse_cbor_len(buf, i)?;
-    let end = header_end + len;
-    if end > buf.len() { return None; }
-    Some((&buf[header_end..end], end))
+pub fn parse_cbor_uint(buf: &[u8], i: usize) -> Result<(u64, usize), ParseError> {
+    expect_major(buf, i, 0)?;
+    parse_cbor_arg(buf, i)
 }
 
-pub fn parse_cbor_text(buf: &[u8], i: usize) -> Option<(&[u8], usize)> {
-    if i >= buf.len() || (buf[i] >> 5) != 3 { return None; }
+fn parse_cbor_string(buf: &[u8], i: usize, major: u8) -> Result<(&[u8], usize), ParseError> {
+    expect_major(buf, i, major)?;
     let (len, header_end) = parse_cbor_len(buf, i)?;
-    let end = header_end + len;
-    if end > buf.len() { return None; }
-    Some((&buf[header_end..end], end))
+    let end = header_end.checked_add(len).filter(|&e| e <= buf.len())
+        .ok_or(ParseError::UnexpectedEof { offset: buf.len() })?;
+    Ok((&buf[header_end..end], end))
+}
+
+pub fn parse_cbor_bytes(buf: &[u8], i: usize) -> Result<(&[u8], usize), ParseError> {
+    parse_cbor_string(buf, i, 2)
+}
+
+pub fn parse_cbor_text(buf: &[u8], i: usize) -> Result<(&[u8], usize), ParseError> {
+    parse_cbor_string(buf, i, 3)
 }
 
-pub fn parse_cbor_tag(buf: &[u8], i: usize) -> Option<(u64, usize)> {
-    if i >= buf.len() || (buf[i] >> 5) != 6 { return None; }
-    let (tag, next) = parse_cbor_len(buf, i)?;
-    Some((tag as u64, next))
+pub fn parse_cbor_tag(buf: &[u8], i: usize) -> Result<(u64, usize), ParseError> {
+    expect_major(buf, i, 6)?;
+    parse_cbor_arg(buf, i)
 }
 
-pub fn skip_cbor_value(buf: &[u8], i: usize) -> Option<usize> {
-    if i >= buf.len() { return None; }
-    let head = buf[i];
+/// Maximum container nesting accepted by `skip_cbor_value`. Real ATProto records stay in
+/// single digits; the cap keeps hostile frames from exhausting the small worker stacks.
+pub const MAX_CBOR_DEPTH: usize = 128;
+
+pub fn skip_cbor_value(buf: &[u8], i: usize) -> Result<usize, ParseError> {
+    skip_cbor_value_depth(buf, i, 0)
+}
+
+fn skip_cbor_value_depth(buf: &[u8], i: usize, depth: usize) -> Result<usize, ParseError> {
+    if depth > MAX_CBOR_DEPTH {
+        return Err(ParseError::DepthExceeded { offset: i });
+    }
+    let head = byte_at(buf, i)?;
     let major = head >> 5;
     let addl = head & 0x1f;
 
     if addl == 31 {
         // Indefinite length
         match major {
-            2 | 3 | 4 | 5 => {
+            2..=5 => {
                 let mut idx = i + 1;
-                while idx < buf.len() && buf[idx] != 0xff {
-                    idx = skip_cbor_value(buf, idx)?;
+                while byte_at(buf, idx)? != 0xff {
+                    idx = skip_cbor_value_depth(buf, idx, depth + 1)?;
                 }
-                return if idx < buf.len() && buf[idx] == 0xff { Some(idx + 1) } else { None };
+                return Ok(idx + 1);
             }
-            _ => return None,
+            _ => return Err(ParseError::InvalidLength { offset: i }),
         }
     }
 
     match major {
-        0 | 1 => parse_cbor_len(buf, i).map(|(_, n)| n),
-        2 | 3 => {
-            let (len, n) = parse_cbor_len(buf, i)?;
-            Some(n + len)
-        }
+        0 | 1 => parse_cbor_arg(buf, i).map(|(_, n)| n),
+        2 | 3 => parse_cbor_string(buf, i, major).map(|(_, n)| n),
         4 => {
             let (len, mut next) = parse_cbor_len(buf, i)?;
-            for _ in 0..len { next = skip_cbor_value(buf, next)?; }
-            Some(next)
+            // Every element takes at least one byte, so a count larger than what's left is bogus
+            if len > buf.len() - next {
+                return Err(ParseError::UnexpectedEof { offset: buf.len() });
+            }
+            for _ in 0..len { next = skip_cbor_value_depth(buf, next, depth + 1)?; }
+            Ok(next)
         }
         5 => {
             let (len, mut next) = parse_cbor_len(buf, i)?;
-            for _ in 0..(len * 2) { next = skip_cbor_value(buf, next)?; }
-            Some(next)
+            if len.saturating_mul(2) > buf.len() - next {
+                return Err(ParseError::UnexpectedEof { offset: buf.len() });
+            }
+            for _ in 0..len {
+                next = skip_cbor_value_depth(buf, next, depth + 1)?;
+                next = skip_cbor_value_depth(buf, next, depth + 1)?;
+            }
+            Ok(next)
         }
         6 => {
-            let (_, next) = parse_cbor_len(buf, i)?;
-            skip_cbor_value(buf, next)
+            let (_, next) = parse_cbor_arg(buf, i)?;
+            skip_cbor_value_depth(buf, next, depth + 1)
         }
-        7 => { Some(i + 1) } // Simple values
-        _ => None,
+        // Simple values and floats: 24 carries a 1-byte simple value, 25/26/27 are f16/f32/f64
+        _ => parse_cbor_arg(buf, i).map(|(_, n)| n),
     }
 }
 
+// --- OPTION WRAPPERS ---
+// Thin adapters for callers that only care whether a value was there.
+
+pub fn parse_cbor_len_opt(buf: &[u8], i: usize) -> Option<(usize, usize)> {
+    parse_cbor_len(buf, i).ok()
+}
+
+pub fn parse_cbor_uint_opt(buf: &[u8], i: usize) -> Option<(u64, usize)> {
+    parse_cbor_uint(buf, i).ok()
+}
+
+pub fn parse_cbor_bytes_opt(buf: &[u8], i: usize) -> Option<(&[u8], usize)> {
+    parse_cbor_bytes(buf, i).ok()
+}
+
+pub fn parse_cbor_text_opt(buf: &[u8], i: usize) -> Option<(&[u8], usize)> {
+    parse_cbor_text(buf, i).ok()
+}
+
+pub fn parse_cbor_tag_opt(buf: &[u8], i: usize) -> Option<(u64, usize)> {
+    parse_cbor_tag(buf, i).ok()
+}
+
+pub fn skip_cbor_value_opt(buf: &[u8], i: usize) -> Option<usize> {
+    skip_cbor_value(buf, i).ok()
+}
+
+pub fn parse_input_opt(input: &[u8]) -> Option<CommitEnvelope<'_>> {
+    parse_input(input).ok()
+}
+
 // --- VARINT & CAR EXTRACTION ---
 
 fn read_varint(buf: &[u8], mut offset: usize) -> Option<(u64, usize)> {
@@ -132,7 +342,15 @@ fn read_varint(buf: &[u8], mut offset: usize) -> Option<(u64, usize)> {
     None
 }
 
+/// Largest digest a `libipld::Cid` holds (sha2-512, blake2b-512).
+const MAX_DIGEST_LEN: u64 = 64;
+
+/// Length of the binary CID at the start of `input`: a CIDv1 of any codec and hash, or a
+/// CIDv0 (a bare sha2-256 multihash).
 fn parse_raw_cid_len(input: &[u8]) -> Option<usize> {
+    if input.starts_with(&[0x12, 0x20]) {
+        return Some(34);
+    }
     let mut offset = 0;
     let (ver, n1) = read_varint(input, offset)?;
     if ver != 1 { return None; }
@@ -142,260 +360,509 @@ fn parse_raw_cid_len(input: &[u8]) -> Option<usize> {
     let (_, n3) = read_varint(input, offset)?; // hash type
     offset += n3;
     let (mh_len, n4) = read_varint(input, offset)?; // hash len
+    if mh_len > MAX_DIGEST_LEN { return None; }
     offset += n4;
-    Some(offset + (mh_len as usize))
+    offset.checked_add(usize::try_from(mh_len).ok()?)
 }
 
-fn extract_from_car<'a>(data: &'a [u8], target_cid: Option<&[u8]>) -> Option<&'a [u8]> {
-    if data.is_empty() { return None; }
-    
+/// Offset of the first block, past the varint-prefixed CBOR header of a CAR.
+fn car_blocks_start(data: &[u8]) -> Result<usize, ParseError> {
+    if data.is_empty() { return Err(ParseError::InvalidCar { reason: "empty blocks" }); }
+
     // CAR file starts with a varint-encoded header length, followed by the CBOR header
-    let (header_len, v_len) = match read_varint(data, 0) {
-        Some(res) => res,
-        None => return None,
-    };
-    let mut offset = v_len + (header_len as usize);
+    let (header_len, v_len) = read_varint(data, 0)
+        .ok_or(ParseError::InvalidCar { reason: "bad header length varint" })?;
+    usize::try_from(header_len).ok()
+        .and_then(|h| h.checked_add(v_len))
+        .filter(|&o| o <= data.len())
+        .ok_or(ParseError::InvalidCar { reason: "header overruns buffer" })
+}
 
+/// The block at `offset` as (CID, data), None if its CID doesn't parse, and the offset past it.
+type CarBlock<'a> = (Option<(&'a [u8], &'a [u8])>, usize);
+
+fn car_block_at(data: &[u8], offset: usize) -> Result<CarBlock<'_>, ParseError> {
+    let (total_len, v_len) = read_varint(data, offset)
+        .ok_or(ParseError::InvalidCar { reason: "bad block length varint" })?;
+    let block_start = offset + v_len;
+    let block_end = usize::try_from(total_len).ok()
+        .and_then(|t| block_start.checked_add(t))
+        .filter(|&e| e <= data.len())
+        .ok_or(ParseError::InvalidCar { reason: "block overruns buffer" })?;
+
+    let block = parse_raw_cid_len(&data[block_start..block_end])
+        .filter(|&len| len <= block_end - block_start)
+        .map(|len| (&data[block_start..block_start + len], &data[block_start + len..block_end]));
+    Ok((block, block_end))
+}
+
+fn extract_from_car<'a>(data: &'a [u8], target_cid: Option<&[u8]>) -> Result<&'a [u8], ParseError> {
+    let target = target_cid.map(normalize_cid_bytes);
+    // Parsed once; blocks whose bytes differ are compared by codec and multihash
+    let parsed_target = target.and_then(parse_cid);
+    let matches = |cid_bytes: &[u8], t: &[u8]| {
+        cid_bytes == t || parsed_target.as_ref().is_some_and(|p| parse_cid(cid_bytes).is_some_and(|c| same_cid(&c, p)))
+    };
+    let mut offset = car_blocks_start(data)?;
     while offset < data.len() {
-        let (total_len, v_len) = match read_varint(data, offset) {
-            Some(res) => res,
-            None => break,
-        };
-        offset += v_len;
-        let block_start = offset;
-        let block_end = block_start + (total_len as usize);
-        if block_end > data.len() { break; }
-
-        let cid_len = match parse_raw_cid_len(&data[offset..]) {
-            Some(len) => len,
-            None => {
-                offset = block_end;
-                continue;
+        let (block, next) = car_block_at(data, offset)?;
+        if let Some((cid_bytes, block_data)) = block {
+            if target.is_none_or(|t| matches(cid_bytes, t)) {
+                return Ok(block_data);
             }
-        };
-        let cid_bytes = &data[offset..offset + cid_len];
-        let data_start = offset + cid_len;
-        let data_len = (total_len as usize).saturating_sub(cid_len);
-
-        match target_cid {
-            Some(target) => {
-                let clean_target = if target.first() == Some(&0x00) { &target[1..] } else { target };
-                if cid_bytes == clean_target {
-                    if data_start + data_len <= data.len() {
-                        return Some(&data[data_start..data_start + data_len]);
-                    }
+        }
+        offset = next;
+    }
+    Err(ParseError::InvalidCar { reason: "commit block not found" })
+}
+
+/// The first block of a CAR if it is `target_cid`, without looking any further.
+fn first_car_block<'a>(data: &'a [u8], target_cid: &[u8]) -> Option<&'a [u8]> {
+    let offset = car_blocks_start(data).ok()?;
+    match car_block_at(data, offset).ok()?.0 {
+        Some((cid_bytes, block_data)) if cid_eq(cid_bytes, target_cid) => Some(block_data),
+        _ => None,
+    }
+}
+
+/// Skips any leading tags (e.g. tag 42 on CIDs) and returns the offset of the tagged value.
+fn skip_tags(buf: &[u8], mut i: usize) -> Result<usize, ParseError> {
+    while (byte_at(buf, i)? >> 5) == 6 {
+        i = parse_cbor_arg(buf, i)?.1;
+    }
+    Ok(i)
+}
+
+/// Reads a map header at `i`, returning (pair count, offset of first key).
+fn parse_map_header(buf: &[u8], i: usize) -> Result<(usize, usize), ParseError> {
+    expect_major(buf, i, 5)?;
+    parse_cbor_len(buf, i)
+}
+
+/// Reads a `{action, path, cid}` op entry starting at `i`.
+fn parse_repo_op(buf: &[u8], i: usize) -> Result<(RepoOp<'_>, usize), ParseError> {
+    let (o_pairs, mut op_idx) = parse_map_header(buf, i)?;
+    let mut action = "";
+    let mut path = "";
+    let mut op_cid = None;
+    for _ in 0..o_pairs {
+        let (k, n_k) = parse_cbor_text(buf, op_idx)?;
+        op_idx = n_k;
+        match k {
+            b"action" => {
+                if let Ok((v, _)) = parse_cbor_text(buf, op_idx) {
+                    action = str::from_utf8(v).unwrap_or("");
                 }
             }
-            None => {
-                if data_start + data_len <= data.len() {
-                    return Some(&data[data_start..data_start + data_len]);
+            b"path" => {
+                if let Ok((v, _)) = parse_cbor_text(buf, op_idx) {
+                    path = str::from_utf8(v).unwrap_or("");
                 }
             }
+            b"cid" => {
+                // null for deletes, otherwise tag 42 + bytes
+                if let Ok((v, _)) = parse_cbor_bytes(buf, skip_tags(buf, op_idx)?) {
+                    op_cid = Some(v);
+                }
+            }
+            _ => {}
         }
-        offset = block_end;
+        op_idx = skip_cbor_value(buf, op_idx)?;
+    }
+    Ok((RepoOp { action, path, cid: op_cid }, op_idx))
+}
+
+/// Looks up the `sig` field inside a decoded commit block.
+fn signature_from_commit(commit_data: &[u8]) -> Option<&[u8]> {
+    let (c_pairs, mut c_off) = parse_map_header(commit_data, 0).ok()?;
+    for _ in 0..c_pairs {
+        let (k, next_k) = parse_cbor_text(commit_data, c_off).ok()?;
+        c_off = next_k;
+        if k == b"sig" {
+            return parse_cbor_bytes(commit_data, c_off).ok().map(|(v, _)| v);
+        }
+        c_off = skip_cbor_value(commit_data, c_off).ok()?;
     }
     None
 }
 
-// --- MAIN ENTRY POINT ---
+// --- FRAME REWRITING ---
 
-pub fn parse_input<'a>(input: &'a [u8]) -> Option<CommitEnvelope<'a>> {
-    if input.is_empty() { return None; }
+fn write_cbor_head(major: u8, len: usize, out: &mut Vec<u8>) {
+    let m = major << 5;
+    match len {
+        0..=23 => out.push(m | len as u8),
+        24..=0xff => out.extend_from_slice(&[m | 24, len as u8]),
+        0x100..=0xffff => {
+            out.push(m | 25);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.push(m | 26);
+            out.extend_from_slice(&(len as u32).to_be_bytes());
+        }
+        _ => {
+            out.push(m | 27);
+            out.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+}
 
-    let header_end = skip_cbor_value(input, 0)?;
-    let is_firehose = header_end < input.len();
-
-    if is_firehose {
-        let header = &input[0..header_end];
-        let payload = &input[header_end..];
-        
-        let mut event_t = None;
-        let mut op_code = None;
-
-        // Parse Header
-        let mut h_off = 0;
-        while h_off < header.len() && (header[h_off] >> 5) == 6 {
-            let (_, next) = parse_cbor_len(header, h_off).unwrap_or((0, h_off + 1));
-            h_off = next;
+/// Copies a CAR, leaving out the blocks whose CID is in `dropped`.
+fn retain_car_blocks(data: &[u8], dropped: &[Vec<u8>]) -> Result<Vec<u8>, ParseError> {
+    let (header_len, v_len) = read_varint(data, 0)
+        .ok_or(ParseError::InvalidCar { reason: "bad header length varint" })?;
+    let mut offset = usize::try_from(header_len).ok()
+        .and_then(|h| h.checked_add(v_len))
+        .filter(|&o| o <= data.len())
+        .ok_or(ParseError::InvalidCar { reason: "header overruns buffer" })?;
+    let mut out = data[..offset].to_vec();
+    while offset < data.len() {
+        let (total_len, v_len) = read_varint(data, offset)
+            .ok_or(ParseError::InvalidCar { reason: "bad block length varint" })?;
+        let block_start = offset + v_len;
+        let block_end = usize::try_from(total_len).ok()
+            .and_then(|t| block_start.checked_add(t))
+            .filter(|&e| e <= data.len())
+            .ok_or(ParseError::InvalidCar { reason: "block overruns buffer" })?;
+        let cid = parse_raw_cid_len(&data[block_start..block_end])
+            .filter(|&len| len <= block_end - block_start)
+            .map(|len| &data[block_start..block_start + len]);
+        if !cid.is_some_and(|cid| dropped.iter().any(|d| d.as_slice() == cid)) {
+            out.extend_from_slice(&data[offset..block_end]);
         }
+        offset = block_end;
+    }
+    Ok(out)
+}
+
+/// Rebuilds a firehose `#commit` frame with only the ops `keep` accepts. The records of the
+/// other ops are dropped from `blocks`, unless a kept op points at the same CID. The header,
+/// the other fields, the commit block and the MST nodes are copied unchanged, so the
+/// signature still verifies.
+pub fn retain_ops(frame: &[u8], mut keep: impl FnMut(&RepoOp) -> bool) -> Result<Vec<u8>, ParseError> {
+    let header_end = skip_cbor_value(frame, 0)?;
+    let (pairs, mut p_off) = parse_map_header(frame, skip_tags(frame, header_end)?)?;
+    let body_start = p_off;
+    // (key, key offset, value offset, value end)
+    let mut fields = Vec::with_capacity(pairs);
+    for _ in 0..pairs {
+        let (key, v_off) = parse_cbor_text(frame, p_off)?;
+        let end = skip_cbor_value(frame, v_off)?;
+        fields.push((key, p_off, v_off, end));
+        p_off = end;
+    }
 
-        if let Some((h_pairs, it_h_off)) = parse_cbor_len(header, h_off) {
-            h_off = it_h_off;
-            for _ in 0..h_pairs {
-                if let Some((key, next_k)) = parse_cbor_text(header, h_off) {
-                    h_off = next_k;
-                    let key_str = str::from_utf8(key).unwrap_or("");
-                    match key_str {
-                        "t" => {
-                            if let Some((v, n)) = parse_cbor_text(header, h_off) {
-                                event_t = Some(v); h_off = n;
-                            } else { h_off = skip_cbor_value(header, h_off).unwrap_or(h_off + 1); }
-                        }
-                        "op" => {
-                            if let Some((v, n)) = parse_cbor_uint(header, h_off) {
-                                op_code = Some(v); h_off = n;
-                            } else { h_off = skip_cbor_value(header, h_off).unwrap_or(h_off + 1); }
-                        }
-                        _ => h_off = skip_cbor_value(header, h_off).unwrap_or(h_off + 1),
-                    }
-                } else { break; }
+    let mut kept = Vec::new();
+    let mut kept_cids = Vec::new();
+    let mut dropped_cids = Vec::new();
+    if let Some(&(_, _, v_off, _)) = fields.iter().find(|f| f.0 == b"ops") {
+        expect_major(frame, v_off, 4)?;
+        let (n, mut op_idx) = parse_cbor_len(frame, v_off)?;
+        for _ in 0..n {
+            let (op, next) = parse_repo_op(frame, op_idx)?;
+            // Op CIDs carry the 0x00 multibase prefix that block CIDs don't
+            let cid = op.cid.map(|c| normalize_cid_bytes(c).to_vec());
+            if keep(&op) {
+                kept.push(op_idx..next);
+                kept_cids.extend(cid);
+            } else {
+                dropped_cids.extend(cid);
             }
+            op_idx = next;
         }
+    }
+    dropped_cids.retain(|cid| !kept_cids.contains(cid));
 
-        // Parse Payload
-        let mut p_off = 0;
-        while p_off < payload.len() && (payload[p_off] >> 5) == 6 {
-            let (_, next) = parse_cbor_len(payload, p_off).unwrap_or((0, p_off + 1));
-            p_off = next;
+    let mut out = frame[..body_start].to_vec();
+    for (key, k_off, v_off, end) in fields {
+        out.extend_from_slice(&frame[k_off..v_off]);
+        match key {
+            b"ops" => {
+                write_cbor_head(4, kept.len(), &mut out);
+                for range in &kept {
+                    out.extend_from_slice(&frame[range.clone()]);
+                }
+            }
+            b"blocks" if !dropped_cids.is_empty() => {
+                let (car, _) = parse_cbor_bytes(frame, v_off)?;
+                let car = retain_car_blocks(car, &dropped_cids)?;
+                write_cbor_head(2, car.len(), &mut out);
+                out.extend_from_slice(&car);
+            }
+            _ => out.extend_from_slice(&frame[v_off..end]),
         }
+    }
+    out.extend_from_slice(&frame[p_off..]);
+    Ok(out)
+}
 
-        let (pairs, it_p_off) = parse_cbor_len(payload, p_off).unwrap_or((0, p_off));
-        p_off = it_p_off;
-        let mut did = None;
-        let mut seq = None;
-        let mut blocks_bytes = None;
-        let mut commit_cid = None;
-        let mut signature = None;
-        let mut ops = Vec::new();
+// --- PHASE TIMING ---
 
-        for _ in 0..pairs {
-            if let Some((key, next_k)) = parse_cbor_text(payload, p_off) {
-                p_off = next_k;
-                let key_str = str::from_utf8(key).unwrap_or("");
-
-                match key_str {
-                    "repo" | "did" => {
-                        if let Some((v, n)) = parse_cbor_text(payload, p_off).or_else(|| parse_cbor_bytes(payload, p_off)) {
-                            did = Some(v); p_off = n;
-                        } else { p_off = skip_cbor_value(payload, p_off).unwrap_or(p_off + 1); }
-                    }
-                    "ops" => {
-                        if let Some((op_len, next_op)) = parse_cbor_len(payload, p_off) {
-                            p_off = skip_cbor_value(payload, p_off).unwrap_or(next_op);
-                            let mut op_idx = next_op;
-                            for _ in 0..op_len {
-                                if let Some((o_pairs, next_o)) = parse_cbor_len(payload, op_idx) {
-                                    op_idx = next_o;
-                                    let mut action = String::new();
-                                    let mut path = String::new();
-                                    let mut op_cid = None;
-                                    for _ in 0..o_pairs {
-                                        if let Some((k, n_k)) = parse_cbor_text(payload, op_idx) {
-                                            op_idx = n_k;
-                                            let k_str = str::from_utf8(k).unwrap_or("");
-                                            match k_str {
-                                                "action" => {
-                                                    if let Some((v, n)) = parse_cbor_text(payload, op_idx) {
-                                                        action = str::from_utf8(v).unwrap_or("").to_string();
-                                                        op_idx = n;
-                                                    } else { op_idx = skip_cbor_value(payload, op_idx).unwrap_or(op_idx + 1); }
-                                                }
-                                                "path" => {
-                                                    if let Some((v, n)) = parse_cbor_text(payload, op_idx) {
-                                                        path = str::from_utf8(v).unwrap_or("").to_string();
-                                                        op_idx = n;
-                                                    } else { op_idx = skip_cbor_value(payload, op_idx).unwrap_or(op_idx + 1); }
-                                                }
-                                                "cid" => {
-                                                    let mut it_op_idx = op_idx;
-                                                    if payload.get(it_op_idx) == Some(&0xd8) && payload.get(it_op_idx+1) == Some(&0x2a) {
-                                                        it_op_idx += 2;
-                                                    }
-                                                    if let Some((v, n)) = parse_cbor_bytes(payload, it_op_idx) {
-                                                        op_cid = Some(v.to_vec());
-                                                        op_idx = n;
-                                                    } else { op_idx = skip_cbor_value(payload, op_idx).unwrap_or(op_idx + 1); }
-                                                }
-                                                _ => op_idx = skip_cbor_value(payload, op_idx).unwrap_or(op_idx + 1),
-                                            }
-                                        } else { break; }
-                                    }
-                                    ops.push(RepoOp { action, path, cid: op_cid });
-                                } else { break; }
-                            }
-                        } else { p_off = skip_cbor_value(payload, p_off).unwrap_or(p_off + 1); }
-                    }
-                    "seq" => {
-                        if let Some((v, n)) = parse_cbor_uint(payload, p_off) {
-                            seq = Some(v); p_off = n;
-                        } else { p_off = skip_cbor_value(payload, p_off).unwrap_or(p_off + 1); }
-                    }
-                    "blocks" => {
-                        if let Some((v, n)) = parse_cbor_bytes(payload, p_off) {
-                            blocks_bytes = Some(v); p_off = n;
-                        } else { p_off = skip_cbor_value(payload, p_off).unwrap_or(p_off + 1); }
-                    }
-                    "commit" => {
-                        // Handle potential tag 42 before the CID bytes
-                        let mut it_p_off = p_off;
-                        if payload.get(it_p_off) == Some(&0xd8) && payload.get(it_p_off+1) == Some(&0x2a) {
-                            it_p_off += 2;
-                        }
-                        if let Some((v, n)) = parse_cbor_bytes(payload, it_p_off) {
-                            commit_cid = Some(v); p_off = n;
-                        } else { p_off = skip_cbor_value(payload, p_off).unwrap_or(p_off + 1); }
-                    }
-                    "sig" => {
-                        if let Some((v, n)) = parse_cbor_bytes(payload, p_off) {
-                            signature = Some(v); p_off = n;
-                        } else {
-                            // Try skipping tag if signature is tagged for some reason
-                            let mut it_p_off = p_off;
-                            if it_p_off < payload.len() && (payload[it_p_off] >> 5) == 6 {
-                                let (_, next) = parse_cbor_len(payload, it_p_off).unwrap_or((0, it_p_off + 1));
-                                it_p_off = next;
-                            }
-                            if let Some((v, n)) = parse_cbor_bytes(payload, it_p_off) {
-                                signature = Some(v); p_off = n;
-                            } else {
-                                p_off = skip_cbor_value(payload, p_off).unwrap_or(p_off + 1);
-                            }
-                        }
-                    }
-                    _ => p_off = skip_cbor_value(payload, p_off).unwrap_or(p_off + 1),
-                }
-            } else { break; }
+/// Parse phases `parse_input_timed` accounts time to.
+#[derive(Clone, Copy)]
+enum Phase {
+    Header,
+    Payload,
+    Ops,
+    Car,
+    Signature,
+}
+
+/// Phase hooks inside `parse_input`. Each `lap` charges the time since the previous one to
+/// `phase`. The plain parser uses `NoClock`, whose hooks compile away.
+trait ParseClock {
+    fn lap(&mut self, phase: Phase);
+}
+
+struct NoClock;
+
+impl ParseClock for NoClock {
+    #[inline(always)]
+    fn lap(&mut self, _phase: Phase) {}
+}
+
+/// Nanoseconds `parse_input_timed` spent in each phase of one frame.
+#[cfg(feature = "profiling")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseTimings {
+    /// Skipping the frame header and reading `t`/`op` from it
+    pub header_ns: u64,
+    /// Payload fields other than `ops`
+    pub payload_ns: u64,
+    /// The `ops` array
+    pub ops_ns: u64,
+    /// Finding the commit block in the CAR `blocks`
+    pub car_ns: u64,
+    /// Pulling `sig` out of the commit block when the payload has none
+    pub signature_ns: u64,
+    /// The whole call, including anything between phases
+    pub total_ns: u64,
+}
+
+#[cfg(feature = "profiling")]
+impl ParseTimings {
+    /// Adds another frame's timings, for totals over a batch.
+    pub fn accumulate(&mut self, other: &ParseTimings) {
+        self.header_ns += other.header_ns;
+        self.payload_ns += other.payload_ns;
+        self.ops_ns += other.ops_ns;
+        self.car_ns += other.car_ns;
+        self.signature_ns += other.signature_ns;
+        self.total_ns += other.total_ns;
+    }
+}
+
+#[cfg(feature = "profiling")]
+struct Stopwatch {
+    last: std::time::Instant,
+    timings: ParseTimings,
+}
+
+#[cfg(feature = "profiling")]
+impl ParseClock for Stopwatch {
+    fn lap(&mut self, phase: Phase) {
+        let now = std::time::Instant::now();
+        let ns = now.duration_since(self.last).as_nanos() as u64;
+        self.last = now;
+        let t = &mut self.timings;
+        match phase {
+            Phase::Header => t.header_ns += ns,
+            Phase::Payload => t.payload_ns += ns,
+            Phase::Ops => t.ops_ns += ns,
+            Phase::Car => t.car_ns += ns,
+            Phase::Signature => t.signature_ns += ns,
         }
+    }
+}
 
-        let extracted = blocks_bytes.and_then(|b| extract_from_car(b, commit_cid));
-        // If signature is missing from top-level (standard for firehose), extract it from commit object
-        if signature.is_none() {
-            if let Some(commit_data) = extracted {
-                // The commit block is a CBOR map (a6 ...). We search it for "sig".
-                let mut c_off = 0;
-                if let Some((c_pairs, next_c)) = parse_cbor_len(commit_data, c_off) {
-                    c_off = next_c;
-                    for _ in 0..c_pairs {
-                        if let Some((k, next_k)) = parse_cbor_text(commit_data, c_off) {
-                            c_off = next_k;
-                            if k == b"sig" {
-                                if let Some((sig_val, _)) = parse_cbor_bytes(commit_data, c_off) {
-                                    signature = Some(sig_val);
-                                    break;
-                                } 
-                            }
-                            c_off = skip_cbor_value(commit_data, c_off).unwrap_or(c_off + 1);
-                        } else { break; }
-                    }
-                }
+/// `parse_input_opt` that also reports where the time went. Only built with the
+/// `profiling` feature; `parse_input` itself carries no timing code either way.
+#[cfg(feature = "profiling")]
+pub fn parse_input_timed(input: &[u8]) -> (Option<CommitEnvelope<'_>>, ParseTimings) {
+    let start = std::time::Instant::now();
+    let mut clock = Stopwatch { last: start, timings: ParseTimings::default() };
+    let envelope = parse_input_with(input, false, &mut clock).ok();
+    let mut timings = clock.timings;
+    timings.total_ns = start.elapsed().as_nanos() as u64;
+    (envelope, timings)
+}
+
+// --- MAIN ENTRY POINT ---
+
+pub fn parse_input<'a>(input: &'a [u8]) -> Result<CommitEnvelope<'a>, ParseError> {
+    parse_input_with(input, false, &mut NoClock)
+}
+
+/// `parse_input` for callers that only verify signatures. `ops` is left empty, and when the
+/// payload carries a top-level `sig` and the commit is the first CAR block, that block is taken
+/// without walking the CAR for it. Everything else comes out as from `parse_input`.
+pub fn parse_for_verify<'a>(input: &'a [u8]) -> Result<CommitEnvelope<'a>, ParseError> {
+    parse_input_with(input, true, &mut NoClock)
+}
+
+fn parse_input_with<'a, C: ParseClock>(input: &'a [u8], verify_only: bool, clock: &mut C) -> Result<CommitEnvelope<'a>, ParseError> {
+    match peek_with(input, clock)? {
+        Some(peek) => parse_full_with(peek, input, verify_only, clock),
+        None => {
+            let extracted = extract_from_car(input, None).ok();
+            clock.lap(Phase::Car);
+            Ok(CommitEnvelope {
+                did: None, sequence: None, signature: None, t: None, op: None,
+                raw: input, blocks: Some(input), commit: extracted,
+                cid: None, record_cid: None,
+                ops: RepoOps::new(),
+                source_type: "car_file",
+            })
+        }
+    }
+}
+
+// --- TWO-STAGE PARSE ---
+
+/// What a firehose frame is: its header and the top-level payload fields, found without
+/// parsing `ops` or looking inside `blocks`. Enough to route, dedupe or drop a frame;
+/// `parse_full` finishes the job for the ones that need more.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FramePeek<'a> {
+    pub t: Option<&'a [u8]>,
+    pub op: Option<u64>,
+    pub seq: Option<u64>,
+    /// `repo` of a commit, `did` of other events
+    pub did: Option<&'a [u8]>,
+    /// `commit` CID bytes, past any tag 42
+    pub cid: Option<&'a [u8]>,
+    signature: Option<&'a [u8]>,
+    blocks: Option<&'a [u8]>,
+    // Offset of the `ops` value, parsed only by `parse_full`
+    ops_at: Option<usize>,
+}
+
+impl FramePeek<'_> {
+    pub fn is_commit(&self) -> bool {
+        matches!(self.t, Some(b"#commit") | Some(b"commit"))
+    }
+}
+
+/// Header and top-level payload fields of a firehose frame; see `FramePeek`. None if the
+/// frame is malformed that far, or is a bare CAR rather than a header and payload.
+pub fn peek_frame(input: &[u8]) -> Option<FramePeek<'_>> {
+    peek_with(input, &mut NoClock).ok().flatten()
+}
+
+/// First stage of `parse_input`. `Ok(None)` when the input is a single CBOR value, i.e. not
+/// a firehose frame.
+fn peek_with<'a, C: ParseClock>(input: &'a [u8], clock: &mut C) -> Result<Option<FramePeek<'a>>, ParseError> {
+    if input.is_empty() { return Err(ParseError::UnexpectedEof { offset: 0 }); }
+
+    let header_end = skip_cbor_value(input, 0)?;
+    if header_end >= input.len() {
+        return Ok(None);
+    }
+    let header = &input[0..header_end];
+    let mut peek = FramePeek { t: None, op: None, seq: None, did: None, cid: None, signature: None, blocks: None, ops_at: None };
+
+    // Parse Header
+    let (h_pairs, mut h_off) = parse_map_header(header, skip_tags(header, 0)?)?;
+    for _ in 0..h_pairs {
+        let (key, next_k) = parse_cbor_text(header, h_off)?;
+        h_off = next_k;
+        match key {
+            b"t" => { peek.t = parse_cbor_text(header, h_off).ok().map(|(v, _)| v); }
+            b"op" => { peek.op = parse_cbor_uint(header, h_off).ok().map(|(v, _)| v); }
+            _ => {}
+        }
+        h_off = skip_cbor_value(header, h_off)?;
+    }
+    clock.lap(Phase::Header);
+
+    // Parse Payload (offsets stay relative to `input`, so errors point into the whole frame).
+    // `blocks` is a byte string and `ops` is only stepped over, so this stays cheap.
+    let (pairs, mut p_off) = parse_map_header(input, skip_tags(input, header_end)?)?;
+    for _ in 0..pairs {
+        let (key, next_k) = parse_cbor_text(input, p_off)?;
+        p_off = next_k;
+
+        match key {
+            b"repo" | b"did" => {
+                peek.did = parse_cbor_text(input, p_off).or_else(|_| parse_cbor_bytes(input, p_off)).ok().map(|(v, _)| v);
+            }
+            b"ops" => { peek.ops_at = Some(p_off); }
+            b"seq" => { peek.seq = parse_cbor_uint(input, p_off).ok().map(|(v, _)| v); }
+            b"blocks" => { peek.blocks = parse_cbor_bytes(input, p_off).ok().map(|(v, _)| v); }
+            b"commit" => {
+                // Handle potential tag 42 before the CID bytes
+                peek.cid = parse_cbor_bytes(input, skip_tags(input, p_off)?).ok().map(|(v, _)| v);
             }
+            b"sig" => {
+                // Tolerate a tagged signature
+                peek.signature = parse_cbor_bytes(input, skip_tags(input, p_off)?).ok().map(|(v, _)| v);
+            }
+            _ => {}
         }
-        
-        Some(CommitEnvelope {
-            did, sequence: seq, signature, t: event_t, op: op_code,
-            raw: input, blocks: blocks_bytes, commit: extracted,
-            cid: commit_cid, record_cid: None, // Will be improved later
-            ops,
-            source_type: "firehose",
-        })
-    } else {
-        let extracted = extract_from_car(input, None);
-        Some(CommitEnvelope {
-            did: None, sequence: None, signature: None, t: None, op: None,
-            raw: input, blocks: Some(input), commit: extracted,
-            cid: None, record_cid: None,
-            ops: Vec::new(),
-            source_type: "car_file",
-        })
+        p_off = skip_cbor_value(input, p_off)?;
     }
-}
\ No newline at end of file
+    clock.lap(Phase::Payload);
+    Ok(Some(peek))
+}
+
+/// Second stage of `parse_input` for a frame `peek_frame` accepted: the `ops`, the commit
+/// block out of the CAR and its signature. `input` must be the frame `peek` came from.
+pub fn parse_full<'a>(peek: FramePeek<'a>, input: &'a [u8]) -> Result<CommitEnvelope<'a>, ParseError> {
+    parse_full_with(peek, input, false, &mut NoClock)
+}
+
+fn parse_full_with<'a, C: ParseClock>(peek: FramePeek<'a>, input: &'a [u8], verify_only: bool, clock: &mut C) -> Result<CommitEnvelope<'a>, ParseError> {
+    let mut ops = RepoOps::new();
+    if let Some(ops_at) = peek.ops_at.filter(|_| !verify_only) {
+        if let Ok((op_len, next_op)) = expect_major(input, ops_at, 4).and_then(|_| parse_cbor_len(input, ops_at)) {
+            let mut op_idx = next_op;
+            for _ in 0..op_len {
+                let (op, next) = parse_repo_op(input, op_idx)?;
+                ops.push(op);
+                op_idx = next;
+            }
+        }
+        clock.lap(Phase::Ops);
+    }
+
+    let FramePeek { t: event_t, op: op_code, seq, did, cid: commit_cid, mut signature, blocks: blocks_bytes, .. } = peek;
+    let is_commit = peek.is_commit();
+    if is_commit && did.is_none() {
+        return Err(ParseError::MissingField("repo"));
+    }
+    // A #sync carries the repo's current commit as the only block of its CAR
+    let is_sync = matches!(event_t, Some(b"#sync"));
+    if is_sync && did.is_none() {
+        return Err(ParseError::MissingField("did"));
+    }
+
+    // With the signature already in hand the commit is only needed to hash, and it is
+    // almost always the first block
+    let direct = match (blocks_bytes, commit_cid) {
+        (Some(b), Some(cid)) if verify_only && is_commit && signature.is_some() => first_car_block(b, cid),
+        _ => None,
+    };
+    let extracted = match blocks_bytes {
+        _ if direct.is_some() => direct,
+        Some(b) if is_commit || is_sync => Some(extract_from_car(b, commit_cid)?),
+        Some(b) => extract_from_car(b, commit_cid).ok(),
+        None if is_commit || is_sync => return Err(ParseError::MissingField("blocks")),
+        None => None,
+    };
+    clock.lap(Phase::Car);
+
+    // If signature is missing from top-level (standard for firehose), extract it from commit object
+    if signature.is_none() {
+        signature = extracted.and_then(signature_from_commit);
+    }
+    clock.lap(Phase::Signature);
+
+    Ok(CommitEnvelope {
+        did, sequence: seq, signature, t: event_t, op: op_code,
+        raw: input, blocks: blocks_bytes, commit: extracted,
+        cid: commit_cid, record_cid: None, // Will be improved later
+        ops,
+        source_type: "firehose",
+    })
+}
@@ -0,0 +1,209 @@
+use std::fmt;
+use std::str;
+
+use super::core::{parse_cbor_len_opt, parse_cbor_text_opt, skip_cbor_value_opt};
+
+/// Reply references of a post (`reply.root.uri` / `reply.parent.uri`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplyRef {
+    pub root_uri: String,
+    pub parent_uri: String,
+}
+
+/// A decoded view of the lexicon records we see most often on the firehose.
+/// Anything else with a `$type` is surfaced as `Unknown` so callers can still label it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordView {
+    Post {
+        text: String,
+        created_at: Option<String>,
+        reply: Option<ReplyRef>,
+        langs: Vec<String>,
+    },
+    Like {
+        subject_uri: String,
+        subject_cid: Option<String>,
+    },
+    Follow {
+        subject: String,
+    },
+    Profile {
+        display_name: Option<String>,
+        description: Option<String>,
+    },
+    Unknown {
+        type_str: String,
+    },
+}
+
+impl RecordView {
+    /// Short lexicon-ish label used in logs and the TUI.
+    pub fn kind(&self) -> &str {
+        match self {
+            RecordView::Post { .. } => "post",
+            RecordView::Like { .. } => "like",
+            RecordView::Follow { .. } => "follow",
+            RecordView::Profile { .. } => "profile",
+            RecordView::Unknown { type_str } => type_str,
+        }
+    }
+}
+
+impl fmt::Display for RecordView {
+    /// Single-line summary, truncated on char boundaries so CJK/emoji stay intact.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordView::Post { text, reply, .. } => {
+                let prefix = if reply.is_some() { "reply" } else { "post" };
+                write!(f, "{}: {}", prefix, truncate_chars(&flatten(text), 120))
+            }
+            RecordView::Like { subject_uri, .. } => write!(f, "like: {}", subject_uri),
+            RecordView::Follow { subject } => write!(f, "follow: {}", subject),
+            RecordView::Profile { display_name, description } => {
+                let name = display_name.as_deref().unwrap_or("");
+                let desc = description.as_deref().map(flatten).unwrap_or_default();
+                write!(f, "profile: {} {}", name, truncate_chars(&desc, 80))
+            }
+            RecordView::Unknown { type_str } => write!(f, "{}", type_str),
+        }
+    }
+}
+
+fn flatten(s: &str) -> String {
+    s.replace(['\n', '\r'], " ")
+}
+
+fn truncate_chars(s: &str, max: usize) -> String {
+    match s.char_indices().nth(max) {
+        Some((idx, _)) => format!("{}...", &s[..idx]),
+        None => s.to_string(),
+    }
+}
+
+// --- DECODING ---
+
+fn text_at(buf: &[u8], i: usize) -> Option<(String, usize)> {
+    let (v, n) = parse_cbor_text_opt(buf, i)?;
+    Some((String::from_utf8_lossy(v).into_owned(), n))
+}
+
+/// Iterates the key/value pairs of a CBOR map at `i`, calling `f(key, value_offset)`.
+/// Returns the offset just past the map.
+fn walk_map<'a>(buf: &'a [u8], i: usize, mut f: impl FnMut(&'a [u8], usize)) -> Option<usize> {
+    if (*buf.get(i)? >> 5) != 5 { return None; }
+    let (pairs, mut off) = parse_cbor_len_opt(buf, i)?;
+    for _ in 0..pairs {
+        let (key, next_k) = parse_cbor_text_opt(buf, off)?;
+        f(key, next_k);
+        off = skip_cbor_value_opt(buf, next_k)?;
+    }
+    Some(off)
+}
+
+/// Reads `{ uri, cid }` (com.atproto.repo.strongRef).
+fn strong_ref(buf: &[u8], i: usize) -> Option<(String, Option<String>)> {
+    let mut uri = None;
+    let mut cid = None;
+    walk_map(buf, i, |k, v| match k {
+        b"uri" => uri = text_at(buf, v).map(|(s, _)| s),
+        b"cid" => cid = text_at(buf, v).map(|(s, _)| s),
+        _ => {}
+    })?;
+    Some((uri?, cid))
+}
+
+fn string_array(buf: &[u8], i: usize) -> Vec<String> {
+    let mut out = Vec::new();
+    if buf.get(i).map(|b| b >> 5) != Some(4) { return out; }
+    if let Some((len, mut off)) = parse_cbor_len_opt(buf, i) {
+        for _ in 0..len {
+            match text_at(buf, off) {
+                Some((s, n)) => { out.push(s); off = n; }
+                None => match skip_cbor_value_opt(buf, off) {
+                    Some(n) => off = n,
+                    None => break,
+                },
+            }
+        }
+    }
+    out
+}
+
+/// Decodes a DAG-CBOR record block into a `RecordView`.
+///
+/// Returns `None` if the block is not a map or carries no `$type` (commit objects,
+/// MST nodes). Unrecognised fields are skipped, and invalid UTF-8 is replaced rather
+/// than rejected, so a slightly odd record still yields something printable.
+pub fn decode_record(block: &[u8]) -> Option<RecordView> {
+    let mut type_str: Option<&[u8]> = None;
+    let mut text = None;
+    let mut created_at = None;
+    let mut reply = None;
+    let mut langs = Vec::new();
+    let mut subject = None;
+    let mut subject_ref = None;
+    let mut display_name = None;
+    let mut description = None;
+
+    walk_map(block, 0, |k, v| match k {
+        b"$type" => type_str = parse_cbor_text_opt(block, v).map(|(s, _)| s),
+        b"text" => text = text_at(block, v).map(|(s, _)| s),
+        b"createdAt" => created_at = text_at(block, v).map(|(s, _)| s),
+        b"langs" => langs = string_array(block, v),
+        b"displayName" => display_name = text_at(block, v).map(|(s, _)| s),
+        b"description" => description = text_at(block, v).map(|(s, _)| s),
+        b"subject" => {
+            // follow/block use a bare DID, like/repost use a strongRef
+            subject = text_at(block, v).map(|(s, _)| s);
+            if subject.is_none() {
+                subject_ref = strong_ref(block, v);
+            }
+        }
+        b"reply" => {
+            let mut root = None;
+            let mut parent = None;
+            walk_map(block, v, |rk, rv| match rk {
+                b"root" => root = strong_ref(block, rv).map(|(u, _)| u),
+                b"parent" => parent = strong_ref(block, rv).map(|(u, _)| u),
+                _ => {}
+            });
+            if let (Some(root_uri), Some(parent_uri)) = (root, parent) {
+                reply = Some(ReplyRef { root_uri, parent_uri });
+            }
+        }
+        _ => {}
+    })?;
+
+    let type_str = str::from_utf8(type_str?).ok()?;
+    let view = match type_str {
+        "app.bsky.feed.post" => RecordView::Post {
+            text: text.unwrap_or_default(),
+            created_at,
+            reply,
+            langs,
+        },
+        "app.bsky.feed.like" => match subject_ref {
+            Some((subject_uri, subject_cid)) => RecordView::Like { subject_uri, subject_cid },
+            None => RecordView::Unknown { type_str: type_str.to_string() },
+        },
+        "app.bsky.graph.follow" => match subject {
+            Some(subject) => RecordView::Follow { subject },
+            None => RecordView::Unknown { type_str: type_str.to_string() },
+        },
+        "app.bsky.actor.profile" => RecordView::Profile { display_name, description },
+        other => RecordView::Unknown { type_str: other.to_string() },
+    };
+    Some(view)
+}
+
+/// Finds and decodes the first record block in a CAR slice (e.g. `CommitEnvelope::blocks`).
+/// `cid` narrows the search to a specific block (typically an op's record CID).
+pub fn decode_record_from_car(blocks: &[u8], cid: Option<&[u8]>) -> Option<RecordView> {
+    let store = crate::mst::car::CarStore::new(blocks);
+    if let Some(cid) = cid {
+        if let Some(view) = store.get_block(cid).and_then(decode_record) {
+            return Some(view);
+        }
+    }
+    store.blocks.values().find_map(|b| decode_record(b))
+}
@@ -1,11 +1,23 @@
+use crate::net::HostHealth;
 use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::{Deref, DerefMut};
 use memmap2::MmapMut;
 use std::path::Path;
 use tracing::{info, warn};
+use url::Url;
 
 pub const ENTRY_SIZE: usize = 256;
 pub const URL_MAX_LEN: usize = 200;
 
+// File header: magic(8) + version u32, zero padded to one entry so entries stay aligned.
+// Version 1 files have no header and start directly with entry 0; they are upgraded on open.
+const HEADER_SIZE: usize = ENTRY_SIZE;
+const MAGIC: &[u8; 8] = b"PDSLEDGR";
+const FORMAT_VERSION: u32 = 2;
+/// The checksum covers every byte before it.
+const CHECKSUM_OFFSET: usize = ENTRY_SIZE - 4;
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
 pub struct PdsEntry {
@@ -21,12 +33,46 @@ pub struct PdsEntry {
     pub last_attempt: u64,
     /// Unix timestamp until which this node is penalized
     pub penalty_until: u64,
+    /// Smoothed messages per second while connected
+    pub msg_rate: f64,
+    /// `FLAG_*` bits
+    pub flags: u32,
     /// Reserved for future metrics
-    pub reserved: [u8; 24],
+    pub reserved: [u8; 8],
+    /// First 4 bytes (LE) of the BLAKE3 hash of the rest of the entry
+    pub checksum: u32,
+}
+
+/// The node failed in a way that won't fix itself (see `BackoffPolicy::should_blacklist`)
+pub const FLAG_BLACKLISTED: u32 = 1;
+
+const _: () = assert!(std::mem::size_of::<PdsEntry>() == ENTRY_SIZE);
+
+/// Checks that `url` is something a PDS can be reached at: an `http(s)` or `ws(s)` URL
+/// with a host. Surrounding whitespace should be trimmed first.
+pub fn validate_url(url: &str) -> anyhow::Result<()> {
+    if url.is_empty() {
+        anyhow::bail!("empty PDS URL");
+    }
+    let parsed = Url::parse(url).map_err(|e| anyhow::anyhow!("invalid PDS URL {:?}: {}", url, e))?;
+    if !matches!(parsed.scheme(), "https" | "http" | "wss" | "ws") {
+        anyhow::bail!("PDS URL {:?} has scheme {}, expected http(s) or ws(s)", url, parsed.scheme());
+    }
+    if parsed.host_str().is_none_or(str::is_empty) {
+        anyhow::bail!("PDS URL {:?} has no host", url);
+    }
+    Ok(())
 }
 
 impl PdsEntry {
+    /// An entry for `url_str` with fresh health, or None if it isn't a valid PDS URL
+    /// (see `validate_url`) once trimmed, or doesn't fit.
     pub fn new(url_str: &str) -> Option<Self> {
+        let url_str = url_str.trim();
+        if let Err(e) = validate_url(url_str) {
+            warn!("Skipping PDS entry: {}", e);
+            return None;
+        }
         if url_str.len() >= URL_MAX_LEN {
             warn!("URL too long ({} chars): {}", url_str.len(), url_str);
             return None;
@@ -48,14 +94,98 @@ impl PdsEntry {
             last_success: 0,
             last_attempt: 0,
             penalty_until: 0,
-            reserved: [0u8; 24],
-        })
+            msg_rate: 0.0,
+            flags: 0,
+            reserved: [0u8; 8],
+            checksum: 0,
+        }.sealed())
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        // SAFETY: repr(C) with explicit padding fields, so all ENTRY_SIZE bytes are initialized.
+        unsafe { std::slice::from_raw_parts(self as *const Self as *const u8, ENTRY_SIZE) }
+    }
+
+    fn compute_checksum(&self) -> u32 {
+        let hash = blake3::hash(&self.as_bytes()[..CHECKSUM_OFFSET]);
+        u32::from_le_bytes(hash.as_bytes()[..4].try_into().unwrap())
+    }
+
+    fn sealed(mut self) -> Self {
+        self.seal();
+        self
+    }
+
+    /// Recomputes the checksum after the fields were changed directly.
+    pub fn seal(&mut self) {
+        self.checksum = self.compute_checksum();
+    }
+
+    /// An unused slot: every byte zero.
+    pub fn is_empty(&self) -> bool {
+        self.as_bytes().iter().all(|&b| b == 0)
+    }
+
+    /// The checksum matches, e.g. the entry wasn't torn by a crash mid-write.
+    pub fn is_valid(&self) -> bool {
+        self.checksum == self.compute_checksum()
+    }
+
+    /// The slot names a PDS: its URL isn't empty or only whitespace.
+    pub fn has_url(&self) -> bool {
+        !self.get_url().trim().is_empty()
     }
 
     pub fn get_url(&self) -> String {
         let len = self.url.iter().position(|&b| b == 0).unwrap_or(URL_MAX_LEN);
         String::from_utf8_lossy(&self.url[..len]).to_string()
     }
+
+    pub fn health(&self) -> HostHealth {
+        HostHealth {
+            failures: self.fail_count,
+            last_success: self.last_success,
+            last_attempt: self.last_attempt,
+            penalty_until: self.penalty_until,
+            msg_rate: self.msg_rate,
+            blacklisted: self.flags & FLAG_BLACKLISTED != 0,
+        }
+    }
+
+    pub fn set_health(&mut self, health: &HostHealth) {
+        self.fail_count = health.failures;
+        self.last_success = health.last_success;
+        self.last_attempt = health.last_attempt;
+        self.penalty_until = health.penalty_until;
+        self.msg_rate = health.msg_rate;
+        if health.blacklisted {
+            self.flags |= FLAG_BLACKLISTED;
+        } else {
+            self.flags &= !FLAG_BLACKLISTED;
+        }
+    }
+}
+
+/// Mutable access to a ledger entry; the checksum is updated when it goes out of scope.
+pub struct EntryMut<'a>(&'a mut PdsEntry);
+
+impl Deref for EntryMut<'_> {
+    type Target = PdsEntry;
+    fn deref(&self) -> &PdsEntry {
+        self.0
+    }
+}
+
+impl DerefMut for EntryMut<'_> {
+    fn deref_mut(&mut self) -> &mut PdsEntry {
+        self.0
+    }
+}
+
+impl Drop for EntryMut<'_> {
+    fn drop(&mut self) {
+        self.0.seal();
+    }
 }
 
 pub struct PdsLedger {
@@ -66,85 +196,170 @@ pub struct PdsLedger {
 
 impl PdsLedger {
     pub fn open_or_create<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
-        let file = OpenOptions::new()
+        let path = path.as_ref();
+        let mut file = OpenOptions::new()
             .read(true)
             .write(true)
             .create(true)
+            .truncate(false)
             .open(path)?;
 
-        let metadata = file.metadata()?;
-        let mut len = metadata.len() as usize;
+        let mut header = [0u8; 12];
+        if file.metadata()?.len() == 0 {
+            write_header(&mut file)?;
+        } else if file.read_exact(&mut header).is_err() || &header[0..8] != MAGIC {
+            upgrade_v1(path, &mut file)?;
+            file = OpenOptions::new().read(true).write(true).open(path)?;
+        } else {
+            let version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+            if version != FORMAT_VERSION {
+                anyhow::bail!("PDS Ledger {} has format version {}, expected {}", path.display(), version, FORMAT_VERSION);
+            }
+        }
 
-        // Ensure file is at least 1 entry size
-        if len == 0 {
-            len = ENTRY_SIZE;
+        // Header plus room for at least one entry
+        let mut len = file.metadata()?.len() as usize;
+        if len < HEADER_SIZE + ENTRY_SIZE {
+            len = HEADER_SIZE + ENTRY_SIZE;
             file.set_len(len as u64)?;
         }
 
         let mmap = unsafe { MmapMut::map_mut(&file)? };
-        Ok(Self { file, mmap, capacity: len / ENTRY_SIZE })
+        Ok(Self { file, mmap, capacity: (len - HEADER_SIZE) / ENTRY_SIZE })
     }
 
+    /// Slots up to the last one in use, i.e. the index bound for `get_entry`. Empty or
+    /// corrupt slots before it are included; `valid_entry_count` leaves them out.
     pub fn entry_count(&self) -> usize {
-        // Find the first "empty" entry to determine logical count
-        // Or just return capacity if we want to be simple
-        // For our use case, we'll scan backwards for the first non-zero URL
+        // Scan backwards for the last non-zero URL
         for i in (0..self.capacity).rev() {
-            if let Some(entry) = self.get_entry(i) {
-                if entry.url[0] != 0 {
-                    return i + 1;
-                }
+            if self.slot(i).url[0] != 0 {
+                return i + 1;
             }
         }
         0
     }
 
-    pub fn get_entry(&self, index: usize) -> Option<&PdsEntry> {
-        if index >= self.capacity {
-            return None;
-        }
-        let offset = index * ENTRY_SIZE;
+    fn slot(&self, index: usize) -> &PdsEntry {
+        let offset = HEADER_SIZE + index * ENTRY_SIZE;
         unsafe {
             let ptr = self.mmap.as_ptr().add(offset) as *const PdsEntry;
-            Some(&*ptr)
+            &*ptr
         }
     }
 
-    pub fn get_entry_mut(&mut self, index: usize) -> Option<&mut PdsEntry> {
-        if index >= self.capacity {
-            return None;
-        }
-        let offset = index * ENTRY_SIZE;
+    fn slot_mut(&mut self, index: usize) -> &mut PdsEntry {
+        let offset = HEADER_SIZE + index * ENTRY_SIZE;
         unsafe {
             let ptr = self.mmap.as_mut_ptr().add(offset) as *mut PdsEntry;
-            Some(&mut *ptr)
+            &mut *ptr
+        }
+    }
+
+    /// The entry at `index`; `None` past the end, for a slot without a URL, or if its
+    /// checksum doesn't match.
+    pub fn get_entry(&self, index: usize) -> Option<&PdsEntry> {
+        if index >= self.capacity {
+            return None;
         }
+        let entry = self.slot(index);
+        (entry.has_url() && entry.is_valid()).then_some(entry)
     }
 
+    /// Entries `get_entry` returns: slots with a URL and a matching checksum.
+    pub fn valid_entry_count(&self) -> usize {
+        (0..self.entry_count()).filter(|&i| self.get_entry(i).is_some()).count()
+    }
+
+    pub fn get_entry_mut(&mut self, index: usize) -> Option<EntryMut<'_>> {
+        self.get_entry(index)?;
+        Some(EntryMut(self.slot_mut(index)))
+    }
+
+    /// Stores `entry` after the last slot in use and returns its index. Fails for an entry
+    /// whose URL is empty or invalid, which would otherwise look like a free slot.
     pub fn append(&mut self, entry: &PdsEntry) -> anyhow::Result<usize> {
+        validate_url(&entry.get_url())?;
         let logical_count = self.entry_count();
         
         if logical_count >= self.capacity {
             // GROW: Pre-allocate 40960 entries (approx 1MB) at a time
             let new_capacity = self.capacity + 4096;
-            let new_len = new_capacity * ENTRY_SIZE;
+            let new_len = HEADER_SIZE + new_capacity * ENTRY_SIZE;
             
-            self.file.set_len(new_len as u64)?;
-            self.mmap = unsafe { MmapMut::map_mut(&self.file)? };
+            crate::platform::grow_mapped(&self.file, &mut self.mmap, new_len as u64)?;
             self.capacity = new_capacity;
             info!("PDS Ledger capacity grown to {} entries.", new_capacity);
         }
 
         let index = logical_count;
-        let entry_mut = self.get_entry_mut(index).unwrap();
-        *entry_mut = *entry;
+        *self.slot_mut(index) = entry.sealed();
         
         Ok(index)
     }
 
+    /// Clears corrupt entries at the end of the ledger (a crash mid-append) and cuts the file
+    /// back to a whole number of entries. Returns how many entries were cleared.
+    /// Corrupt entries further in stay in place; `get_entry` skips them.
+    pub fn repair(&mut self) -> anyhow::Result<usize> {
+        let mut cleared = 0;
+        while let Some(last) = self.entry_count().checked_sub(1) {
+            if self.slot(last).is_valid() {
+                break;
+            }
+            // SAFETY: all-zero bytes are a valid (empty) PdsEntry.
+            *self.slot_mut(last) = unsafe { std::mem::zeroed() };
+            cleared += 1;
+        }
+
+        let whole_len = HEADER_SIZE + self.capacity * ENTRY_SIZE;
+        if self.file.metadata()?.len() as usize != whole_len {
+            crate::platform::grow_mapped(&self.file, &mut self.mmap, whole_len as u64)?;
+        }
+        if cleared > 0 {
+            warn!("PDS Ledger repair cleared {} trailing corrupt entries.", cleared);
+        }
+        self.flush()?;
+        Ok(cleared)
+    }
+
     /// Flushes changes to disk
     pub fn flush(&self) -> anyhow::Result<()> {
         self.mmap.flush()?;
         Ok(())
     }
 }
+
+fn write_header(file: &mut File) -> std::io::Result<()> {
+    let mut header = [0u8; HEADER_SIZE];
+    header[0..8].copy_from_slice(MAGIC);
+    header[8..12].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(&header)
+}
+
+/// Rewrites a header-less version 1 ledger with a header and checksummed entries.
+/// The new file is built next to the old one and renamed over it.
+fn upgrade_v1(path: &Path, file: &mut File) -> anyhow::Result<()> {
+    let mut old = Vec::new();
+    file.seek(SeekFrom::Start(0))?;
+    file.read_to_end(&mut old)?;
+
+    let tmp_path = path.with_extension("upgrade");
+    let mut tmp = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(&tmp_path)?;
+    write_header(&mut tmp)?;
+    let mut entries = 0;
+    for chunk in old.chunks_exact(ENTRY_SIZE) {
+        // SAFETY: PdsEntry is plain old data and the chunk is exactly ENTRY_SIZE bytes.
+        let mut entry: PdsEntry = unsafe { std::ptr::read_unaligned(chunk.as_ptr() as *const PdsEntry) };
+        if !entry.is_empty() {
+            entry.seal();
+        }
+        tmp.write_all(entry.as_bytes())?;
+        entries += 1;
+    }
+    tmp.sync_all()?;
+    std::fs::rename(&tmp_path, path)?;
+    info!("Upgraded PDS Ledger {} to format version {} ({} slots).", path.display(), FORMAT_VERSION, entries);
+    Ok(())
+}
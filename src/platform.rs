@@ -0,0 +1,122 @@
+//! Small filesystem shims so the mmap-backed stores behave the same on Linux, macOS and Windows.
+
+use memmap2::MmapMut;
+use std::fs::File;
+use std::io;
+
+/// Sets the file length without committing disk blocks for the new range.
+/// Unix filesystems leave the extension sparse on their own; NTFS has to be told first,
+/// otherwise a 512MB tombstone bitset really costs 512MB.
+pub fn set_len_sparse(file: &File, len: u64) -> io::Result<()> {
+    #[cfg(windows)]
+    mark_sparse(file)?;
+    file.set_len(len)
+}
+
+#[cfg(windows)]
+fn mark_sparse(file: &File) -> io::Result<()> {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::System::IO::DeviceIoControl;
+    use windows_sys::Win32::System::Ioctl::FSCTL_SET_SPARSE;
+
+    let mut returned = 0u32;
+    // SAFETY: the handle is owned by `file` and outlives the call; no in/out buffers are used.
+    let ok = unsafe {
+        DeviceIoControl(
+            file.as_raw_handle() as _,
+            FSCTL_SET_SPARSE,
+            std::ptr::null(),
+            0,
+            std::ptr::null_mut(),
+            0,
+            &mut returned,
+            std::ptr::null_mut(),
+        )
+    };
+    if ok == 0 {
+        // FAT/exFAT and some network shares don't do sparse files; fall back to a dense file.
+        let err = io::Error::last_os_error();
+        tracing::debug!("FSCTL_SET_SPARSE failed, file will be fully allocated: {}", err);
+    }
+    Ok(())
+}
+
+/// Resizes a file that is currently mapped and remaps it.
+/// Windows refuses `set_len` while a view is open, so the old view is flushed and
+/// dropped before the resize on every platform rather than only where it fails.
+pub fn grow_mapped(file: &File, mmap: &mut MmapMut, new_len: u64) -> io::Result<()> {
+    mmap.flush()?;
+    // Swap in a tiny anonymous map so the file view is unmapped before resizing.
+    drop(std::mem::replace(mmap, MmapMut::map_anon(1)?));
+    set_len_sparse(file, new_len)?;
+    // SAFETY: same contract as the original mapping; the file is still owned by the caller.
+    *mmap = unsafe { MmapMut::map_mut(file)? };
+    Ok(())
+}
+
+/// Access pattern hints for `advise`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advice {
+    /// Start reading the range in now (`MADV_WILLNEED`)
+    WillNeed,
+    /// Accesses land anywhere, so don't read ahead around faults (`MADV_RANDOM`)
+    Random,
+}
+
+/// `madvise` over `region`, which must lie inside a file mapping. Where there is no
+/// `madvise` the hint is logged and dropped.
+pub fn advise(region: &[u8], advice: Advice) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let flag = match advice {
+            Advice::WillNeed => libc::MADV_WILLNEED,
+            Advice::Random => libc::MADV_RANDOM,
+        };
+        let (addr, len) = page_span(region);
+        // SAFETY: the span only covers pages of the mapping `region` lives in, and neither
+        // hint changes what those pages contain.
+        if unsafe { libc::madvise(addr, len, flag) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    {
+        tracing::info!("madvise({:?}) isn't available on this platform, skipping", advice);
+        let _ = region;
+        Ok(())
+    }
+}
+
+/// Pins the pages under `region` in RAM until they are unmapped. Fails with the OS error
+/// (usually `ENOMEM` or `EPERM`) once `RLIMIT_MEMLOCK` is used up; logged and skipped
+/// where there is no `mlock`.
+pub fn lock_region(region: &[u8]) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        let (addr, len) = page_span(region);
+        // SAFETY: as in `advise`; locking pages leaves them readable and unchanged.
+        if unsafe { libc::mlock(addr, len) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+    #[cfg(not(unix))]
+    {
+        tracing::info!("mlock isn't available on this platform, skipping {} bytes", region.len());
+        Ok(())
+    }
+}
+
+/// `region` widened to whole pages, as `madvise` wants a page-aligned address.
+#[cfg(unix)]
+fn page_span(region: &[u8]) -> (*mut libc::c_void, usize) {
+    // SAFETY: sysconf has no preconditions
+    let page = match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        n if n > 0 => n as usize,
+        _ => 4096,
+    };
+    let start = region.as_ptr() as usize;
+    let aligned = start & !(page - 1);
+    (aligned as *mut libc::c_void, region.len() + (start - aligned))
+}
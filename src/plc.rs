@@ -0,0 +1,270 @@
+//! Building the DID cache from PLC directory dumps.
+//!
+//! Two strategies produce the same cache from a preprocessed PLC JSONL dump:
+//!
+//! - `build_batch` collects the final key of every DID in RAM and writes the table once at
+//!   the end. On a full dump that map alone needs several GB on top of the mmap.
+//! - `build_streaming` writes each operation into the mmap as it is read. Later operations
+//!   for a DID overwrite earlier ones, and a nullified operation tombstones the DID on sight.
+//!   It checkpoints the byte offset of the input, so an interrupted build resumes where it
+//!   stopped instead of starting over.
+//!
+//! In both, a DID with any nullified operation ends up without a key, even if operations
+//! follow it, and a DID's key is the last decodable one of its last operation that has one.
+//! `sample_dids` and `compare_sampled` check that two caches agree on a sample of the dump.
+//!
+//! Once built, a cache is kept current from plc.directory's `/export` endpoint:
+//! `ExportStream` pages through it from a saved cursor, and `apply_to_cache` writes what it
+//! returns with the same rules as `build_streaming`. `RotationWatcher` does the same while
+//! a verifier runs, reporting each key it replaces.
+
+use crate::mmap_did_cache::{hash_did, MmapDidCache};
+use crate::resolver::decode_key_string;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+pub mod export;
+pub use export::{apply_to_cache, ApplyStats, ExportStream, PlcExportError, PlcOperation, RateLimit};
+pub mod watcher;
+pub use watcher::{RotationEvent, RotationWatcher, WatchStats};
+
+/// One line of a preprocessed PLC dump.
+#[derive(Debug, Deserialize)]
+pub struct PlcRecord {
+    pub did: String,
+    pub operation: Option<Value>,
+    #[serde(default)]
+    pub nullified: Option<bool>,
+}
+
+/// Every key string an operation names: its verification methods, then `signingKey`.
+pub fn find_all_keys(op: &Value) -> Vec<String> {
+    let mut keys = Vec::new();
+    if let Some(obj) = op.get("verificationMethods").and_then(|vm| vm.as_object()) {
+        for val in obj.values() {
+            if let Some(s) = val.as_str() {
+                keys.push(s.to_string());
+            } else if let Some(arr) = val.as_array() {
+                keys.extend(arr.iter().filter_map(|v| v.as_str()).map(str::to_string));
+            }
+        }
+    }
+    if let Some(s) = op.get("signingKey").and_then(|sk| sk.as_str()) {
+        keys.push(s.to_string());
+    }
+    keys
+}
+
+/// Last key of `op` that decodes to a supported curve, as (key_type, pubkey).
+fn last_decodable_key(op: &Value) -> Option<(u8, [u8; 33])> {
+    last_decodable(&find_all_keys(op))
+}
+
+fn last_decodable(keys: &[String]) -> Option<(u8, [u8; 33])> {
+    keys.iter().rev().find_map(|k| decode_key_string(k)).map(|(pubkey, key_type)| (key_type, pubkey))
+}
+
+/// How far a build got. Saved as the streaming checkpoint, so it only holds counters.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildProgress {
+    /// Bytes of input consumed; a resumed streaming build seeks here
+    pub offset: u64,
+    /// Non-nullified operations read
+    pub operations: u64,
+    /// Nullified operations read
+    pub nullified: u64,
+    /// Slot writes (keys and tombstones) made to the cache
+    pub writes: u64,
+}
+
+impl BuildProgress {
+    /// Reads a checkpoint written by `save`. None if there isn't one.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Option<Self>> {
+        match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)
+                .map(Some)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Writes the checkpoint through a temp file and rename, so a crash leaves the old one.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?)?;
+        fs::rename(&tmp, path)
+    }
+}
+
+fn cache_full(writes: u64) -> io::Error {
+    io::Error::other(format!("cache is full after {} writes; rebuild with more slots", writes))
+}
+
+/// Writes one operation into `cache`: a nullified one tombstones the DID, any other sets its
+/// key unless the DID is already tombstoned. Returns whether a slot was written; `writes`
+/// only goes into the error if the cache is full.
+fn write_op(
+    cache: &mut MmapDidCache,
+    did_hash: &[u8; 32],
+    nullified: bool,
+    key: Option<(u8, [u8; 33])>,
+    writes: u64,
+) -> io::Result<bool> {
+    let written = if nullified {
+        cache.update_hashed(did_hash, None, None)
+    } else {
+        match key {
+            // The tombstone is the only record that the DID was nullified, so it sticks
+            Some((key_type, pubkey)) if !cache.is_tombstoned_hashed(did_hash) => {
+                cache.update_hashed(did_hash, Some(key_type), Some(&pubkey))
+            }
+            _ => return Ok(false),
+        }
+    };
+    if written {
+        Ok(true)
+    } else {
+        Err(cache_full(writes))
+    }
+}
+
+/// Reads the next line into `buf`, returning its length in bytes (0 at EOF). Lines that
+/// aren't valid JSON records come back as `Ok((len, None))` and are skipped by callers.
+fn next_record<R: BufRead>(input: &mut R, buf: &mut Vec<u8>) -> io::Result<(u64, Option<PlcRecord>)> {
+    buf.clear();
+    let n = input.read_until(b'\n', buf)? as u64;
+    Ok((n, serde_json::from_slice(buf).ok()))
+}
+
+/// Builds `cache` holding the final state of every DID in memory and writing it at the end.
+/// `on_progress` is called every million operations while reading and once when done.
+pub fn build_batch<R: BufRead>(
+    mut input: R,
+    cache: &mut MmapDidCache,
+    mut on_progress: impl FnMut(&BuildProgress),
+) -> io::Result<BuildProgress> {
+    let mut progress = BuildProgress::default();
+    let mut all_keys: HashMap<[u8; 32], (u8, [u8; 33])> = HashMap::new();
+    let mut nullified_dids: HashSet<[u8; 32]> = HashSet::new();
+    let mut buf = Vec::new();
+    loop {
+        let (n, rec) = next_record(&mut input, &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        progress.offset += n;
+        let Some(rec) = rec else { continue };
+        let did_hash = hash_did(&rec.did);
+        if rec.nullified.unwrap_or(false) {
+            nullified_dids.insert(did_hash);
+            all_keys.remove(&did_hash);
+            progress.nullified += 1;
+            continue;
+        }
+        if let Some(key) = rec.operation.as_ref().and_then(last_decodable_key) {
+            all_keys.insert(did_hash, key);
+        }
+        progress.operations += 1;
+        if progress.operations.is_multiple_of(1_000_000) {
+            on_progress(&progress);
+        }
+    }
+    for (did_hash, (key_type, pubkey)) in all_keys.drain() {
+        if nullified_dids.contains(&did_hash) {
+            continue;
+        }
+        if !cache.update_hashed(&did_hash, Some(key_type), Some(&pubkey)) {
+            return Err(cache_full(progress.writes));
+        }
+        progress.writes += 1;
+    }
+    on_progress(&progress);
+    Ok(progress)
+}
+
+/// Builds `cache` by writing each operation as it is read. `input` must already be
+/// positioned at `resume.offset`, and `cache` must hold the state that offset was
+/// checkpointed with (empty for a fresh build).
+///
+/// Every `checkpoint_every` operations, and once at the end, the cache is flushed and
+/// `on_checkpoint` receives the progress; persisting it is what makes the build resumable.
+pub fn build_streaming<R: BufRead>(
+    mut input: R,
+    cache: &mut MmapDidCache,
+    resume: BuildProgress,
+    checkpoint_every: u64,
+    mut on_checkpoint: impl FnMut(&BuildProgress) -> io::Result<()>,
+) -> io::Result<BuildProgress> {
+    let mut progress = resume;
+    let mut since_checkpoint = 0u64;
+    let mut buf = Vec::new();
+    loop {
+        let (n, rec) = next_record(&mut input, &mut buf)?;
+        if n == 0 {
+            break;
+        }
+        progress.offset += n;
+        let Some(rec) = rec else { continue };
+        let nullified = rec.nullified.unwrap_or(false);
+        let key = if nullified { None } else { rec.operation.as_ref().and_then(last_decodable_key) };
+        if write_op(cache, &hash_did(&rec.did), nullified, key, progress.writes)? {
+            progress.writes += 1;
+        }
+        if nullified {
+            progress.nullified += 1;
+        } else {
+            progress.operations += 1;
+        }
+        since_checkpoint += 1;
+        if checkpoint_every > 0 && since_checkpoint >= checkpoint_every {
+            cache.flush()?;
+            on_checkpoint(&progress)?;
+            since_checkpoint = 0;
+        }
+    }
+    cache.flush()?;
+    on_checkpoint(&progress)?;
+    Ok(progress)
+}
+
+/// Up to `n` DIDs drawn uniformly from the records of a dump (reservoir sampling over
+/// records, so a DID with many operations is more likely to be picked). Duplicates are
+/// dropped, so the sample can come back smaller than `n`.
+pub fn sample_dids<R: BufRead>(mut input: R, n: usize) -> io::Result<Vec<String>> {
+    let mut rng = rand::thread_rng();
+    let mut sample: Vec<String> = Vec::with_capacity(n);
+    let mut records = 0usize;
+    let mut buf = Vec::new();
+    loop {
+        let (len, rec) = next_record(&mut input, &mut buf)?;
+        if len == 0 {
+            break;
+        }
+        let Some(rec) = rec else { continue };
+        records += 1;
+        if sample.len() < n {
+            sample.push(rec.did);
+        } else if n > 0 {
+            let j = rng.gen_range(0..records);
+            if j < n {
+                sample[j] = rec.did;
+            }
+        }
+    }
+    sample.sort_unstable();
+    sample.dedup();
+    Ok(sample)
+}
+
+/// DIDs from `dids` that `a` and `b` resolve differently. Tombstoned and absent both
+/// count as "no key".
+pub fn compare_sampled<'d>(a: &MmapDidCache, b: &MmapDidCache, dids: &'d [String]) -> Vec<&'d str> {
+    dids.iter().filter(|did| a.get(did) != b.get(did)).map(String::as_str).collect()
+}
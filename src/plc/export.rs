@@ -0,0 +1,453 @@
+//! Following plc.directory's `/export` endpoint.
+//!
+//! `ExportStream` pages through `/export?after=<createdAt>` and yields each operation. Its
+//! cursor is the `createdAt` of the last operation handed out; `checkpoint` writes it to the
+//! cursor file, so a consumer saves it only once what it did with those operations is durable.
+//! Pages are paced by the "sweet spot" search described on `RateLimit`, and 429s, 5xx and
+//! network errors are retried (forever, unless `max_retries` says otherwise).
+
+use super::{find_all_keys, hash_did, last_decodable, write_op};
+use crate::mmap_did_cache::MmapDidCache;
+use chrono::{DateTime, Utc};
+use reqwest::blocking::Client;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Operations requested per page, the most `/export` returns.
+pub const DEFAULT_PAGE_SIZE: usize = 1000;
+
+/// Cache writes `apply_to_cache` makes between checkpoints. After a power loss at most this
+/// many updates are lost, and the next run fetches them again.
+pub const APPLY_CHECKPOINT_EVERY: u64 = 1000;
+
+// Bounds of the sweet spot search
+const MAX_FLOOR: Duration = Duration::from_secs(2);
+const MAX_DELAY: Duration = Duration::from_secs(5);
+const FLOOR_MARGIN: Duration = Duration::from_millis(50);
+const DELAY_MARGIN: Duration = Duration::from_millis(250);
+const DELAY_STEP: Duration = Duration::from_millis(25);
+const NETWORK_PENALTY: Duration = Duration::from_millis(200);
+const STREAK_TO_SPEED_UP: u32 = 8;
+
+/// One line of `/export`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlcOperation {
+    pub did: String,
+    /// As the directory sent it; this is what the cursor holds
+    pub created_at: String,
+    pub nullified: bool,
+    /// Every key the operation names, in `find_all_keys` order
+    pub signing_keys: Vec<String>,
+    /// `services.atproto_pds.endpoint`, if the operation sets one
+    pub pds_endpoint: Option<String>,
+}
+
+impl PlcOperation {
+    /// Parses one line of `/export`. None unless it has a `did` and a `createdAt`.
+    pub fn from_json(line: &str) -> Option<Self> {
+        let v: Value = serde_json::from_str(line).ok()?;
+        let op = v.get("operation");
+        Some(PlcOperation {
+            did: v.get("did")?.as_str()?.to_string(),
+            created_at: v.get("createdAt")?.as_str()?.to_string(),
+            nullified: v.get("nullified").and_then(Value::as_bool).unwrap_or(false),
+            signing_keys: op.map(find_all_keys).unwrap_or_default(),
+            pds_endpoint: op
+                .and_then(|op| op.pointer("/services/atproto_pds/endpoint"))
+                .and_then(Value::as_str)
+                .map(str::to_string),
+        })
+    }
+
+    /// Last signing key that decodes to a supported curve, as (key_type, pubkey).
+    pub fn decoded_key(&self) -> Option<(u8, [u8; 33])> {
+        last_decodable(&self.signing_keys)
+    }
+
+    /// Whether the operation was created after `t`. False if `created_at` doesn't parse.
+    pub fn created_after(&self, t: &DateTime<Utc>) -> bool {
+        DateTime::parse_from_rfc3339(&self.created_at).is_ok_and(|created| created > *t)
+    }
+}
+
+/// Why `ExportStream` couldn't deliver the next operation.
+#[derive(Debug)]
+pub enum PlcExportError {
+    /// Connecting or reading the response failed
+    Http(reqwest::Error),
+    /// The directory kept answering 429
+    RateLimited,
+    /// Any other non-success status; 4xx ones aren't retried
+    Status(u16),
+    /// Reading or writing the cursor file, or writing the cache
+    Io(io::Error),
+}
+
+impl std::fmt::Display for PlcExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlcExportError::Http(e) => write!(f, "PLC export request failed: {}", e),
+            PlcExportError::RateLimited => f.write_str("PLC export is rate limiting us"),
+            PlcExportError::Status(status) => write!(f, "PLC export answered HTTP {}", status),
+            PlcExportError::Io(e) => write!(f, "PLC export I/O failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PlcExportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PlcExportError::Http(e) => Some(e),
+            PlcExportError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PlcExportError {
+    fn from(e: io::Error) -> Self {
+        PlcExportError::Io(e)
+    }
+}
+
+/// Pacing between pages. A page is requested `delay` after the previous one, starting at
+/// `initial_delay`. A 429 means the current delay is too fast: the floor moves 50ms above it
+/// (at most 2s), the delay 250ms above the floor (at most 5s), and the stream waits out
+/// `cooldown`. Eight pages in a row without a failure take 25ms off the delay, down to the
+/// floor. A network error adds 200ms to the delay.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub initial_delay: Duration,
+    /// Lowest delay the search starts out allowed to reach
+    pub floor: Duration,
+    /// Wait after a 429
+    pub cooldown: Duration,
+    /// Wait after a 5xx
+    pub error_backoff: Duration,
+    /// Wait after a network error
+    pub network_backoff: Duration,
+    /// Wait before polling again at the tip, when following
+    pub tip_wait: Duration,
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        RateLimit {
+            initial_delay: Duration::from_millis(500),
+            floor: Duration::from_millis(150),
+            cooldown: Duration::from_secs(120),
+            error_backoff: Duration::from_secs(30),
+            network_backoff: Duration::from_secs(15),
+            tip_wait: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Operations from plc.directory's `/export`, oldest first. See the module docs.
+pub struct ExportStream {
+    client: Client,
+    directory: String,
+    page_size: usize,
+    limits: RateLimit,
+    max_retries: Option<u32>,
+    follow: bool,
+    running: Option<Arc<AtomicBool>>,
+    cursor_file: Option<PathBuf>,
+    // createdAt of the last operation handed out, and of the last one fetched
+    cursor: String,
+    fetched: String,
+    buffered: VecDeque<PlcOperation>,
+    at_tip: bool,
+    delay: Duration,
+    floor: Duration,
+    streak: u32,
+    last_request: Option<Instant>,
+    skipped: u64,
+}
+
+impl ExportStream {
+    /// Operations created after `after`, from the directory at `directory` (e.g. `PLC_DIRECTORY`).
+    pub fn new(directory: &str, after: &str) -> Self {
+        let limits = RateLimit::default();
+        ExportStream {
+            client: Client::new(),
+            directory: directory.trim_end_matches('/').to_string(),
+            page_size: DEFAULT_PAGE_SIZE,
+            limits,
+            max_retries: None,
+            follow: false,
+            running: None,
+            cursor_file: None,
+            cursor: after.to_string(),
+            fetched: after.to_string(),
+            buffered: VecDeque::new(),
+            at_tip: false,
+            delay: limits.initial_delay,
+            floor: limits.floor,
+            streak: 0,
+            last_request: None,
+            skipped: 0,
+        }
+    }
+
+    /// Resumes from the cursor saved in `path`, if there is one, and makes `checkpoint` save there.
+    pub fn with_cursor_file(mut self, path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        match fs::read_to_string(path) {
+            Ok(saved) if !saved.trim().is_empty() => {
+                self.cursor = saved.trim().to_string();
+                self.fetched = self.cursor.clone();
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        self.cursor_file = Some(path.to_path_buf());
+        Ok(self)
+    }
+
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size.max(1);
+        self
+    }
+
+    pub fn rate_limit(mut self, limits: RateLimit) -> Self {
+        self.limits = limits;
+        self.delay = limits.initial_delay;
+        self.floor = limits.floor;
+        self
+    }
+
+    /// Consecutive failed requests to retry before the error is returned. None retries forever.
+    pub fn max_retries(mut self, max_retries: Option<u32>) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Keep polling once caught up, every `RateLimit::tip_wait`, instead of ending.
+    pub fn follow(mut self, follow: bool) -> Self {
+        self.follow = follow;
+        self
+    }
+
+    /// Ends the stream once `running` is cleared (e.g. from a Ctrl-C handler), cutting any
+    /// wait short. Operations already handed out are unaffected.
+    pub fn with_running_flag(mut self, running: Arc<AtomicBool>) -> Self {
+        self.running = Some(running);
+        self
+    }
+
+    /// `createdAt` of the last operation handed out, or where the stream started.
+    pub fn cursor(&self) -> &str {
+        &self.cursor
+    }
+
+    /// Writes `cursor` to the cursor file through a temp file and rename. A no-op without one.
+    pub fn checkpoint(&self) -> io::Result<()> {
+        let Some(path) = &self.cursor_file else { return Ok(()) };
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, format!("{}\n", self.cursor))?;
+        fs::rename(&tmp, path)
+    }
+
+    /// Current delay between pages.
+    pub fn delay(&self) -> Duration {
+        self.delay
+    }
+
+    /// Operations fetched but not handed out yet.
+    pub fn pending(&self) -> usize {
+        self.buffered.len()
+    }
+
+    /// Lines that weren't operations with a `did` and a `createdAt`.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    fn running(&self) -> bool {
+        self.running.as_ref().is_none_or(|r| r.load(Ordering::SeqCst))
+    }
+
+    /// Sleeps for `d` in short steps. False if the stream was stopped meanwhile.
+    fn pause(&self, d: Duration) -> bool {
+        let until = Instant::now() + d;
+        while self.running() {
+            let left = until.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return true;
+            }
+            std::thread::sleep(left.min(Duration::from_millis(100)));
+        }
+        false
+    }
+
+    /// The next operation created no later than `until`. None at the end of the stream, once
+    /// stopped, or if the next operation is past `until`; that one stays buffered.
+    pub fn next_until(&mut self, until: Option<&DateTime<Utc>>) -> Option<Result<PlcOperation, PlcExportError>> {
+        loop {
+            if !self.running() {
+                return None;
+            }
+            if let Some(op) = self.buffered.front() {
+                if until.is_some_and(|t| op.created_after(t)) {
+                    return None;
+                }
+                let op = self.buffered.pop_front()?;
+                self.cursor.clone_from(&op.created_at);
+                return Some(Ok(op));
+            }
+            if self.at_tip {
+                if !self.follow || !self.pause(self.limits.tip_wait) {
+                    return None;
+                }
+                self.at_tip = false;
+            }
+            match self.fetch_page() {
+                Ok(0) => self.at_tip = true,
+                Ok(_) => {}
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+
+    /// Fetches the page after `fetched` into `buffered`, retrying failures. Returns how many
+    /// operations it held; 0 at the tip or if the stream was stopped.
+    fn fetch_page(&mut self) -> Result<usize, PlcExportError> {
+        let mut failures = 0u32;
+        loop {
+            let wait = self.last_request.map_or(Duration::ZERO, |t| self.delay.saturating_sub(t.elapsed()));
+            if !self.pause(wait) {
+                return Ok(0);
+            }
+            self.last_request = Some(Instant::now());
+            let url = format!("{}/export?count={}&after={}", self.directory, self.page_size, self.fetched);
+            let (error, backoff) = match self.client.get(&url).send().and_then(|r| {
+                let status = r.status();
+                r.text().map(|body| (status, body))
+            }) {
+                Ok((status, body)) if status.is_success() => return Ok(self.buffer_page(&body)),
+                Ok((status, _)) if status.as_u16() == 429 => {
+                    let failure_point = self.delay;
+                    self.floor = (failure_point + FLOOR_MARGIN).min(MAX_FLOOR);
+                    self.delay = (self.floor + DELAY_MARGIN).min(MAX_DELAY);
+                    tracing::warn!(
+                        "PLC export: {}ms was too fast; new floor {}ms, cooling down",
+                        failure_point.as_millis(),
+                        self.floor.as_millis()
+                    );
+                    (PlcExportError::RateLimited, self.limits.cooldown)
+                }
+                Ok((status, _)) if status.is_server_error() => {
+                    tracing::warn!("PLC export: HTTP {}, backing off", status);
+                    (PlcExportError::Status(status.as_u16()), self.limits.error_backoff)
+                }
+                Ok((status, _)) => return Err(PlcExportError::Status(status.as_u16())),
+                Err(e) => {
+                    self.delay = (self.delay + NETWORK_PENALTY).min(MAX_DELAY);
+                    tracing::warn!("PLC export: {}; delay now {}ms", e, self.delay.as_millis());
+                    (PlcExportError::Http(e), self.limits.network_backoff)
+                }
+            };
+            self.streak = 0;
+            failures += 1;
+            if self.max_retries.is_some_and(|max| failures > max) {
+                return Err(error);
+            }
+            if !self.pause(backoff) {
+                return Ok(0);
+            }
+        }
+    }
+
+    fn buffer_page(&mut self, body: &str) -> usize {
+        self.streak += 1;
+        if self.streak >= STREAK_TO_SPEED_UP && self.delay > self.floor {
+            self.delay = self.delay.saturating_sub(DELAY_STEP).max(self.floor);
+            self.streak = 0;
+        }
+        let before = self.buffered.len();
+        for line in body.lines().filter(|l| !l.trim().is_empty()) {
+            match PlcOperation::from_json(line) {
+                Some(op) => {
+                    self.fetched.clone_from(&op.created_at);
+                    self.buffered.push_back(op);
+                }
+                None => self.skipped += 1,
+            }
+        }
+        self.buffered.len() - before
+    }
+}
+
+impl Iterator for ExportStream {
+    type Item = Result<PlcOperation, PlcExportError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_until(None)
+    }
+}
+
+/// Counts from `apply_to_cache`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ApplyStats {
+    /// Non-nullified operations read, with or without a usable key
+    pub operations: u64,
+    /// Nullified operations read
+    pub nullified: u64,
+    /// Slot writes (keys and tombstones) made to the cache
+    pub writes: u64,
+    /// Non-nullified operations without a key that decodes to a supported curve
+    pub no_key: u64,
+}
+
+/// Writes operations from `stream` into `cache` until the stream ends or the next one was
+/// created after `until`, with the same rules as `build_streaming`. Every
+/// `APPLY_CHECKPOINT_EVERY` writes, and once at the end, the cache is flushed and then the
+/// stream checkpointed, so the cursor never runs ahead of what's on disk.
+///
+/// If the stream fails, what was applied up to then is checkpointed before the error is
+/// returned. If the cache fills up, nothing more is checkpointed.
+pub fn apply_to_cache(
+    stream: &mut ExportStream,
+    cache: &mut MmapDidCache,
+    until: Option<DateTime<Utc>>,
+) -> Result<ApplyStats, PlcExportError> {
+    let mut stats = ApplyStats::default();
+    let mut unflushed = 0u64;
+    let result = loop {
+        let op = match stream.next_until(until.as_ref()) {
+            None => break Ok(()),
+            Some(Ok(op)) => op,
+            Some(Err(e)) => break Err(e),
+        };
+        let key = if op.nullified { None } else { op.decoded_key() };
+        if op.nullified {
+            stats.nullified += 1;
+        } else {
+            stats.operations += 1;
+            if key.is_none() {
+                stats.no_key += 1;
+            }
+        }
+        if write_op(cache, &hash_did(&op.did), op.nullified, key, stats.writes)? {
+            stats.writes += 1;
+            unflushed += 1;
+        }
+        if unflushed >= APPLY_CHECKPOINT_EVERY {
+            cache.flush()?;
+            stream.checkpoint()?;
+            unflushed = 0;
+        }
+    };
+    cache.flush()?;
+    stream.checkpoint()?;
+    result.map(|()| stats)
+}
@@ -0,0 +1,196 @@
+//! Applying key rotations as plc.directory publishes them, instead of on the first failure.
+//!
+//! Without this a rotation is found when the first commit signed with the new key fails to
+//! verify, which costs that verification plus a PLC lookup on the verifier thread.
+//! `RotationWatcher` follows `/export` through an `ExportStream`, `lag` behind the present,
+//! compares each operation with the cache and writes what changed, by the same rules as
+//! `apply_to_cache`. Changes to DIDs that already had a key go out as `RotationEvent`s.
+
+use super::export::{ExportStream, PlcExportError, PlcOperation, APPLY_CHECKPOINT_EVERY};
+use super::{cache_full, hash_did};
+use crate::mmap_did_cache::MmapDidCache;
+use chrono::Utc;
+use crossbeam_channel::{bounded, Receiver, Sender};
+use serde::Serialize;
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How far behind the present the watcher reads by default.
+pub const DEFAULT_WATCH_LAG: Duration = Duration::from_secs(10);
+
+/// Events held for a slow reader; past this they're dropped (`WatchStats` still counts them).
+const EVENT_QUEUE: usize = 1024;
+
+/// A DID whose cached key was replaced or tombstoned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RotationEvent {
+    pub did: String,
+    /// First 4 bytes of the key that was in the cache, hex
+    pub old_key_prefix: String,
+    /// Key type the cache holds now; None if the DID was nullified
+    pub new_key_type: Option<u8>,
+}
+
+impl fmt::Display for RotationEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let new = match self.new_key_type {
+            Some(1) => "secp256k1".to_string(),
+            Some(2) => "p256".to_string(),
+            Some(n) => format!("type {}", n),
+            None => "nullified".to_string(),
+        };
+        write!(f, "{} {}.. -> {}", self.did, self.old_key_prefix, new)
+    }
+}
+
+/// Counts from a `RotationWatcher`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct WatchStats {
+    /// Non-nullified operations read
+    pub operations: u64,
+    /// Nullified operations read
+    pub nullified: u64,
+    /// Keys replaced or tombstoned for DIDs that had one
+    pub rotations: u64,
+    /// Keys written for DIDs the cache didn't have
+    pub new_dids: u64,
+    /// Slot writes (keys and tombstones) made to the cache
+    pub writes: u64,
+}
+
+pub struct RotationWatcher {
+    stream: ExportStream,
+    cache: Arc<RwLock<MmapDidCache>>,
+    events: Sender<RotationEvent>,
+    lag: Duration,
+    running: Option<Arc<AtomicBool>>,
+    stats: WatchStats,
+}
+
+impl RotationWatcher {
+    /// Watches `stream` (normally set to `follow`) on behalf of `cache`. Rotations arrive on
+    /// the returned receiver.
+    pub fn new(stream: ExportStream, cache: Arc<RwLock<MmapDidCache>>) -> (Self, Receiver<RotationEvent>) {
+        let (events, rx) = bounded(EVENT_QUEUE);
+        let watcher = RotationWatcher {
+            stream,
+            cache,
+            events,
+            lag: DEFAULT_WATCH_LAG,
+            running: None,
+            stats: WatchStats::default(),
+        };
+        (watcher, rx)
+    }
+
+    /// Operations are applied once they're at least `lag` old.
+    pub fn lag(mut self, lag: Duration) -> Self {
+        self.lag = lag;
+        self
+    }
+
+    /// Stops the watcher, and its stream, once `running` is cleared.
+    pub fn with_running_flag(mut self, running: Arc<AtomicBool>) -> Self {
+        self.stream = self.stream.with_running_flag(Arc::clone(&running));
+        self.running = Some(running);
+        self
+    }
+
+    pub fn stats(&self) -> &WatchStats {
+        &self.stats
+    }
+
+    fn running(&self) -> bool {
+        self.running.as_ref().is_none_or(|r| r.load(Ordering::SeqCst))
+    }
+
+    /// Compares `op` with the cache and writes the difference. Returns the rotation it
+    /// caused, if any; the event has also been queued. Errors only if the cache is full.
+    pub fn apply(&mut self, op: &PlcOperation) -> io::Result<Option<RotationEvent>> {
+        let did_hash = hash_did(&op.did);
+        let mut cache = self.cache.write().unwrap();
+        let old = cache.get(&op.did);
+        let new_key = if op.nullified {
+            self.stats.nullified += 1;
+            if cache.is_tombstoned_hashed(&did_hash) {
+                return Ok(None);
+            }
+            None
+        } else {
+            self.stats.operations += 1;
+            match op.decoded_key() {
+                // The tombstone is the only record that the DID was nullified, so it sticks
+                Some(_) if cache.is_tombstoned_hashed(&did_hash) => return Ok(None),
+                Some((key_type, pubkey)) if old != Some((pubkey, key_type)) => Some((key_type, pubkey)),
+                _ => return Ok(None),
+            }
+        };
+        if !cache.atomic_update_or_tombstone(&op.did, new_key.map(|(t, _)| t), new_key.as_ref().map(|(_, k)| k)) {
+            return Err(cache_full(self.stats.writes));
+        }
+        drop(cache);
+        self.stats.writes += 1;
+
+        let Some((old_key, _)) = old else {
+            if new_key.is_some() {
+                self.stats.new_dids += 1;
+            }
+            return Ok(None);
+        };
+        self.stats.rotations += 1;
+        let event = RotationEvent {
+            did: op.did.clone(),
+            old_key_prefix: hex::encode(&old_key[..4]),
+            new_key_type: new_key.map(|(t, _)| t),
+        };
+        let _ = self.events.try_send(event.clone());
+        Ok(Some(event))
+    }
+
+    /// Applies operations until the stream ends or the watcher is stopped. The cache is
+    /// flushed and the stream checkpointed every `APPLY_CHECKPOINT_EVERY` writes, whenever
+    /// the watcher has caught up to the lag, and at the end.
+    pub fn run(&mut self) -> Result<WatchStats, PlcExportError> {
+        let mut unflushed = 0u64;
+        let result = loop {
+            let cutoff = Utc::now() - chrono::Duration::from_std(self.lag).unwrap_or_default();
+            match self.stream.next_until(Some(&cutoff)) {
+                Some(Ok(op)) => {
+                    let writes = self.stats.writes;
+                    self.apply(&op)?;
+                    unflushed += self.stats.writes - writes;
+                    if unflushed >= APPLY_CHECKPOINT_EVERY {
+                        self.checkpoint()?;
+                        unflushed = 0;
+                    }
+                }
+                Some(Err(e)) => break Err(e),
+                // The next operation is younger than the lag: wait for it to age
+                None if self.stream.pending() > 0 && self.running() => {
+                    if unflushed > 0 {
+                        self.checkpoint()?;
+                        unflushed = 0;
+                    }
+                    thread::sleep(self.lag.clamp(Duration::from_millis(10), Duration::from_secs(1)));
+                }
+                None => break Ok(()),
+            }
+        };
+        self.checkpoint()?;
+        result.map(|()| self.stats.clone())
+    }
+
+    /// `run` on a thread of its own.
+    pub fn spawn(mut self) -> io::Result<JoinHandle<Result<WatchStats, PlcExportError>>> {
+        thread::Builder::new().name("plc-watcher".into()).spawn(move || self.run())
+    }
+
+    fn checkpoint(&self) -> io::Result<()> {
+        self.cache.read().unwrap().flush()?;
+        self.stream.checkpoint()
+    }
+}
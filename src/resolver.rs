@@ -1,6 +1,8 @@
 use reqwest::blocking::Client;
 use serde_json::Value;
-use std::sync::OnceLock;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
 static CLIENT: OnceLock<Client> = OnceLock::new();
 
@@ -8,13 +10,57 @@ fn get_client() -> &'static Client {
     CLIENT.get_or_init(|| Client::new())
 }
 
+/// Public PLC directory, used unless a base URL is configured.
+pub const PLC_DIRECTORY: &str = "https://plc.directory";
+/// Environment variable naming a PLC directory (a mirror, or a mock in tests) to use instead.
+pub const PLC_DIRECTORY_ENV: &str = "PLC_DIRECTORY_BASE";
+
+/// `PLC_DIRECTORY_BASE` if it's set and not empty, else `PLC_DIRECTORY`; without a
+/// trailing slash.
+pub fn plc_directory_base() -> String {
+    match std::env::var(PLC_DIRECTORY_ENV) {
+        Ok(base) if !base.trim().is_empty() => base.trim().trim_end_matches('/').to_string(),
+        _ => PLC_DIRECTORY.to_string(),
+    }
+}
+
+/// Where resolution traffic goes. The default (all `None`) fetches from plc.directory (or
+/// `PLC_DIRECTORY_BASE`) and each did:web host directly, and looks up `_atproto` TXT
+/// records with the system resolver.
+/// Setting all three routes DID document and TXT lookups through endpoints you choose. The
+/// HTTPS handle check still goes to the handle's own host: it is that host's answer being
+/// checked.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResolverConfig {
+    /// Base URL used for did:plc documents instead of `plc_directory_base()`
+    pub plc_directory: Option<String>,
+    /// Fetch did:web documents from `{gateway}/{did}` instead of the DID's own host. The
+    /// gateway may return the document itself or a resolution result with a `didDocument`.
+    pub did_web_gateway: Option<String>,
+    /// DNS-over-HTTPS endpoint speaking the JSON API (e.g. `https://cloudflare-dns.com/dns-query`)
+    /// for handle TXT lookups. Works without the `dns` feature.
+    pub doh_endpoint: Option<String>,
+}
+
+impl ResolverConfig {
+    /// The PLC directory did:plc lookups go to: `plc_directory`, else `plc_directory_base()`.
+    pub fn plc_base(&self) -> String {
+        self.plc_directory.as_deref().map_or_else(plc_directory_base, |base| base.trim_end_matches('/').to_string())
+    }
+}
+
 /// Resolves a DID (supports did:plc, did:web, and did:key)
 /// Returns (pubkey_bytes, key_type) if found.
 pub fn resolve_did(did: &str) -> Option<([u8; 33], u8)> {
+    resolve_did_with(did, &ResolverConfig::default())
+}
+
+/// `resolve_did` with lookups routed per `config`.
+pub fn resolve_did_with(did: &str, config: &ResolverConfig) -> Option<([u8; 33], u8)> {
     if did.starts_with("did:plc:") {
-        resolve_did_plc(did)
+        resolve_did_plc(did, config)
     } else if did.starts_with("did:web:") {
-        resolve_did_web(did)
+        resolve_did_web(did, config)
     } else if did.starts_with("did:key:") {
         did_key_to_raw_pubkey(did)
     } else {
@@ -22,25 +68,299 @@ pub fn resolve_did(did: &str) -> Option<([u8; 33], u8)> {
     }
 }
 
+/// How long a failed resolution is remembered by default.
+pub const DEFAULT_NEGATIVE_TTL: Duration = Duration::from_secs(300);
+/// Failed DIDs remembered at once by default; the oldest failure is forgotten first.
+pub const DEFAULT_NEGATIVE_CAPACITY: usize = 100_000;
+
+type FetchFn = Box<dyn Fn(&str) -> Option<([u8; 33], u8)> + Send + Sync>;
+
+/// `resolve_did` with a negative cache in front of it.
+///
+/// A repo whose DID no longer resolves still produces commits; without this, each one would
+/// cost a network round-trip. Failures are remembered for `ttl`, successes are not cached
+/// here (the mmap cache holds those).
+pub struct ResolverCache {
+    ttl: Duration,
+    capacity: usize,
+    failed: Mutex<NegativeEntries>,
+    fetch: FetchFn,
+}
+
+#[derive(Default)]
+struct NegativeEntries {
+    by_did: HashMap<String, Instant>,
+    /// Failures oldest first. A DID that failed again has a stale earlier entry here,
+    /// skipped on eviction because its instant no longer matches `by_did`.
+    order: VecDeque<(String, Instant)>,
+}
+
+impl Default for ResolverCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_NEGATIVE_TTL, DEFAULT_NEGATIVE_CAPACITY)
+    }
+}
+
+impl ResolverCache {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self { ttl, capacity: capacity.max(1), failed: Mutex::new(NegativeEntries::default()), fetch: Box::new(resolve_did) }
+    }
+
+    /// Replaces the network lookup, e.g. with a mock in tests.
+    pub fn with_fetch<F>(mut self, fetch: F) -> Self
+    where
+        F: Fn(&str) -> Option<([u8; 33], u8)> + Send + Sync + 'static,
+    {
+        self.fetch = Box::new(fetch);
+        self
+    }
+
+    /// Resolves through `config` instead of the default endpoints.
+    pub fn with_config(self, config: ResolverConfig) -> Self {
+        self.with_fetch(move |did| resolve_did_with(did, &config))
+    }
+
+    /// Resolves `did`, or returns `None` without a lookup if it failed within the TTL.
+    pub fn resolve(&self, did: &str) -> Option<([u8; 33], u8)> {
+        if self.recently_failed(did) {
+            return None;
+        }
+        let result = (self.fetch)(did);
+        let mut failed = self.failed.lock().unwrap();
+        match result {
+            Some(_) => {
+                failed.by_did.remove(did);
+            }
+            None => {
+                let now = Instant::now();
+                failed.by_did.insert(did.to_string(), now);
+                failed.order.push_back((did.to_string(), now));
+                while failed.by_did.len() > self.capacity {
+                    let Some((old, at)) = failed.order.pop_front() else { break };
+                    if failed.by_did.get(&old) == Some(&at) {
+                        failed.by_did.remove(&old);
+                    }
+                }
+                // Keep stale queue entries from piling up behind repeat failures
+                if failed.order.len() > self.capacity * 2 {
+                    let NegativeEntries { by_did, order } = &mut *failed;
+                    order.retain(|(d, at)| by_did.get(d) == Some(at));
+                }
+            }
+        }
+        result
+    }
+
+    pub fn recently_failed(&self, did: &str) -> bool {
+        let failed = self.failed.lock().unwrap();
+        failed.by_did.get(did).is_some_and(|at| at.elapsed() < self.ttl)
+    }
+
+    /// Drops a remembered failure, e.g. after an identity event for the DID.
+    pub fn forget(&self, did: &str) {
+        self.failed.lock().unwrap().by_did.remove(did);
+    }
+
+    /// Failures currently remembered (including expired ones not yet evicted).
+    pub fn negative_len(&self) -> usize {
+        self.failed.lock().unwrap().by_did.len()
+    }
+}
+
+/// The handle a DID document *claims* (first `alsoKnownAs` entry). Anyone can claim any
+/// handle; use `resolve_handle_verified` before showing it as the account's name.
 pub fn resolve_handle(did: &str) -> Option<String> {
-    if !did.starts_with("did:plc:") { return None; }
-    let url = format!("https://plc.directory/{}/data", did);
+    resolve_handle_with(did, &ResolverConfig::default())
+}
+
+/// `resolve_handle` with the DID document fetched per `config`.
+pub fn resolve_handle_with(did: &str, config: &ResolverConfig) -> Option<String> {
+    let url = if did.starts_with("did:plc:") {
+        format!("{}/{}/data", config.plc_base(), did)
+    } else if did.starts_with("did:web:") {
+        did_web_fetch_url(did, config)?
+    } else {
+        return None;
+    };
     let client = get_client();
     let resp = client.get(url).send().ok()?;
     if !resp.status().is_success() { return None; }
-    let json: Value = resp.json().ok()?;
+    let json = did_document(resp.json().ok()?);
     
     // alsoKnownAs is usually ["at://..."]
     if let Some(aka) = json.get("alsoKnownAs").and_then(|a| a.as_array()) {
-        if let Some(first) = aka.get(0).and_then(|v| v.as_str()) {
+        if let Some(first) = aka.first().and_then(|v| v.as_str()) {
             return Some(first.trim_start_matches("at://").to_string());
         }
     }
     None
 }
 
-fn resolve_did_plc(did: &str) -> Option<([u8; 33], u8)> {
-    let url = format!("https://plc.directory/{}/log/last", did);
+/// How a claimed handle held up against the handle's own domain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HandleVerification {
+    /// `_atproto.<handle>` TXT record names the DID
+    VerifiedViaDns,
+    /// `https://<handle>/.well-known/atproto-did` names the DID
+    VerifiedViaHttp,
+    /// The domain didn't answer either way; only the DID document's word for it
+    ClaimOnly,
+    /// The domain points at a different DID (the one given here)
+    Mismatch(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedHandle {
+    pub handle: String,
+    pub verification: HandleVerification,
+}
+
+impl VerifiedHandle {
+    pub fn is_verified(&self) -> bool {
+        matches!(self.verification, HandleVerification::VerifiedViaDns | HandleVerification::VerifiedViaHttp)
+    }
+
+    /// Handle for display, suffixed when it couldn't be confirmed.
+    pub fn display_name(&self) -> String {
+        match self.verification {
+            HandleVerification::VerifiedViaDns | HandleVerification::VerifiedViaHttp => self.handle.clone(),
+            HandleVerification::ClaimOnly => format!("{} [unverified]", self.handle),
+            HandleVerification::Mismatch(_) => format!("{} [mismatch]", self.handle),
+        }
+    }
+}
+
+/// The two places a handle's domain can name its DID. Split out so the lookups can be mocked.
+pub trait HandleLookup {
+    /// DIDs from `_atproto.<handle>` TXT records (`did=...`)
+    fn dns_dids(&self, handle: &str) -> Vec<String>;
+    /// Body of `https://<handle>/.well-known/atproto-did`
+    fn http_did(&self, handle: &str) -> Option<String>;
+}
+
+/// Live DNS (with the `dns` feature) and HTTPS lookups.
+pub struct NetworkLookup;
+
+impl HandleLookup for NetworkLookup {
+    #[cfg(feature = "dns")]
+    fn dns_dids(&self, handle: &str) -> Vec<String> {
+        use hickory_resolver::Resolver;
+        static RESOLVER: OnceLock<Option<Resolver>> = OnceLock::new();
+        let Some(resolver) = RESOLVER.get_or_init(|| Resolver::from_system_conf().ok()) else { return Vec::new() };
+        let Ok(lookup) = resolver.txt_lookup(format!("_atproto.{}.", handle)) else { return Vec::new() };
+        lookup.iter()
+            .map(|txt| txt.txt_data().iter().map(|part| String::from_utf8_lossy(part)).collect::<String>())
+            .filter_map(|record| record.trim().strip_prefix("did=").map(str::to_string))
+            .collect()
+    }
+
+    #[cfg(not(feature = "dns"))]
+    fn dns_dids(&self, _handle: &str) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn http_did(&self, handle: &str) -> Option<String> {
+        let url = format!("https://{}/.well-known/atproto-did", handle);
+        let resp = get_client().get(url).timeout(Duration::from_secs(5)).send().ok()?;
+        if !resp.status().is_success() { return None; }
+        let body = resp.text().ok()?;
+        let did = body.trim();
+        (did.starts_with("did:") && did.len() <= 2048).then(|| did.to_string())
+    }
+}
+
+/// `NetworkLookup`, except that TXT records come over DNS-over-HTTPS when `doh_endpoint`
+/// is set.
+impl HandleLookup for ResolverConfig {
+    fn dns_dids(&self, handle: &str) -> Vec<String> {
+        match &self.doh_endpoint {
+            Some(endpoint) => doh_dids(endpoint, handle),
+            None => NetworkLookup.dns_dids(handle),
+        }
+    }
+
+    // No endpoint can stand in for the handle's host here
+    fn http_did(&self, handle: &str) -> Option<String> {
+        NetworkLookup.http_did(handle)
+    }
+}
+
+/// `_atproto.<handle>` TXT lookup against a DoH JSON endpoint.
+fn doh_dids(endpoint: &str, handle: &str) -> Vec<String> {
+    let Ok(url) = reqwest::Url::parse_with_params(endpoint, &[("name", format!("_atproto.{}", handle).as_str()), ("type", "TXT")]) else {
+        return Vec::new();
+    };
+    let resp = get_client()
+        .get(url)
+        .header(reqwest::header::ACCEPT, "application/dns-json")
+        .timeout(Duration::from_secs(5))
+        .send();
+    match resp {
+        Ok(resp) if resp.status().is_success() => resp.json().map(|json| doh_txt_dids(&json)).unwrap_or_default(),
+        _ => Vec::new(),
+    }
+}
+
+/// `did=` values from the TXT answers (type 16) of a DNS JSON response. Record data comes
+/// as one or more quoted strings, which are joined like the wire format's chunks.
+fn doh_txt_dids(json: &Value) -> Vec<String> {
+    let Some(answers) = json.get("Answer").and_then(|a| a.as_array()) else { return Vec::new() };
+    answers.iter()
+        .filter(|a| a.get("type").and_then(|t| t.as_u64()) == Some(16))
+        .filter_map(|a| a.get("data").and_then(|d| d.as_str()))
+        .map(|data| if data.contains('"') { data.split('"').skip(1).step_by(2).collect() } else { data.to_string() })
+        .filter_map(|record| record.trim().strip_prefix("did=").map(str::to_string))
+        .collect()
+}
+
+/// Resolves the handle `did` claims and checks that the handle's domain names `did` back.
+pub fn resolve_handle_verified(did: &str) -> Option<VerifiedHandle> {
+    resolve_handle_verified_with(did, &ResolverConfig::default())
+}
+
+/// `resolve_handle_verified` with the DID document and TXT lookups routed per `config`.
+pub fn resolve_handle_verified_with(did: &str, config: &ResolverConfig) -> Option<VerifiedHandle> {
+    let handle = resolve_handle_with(did, config)?;
+    let verification = verify_handle(did, &handle, config);
+    Some(VerifiedHandle { handle, verification })
+}
+
+/// Bidirectional check of a claimed `handle` for `did`. DNS is tried first; HTTP only
+/// if DNS has no answer pointing at `did`.
+pub fn verify_handle(did: &str, handle: &str, lookup: &impl HandleLookup) -> HandleVerification {
+    let handle = handle.trim().trim_end_matches('.').to_ascii_lowercase();
+    if !is_valid_handle(&handle) {
+        return HandleVerification::ClaimOnly;
+    }
+    let dns = lookup.dns_dids(&handle);
+    if dns.iter().any(|d| d == did) {
+        return HandleVerification::VerifiedViaDns;
+    }
+    let http = lookup.http_did(&handle);
+    if http.as_deref() == Some(did) {
+        return HandleVerification::VerifiedViaHttp;
+    }
+    match dns.into_iter().next().or(http) {
+        Some(other) => HandleVerification::Mismatch(other),
+        None => HandleVerification::ClaimOnly,
+    }
+}
+
+/// Domain-name syntax only: at least two labels of `[a-z0-9-]`, no leading/trailing hyphen.
+/// Keeps the lookups away from things like `localhost` or IP literals in a spoofed claim.
+fn is_valid_handle(handle: &str) -> bool {
+    let labels: Vec<&str> = handle.split('.').collect();
+    handle.len() <= 253
+        && labels.len() >= 2
+        && !labels.last().unwrap().starts_with(|c: char| c.is_ascii_digit())
+        && labels.iter().all(|l| {
+            !l.is_empty() && l.len() <= 63 && !l.starts_with('-') && !l.ends_with('-')
+                && l.bytes().all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        })
+}
+
+fn resolve_did_plc(did: &str, config: &ResolverConfig) -> Option<([u8; 33], u8)> {
+    let url = format!("{}/{}/log/last", config.plc_base(), did);
     let client = get_client();
     
     let resp = client.get(url).send().ok()?;
@@ -101,7 +421,7 @@ fn resolve_did_plc(did: &str) -> Option<([u8; 33], u8)> {
     None
 }
 
-fn resolve_did_web(did: &str) -> Option<([u8; 33], u8)> {
+fn did_web_document_url(did: &str) -> Option<String> {
     // did:web:example.com -> https://example.com/.well-known/did.json
     // did:web:example.com:path:to:user -> https://example.com/path/to/user/did.json
     let parts: Vec<&str> = did.split(':').collect();
@@ -110,20 +430,40 @@ fn resolve_did_web(did: &str) -> Option<([u8; 33], u8)> {
     }
 
     let host = parts[2];
-    let url = if parts.len() == 3 {
+    Some(if parts.len() == 3 {
         format!("https://{}/.well-known/did.json", host)
     } else {
         let path = parts[3..].join("/");
         format!("https://{}/{}/did.json", host, path)
-    };
+    })
+}
+
+/// Where `did`'s document is fetched from: the did:web host, or the configured gateway.
+fn did_web_fetch_url(did: &str, config: &ResolverConfig) -> Option<String> {
+    let direct = did_web_document_url(did)?;
+    Some(match &config.did_web_gateway {
+        Some(gateway) => format!("{}/{}", gateway.trim_end_matches('/'), did),
+        None => direct,
+    })
+}
+
+/// The DID document in a fetched body, unwrapping a resolver's `{"didDocument": ...}`.
+fn did_document(json: Value) -> Value {
+    match json {
+        Value::Object(mut obj) if obj.contains_key("didDocument") => obj.remove("didDocument").unwrap_or_default(),
+        other => other,
+    }
+}
 
+fn resolve_did_web(did: &str, config: &ResolverConfig) -> Option<([u8; 33], u8)> {
+    let url = did_web_fetch_url(did, config)?;
     let client = get_client();
     let resp = client.get(url).send().ok()?;
     if !resp.status().is_success() {
         return None;
     }
 
-    let json: Value = resp.json().ok()?;
+    let json = did_document(resp.json().ok()?);
 
     // In a DID document, keys are in verificationMethod
     if let Some(vms) = json.get("verificationMethod").and_then(|v| v.as_array()) {
@@ -169,68 +509,121 @@ fn jwk_to_raw_pubkey(jwk: &Value) -> Option<([u8; 33], u8)> {
     let y = URL_SAFE_NO_PAD.decode(y_b64).ok()?;
     
     if x.len() == 32 && y.len() == 32 {
-        // Convert to compressed form: 02 or 03 based on y's parity
-        let mut pk = [0u8; 33];
-        pk[0] = if y[31] % 2 == 0 { 0x02 } else { 0x03 };
-        pk[1..33].copy_from_slice(&x);
-        return Some((pk, key_type));
+        let mut uncompressed = Vec::with_capacity(65);
+        uncompressed.push(0x04);
+        uncompressed.extend_from_slice(&x);
+        uncompressed.extend_from_slice(&y);
+        return Some((compress_sec1(&uncompressed, key_type)?, key_type));
     }
     None
 }
 
-/// Decodes a multibase public key (e.g. "zQ3sh..." for secp256k1 or "zDna..." for P-256)
-fn multibase_to_raw_pubkey(multibase_key: &str) -> Option<([u8; 33], u8)> {
+/// Normalizes a SEC1 public key to the 33-byte compressed form the cache stores.
+/// Compressed keys (0x02/0x03) pass through as they are. Uncompressed ones (0x04 || X || Y)
+/// must be a point on `key_type`'s curve (1 = secp256k1, 2 = P-256) and get the 0x02/0x03
+/// prefix from Y's parity.
+pub fn compress_sec1(key: &[u8], key_type: u8) -> Option<[u8; 33]> {
+    match (key.len(), key.first()?) {
+        (33, 0x02 | 0x03) => key.try_into().ok(),
+        (65, 0x04) => {
+            // Checks the curve equation, so a garbled key isn't stored as a plausible-looking one
+            let on_curve = match key_type {
+                1 => k256::PublicKey::from_sec1_bytes(key).is_ok(),
+                2 => p256::PublicKey::from_sec1_bytes(key).is_ok(),
+                _ => false,
+            };
+            if !on_curve {
+                return None;
+            }
+            let mut pk = [0u8; 33];
+            pk[0] = if key[64] & 1 == 0 { 0x02 } else { 0x03 };
+            pk[1..].copy_from_slice(&key[1..33]);
+            Some(pk)
+        }
+        _ => None,
+    }
+}
+
+/// Decodes a multibase public key (e.g. "zQ3sh..." for secp256k1 or "zDna..." for P-256).
+/// The key type comes from the multicodec prefix, never from the string prefix.
+pub fn multibase_to_raw_pubkey(multibase_key: &str) -> Option<([u8; 33], u8)> {
     if !multibase_key.starts_with('z') {
         return None;
     }
     let rest = &multibase_key[1..];
     let decoded = bs58::decode(rest).into_vec().ok()?;
     
-    // Secp256k1 prefix: 0xe7 0x01 (35 bytes total)
-    if decoded.starts_with(&[0xe7, 0x01]) && decoded.len() == 35 {
-        let mut pk = [0u8; 33];
-        pk.copy_from_slice(&decoded[2..]);
-        return Some((pk, 1)); // 1 = Secp256k1
-    }
-    
-    // P-256 prefix: 0x80 0x24 (35 bytes total)
-    if decoded.starts_with(&[0x80, 0x24]) && decoded.len() == 35 {
-        let mut pk = [0u8; 33];
-        pk.copy_from_slice(&decoded[2..]);
-        return Some((pk, 2)); // 2 = P-256
-    }
-
-    None
+    // Secp256k1 prefix: 0xe7 0x01, P-256 prefix: 0x80 0x24. The key after it is
+    // normally compressed (35 bytes total), but some documents publish it uncompressed (67).
+    let key_type = match decoded.get(..2)? {
+        [0xe7, 0x01] => 1, // 1 = Secp256k1
+        [0x80, 0x24] => 2, // 2 = P-256
+        _ => return None,
+    };
+    Some((compress_sec1(&decoded[2..], key_type)?, key_type))
 }
 
 /// Helper to decode did:key:z... (secp256k1 or P-256)
-fn did_key_to_raw_pubkey(did_key: &str) -> Option<([u8; 33], u8)> {
+pub fn did_key_to_raw_pubkey(did_key: &str) -> Option<([u8; 33], u8)> {
     if !did_key.starts_with("did:key:z") {
         return None;
     }
     multibase_to_raw_pubkey(&did_key[8..])
 }
 
+/// Decodes a PLC operation key, which may be a bare multibase string or a did:key.
+pub fn decode_key_string(key: &str) -> Option<([u8; 33], u8)> {
+    match key.strip_prefix("did:key:") {
+        Some(rest) => multibase_to_raw_pubkey(rest),
+        None => multibase_to_raw_pubkey(key),
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use super::{did_document, did_web_document_url, did_web_fetch_url, doh_txt_dids, ResolverConfig};
+    use serde_json::json;
+
     #[test]
     fn test_did_web_transform() {
-        let did = "did:web:example.com";
-        let parts: Vec<&str> = did.split(':').collect();
-        let host = parts[2];
-        let url = if parts.len() == 3 {
-            format!("https://{}/.well-known/did.json", host)
-        } else {
-            let path = parts[3..].join("/");
-            format!("https://{}/{}/did.json", host, path)
-        };
-        assert_eq!(url, "https://example.com/.well-known/did.json");
-
-        let did2 = "did:web:example.com:user:alice";
-        let parts2: Vec<&str> = did2.split(':').collect();
-        let host2 = parts2[2];
-        let path2 = parts2[3..].join("/");
-        let url2 = format!("https://{}/{}/did.json", host2, path2);
-        assert_eq!(url2, "https://example.com/user/alice/did.json");
+        assert_eq!(did_web_document_url("did:web:example.com").unwrap(), "https://example.com/.well-known/did.json");
+        assert_eq!(
+            did_web_document_url("did:web:example.com:user:alice").unwrap(),
+            "https://example.com/user/alice/did.json"
+        );
+        assert_eq!(did_web_document_url("did:web"), None);
+    }
+
+    #[test]
+    fn test_did_web_gateway() {
+        let direct = ResolverConfig::default();
+        assert_eq!(did_web_fetch_url("did:web:example.com", &direct).unwrap(), "https://example.com/.well-known/did.json");
+
+        let gateway = ResolverConfig { did_web_gateway: Some("https://resolver.internal/1.0/identifiers/".into()), ..Default::default() };
+        assert_eq!(
+            did_web_fetch_url("did:web:example.com:user:alice", &gateway).unwrap(),
+            "https://resolver.internal/1.0/identifiers/did:web:example.com:user:alice"
+        );
+        assert_eq!(did_web_fetch_url("did:web", &gateway), None);
+
+        let doc = json!({"id": "did:web:example.com", "verificationMethod": []});
+        assert_eq!(did_document(json!({"didDocument": doc.clone(), "didResolutionMetadata": {}})), doc);
+        assert_eq!(did_document(doc.clone()), doc);
+    }
+
+    #[test]
+    fn test_doh_txt_answers() {
+        let response = json!({
+            "Status": 0,
+            "Answer": [
+                {"name": "_atproto.alice.example.com.", "type": 5, "data": "alias.example.net."},
+                {"name": "_atproto.alice.example.com.", "type": 16, "data": "\"did=did:plc:alice\""},
+                {"name": "_atproto.alice.example.com.", "type": 16, "data": "\"did=did:plc:\" \"split\""},
+                {"name": "_atproto.alice.example.com.", "type": 16, "data": "did=did:web:unquoted.example"},
+                {"name": "_atproto.alice.example.com.", "type": 16, "data": "\"v=spf1 -all\""}
+            ]
+        });
+        assert_eq!(doh_txt_dids(&response), ["did:plc:alice", "did:plc:split", "did:web:unquoted.example"]);
+        assert!(doh_txt_dids(&json!({"Status": 3})).is_empty());
     }
 }
@@ -0,0 +1,456 @@
+//! Synthesized firehose frames for tests (feature `test-fixtures`).
+//!
+//! `FrameBuilder` produces a `#commit` frame the way a PDS would: the ops' records go into an
+//! MST, the commit over its root is signed, and commit, MST nodes and records are packed into
+//! a CAR with the commit as root. The result parses with `parse_input` and verifies with
+//! `verify_commit` against `SigningKey::public_key`. `identity_frame` and `account_frame`
+//! build the other event types.
+
+use crate::parser::canonical::hash_canonical_commit;
+use k256::ecdsa::signature::hazmat::PrehashSigner;
+use cbor::{bytes, event_header, head, link, text, varint};
+use sha2::{Digest, Sha256};
+
+/// Time stamped on frames that don't set one.
+pub const DEFAULT_TIME: &str = "2024-11-02T17:04:11.913Z";
+
+/// A repo signing key, of either type the verifier accepts.
+pub enum SigningKey {
+    K256(k256::ecdsa::SigningKey),
+    P256(p256::ecdsa::SigningKey),
+}
+
+impl SigningKey {
+    /// A secp256k1 key with every secret byte `seed`, for reproducible fixtures.
+    pub fn k256_from_seed(seed: u8) -> Self {
+        SigningKey::K256(k256::ecdsa::SigningKey::from_slice(&[seed; 32]).expect("valid secret"))
+    }
+
+    /// A P-256 key with every secret byte `seed`.
+    pub fn p256_from_seed(seed: u8) -> Self {
+        SigningKey::P256(p256::ecdsa::SigningKey::from_slice(&[seed; 32]).expect("valid secret"))
+    }
+
+    /// Key type as stored in the cache: 1 for secp256k1, 2 for P-256.
+    pub fn key_type(&self) -> u8 {
+        match self {
+            SigningKey::K256(_) => 1,
+            SigningKey::P256(_) => 2,
+        }
+    }
+
+    /// Compressed SEC1 public key, as stored in the cache.
+    pub fn public_key(&self) -> [u8; 33] {
+        let point = match self {
+            SigningKey::K256(k) => k.verifying_key().to_encoded_point(true).as_bytes().to_vec(),
+            SigningKey::P256(k) => k.verifying_key().to_encoded_point(true).as_bytes().to_vec(),
+        };
+        point.try_into().expect("compressed point is 33 bytes")
+    }
+
+    /// Low-S signature over a SHA-256 prehash.
+    pub fn sign_prehash(&self, prehash: &[u8]) -> Vec<u8> {
+        match self {
+            SigningKey::K256(k) => {
+                let sig: k256::ecdsa::Signature = k.sign_prehash(prehash).expect("32-byte prehash");
+                sig.to_bytes().to_vec()
+            }
+            SigningKey::P256(k) => {
+                // ATProto requires low-S, which p256 doesn't produce on its own
+                let sig: p256::ecdsa::Signature = k.sign_prehash(prehash).expect("32-byte prehash");
+                sig.normalize_s().unwrap_or(sig).to_bytes().to_vec()
+            }
+        }
+    }
+}
+
+/// One op of a commit; `record` is None for a delete.
+struct Op {
+    action: &'static str,
+    path: String,
+    record: Option<Vec<u8>>,
+}
+
+/// Builds a signed `#commit` frame. Ops are applied to an otherwise empty repo, so the MST
+/// holds the created and updated records.
+pub struct FrameBuilder {
+    did: String,
+    key: SigningKey,
+    seq: u64,
+    rev: Option<String>,
+    time: String,
+    ops: Vec<Op>,
+    tamper: bool,
+}
+
+impl FrameBuilder {
+    pub fn new(did: impl Into<String>, key: SigningKey) -> Self {
+        FrameBuilder { did: did.into(), key, seq: 1, rev: None, time: DEFAULT_TIME.to_string(), ops: Vec::new(), tamper: false }
+    }
+
+    pub fn seq(mut self, seq: u64) -> Self {
+        self.seq = seq;
+        self
+    }
+
+    /// Defaults to a TID-shaped string derived from the seq.
+    pub fn rev(mut self, rev: impl Into<String>) -> Self {
+        self.rev = Some(rev.into());
+        self
+    }
+
+    pub fn time(mut self, time: impl Into<String>) -> Self {
+        self.time = time.into();
+        self
+    }
+
+    /// Adds a create of `record` (DAG-CBOR) at `path` (`collection/rkey`).
+    pub fn create(mut self, path: impl Into<String>, record: Vec<u8>) -> Self {
+        self.ops.push(Op { action: "create", path: path.into(), record: Some(record) });
+        self
+    }
+
+    pub fn update(mut self, path: impl Into<String>, record: Vec<u8>) -> Self {
+        self.ops.push(Op { action: "update", path: path.into(), record: Some(record) });
+        self
+    }
+
+    pub fn delete(mut self, path: impl Into<String>) -> Self {
+        self.ops.push(Op { action: "delete", path: path.into(), record: None });
+        self
+    }
+
+    /// Flips a bit of the signature, so the frame parses but fails verification.
+    pub fn tamper_signature(mut self) -> Self {
+        self.tamper = true;
+        self
+    }
+
+    /// The public key the frame verifies against.
+    pub fn public_key(&self) -> [u8; 33] {
+        self.key.public_key()
+    }
+
+    pub fn key_type(&self) -> u8 {
+        self.key.key_type()
+    }
+
+    /// The framed header + payload, as it comes off the websocket.
+    pub fn build(&self) -> Vec<u8> {
+        let rev = self.rev.clone().unwrap_or_else(|| format!("3lbf{:09}", self.seq));
+
+        let mut records: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+        for op in &self.ops {
+            records.retain(|(path, _)| path != op.path.as_bytes());
+            if let Some(record) = &op.record {
+                records.push((op.path.as_bytes().to_vec(), record.clone()));
+            }
+        }
+        records.sort();
+        let mut mst_blocks = Vec::new();
+        let leaves: Vec<(&[u8], Vec<u8>, u32)> =
+            records.iter().map(|(path, record)| (path.as_slice(), cid_for(record), key_layer(path))).collect();
+        let root_layer = leaves.iter().map(|l| l.2).max().unwrap_or(0);
+        let data = mst_node(&leaves, root_layer, &mut mst_blocks);
+
+        let mut hasher = Sha256::new();
+        assert!(hash_canonical_commit(&commit_block(&self.did, &data, &rev, None), &mut hasher));
+        let mut sig = self.key.sign_prehash(&hasher.finalize());
+        if self.tamper {
+            sig[20] ^= 0x40;
+        }
+        let commit = commit_block(&self.did, &data, &rev, Some(&sig));
+        let commit_cid = cid_for(&commit);
+
+        let mut blocks: Vec<&[u8]> = vec![&commit];
+        blocks.extend(mst_blocks.iter().map(Vec::as_slice));
+        for (_, record) in &records {
+            if !blocks.contains(&record.as_slice()) {
+                blocks.push(record);
+            }
+        }
+        let blocks = car(&commit_cid, &blocks);
+
+        let mut f = Vec::new();
+        event_header("#commit", &mut f);
+        head(5, 11, &mut f);
+        text("ops", &mut f);
+        head(4, self.ops.len() as u64, &mut f);
+        for op in &self.ops {
+            head(5, 3, &mut f);
+            text("cid", &mut f);
+            match &op.record {
+                Some(record) => link(&cid_for(record), &mut f),
+                None => f.push(0xf6),
+            }
+            text("path", &mut f);
+            text(&op.path, &mut f);
+            text("action", &mut f);
+            text(op.action, &mut f);
+        }
+        text("rev", &mut f);
+        text(&rev, &mut f);
+        text("seq", &mut f);
+        head(0, self.seq, &mut f);
+        text("repo", &mut f);
+        text(&self.did, &mut f);
+        text("time", &mut f);
+        text(&self.time, &mut f);
+        text("blobs", &mut f);
+        head(4, 0, &mut f);
+        text("since", &mut f);
+        f.push(0xf6);
+        text("blocks", &mut f);
+        bytes(&blocks, &mut f);
+        text("commit", &mut f);
+        link(&commit_cid, &mut f);
+        text("rebase", &mut f);
+        f.push(0xf4);
+        text("tooBig", &mut f);
+        f.push(0xf4);
+        f
+    }
+}
+
+/// An `#identity` frame, with the new handle if there is one.
+pub fn identity_frame(did: &str, seq: u64, handle: Option<&str>) -> Vec<u8> {
+    let mut f = Vec::new();
+    event_header("#identity", &mut f);
+    head(5, if handle.is_some() { 4 } else { 3 }, &mut f);
+    text("did", &mut f);
+    text(did, &mut f);
+    text("seq", &mut f);
+    head(0, seq, &mut f);
+    text("time", &mut f);
+    text(DEFAULT_TIME, &mut f);
+    if let Some(handle) = handle {
+        text("handle", &mut f);
+        text(handle, &mut f);
+    }
+    f
+}
+
+/// An `#account` frame; an inactive account carries `status`.
+pub fn account_frame(did: &str, seq: u64, active: bool, status: Option<&str>) -> Vec<u8> {
+    let mut f = Vec::new();
+    event_header("#account", &mut f);
+    head(5, if status.is_some() { 5 } else { 4 }, &mut f);
+    text("did", &mut f);
+    text(did, &mut f);
+    text("seq", &mut f);
+    head(0, seq, &mut f);
+    text("time", &mut f);
+    text(DEFAULT_TIME, &mut f);
+    text("active", &mut f);
+    f.push(if active { 0xf5 } else { 0xf4 });
+    if let Some(status) = status {
+        text("status", &mut f);
+        text(status, &mut f);
+    }
+    f
+}
+
+/// An `app.bsky.feed.post` record with `body` as its text.
+pub fn post_record(body: &str) -> Vec<u8> {
+    post_record_at(body, DEFAULT_TIME)
+}
+
+/// `post_record` created at `created_at`.
+pub fn post_record_at(body: &str, created_at: &str) -> Vec<u8> {
+    let mut r = Vec::new();
+    head(5, 4, &mut r);
+    text("text", &mut r);
+    text(body, &mut r);
+    text("$type", &mut r);
+    text("app.bsky.feed.post", &mut r);
+    text("langs", &mut r);
+    head(4, 1, &mut r);
+    text("en", &mut r);
+    text("createdAt", &mut r);
+    text(created_at, &mut r);
+    r
+}
+
+/// DAG-CBOR CIDv1 (sha2-256) of `block`, as raw bytes.
+pub fn cid_for(block: &[u8]) -> Vec<u8> {
+    let mut cid = vec![0x01, 0x71, 0x12, 0x20];
+    cid.extend_from_slice(&Sha256::digest(block));
+    cid
+}
+
+/// A CARv1 with root `root` holding `blocks` in order.
+pub fn car(root: &[u8], blocks: &[&[u8]]) -> Vec<u8> {
+    let mut header = Vec::new();
+    head(5, 2, &mut header);
+    text("roots", &mut header);
+    head(4, 1, &mut header);
+    link(root, &mut header);
+    text("version", &mut header);
+    header.push(0x01);
+
+    let mut out = Vec::new();
+    varint(header.len(), &mut out);
+    out.extend_from_slice(&header);
+    for block in blocks {
+        let cid = cid_for(block);
+        varint(cid.len() + block.len(), &mut out);
+        out.extend_from_slice(&cid);
+        out.extend_from_slice(block);
+    }
+    out
+}
+
+// --- MST ---
+
+/// MST layer of a key: leading zero bits of its SHA-256, two per layer (fanout 4).
+fn key_layer(key: &[u8]) -> u32 {
+    let mut zeros = 0;
+    for byte in Sha256::digest(key) {
+        zeros += byte.leading_zeros();
+        if byte != 0 {
+            break;
+        }
+    }
+    zeros / 2
+}
+
+/// An entry of the node being built: key, value CID, and the CID of the subtree to its right.
+type NodeEntry<'a> = (&'a [u8], &'a [u8], Option<Vec<u8>>);
+
+/// Encodes the node at `layer` over `leaves` (sorted `(key, value CID, layer)`, none above
+/// `layer`), pushing it and its subtrees onto `blocks`. Returns its CID.
+fn mst_node(leaves: &[(&[u8], Vec<u8>, u32)], layer: u32, blocks: &mut Vec<Vec<u8>>) -> Vec<u8> {
+    // Keys at this layer are the node's entries; the runs between them become subtrees
+    let mut left = None;
+    let mut entries: Vec<NodeEntry> = Vec::new();
+    let mut run_start = 0;
+    for (i, (key, value, leaf_layer)) in leaves.iter().enumerate() {
+        if *leaf_layer == layer {
+            let subtree = subtree(&leaves[run_start..i], layer, blocks);
+            match entries.last_mut() {
+                Some(prev) => prev.2 = subtree,
+                None => left = subtree,
+            }
+            entries.push((key, value.as_slice(), None));
+            run_start = i + 1;
+        }
+    }
+    let subtree = subtree(&leaves[run_start..], layer, blocks);
+    match entries.last_mut() {
+        Some(prev) => prev.2 = subtree,
+        None => left = subtree,
+    }
+
+    let mut node = Vec::new();
+    head(5, 2, &mut node);
+    text("e", &mut node);
+    head(4, entries.len() as u64, &mut node);
+    let mut prev_key: &[u8] = &[];
+    for (key, value, tree) in &entries {
+        let prefix = key.iter().zip(prev_key).take_while(|(a, b)| a == b).count();
+        head(5, 4, &mut node);
+        text("k", &mut node);
+        bytes(&key[prefix..], &mut node);
+        text("p", &mut node);
+        head(0, prefix as u64, &mut node);
+        text("t", &mut node);
+        match tree {
+            Some(cid) => link(cid, &mut node),
+            None => node.push(0xf6),
+        }
+        text("v", &mut node);
+        link(value, &mut node);
+        prev_key = key;
+    }
+    text("l", &mut node);
+    match &left {
+        Some(cid) => link(cid, &mut node),
+        None => node.push(0xf6),
+    }
+    let cid = cid_for(&node);
+    blocks.push(node);
+    cid
+}
+
+fn subtree(run: &[(&[u8], Vec<u8>, u32)], layer: u32, blocks: &mut Vec<Vec<u8>>) -> Option<Vec<u8>> {
+    (!run.is_empty() && layer > 0).then(|| mst_node(run, layer - 1, blocks))
+}
+
+/// DAG-CBOR pieces, for tests that need frames or blocks `FrameBuilder` doesn't shape:
+/// malformed frames, unusual records, hand-picked CIDs.
+pub mod cbor {
+    /// Initial byte(s) of an item of `major` type with argument `len`, minimally encoded.
+    pub fn head(major: u8, len: u64, out: &mut Vec<u8>) {
+        let m = major << 5;
+        if len < 24 {
+            out.push(m | len as u8);
+        } else if len < 0x100 {
+            out.extend_from_slice(&[m | 24, len as u8]);
+        } else if len < 0x10000 {
+            out.push(m | 25);
+            out.extend_from_slice(&(len as u16).to_be_bytes());
+        } else if len < 0x1_0000_0000 {
+            out.push(m | 26);
+            out.extend_from_slice(&(len as u32).to_be_bytes());
+        } else {
+            out.push(m | 27);
+            out.extend_from_slice(&len.to_be_bytes());
+        }
+    }
+
+    pub fn text(s: &str, out: &mut Vec<u8>) {
+        head(3, s.len() as u64, out);
+        out.extend_from_slice(s.as_bytes());
+    }
+
+    pub fn bytes(b: &[u8], out: &mut Vec<u8>) {
+        head(2, b.len() as u64, out);
+        out.extend_from_slice(b);
+    }
+
+    /// Tag 42 link, with the multibase identity prefix.
+    pub fn link(cid: &[u8], out: &mut Vec<u8>) {
+        out.extend_from_slice(&[0xd8, 0x2a]);
+        head(2, cid.len() as u64 + 1, out);
+        out.push(0x00);
+        out.extend_from_slice(cid);
+    }
+
+    /// Unsigned LEB128, as CAR sections are prefixed.
+    pub fn varint(mut n: usize, out: &mut Vec<u8>) {
+        while n >= 0x80 {
+            out.push((n as u8 & 0x7f) | 0x80);
+            n >>= 7;
+        }
+        out.push(n as u8);
+    }
+
+    /// The `{t, op: 1}` header of an event frame of type `t`.
+    pub fn event_header(t: &str, out: &mut Vec<u8>) {
+        head(5, 2, out);
+        text("t", out);
+        text(t, out);
+        text("op", out);
+        out.push(0x01);
+    }
+}
+
+fn commit_block(did: &str, data: &[u8], rev: &str, sig: Option<&[u8]>) -> Vec<u8> {
+    let mut b = Vec::new();
+    head(5, if sig.is_some() { 6 } else { 5 }, &mut b);
+    text("did", &mut b);
+    text(did, &mut b);
+    text("rev", &mut b);
+    text(rev, &mut b);
+    if let Some(sig) = sig {
+        text("sig", &mut b);
+        bytes(sig, &mut b);
+    }
+    text("data", &mut b);
+    link(data, &mut b);
+    text("prev", &mut b);
+    b.push(0xf6);
+    text("version", &mut b);
+    b.push(0x03);
+    b
+}
@@ -1,68 +1,497 @@
 // High-performance verification logic for ATProto commit blocks
-use crate::parser::core::CommitEnvelope;
+use crate::archive::MultiShardArchive;
+use crate::mmap_did_cache::MmapDidCache;
+use crate::monitor::ErrorType;
+use crate::parser::core::{parse_input, CommitEnvelope};
 use k256::ecdsa::signature::hazmat::PrehashVerifier as _;
 use sha2::{Digest, Sha256};
 use dashmap::DashMap;
-use std::sync::OnceLock;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
 
-// Global caches for parsed VerifyingKeys to eliminate EC parsing overhead.
-// These are keyed by the 33-byte raw SEC1 pubkey.
-static SECP_CACHE: OnceLock<DashMap<[u8; 33], k256::ecdsa::VerifyingKey>> = OnceLock::new();
-static P256_CACHE: OnceLock<DashMap<[u8; 33], p256::ecdsa::VerifyingKey>> = OnceLock::new();
+// Global cache of parsed VerifyingKeys to eliminate EC parsing overhead.
+// Keyed by key type + the 33-byte raw SEC1 pubkey.
+static KEY_CACHE: OnceLock<DashMap<(u8, [u8; 33]), ParsedKey>> = OnceLock::new();
 
-pub fn verify_commit(envelope: &CommitEnvelope, pubkey_bytes: &[u8; 33], key_type: u8) -> bool {
-    let commit_raw = match envelope.commit {
-        Some(c) => c,
-        None => return false,
+/// A public key parsed once for repeated verification.
+#[derive(Clone, Debug)]
+pub enum ParsedKey {
+    Secp256k1(k256::ecdsa::VerifyingKey),
+    P256(p256::ecdsa::VerifyingKey),
+}
+
+impl ParsedKey {
+    /// Parses a compressed SEC1 key of `key_type` (1 = secp256k1, 2 = P-256). None for an
+    /// unknown type or bytes that aren't a point on the curve.
+    pub fn from_bytes(key_type: u8, pubkey: &[u8; 33]) -> Option<Self> {
+        match key_type {
+            1 => k256::ecdsa::VerifyingKey::from_sec1_bytes(pubkey).ok().map(ParsedKey::Secp256k1),
+            2 => p256::ecdsa::VerifyingKey::from_sec1_bytes(pubkey).ok().map(ParsedKey::P256),
+            _ => None,
+        }
+    }
+
+    /// `from_bytes` through the process-wide parsed key cache.
+    pub fn cached(key_type: u8, pubkey: &[u8; 33]) -> Option<Self> {
+        let cache = KEY_CACHE.get_or_init(|| DashMap::with_capacity(10000));
+        // Fast Path: Check if the key is already parsed in our cache
+        if let Some(key) = cache.get(&(key_type, *pubkey)) {
+            return Some(key.clone());
+        }
+        // Slow Path: Parse and cache it
+        let key = Self::from_bytes(key_type, pubkey)?;
+        // Self-cleaning cache if it grows too large (e.g., > 100k entries)
+        if cache.len() > 100_000 { cache.clear(); }
+        cache.insert((key_type, *pubkey), key.clone());
+        Some(key)
+    }
+
+    /// The cache's key type byte for this curve.
+    pub fn key_type(&self) -> u8 {
+        match self {
+            ParsedKey::Secp256k1(_) => 1,
+            ParsedKey::P256(_) => 2,
+        }
+    }
+
+    fn verify_prehash(&self, hash: &[u8], sig_bytes: &[u8], opts: &VerifyOptions) -> Result<SignatureForm, VerifyError> {
+        let (ok, form) = match self {
+            ParsedKey::Secp256k1(vk) => {
+                let sig = k256::ecdsa::Signature::from_slice(sig_bytes).map_err(|_| VerifyError::BadSignatureEncoding)?;
+                let normalized = sig.normalize_s();
+                let (sig, form) = low_s(sig, normalized, opts)?;
+                (vk.verify_prehash(hash, &sig).is_ok(), form)
+            }
+            ParsedKey::P256(vk) => {
+                let sig = p256::ecdsa::Signature::from_slice(sig_bytes).map_err(|_| VerifyError::BadSignatureEncoding)?;
+                let normalized = sig.normalize_s();
+                let (sig, form) = low_s(sig, normalized, opts)?;
+                (vk.verify_prehash(hash, &sig).is_ok(), form)
+            }
+        };
+        if ok { Ok(form) } else { Err(VerifyError::VerifyFailed) }
+    }
+}
+
+/// Picks the signature to verify: `sig` itself if it is low-S, else its low-S form
+/// `normalized` when `opts` tolerates high-S. p256 would accept a high-S signature as is,
+/// so strict mode rejects it here for both curves.
+fn low_s<S>(sig: S, normalized: Option<S>, opts: &VerifyOptions) -> Result<(S, SignatureForm), VerifyError> {
+    match normalized {
+        None => Ok((sig, SignatureForm::LowS)),
+        Some(low) if opts.allow_high_s || opts.normalize => Ok((low, SignatureForm::HighS)),
+        Some(_) => Err(VerifyError::VerifyFailed),
+    }
+}
+
+/// How verification treats high-S signatures. ATProto requires low-S, and the default
+/// rejects anything else.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct VerifyOptions {
+    /// Accept a high-S signature whose low-S form verifies, reported as a plain `Ok`
+    pub allow_high_s: bool,
+    /// Accept a high-S signature whose low-S form verifies, reported as `VerifiedHighS`
+    /// so spec violations can be counted
+    pub normalize: bool,
+}
+
+/// The encoding of a signature that verified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureForm {
+    LowS,
+    /// Only verified after normalizing to low-S (see `VerifyOptions`)
+    HighS,
+}
+
+/// Why `verify_commit_parsed` rejected a commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyError {
+    /// The envelope carries no (or an empty) signature
+    NoSignature,
+    /// The envelope has no signed commit block
+    NoCommit,
+    /// The commit block isn't canonical DAG-CBOR we can hash
+    CanonicalizationFailed,
+    /// The signature isn't a 64-byte r||s for the key's curve
+    BadSignatureEncoding,
+    /// Well-formed input, but the signature doesn't match the key
+    VerifyFailed,
+}
+
+impl VerifyError {
+    /// Monitor bucket, the same one its `VerifyOutcome` lands in: a signature that doesn't
+    /// match, or can't as encoded, is an invalid signature; broken envelopes are malformed CBOR.
+    pub fn error_type(self) -> ErrorType {
+        match self {
+            VerifyError::BadSignatureEncoding | VerifyError::VerifyFailed => ErrorType::InvalidSignature,
+            VerifyError::NoSignature | VerifyError::NoCommit | VerifyError::CanonicalizationFailed => ErrorType::MalformedCbor,
+        }
+    }
+}
+
+impl std::fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            VerifyError::NoSignature => "commit has no signature",
+            VerifyError::NoCommit => "envelope has no commit block",
+            VerifyError::CanonicalizationFailed => "commit block is not canonical DAG-CBOR",
+            VerifyError::BadSignatureEncoding => "signature is not a valid ECDSA encoding",
+            VerifyError::VerifyFailed => "signature does not match the key",
+        })
+    }
+}
+
+impl std::error::Error for VerifyError {}
+
+/// Why a commit did or didn't verify.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyOutcome {
+    Ok,
+    /// Verified after normalizing a high-S signature (`VerifyOptions::normalize`)
+    VerifiedHighS,
+    /// The envelope carries no (or an empty) signature
+    NoSignature,
+    /// The envelope has no signed commit block
+    NoCommit,
+    /// The commit block isn't canonical DAG-CBOR we can hash
+    CanonicalizationFailed,
+    /// Unknown key type or a public key that doesn't parse
+    BadKey,
+    /// Well-formed input, but the signature doesn't match the key
+    Mismatch,
+}
+
+impl VerifyOutcome {
+    pub fn is_ok(self) -> bool {
+        matches!(self, VerifyOutcome::Ok | VerifyOutcome::VerifiedHighS)
+    }
+
+    /// Monitor bucket for a failure: broken input is malformed CBOR, not a bad signature.
+    pub fn error_type(self) -> Option<ErrorType> {
+        match self {
+            VerifyOutcome::Ok | VerifyOutcome::VerifiedHighS => None,
+            VerifyOutcome::NoSignature | VerifyOutcome::NoCommit | VerifyOutcome::CanonicalizationFailed => {
+                Some(ErrorType::MalformedCbor)
+            }
+            VerifyOutcome::BadKey => Some(ErrorType::MissingKey),
+            VerifyOutcome::Mismatch => Some(ErrorType::InvalidSignature),
+        }
+    }
+}
+
+impl From<Result<(), VerifyError>> for VerifyOutcome {
+    /// A signature of the wrong shape can't match any key, so it counts as a mismatch.
+    fn from(result: Result<(), VerifyError>) -> Self {
+        match result {
+            Ok(()) => VerifyOutcome::Ok,
+            Err(VerifyError::NoSignature) => VerifyOutcome::NoSignature,
+            Err(VerifyError::NoCommit) => VerifyOutcome::NoCommit,
+            Err(VerifyError::CanonicalizationFailed) => VerifyOutcome::CanonicalizationFailed,
+            Err(VerifyError::BadSignatureEncoding | VerifyError::VerifyFailed) => VerifyOutcome::Mismatch,
+        }
+    }
+}
+
+/// SHA-256 of the canonical commit and the signature to check against it.
+fn commit_prehash<'a>(envelope: &CommitEnvelope<'a>) -> Result<([u8; 32], &'a [u8]), VerifyError> {
+    let commit_raw = envelope.commit.ok_or(VerifyError::NoCommit)?;
+    let sig_bytes = match envelope.signature {
+        Some(s) if !s.is_empty() => s,
+        _ => return Err(VerifyError::NoSignature),
     };
-    let sig_bytes = envelope.signature.unwrap_or(&[]);
-    
-    // 1. Hash and Verify (Zero-Copy)
+    // Zero-Copy Hash (Updates hasher directly from raw buffer slices)
     let mut hasher = Sha256::new();
-    if crate::parser::canonical::hash_canonical_commit(commit_raw, &mut hasher) {
-        let hash = hasher.finalize();
+    if !crate::parser::canonical::hash_canonical_commit(commit_raw, &mut hasher) {
+        return Err(VerifyError::CanonicalizationFailed);
+    }
+    Ok((hasher.finalize().into(), sig_bytes))
+}
 
-        match key_type {
-            1 => { // Secp256k1
-                let cache = SECP_CACHE.get_or_init(|| DashMap::with_capacity(10000));
-                
-                // Fast Path: Check if the key is already parsed in our cache
-                let signature_res = k256::ecdsa::Signature::from_slice(sig_bytes);
-                if let Ok(signature) = signature_res {
-                    if let Some(vk) = cache.get(pubkey_bytes) {
-                        return vk.verify_prehash(&hash, &signature).is_ok();
-                    }
+/// Verifies the commit signature against an already parsed key, rejecting high-S.
+pub fn verify_commit_parsed(envelope: &CommitEnvelope, key: &ParsedKey) -> Result<(), VerifyError> {
+    verify_commit_parsed_with(envelope, key, &VerifyOptions::default()).map(|_| ())
+}
 
-                    // Slow Path: Parse and cache it
-                    if let Ok(verifying_key) = k256::ecdsa::VerifyingKey::from_sec1_bytes(pubkey_bytes) {
-                        let ok = verifying_key.verify_prehash(&hash, &signature).is_ok();
-                        // Self-cleaning cache if it grows too large (e.g., > 100k entries)
-                        if cache.len() > 100_000 { cache.clear(); }
-                        cache.insert(*pubkey_bytes, verifying_key);
-                        return ok;
-                    }
+/// `verify_commit_parsed` with explicit high-S handling; says which form verified.
+pub fn verify_commit_parsed_with(envelope: &CommitEnvelope, key: &ParsedKey, opts: &VerifyOptions) -> Result<SignatureForm, VerifyError> {
+    let (hash, sig_bytes) = commit_prehash(envelope)?;
+    key.verify_prehash(&hash, sig_bytes, opts)
+}
+
+pub fn verify_commit(envelope: &CommitEnvelope, pubkey_bytes: &[u8; 33], key_type: u8) -> bool {
+    verify_commit_detailed(envelope, pubkey_bytes, key_type).is_ok()
+}
+
+/// `verify_commit_parsed` from raw key bytes, parsed through the shared key cache. Problems
+/// with the envelope are reported ahead of a bad key.
+pub fn verify_commit_detailed(envelope: &CommitEnvelope, pubkey_bytes: &[u8; 33], key_type: u8) -> VerifyOutcome {
+    verify_commit_detailed_with(envelope, pubkey_bytes, key_type, &VerifyOptions::default())
+}
+
+/// `verify_commit_detailed` with explicit high-S handling. A high-S signature accepted under
+/// `normalize` comes back as `VerifiedHighS`; under `allow_high_s` alone it is just `Ok`.
+pub fn verify_commit_detailed_with(envelope: &CommitEnvelope, pubkey_bytes: &[u8; 33], key_type: u8, opts: &VerifyOptions) -> VerifyOutcome {
+    let Some(key) = ParsedKey::cached(key_type, pubkey_bytes) else {
+        return match commit_prehash(envelope) {
+            Err(e) => Err(e).into(),
+            Ok(_) => VerifyOutcome::BadKey,
+        };
+    };
+    match verify_commit_parsed_with(envelope, &key, opts) {
+        Ok(SignatureForm::HighS) if opts.normalize => VerifyOutcome::VerifiedHighS,
+        result => result.map(|_| ()).into(),
+    }
+}
+
+/// Seqs a `reverify` worker claims at a time.
+const REVERIFY_CHUNK: u64 = 1024;
+
+/// A stored commit that doesn't verify (any more).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReverifyFailure {
+    pub seq: u64,
+    /// Empty if the frame didn't parse far enough to name its repo
+    pub did: String,
+    /// `None` when the frame didn't parse or no key was found for the DID
+    pub outcome: Option<VerifyOutcome>,
+    pub error: ErrorType,
+}
+
+impl ReverifyFailure {
+    pub fn verdict(&self) -> String {
+        match (self.outcome, self.error) {
+            (Some(outcome), _) => format!("{:?}", outcome),
+            (None, ErrorType::MissingKey) => "NoKey".to_string(),
+            (None, _) => "Unparseable".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct KeyTypeCounts {
+    pub verified: u64,
+    pub failed: u64,
+}
+
+/// What re-verifying one stored message found.
+enum Audit<'a> {
+    /// Not a commit (identity, account events)
+    Skipped,
+    Verified { did: &'a str, key_type: u8 },
+    /// `key_type` is `None` when the frame didn't parse or no key was found for the DID
+    Failed { did: &'a str, key_type: Option<u8>, outcome: Option<VerifyOutcome>, error: ErrorType },
+}
+
+fn audit<'a, F>(msg: &'a [u8], lookup: &F) -> Audit<'a>
+where
+    F: Fn(&str) -> Option<([u8; 33], u8)>,
+{
+    let Ok(envelope) = parse_input(msg) else {
+        return Audit::Failed { did: "", key_type: None, outcome: None, error: ErrorType::MalformedCbor };
+    };
+    if envelope.t.is_some_and(|t| t != b"#commit") {
+        return Audit::Skipped;
+    }
+    let Some(did) = envelope.did.and_then(|d| std::str::from_utf8(d).ok()) else {
+        return Audit::Failed { did: "", key_type: None, outcome: None, error: ErrorType::MalformedCbor };
+    };
+    let Some((pubkey, key_type)) = lookup(did) else {
+        return Audit::Failed { did, key_type: None, outcome: None, error: ErrorType::MissingKey };
+    };
+    let outcome = verify_commit_detailed(&envelope, &pubkey, key_type);
+    match outcome.error_type() {
+        None => Audit::Verified { did, key_type },
+        Some(error) => Audit::Failed { did, key_type: Some(key_type), outcome: Some(outcome), error },
+    }
+}
+
+/// Outcome of a `reverify` run.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ReverifyReport {
+    /// Stored commits looked at; gaps and tombstoned seqs aren't counted
+    pub checked: u64,
+    pub verified: u64,
+    /// Stored frames that aren't commits (identity, account events)
+    pub skipped: u64,
+    /// Failures per `ErrorType`
+    pub by_error: BTreeMap<String, u64>,
+    /// Per key type, for commits whose key was found
+    pub by_key_type: BTreeMap<String, KeyTypeCounts>,
+    /// Every failure, in seq order
+    pub failures: Vec<ReverifyFailure>,
+}
+
+impl ReverifyReport {
+    fn check<F>(&mut self, seq: u64, msg: &[u8], lookup: &F)
+    where
+        F: Fn(&str) -> Option<([u8; 33], u8)>,
+    {
+        match audit(msg, lookup) {
+            Audit::Skipped => self.skipped += 1,
+            Audit::Verified { key_type, .. } => {
+                self.checked += 1;
+                self.verified += 1;
+                self.by_key_type.entry(key_type_name(key_type)).or_default().verified += 1;
+            }
+            Audit::Failed { did, key_type, outcome, error } => {
+                self.checked += 1;
+                if let Some(key_type) = key_type {
+                    self.by_key_type.entry(key_type_name(key_type)).or_default().failed += 1;
                 }
-            },
-            2 => { // P-256
-                let cache = P256_CACHE.get_or_init(|| DashMap::with_capacity(10000));
-                
-                let signature_res = p256::ecdsa::Signature::from_slice(sig_bytes);
-                if let Ok(signature) = signature_res {
-                    if let Some(vk) = cache.get(pubkey_bytes) {
-                        return vk.verify_prehash(&hash, &signature).is_ok();
-                    }
+                self.fail(seq, did, outcome, error);
+            }
+        }
+    }
 
-                    if let Ok(verifying_key) = p256::ecdsa::VerifyingKey::from_sec1_bytes(pubkey_bytes) {
-                        let ok = verifying_key.verify_prehash(&hash, &signature).is_ok();
-                        if cache.len() > 100_000 { cache.clear(); }
-                        cache.insert(*pubkey_bytes, verifying_key);
-                        return ok;
+    fn fail(&mut self, seq: u64, did: &str, outcome: Option<VerifyOutcome>, error: ErrorType) {
+        *self.by_error.entry(format!("{:?}", error)).or_default() += 1;
+        self.failures.push(ReverifyFailure { seq, did: did.to_string(), outcome, error });
+    }
+
+    fn merge(&mut self, other: ReverifyReport) {
+        self.checked += other.checked;
+        self.verified += other.verified;
+        self.skipped += other.skipped;
+        for (error, n) in other.by_error {
+            *self.by_error.entry(error).or_default() += n;
+        }
+        for (key_type, counts) in other.by_key_type {
+            let total = self.by_key_type.entry(key_type).or_default();
+            total.verified += counts.verified;
+            total.failed += counts.failed;
+        }
+        self.failures.extend(other.failures);
+    }
+
+    /// One `seq,did,verdict,error_kind` row per failure, in seq order, so two runs can be diffed.
+    pub fn write_csv<W: Write>(&self, mut out: W) -> io::Result<()> {
+        writeln!(out, "seq,did,verdict,error_kind")?;
+        for f in &self.failures {
+            writeln!(out, "{},{},{},{:?}", f.seq, f.did, f.verdict(), f.error)?;
+        }
+        out.flush()
+    }
+}
+
+fn key_type_name(key_type: u8) -> String {
+    match key_type {
+        1 => "secp256k1".to_string(),
+        2 => "p256".to_string(),
+        n => format!("type {}", n),
+    }
+}
+
+/// Re-verifies every stored commit in `range` (the whole archive by default) against keys
+/// from `cache` only, on `workers` threads.
+pub fn reverify(archive: &MultiShardArchive, cache: &MmapDidCache, range: Option<Range<u64>>, workers: usize) -> ReverifyReport {
+    reverify_with(archive, range, workers, |did| cache.get(did))
+}
+
+/// `reverify` with a custom key lookup, e.g. the cache with a network fallback.
+pub fn reverify_with<F>(archive: &MultiShardArchive, range: Option<Range<u64>>, workers: usize, lookup: F) -> ReverifyReport
+where
+    F: Fn(&str) -> Option<([u8; 33], u8)> + Sync,
+{
+    let range = match (range, archive.min_seq(), archive.max_seq()) {
+        (Some(range), _, _) => range,
+        (None, Some(min), Some(max)) => min..max + 1,
+        _ => return ReverifyReport::default(),
+    };
+
+    let next = AtomicU64::new(range.start);
+    let total = Mutex::new(ReverifyReport::default());
+    thread::scope(|scope| {
+        for _ in 0..workers.max(1) {
+            scope.spawn(|| {
+                let mut report = ReverifyReport::default();
+                loop {
+                    let start = next.fetch_add(REVERIFY_CHUNK, Ordering::Relaxed);
+                    if start >= range.end {
+                        break;
+                    }
+                    for (seq, at) in archive.iter_range(start, start.saturating_add(REVERIFY_CHUNK).min(range.end)) {
+                        // Tombstones come back as errors
+                        if let Ok(msg) = archive.read_at(seq, &at) {
+                            report.check(seq, &msg, &lookup);
+                        }
                     }
                 }
-            },
-            _ => return false,
+                total.lock().unwrap().merge(report);
+            });
         }
+    });
+
+    let mut report = total.into_inner().unwrap();
+    report.failures.sort_by_key(|f| f.seq);
+    report
+}
+
+/// Failures `verify_archived_range` keeps in its report; the counts cover all of them.
+pub const VERIFY_FAILURE_SAMPLE: usize = 100;
+
+/// Commits of one DID that did and didn't verify in a `verify_archived_range` audit.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DidVerifyCounts {
+    pub passed: u64,
+    pub failed: u64,
+}
+
+/// Outcome of `MultiShardArchive::verify_archived_range`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Stored commits looked at; gaps and tombstoned seqs aren't counted
+    pub checked: u64,
+    pub passed: u64,
+    pub failed: u64,
+    /// Stored frames that aren't commits (identity, account events)
+    pub skipped: u64,
+    /// Per repo, for commits that parsed far enough to name it
+    pub by_did: BTreeMap<String, DidVerifyCounts>,
+    /// The first `VERIFY_FAILURE_SAMPLE` failures, in seq order
+    pub sampled_failures: Vec<ReverifyFailure>,
+}
+
+impl VerifyReport {
+    /// True if every stored commit in the range verified.
+    pub fn passed_all(&self) -> bool {
+        self.failed == 0
     }
-    false
 }
 
+impl MultiShardArchive {
+    /// Decompresses every stored message in `start..end`, re-parses it and checks its commit
+    /// signature against the key `cache` holds now. A failure is either a damaged frame or
+    /// a key that no longer matches (rotated since it was archived); `reverify` is the
+    /// multi-threaded version for whole-archive sweeps.
+    pub fn verify_archived_range(&self, start: u64, end: u64, cache: &MmapDidCache) -> VerifyReport {
+        let lookup = |did: &str| cache.get(did);
+        let mut report = VerifyReport::default();
+        for (seq, at) in self.iter_range(start, end) {
+            // Tombstones come back as errors
+            let Ok(msg) = self.read_at(seq, &at) else { continue };
+            match audit(&msg, &lookup) {
+                Audit::Skipped => report.skipped += 1,
+                Audit::Verified { did, .. } => {
+                    report.checked += 1;
+                    report.passed += 1;
+                    report.by_did.entry(did.to_string()).or_default().passed += 1;
+                }
+                Audit::Failed { did, outcome, error, .. } => {
+                    report.checked += 1;
+                    report.failed += 1;
+                    if !did.is_empty() {
+                        report.by_did.entry(did.to_string()).or_default().failed += 1;
+                    }
+                    if report.sampled_failures.len() < VERIFY_FAILURE_SAMPLE {
+                        report.sampled_failures.push(ReverifyFailure { seq, did: did.to_string(), outcome, error });
+                    }
+                }
+            }
+        }
+        report
+    }
+}
@@ -0,0 +1,69 @@
+#[cfg(test)]
+mod bench_archive {
+    use did_mmap_cache::archive::cluster_cache::ClusterCache;
+    use did_mmap_cache::archive::{ArchiveWriter, Segment};
+    use memmap2::Mmap;
+    use std::fs::File;
+    use std::sync::Arc;
+    use std::thread;
+    use tempfile::tempdir;
+
+    const THREADS: usize = 8;
+    const READS_PER_THREAD: usize = 2_000;
+
+    /// One segment with `dids` clusters of 4 messages each.
+    fn build_segment(dir: &std::path::Path, dids: u64) -> (Segment, Vec<Vec<u8>>) {
+        let total = dids * 4;
+        let mut writer = ArchiveWriter::new(dir, 0, 0, total + 1, None).unwrap();
+        let mut expected = Vec::new();
+        for seq in 0..total {
+            let msg = format!("cluster {} message {} {}", seq % dids, seq, "x".repeat(200)).into_bytes();
+            writer.append_message(seq, &format!("did:plc:user{}", seq % dids), &format!("app.bsky.feed.post/{}", seq), &msg).unwrap();
+            expected.push(msg);
+        }
+        writer.finalize_segment().unwrap();
+
+        let bin = unsafe { Mmap::map(&File::open(dir.join("s0_0.bin")).unwrap()).unwrap() };
+        let idx = unsafe { Mmap::map(&File::open(dir.join("s0_0.idx")).unwrap()).unwrap() };
+        (Segment::new(0, bin, idx), expected)
+    }
+
+    /// Each thread walks its own slice of the index so readers hit different clusters.
+    /// Timing the same reads against a per-segment mutex is `cargo bench --bench cluster_cache`.
+    #[test]
+    fn test_concurrent_cluster_reads() {
+        let dir = tempdir().unwrap();
+        let (segment, expected) = build_segment(dir.path(), 128);
+        let (segment, expected) = (Arc::new(segment), Arc::new(expected));
+        let cache = Arc::new(ClusterCache::default());
+
+        let handles: Vec<_> = (0..THREADS).map(|t| {
+            let (segment, expected, cache) = (Arc::clone(&segment), Arc::clone(&expected), Arc::clone(&cache));
+            thread::spawn(move || {
+                for i in 0..READS_PER_THREAD {
+                    let index = (t * 7919 + i) % expected.len();
+                    assert_eq!(segment.get_decompressed_message_by_index(index as u64, None, &cache).unwrap(), expected[index], "index {}", index);
+                }
+            })
+        }).collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+        // Repeat reads were served from the cache
+        assert!(cache.stats().hits > 0);
+    }
+
+    #[test]
+    fn test_cluster_cache_eviction_keeps_reads_correct() {
+        let dir = tempdir().unwrap();
+        // More clusters than the cache holds, so reads cycle through eviction
+        let (segment, expected) = build_segment(dir.path(), 700);
+        let cache = ClusterCache::new(64 * 1024);
+        for _ in 0..2 {
+            for (i, msg) in expected.iter().enumerate() {
+                assert_eq!(&segment.get_decompressed_message_by_index(i as u64, None, &cache).unwrap(), msg, "index {}", i);
+            }
+        }
+        assert!(cache.stats().evictions > 0);
+    }
+}
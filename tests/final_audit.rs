@@ -1,11 +1,9 @@
 #[cfg(test)]
 mod audit {
-    use did_mmap_cache::archive::{ArchiveWriter, SegmentedArchive, MultiShardArchive, TombstoneStore};
+    use did_mmap_cache::archive::{ArchiveWriter, PathHash, SegmentedArchive, MultiShardArchive, TombstoneStore};
     use tempfile::tempdir;
     use std::fs;
     use std::io::Write;
-    use fxhash::FxHasher;
-    use std::hash::{Hasher, Hash};
 
     #[test]
     fn test_se1_index_record_size() {
@@ -16,7 +14,7 @@ mod audit {
 
         let idx_path = dir.path().join("s0_0.idx");
         let metadata = fs::metadata(idx_path).unwrap();
-        assert_eq!(metadata.len(), 60, "Index file should be exactly 60 bytes for 1 message");
+        assert_eq!(metadata.len(), 76, "Index file should be a 48-byte header and one 28-byte record");
     }
 
     #[test]
@@ -80,9 +78,7 @@ mod audit {
         let dir = tempdir().unwrap();
         let mut writer = ArchiveWriter::new(dir.path(), 0, 0, 100, None).unwrap();
         let path = "app.bsky.feed.post/12345";
-        let mut hasher = FxHasher::default();
-        path.hash(&mut hasher);
-        let target_hash = hasher.finish();
+        let target_hash = PathHash::default().hash(path);
         writer.append_message(500, "did:1", path, b"data").unwrap();
         writer.finalize_segment().unwrap();
         let archive = SegmentedArchive::open_directory(dir.path(), None, None).unwrap();
@@ -96,7 +92,7 @@ mod audit {
         let mut ts = TombstoneStore::open_or_create(&ts_path).unwrap();
         let start = std::time::Instant::now();
         for i in 0..10_000 {
-            ts.mark_deleted(i as u64);
+            ts.mark_deleted(i as u64).unwrap();
         }
         let per_op = start.elapsed().as_nanos() / 10_000;
         assert!(per_op < 1000); // Allow more overhead in virtualized environment
@@ -116,7 +112,7 @@ mod audit {
     #[test]
     fn test_ci3_crypto_integration() {
         use did_mmap_cache::verify::verify_commit;
-        use did_mmap_cache::parser::core::CommitEnvelope;
+        use did_mmap_cache::parser::core::{CommitEnvelope, RepoOps};
         use k256::ecdsa::{SigningKey, signature::Signer, signature::hazmat::PrehashSigner};
         use sha2::Digest;
 
@@ -148,7 +144,7 @@ mod audit {
             commit: Some(&commit_raw), 
             cid: None,
             record_cid: None,
-            ops: vec![],
+            ops: RepoOps::new(),
             source_type: "test",
         };
 
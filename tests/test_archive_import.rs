@@ -0,0 +1,106 @@
+#[cfg(test)]
+mod archive_import_tests {
+    use did_mmap_cache::archive::{decode_cluster, MultiShardArchive, PathHash, SegmentedArchive, DEFAULT_MAX_DECOMPRESSED_CLUSTER_BYTES};
+    use std::fs;
+    use std::io::ErrorKind;
+    use std::path::Path;
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    const OFFSET: u64 = 1_000;
+
+    fn path_of(seq: u64) -> String {
+        format!("app.bsky.feed.post/{}", seq)
+    }
+
+    fn message(node: &str, seq: u64) -> Vec<u8> {
+        format!("{} message {}", node, seq).into_bytes()
+    }
+
+    fn path_hash(path: &str) -> u64 {
+        PathHash::default().hash(path)
+    }
+
+    /// One shard of 99 messages over 4 segments, every tenth seq left as a gap.
+    fn write_node(root: &Path, node: &str, dict: Option<Vec<u8>>) {
+        let archive = MultiShardArchive::new(root, 1, 25, dict).unwrap();
+        archive.set_message_hashes(true);
+        for seq in stored_seqs() {
+            archive.ingest(seq, &format!("did:plc:{}{}", node, seq % 7), path_of(seq), message(node, seq));
+        }
+        archive.shutdown();
+    }
+
+    fn stored_seqs() -> impl Iterator<Item = u64> {
+        (0..110u64).filter(|s| s % 10 != 9)
+    }
+
+    #[test]
+    fn test_import_remaps_every_seq() {
+        let dir = tempdir().unwrap();
+        let (remote, local) = (dir.path().join("remote"), dir.path().join("local"));
+        write_node(&remote, "remote", None);
+        write_node(&local, "local", None);
+        assert_eq!(stored_seqs().count(), 99);
+
+        let shard = SegmentedArchive::open_directory(local.join("shard_0"), None, None).unwrap();
+        assert_eq!(shard.import_segments(&remote.join("shard_0"), OFFSET).unwrap(), 4);
+
+        for seq in stored_seqs() {
+            assert_eq!(shard.get_message_by_seq(seq + OFFSET, None).unwrap(), message("remote", seq), "seq {}", seq);
+            assert_eq!(shard.get_message_by_seq(seq, None).unwrap(), message("local", seq), "seq {}", seq);
+            let cluster = shard.get_raw_cluster_at_seq(seq + OFFSET).unwrap();
+            let records = decode_cluster(&cluster, None, DEFAULT_MAX_DECOMPRESSED_CLUSTER_BYTES).unwrap();
+            assert!(records.iter().all(|(s, _)| *s >= OFFSET), "cluster at {} kept an old seq", seq);
+            assert!(records.contains(&(seq + OFFSET, message("remote", seq))));
+        }
+        assert!(shard.get_message_by_seq(9 + OFFSET, None).is_err());
+        assert_eq!(shard.max_seq(), Some(108 + OFFSET));
+
+        // Roots still match, and the sidecars moved with the seqs
+        assert!(shard.verify_integrity_at_seq(OFFSET, None).unwrap());
+        assert_eq!(shard.find_seq_by_path_hash(path_hash(&path_of(42))), Some(42 + OFFSET));
+        assert_eq!(shard.contains_hash(blake3::hash(&message("remote", 42)).as_bytes()), Some(42 + OFFSET));
+
+        // The imported segments survive a fresh open
+        let reopened = MultiShardArchive::open_readonly(&local, None).unwrap();
+        assert_eq!(reopened.get_message_by_seq(OFFSET + 108).unwrap(), message("remote", 108));
+        assert!(reopened.consistency_report().is_consistent());
+    }
+
+    #[test]
+    fn test_import_refuses_overlaps_without_writing() {
+        let dir = tempdir().unwrap();
+        let (remote, local) = (dir.path().join("remote"), dir.path().join("local"));
+        write_node(&remote, "remote", None);
+        write_node(&local, "local", None);
+        let shard = SegmentedArchive::open_directory(local.join("shard_0"), None, None).unwrap();
+        let before = fs::read_dir(local.join("shard_0")).unwrap().count();
+        // Lands on top of the local seqs 50..
+        let err = shard.import_segments(&remote.join("shard_0"), 50).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert_eq!(fs::read_dir(local.join("shard_0")).unwrap().count(), before);
+        assert_eq!(shard.get_message_by_seq(60, None).unwrap(), message("local", 60));
+
+        // Importing the same segments twice collides with the first copy
+        shard.import_segments(&remote.join("shard_0"), OFFSET).unwrap();
+        assert_eq!(shard.import_segments(&remote.join("shard_0"), OFFSET).unwrap_err().kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_import_checks_the_dictionary() {
+        let dir = tempdir().unwrap();
+        let dict = b"did:plc: app.bsky.feed.post message remote local ".repeat(64);
+        let (remote, local) = (dir.path().join("remote"), dir.path().join("local"));
+        write_node(&remote, "remote", Some(dict.clone()));
+
+        let plain = SegmentedArchive::open_directory(local.join("plain"), None, None).unwrap();
+        assert_eq!(plain.import_segments(&remote.join("shard_0"), OFFSET).unwrap_err().kind(), ErrorKind::InvalidData);
+
+        let dict = Arc::new(dict);
+        let same = SegmentedArchive::open_directory(local.join("same"), None, Some(Arc::clone(&dict))).unwrap();
+        assert_eq!(same.import_segments(&remote.join("shard_0"), OFFSET).unwrap(), 4);
+        assert_eq!(same.get_message_by_seq(OFFSET + 17, Some(&dict[..])).unwrap(), message("remote", 17));
+        assert_eq!(same.dict_hash_at_seq(OFFSET + 17), Some(did_mmap_cache::archive::dict_hash(&dict)));
+    }
+}
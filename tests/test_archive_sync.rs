@@ -0,0 +1,163 @@
+#[cfg(test)]
+mod archive_sync_tests {
+    use did_mmap_cache::archive::sync::{build_manifest, SyncClient, SyncServer};
+    use did_mmap_cache::archive::{ArchiveMeta, MultiShardArchive};
+    use std::fs;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    const TOTAL: u64 = 300;
+
+    /// Two-shard source archive with a few tombstones, served on a local port.
+    fn serve_source(root: &Path) -> SyncClient {
+        let archive = MultiShardArchive::new(root, 2, 50, None).unwrap();
+        for seq in 0..TOTAL {
+            let did = format!("did:plc:user{}", seq % 11);
+            archive.ingest(seq, &did, format!("app.bsky.feed.post/{}", seq), format!("message {} {}", seq, "x".repeat(64)).into_bytes());
+        }
+        archive.shutdown();
+        let reader = MultiShardArchive::open_readonly(root, None).unwrap();
+        for seq in [3, 77, 150] {
+            reader.mark_deleted(seq).unwrap();
+        }
+
+        let server = SyncServer::bind(root, "127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.local_addr().unwrap());
+        server.spawn();
+        SyncClient::new(&url).unwrap()
+    }
+
+    fn assert_mirrors(source: &Path, mirror: &Path) {
+        let src = MultiShardArchive::open_readonly(source, None).unwrap();
+        let dst = MultiShardArchive::open_readonly(mirror, None).unwrap();
+        for seq in 0..TOTAL {
+            match src.get_message_by_seq(seq) {
+                Ok(msg) => assert_eq!(dst.get_message_by_seq(seq).unwrap(), msg, "seq {}", seq),
+                Err(_) => assert!(dst.get_message_by_seq(seq).is_err(), "seq {} should be deleted", seq),
+            }
+        }
+    }
+
+    /// Every file under the mirror's shard directories with this extension.
+    fn files_with_ext(root: &Path, ext: &str) -> Vec<std::path::PathBuf> {
+        let mut out = Vec::new();
+        for shard in fs::read_dir(root).unwrap().flatten().filter(|e| e.path().is_dir()) {
+            for file in fs::read_dir(shard.path()).unwrap().flatten() {
+                if file.path().extension().is_some_and(|x| x == ext) {
+                    out.push(file.path());
+                }
+            }
+        }
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn test_pull_mirrors_archive_and_skips_known_segments() {
+        let dir = tempdir().unwrap();
+        let (source, mirror) = (dir.path().join("source"), dir.path().join("mirror"));
+        let client = serve_source(&source);
+
+        let manifest = build_manifest(&source).unwrap();
+        assert_eq!(manifest.num_shards, 2);
+        assert!(manifest.segments.len() >= 2);
+        assert_eq!(client.manifest().unwrap(), manifest);
+
+        let report = client.pull(&mirror).unwrap();
+        assert!(report.failed.is_empty(), "{:?}", report.failed);
+        assert_eq!(report.downloaded, manifest.segments.len());
+        assert_eq!(report.tombstones_added, 3);
+        assert_eq!(ArchiveMeta::load(&mirror).unwrap().unwrap().num_shards, 2);
+        assert!(files_with_ext(&mirror, "partial").is_empty());
+        assert_mirrors(&source, &mirror);
+
+        // Nothing new: no segment transferred, no tombstone new
+        let again = client.pull(&mirror).unwrap();
+        assert_eq!((again.downloaded, again.up_to_date, again.bytes, again.tombstones_added), (0, manifest.segments.len(), 0, 0));
+    }
+
+    #[test]
+    fn test_interrupted_download_resumes() {
+        let dir = tempdir().unwrap();
+        let (source, mirror) = (dir.path().join("source"), dir.path().join("mirror"));
+        let client = serve_source(&source);
+        client.pull(&mirror).unwrap();
+
+        // Simulate a transfer cut off halfway through a .bin
+        let seg = &client.manifest().unwrap().segments[0];
+        let bin = files_with_ext(&mirror, "bin").into_iter()
+            .find(|p| p.ends_with(format!("s{}_{}.bin", seg.shard, seg.start_seq)))
+            .unwrap();
+        let full = fs::read(&bin).unwrap();
+        let half = full.len() / 2;
+        fs::write(bin.with_extension("bin.partial"), &full[..half]).unwrap();
+        fs::remove_file(&bin).unwrap();
+
+        let report = client.pull(&mirror).unwrap();
+        assert!(report.failed.is_empty(), "{:?}", report.failed);
+        assert_eq!(report.downloaded, 1);
+        // Only the missing half of the .bin is re-sent (plus the small index files)
+        let index_bytes = seg.idx_size + if seg.has_path_index { seg.message_count * 16 } else { 0 };
+        assert_eq!(report.bytes, (full.len() - half) as u64 + index_bytes);
+        assert_eq!(fs::read(&bin).unwrap(), full);
+        assert_mirrors(&source, &mirror);
+    }
+
+    #[test]
+    fn test_corrupt_download_is_rejected_then_refetched() {
+        let dir = tempdir().unwrap();
+        let (source, mirror) = (dir.path().join("source"), dir.path().join("mirror"));
+        let client = serve_source(&source);
+
+        // A full-length partial with the wrong bytes is taken as complete, then fails verification
+        let seg = client.manifest().unwrap().segments[0].clone();
+        let shard_dir = mirror.join(format!("shard_{}", seg.shard));
+        fs::create_dir_all(&shard_dir).unwrap();
+        let partial = shard_dir.join(format!("s{}_{}.bin.partial", seg.shard, seg.start_seq));
+        fs::write(&partial, vec![0xAB; seg.bin_size as usize]).unwrap();
+
+        let report = client.pull(&mirror).unwrap();
+        assert_eq!(report.failed.len(), 1, "{:?}", report.failed);
+        assert!(report.failed[0].contains("Merkle root mismatch"), "{}", report.failed[0]);
+        assert!(!partial.exists());
+        assert!(!shard_dir.join(format!("s{}_{}.idx", seg.shard, seg.start_seq)).exists());
+
+        let report = client.pull(&mirror).unwrap();
+        assert!(report.failed.is_empty(), "{:?}", report.failed);
+        assert_eq!(report.downloaded, 1);
+        assert_mirrors(&source, &mirror);
+    }
+
+    #[test]
+    fn test_tombstones_are_merged() {
+        let dir = tempdir().unwrap();
+        let (source, mirror) = (dir.path().join("source"), dir.path().join("mirror"));
+        let client = serve_source(&source);
+        client.pull(&mirror).unwrap();
+
+        // Deleted on the mirror only, then on the source only
+        MultiShardArchive::open_readonly(&mirror, None).unwrap().mark_deleted(10).unwrap();
+        MultiShardArchive::open_readonly(&source, None).unwrap().mark_deleted(200).unwrap();
+
+        let report = client.pull(&mirror).unwrap();
+        assert_eq!(report.tombstones_added, 1);
+        let dst = MultiShardArchive::open_readonly(&mirror, None).unwrap();
+        for seq in [3, 10, 77, 150, 200] {
+            assert!(dst.get_message_by_seq(seq).is_err(), "seq {}", seq);
+        }
+        assert!(dst.get_message_by_seq(11).is_ok());
+    }
+
+    #[test]
+    fn test_shard_count_mismatch_is_refused() {
+        let dir = tempdir().unwrap();
+        let (source, mirror) = (dir.path().join("source"), dir.path().join("mirror"));
+        let client = serve_source(&source);
+        fs::create_dir_all(&mirror).unwrap();
+        ArchiveMeta { num_shards: 3 }.save(&mirror).unwrap();
+
+        let err = client.pull(&mirror).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(files_with_ext(&mirror, "bin").is_empty());
+    }
+}
@@ -0,0 +1,45 @@
+#[cfg(test)]
+mod cache_diff_tests {
+    use did_mmap_cache::mmap_did_cache::{hash_did, CacheDiff, KeyChange, MmapDidCache};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_diff_reports_added_dropped_and_rekeyed_dids() {
+        let dir = tempdir().unwrap();
+        let mut old = MmapDidCache::create(dir.path().join("old.bin"), 64).unwrap();
+        // A different slot count, so the same DID sits in different slots
+        let mut new = MmapDidCache::create(dir.path().join("new.bin"), 101).unwrap();
+
+        for did in ["did:plc:same", "did:plc:rotated", "did:plc:retyped", "did:plc:dropped", "did:plc:tombstoned"] {
+            assert!(old.atomic_update_or_tombstone(did, Some(1), Some(&[1; 33])));
+        }
+        assert!(new.atomic_update_or_tombstone("did:plc:same", Some(1), Some(&[1; 33])));
+        assert!(new.atomic_update_or_tombstone("did:plc:rotated", Some(1), Some(&[2; 33])));
+        assert!(new.atomic_update_or_tombstone("did:plc:retyped", Some(2), Some(&[1; 33])));
+        assert!(new.atomic_update_or_tombstone("did:plc:tombstoned", None, None));
+        assert!(new.atomic_update_or_tombstone("did:plc:added", Some(2), Some(&[3; 33])));
+
+        let diff = old.diff(&new);
+        let mut dropped = vec![hash_did("did:plc:dropped"), hash_did("did:plc:tombstoned")];
+        dropped.sort_unstable();
+        assert_eq!(diff.only_in_self, dropped);
+        assert_eq!(diff.only_in_other, vec![hash_did("did:plc:added")]);
+        let mut changed = vec![
+            KeyChange { did_hash: hash_did("did:plc:rotated"), old: ([1; 33], 1), new: ([2; 33], 1) },
+            KeyChange { did_hash: hash_did("did:plc:retyped"), old: ([1; 33], 1), new: ([1; 33], 2) },
+        ];
+        changed.sort_unstable_by_key(|c| c.did_hash);
+        assert_eq!(diff.changed, changed);
+
+        let labels = diff.label(["did:plc:rotated", "did:plc:added", "did:plc:same", "did:plc:unknown"]);
+        assert_eq!(labels.len(), 2);
+        assert_eq!(labels[&hash_did("did:plc:rotated")], "did:plc:rotated");
+        assert_eq!(labels[&hash_did("did:plc:added")], "did:plc:added");
+
+        // The other way round, added and dropped swap
+        let reverse = new.diff(&old);
+        assert_eq!((reverse.only_in_self, reverse.only_in_other), (diff.only_in_other, diff.only_in_self));
+        assert_eq!(old.diff(&old), CacheDiff::default());
+        assert!(old.diff(&old).is_empty());
+    }
+}
@@ -0,0 +1,92 @@
+#[cfg(test)]
+mod cache_lock_tests {
+    use did_mmap_cache::mmap_did_cache::MmapDidCache;
+    use std::path::Path;
+    use std::process::{Child, Command};
+    use std::time::{Duration, Instant};
+    use tempfile::tempdir;
+
+    // Set in a child process to the cache it should write; the stop file ends it
+    const CACHE_ENV: &str = "CACHE_LOCK_TEST_CACHE";
+    const STOP_ENV: &str = "CACHE_LOCK_TEST_STOP";
+    const DIDS: usize = 6;
+
+    fn did(i: usize) -> String {
+        format!("did:plc:writer{}", i)
+    }
+
+    /// The writer side of `test_no_torn_reads_across_processes`, run in a child process:
+    /// rewrites every DID with key type `b` and a pubkey of all `b`s, for ever-changing `b`,
+    /// tombstoning one of them now and then.
+    #[test]
+    #[ignore]
+    fn writer_child() {
+        let (Ok(cache), Ok(stop)) = (std::env::var(CACHE_ENV), std::env::var(STOP_ENV)) else {
+            return;
+        };
+        let mut cache = MmapDidCache::open_mut(cache).unwrap();
+        let mut round = 0usize;
+        while !Path::new(&stop).exists() {
+            for i in 0..DIDS {
+                let b = (round % 255 + 1) as u8;
+                if (round + i).is_multiple_of(7) {
+                    cache.atomic_update_or_tombstone(&did(i), None, None);
+                } else {
+                    assert!(cache.atomic_update_or_tombstone(&did(i), Some(b), Some(&[b; 33])));
+                }
+                round += 1;
+            }
+        }
+    }
+
+    fn spawn_writer(cache: &Path, stop: &Path) -> Child {
+        Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "cache_lock_tests::writer_child", "--ignored", "--nocapture", "--test-threads=1"])
+            .env(CACHE_ENV, cache)
+            .env(STOP_ENV, stop)
+            .spawn()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_no_torn_reads_across_processes() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("cache.bin");
+        let stop = dir.path().join("stop");
+        drop(MmapDidCache::create(&path, 16).unwrap());
+        let mut child = spawn_writer(&path, &stop);
+
+        // Once the child holds the writer lock, another writer fails fast
+        let deadline = Instant::now() + Duration::from_secs(30);
+        loop {
+            match MmapDidCache::try_open_mut(&path) {
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => break,
+                Err(e) => panic!("{}", e),
+                Ok(_) => {
+                    assert!(Instant::now() < deadline, "writer never took the lock");
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+            }
+        }
+        assert!(matches!(MmapDidCache::create(&path, 16), Err(e) if e.kind() == std::io::ErrorKind::WouldBlock));
+
+        // Hammer the slots the child is rewriting; a torn read would mix two rounds
+        let reader = MmapDidCache::open(&path).unwrap();
+        let mut seen = std::collections::HashSet::new();
+        let until = Instant::now() + Duration::from_secs(1);
+        while Instant::now() < until {
+            for i in 0..DIDS {
+                if let Some((pubkey, key_type)) = reader.get(&did(i)) {
+                    assert!(pubkey.iter().all(|&b| b == key_type), "torn entry for {}: {} {:?}", did(i), key_type, pubkey);
+                    seen.insert(key_type);
+                }
+            }
+        }
+        assert!(seen.len() > 1, "writer made no progress");
+
+        std::fs::write(&stop, b"").unwrap();
+        assert!(child.wait().unwrap().success());
+        // The lock goes with the writer
+        MmapDidCache::try_open_mut(&path).unwrap();
+    }
+}
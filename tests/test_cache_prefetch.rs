@@ -0,0 +1,25 @@
+#[cfg(test)]
+mod cache_prefetch_tests {
+    use did_mmap_cache::mmap_did_cache::MmapDidCache;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_prefetch_and_lock_keep_lookups_working() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("cache.bin");
+        let mut writer = MmapDidCache::create(&path, 64).unwrap();
+        assert!(writer.atomic_update_or_tombstone("did:plc:warm", Some(1), Some(&[7; 33])));
+        writer.prefetch().unwrap();
+        writer.advise_random().unwrap();
+        drop(writer);
+
+        let reader = MmapDidCache::open(&path).unwrap();
+        reader.prefetch().unwrap();
+        reader.advise_random().unwrap();
+        // A handful of slots stays well under the default RLIMIT_MEMLOCK; ranges past the end are clamped
+        reader.lock_slots(0..8).unwrap();
+        reader.lock_slots(60..1000).unwrap();
+        reader.lock_slots(100..200).unwrap();
+        assert_eq!(reader.get("did:plc:warm"), Some(([7; 33], 1)));
+    }
+}
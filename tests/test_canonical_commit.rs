@@ -0,0 +1,166 @@
+#[cfg(test)]
+mod canonical_commit_tests {
+    use did_mmap_cache::parser::canonical::{hash_canonical_commit, prepare_canonical_commit};
+    use did_mmap_cache::parser::core::{CommitEnvelope, RepoOps};
+    use did_mmap_cache::verify::{verify_commit_detailed, VerifyOutcome};
+    use k256::ecdsa::signature::hazmat::PrehashSigner;
+    use libipld::cbor::DagCborCodec;
+    use libipld::codec::Codec;
+    use libipld::{Cid, Ipld};
+    use sha2::{Digest, Sha256};
+    use std::collections::BTreeMap;
+
+    fn envelope<'a>(commit: &'a [u8], signature: &'a [u8]) -> CommitEnvelope<'a> {
+        CommitEnvelope {
+            did: None,
+            sequence: None,
+            signature: Some(signature),
+            t: None,
+            op: None,
+            raw: &[],
+            blocks: None,
+            commit: Some(commit),
+            cid: None,
+            record_cid: None,
+            ops: RepoOps::new(),
+            source_type: "test",
+        }
+    }
+
+    /// A dag-cbor sha2-256 CIDv1.
+    fn cid(seed: u8) -> Cid {
+        let mut bytes = vec![0x01, 0x71, 0x12, 0x20];
+        bytes.extend_from_slice(&Sha256::digest([seed]));
+        Cid::read_bytes(&bytes[..]).unwrap()
+    }
+
+    /// An unsigned v3 commit, with `prev` null or a link, plus `extra` keys.
+    fn commit(prev: Option<Cid>, extra: &[(String, Ipld)]) -> BTreeMap<String, Ipld> {
+        let mut map = BTreeMap::from([
+            ("did".to_string(), Ipld::String("did:plc:ewvi7nxzyoun6zhxrhs64oiz".to_string())),
+            ("rev".to_string(), Ipld::String("3l3qo2vutsw2b".to_string())),
+            ("data".to_string(), Ipld::Link(cid(1))),
+            ("prev".to_string(), prev.map_or(Ipld::Null, Ipld::Link)),
+            ("version".to_string(), Ipld::Integer(3)),
+        ]);
+        for (key, value) in extra {
+            map.insert(key.clone(), value.clone());
+        }
+        map
+    }
+
+    /// A fixed secp256k1 (1) or P-256 (2) key's compressed public key and its low-S
+    /// signature over `digest`.
+    fn sign(key_type: u8, digest: &[u8]) -> ([u8; 33], Vec<u8>) {
+        let secret = [0x42; 32];
+        match key_type {
+            1 => {
+                let key = k256::ecdsa::SigningKey::from_slice(&secret).unwrap();
+                let sig: k256::ecdsa::Signature = key.sign_prehash(digest).unwrap();
+                (key.verifying_key().to_sec1_bytes().as_ref().try_into().unwrap(), sig.to_bytes().to_vec())
+            }
+            _ => {
+                let key = p256::ecdsa::SigningKey::from_slice(&secret).unwrap();
+                let sig: p256::ecdsa::Signature = key.sign_prehash(digest).unwrap();
+                let pubkey = key.verifying_key().to_encoded_point(true).as_bytes().try_into().unwrap();
+                (pubkey, sig.normalize_s().unwrap_or(sig).to_bytes().to_vec())
+            }
+        }
+    }
+
+    /// A CBOR text string with its header.
+    fn text(s: &str) -> Vec<u8> {
+        let mut out = match s.len() {
+            len @ 0..=23 => vec![0x60 | len as u8],
+            len => vec![0x78, len as u8],
+        };
+        out.extend_from_slice(s.as_bytes());
+        out
+    }
+
+    /// A definite-length map of `entries` in the order given, values already encoded.
+    fn map(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut out = vec![0xa0 | entries.len() as u8];
+        for (key, value) in entries {
+            out.extend(text(key));
+            out.extend_from_slice(value);
+        }
+        out
+    }
+
+    #[test]
+    fn test_signed_commits_verify_across_key_sets() {
+        let keys = |set: &[(&str, Ipld)]| -> Vec<(String, Ipld)> { set.iter().map(|(k, v)| (k.to_string(), v.clone())).collect() };
+        let key_sets: Vec<Vec<(String, Ipld)>> = vec![
+            vec![],
+            // Keys that share a prefix with, or sort next to, the stripped `sig`
+            keys(&[("sig2", Ipld::Bytes(vec![1, 2])), ("si", Ipld::Bool(true)), ("sigs", Ipld::Null)]),
+            // Shorter keys first even where bytewise order says otherwise
+            keys(&[("z", Ipld::Integer(1)), ("aa", Ipld::Integer(2)), ("Z", Ipld::Integer(3)), ("zzzzzzzzzz", Ipld::Integer(4))]),
+            // The 1- and 2-byte text headers meet at 24 bytes
+            vec![("a".repeat(23), Ipld::Integer(1)), ("a".repeat(24), Ipld::Integer(2)), ("b".repeat(23), Ipld::Integer(3))],
+            keys(&[("ключ", Ipld::String("значение".to_string())), ("\u{1F600}", Ipld::List(vec![Ipld::Integer(-1)]))]),
+            // More entries than fit on the stack
+            (0..20).map(|i| (format!("k{:02}", i), Ipld::Integer(i))).collect(),
+        ];
+
+        for (set, extra) in key_sets.iter().enumerate() {
+            for prev in [None, Some(cid(2))] {
+                for key_type in [1, 2] {
+                    let unsigned = commit(prev, extra);
+                    let unsigned_bytes = DagCborCodec.encode(&Ipld::Map(unsigned.clone())).unwrap();
+                    let (pubkey, sig) = sign(key_type, &Sha256::digest(&unsigned_bytes));
+
+                    let mut signed = unsigned;
+                    signed.insert("sig".to_string(), Ipld::Bytes(sig.clone()));
+                    let block = DagCborCodec.encode(&Ipld::Map(signed)).unwrap();
+
+                    let case = format!("key set {}, prev {:?}, key type {}", set, prev.is_some(), key_type);
+                    assert_eq!(prepare_canonical_commit(&block).as_deref(), Some(&unsigned_bytes[..]), "{}", case);
+                    let mut hasher = Sha256::new();
+                    assert!(hash_canonical_commit(&block, &mut hasher), "{}", case);
+                    assert_eq!(hasher.finalize(), Sha256::digest(&unsigned_bytes), "{}", case);
+                    assert_eq!(verify_commit_detailed(&envelope(&block, &sig), &pubkey, key_type), VerifyOutcome::Ok, "{}", case);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_keys_sort_by_length_then_bytes() {
+        let one = [0x01];
+        let scrambled = map(&[("version", &one), ("sig", &[0x40]), ("aa", &one), ("b", &one), ("did", &one), ("Z", &one)]);
+        let expected = map(&[("Z", &one), ("b", &one), ("aa", &one), ("did", &one), ("version", &one)]);
+        assert_eq!(prepare_canonical_commit(&scrambled), Some(expected.clone()));
+
+        // The same map with an indefinite-length header, and behind a tag
+        let mut indefinite = vec![0xbf];
+        indefinite.extend_from_slice(&scrambled[1..]);
+        indefinite.push(0xff);
+        assert_eq!(prepare_canonical_commit(&indefinite), Some(expected.clone()));
+        let mut tagged = vec![0xd8, 0x2a];
+        tagged.extend_from_slice(&scrambled);
+        assert_eq!(prepare_canonical_commit(&tagged), Some(expected));
+    }
+
+    #[test]
+    fn test_non_canonicalizable_blocks_are_refused() {
+        let one = [0x01];
+        let cases: Vec<(&str, Vec<u8>)> = vec![
+            ("integer key", vec![0xa2, 0x01, 0x01, 0x63, b'd', b'i', b'd', 0x01]),
+            ("repeated key", map(&[("did", &one), ("did", &[0x02])])),
+            ("no break byte", {
+                let mut m = map(&[("did", &one)]);
+                m[0] = 0xbf;
+                m
+            }),
+            ("array", vec![0x82, 0x01, 0x02]),
+            ("only sig", map(&[("sig", &[0x40])])),
+            ("key past the end", vec![0xa1, 0x78, 0xff, b'a']),
+        ];
+        for (name, block) in cases {
+            assert_eq!(prepare_canonical_commit(&block), None, "{}", name);
+            assert!(!hash_canonical_commit(&block, &mut Sha256::new()), "{}", name);
+        }
+    }
+}
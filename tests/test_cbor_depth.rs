@@ -0,0 +1,86 @@
+#[cfg(test)]
+mod cbor_depth_tests {
+    use did_mmap_cache::parser::core::{parse_input, skip_cbor_value, ParseError, MAX_CBOR_DEPTH};
+    use did_mmap_cache::mst::MstNode;
+    use did_mmap_cache::mmap_cache_entry::parse_commit_block;
+
+    const DEEP: usize = 10_000;
+
+    /// `[[[[ ... 0 ... ]]]]`, one 0x81 header per level
+    fn nested_array(depth: usize) -> Vec<u8> {
+        let mut v = vec![0x81; depth];
+        v.push(0x00);
+        v
+    }
+
+    /// Runs `f` on a thread with the same 256KB stack the ingester workers use.
+    fn on_small_stack<F: FnOnce() + Send + 'static>(f: F) {
+        std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(f)
+            .unwrap()
+            .join()
+            .expect("parser overflowed a 256KB stack");
+    }
+
+    #[test]
+    fn test_skip_rejects_deep_nesting() {
+        on_small_stack(|| {
+            let buf = nested_array(DEEP);
+            assert!(matches!(skip_cbor_value(&buf, 0), Err(ParseError::DepthExceeded { .. })));
+
+            // Right at the cap still works
+            let ok = nested_array(MAX_CBOR_DEPTH);
+            assert_eq!(skip_cbor_value(&ok, 0), Ok(ok.len()));
+
+            // Same for maps and tags
+            let mut maps = Vec::new();
+            for _ in 0..DEEP { maps.extend_from_slice(&[0xa1, 0x61, b'k']); }
+            maps.push(0x00);
+            assert!(matches!(skip_cbor_value(&maps, 0), Err(ParseError::DepthExceeded { .. })));
+
+            let mut tags = vec![0xc1; DEEP];
+            tags.push(0x00);
+            assert!(matches!(skip_cbor_value(&tags, 0), Err(ParseError::DepthExceeded { .. })));
+        });
+    }
+
+    #[test]
+    fn test_parse_input_deep_frame() {
+        on_small_stack(|| {
+            // As a header
+            let mut frame = nested_array(DEEP);
+            frame.extend_from_slice(&[0xa0]);
+            assert!(matches!(parse_input(&frame), Err(ParseError::DepthExceeded { .. })));
+
+            // As an unknown payload field
+            let mut frame = vec![0xa1, 0x61, b't', 0x67];
+            frame.extend_from_slice(b"#commit");
+            frame.extend_from_slice(&[0xa1, 0x63]);
+            frame.extend_from_slice(b"xyz");
+            frame.extend_from_slice(&nested_array(DEEP));
+            assert!(matches!(parse_input(&frame), Err(ParseError::DepthExceeded { .. })));
+        });
+    }
+
+    #[test]
+    fn test_mst_and_commit_block_deep_values() {
+        on_small_stack(|| {
+            // MST node { "e": [[[[...]]]] }
+            let mut node = vec![0xa1, 0x61, b'e'];
+            node.extend_from_slice(&nested_array(DEEP));
+            assert!(MstNode::from_bytes(&node).is_err());
+
+            // Commit block with an unknown deeply nested field
+            let mut commit = vec![0xa2, 0x63];
+            commit.extend_from_slice(b"zzz");
+            commit.extend_from_slice(&nested_array(DEEP));
+            commit.extend_from_slice(&[0x63]);
+            commit.extend_from_slice(b"did");
+            commit.extend_from_slice(&[0x63]);
+            commit.extend_from_slice(b"abc");
+            let parsed = parse_commit_block(&commit);
+            assert!(parsed.did.is_none());
+        });
+    }
+}
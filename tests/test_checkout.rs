@@ -0,0 +1,111 @@
+#[cfg(all(test, feature = "test-fixtures"))]
+mod checkout_tests {
+    use did_mmap_cache::archive::{checkout_did, MultiShardArchive};
+    use did_mmap_cache::mst::car::CarStore;
+    use did_mmap_cache::testutil::{cid_for, post_record, FrameBuilder, SigningKey};
+    use std::fs;
+    use tempfile::tempdir;
+
+    const ALICE: &str = "did:plc:ewvi7nxzyoun6zhxrhs64oiz";
+    const BOB: &str = "did:plc:bobbobbobbobbobbobbobbob";
+
+    fn alice(seq: u64) -> FrameBuilder {
+        FrameBuilder::new(ALICE, SigningKey::k256_from_seed(1)).seq(seq)
+    }
+
+    fn ingest(archive: &MultiShardArchive, seq: u64, did: &str, path: &str, frame: Vec<u8>) {
+        archive.ingest(seq, did, path.to_string(), frame);
+    }
+
+    /// alice creates, updates and deletes posts across commits in one shard with bob.
+    fn history(archive: &MultiShardArchive) {
+        let bob = |seq| FrameBuilder::new(BOB, SigningKey::p256_from_seed(2)).seq(seq);
+        ingest(archive, 1, ALICE, "app.bsky.feed.post/a", alice(1).create("app.bsky.feed.post/a", post_record("first")).build());
+        ingest(archive, 2, BOB, "app.bsky.feed.post/a", bob(2).create("app.bsky.feed.post/a", post_record("bob's")).build());
+        ingest(archive, 3, ALICE, "app.bsky.feed.post/b", alice(3).create("app.bsky.feed.post/b", post_record("second")).build());
+        ingest(archive, 4, ALICE, "app.bsky.feed.post/a", alice(4).update("app.bsky.feed.post/a", post_record("first, edited")).build());
+        ingest(archive, 5, ALICE, "app.bsky.feed.post/b", alice(5).delete("app.bsky.feed.post/b").build());
+        ingest(archive, 6, ALICE, "app.bsky.actor.profile/self", alice(6).create("app.bsky.actor.profile/self", post_record("me")).build());
+    }
+
+    #[test]
+    fn test_create_update_delete_replay() {
+        let dir = tempdir().unwrap();
+        // Small segments, so alice's commits span several
+        let archive = MultiShardArchive::new(dir.path(), 1, 2, None).unwrap();
+        history(&archive);
+        archive.shutdown();
+
+        let reader = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        let checkout = checkout_did(&reader, ALICE).unwrap();
+        let paths: Vec<&str> = checkout.records.keys().map(String::as_str).collect();
+        assert_eq!(paths, vec!["app.bsky.actor.profile/self", "app.bsky.feed.post/a"]);
+        let post = &checkout.records["app.bsky.feed.post/a"];
+        assert_eq!((post.seq, post.bytes.as_deref()), (4, Some(&post_record("first, edited")[..])));
+        assert_eq!(post.cid, cid_for(&post_record("first, edited")));
+        assert_eq!(checkout.missing_blocks().count(), 0);
+        assert_eq!(checkout.commits, 5);
+
+        let head = checkout.head.as_ref().unwrap();
+        assert_eq!((head.seq, head.rev.as_deref()), (6, Some("3lbf000000006")));
+        assert_eq!(head.commit_cid, cid_for(head.commit.as_ref().unwrap()));
+
+        let bob = checkout_did(&reader, BOB).unwrap();
+        assert_eq!(bob.records["app.bsky.feed.post/a"].bytes.as_deref(), Some(&post_record("bob's")[..]));
+        assert_eq!(bob.commits, 1);
+        assert!(checkout_did(&reader, "did:plc:nobody").unwrap().head.is_none());
+    }
+
+    #[test]
+    fn test_segments_without_did_index_and_tombstones() {
+        let dir = tempdir().unwrap();
+        let archive = MultiShardArchive::new(dir.path(), 1, 2, None).unwrap();
+        history(&archive);
+        archive.shutdown();
+        for entry in fs::read_dir(dir.path().join("shard_0")).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_some_and(|e| e == "didx") {
+                fs::remove_file(path).unwrap();
+            }
+        }
+
+        let reader = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        let scanned = checkout_did(&reader, ALICE).unwrap();
+        assert_eq!(scanned.records.len(), 2);
+
+        // Without its update, the post is left as first created
+        reader.mark_deleted(4).unwrap();
+        let checkout = checkout_did(&reader, ALICE).unwrap();
+        assert_eq!(checkout.tombstoned, 1);
+        assert_eq!(checkout.records["app.bsky.feed.post/a"].bytes.as_deref(), Some(&post_record("first")[..]));
+    }
+
+    #[test]
+    fn test_missing_blocks_are_listed() {
+        let dir = tempdir().unwrap();
+        let archive = MultiShardArchive::new(dir.path(), 1, 100, None).unwrap();
+        let record = post_record("lost");
+        let mut frame = alice(1).create("app.bsky.feed.post/lost", record.clone()).create("app.bsky.feed.post/kept", post_record("kept")).build();
+        // The record's CAR entry is the last copy of its CID in the frame
+        let cid = cid_for(&record);
+        let at = frame.windows(cid.len()).rposition(|w| w == cid).unwrap();
+        frame[at + cid.len() - 1] ^= 0xff;
+        ingest(&archive, 1, ALICE, "app.bsky.feed.post/lost", frame);
+        archive.shutdown();
+
+        let reader = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        let checkout = checkout_did(&reader, ALICE).unwrap();
+        let missing: Vec<(&str, u64)> = checkout.missing_blocks().map(|(path, r)| (path, r.seq)).collect();
+        assert_eq!(missing, vec![("app.bsky.feed.post/lost", 1)]);
+        assert_eq!(checkout.records["app.bsky.feed.post/lost"].cid, cid);
+        assert!(checkout.records["app.bsky.feed.post/kept"].bytes.is_some());
+
+        let mut car = Vec::new();
+        checkout.write_car(&mut car).unwrap();
+        let store = CarStore::new(&car);
+        let head = checkout.head.as_ref().unwrap();
+        assert_eq!(store.get_block(&head.commit_cid), head.commit.as_deref());
+        assert_eq!(store.get_block(&cid_for(&post_record("kept"))), Some(&post_record("kept")[..]));
+        assert_eq!(store.blocks.len(), 2);
+    }
+}
@@ -0,0 +1,127 @@
+#[cfg(test)]
+mod cid_utils_tests {
+    use did_mmap_cache::parser::cid_utils::{cid_eq, normalize_cid_bytes, parse_cid};
+    use did_mmap_cache::parser::core::parse_input;
+    use did_mmap_cache::testutil::cbor::{bytes, event_header, head, link, text, varint};
+    use sha2::{Digest, Sha256, Sha512};
+
+    // A dag-cbor sha2-256 CIDv1, bare and as a tag-42 link carries it
+    const BARE: [u8; 6] = [0x01, 0x71, 0x12, 0x20, 0xab, 0xcd];
+    const LINKED: [u8; 7] = [0x00, 0x01, 0x71, 0x12, 0x20, 0xab, 0xcd];
+
+    #[test]
+    fn test_prefixed_and_bare_cids_compare_equal() {
+        assert_eq!(normalize_cid_bytes(&LINKED), &BARE[..]);
+        assert_eq!(normalize_cid_bytes(&BARE), &BARE[..]);
+        assert!(cid_eq(&LINKED, &BARE));
+        assert!(cid_eq(&BARE, &LINKED));
+        assert!(cid_eq(&LINKED, &LINKED));
+
+        // Only one prefix byte is dropped, and different CIDs stay different
+        assert!(!cid_eq(&[0x00, 0x00, 0x01], &[0x01]));
+        assert!(!cid_eq(&LINKED, &BARE[..5]));
+        assert!(normalize_cid_bytes(&[]).is_empty());
+    }
+
+    /// `#commit` frame whose `commit` links `root`, with a CAR of `(cid, data)` blocks. The
+    /// CIDs are taken as given, which `testutil::car` can't do.
+    fn commit_frame(root: &[u8], blocks: &[(&[u8], &[u8])]) -> Vec<u8> {
+        let mut car = Vec::new();
+        let mut header = Vec::new();
+        head(5, 2, &mut header);
+        text("roots", &mut header);
+        head(4, 1, &mut header);
+        link(root, &mut header);
+        text("version", &mut header);
+        header.push(0x01);
+        varint(header.len(), &mut car);
+        car.extend_from_slice(&header);
+        for (cid, data) in blocks {
+            varint(cid.len() + data.len(), &mut car);
+            car.extend_from_slice(cid);
+            car.extend_from_slice(data);
+        }
+
+        let mut frame = Vec::new();
+        event_header("#commit", &mut frame);
+        head(5, 4, &mut frame);
+        text("repo", &mut frame);
+        text("did:plc:alice", &mut frame);
+        text("seq", &mut frame);
+        frame.push(0x07);
+        text("commit", &mut frame);
+        link(root, &mut frame);
+        text("blocks", &mut frame);
+        bytes(&car, &mut frame);
+        frame
+    }
+
+    fn cid_v1(codec: u8, hash: u8, digest: &[u8]) -> Vec<u8> {
+        let mut cid = vec![0x01, codec, hash, digest.len() as u8];
+        cid.extend_from_slice(digest);
+        cid
+    }
+
+    #[test]
+    fn test_codec_and_hash_decide_equality() {
+        let digest = Sha256::digest(b"block");
+        let dag_cbor = cid_v1(0x71, 0x12, &digest);
+        let raw = cid_v1(0x55, 0x12, &digest);
+        let dag_pb = cid_v1(0x70, 0x12, &digest);
+        let mut v0 = vec![0x12, 0x20];
+        v0.extend_from_slice(&digest);
+
+        assert_eq!(parse_cid(&dag_cbor).unwrap().codec(), 0x71);
+        // Same digest, other codec: another block as far as a link is concerned
+        assert!(!cid_eq(&dag_cbor, &raw));
+        // A CIDv0 is the dag-pb CIDv1 of the same hash
+        assert!(cid_eq(&v0, &dag_pb));
+        assert!(!cid_eq(&v0, &dag_cbor));
+
+        let sha512 = cid_v1(0x71, 0x13, &Sha512::digest(b"block"));
+        assert_eq!(parse_cid(&sha512).unwrap().hash().size(), 64);
+        assert!(cid_eq(&sha512, &[&[0x00][..], &sha512[..]].concat()));
+        assert!(!cid_eq(&sha512, &dag_cbor));
+    }
+
+    #[test]
+    fn test_commit_found_among_raw_codec_blocks() {
+        let commit = b"\xa1\x63did\x6ddid:plc:alice";
+        let commit_cid = cid_v1(0x71, 0x13, &Sha512::digest(commit));
+        // The same bytes stored as a raw block share the digest but not the codec
+        let raw_cid = cid_v1(0x55, 0x13, &Sha512::digest(commit));
+        let blob = b"not a commit";
+        let blob_cid = cid_v1(0x55, 0x12, &Sha256::digest(blob));
+
+        let frame = commit_frame(&commit_cid, &[(&raw_cid, b"decoy"), (&blob_cid, blob), (&commit_cid, commit)]);
+        let envelope = parse_input(&frame).unwrap();
+        assert_eq!(envelope.commit, Some(&commit[..]));
+
+        // Linking the raw block finds it, even though it comes first
+        let frame = commit_frame(&raw_cid, &[(&raw_cid, b"decoy"), (&commit_cid, commit)]);
+        assert_eq!(parse_input(&frame).unwrap().commit, Some(&b"decoy"[..]));
+
+        // Without a block under the commit's CID there is nothing to fall back on
+        let frame = commit_frame(&commit_cid, &[(&raw_cid, b"decoy")]);
+        assert!(parse_input(&frame).is_err());
+    }
+
+    #[test]
+    fn test_commit_block_found_by_either_form() {
+        use did_mmap_cache::mst::car::CarStore;
+        use did_mmap_cache::testutil::{post_record, FrameBuilder, SigningKey};
+
+        let frame = FrameBuilder::new("did:plc:alice", SigningKey::k256_from_seed(1))
+            .seq(1)
+            .create("app.bsky.feed.post/3lbf000000001", post_record("hi"))
+            .build();
+        let envelope = parse_input(&frame).unwrap();
+        let linked = envelope.cid.unwrap();
+        assert_eq!(linked[0], 0x00);
+
+        let store = CarStore::new(envelope.blocks.unwrap());
+        let block = store.get_block(linked).unwrap();
+        assert_eq!(store.get_block(normalize_cid_bytes(linked)), Some(block));
+        assert_eq!(envelope.commit, Some(block));
+    }
+}
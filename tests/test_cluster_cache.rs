@@ -0,0 +1,83 @@
+#[cfg(test)]
+mod cluster_cache_tests {
+    use did_mmap_cache::archive::cluster_cache::ClusterCache;
+    use did_mmap_cache::archive::MultiShardArchive;
+    use std::sync::Arc;
+    use std::thread;
+    use tempfile::tempdir;
+
+    const CLUSTER: usize = 1000;
+
+    fn cluster() -> Arc<Vec<u8>> {
+        Arc::new(vec![7u8; CLUSTER])
+    }
+
+    #[test]
+    fn test_budget_enforced() {
+        // 16 shares of 4KB: four clusters each
+        let budget = 16 * 4096;
+        let cache = ClusterCache::new(budget);
+        let hot = (1, usize::MAX);
+        cache.insert(hot.0, hot.1, cluster());
+        for i in 0..200 {
+            cache.insert(2, i * CLUSTER, cluster());
+            // Used after every insert, so it never becomes the oldest in its share
+            assert!(cache.get(hot.0, hot.1).is_some());
+        }
+
+        let stats = cache.stats();
+        assert!(stats.bytes <= budget as u64, "{} bytes cached", stats.bytes);
+        assert_eq!(stats.bytes, stats.entries * CLUSTER as u64);
+        assert_eq!(stats.entries + stats.evictions, 201);
+        assert!(stats.evictions >= 201 - 64);
+        assert_eq!(cache.segment_entries(1), 1);
+        assert_eq!(stats.hits, 200);
+
+        // Bigger than a share: never cached
+        cache.insert(3, 0, Arc::new(vec![0u8; 5000]));
+        assert!(cache.get(3, 0).is_none());
+        assert_eq!(cache.stats().misses, 1);
+
+        cache.remove_segment(1);
+        assert!(cache.get(hot.0, hot.1).is_none());
+        cache.set_budget(0);
+        assert_eq!((cache.stats().entries, cache.stats().bytes), (0, 0));
+    }
+
+    #[test]
+    fn test_shards_share_one_cache() {
+        let dir = tempdir().unwrap();
+        let archive = MultiShardArchive::new(dir.path(), 4, 10, None).unwrap();
+        for seq in 0..200u64 {
+            archive.ingest(seq, &format!("did:plc:user{}", seq % 8), format!("app.bsky.feed.post/{}", seq), format!("message {}", seq).into_bytes());
+        }
+        archive.shutdown();
+
+        let reader = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        let read = |ranges: [std::ops::Range<u64>; 4]| {
+            thread::scope(|s| {
+                for range in ranges {
+                    let reader = &reader;
+                    s.spawn(move || {
+                        for seq in range {
+                            assert_eq!(reader.get_message_by_seq(seq).unwrap(), format!("message {}", seq).into_bytes());
+                        }
+                    });
+                }
+            });
+        };
+
+        read([0..50, 50..100, 100..150, 150..200]);
+        let first = reader.cluster_cache().stats();
+        assert_eq!(first.hits + first.misses, 200);
+        // Two threads can miss on a cluster straddling their ranges at once
+        assert!(first.entries > 0 && first.entries <= first.misses);
+        assert_eq!(first.evictions, 0);
+
+        // Each thread now reads what another one cached
+        read([150..200, 100..150, 50..100, 0..50]);
+        let second = reader.cluster_cache().stats();
+        assert_eq!(second.misses, first.misses);
+        assert_eq!(second.hits, first.hits + 200);
+    }
+}
@@ -0,0 +1,72 @@
+#[cfg(test)]
+mod cluster_format_tests {
+    use did_mmap_cache::archive::cluster::{CLUSTER_ENTRY_HEADER_SIZE, MAX_CLUSTER_RECORDS};
+    use did_mmap_cache::archive::{split_cluster, ClusterReader, ClusterWriter, MultiShardArchive};
+    use std::io;
+    use tempfile::tempdir;
+
+    fn records() -> Vec<(u64, Vec<u8>)> {
+        [3u64, 5, 6, 9].iter().map(|&seq| (seq, format!("record {}", seq).repeat(seq as usize).into_bytes())).collect()
+    }
+
+    fn write(records: &[(u64, Vec<u8>)]) -> Vec<u8> {
+        let mut writer = ClusterWriter::new();
+        for (seq, data) in records {
+            writer.push(*seq, data).unwrap();
+        }
+        writer.finish()
+    }
+
+    #[test]
+    fn test_writer_output_reads_back() {
+        let records = records();
+        let raw = write(&records);
+        let reader = ClusterReader::new(&raw).unwrap();
+        assert_eq!(reader.len(), 4);
+        assert_eq!(reader.data_offset(), 2 + 4 * CLUSTER_ENTRY_HEADER_SIZE);
+
+        let entries: Vec<_> = reader.iter().collect();
+        for (entry, (seq, data)) in entries.iter().zip(&records) {
+            assert_eq!((entry.seq, entry.data), (*seq, &data[..]));
+            assert_eq!(&raw[entry.offset..entry.offset + data.len()], &data[..]);
+        }
+        assert_eq!(entries[0].offset, reader.data_offset());
+        assert_eq!(split_cluster(&raw).unwrap(), records.iter().map(|(s, d)| (*s, &d[..])).collect::<Vec<_>>());
+
+        let empty = ClusterWriter::new().finish();
+        assert_eq!(empty, [0, 0]);
+        assert!(ClusterReader::new(&empty).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_reader_rejects_short_clusters() {
+        let raw = write(&records());
+        for cut in [0, 1, 2 + CLUSTER_ENTRY_HEADER_SIZE, raw.len() - 1] {
+            assert_eq!(ClusterReader::new(&raw[..cut]).unwrap_err().kind(), io::ErrorKind::InvalidData, "cut at {}", cut);
+        }
+        let mut full = ClusterWriter::new();
+        for seq in 0..MAX_CLUSTER_RECORDS as u64 {
+            full.push(seq, b"").unwrap();
+        }
+        assert_eq!(full.push(0, b"one too many").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn test_stored_and_tombstone_filtered_clusters_match_the_writer() {
+        let dir = tempdir().unwrap();
+        let archive = MultiShardArchive::new(dir.path(), 1, 100, None).unwrap();
+        for (seq, data) in records() {
+            archive.ingest(seq, "did:plc:alice", format!("app.bsky.feed.post/{}", seq), data);
+        }
+        archive.shutdown();
+        let reader = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+
+        let stored = zstd::decode_all(&reader.get_raw_cluster_at_seq(3).unwrap()[..]).unwrap();
+        assert_eq!(stored, write(&records()));
+
+        reader.mark_deleted(6).unwrap();
+        let filtered = zstd::decode_all(&reader.get_raw_cluster_at_seq(3).unwrap()[..]).unwrap();
+        let kept: Vec<_> = records().into_iter().filter(|(seq, _)| *seq != 6).collect();
+        assert_eq!(filtered, write(&kept));
+    }
+}
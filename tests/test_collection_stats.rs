@@ -0,0 +1,98 @@
+#[cfg(test)]
+mod collection_stats_tests {
+    use did_mmap_cache::archive::stats::{collection_of, NO_COLLECTION};
+    use did_mmap_cache::archive::{CollStat, MultiShardArchive};
+    use std::collections::HashMap;
+    use tempfile::tempdir;
+
+    /// Posts, likes, a follow, a profile and an identity event, with sizes told apart by seq.
+    fn fixture() -> Vec<(u64, &'static str, &'static str, Vec<u8>)> {
+        let paths = [
+            ("did:plc:alice", "app.bsky.feed.post/a1"),
+            ("did:plc:bob", "app.bsky.feed.like/b1"),
+            ("did:plc:alice", "app.bsky.feed.post/a2"),
+            ("did:plc:carol", "app.bsky.graph.follow/c1"),
+            ("did:plc:bob", "app.bsky.feed.like/b2"),
+            ("did:plc:carol", "app.bsky.actor.profile/self"),
+            ("did:plc:alice", ""),
+            ("did:plc:bob", "app.bsky.feed.post/b3"),
+        ];
+        paths.iter().enumerate().map(|(seq, (did, path))| (seq as u64, *did, *path, vec![b'x'; 100 + seq])).collect()
+    }
+
+    fn stat(messages: u64, bytes: u64) -> CollStat {
+        CollStat { messages, bytes }
+    }
+
+    #[test]
+    fn test_collection_of() {
+        assert_eq!(collection_of("app.bsky.feed.post/3k2a"), "app.bsky.feed.post");
+        assert_eq!(collection_of("app.bsky.actor.profile"), "app.bsky.actor.profile");
+        assert_eq!(collection_of(""), NO_COLLECTION);
+        assert_eq!(collection_of("/x"), NO_COLLECTION);
+    }
+
+    #[test]
+    fn test_sidecars_count_each_collection() {
+        let dir = tempdir().unwrap();
+        let archive = MultiShardArchive::new(dir.path(), 2, 4, None).unwrap();
+        archive.set_collection_stats(true);
+        for (seq, did, path, data) in fixture() {
+            archive.ingest(seq, did, path.to_string(), data);
+        }
+        archive.shutdown();
+
+        let archive = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        let expected = HashMap::from([
+            ("app.bsky.feed.post".to_string(), stat(3, 100 + 102 + 107)),
+            ("app.bsky.feed.like".to_string(), stat(2, 101 + 104)),
+            ("app.bsky.graph.follow".to_string(), stat(1, 103)),
+            ("app.bsky.actor.profile".to_string(), stat(1, 105)),
+            (NO_COLLECTION.to_string(), stat(1, 106)),
+        ]);
+        assert_eq!(archive.collection_stats(0..u64::MAX), expected);
+        assert!(archive.collection_stats(5..5).is_empty());
+
+        // The sidecars are JSON next to each segment, and add up to the same
+        let mut summed: HashMap<String, CollStat> = HashMap::new();
+        for shard in ["shard_0", "shard_1"] {
+            for path in std::fs::read_dir(dir.path().join(shard)).unwrap().map(|e| e.unwrap().path()) {
+                if path.extension().is_some_and(|e| e == "stats") {
+                    let sidecar: HashMap<String, CollStat> = serde_json::from_slice(&std::fs::read(path).unwrap()).unwrap();
+                    for (collection, stat) in sidecar {
+                        summed.entry(collection).or_default().add(stat);
+                    }
+                }
+            }
+        }
+        assert_eq!(summed, expected);
+    }
+
+    #[test]
+    fn test_segments_without_stats_are_skipped() {
+        let dir = tempdir().unwrap();
+        let archive = MultiShardArchive::new(dir.path(), 1, 4, None).unwrap();
+        let fixture = fixture();
+        // Seqs 0..4 fill the first segment with stats on; the rest are written without
+        archive.set_collection_stats(true);
+        for (seq, did, path, data) in &fixture[..4] {
+            archive.ingest(*seq, did, path.to_string(), data.clone());
+        }
+        archive.set_collection_stats(false);
+        for (seq, did, path, data) in &fixture[4..] {
+            archive.ingest(*seq, did, path.to_string(), data.clone());
+        }
+        archive.shutdown();
+
+        assert!(dir.path().join("shard_0/s0_0.stats").exists());
+        assert!(!dir.path().join("shard_0/s0_4.stats").exists());
+        let archive = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        let expected = HashMap::from([
+            ("app.bsky.feed.post".to_string(), stat(2, 100 + 102)),
+            ("app.bsky.feed.like".to_string(), stat(1, 101)),
+            ("app.bsky.graph.follow".to_string(), stat(1, 103)),
+        ]);
+        assert_eq!(archive.collection_stats(0..u64::MAX), expected);
+        assert!(archive.collection_stats(4..u64::MAX).is_empty());
+    }
+}
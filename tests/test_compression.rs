@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod compression {
-    use did_mmap_cache::archive::ArchiveWriter;
+    use did_mmap_cache::archive::{decode_cluster, ArchiveWriter, CompressionConfig, SegmentedArchive};
     use tempfile::tempdir;
     use std::fs;
 
@@ -30,4 +30,122 @@ mod compression {
         // Claim: 68.22% reduction. We should see at least 50% for clustered similar messages.
         assert!(reduction > 50.0, "Compression reduction should be significant (found {:.2}%)", reduction);
     }
+
+    /// Posts that repeat each other loosely, so higher levels have something to find.
+    fn post(i: u64) -> Vec<u8> {
+        let noise = blake3::hash(&(i / 7).to_le_bytes()).to_hex();
+        format!(r#"{{"text": "post {} about {}", "langs": ["en"], "createdAt": "2024-01-01T00:00:{:02}.000Z"}}"#, i, &noise[..24], i % 60).into_bytes()
+    }
+
+    #[test]
+    fn test_mixed_levels_and_recompression() {
+        let dir = tempdir().unwrap();
+        let fast = CompressionConfig { level: 1, long_distance: false, window_log: None };
+        let dense = CompressionConfig { level: 19, long_distance: true, window_log: Some(20) };
+        let writer = |start: u64, config: CompressionConfig| ArchiveWriter::new_with_compression(dir.path(), 0, start, 1_000, None, config);
+        assert!(writer(0, CompressionConfig { window_log: Some(31), ..dense }).is_err());
+        assert!(writer(0, CompressionConfig { level: 100, ..fast }).is_err());
+
+        // Three segments: level 1, level 19 with long-distance matching, then the default
+        for (start, config) in [(0u64, fast), (300, dense), (600, CompressionConfig::default())] {
+            // Room for all 300, so finalize_segment is what cuts the segment
+            let mut writer = writer(start, config).unwrap();
+            for seq in start..start + 300 {
+                writer.append_message(seq, &format!("did:plc:author{}", seq % 3), "app.bsky.feed.post/x", &post(seq)).unwrap();
+            }
+            writer.finalize_segment().unwrap();
+        }
+
+        let archive = SegmentedArchive::open_directory(dir.path(), None, None).unwrap();
+        for seq in 0..900 {
+            assert_eq!(archive.get_message_by_seq(seq, None).unwrap(), post(seq), "seq {}", seq);
+        }
+        assert_eq!(archive.compression_at_seq(10), Some(fast));
+        assert_eq!(archive.compression_at_seq(310), Some(dense));
+        assert_eq!(archive.compression_at_seq(610), Some(CompressionConfig::default()));
+        // The settings live in the .idx header, after the algorithm id
+        let header = fs::read(dir.path().join("s0_300.idx")).unwrap();
+        assert_eq!(header[40..48], [0, 1, 19, 0, 0, 0, 1, 20]);
+        assert!(!dir.path().join("s0_300.zcfg").exists());
+
+        // Moving the level-1 segment to cold storage shrinks it and keeps its root
+        let before = fs::metadata(dir.path().join("s0_0.bin")).unwrap().len();
+        let after = archive.recompress_segment(0, dense).unwrap();
+        assert!(after < before, "recompressed to {} bytes from {}", after, before);
+        assert_eq!(fs::metadata(dir.path().join("s0_0.bin")).unwrap().len(), after);
+        assert!(archive.verify_integrity_at_seq(0, None).unwrap());
+        assert_eq!(archive.compression_at_seq(10), Some(dense));
+        for seq in 0..300 {
+            assert_eq!(archive.get_message_by_seq(seq, None).unwrap(), post(seq));
+        }
+        assert!(archive.recompress_segment(42, dense).is_err());
+
+        // A cluster rebuilt around a tombstone still opens with a plain decoder
+        archive.mark_deleted(3).unwrap();
+        let rebuilt = archive.get_raw_cluster_at_seq(0).unwrap();
+        let seqs: Vec<u64> = decode_cluster(&rebuilt, None, 1 << 24).unwrap().into_iter().map(|(seq, _)| seq).collect();
+        assert!(seqs.contains(&0) && !seqs.contains(&3));
+
+        // And a fresh reader sees the same thing
+        let reopened = SegmentedArchive::open_directory(dir.path(), None, None).unwrap();
+        assert_eq!(reopened.compression_at_seq(10), Some(dense));
+        assert_eq!(reopened.get_message_by_seq(299, None).unwrap(), post(299));
+
+        // A header from before the settings moved into it falls back to a .zcfg sidecar
+        let mut legacy = fs::read(dir.path().join("s0_300.idx")).unwrap();
+        legacy[41..48].fill(0);
+        fs::write(dir.path().join("legacy.idx"), legacy).unwrap();
+        fs::rename(dir.path().join("legacy.idx"), dir.path().join("s0_300.idx")).unwrap();
+        fs::write(dir.path().join("s0_300.zcfg"), [19, 0, 0, 0, 1, 20]).unwrap();
+        let legacy = SegmentedArchive::open_directory(dir.path(), None, None).unwrap();
+        assert_eq!(legacy.compression_at_seq(310), Some(dense));
+        assert_eq!(legacy.get_message_by_seq(310, None).unwrap(), post(310));
+    }
+
+    #[test]
+    fn test_interrupted_recompression_is_finished_or_ignored() {
+        let fast = CompressionConfig { level: 1, long_distance: false, window_log: None };
+        let dense = CompressionConfig { level: 19, long_distance: true, window_log: Some(20) };
+        let write = |dir: &std::path::Path| {
+            let mut writer = ArchiveWriter::new_with_compression(dir, 0, 0, 1_000, None, fast).unwrap();
+            for seq in 0..300 {
+                writer.append_message(seq, &format!("did:plc:author{}", seq % 3), "app.bsky.feed.post/x", &post(seq)).unwrap();
+            }
+            writer.finalize_segment().unwrap();
+        };
+        // The pair a finished recompression leaves, to plant as a half-done one
+        let done = tempdir().unwrap();
+        write(done.path());
+        SegmentedArchive::open_directory(done.path(), None, None).unwrap().recompress_segment(0, dense).unwrap();
+        let (new_bin, new_idx) = (fs::read(done.path().join("s0_0.bin")).unwrap(), fs::read(done.path().join("s0_0.idx")).unwrap());
+
+        // (files to plant, settings the reopened segment should report)
+        let crashes: [(&[(&str, &Vec<u8>)], CompressionConfig); 3] = [
+            // Written but never committed: the old pair stands
+            (&[("s0_0.bin.new", &new_bin), ("s0_0.idx.new", &new_idx)], fast),
+            // Committed, nothing renamed yet
+            (&[("s0_0.bin.new", &new_bin), ("s0_0.idx.swap", &new_idx)], dense),
+            // Committed, the new .bin in place but the .idx not
+            (&[("s0_0.bin", &new_bin), ("s0_0.idx.swap", &new_idx)], dense),
+        ];
+        for (planted, expected) in crashes {
+            let dir = tempdir().unwrap();
+            write(dir.path());
+            if planted[0].0 == "s0_0.bin" {
+                fs::rename(dir.path().join("s0_0.bin"), dir.path().join("s0_0.bin.old")).unwrap();
+            }
+            for (name, bytes) in planted {
+                fs::write(dir.path().join(name), bytes).unwrap();
+            }
+
+            let archive = SegmentedArchive::open_directory(dir.path(), None, None).unwrap();
+            assert_eq!(archive.compression_at_seq(10), Some(expected), "{:?}", planted.iter().map(|p| p.0).collect::<Vec<_>>());
+            for seq in 0..300 {
+                assert_eq!(archive.get_message_by_seq(seq, None).unwrap(), post(seq));
+            }
+            assert!(archive.verify_integrity_at_seq(0, None).unwrap());
+            assert!(!dir.path().join("s0_0.idx.swap").exists());
+            assert!(!dir.path().join("s0_0.bin.old").exists());
+        }
+    }
 }
@@ -0,0 +1,127 @@
+#[cfg(test)]
+mod consistency_tests {
+    use did_mmap_cache::archive::consistency::{ConsistencyIssue, ConsistencyReport};
+    use did_mmap_cache::archive::{MultiShardArchive, TombstoneStore};
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    /// One shard, seqs 0..20 in a single segment.
+    fn write_archive(root: &Path) {
+        let archive = MultiShardArchive::new(root, 1, 50, None).unwrap();
+        for seq in 0..20u64 {
+            archive.ingest(seq, &format!("did:plc:user{}", seq % 3), format!("app.bsky.feed.post/{}", seq), vec![seq as u8; 40]);
+        }
+        archive.shutdown();
+    }
+
+    fn segment_file(root: &Path, ext: &str) -> std::path::PathBuf {
+        root.join("shard_0").join(format!("s0_0.{}", ext))
+    }
+
+    #[test]
+    fn test_clean_archive_is_consistent() {
+        let dir = tempdir().unwrap();
+        write_archive(dir.path());
+
+        let archive = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        archive.mark_deleted(5).unwrap();
+        let report = archive.consistency_check();
+        assert!(report.is_consistent(), "{}", report);
+        assert_eq!((report.shards, report.segments, report.min_seq, report.max_seq), (1, 1, Some(0), Some(19)));
+        assert!(report.to_string().ends_with("consistent"));
+        assert_eq!(archive.consistency_report(), &report);
+
+        // A fresh archive has nothing to disagree about either
+        let empty = MultiShardArchive::new(dir.path().join("empty"), 2, 50, None).unwrap();
+        assert!(empty.consistency_report().is_consistent());
+        assert!(MultiShardArchive::open_readonly_strict(dir.path(), None).is_ok());
+    }
+
+    #[test]
+    fn test_overlapping_segments_are_detected() {
+        let dir = tempdir().unwrap();
+        write_archive(dir.path());
+        // The same 20 records again, claiming seqs 10..30
+        for ext in ["bin", "idx"] {
+            fs::copy(segment_file(dir.path(), ext), dir.path().join("shard_0").join(format!("s0_10.{}", ext))).unwrap();
+        }
+
+        let archive = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        let report = archive.consistency_report();
+        assert!(report.has_overlaps());
+        assert_eq!(report.issues, vec![ConsistencyIssue::OverlappingSegments { shard: 0, first_start: 0, second_start: 10, overlap: 10 }]);
+
+        let err = MultiShardArchive::new_strict(dir.path(), 1, 50, None).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("share 10 seqs"));
+        assert!(MultiShardArchive::open_readonly_strict(dir.path(), None).is_err());
+    }
+
+    #[test]
+    fn test_index_and_data_mismatch_is_detected() {
+        let dir = tempdir().unwrap();
+        write_archive(dir.path());
+        let bin = segment_file(dir.path(), "bin");
+        let len = fs::metadata(&bin).unwrap().len();
+        OpenOptions::new().write(true).open(&bin).unwrap().set_len(len / 2).unwrap();
+        OpenOptions::new().append(true).open(segment_file(dir.path(), "idx")).unwrap().write_all(&[0; 5]).unwrap();
+
+        let report = MultiShardArchive::open_readonly(dir.path(), None).unwrap().consistency_check();
+        assert!(!report.has_overlaps());
+        let reasons: Vec<String> = report.issues.iter().map(|issue| match issue {
+            ConsistencyIssue::IndexMismatch { shard: 0, start_seq: 0, reason } => reason.clone(),
+            other => panic!("unexpected issue {}", other),
+        }).collect();
+        assert_eq!(reasons.len(), 2);
+        assert!(reasons[0].contains("partial record"));
+        assert!(reasons[1].contains("runs past"));
+    }
+
+    #[test]
+    fn test_tombstones_beyond_max_seq_are_detected() {
+        let dir = tempdir().unwrap();
+        write_archive(dir.path());
+        {
+            let mut ts = TombstoneStore::open_or_create(dir.path().join("tombstones.bin")).unwrap();
+            assert_eq!(ts.highest_deleted(), None);
+            ts.mark_deleted(3).unwrap();
+            ts.mark_deleted(100_000).unwrap();
+            assert_eq!(ts.highest_deleted(), Some(100_000));
+        }
+
+        let report = MultiShardArchive::open_readonly(dir.path(), None).unwrap().consistency_check();
+        assert_eq!(report.issues, vec![ConsistencyIssue::TombstonesBeyondMax { max_seq: Some(19), highest_deleted: 100_000 }]);
+
+        // Tombstones restored without any segments
+        let bare = dir.path().join("bare");
+        fs::create_dir(&bare).unwrap();
+        fs::copy(dir.path().join("tombstones.bin"), bare.join("tombstones.bin")).unwrap();
+        let report = MultiShardArchive::open_readonly(&bare, None).unwrap().consistency_check();
+        assert_eq!(report.issues, vec![ConsistencyIssue::TombstonesBeyondMax { max_seq: None, highest_deleted: 100_000 }]);
+    }
+
+    #[test]
+    fn test_cursors_far_ahead_are_detected() {
+        let dir = tempdir().unwrap();
+        write_archive(dir.path());
+        let mut report = MultiShardArchive::open_readonly(dir.path(), None).unwrap().consistency_check();
+        report.check_cursors([("near", 25), ("far", 5_000)], 100);
+        assert_eq!(report.issues, vec![ConsistencyIssue::CursorAhead { name: "far".into(), cursor: 5_000, max_seq: Some(19) }]);
+
+        // Serializable for tooling, printable for people
+        let json = serde_json::to_string(&report).unwrap();
+        assert!(json.contains("\"kind\":\"cursor_ahead\""));
+        assert_eq!(serde_json::from_str::<ConsistencyReport>(&json).unwrap(), report);
+        assert!(report.to_string().contains("cursor far = 5000"));
+
+        // An unbounded slack only flags cursors kept over an empty archive
+        let mut empty = ConsistencyReport::default();
+        empty.check_cursors([("pds.example", 42), ("fresh.example", 0)], u64::MAX);
+        assert_eq!(empty.issues.len(), 1);
+        let mut full = MultiShardArchive::open_readonly(dir.path(), None).unwrap().consistency_check();
+        full.check_cursors([("pds.example", u64::MAX)], u64::MAX);
+        assert!(full.is_consistent());
+    }
+}
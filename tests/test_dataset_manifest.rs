@@ -0,0 +1,85 @@
+#[cfg(test)]
+mod dataset_manifest_tests {
+    use did_mmap_cache::archive::dataset::{verify_manifest, DatasetManifest, ManifestDrift};
+    use did_mmap_cache::archive::MultiShardArchive;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    /// Two shards, 40 messages per segment.
+    fn write_archive(root: &Path) {
+        let archive = MultiShardArchive::new(root, 2, 40, None).unwrap();
+        for seq in 0..200u64 {
+            let did = format!("did:plc:user{}", seq % 7);
+            archive.ingest(seq, &did, format!("app.bsky.feed.post/{}", seq), format!("message {} {}", seq, "y".repeat(48)).into_bytes());
+        }
+        archive.shutdown();
+    }
+
+    #[test]
+    fn test_export_is_deterministic_and_verifies_clean() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("archive");
+        write_archive(&root);
+
+        let archive = MultiShardArchive::open_readonly(&root, None).unwrap();
+        let manifest = archive.export_manifest(&dir.path().join("a.json")).unwrap();
+        archive.export_manifest(&dir.path().join("b.json")).unwrap();
+        assert_eq!(fs::read(dir.path().join("a.json")).unwrap(), fs::read(dir.path().join("b.json")).unwrap());
+
+        assert_eq!(manifest.num_shards, 2);
+        assert!(manifest.segments.len() >= 2);
+        assert_eq!(manifest.min_seq, Some(0));
+        assert_eq!(manifest.max_seq, Some(199));
+        assert_eq!(manifest.tombstone_count, 0);
+        assert_eq!(DatasetManifest::load(&dir.path().join("a.json")).unwrap(), manifest);
+
+        let report = verify_manifest(&root, &dir.path().join("a.json")).unwrap();
+        assert!(report.is_clean(), "{}", report);
+        assert_eq!(report.segments_checked, manifest.segments.len());
+    }
+
+    #[test]
+    fn test_flipped_byte_is_pinned_to_its_segment() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("archive");
+        write_archive(&root);
+        let manifest_path = dir.path().join("manifest.json");
+        let manifest = MultiShardArchive::open_readonly(&root, None).unwrap().export_manifest(&manifest_path).unwrap();
+
+        let target = &manifest.segments[1];
+        let bin = root.join(format!("shard_{}", target.shard)).join(format!("s{}_{}.bin", target.shard, target.start_seq));
+        let mut bytes = fs::read(&bin).unwrap();
+        let mid = bytes.len() / 2;
+        bytes[mid] ^= 0x01;
+        fs::write(&bin, bytes).unwrap();
+
+        let report = verify_manifest(&root, &manifest_path).unwrap();
+        assert_eq!(
+            report.drift,
+            vec![ManifestDrift::ChangedSegment { shard: target.shard, start_seq: target.start_seq, fields: vec!["bin"] }]
+        );
+    }
+
+    #[test]
+    fn test_tombstones_and_removed_segments_are_drift() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("archive");
+        write_archive(&root);
+        let manifest_path = dir.path().join("manifest.json");
+        let archive = MultiShardArchive::open_readonly(&root, None).unwrap();
+        let manifest = archive.export_manifest(&manifest_path).unwrap();
+
+        archive.mark_deleted(17).unwrap();
+        archive.mark_deleted(90).unwrap();
+        let report = verify_manifest(&root, &manifest_path).unwrap();
+        assert_eq!(report.drift, vec![ManifestDrift::Tombstones { expected_count: 0, found_count: 2 }]);
+
+        let last = manifest.segments.last().unwrap();
+        let bin = root.join(format!("shard_{}", last.shard)).join(format!("s{}_{}.bin", last.shard, last.start_seq));
+        fs::remove_file(&bin).unwrap();
+        let report = verify_manifest(&root, &manifest_path).unwrap();
+        assert!(report.drift.contains(&ManifestDrift::MissingSegment { shard: last.shard, start_seq: last.start_seq }));
+        assert_eq!(report.drift.len(), 2);
+    }
+}
@@ -0,0 +1,107 @@
+#[cfg(test)]
+mod drop_evidence_tests {
+    use did_mmap_cache::monitor::{read_evidence, verify_evidence_with, DropEvidenceStore};
+    use did_mmap_cache::verify::VerifyOutcome;
+    use did_mmap_cache::parser::cid_utils::normalize_cid_bytes;
+    use did_mmap_cache::parser::core::parse_input;
+    use did_mmap_cache::testutil::{post_record, FrameBuilder, SigningKey};
+    use std::fs;
+    use std::time::{Duration, SystemTime};
+    use tempfile::tempdir;
+
+    fn cid(n: u8) -> Vec<u8> {
+        let mut c = vec![0x01, 0x71, 0x12, 0x20];
+        c.extend_from_slice(&[n; 32]);
+        c
+    }
+
+    /// A `#commit` frame for `did` signed by `key`, with its commit CID.
+    fn signed_frame(key: SigningKey, did: &str, n: u8) -> (Vec<u8>, Vec<u8>) {
+        let frame = FrameBuilder::new(did, key).seq(n as u64).create(format!("app.bsky.feed.post/{}", n), post_record("dropped")).build();
+        let cid = normalize_cid_bytes(parse_input(&frame).unwrap().cid.unwrap()).to_vec();
+        (cid, frame)
+    }
+
+    #[test]
+    fn test_recorded_drops_round_trip_and_verify() {
+        let dir = tempdir().unwrap();
+        let alice = || SigningKey::k256_from_seed(0x11);
+        let first_seen = SystemTime::now() - Duration::from_secs(5);
+        let window = Duration::from_secs(3);
+
+        let mut store = DropEvidenceStore::open(dir.path(), 1024 * 1024).unwrap();
+        let frames = [
+            signed_frame(alice(), "did:plc:alice", 1),
+            // Signed by a key the account doesn't have
+            signed_frame(SigningKey::k256_from_seed(0x22), "did:plc:alice", 2),
+            // Recorded under a CID that isn't the frame's
+            (cid(9), signed_frame(alice(), "did:plc:alice", 3).1),
+            // Nobody we can find a key for
+            signed_frame(alice(), "did:plc:stranger", 4),
+            (cid(5), b"not cbor".to_vec()),
+        ];
+        for (c, frame) in &frames {
+            assert!(store.record(c, "pds.example", frame, first_seen, window).unwrap());
+        }
+
+        let stored = read_evidence(dir.path()).unwrap();
+        assert_eq!(stored.len(), frames.len());
+        assert_eq!(stored[0].frame, frames[0].1);
+        assert_eq!(stored[0].source_host, "pds.example");
+        assert_eq!(stored[0].relay_window, window);
+        let skew = stored[0].first_seen.duration_since(first_seen).unwrap_or_else(|e| e.duration());
+        assert!(skew < Duration::from_millis(1));
+
+        let alice_key = alice().public_key();
+        let summaries = verify_evidence_with(dir.path(), |did| (did == "did:plc:alice").then_some((alice_key, 1))).unwrap();
+        let attributable: Vec<bool> = summaries.iter().map(|s| s.is_attributable()).collect();
+        assert_eq!(attributable, [true, false, false, false, false]);
+
+        assert_eq!(summaries[0].did.as_deref(), Some("did:plc:alice"));
+        assert_eq!(summaries[0].seq, Some(1));
+        assert_eq!(summaries[1].outcome, Some(VerifyOutcome::Mismatch));
+        assert!(!summaries[2].cid_matches && summaries[2].outcome == Some(VerifyOutcome::Ok));
+        assert!(summaries[3].outcome.is_none() && summaries[3].error.is_none());
+        assert!(summaries[4].error.is_some() && summaries[4].did.is_none());
+    }
+
+    #[test]
+    fn test_cap_prunes_oldest_day_then_refuses() {
+        let dir = tempdir().unwrap();
+        let (commit, frame) = signed_frame(SigningKey::k256_from_seed(0x11), "did:plc:alice", 1);
+
+        // An older day's file takes most of the budget
+        fs::write(dir.path().join("drops-2020-01-01.bin"), vec![0u8; 900]).unwrap();
+        let mut store = DropEvidenceStore::open(dir.path(), 1000).unwrap();
+        assert_eq!(store.total_bytes(), 900);
+
+        assert!(store.record(&commit, "pds.example", &frame, SystemTime::now(), Duration::from_secs(3)).unwrap());
+        assert!(!dir.path().join("drops-2020-01-01.bin").exists());
+        assert_eq!(read_evidence(dir.path()).unwrap().len(), 1);
+
+        // Only today's file is left, so once it's full new drops are refused
+        let mut stored = 1;
+        while store.record(&commit, "pds.example", &frame, SystemTime::now(), Duration::from_secs(3)).unwrap() {
+            stored += 1;
+        }
+        assert!(store.total_bytes() <= 1000);
+        assert_eq!(read_evidence(dir.path()).unwrap().len(), stored);
+        assert_eq!(DropEvidenceStore::open(dir.path(), 1000).unwrap().total_bytes(), store.total_bytes());
+    }
+
+    #[test]
+    fn test_torn_tail_is_ignored() {
+        let dir = tempdir().unwrap();
+        let mut store = DropEvidenceStore::open(dir.path(), 1024 * 1024).unwrap();
+        for n in 0..3 {
+            store.record(&cid(n), "pds.example", &[n; 100], SystemTime::now(), Duration::from_secs(3)).unwrap();
+        }
+        let file = fs::read_dir(dir.path()).unwrap().next().unwrap().unwrap().path();
+        let data = fs::read(&file).unwrap();
+        fs::write(&file, &data[..data.len() - 10]).unwrap();
+
+        let stored = read_evidence(&file).unwrap();
+        assert_eq!(stored.len(), 2);
+        assert_eq!(stored[1].frame, vec![1; 100]);
+    }
+}
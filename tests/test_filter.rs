@@ -0,0 +1,235 @@
+#[cfg(test)]
+mod filter_tests {
+    use did_mmap_cache::archive::MultiShardArchive;
+    use did_mmap_cache::filter::{DidAllowlist, FilterDecision, FilterSpec, MixedCommitPolicy, SubscriptionFilter};
+    use did_mmap_cache::parser::core::{parse_input, CommitEnvelope, RepoOp};
+    use did_mmap_cache::testutil::{cid_for, post_record, FrameBuilder, SigningKey};
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn envelope<'a>(did: &'a str, cid: &'a [u8], paths: &[&'a str]) -> CommitEnvelope<'a> {
+        CommitEnvelope {
+            did: Some(did.as_bytes()),
+            sequence: Some(1),
+            signature: None,
+            t: Some(b"#commit"),
+            op: Some(1),
+            raw: cid,
+            blocks: None,
+            commit: None,
+            cid: Some(cid),
+            record_cid: None,
+            ops: paths
+                .iter()
+                .map(|&path| RepoOp { action: "create", path, cid: None })
+                .collect(),
+            source_type: "test",
+        }
+    }
+
+    fn decide(spec: &FilterSpec, did: &str, paths: &[&str]) -> FilterDecision {
+        spec.matches(&envelope(did, b"cid", paths))
+    }
+
+    #[test]
+    fn test_default_keeps_everything() {
+        let spec = FilterSpec::default();
+        assert!(!spec.is_active());
+        assert_eq!(decide(&spec, "did:plc:a", &["app.bsky.feed.like/1"]), FilterDecision::Keep);
+        assert_eq!(decide(&spec, "did:plc:a", &[]), FilterDecision::Keep);
+    }
+
+    #[test]
+    fn test_collection_prefixes() {
+        let spec = FilterSpec::default().with_collections("app.bsky.feed.post");
+        assert!(spec.is_active());
+        assert_eq!(decide(&spec, "did:plc:a", &["app.bsky.feed.post/3k2a"]), FilterDecision::Keep);
+        assert_eq!(decide(&spec, "did:plc:a", &["app.bsky.feed.like/3k2a"]), FilterDecision::ExcludedCollection);
+        // Prefixes match whole NSID segments only
+        assert_eq!(decide(&spec, "did:plc:a", &["app.bsky.feed.postgate/3k2a"]), FilterDecision::ExcludedCollection);
+        // A commit with no ops has nothing to match
+        assert_eq!(decide(&spec, "did:plc:a", &[]), FilterDecision::ExcludedCollection);
+
+        // Namespace prefix with an exclusion carved out of it
+        let spec = FilterSpec::default().with_collections(" app.bsky.feed.* , -app.bsky.feed.like ");
+        assert_eq!(decide(&spec, "did:plc:a", &["app.bsky.feed.repost/1"]), FilterDecision::Keep);
+        assert_eq!(decide(&spec, "did:plc:a", &["app.bsky.feed.like/1"]), FilterDecision::ExcludedCollection);
+        assert_eq!(decide(&spec, "did:plc:a", &["app.bsky.graph.follow/1"]), FilterDecision::ExcludedCollection);
+
+        // Exclusions alone keep everything else
+        let spec = FilterSpec::default().with_collections("-app.bsky.feed.like");
+        assert_eq!(decide(&spec, "did:plc:a", &["app.bsky.graph.follow/1"]), FilterDecision::Keep);
+        assert_eq!(decide(&spec, "did:plc:a", &["app.bsky.feed.like/1"]), FilterDecision::ExcludedCollection);
+    }
+
+    #[test]
+    fn test_multi_op_commit_kept_if_any_op_matches() {
+        let spec = FilterSpec::default().with_collections("app.bsky.feed.post");
+        let paths = ["app.bsky.feed.like/1", "app.bsky.feed.post/2", "app.bsky.graph.follow/3"];
+        assert_eq!(decide(&spec, "did:plc:a", &paths), FilterDecision::Keep);
+    }
+
+    #[test]
+    fn test_did_allowlist() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("dids.txt");
+        fs::write(&path, "# research cohort\ndid:plc:alice\n\n  did:web:bob.example.com  \n").unwrap();
+        let dids = DidAllowlist::from_file(&path).unwrap();
+        assert_eq!(dids.len(), 2);
+        assert!(dids.contains(b"did:plc:alice"));
+        assert!(dids.contains(b"did:web:bob.example.com"));
+        assert!(!dids.contains(b"did:plc:alic"));
+
+        let spec = FilterSpec::default().with_dids(dids).with_collections("app.bsky.feed.post");
+        assert_eq!(decide(&spec, "did:plc:alice", &["app.bsky.feed.post/1"]), FilterDecision::Keep);
+        assert_eq!(decide(&spec, "did:plc:mallory", &["app.bsky.feed.post/1"]), FilterDecision::NotAllowlisted);
+        assert_eq!(decide(&spec, "did:plc:alice", &["app.bsky.feed.like/1"]), FilterDecision::ExcludedCollection);
+
+        let mut no_did = envelope("did:plc:alice", b"cid", &["app.bsky.feed.post/1"]);
+        no_did.did = None;
+        assert_eq!(spec.matches(&no_did), FilterDecision::NotAllowlisted);
+
+        // A typo'd line is an error, not an empty allowlist
+        fs::write(&path, "did:plc:alice\nalice.bsky.social\n").unwrap();
+        let err = DidAllowlist::from_file(&path).err().unwrap();
+        assert!(err.to_string().contains("line 2"), "{}", err);
+    }
+
+    #[test]
+    fn test_envelope_collections() {
+        let env = envelope("did:plc:a", b"cid", &["app.bsky.feed.post/1", "app.bsky.feed.like/2", "app.bsky.feed.post/3", "app.bsky.actor.profile/self"]);
+        assert_eq!(env.ops_len(), 4);
+        assert_eq!(env.blocks_len(), 0);
+        assert_eq!(env.collections().collect::<Vec<_>>(), ["app.bsky.feed.post", "app.bsky.feed.like", "app.bsky.actor.profile"]);
+        assert!(env.touches_collection("app.bsky.feed.like"));
+        // Exact NSIDs, not prefixes
+        assert!(!env.touches_collection("app.bsky.feed"));
+        assert!(!env.touches_collection("app.bsky.feed.postgate"));
+
+        let empty = CommitEnvelope { blocks: Some(&b"car"[..]), ..envelope("did:plc:a", b"cid", &[]) };
+        assert_eq!((empty.ops_len(), empty.blocks_len()), (0, 3));
+        assert_eq!(empty.collections().count(), 0);
+    }
+
+    #[test]
+    fn test_sampling_is_deterministic_per_commit() {
+        let spec = FilterSpec::default().with_sample_rate(0.25);
+        let cids: Vec<Vec<u8>> = (0..4000u32).map(|i| i.to_le_bytes().to_vec()).collect();
+        let kept: Vec<bool> = cids
+            .iter()
+            .map(|cid| spec.matches(&envelope("did:plc:a", cid, &["app.bsky.feed.post/1"])).is_keep())
+            .collect();
+        let rate = kept.iter().filter(|k| **k).count() as f64 / kept.len() as f64;
+        assert!((0.18..0.32).contains(&rate), "{}", rate);
+
+        // The answer depends only on the commit, so it repeats for the same commit seen again
+        for (cid, was_kept) in cids.iter().zip(&kept) {
+            assert_eq!(spec.matches(&envelope("did:plc:a", cid, &["app.bsky.feed.post/1"])).is_keep(), *was_kept);
+        }
+
+        let none = FilterSpec::default().with_sample_rate(0.0);
+        assert_eq!(none.matches(&envelope("did:plc:a", b"x", &[])), FilterDecision::Sampled);
+    }
+
+    /// A signed `#commit` frame from did:plc:mixed creating a post at each of `paths`, with
+    /// the path as its text.
+    fn commit_frame(seq: u64, paths: &[&str]) -> Vec<u8> {
+        let builder = FrameBuilder::new("did:plc:mixed", SigningKey::k256_from_seed(0x11)).seq(seq);
+        paths.iter().fold(builder, |b, path| b.create(*path, post_record(path))).build()
+    }
+
+    /// Runs `frames` through `spec` into a fresh archive the way sovereign_ingester does,
+    /// returning what it stored.
+    fn archive_filtered(spec: &FilterSpec, frames: &[Vec<u8>]) -> Vec<Vec<u8>> {
+        let dir = tempdir().unwrap();
+        let archive = MultiShardArchive::new(dir.path(), 2, 10, None).unwrap();
+        let mut seq = 0;
+        for frame in frames {
+            let envelope = parse_input(frame).unwrap();
+            if !spec.matches(&envelope).is_keep() {
+                continue;
+            }
+            let msg = spec.trim(&envelope).unwrap_or_else(|| frame.clone());
+            let primary = spec.kept_ops(&envelope).next().unwrap().path.to_string();
+            archive.ingest(seq, "did:plc:mixed", primary, msg);
+            seq += 1;
+        }
+        archive.shutdown();
+        let reader = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        (0..seq).map(|s| reader.get_message_by_seq(s).unwrap()).collect()
+    }
+
+    fn paths(frame: &[u8]) -> Vec<String> {
+        parse_input(frame).unwrap().ops.iter().map(|op| op.path.to_string()).collect()
+    }
+
+    #[test]
+    fn test_mixed_commits_archive_whole_or_trimmed() {
+        let frames = vec![
+            commit_frame(1, &["app.bsky.feed.post/1", "app.bsky.feed.like/2", "app.bsky.graph.follow/3"]),
+            commit_frame(2, &["app.bsky.feed.like/4"]),
+            commit_frame(3, &["app.bsky.feed.post/5"]),
+        ];
+        let spec = || FilterSpec::default().with_collections("app.bsky.feed.post");
+
+        // By default a matching commit is stored as it arrived
+        let whole = archive_filtered(&spec(), &frames);
+        assert_eq!(whole, vec![frames[0].clone(), frames[2].clone()]);
+
+        let trimmed = archive_filtered(&spec().with_policy(MixedCommitPolicy::Trim), &frames);
+        assert_eq!(trimmed.len(), 2);
+        assert_eq!(paths(&trimmed[0]), ["app.bsky.feed.post/1"]);
+        // Nothing to trim: the frame is untouched
+        assert_eq!(trimmed[1], frames[2]);
+
+        let env = parse_input(&trimmed[0]).unwrap();
+        let original = parse_input(&frames[0]).unwrap();
+        assert_eq!((env.did, env.sequence, env.signature), (original.did, original.sequence, original.signature));
+        assert_eq!(env.commit, original.commit);
+        let car = env.blocks.unwrap();
+        // The MST still names every record's CID, so look for the record's own block
+        let has = |path: &str| {
+            let record = post_record(path);
+            let block = [cid_for(&record), record].concat();
+            car.windows(block.len()).any(|w| w == block.as_slice())
+        };
+        assert!(has("app.bsky.feed.post/1"));
+        assert!(!has("app.bsky.feed.like/2") && !has("app.bsky.graph.follow/3"));
+        assert!(trimmed[0].len() < frames[0].len());
+
+        // Exclusions trim the same way
+        let spec = FilterSpec::default().with_collections("-app.bsky.feed.like").with_policy(MixedCommitPolicy::Trim);
+        let kept = archive_filtered(&spec, &frames);
+        assert_eq!(kept.iter().map(|f| paths(f)).collect::<Vec<_>>(), [
+            vec!["app.bsky.feed.post/1", "app.bsky.graph.follow/3"],
+            vec!["app.bsky.feed.post/5"],
+        ]);
+    }
+
+    #[test]
+    fn test_subscription_filter_from_client_message() {
+        let filter = SubscriptionFilter::from_message(r#"{"filter":{"collections":["app.bsky.feed.post"],"dids":["did:plc:mixed"]}}"#).unwrap();
+        assert_eq!(filter.collections, ["app.bsky.feed.post"]);
+        assert_eq!(filter.dids, ["did:plc:mixed"]);
+        let spec = filter.spec();
+        assert!(spec.is_active());
+
+        let post = commit_frame(1, &["app.bsky.feed.post/1"]);
+        let like = commit_frame(2, &["app.bsky.feed.like/1"]);
+        assert_eq!(spec.matches_frame(&post), Some(FilterDecision::Keep));
+        assert_eq!(spec.matches_frame(&like), Some(FilterDecision::ExcludedCollection));
+        assert_eq!(spec.matches_frame(b"not a frame"), None);
+        // Turned away on the DID alone
+        let other = SubscriptionFilter::from_message(r#"{"filter":{"dids":["did:plc:other"]}}"#).unwrap().spec();
+        assert_eq!(other.matches_frame(&post), Some(FilterDecision::NotAllowlisted));
+
+        // Either list may be left out; an empty filter keeps everything
+        let open = SubscriptionFilter::from_message(r#"{"filter":{}}"#).unwrap();
+        assert_eq!(open, SubscriptionFilter::default());
+        assert!(!open.spec().is_active());
+
+        for bad in [r#"{"collections":["app.bsky.feed.post"]}"#, r#"{"filter":{"dids":["alice.example.com"]}}"#, "filter"] {
+            assert_eq!(SubscriptionFilter::from_message(bad).unwrap_err().kind(), std::io::ErrorKind::InvalidData, "{}", bad);
+        }
+    }
+}
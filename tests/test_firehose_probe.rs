@@ -0,0 +1,126 @@
+#[cfg(test)]
+mod firehose_probe_tests {
+    use did_mmap_cache::net::{probe_firehose, subscribe_repos_url};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use std::time::Duration;
+    use tungstenite::Message;
+
+    fn text(s: &str, out: &mut Vec<u8>) {
+        out.push(0x60 | s.len() as u8);
+        out.extend_from_slice(s.as_bytes());
+    }
+
+    /// A `{op: 1, t}` frame whose body is one text field, plus `seq` when given.
+    fn frame(t: &str, field: (&str, &str), seq: Option<u8>) -> Vec<u8> {
+        let mut f = vec![0xa2];
+        text("op", &mut f);
+        f.push(0x01);
+        text("t", &mut f);
+        text(t, &mut f);
+        f.push(if seq.is_some() { 0xa2 } else { 0xa1 });
+        text(field.0, &mut f);
+        text(field.1, &mut f);
+        if let Some(seq) = seq {
+            text("seq", &mut f);
+            f.extend_from_slice(&[0x18, seq]);
+        }
+        f
+    }
+
+    /// A PDS that accepts the websocket upgrade and sends `frames`.
+    fn ws_pds(frames: Vec<Vec<u8>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut ws = tungstenite::accept(stream).unwrap();
+            for f in frames {
+                ws.send(Message::Binary(f)).unwrap();
+            }
+            // Hold the connection open until the client is done
+            while ws.read().is_ok() {}
+        });
+        format!("http://{}", addr)
+    }
+
+    /// A PDS that answers plain HTTP (describeServer) but refuses the firehose upgrade.
+    fn http_only_pds() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let response = if request_line.contains("describeServer") {
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 2\r\n\r\n{}"
+                } else {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n"
+                };
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    #[test]
+    fn test_subscribe_repos_url() {
+        assert_eq!(
+            subscribe_repos_url("https://pds.example/", Some(0)).unwrap().as_str(),
+            "wss://pds.example/xrpc/com.atproto.sync.subscribeRepos?cursor=0"
+        );
+        assert_eq!(
+            subscribe_repos_url("http://127.0.0.1:2583", None).unwrap().as_str(),
+            "ws://127.0.0.1:2583/xrpc/com.atproto.sync.subscribeRepos"
+        );
+        assert!(subscribe_repos_url("ftp://pds.example", Some(0)).is_none());
+    }
+
+    #[test]
+    fn test_probe_rejected_upgrade() {
+        let base = http_only_pds();
+        let describe = reqwest::blocking::get(format!("{}/xrpc/com.atproto.server.describeServer", base)).unwrap();
+        assert!(describe.status().is_success());
+
+        let probe = probe_firehose(&base, Duration::from_secs(2));
+        assert!(!probe.ws_ok);
+        assert_eq!(probe.first_frame_ms, None);
+        assert!(probe.error.as_deref().unwrap().contains("404"), "{:?}", probe.error);
+    }
+
+    #[test]
+    fn test_probe_accepts_info_or_frame() {
+        let probe = probe_firehose(&ws_pds(vec![frame("#info", ("name", "OutdatedCursor"), None)]), Duration::from_secs(2));
+        assert!(probe.ws_ok, "{:?}", probe.error);
+        assert!(probe.first_frame_ms.is_some());
+        assert_eq!(probe.latest_seq, None);
+
+        let probe = probe_firehose(&ws_pds(vec![frame("#identity", ("did", "did:plc:x"), Some(42))]), Duration::from_secs(2));
+        assert!(probe.ws_ok, "{:?}", probe.error);
+        assert_eq!(probe.latest_seq, Some(42));
+    }
+
+    #[test]
+    fn test_probe_silent_firehose_times_out() {
+        let probe = probe_firehose(&ws_pds(Vec::new()), Duration::from_millis(300));
+        assert!(!probe.ws_ok);
+        assert!(probe.error.as_deref().unwrap().starts_with("no frame"), "{:?}", probe.error);
+
+        // An error frame (op -1) upgrades fine but isn't a live firehose
+        let mut error_frame = vec![0xa1];
+        text("op", &mut error_frame);
+        error_frame.push(0x20);
+        error_frame.push(0xa1);
+        text("error", &mut error_frame);
+        text("FutureCursor", &mut error_frame);
+        let probe = probe_firehose(&ws_pds(vec![error_frame]), Duration::from_secs(2));
+        assert!(!probe.ws_ok);
+        assert!(probe.first_frame_ms.is_some());
+    }
+}
@@ -0,0 +1,73 @@
+#[cfg(test)]
+mod firehose_tap_tests {
+    use std::net::TcpListener;
+    use std::process::Command;
+    use std::sync::mpsc::{channel, Receiver};
+    use std::thread;
+    use tungstenite::handshake::server::{Request, Response};
+    use tungstenite::Message;
+
+    /// `{ op: 1, t: "#identity" }` header plus a `{ seq }` body.
+    fn frame(seq: u64) -> Vec<u8> {
+        let mut f = vec![0xa2, 0x62, b'o', b'p', 0x01, 0x61, b't', 0x69];
+        f.extend_from_slice(b"#identity");
+        f.extend_from_slice(&[0xa1, 0x63, b's', b'e', b'q', 0x1b]);
+        f.extend_from_slice(&seq.to_be_bytes());
+        f
+    }
+
+    /// Serves each batch of seqs on its own connection, closing it afterwards. Returns the
+    /// endpoint and the request URIs seen.
+    #[allow(clippy::result_large_err)] // the handshake callback's error type is tungstenite's, not ours
+    fn mock_server(sessions: Vec<Vec<u64>>) -> (String, Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            for seqs in sessions {
+                let (stream, _) = listener.accept().unwrap();
+                let tx = tx.clone();
+                let mut ws = tungstenite::accept_hdr(stream, |req: &Request, resp: Response| {
+                    let _ = tx.send(req.uri().to_string());
+                    Ok(resp)
+                })
+                .unwrap();
+                for seq in seqs {
+                    ws.send(Message::Binary(frame(seq))).unwrap();
+                }
+                let _ = ws.close(None);
+                let _ = ws.flush();
+            }
+        });
+        (format!("ws://{}", addr), rx)
+    }
+
+    fn tap_seqs(url: &str, max_reconnects: u32) -> Vec<u64> {
+        let output = Command::new(env!("CARGO_BIN_EXE_firehose_tap"))
+            .args(["-e", url, "--max-reconnects", &max_reconnects.to_string()])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["seq"].as_u64().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_exits_when_the_connection_drops() {
+        let (url, uris) = mock_server(vec![vec![1, 2, 3]]);
+        assert_eq!(tap_seqs(&url, 0), vec![1, 2, 3]);
+        assert_eq!(uris.iter().count(), 1);
+    }
+
+    #[test]
+    fn test_reconnects_resume_from_the_last_seq() {
+        let (url, uris) = mock_server(vec![vec![1, 2, 3], vec![4]]);
+        assert_eq!(tap_seqs(&url, 1), vec![1, 2, 3, 4]);
+        let uris: Vec<String> = uris.iter().collect();
+        assert!(!uris[0].contains("cursor="));
+        assert!(uris[1].ends_with("cursor=3"));
+    }
+}
@@ -0,0 +1,55 @@
+#[cfg(test)]
+mod gap_log_tests {
+    use did_mmap_cache::archive::gaps::GAPS_FILE;
+    use did_mmap_cache::archive::{GapLog, GapRecord};
+    use std::fs;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_gaps_persist_and_query_by_host() {
+        let dir = tempdir().unwrap();
+        let hooked = Arc::new(AtomicU64::new(0));
+        let seen = Arc::clone(&hooked);
+        let log = GapLog::open(dir.path()).unwrap().with_hook(Box::new(move |gap: &GapRecord| {
+            seen.fetch_add(gap.span(), Ordering::Relaxed);
+        }));
+        assert!(log.is_empty());
+
+        log.record(GapRecord::now("pds.a.example", 10, 20)).unwrap();
+        log.record(GapRecord::now("pds.b.example", 5, 6)).unwrap();
+        log.record(GapRecord::now("pds.a.example", 30, 100)).unwrap();
+        assert_eq!(hooked.load(Ordering::Relaxed), 9 + 69);
+        assert_eq!(log.total_gap_span(), 9 + 69);
+
+        // One JSON line per gap, readable back after a restart
+        let text = fs::read_to_string(dir.path().join(GAPS_FILE)).unwrap();
+        assert_eq!(text.lines().count(), 3);
+        let first: serde_json::Value = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+        assert_eq!(first["host"], "pds.a.example");
+        assert_eq!((first["saved_cursor"].as_u64(), first["new_start_seq"].as_u64()), (Some(10), Some(20)));
+        assert!(first["time"].as_u64().unwrap() > 0);
+
+        let reopened = GapLog::open(dir.path()).unwrap();
+        let a: Vec<(u64, u64)> = reopened.gaps_for_host("pds.a.example").iter().map(|g| (g.saved_cursor, g.new_start_seq)).collect();
+        assert_eq!(a, vec![(10, 20), (30, 100)]);
+        assert_eq!(reopened.gaps_for_host("pds.b.example")[0].span(), 0);
+        assert!(reopened.gaps_for_host("pds.c.example").is_empty());
+        assert_eq!(reopened.total_gap_span(), 78);
+    }
+
+    #[test]
+    fn test_torn_line_is_skipped() {
+        let dir = tempdir().unwrap();
+        let log = GapLog::open(dir.path()).unwrap();
+        log.record(GapRecord::now("pds.a.example", 1, 4)).unwrap();
+        let mut text = fs::read_to_string(dir.path().join(GAPS_FILE)).unwrap();
+        text.push_str("{\"host\":\"pds.a.exa");
+        fs::write(dir.path().join(GAPS_FILE), text).unwrap();
+
+        let reopened = GapLog::open(dir.path()).unwrap();
+        assert_eq!(reopened.len(), 1);
+        assert_eq!(reopened.total_gap_span(), 2);
+    }
+}
@@ -0,0 +1,62 @@
+#[cfg(test)]
+mod get_record_tests {
+    use did_mmap_cache::archive::MultiShardArchive;
+    use std::io;
+    use tempfile::tempdir;
+
+    const ALICE: &str = "did:plc:alice";
+
+    #[test]
+    fn test_latest_version_of_a_path_wins() {
+        let dir = tempdir().unwrap();
+        // Three messages per segment: v1 and v2 share a segment, v3 lands in a later one
+        let archive = MultiShardArchive::new(dir.path(), 2, 3, None).unwrap();
+        archive.ingest(1, ALICE, "app.bsky.feed.post/3kabc".into(), b"v1".to_vec());
+        archive.ingest(2, ALICE, "app.bsky.feed.post/3kabc".into(), b"v2".to_vec());
+        archive.ingest(3, ALICE, "app.bsky.feed.post/3kxyz".into(), b"other".to_vec());
+        archive.ingest(4, ALICE, "app.bsky.feed.like/3kabc".into(), b"like".to_vec());
+        archive.shutdown();
+
+        let reader = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        assert_eq!(reader.get_record(ALICE, "app.bsky.feed.post", "3kabc").unwrap(), b"v2");
+        assert_eq!(reader.get_record(ALICE, "app.bsky.feed.post", "3kxyz").unwrap(), b"other");
+        assert_eq!(reader.get_record(ALICE, "app.bsky.feed.like", "3kabc").unwrap(), b"like");
+        let missing = reader.get_record(ALICE, "app.bsky.feed.post", "3knope").unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+
+        let archive = MultiShardArchive::new(dir.path(), 2, 3, None).unwrap();
+        archive.ingest(9, ALICE, "app.bsky.feed.post/3kabc".into(), b"v3".to_vec());
+        archive.shutdown();
+        reader.refresh().unwrap();
+        assert_eq!(reader.get_record(ALICE, "app.bsky.feed.post", "3kabc").unwrap(), b"v3");
+
+        // A deleted newest version isn't replaced by the one before it
+        reader.mark_deleted(9).unwrap();
+        assert!(reader.get_record(ALICE, "app.bsky.feed.post", "3kabc").is_err());
+    }
+
+    #[cfg(feature = "test-fixtures")]
+    #[test]
+    fn test_same_path_in_other_repos_is_skipped() {
+        use did_mmap_cache::testutil::{post_record, FrameBuilder, SigningKey};
+
+        let frame = |did: &str, seq: u64, text: &str| {
+            FrameBuilder::new(did, SigningKey::k256_from_seed(seq as u8))
+                .seq(seq)
+                .create("app.bsky.actor.profile/self", post_record(text))
+                .build()
+        };
+        let dir = tempdir().unwrap();
+        // One shard, so both repos share it and the path hash alone can't tell them apart
+        let archive = MultiShardArchive::new(dir.path(), 1, 10, None).unwrap();
+        let alice = frame(ALICE, 1, "alice");
+        let bob = frame("did:plc:bob", 2, "bob");
+        archive.ingest(1, ALICE, "app.bsky.actor.profile/self".into(), alice.clone());
+        archive.ingest(2, "did:plc:bob", "app.bsky.actor.profile/self".into(), bob.clone());
+        archive.shutdown();
+
+        let reader = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        assert_eq!(reader.get_record(ALICE, "app.bsky.actor.profile", "self").unwrap(), alice);
+        assert_eq!(reader.get_record("did:plc:bob", "app.bsky.actor.profile", "self").unwrap(), bob);
+    }
+}
@@ -0,0 +1,102 @@
+#[cfg(test)]
+mod handle_verification_tests {
+    use did_mmap_cache::resolver::{verify_handle, HandleLookup, HandleVerification, VerifiedHandle};
+    use std::cell::Cell;
+    use std::collections::HashMap;
+
+    const ALICE: &str = "did:plc:alice0000000000000000000";
+    const MALLORY: &str = "did:plc:mallory00000000000000000";
+
+    /// Canned TXT records and well-known bodies, keyed by handle.
+    #[derive(Default)]
+    struct MockLookup {
+        dns: HashMap<&'static str, Vec<&'static str>>,
+        http: HashMap<&'static str, &'static str>,
+        calls: Cell<usize>,
+    }
+
+    impl MockLookup {
+        fn dns(mut self, handle: &'static str, dids: &[&'static str]) -> Self {
+            self.dns.insert(handle, dids.to_vec());
+            self
+        }
+
+        fn http(mut self, handle: &'static str, did: &'static str) -> Self {
+            self.http.insert(handle, did);
+            self
+        }
+    }
+
+    impl HandleLookup for MockLookup {
+        fn dns_dids(&self, handle: &str) -> Vec<String> {
+            self.calls.set(self.calls.get() + 1);
+            self.dns.get(handle).map(|v| v.iter().map(|d| d.to_string()).collect()).unwrap_or_default()
+        }
+
+        fn http_did(&self, handle: &str) -> Option<String> {
+            self.calls.set(self.calls.get() + 1);
+            self.http.get(handle).map(|d| d.to_string())
+        }
+    }
+
+    #[test]
+    fn test_verified_via_dns() {
+        let lookup = MockLookup::default().dns("alice.example.com", &[ALICE]);
+        assert_eq!(verify_handle(ALICE, "alice.example.com", &lookup), HandleVerification::VerifiedViaDns);
+        // Claims are compared case-insensitively, with or without the trailing dot
+        assert_eq!(verify_handle(ALICE, "Alice.Example.com.", &lookup), HandleVerification::VerifiedViaDns);
+    }
+
+    #[test]
+    fn test_verified_via_http() {
+        let lookup = MockLookup::default().http("alice.example.com", ALICE);
+        assert_eq!(verify_handle(ALICE, "alice.example.com", &lookup), HandleVerification::VerifiedViaHttp);
+
+        // A stale TXT record doesn't override a matching well-known file
+        let lookup = MockLookup::default().dns("alice.example.com", &[MALLORY]).http("alice.example.com", ALICE);
+        assert_eq!(verify_handle(ALICE, "alice.example.com", &lookup), HandleVerification::VerifiedViaHttp);
+    }
+
+    #[test]
+    fn test_mismatch() {
+        // mallory's DID document claims alice's handle
+        let lookup = MockLookup::default().dns("alice.example.com", &[ALICE]);
+        assert_eq!(
+            verify_handle(MALLORY, "alice.example.com", &lookup),
+            HandleVerification::Mismatch(ALICE.to_string())
+        );
+
+        let lookup = MockLookup::default().http("alice.example.com", ALICE);
+        assert_eq!(
+            verify_handle(MALLORY, "alice.example.com", &lookup),
+            HandleVerification::Mismatch(ALICE.to_string())
+        );
+    }
+
+    #[test]
+    fn test_claim_only() {
+        let lookup = MockLookup::default();
+        assert_eq!(verify_handle(ALICE, "alice.example.com", &lookup), HandleVerification::ClaimOnly);
+        assert_eq!(lookup.calls.get(), 2);
+
+        // Claims that aren't domain names are never looked up
+        let lookup = MockLookup::default();
+        for handle in ["localhost", "127.0.0.1", "alice..example.com", "-alice.example.com", "alice.example.com/x", ""] {
+            assert_eq!(verify_handle(ALICE, handle, &lookup), HandleVerification::ClaimOnly, "{}", handle);
+        }
+        assert_eq!(lookup.calls.get(), 0);
+    }
+
+    #[test]
+    fn test_display_name_marks_unverified() {
+        let named = |verification| VerifiedHandle { handle: "alice.example.com".to_string(), verification };
+        assert_eq!(named(HandleVerification::VerifiedViaDns).display_name(), "alice.example.com");
+        assert_eq!(named(HandleVerification::VerifiedViaHttp).display_name(), "alice.example.com");
+        assert_eq!(named(HandleVerification::ClaimOnly).display_name(), "alice.example.com [unverified]");
+        assert_eq!(
+            named(HandleVerification::Mismatch(MALLORY.to_string())).display_name(),
+            "alice.example.com [mismatch]"
+        );
+        assert!(!named(HandleVerification::ClaimOnly).is_verified());
+    }
+}
@@ -0,0 +1,120 @@
+#[cfg(all(test, feature = "test-fixtures"))]
+mod heads_tests {
+    use did_mmap_cache::archive::heads::HeadInfo;
+    use did_mmap_cache::archive::MultiShardArchive;
+    use did_mmap_cache::parser::core::parse_input;
+    use did_mmap_cache::testutil::{identity_frame, post_record, FrameBuilder, SigningKey};
+    use fxhash::FxHasher;
+    use std::hash::{Hash, Hasher};
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    const ALICE: &str = "did:plc:alice";
+    const BOB: &str = "did:plc:bob";
+    const CAROL: &str = "did:plc:carol";
+
+    fn commit(did: &str, seq: u64) -> Vec<u8> {
+        FrameBuilder::new(did, SigningKey::k256_from_seed(seq as u8))
+            .seq(seq)
+            .rev(format!("rev{}", seq))
+            .create(format!("app.bsky.feed.post/3lbf{:09}", seq), post_record("hi"))
+            .build()
+    }
+
+    /// What `heads.bin` should say for `frame`, archived at `seq`.
+    fn head_of(frame: &[u8], seq: u64) -> HeadInfo {
+        let envelope = parse_input(frame).unwrap();
+        let mut hasher = FxHasher::default();
+        format!("rev{}", seq).hash(&mut hasher);
+        HeadInfo { seq, commit_cid: envelope.cid.unwrap()[1..].try_into().unwrap(), rev_hash: hasher.finish() }
+    }
+
+    /// A did:web with the same FxHash as `victim`, a 24-byte did:web: the second 8-byte word
+    /// is picked, and the third solved for so the hasher's state after both matches the victim's.
+    fn fx_collision(victim: &str) -> String {
+        const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+        let step = |hash: u64, word: &[u8]| (hash.rotate_left(5) ^ u64::from_le_bytes(word.try_into().unwrap())).wrapping_mul(SEED);
+        let bytes = victim.as_bytes();
+        assert_eq!((bytes.len(), &bytes[..8]), (24, &b"did:web:"[..]));
+        let start = step(0, &bytes[..8]);
+        let target = step(start, &bytes[8..16]).rotate_left(5) ^ u64::from_le_bytes(bytes[16..].try_into().unwrap());
+        (0..1_000_000u32)
+            .find_map(|n| {
+                let second: String = format!("{:08}", n).chars().rev().collect();
+                let third = (target ^ step(start, second.as_bytes()).rotate_left(5)).to_le_bytes();
+                third.iter().all(|b| b.is_ascii_graphic()).then(|| format!("did:web:{}{}", second, String::from_utf8(third.to_vec()).unwrap()))
+            })
+            .unwrap()
+    }
+
+    /// One writer session over `dir`, three messages per segment, persisted on return.
+    fn write(dir: &Path, track: bool, frames: &[(u64, &str, Vec<u8>)]) {
+        let archive = MultiShardArchive::new(dir, 1, 3, None).unwrap();
+        archive.set_track_heads(track);
+        for (seq, did, frame) in frames {
+            archive.ingest(*seq, did, String::new(), frame.clone());
+        }
+        archive.shutdown();
+    }
+
+    #[test]
+    fn test_heads_advance_across_segments_and_reopen() {
+        let dir = tempdir().unwrap();
+        let frames: Vec<(u64, &str, Vec<u8>)> = vec![
+            (1, ALICE, commit(ALICE, 1)),
+            (2, BOB, commit(BOB, 2)),
+            (3, ALICE, commit(ALICE, 3)),
+            // Second segment: alice only has an #identity, which isn't a head
+            (4, ALICE, identity_frame(ALICE, 4, Some("alice.example.com"))),
+            (5, BOB, commit(BOB, 5)),
+            (6, CAROL, commit(CAROL, 6)),
+        ];
+        write(dir.path(), true, &frames);
+
+        let reader = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        assert_eq!(reader.latest_for_did(ALICE), Some(head_of(&frames[2].2, 3)));
+        assert_eq!(reader.latest_for_did(BOB), Some(head_of(&frames[4].2, 5)));
+        assert_eq!(reader.latest_for_did(CAROL), Some(head_of(&frames[5].2, 6)));
+        assert_eq!(reader.latest_for_did("did:plc:nobody"), None);
+        let mut dids: Vec<String> = reader.iter_dids().collect();
+        dids.sort();
+        assert_eq!(dids, vec![ALICE, BOB, CAROL]);
+
+        // A later session's segment is picked up on refresh
+        let alice_7 = commit(ALICE, 7);
+        write(dir.path(), true, &[(7, ALICE, alice_7.clone())]);
+        assert_eq!(reader.latest_for_did(ALICE).unwrap().seq, 3);
+        reader.refresh().unwrap();
+        assert_eq!(reader.latest_for_did(ALICE), Some(head_of(&alice_7, 7)));
+
+        // Segments written without tracking leave the heads alone
+        write(dir.path(), false, &[(8, BOB, commit(BOB, 8))]);
+        let reopened = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        assert_eq!(reopened.latest_for_did(ALICE).unwrap().seq, 7);
+        assert_eq!(reopened.latest_for_did(BOB).unwrap().seq, 5);
+        assert_eq!(reopened.get_message_by_seq(8).unwrap(), commit(BOB, 8));
+    }
+
+    #[test]
+    fn test_colliding_dids_keep_their_own_heads() {
+        let victim = "did:web:alice.example.io";
+        let forged = fx_collision(victim);
+        let fx = |did: &str| {
+            let mut hasher = FxHasher::default();
+            did.hash(&mut hasher);
+            hasher.finish()
+        };
+        assert_ne!(forged, victim);
+        assert_eq!(fx(&forged), fx(victim));
+
+        // The forged repo's newer commit must not displace the victim's head
+        let dir = tempdir().unwrap();
+        let frames = vec![(1, victim, commit(victim, 1)), (2, forged.as_str(), commit(&forged, 2))];
+        write(dir.path(), true, &frames);
+
+        let reader = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        assert_eq!(reader.latest_for_did(victim), Some(head_of(&frames[0].2, 1)));
+        assert_eq!(reader.latest_for_did(&forged), Some(head_of(&frames[1].2, 2)));
+        assert_eq!(reader.iter_dids().count(), 2);
+    }
+}
@@ -0,0 +1,60 @@
+#[cfg(test)]
+mod incremental_refresh_tests {
+    use did_mmap_cache::archive::{ArchiveError, MultiShardArchive, SegmentedArchive};
+    use std::path::Path;
+    use std::time::Instant;
+    use tempfile::tempdir;
+
+    fn message(seq: u64) -> Vec<u8> {
+        format!("message {}", seq).into_bytes()
+    }
+
+    /// One message per segment, so `seqs` persists that many segments.
+    fn write_segments(root: &Path, seqs: std::ops::Range<u64>) {
+        let archive = MultiShardArchive::new(root, 1, 1, None).unwrap();
+        for seq in seqs {
+            archive.ingest(seq, "did:plc:alice", format!("app.bsky.feed.post/{}", seq), message(seq));
+        }
+        archive.shutdown();
+    }
+
+    #[test]
+    fn test_refresh_adds_new_segments_and_keeps_caches() {
+        let dir = tempdir().unwrap();
+        write_segments(dir.path(), 0..200);
+        let shard = SegmentedArchive::open_directory(dir.path().join("shard_0"), None, None).unwrap();
+        assert_eq!(shard.segment_count(), 200);
+
+        let started = Instant::now();
+        shard.force_rescan().unwrap();
+        let full = started.elapsed();
+        let started = Instant::now();
+        shard.refresh().unwrap();
+        eprintln!("200 segments: full rescan {:?}, incremental refresh {:?}", full, started.elapsed());
+
+        for seq in (0..200).step_by(10) {
+            assert_eq!(shard.get_message_by_seq(seq, None).unwrap(), message(seq));
+        }
+        assert_eq!(shard.cached_clusters(), 20);
+
+        write_segments(dir.path(), 200..205);
+        assert!(matches!(shard.get_message_by_seq(202, None), Err(ArchiveError::OutOfRange)));
+        shard.refresh().unwrap();
+        assert_eq!(shard.segment_count(), 205);
+        assert_eq!(shard.max_seq(), Some(204));
+        for seq in 200..205 {
+            assert_eq!(shard.get_message_by_seq(seq, None).unwrap(), message(seq));
+        }
+        // The 20 old clusters are still cached next to the 5 new ones
+        assert_eq!(shard.cached_clusters(), 25);
+
+        // Nothing new: refresh is a no-op
+        shard.refresh().unwrap();
+        assert_eq!((shard.segment_count(), shard.cached_clusters()), (205, 25));
+
+        // A full rescan starts over with fresh mappings
+        shard.force_rescan().unwrap();
+        assert_eq!((shard.segment_count(), shard.cached_clusters()), (205, 0));
+        assert_eq!(shard.get_message_by_seq(150, None).unwrap(), message(150));
+    }
+}
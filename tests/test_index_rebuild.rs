@@ -0,0 +1,100 @@
+#[cfg(test)]
+mod index_rebuild_tests {
+    use did_mmap_cache::archive::{MultiShardArchive, Segment, SegmentedArchive};
+    use did_mmap_cache::mst::builder::MerkleAlgorithm;
+    use std::fs;
+    use std::io;
+    use std::path::{Path, PathBuf};
+    use tempfile::tempdir;
+
+    /// One shard, one 50-message segment from seq 100, with seq 120 left as a gap.
+    fn write_archive(root: &Path) -> PathBuf {
+        write_archive_with(root, MerkleAlgorithm::default())
+    }
+
+    fn write_archive_with(root: &Path, alg: MerkleAlgorithm) -> PathBuf {
+        let archive = MultiShardArchive::new(root, 1, 49, None).unwrap();
+        archive.set_hash_algorithm(alg);
+        for seq in (100..150u64).filter(|&s| s != 120) {
+            let did = format!("did:plc:user{}", seq % 5);
+            archive.ingest(seq, &did, format!("app.bsky.feed.post/{}", seq), format!("message {}", seq).into_bytes());
+        }
+        archive.shutdown();
+        root.join("shard_0").join("s0_100.bin")
+    }
+
+    #[test]
+    fn test_deleted_idx_is_rebuilt_from_the_bin() {
+        let dir = tempdir().unwrap();
+        let bin = write_archive(dir.path());
+        let idx = bin.with_extension("idx");
+        let original = fs::read(&idx).unwrap();
+
+        fs::remove_file(&idx).unwrap();
+        assert!(MultiShardArchive::open_readonly(dir.path(), None).unwrap().get_message_by_seq(100).is_err());
+
+        Segment::rebuild_index_from_bin(&bin, 100, 149, None).unwrap();
+        // Same clusters, same path index: the same bytes, root included
+        assert_eq!(fs::read(&idx).unwrap(), original);
+
+        let reader = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        for seq in (100..150u64).filter(|&s| s != 120) {
+            assert_eq!(reader.get_message_by_seq(seq).unwrap(), format!("message {}", seq).into_bytes());
+        }
+        assert!(reader.get_message_by_seq(120).is_err());
+        assert_eq!(reader.get_record("did:plc:user2", "app.bsky.feed.post", "137").unwrap(), b"message 137");
+    }
+
+    #[test]
+    fn test_truncated_idx_without_path_index() {
+        let dir = tempdir().unwrap();
+        let bin = write_archive(dir.path());
+        let idx = bin.with_extension("idx");
+        let original = fs::read(&idx).unwrap();
+        fs::write(&idx, &original[..40]).unwrap();
+        fs::remove_file(bin.with_extension("pidx")).unwrap();
+
+        Segment::rebuild_index_from_bin(&bin, 100, 149, None).unwrap();
+        let rebuilt = fs::read(&idx).unwrap();
+        assert_eq!(rebuilt.len(), original.len());
+        // The root only covers the messages, so it comes back even without the paths
+        assert_eq!(rebuilt[..32], original[..32]);
+
+        let reader = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        assert_eq!(reader.get_message_by_seq(149).unwrap(), b"message 149");
+        assert_eq!(reader.get_record("did:plc:user2", "app.bsky.feed.post", "137").unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_rebuild_keeps_the_hash_algorithm() {
+        let dir = tempdir().unwrap();
+        let bin = write_archive_with(dir.path(), MerkleAlgorithm::Sha256);
+        let idx = bin.with_extension("idx");
+        let original = fs::read(&idx).unwrap();
+
+        // Header intact: the id is read from it. Cut before the id: the surviving root picks it.
+        for cut in [48, 40] {
+            fs::write(&idx, &original[..cut]).unwrap();
+            Segment::rebuild_index_from_bin(&bin, 100, 149, None).unwrap();
+            assert_eq!(fs::read(&idx).unwrap(), original, "cut at {}", cut);
+        }
+        let shard = SegmentedArchive::open_directory(dir.path().join("shard_0"), None, None).unwrap();
+        assert!(shard.verify_integrity_at_seq(100, None).unwrap());
+    }
+
+    #[test]
+    fn test_seqs_outside_the_range_are_rejected() {
+        let dir = tempdir().unwrap();
+        let bin = write_archive(dir.path());
+        let original = fs::read(bin.with_extension("idx")).unwrap();
+
+        let err = Segment::rebuild_index_from_bin(&bin, 100, 140, None).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(bin.with_extension("idx")).unwrap(), original);
+
+        let mut bytes = fs::read(&bin).unwrap();
+        bytes.truncate(bytes.len() - 3);
+        fs::write(&bin, bytes).unwrap();
+        assert!(Segment::rebuild_index_from_bin(&bin, 100, 149, None).is_err());
+    }
+}
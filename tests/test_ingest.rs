@@ -0,0 +1,390 @@
+#[cfg(test)]
+mod ingest_tests {
+    use did_mmap_cache::archive::{GapLog, GapRecord, MultiShardArchive};
+    use did_mmap_cache::ingest::{backoff_delay, ConnectorConfig, Endpoint, EndpointKind, FirehoseConnector, FirehoseEvent, Flow, PipelineShutdown};
+    use did_mmap_cache::monitor::SovereignMonitor;
+    use std::io;
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+    use std::sync::mpsc::{channel, Receiver};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+    use tungstenite::handshake::server::{Request, Response};
+    use tungstenite::Message;
+
+    /// What the mock does with one accepted connection.
+    enum Session {
+        /// Send these seqs, then close the socket
+        SendAndClose(Vec<u64>),
+        /// Send these seqs, then go quiet without closing
+        SendAndStall(Vec<u64>, Duration),
+        /// Send `#info` `OutdatedCursor` and wait for the client to hang up
+        OutdatedCursor,
+    }
+
+    /// `{ op: 1, t: "#identity" }` header plus a `{ seq }` body; enough for the connector to track the cursor.
+    fn frame(seq: u64) -> Vec<u8> {
+        let mut f = vec![0xa2, 0x62, b'o', b'p', 0x01, 0x61, b't', 0x69];
+        f.extend_from_slice(b"#identity");
+        f.extend_from_slice(&[0xa1, 0x63, b's', b'e', b'q', 0x1b]);
+        f.extend_from_slice(&seq.to_be_bytes());
+        f
+    }
+
+    /// `{ op: 1, t: "#info" }` header plus a `{ name: "OutdatedCursor" }` body.
+    fn outdated_cursor_frame() -> Vec<u8> {
+        let mut f = vec![0xa2, 0x62, b'o', b'p', 0x01, 0x61, b't', 0x65];
+        f.extend_from_slice(b"#info");
+        f.extend_from_slice(&[0xa1, 0x64, b'n', b'a', b'm', b'e', 0x6e]);
+        f.extend_from_slice(b"OutdatedCursor");
+        f
+    }
+
+    /// Serves one `Session` per accepted connection, then stops listening.
+    /// Returns the endpoint URL and the request URIs the mock saw, in order.
+    #[allow(clippy::result_large_err)] // the handshake callback's error type is tungstenite's, not ours
+    fn mock_server(sessions: Vec<Session>) -> (String, Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = channel();
+        thread::spawn(move || {
+            for session in sessions {
+                let (stream, _) = listener.accept().unwrap();
+                let tx = tx.clone();
+                let mut ws = tungstenite::accept_hdr(stream, |req: &Request, resp: Response| {
+                    let _ = tx.send(req.uri().to_string());
+                    Ok(resp)
+                }).unwrap();
+                match session {
+                    Session::SendAndClose(seqs) => {
+                        for s in seqs {
+                            ws.send(Message::Binary(frame(s))).unwrap();
+                        }
+                        let _ = ws.close(None);
+                        let _ = ws.flush();
+                    }
+                    Session::SendAndStall(seqs, hold) => {
+                        for s in seqs {
+                            ws.send(Message::Binary(frame(s))).unwrap();
+                        }
+                        thread::sleep(hold);
+                    }
+                    Session::OutdatedCursor => {
+                        ws.send(Message::Binary(outdated_cursor_frame())).unwrap();
+                        while ws.read().is_ok() {}
+                    }
+                }
+            }
+        });
+        (format!("ws://{}", addr), rx)
+    }
+
+    fn fast_config(failover: bool) -> ConnectorConfig {
+        ConnectorConfig {
+            failover,
+            max_failures: 1,
+            stall_timeout: Some(Duration::from_millis(300)),
+            read_timeout: Duration::from_millis(100),
+            reconnect_delay: Duration::from_millis(10),
+            connect_error_delay: Duration::from_millis(10),
+            compressed: false,
+            relay_dict: None,
+            max_reconnects: None,
+        }
+    }
+
+    /// Runs the connector until `stop_at` arrives, returning every seq seen and, per switch, whether the cursor was reset.
+    fn run_until(connector: &mut FirehoseConnector, stop_at: u64) -> (Vec<u64>, Vec<bool>) {
+        let mut seqs = Vec::new();
+        let mut switches = Vec::new();
+        connector.run(|event| match event {
+            FirehoseEvent::Frame { seq: Some(seq), .. } => {
+                seqs.push(seq);
+                if seq == stop_at { Flow::Stop } else { Flow::Continue }
+            }
+            FirehoseEvent::Switched { cursor_reset, .. } => {
+                switches.push(cursor_reset);
+                Flow::Continue
+            }
+            _ => Flow::Continue,
+        });
+        (seqs, switches)
+    }
+
+    fn uri_cursor(uri: &str) -> Option<u64> {
+        uri.split_once("cursor=").map(|(_, c)| c.parse().unwrap())
+    }
+
+    #[test]
+    fn test_endpoint_parsing() {
+        let relay = Endpoint::parse("bsky.network").unwrap();
+        assert_eq!(relay.kind, EndpointKind::Relay);
+        assert_eq!(relay.subscribe_url(Some(42)), "wss://bsky.network/xrpc/com.atproto.sync.subscribeRepos?cursor=42");
+
+        let pds = Endpoint::parse("pds=wss://pds.example:2470/xrpc/com.atproto.sync.subscribeRepos?cursor=9").unwrap();
+        assert_eq!(pds.kind, EndpointKind::Pds);
+        assert_eq!(pds.host(), "pds.example:2470");
+        assert_eq!(pds.subscribe_url(None), "wss://pds.example:2470/xrpc/com.atproto.sync.subscribeRepos");
+
+        assert!(Endpoint::parse("https://bsky.network").is_none());
+        assert!(Endpoint::parse("relay=").is_none());
+    }
+
+    #[test]
+    fn test_endpoint_from_address() {
+        let v6 = Endpoint::from_address(EndpointKind::Pds, "http://[::1]:3000").unwrap();
+        assert_eq!(v6.host(), "[::1]:3000");
+        assert_eq!(v6.subscribe_url(None), "wss://[::1]:3000/xrpc/com.atproto.sync.subscribeRepos");
+
+        let ported = Endpoint::from_address(EndpointKind::Pds, "wss://pds.example:8443/xrpc/com.atproto.sync.subscribeRepos").unwrap();
+        assert_eq!(ported.host(), "pds.example:8443");
+        assert_eq!(ported.subscribe_url(Some(5)), "wss://pds.example:8443/xrpc/com.atproto.sync.subscribeRepos?cursor=5");
+        // Without a scheme the port is still a port
+        assert_eq!(Endpoint::from_address(EndpointKind::Pds, "pds.example:8443").unwrap(), ported);
+        // A path on an https base URL is not where the firehose lives
+        assert_eq!(Endpoint::from_address(EndpointKind::Pds, "https://pds.example:8443/some/page").unwrap(), ported);
+
+        let bare = Endpoint::from_address(EndpointKind::Pds, " pds.example ").unwrap();
+        assert_eq!(bare.host(), "pds.example");
+        assert_eq!(bare.subscribe_url(None), "wss://pds.example/xrpc/com.atproto.sync.subscribeRepos");
+        assert_eq!(Endpoint::from_address(EndpointKind::Pds, "https://pds.example:443").unwrap(), bare);
+
+        assert!(Endpoint::from_address(EndpointKind::Pds, "not a host").is_none());
+        assert!(Endpoint::from_address(EndpointKind::Pds, "").is_none());
+    }
+
+    #[test]
+    fn test_failover_between_relays_keeps_cursor() {
+        let (a_url, a_uris) = mock_server(vec![Session::SendAndClose(vec![1, 2, 3])]);
+        let (b_url, b_uris) = mock_server(vec![Session::SendAndClose(vec![4, 5])]);
+        let endpoints = vec![Endpoint::parse(&a_url).unwrap(), Endpoint::parse(&b_url).unwrap()];
+
+        let mut connector = FirehoseConnector::new(endpoints, fast_config(true), Arc::new(AtomicBool::new(true)));
+        let (seqs, switches) = run_until(&mut connector, 5);
+
+        assert_eq!(seqs, vec![1, 2, 3, 4, 5]);
+        assert_eq!(switches, vec![false]);
+        assert_eq!(uri_cursor(&a_uris.recv().unwrap()), None);
+        assert_eq!(uri_cursor(&b_uris.recv().unwrap()), Some(3));
+        assert_eq!(connector.cursor(), Some(5));
+    }
+
+    #[test]
+    fn test_relay_to_pds_resets_cursor() {
+        let (a_url, _a_uris) = mock_server(vec![Session::SendAndClose(vec![1_000_000, 1_000_001])]);
+        let (b_url, b_uris) = mock_server(vec![Session::SendAndClose(vec![7])]);
+        let endpoints = vec![Endpoint::parse(&a_url).unwrap(), Endpoint::parse(&format!("pds={}", b_url)).unwrap()];
+
+        let mut connector = FirehoseConnector::new(endpoints, fast_config(true), Arc::new(AtomicBool::new(true)))
+            .with_cursor(Some(999_999));
+        let (seqs, switches) = run_until(&mut connector, 7);
+
+        assert_eq!(seqs, vec![1_000_000, 1_000_001, 7]);
+        assert_eq!(switches, vec![true]);
+        // The relay cursor would be meaningless to the PDS
+        assert_eq!(uri_cursor(&b_uris.recv().unwrap()), None);
+    }
+
+    #[test]
+    fn test_stall_rotates_to_next_endpoint() {
+        let (a_url, _a_uris) = mock_server(vec![Session::SendAndStall(vec![10], Duration::from_secs(5))]);
+        let (b_url, b_uris) = mock_server(vec![Session::SendAndClose(vec![11, 12])]);
+        let endpoints = vec![Endpoint::parse(&a_url).unwrap(), Endpoint::parse(&b_url).unwrap()];
+
+        let mut connector = FirehoseConnector::new(endpoints, fast_config(true), Arc::new(AtomicBool::new(true)));
+        let (seqs, _) = run_until(&mut connector, 12);
+
+        assert_eq!(seqs, vec![10, 11, 12]);
+        assert_eq!(uri_cursor(&b_uris.recv().unwrap()), Some(10));
+    }
+
+    #[test]
+    fn test_without_failover_reconnects_to_same_endpoint() {
+        let (a_url, a_uris) = mock_server(vec![
+            Session::SendAndClose(vec![1, 2]),
+            Session::SendAndClose(vec![3]),
+        ]);
+        let (b_url, b_uris) = mock_server(vec![Session::SendAndClose(vec![100])]);
+        let endpoints = vec![Endpoint::parse(&a_url).unwrap(), Endpoint::parse(&b_url).unwrap()];
+
+        let mut connector = FirehoseConnector::new(endpoints, fast_config(false), Arc::new(AtomicBool::new(true)));
+        let (seqs, switches) = run_until(&mut connector, 3);
+
+        assert_eq!(seqs, vec![1, 2, 3]);
+        assert!(switches.is_empty());
+        assert_eq!(uri_cursor(&a_uris.recv().unwrap()), None);
+        assert_eq!(uri_cursor(&a_uris.recv().unwrap()), Some(2));
+        assert!(b_uris.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_outdated_cursor_reconnects_from_head() {
+        let (url, uris) = mock_server(vec![Session::OutdatedCursor, Session::SendAndClose(vec![500])]);
+        let mut connector = FirehoseConnector::new(vec![Endpoint::parse(&url).unwrap()], fast_config(false), Arc::new(AtomicBool::new(true)))
+            .with_cursor(Some(7));
+
+        let dir = tempfile::tempdir().unwrap();
+        let gaps = GapLog::open(dir.path()).unwrap();
+        let mut events = Vec::new();
+        connector.run(|event| match event {
+            FirehoseEvent::Info { name, message, .. } => {
+                events.push(format!("info {} {:?}", name, message));
+                Flow::Continue
+            }
+            FirehoseEvent::Gap { endpoint, saved_cursor, resumed_at } => {
+                events.push(format!("gap {} {}", saved_cursor, resumed_at));
+                gaps.record(GapRecord::now(endpoint.host(), saved_cursor, resumed_at)).unwrap();
+                Flow::Continue
+            }
+            FirehoseEvent::Frame { seq, .. } => {
+                events.push(format!("frame {:?}", seq));
+                Flow::Stop
+            }
+            _ => Flow::Continue,
+        });
+
+        // The #info isn't a frame, the reconnect drops the stale cursor, and the first frame
+        // after it is announced as a gap
+        assert_eq!(events, vec!["info OutdatedCursor None".to_string(), "gap 7 500".to_string(), "frame Some(500)".to_string()]);
+        assert_eq!(uri_cursor(&uris.recv().unwrap()), Some(7));
+        assert_eq!(uri_cursor(&uris.recv().unwrap()), None);
+        assert_eq!(connector.cursor(), Some(500));
+
+        let host = Endpoint::parse(&url).unwrap().host();
+        let reopened = GapLog::open(dir.path()).unwrap();
+        assert_eq!(reopened.gaps_for_host(&host).iter().map(|g| (g.saved_cursor, g.new_start_seq)).collect::<Vec<_>>(), vec![(7, 500)]);
+        assert_eq!(reopened.total_gap_span(), 492);
+    }
+
+    #[test]
+    fn test_dropped_endpoints_end_the_run() {
+        // Nothing listens here once the listener is dropped
+        let a_url = format!("ws://{}", TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap());
+        let mut connector = FirehoseConnector::new(vec![Endpoint::parse(&a_url).unwrap()], fast_config(true), Arc::new(AtomicBool::new(true)));
+        let mut failures = 0;
+        connector.run(|event| match event {
+            FirehoseEvent::ConnectFailed { .. } => {
+                failures += 1;
+                Flow::DropEndpoint
+            }
+            _ => Flow::Continue,
+        });
+        assert_eq!(failures, 1);
+        assert!(connector.current_endpoint().is_none());
+    }
+
+    #[test]
+    fn test_reconnect_budget_ends_the_run() {
+        let (url, uris) = mock_server(vec![Session::SendAndClose(vec![1, 2, 3])]);
+        let config = ConnectorConfig { max_reconnects: Some(0), ..fast_config(false) };
+        let mut connector = FirehoseConnector::new(vec![Endpoint::parse(&url).unwrap()], config, Arc::new(AtomicBool::new(true)));
+        let (seqs, _) = run_until(&mut connector, u64::MAX);
+
+        assert_eq!(seqs, vec![1, 2, 3]);
+        assert!(connector.out_of_reconnects());
+        assert_eq!(uris.iter().count(), 1);
+    }
+
+    #[test]
+    #[allow(clippy::result_large_err)] // the connect callback's error type is tungstenite's, not ours
+    fn test_injected_connector_counts_against_the_budget() {
+        let attempts = Arc::new(AtomicU32::new(0));
+        let seen = Arc::clone(&attempts);
+        let config = ConnectorConfig { max_reconnects: Some(2), ..fast_config(false) };
+        let mut connector = FirehoseConnector::new(vec![Endpoint::parse("ws://127.0.0.1:1").unwrap()], config, Arc::new(AtomicBool::new(true)))
+            .with_cursor(Some(9))
+            .with_connect(move |url, cursor| {
+                assert_eq!((url.port(), cursor), (Some(1), Some(9)));
+                seen.fetch_add(1, Ordering::SeqCst);
+                Err(tungstenite::Error::Io(io::Error::new(io::ErrorKind::ConnectionRefused, "mock")))
+            });
+        let mut failures = 0;
+        connector.run(|event| {
+            if let FirehoseEvent::ConnectFailed { .. } = event {
+                failures += 1;
+            }
+            Flow::Continue
+        });
+
+        assert_eq!((attempts.load(Ordering::SeqCst), failures), (3, 3));
+        assert!(connector.out_of_reconnects());
+    }
+
+    /// Producer sends `total` frames and idles until stopped; two verifiers archive each frame after `delay`.
+    /// Returns the report and how many distinct frames made it into the archive.
+    fn run_pipeline(total: u64, delay: Duration, drain_timeout: Duration) -> (did_mmap_cache::ingest::ShutdownReport, u64) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("archive");
+        let archive = Arc::new(MultiShardArchive::new(&root, 2, 50, None).unwrap());
+        let monitor = SovereignMonitor::new();
+        let running = Arc::new(AtomicBool::new(true));
+        let shutdown = Arc::new(PipelineShutdown::new(Arc::clone(&running), drain_timeout)
+            .with_producer_grace(Duration::from_secs(1)));
+        let (tx, rx) = crossbeam_channel::unbounded::<u64>();
+
+        let running_p = Arc::clone(&running);
+        let producer = thread::spawn(move || {
+            for seq in 0..total {
+                tx.send(seq).unwrap();
+            }
+            while running_p.load(Ordering::SeqCst) {
+                thread::sleep(Duration::from_millis(5));
+            }
+        });
+        // Archive seqs are handed out at ingest time, in order, as the ingester does
+        let next_seq = Arc::new(std::sync::Mutex::new(0u64));
+        let verifiers: Vec<_> = (0..2).map(|_| {
+            let (rx, shutdown, archive, next_seq) = (rx.clone(), Arc::clone(&shutdown), Arc::clone(&archive), Arc::clone(&next_seq));
+            thread::spawn(move || {
+                while let Some(frame) = shutdown.recv(&rx) {
+                    thread::sleep(delay);
+                    let mut seq = next_seq.lock().unwrap();
+                    let did = format!("did:plc:user{}", frame % 7);
+                    archive.ingest(*seq, &did, format!("app.bsky.feed.post/{}", frame), frame.to_be_bytes().to_vec());
+                    *seq += 1;
+                }
+            })
+        }).collect();
+
+        // Let part of the backlog through before stopping
+        thread::sleep(Duration::from_millis(50));
+        let report = shutdown.run(&rx, vec![producer], verifiers, Some(&archive), Some(&monitor));
+        assert!(monitor.stopped.load(Ordering::Relaxed));
+        assert!(!monitor.draining.load(Ordering::Relaxed));
+
+        let reader = MultiShardArchive::open_readonly(&root, None).unwrap();
+        let frames: std::collections::HashSet<u64> = (0..total)
+            .filter_map(|seq| reader.get_message_by_seq(seq).ok())
+            .map(|m| u64::from_be_bytes(m.try_into().unwrap()))
+            .collect();
+        (report, frames.len() as u64)
+    }
+
+    #[test]
+    fn test_shutdown_drains_queue() {
+        let (report, archived) = run_pipeline(300, Duration::from_millis(1), Duration::from_secs(30));
+        assert!(!report.timed_out);
+        assert_eq!(report.dropped, 0);
+        assert!(report.drained > 0);
+        assert_eq!(archived, 300);
+    }
+
+    #[test]
+    fn test_shutdown_counts_frames_dropped_at_timeout() {
+        let (report, archived) = run_pipeline(300, Duration::from_millis(10), Duration::from_millis(200));
+        assert!(report.timed_out);
+        assert!(report.drained > 0 && report.dropped > 0);
+        // Every sent frame is either archived or reported as dropped
+        assert_eq!(archived + report.dropped, 300);
+    }
+
+    #[test]
+    fn test_backoff_doubles_and_caps() {
+        let (base, cap) = (Duration::from_secs(5), Duration::from_secs(300));
+        let delays: Vec<u64> = (0..8).map(|n| backoff_delay(base, n, cap).as_secs()).collect();
+        assert_eq!(delays, vec![5, 10, 20, 40, 80, 160, 300, 300]);
+        // No overflow however long a host keeps failing
+        assert_eq!(backoff_delay(base, u32::MAX, cap), cap);
+    }
+}
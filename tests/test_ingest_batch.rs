@@ -0,0 +1,103 @@
+#[cfg(test)]
+mod ingest_batch_tests {
+    use did_mmap_cache::archive::MultiShardArchive;
+    use std::collections::BTreeMap;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    const TOTAL: u64 = 500;
+
+    fn messages() -> Vec<(u64, String, String, Vec<u8>)> {
+        (0..TOTAL)
+            .map(|seq| {
+                let did = format!("did:plc:user{}", (seq * 7) % 13);
+                (seq, did, format!("app.bsky.feed.post/{}", seq), format!("message {} {}", seq, "x".repeat((seq % 40) as usize)).into_bytes())
+            })
+            .collect()
+    }
+
+    /// Relative path -> contents for every file under `root`.
+    fn snapshot(root: &Path) -> BTreeMap<PathBuf, Vec<u8>> {
+        let mut out = BTreeMap::new();
+        let mut stack = vec![root.to_path_buf()];
+        while let Some(dir) = stack.pop() {
+            for entry in fs::read_dir(&dir).unwrap().flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    stack.push(path);
+                } else {
+                    out.insert(path.strip_prefix(root).unwrap().to_path_buf(), fs::read(&path).unwrap());
+                }
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_batch_ingest_matches_sequential_ingest() {
+        let dir = tempdir().unwrap();
+        let (seq_root, batch_root) = (dir.path().join("sequential"), dir.path().join("batched"));
+        let msgs = messages();
+
+        let archive = MultiShardArchive::new(&seq_root, 4, 40, None).unwrap();
+        for (seq, did, path, data) in &msgs {
+            archive.ingest(*seq, did, path.clone(), data.clone());
+        }
+        archive.shutdown();
+
+        // Uneven batch sizes, so segment boundaries fall both inside and between batches
+        let archive = MultiShardArchive::new(&batch_root, 4, 40, None).unwrap();
+        let mut rest = &msgs[..];
+        for size in [1, 17, 64, 3, 200].iter().cycle() {
+            if rest.is_empty() {
+                break;
+            }
+            let (chunk, tail) = rest.split_at((*size).min(rest.len()));
+            let batch: Vec<(u64, &str, String, Vec<u8>)> =
+                chunk.iter().map(|(seq, did, path, data)| (*seq, did.as_str(), path.clone(), data.clone())).collect();
+            archive.ingest_batch(&batch);
+            rest = tail;
+        }
+        archive.ingest_batch(&[]);
+        archive.shutdown();
+
+        let (expected, actual) = (snapshot(&seq_root), snapshot(&batch_root));
+        // Several segments per shard, not just the final flush
+        assert!(expected.keys().filter(|p| p.starts_with("shard_0") && p.extension().is_some_and(|x| x == "bin")).count() > 1);
+        assert_eq!(expected.keys().collect::<Vec<_>>(), actual.keys().collect::<Vec<_>>());
+        for (path, data) in &expected {
+            assert!(actual[path] == *data, "{} differs", path.display());
+        }
+
+        let reader = MultiShardArchive::open_readonly(&batch_root, None).unwrap();
+        for (seq, _, _, data) in &msgs {
+            assert_eq!(&reader.get_message_by_seq(*seq).unwrap(), data);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_async_ingest_reads_back_blocking() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("archive");
+        let msgs = messages();
+        let (singles, batched) = msgs.split_at(msgs.len() / 2);
+
+        let archive = Arc::new(MultiShardArchive::new(&root, 4, 40, None).unwrap());
+        for (seq, did, path, data) in singles {
+            Arc::clone(&archive).ingest_async(*seq, did.clone(), path.clone(), data.clone()).await.unwrap();
+        }
+        for chunk in batched.chunks(64) {
+            Arc::clone(&archive).ingest_batch_async(chunk.to_vec()).await.unwrap();
+        }
+        // shutdown joins the persister thread, so it goes to the blocking pool too
+        let writer = Arc::clone(&archive);
+        tokio::task::spawn_blocking(move || writer.shutdown()).await.unwrap();
+
+        let reader = MultiShardArchive::open_readonly(&root, None).unwrap();
+        for (seq, _, _, data) in &msgs {
+            assert_eq!(&reader.get_message_by_seq(*seq).unwrap(), data);
+        }
+    }
+}
@@ -0,0 +1,60 @@
+#[cfg(test)]
+mod iter_range_tests {
+    use did_mmap_cache::archive::{ArchiveError, MultiShardArchive};
+    use tempfile::tempdir;
+
+    /// Seqs 0..3000 over 37 DIDs, so every one of 8 shards holds interleaved seqs, with
+    /// every seventh seq left out as a gap.
+    fn stored_seqs() -> Vec<u64> {
+        (0..3_000).filter(|seq| seq % 7 != 3).collect()
+    }
+
+    fn build(dir: &std::path::Path) -> MultiShardArchive {
+        let archive = MultiShardArchive::new(dir, 8, 50, None).unwrap();
+        for seq in stored_seqs() {
+            let did = format!("did:plc:user{}", seq % 37);
+            archive.ingest(seq, &did, format!("app.bsky.feed.post/{}", seq), format!("message {}", seq).into_bytes());
+        }
+        archive.shutdown();
+        MultiShardArchive::open_readonly(dir, None).unwrap()
+    }
+
+    #[test]
+    fn test_every_stored_seq_comes_once_in_order() {
+        let dir = tempdir().unwrap();
+        let archive = build(dir.path());
+
+        let yielded: Vec<_> = archive.iter_range(0, u64::MAX).collect();
+        let seqs: Vec<u64> = yielded.iter().map(|(seq, _)| *seq).collect();
+        assert_eq!(seqs, stored_seqs());
+
+        let mut shards: Vec<usize> = yielded.iter().map(|(_, at)| at.shard).collect();
+        shards.sort_unstable();
+        shards.dedup();
+        assert_eq!(shards, (0..8).collect::<Vec<_>>());
+        for (seq, at) in &yielded {
+            assert_eq!(at.segment_start + at.index, *seq);
+            assert_eq!(archive.read_at(*seq, at).unwrap(), format!("message {}", seq).into_bytes());
+        }
+        assert_eq!(archive.persisted_through().map(|through| through >= 2_900), Some(true));
+    }
+
+    #[test]
+    fn test_sub_ranges_and_tombstones() {
+        let dir = tempdir().unwrap();
+        let archive = build(dir.path());
+
+        let seqs: Vec<u64> = archive.iter_range(333, 1_501).map(|(seq, _)| seq).collect();
+        let expected: Vec<u64> = stored_seqs().into_iter().filter(|seq| (333..1_501).contains(seq)).collect();
+        assert_eq!(seqs, expected);
+        assert_eq!(archive.iter_range(3, 4).count(), 0);
+        assert_eq!(archive.iter_range(10, 10).count(), 0);
+        assert_eq!(archive.iter_range(5_000, 6_000).count(), 0);
+
+        // Tombstoned seqs are still yielded, but reading them is refused
+        archive.mark_deleted(100).unwrap();
+        let (seq, at) = archive.iter_range(100, 101).next().unwrap();
+        assert_eq!(seq, 100);
+        assert!(matches!(archive.read_at(seq, &at), Err(ArchiveError::Tombstoned { seq: 100 })));
+    }
+}
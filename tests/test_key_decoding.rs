@@ -0,0 +1,99 @@
+#[cfg(test)]
+mod key_decoding_tests {
+    use did_mmap_cache::resolver::{compress_sec1, decode_key_string, did_key_to_raw_pubkey, multibase_to_raw_pubkey};
+
+    // Test vectors from the did:key method spec
+    const SECP256K1_DID_KEY: &str = "did:key:zQ3shokFTS3brHcDQrn82RUDfCZESWL1ZdCEJwekUDPQiYBme";
+    const P256_DID_KEY: &str = "did:key:zDnaerDaTF5BXEavCrfRZEk316dpbLsfPDZ3WJ5hRTPFU2169";
+
+    fn encode(prefix: [u8; 2], compressed: &[u8]) -> String {
+        let mut raw = prefix.to_vec();
+        raw.extend_from_slice(compressed);
+        format!("z{}", bs58::encode(raw).into_string())
+    }
+
+    #[test]
+    fn test_spec_vectors_classified_by_multicodec() {
+        let (pk, kt) = did_key_to_raw_pubkey(SECP256K1_DID_KEY).expect("secp256k1 vector");
+        assert_eq!(kt, 1);
+        assert!(k256::ecdsa::VerifyingKey::from_sec1_bytes(&pk).is_ok());
+
+        let (pk, kt) = did_key_to_raw_pubkey(P256_DID_KEY).expect("p256 vector");
+        assert_eq!(kt, 2);
+        assert!(p256::ecdsa::VerifyingKey::from_sec1_bytes(&pk).is_ok());
+
+        // PLC ops carry either the did:key form or the bare multibase form
+        assert_eq!(decode_key_string(SECP256K1_DID_KEY).unwrap().1, 1);
+        assert_eq!(decode_key_string(&P256_DID_KEY[8..]).unwrap().1, 2);
+    }
+
+    #[test]
+    fn test_generated_keys_round_trip() {
+        use rand::rngs::OsRng;
+
+        for _ in 0..16 {
+            let k = k256::ecdsa::SigningKey::random(&mut OsRng);
+            let compressed = k.verifying_key().to_encoded_point(true);
+            let mb = encode([0xe7, 0x01], compressed.as_bytes());
+            let (pk, kt) = multibase_to_raw_pubkey(&mb).unwrap();
+            assert_eq!((&pk[..], kt), (compressed.as_bytes(), 1), "{}", mb);
+
+            let p = p256::ecdsa::SigningKey::random(&mut OsRng);
+            let compressed = p.verifying_key().to_encoded_point(true);
+            let mb = encode([0x80, 0x24], compressed.as_bytes());
+            let (pk, kt) = multibase_to_raw_pubkey(&mb).unwrap();
+            assert_eq!((&pk[..], kt), (compressed.as_bytes(), 2), "{}", mb);
+        }
+    }
+
+    #[test]
+    fn test_unsupported_keys_rejected() {
+        // Ed25519 (0xed 0x01) is not something the cache can store
+        let ed = encode([0xed, 0x01], &[7u8; 32]);
+        assert!(decode_key_string(&ed).is_none());
+        assert!(decode_key_string("not-a-key").is_none());
+        assert!(decode_key_string("did:key:zzz").is_none());
+    }
+
+    #[test]
+    fn test_uncompressed_secp256k1_generator_compresses() {
+        // The secp256k1 generator point G; its Y is even
+        let uncompressed = hex::decode(concat!(
+            "04",
+            "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+            "483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8",
+        )).unwrap();
+        let expected = hex::decode("0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798").unwrap();
+        assert_eq!(&compress_sec1(&uncompressed, 1).unwrap()[..], &expected[..]);
+        assert_eq!(&compress_sec1(&expected, 1).unwrap()[..], &expected[..]);
+
+        // Published uncompressed under the secp256k1 multicodec
+        let (pk, kt) = decode_key_string(&encode([0xe7, 0x01], &uncompressed)).unwrap();
+        assert_eq!((&pk[..], kt), (&expected[..], 1));
+
+        // G is not on P-256, and a flipped Y bit is on neither curve
+        assert!(compress_sec1(&uncompressed, 2).is_none());
+        let mut garbled = uncompressed.clone();
+        garbled[64] ^= 1;
+        assert!(compress_sec1(&garbled, 1).is_none());
+        assert!(compress_sec1(&uncompressed[..64], 1).is_none());
+    }
+
+    #[test]
+    fn test_uncompressed_generated_keys_normalize() {
+        use rand::rngs::OsRng;
+
+        for _ in 0..16 {
+            let k = k256::ecdsa::SigningKey::random(&mut OsRng);
+            let point = k.verifying_key().to_encoded_point(false);
+            let (pk, kt) = multibase_to_raw_pubkey(&encode([0xe7, 0x01], point.as_bytes())).unwrap();
+            assert_eq!((&pk[..], kt), (k.verifying_key().to_encoded_point(true).as_bytes(), 1));
+
+            let p = p256::ecdsa::SigningKey::random(&mut OsRng);
+            let point = p.verifying_key().to_encoded_point(false);
+            let (pk, kt) = multibase_to_raw_pubkey(&encode([0x80, 0x24], point.as_bytes())).unwrap();
+            assert_eq!((&pk[..], kt), (p.verifying_key().to_encoded_point(true).as_bytes(), 2));
+            assert!(p256::ecdsa::VerifyingKey::from_sec1_bytes(&pk).is_ok());
+        }
+    }
+}
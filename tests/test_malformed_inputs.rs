@@ -0,0 +1,190 @@
+#[cfg(test)]
+mod malformed_input_tests {
+    use did_mmap_cache::archive::{ArchiveError, DecompressionLimitExceeded, SegmentedArchive};
+    use did_mmap_cache::mmap_cache_entry::parse_commit_block;
+    use did_mmap_cache::mst::car::CarStore;
+    use did_mmap_cache::mst::MstNode;
+    use did_mmap_cache::parser::core::{parse_cbor_bytes, parse_cbor_len, skip_cbor_value, ParseError};
+    use std::fs;
+    use std::io::ErrorKind;
+    use tempfile::tempdir;
+
+    /// Major type + addl 27 + an all-ones 64-bit length
+    fn huge_head(major: u8) -> Vec<u8> {
+        let mut v = vec![(major << 5) | 27];
+        v.extend_from_slice(&[0xff; 8]);
+        v
+    }
+
+    fn idx_record(bin_off: u64, c_len: u32, inner_off: u32, m_len: u32) -> Vec<u8> {
+        let mut r = Vec::with_capacity(28);
+        r.extend_from_slice(&bin_off.to_le_bytes());
+        r.extend_from_slice(&c_len.to_le_bytes());
+        r.extend_from_slice(&inner_off.to_le_bytes());
+        r.extend_from_slice(&m_len.to_le_bytes());
+        r.extend_from_slice(&0u64.to_le_bytes());
+        r
+    }
+
+    fn write_segment(dir: &std::path::Path, start_seq: u64, bin: &[u8], idx: &[u8]) {
+        fs::write(dir.join(format!("s0_{}.bin", start_seq)), bin).unwrap();
+        fs::write(dir.join(format!("s0_{}.idx", start_seq)), idx).unwrap();
+    }
+
+    #[test]
+    fn test_cbor_huge_declared_lengths() {
+        let mut bytes = huge_head(2);
+        bytes.extend_from_slice(b"abc");
+        assert!(matches!(parse_cbor_bytes(&bytes, 0), Err(ParseError::UnexpectedEof { .. })));
+
+        // Arrays and maps claiming 2^64-1 items are rejected before iterating
+        assert!(skip_cbor_value(&huge_head(4), 0).is_err());
+        assert!(skip_cbor_value(&huge_head(5), 0).is_err());
+
+        // A 32-bit length header cut short
+        assert!(matches!(parse_cbor_len(&[0x5a, 0x00, 0x01], 0), Err(ParseError::UnexpectedEof { .. })));
+    }
+
+    #[test]
+    fn test_car_block_lengths() {
+        // Header of 1 byte, then a block whose varint length is ~2^63
+        let mut car = vec![0x01, 0xa0];
+        car.extend_from_slice(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f]);
+        car.extend_from_slice(&[0u8; 16]);
+        assert!(CarStore::new(&car).blocks.is_empty());
+
+        // Header length varint pointing far past the end
+        let car = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f, 0x00];
+        assert!(CarStore::new(&car).blocks.is_empty());
+
+        // 5-byte block whose CID claims a 32-byte multihash
+        let car = [0x01, 0xa0, 0x05, 0x01, 0x71, 0x12, 0x20, 0xaa];
+        assert!(CarStore::new(&car).blocks.is_empty());
+    }
+
+    #[test]
+    fn test_mst_and_commit_huge_counts() {
+        assert!(MstNode::from_bytes(&huge_head(5)).is_err());
+        assert!(MstNode::get_root_from_commit(&huge_head(5)).is_none());
+
+        // { "e": [ <2^32-1 entries> ] }
+        let node = [0xa1, 0x61, b'e', 0x9a, 0xff, 0xff, 0xff, 0xff, 0xa0];
+        assert!(MstNode::from_bytes(&node).is_err());
+
+        // Commit block with a text value claiming 2^64-1 bytes
+        let mut commit = vec![0xa1, 0x63];
+        commit.extend_from_slice(b"did");
+        commit.extend_from_slice(&huge_head(3));
+        assert!(parse_commit_block(&commit).did.is_none());
+    }
+
+    #[test]
+    fn test_short_idx_file() {
+        let dir = tempdir().unwrap();
+        write_segment(dir.path(), 100, b"", &[0u8; 10]);
+
+        let archive = SegmentedArchive::open_directory(dir.path(), None, None).unwrap();
+        assert!(archive.get_message_by_seq(100, None).is_err());
+        assert!(archive.get_raw_cluster_at_seq(100).is_err());
+        assert_eq!(archive.verify_integrity_at_seq(100, None).unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(archive.find_seq_by_path_hash(0), None);
+        assert_eq!(archive.max_seq(), Some(100));
+    }
+
+    #[test]
+    fn test_idx_offsets_out_of_range() {
+        let dir = tempdir().unwrap();
+        let cluster = zstd::encode_all(&b"hello world"[..], 3).unwrap();
+
+        let mut idx = vec![0u8; 32];
+        // bin_off + c_len overflows u64
+        idx.extend(idx_record(u64::MAX, 16, 0, 5));
+        // Valid cluster, message range past the decompressed end
+        idx.extend(idx_record(0, cluster.len() as u32, u32::MAX, u32::MAX));
+        // Valid cluster, in-range message
+        idx.extend(idx_record(0, cluster.len() as u32, 6, 5));
+        // Trailing partial record
+        idx.extend_from_slice(&[0xff; 12]);
+        write_segment(dir.path(), 0, &cluster, &idx);
+
+        let archive = SegmentedArchive::open_directory(dir.path(), None, None).unwrap();
+        assert!(matches!(archive.get_message_by_seq(0, None), Err(ArchiveError::Corrupt { .. })));
+        assert!(matches!(archive.get_raw_cluster_at_seq(0), Err(ArchiveError::Corrupt { .. })));
+
+        let err = archive.get_message_by_seq(1, None).unwrap_err();
+        assert!(matches!(err, ArchiveError::Corrupt { .. }));
+        assert_eq!(std::io::Error::from(err).kind(), ErrorKind::InvalidData);
+
+        assert_eq!(archive.get_message_by_seq(2, None).unwrap(), b"world");
+        // The first cluster starts at bin_off 0 and must still be reachable
+        assert!(archive.get_raw_cluster_at_seq(2).is_ok());
+
+        // The partial record is not addressable
+        assert!(archive.get_message_by_seq(3, None).is_err());
+        assert_eq!(archive.max_seq(), Some(2));
+    }
+
+    #[test]
+    fn test_oversized_message_is_refused_before_decompressing() {
+        let dir = tempdir().unwrap();
+        // Not zstd: any attempt to decode it would fail with a different error
+        let bin = vec![0xaa; 64];
+        let limit = 1 << 20;
+
+        let mut idx = vec![0u8; 32];
+        idx.extend(idx_record(0, bin.len() as u32, 0, limit as u32 + 1));
+        idx.extend(idx_record(0, bin.len() as u32, 16, limit as u32));
+        write_segment(dir.path(), 0, &bin, &idx);
+
+        let archive = SegmentedArchive::open_directory_with_limit(dir.path(), None, None, limit).unwrap();
+        let ArchiveError::Io(err) = archive.get_message_by_seq(0, None).unwrap_err() else { panic!("expected a read error") };
+        let inner = err.get_ref().and_then(|e| e.downcast_ref::<DecompressionLimitExceeded>());
+        assert_eq!(inner, Some(&DecompressionLimitExceeded { limit }));
+
+        // Within the cap on its own, the record is read and the bad cluster is what fails
+        let ArchiveError::Io(err) = archive.get_message_by_seq(1, None).unwrap_err() else { panic!("expected a read error") };
+        assert!(!err.get_ref().is_some_and(|e| e.is::<DecompressionLimitExceeded>()));
+    }
+
+    /// 256MB of zeros streamed through the encoder, which compresses to a few KB
+    fn zstd_bomb() -> Vec<u8> {
+        use std::io::Write;
+        let mut enc = zstd::Encoder::new(Vec::new(), 1).unwrap();
+        let chunk = vec![0u8; 1 << 20];
+        for _ in 0..256 {
+            enc.write_all(&chunk).unwrap();
+        }
+        enc.finish().unwrap()
+    }
+
+    #[test]
+    fn test_decompression_limit() {
+        let dir = tempdir().unwrap();
+        let bomb = zstd_bomb();
+        assert!(bomb.len() < 64 * 1024);
+
+        let mut idx = vec![0u8; 32];
+        idx.extend(idx_record(0, bomb.len() as u32, 0, 5));
+        idx.extend(idx_record(0, bomb.len() as u32, 5, 5));
+        // A message claiming more than the cap on its own
+        idx.extend(idx_record(0, bomb.len() as u32, 0, 2 << 20));
+        write_segment(dir.path(), 0, &bomb, &idx);
+
+        let limit = 1 << 20;
+        let archive = SegmentedArchive::open_directory_with_limit(dir.path(), None, None, limit).unwrap();
+        assert_eq!(archive.max_decompressed_cluster_bytes(), limit);
+
+        let ArchiveError::Io(err) = archive.get_message_by_seq(0, None).unwrap_err() else { panic!("expected a read error") };
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        let inner = err.get_ref().and_then(|e| e.downcast_ref::<DecompressionLimitExceeded>());
+        assert_eq!(inner, Some(&DecompressionLimitExceeded { limit }));
+
+        let err = std::io::Error::from(archive.get_message_by_seq(2, None).unwrap_err());
+        assert!(err.get_ref().is_some_and(|e| e.is::<DecompressionLimitExceeded>()));
+
+        // The tombstone-filtering path decompresses too
+        archive.mark_deleted(1).unwrap();
+        let ArchiveError::Io(err) = archive.get_raw_cluster_at_seq(0).unwrap_err() else { panic!("expected a read error") };
+        assert!(err.get_ref().is_some_and(|e| e.is::<DecompressionLimitExceeded>()));
+    }
+}
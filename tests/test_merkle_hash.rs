@@ -0,0 +1,136 @@
+#[cfg(test)]
+mod merkle_hash_tests {
+    use did_mmap_cache::archive::sync::{SyncClient, SyncServer};
+    use did_mmap_cache::archive::{MultiShardArchive, SegmentedArchive};
+    use did_mmap_cache::mst::builder::{Blake3Hasher, MerkleAlgorithm, MerkleTree, Sha256Hasher};
+    use std::fs;
+    use std::io::ErrorKind;
+    use std::path::{Path, PathBuf};
+    use tempfile::tempdir;
+
+    const LEAVES: [&[u8]; 5] = [b"alpha", b"beta", b"gamma", b"delta", b"epsilon"];
+
+    /// One shard, seqs 0..20 in a single segment, with roots built by `alg`.
+    fn write_archive(root: &Path, alg: MerkleAlgorithm) {
+        let archive = MultiShardArchive::new(root, 1, 50, None).unwrap();
+        archive.set_hash_algorithm(alg);
+        for seq in 0..20u64 {
+            archive.ingest(seq, &format!("did:plc:user{}", seq % 3), format!("app.bsky.feed.post/{}", seq), format!("message {}", seq).into_bytes());
+        }
+        archive.shutdown();
+    }
+
+    fn segment_file(root: &Path, ext: &str) -> PathBuf {
+        root.join("shard_0").join(format!("s0_0.{}", ext))
+    }
+
+    fn verify_shard_0(root: &Path) -> std::io::Result<bool> {
+        SegmentedArchive::open_directory(root.join("shard_0"), None, None)?.verify_integrity_at_seq(0, None)
+    }
+
+    fn stored_root(root: &Path) -> Vec<u8> {
+        fs::read(segment_file(root, "idx")).unwrap()[..32].to_vec()
+    }
+
+    /// The algorithm id byte of the segment's `.idx` header, after the root and an 8-byte magic.
+    fn header_alg_id(root: &Path) -> u8 {
+        fs::read(segment_file(root, "idx")).unwrap()[40]
+    }
+
+    #[test]
+    fn test_hashers_give_distinct_self_consistent_roots() {
+        let mut blake = MerkleTree::new();
+        let mut sha = MerkleTree::<Sha256Hasher>::default();
+        for leaf in LEAVES {
+            blake.push(leaf);
+            sha.push(leaf);
+        }
+        assert_ne!(blake.root(), sha.root());
+        assert_eq!(blake.root(), MerkleAlgorithm::Blake3.root_of(LEAVES));
+        assert_eq!(sha.root(), MerkleAlgorithm::Sha256.root_of(LEAVES));
+        assert_eq!(MerkleTree::<Blake3Hasher>::default().root(), *blake3::hash(&[]).as_bytes());
+
+        // A one-leaf tree is just the leaf hash, so it matches the plain digest other tools compute
+        let sha_abc = hex::decode("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad").unwrap();
+        assert_eq!(MerkleAlgorithm::Sha256.root_of([b"abc"]).to_vec(), sha_abc);
+
+        // Order matters for both
+        let reversed: Vec<&[u8]> = LEAVES.iter().rev().copied().collect();
+        for alg in [MerkleAlgorithm::Blake3, MerkleAlgorithm::Sha256] {
+            assert_ne!(alg.root_of(&reversed), alg.root_of(LEAVES), "{}", alg);
+            assert_eq!(MerkleAlgorithm::from_id(alg.id()), Some(alg));
+            assert_eq!(alg.name().parse::<MerkleAlgorithm>().unwrap(), alg);
+        }
+        assert_eq!(MerkleAlgorithm::from_id(7), None);
+        assert!("md5".parse::<MerkleAlgorithm>().is_err());
+    }
+
+    #[test]
+    fn test_segments_verify_under_their_own_algorithm() {
+        let dir = tempdir().unwrap();
+        let (blake, sha) = (dir.path().join("blake3"), dir.path().join("sha256"));
+        write_archive(&blake, MerkleAlgorithm::Blake3);
+        write_archive(&sha, MerkleAlgorithm::Sha256);
+
+        // Same data, different roots; the id is in each .idx header, with no sidecar
+        assert_ne!(stored_root(&blake), stored_root(&sha));
+        assert_eq!(header_alg_id(&blake), MerkleAlgorithm::Blake3.id());
+        assert_eq!(header_alg_id(&sha), MerkleAlgorithm::Sha256.id());
+        assert!(!segment_file(&blake, "hashalg").exists());
+        assert!(!segment_file(&sha, "hashalg").exists());
+
+        for root in [&blake, &sha] {
+            assert!(verify_shard_0(root).unwrap(), "{}", root.display());
+            assert_eq!(MultiShardArchive::open_readonly(root, None).unwrap().get_message_by_seq(7).unwrap(), b"message 7");
+        }
+    }
+
+    #[test]
+    fn test_missing_or_unknown_algorithm_id() {
+        let dir = tempdir().unwrap();
+        write_archive(dir.path(), MerkleAlgorithm::Sha256);
+
+        let idx_path = segment_file(dir.path(), "idx");
+        let idx = fs::read(&idx_path).unwrap();
+
+        // The header wins over a stale sidecar
+        fs::write(segment_file(dir.path(), "hashalg"), [MerkleAlgorithm::Blake3.id()]).unwrap();
+        assert!(verify_shard_0(dir.path()).unwrap());
+
+        let mut unknown = idx.clone();
+        unknown[40] = 0xee;
+        fs::write(&idx_path, &unknown).unwrap();
+        let err = verify_shard_0(dir.path()).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        assert!(err.to_string().contains("238"));
+
+        // An older .idx is the bare root, and its id is in the sidecar
+        let legacy = [&idx[..32], &idx[48..]].concat();
+        fs::write(&idx_path, &legacy).unwrap();
+        fs::write(segment_file(dir.path(), "hashalg"), [MerkleAlgorithm::Sha256.id()]).unwrap();
+        assert!(verify_shard_0(dir.path()).unwrap());
+
+        // Without the sidecar it reads as legacy blake3, which its sha256 root doesn't match
+        fs::remove_file(segment_file(dir.path(), "hashalg")).unwrap();
+        assert!(!verify_shard_0(dir.path()).unwrap());
+        assert_eq!(MultiShardArchive::open_readonly(dir.path(), None).unwrap().get_message_by_seq(3).unwrap(), b"message 3");
+    }
+
+    #[test]
+    fn test_sync_carries_algorithm_id() {
+        let dir = tempdir().unwrap();
+        let (source, mirror) = (dir.path().join("source"), dir.path().join("mirror"));
+        write_archive(&source, MerkleAlgorithm::Sha256);
+        let server = SyncServer::bind(&source, "127.0.0.1:0").unwrap();
+        let url = format!("http://{}", server.local_addr().unwrap());
+        server.spawn();
+        let client = SyncClient::new(&url).unwrap();
+
+        let report = client.pull(&mirror).unwrap();
+        assert_eq!((report.downloaded, report.failed.len()), (1, 0), "{:?}", report.failed);
+        assert_eq!(header_alg_id(&mirror), MerkleAlgorithm::Sha256.id());
+        assert!(!segment_file(&mirror, "hashalg").exists());
+        assert!(verify_shard_0(&mirror).unwrap());
+        assert_eq!(client.pull(&mirror).unwrap().up_to_date, 1);
+    }
+}
@@ -0,0 +1,128 @@
+#[cfg(test)]
+mod message_hash_tests {
+    use did_mmap_cache::archive::{MultiShardArchive, SegmentedArchive};
+    use std::fs::{self, OpenOptions};
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    const DID: &str = "did:plc:alice";
+
+    fn frame(n: u64) -> Vec<u8> {
+        format!("frame {}", n).into_bytes()
+    }
+
+    fn hash(data: &[u8]) -> [u8; 32] {
+        *blake3::hash(data).as_bytes()
+    }
+
+    fn ingest(archive: &MultiShardArchive, seq: u64, data: Vec<u8>) {
+        archive.ingest(seq, DID, format!("app.bsky.feed.post/{}", seq), data);
+    }
+
+    fn sidecar(root: &Path, start_seq: u64) -> std::path::PathBuf {
+        root.join("shard_0").join(format!("s0_{}.mhash", start_seq))
+    }
+
+    #[test]
+    fn test_duplicates_skipped_across_flush_boundary() {
+        let dir = tempdir().unwrap();
+        // Two messages per segment
+        let archive = MultiShardArchive::new(dir.path(), 1, 2, None).unwrap();
+        archive.enable_dedup(4).unwrap();
+        ingest(&archive, 1, frame(1));
+        ingest(&archive, 2, frame(2));
+        ingest(&archive, 3, frame(1)); // in the segment just handed to the persister
+        ingest(&archive, 4, frame(3));
+        ingest(&archive, 5, frame(3)); // still pending
+        ingest(&archive, 6, frame(4));
+        assert_eq!(archive.duplicates_skipped(), 2);
+        archive.shutdown();
+
+        let reader = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        assert_eq!(reader.contains_hash(&hash(&frame(1))), Some(1));
+        assert_eq!(reader.contains_hash(&hash(&frame(3))), Some(4));
+        assert_eq!(reader.contains_hash(&hash(b"never archived")), None);
+        assert!(reader.get_message_by_seq(3).is_err());
+        assert!(reader.get_message_by_seq(5).is_err());
+        assert_eq!(reader.get_message_by_seq(6).unwrap(), frame(4));
+    }
+
+    #[test]
+    fn test_duplicates_skipped_across_restart() {
+        let dir = tempdir().unwrap();
+        {
+            let archive = MultiShardArchive::new(dir.path(), 1, 2, None).unwrap();
+            archive.enable_dedup(4).unwrap();
+            for seq in 1..=3 {
+                ingest(&archive, seq, frame(seq));
+            }
+            archive.shutdown();
+        }
+
+        let archive = MultiShardArchive::new(dir.path(), 1, 2, None).unwrap();
+        archive.enable_dedup(4).unwrap();
+        ingest(&archive, 10, frame(1)); // in the first, full segment
+        ingest(&archive, 11, frame(3)); // in the partial segment shutdown flushed
+        ingest(&archive, 12, frame(5));
+        assert_eq!(archive.duplicates_skipped(), 2);
+        archive.shutdown();
+
+        let reader = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        assert_eq!(reader.contains_hash(&hash(&frame(5))), Some(12));
+        assert_eq!(reader.contains_hash(&hash(&frame(3))), Some(3));
+        assert!(reader.get_message_by_seq(10).is_err());
+        assert_eq!(reader.get_message_by_seq(12).unwrap(), frame(5));
+    }
+
+    #[test]
+    fn test_window_only_covers_recent_segments() {
+        let dir = tempdir().unwrap();
+        let archive = MultiShardArchive::new(dir.path(), 1, 2, None).unwrap();
+        archive.enable_dedup(1).unwrap();
+        for seq in 1..=4 {
+            ingest(&archive, seq, frame(seq));
+        }
+        // [1, 2] has left the one-segment window, [3, 4] hasn't
+        ingest(&archive, 5, frame(1));
+        ingest(&archive, 6, frame(3));
+        assert_eq!(archive.duplicates_skipped(), 1);
+        archive.shutdown();
+
+        let reader = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        assert_eq!(reader.get_message_by_seq(5).unwrap(), frame(1));
+        // Newest segment first
+        assert_eq!(reader.contains_hash(&hash(&frame(1))), Some(5));
+    }
+
+    #[test]
+    fn test_sidecar_written_only_with_hashes_on() {
+        let dir = tempdir().unwrap();
+        let plain = dir.path().join("plain");
+        let archive = MultiShardArchive::new(&plain, 1, 10, None).unwrap();
+        ingest(&archive, 1, frame(1));
+        archive.shutdown();
+        assert!(!sidecar(&plain, 1).exists());
+        assert_eq!(MultiShardArchive::open_readonly(&plain, None).unwrap().contains_hash(&hash(&frame(1))), None);
+
+        // Hashes without dedup: duplicates are stored, and the lowest seq answers
+        let hashed = dir.path().join("hashed");
+        let archive = MultiShardArchive::new(&hashed, 1, 10, None).unwrap();
+        archive.set_message_hashes(true);
+        for seq in 1..=3 {
+            ingest(&archive, seq, frame(seq % 2));
+        }
+        assert_eq!(archive.duplicates_skipped(), 0);
+        archive.shutdown();
+        assert_eq!(fs::metadata(sidecar(&hashed, 1)).unwrap().len(), 3 * 40);
+        let shard = SegmentedArchive::open_directory(hashed.join("shard_0"), None, None).unwrap();
+        assert_eq!(shard.contains_hash(&hash(&frame(1))), Some(1));
+        assert_eq!(shard.contains_hash(&hash(&frame(0))), Some(2));
+        assert_eq!(shard.recent_message_hashes(5).len(), 1);
+
+        // A torn sidecar is ignored rather than misread
+        OpenOptions::new().write(true).open(sidecar(&hashed, 1)).unwrap().set_len(39).unwrap();
+        let shard = SegmentedArchive::open_directory(hashed.join("shard_0"), None, None).unwrap();
+        assert_eq!(shard.contains_hash(&hash(&frame(1))), None);
+        assert_eq!(shard.get_message_by_seq(1, None).unwrap(), frame(1));
+    }
+}
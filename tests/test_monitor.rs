@@ -0,0 +1,263 @@
+#[cfg(test)]
+mod monitor_tests {
+    use did_mmap_cache::monitor::{
+        ArrivalOutcome, ArrivalTracker, ErrorType, Histogram, SovereignMonitor, ARRIVAL_TICK, ARRIVAL_WHEEL_BUCKETS, FAILURE_SAMPLE_CAP,
+    };
+    use std::sync::atomic::Ordering;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_pds_stats_rate_and_eviction() {
+        let monitor = SovereignMonitor::new();
+        for seq in 1..=10 {
+            monitor.record_pds_message("busy.pds.example", Some(seq));
+        }
+        monitor.record_pds_message("busy.pds.example", None); // malformed frame keeps the cursor
+        monitor.record_pds_message("quiet.pds.example", Some(7));
+
+        {
+            let busy = monitor.pds_stats.get("busy.pds.example").unwrap();
+            assert_eq!(busy.messages, 11);
+            assert_eq!(busy.cursor, Some(10));
+        }
+
+        std::thread::sleep(Duration::from_millis(20));
+        monitor.refresh_pds_stats(Duration::from_secs(60));
+        let busy_rate = monitor.pds_stats.get("busy.pds.example").unwrap().rate;
+        let quiet_rate = monitor.pds_stats.get("quiet.pds.example").unwrap().rate;
+        assert!(busy_rate > quiet_rate && quiet_rate > 0.0);
+
+        // Only the host that keeps talking survives a short idle threshold
+        monitor.record_pds_message("busy.pds.example", Some(11));
+        monitor.refresh_pds_stats(Duration::from_millis(10));
+        assert!(monitor.pds_stats.contains_key("busy.pds.example"));
+        assert!(!monitor.pds_stats.contains_key("quiet.pds.example"));
+    }
+
+    #[test]
+    fn test_pds_stats_count_every_message_from_new_hosts() {
+        let monitor = std::sync::Arc::new(SovereignMonitor::new());
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let monitor = monitor.clone();
+                std::thread::spawn(move || {
+                    for host in 0..200 {
+                        monitor.record_pds_message(&format!("pds{}.example", host), Some(host));
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert_eq!(monitor.pds_stats.len(), 200);
+        assert!(monitor.pds_stats.iter().all(|stats| stats.messages == 8));
+    }
+
+    #[test]
+    fn test_report_samples_failing_dids() {
+        let monitor = SovereignMonitor::new();
+        monitor.record_event("did:plc:good", true, None, Some(1));
+        monitor.record_event("did:plc:good2", true, None, Some(2));
+        monitor.record_high_s();
+        monitor.record_event("did:plc:forged", false, Some(ErrorType::InvalidSignature), Some(1));
+        monitor.record_event("did:plc:forged", false, Some(ErrorType::InvalidSignature), Some(1));
+        for i in 0..FAILURE_SAMPLE_CAP + 20 {
+            monitor.record_event(&format!("did:plc:unknown{}", i), false, Some(ErrorType::MissingKey), None);
+        }
+        monitor.record_malformed();
+
+        let report = monitor.report();
+        assert_eq!(report.total, 5 + FAILURE_SAMPLE_CAP as u64 + 20);
+        assert_eq!((report.verified, report.secp256k1, report.p256), (2, 1, 1));
+        assert_eq!(report.invalid_sig, 2);
+        assert_eq!(report.invalid_sig_dids, vec!["did:plc:forged".to_string()]);
+        assert_eq!(report.missing_key, FAILURE_SAMPLE_CAP as u64 + 20);
+        assert_eq!(report.missing_key_dids.len(), FAILURE_SAMPLE_CAP);
+        assert_eq!(report.missing_key_dids[0], "did:plc:unknown0");
+        assert_eq!(report.malformed, 1);
+        // High-S commits are also counted as verified
+        assert_eq!(report.high_s, 1);
+
+        let json: serde_json::Value = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["invalid_sig_dids"][0], "did:plc:forged");
+    }
+
+    fn cid(i: u32) -> Vec<u8> {
+        let mut c = vec![0x01, 0x71, 0x12, 0x20];
+        c.extend_from_slice(&i.to_le_bytes());
+        c
+    }
+
+    #[test]
+    fn test_arrivals_match_and_drop() {
+        let tracker = ArrivalTracker::new(ARRIVAL_TICK * 2);
+        assert_eq!(tracker.observe(&cid(1), false, "pds.example", b"frame-1"), ArrivalOutcome::First);
+        assert_eq!(tracker.observe(&cid(1), false, "other.example", b"frame-1"), ArrivalOutcome::Repeat);
+        assert!(matches!(tracker.observe(&cid(1), true, "relay", b"frame-1"), ArrivalOutcome::MeshWon { .. }));
+        assert_eq!(tracker.observe(&cid(1), true, "relay", b"frame-1"), ArrivalOutcome::Repeat);
+
+        assert_eq!(tracker.observe(&cid(2), true, "relay", b"frame-2"), ArrivalOutcome::First);
+        assert_eq!(tracker.observe(&cid(2), false, "pds.example", b"frame-2"), ArrivalOutcome::RelayWon);
+
+        // Never reaches the relay
+        assert_eq!(tracker.observe(&cid(3), false, "pds.example", b"frame-3"), ArrivalOutcome::First);
+        assert_eq!(tracker.frame_bytes(), 7);
+
+        // Decided once a full window has passed, not before
+        assert!(tracker.tick().is_empty());
+        assert!(tracker.tick().is_empty());
+        let ghosts = tracker.tick();
+        assert_eq!(ghosts.len(), 1);
+        assert_eq!((ghosts[0].cid.clone(), ghosts[0].source_host.as_str()), (cid(3), "pds.example"));
+        assert_eq!(ghosts[0].frame.as_deref(), Some(&b"frame-3"[..]));
+        assert_eq!(tracker.frame_bytes(), 0);
+
+        // Matched entries linger for a turn of the wheel, then are forgotten
+        assert_eq!(tracker.len(), 2);
+        for _ in 0..ARRIVAL_WHEEL_BUCKETS {
+            assert!(tracker.tick().is_empty());
+        }
+        assert!(tracker.is_empty());
+        assert_eq!(tracker.observe(&cid(1), true, "relay", b"frame-1"), ArrivalOutcome::First);
+    }
+
+    #[test]
+    fn test_ghost_frames_stay_within_budget() {
+        let tracker = ArrivalTracker::with_budget(ARRIVAL_TICK, 1000);
+        for i in 0..30 {
+            tracker.observe(&cid(i), false, "pds.example", &[i as u8; 100]);
+        }
+        assert!(tracker.frame_bytes() <= 1000);
+        assert_eq!(tracker.evicted_frames(), 20);
+
+        tracker.tick();
+        let ghosts = tracker.tick();
+        assert_eq!(ghosts.len(), 30);
+        // The oldest lost their frames; the drops are still reported
+        assert!(ghosts[..20].iter().all(|g| g.frame.is_none()));
+        assert!(ghosts[20..].iter().all(|g| g.frame.as_ref().is_some_and(|f| f.len() == 100)));
+        assert_eq!(tracker.frame_bytes(), 0);
+    }
+
+    #[test]
+    fn test_tick_cost_stays_bounded() {
+        let tracker = ArrivalTracker::with_budget(ARRIVAL_TICK * 3, 64 * 1024 * 1024);
+        let frame = [0u8; 64];
+        let per_tick = 1_000_000 / (2 * ARRIVAL_WHEEL_BUCKETS as u32);
+        let (mut slowest, mut ghosts, mut peak) = (Duration::ZERO, 0, 0);
+        let mut next = 0u32;
+        // Two turns of the wheel: 1M arrivals, every tenth never reaching the relay
+        for _ in 0..2 * ARRIVAL_WHEEL_BUCKETS {
+            for _ in 0..per_tick {
+                let c = cid(next);
+                tracker.observe(&c, false, "pds.example", &frame);
+                if !next.is_multiple_of(10) {
+                    tracker.observe(&c, true, "relay", &frame);
+                }
+                next += 1;
+            }
+            peak = peak.max(tracker.len());
+            let start = Instant::now();
+            ghosts += tracker.tick().len();
+            slowest = slowest.max(start.elapsed());
+        }
+
+        // A tick touches two buckets however much is tracked
+        assert!(slowest < Duration::from_millis(250), "slowest tick took {:?}", slowest);
+        assert!(peak <= ARRIVAL_WHEEL_BUCKETS * per_tick as usize);
+        assert_eq!(ghosts, (next as usize - 3 * per_tick as usize).div_ceil(10));
+        assert_eq!(tracker.evicted_frames(), 0);
+    }
+
+    /// `quantile` answers with a bucket's upper edge: never below the true value, and no more
+    /// than a quarter above it.
+    fn assert_near(histogram: &Histogram, q: f64, exact: u64) {
+        let got = histogram.quantile(q);
+        assert!(got >= exact && got <= exact + exact / 4 + 1, "q{}: {} for exact {}", q, got, exact);
+    }
+
+    #[test]
+    fn test_histogram_quantiles() {
+        let empty = Histogram::default();
+        assert_eq!((empty.count(), empty.quantile(0.5)), (0, 0));
+
+        // Uniform 1..=10_000 µs
+        let uniform = Histogram::default();
+        for us in 1..=10_000u64 {
+            uniform.record(us * 1000);
+        }
+        assert_eq!(uniform.count(), 10_000);
+        assert_near(&uniform, 0.50, 5_000_000);
+        assert_near(&uniform, 0.95, 9_500_000);
+        assert_near(&uniform, 0.99, 9_900_000);
+        assert_near(&uniform, 1.0, 10_000_000);
+
+        // A heavy tail: 98% fast, 2% a thousand times slower, which an average hides
+        let tail = Histogram::default();
+        for i in 0..1000 {
+            tail.record_duration(if i % 50 == 0 { Duration::from_millis(50) } else { Duration::from_micros(50) });
+        }
+        assert_near(&tail, 0.50, 50_000);
+        assert_near(&tail, 0.95, 50_000);
+        assert_near(&tail, 0.99, 50_000_000);
+
+        // Small values have exact buckets; huge ones don't overflow
+        let edges = Histogram::default();
+        for ns in [0, 1, 2, 3, u64::MAX] {
+            edges.record(ns);
+        }
+        assert_eq!(edges.quantile(0.2), 0);
+        assert_eq!(edges.quantile(0.5), 2);
+        assert_eq!(edges.quantile(1.0), u64::MAX);
+
+        // The monitor's histograms show up in its report
+        let monitor = SovereignMonitor::new();
+        monitor.verify_time.record(2_000_000);
+        let report = monitor.report();
+        assert_eq!(report.verify_time.count, 1);
+        assert!(report.verify_time.p99_ns >= 2_000_000);
+        assert_eq!(report.ingest_time.count, 0);
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("monitor_snapshot.json");
+        let monitor = SovereignMonitor::new();
+        for _ in 0..3 {
+            monitor.record_event("did:plc:busy", true, None, Some(1));
+        }
+        monitor.record_event("did:plc:quiet", true, None, Some(2));
+        monitor.record_event("did:plc:forged", false, Some(ErrorType::InvalidSignature), Some(1));
+        monitor.record_malformed();
+        monitor.record_high_s();
+        monitor.mesh_wins.fetch_add(7, Ordering::Relaxed);
+        monitor.cursor_gaps.fetch_add(2, Ordering::Relaxed);
+        monitor.active_conns.fetch_add(5, Ordering::Relaxed);
+        monitor.save_snapshot(&path).unwrap();
+
+        let fresh = SovereignMonitor::new();
+        let snapshot = fresh.load_snapshot(&path).unwrap();
+        let mut expected = monitor.snapshot();
+        expected.saved_at = snapshot.saved_at;
+        assert_eq!(snapshot, expected);
+        let (before, after) = (monitor.report(), fresh.report());
+        assert_eq!((after.total, after.verified, after.invalid_sig, after.malformed), (before.total, before.verified, before.invalid_sig, before.malformed));
+        assert_eq!((after.secp256k1, after.p256, after.high_s, after.cursor_gaps), (3, 1, 1, 2));
+        assert_eq!(after.invalid_sig_dids, vec!["did:plc:forged".to_string()]);
+        assert_eq!(fresh.mesh_wins.load(Ordering::Relaxed), 7);
+        // Live gauges aren't carried over
+        assert_eq!(fresh.active_conns.load(Ordering::Relaxed), 0);
+        assert_eq!(snapshot.leaderboard[0], ("did:plc:busy".to_string(), 3));
+        assert_eq!(*fresh.leaderboard.get("did:plc:quiet").unwrap(), 1);
+
+        // Counters this build doesn't know are ignored, missing ones left alone
+        std::fs::write(&path, r#"{"counters": {"total": 40, "someday": 1}}"#).unwrap();
+        let partial = SovereignMonitor::new();
+        partial.verified.fetch_add(9, Ordering::Relaxed);
+        partial.load_snapshot(&path).unwrap();
+        assert_eq!((partial.total.load(Ordering::Relaxed), partial.verified.load(Ordering::Relaxed)), (40, 9));
+        assert_eq!(fresh.load_snapshot(dir.path().join("missing.json")).unwrap_err().kind(), std::io::ErrorKind::NotFound);
+    }
+}
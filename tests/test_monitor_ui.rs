@@ -0,0 +1,78 @@
+#[cfg(test)]
+mod monitor_ui_tests {
+    use did_mmap_cache::monitor::{LowerPanel, SovereignMonitor, UiAction, UiKey, UiState};
+
+    fn type_text(ui: &mut UiState, text: &str) {
+        for c in text.chars() {
+            assert_eq!(ui.handle_key(UiKey::Char(c)), UiAction::Redraw);
+        }
+    }
+
+    #[test]
+    fn test_pause_and_panel_toggle() {
+        let mut ui = UiState::default();
+        assert_eq!(ui.handle_key(UiKey::Char('p')), UiAction::Redraw);
+        assert!(ui.paused);
+        assert_eq!(ui.handle_key(UiKey::Char('P')), UiAction::Redraw);
+        assert!(!ui.paused);
+
+        assert_eq!(ui.panel, LowerPanel::Tap);
+        ui.handle_key(UiKey::Char('d'));
+        assert_eq!(ui.panel, LowerPanel::Drops);
+        ui.handle_key(UiKey::Char('d'));
+        assert_eq!(ui.panel, LowerPanel::Tap);
+
+        assert_eq!(ui.handle_key(UiKey::Char('x')), UiAction::None);
+        assert_eq!(ui.handle_key(UiKey::Enter), UiAction::None);
+    }
+
+    #[test]
+    fn test_filter_prompt() {
+        let mut ui = UiState::default();
+        ui.handle_key(UiKey::Char('f'));
+        assert_eq!(ui.prompt.as_deref(), Some(""));
+        // 'q' and 'p' are input while the prompt is open
+        type_text(&mut ui, "alqpx");
+        ui.handle_key(UiKey::Backspace);
+        assert_eq!(ui.prompt.as_deref(), Some("alqp"));
+        assert!(!ui.paused);
+        ui.handle_key(UiKey::Enter);
+        assert_eq!((ui.prompt.as_deref(), ui.filter.as_deref()), (None, Some("alqp")));
+
+        assert!(ui.matches("did:plc:ALQP123"));
+        assert!(!ui.matches("did:plc:bob"));
+
+        // Reopening starts from the current filter; Esc leaves it untouched
+        ui.handle_key(UiKey::Char('f'));
+        assert_eq!(ui.prompt.as_deref(), Some("alqp"));
+        type_text(&mut ui, "zzz");
+        ui.handle_key(UiKey::Esc);
+        assert_eq!(ui.filter.as_deref(), Some("alqp"));
+
+        // Submitting an empty prompt clears the filter
+        ui.handle_key(UiKey::Char('f'));
+        for _ in 0..4 {
+            ui.handle_key(UiKey::Backspace);
+        }
+        ui.handle_key(UiKey::Enter);
+        assert_eq!(ui.filter, None);
+        assert!(ui.matches("anything"));
+    }
+
+    #[test]
+    fn test_quit_keys() {
+        let mut ui = UiState::default();
+        assert_eq!(ui.handle_key(UiKey::Char('q')), UiAction::Quit);
+        ui.handle_key(UiKey::Char('f'));
+        assert_eq!(ui.handle_key(UiKey::CtrlC), UiAction::Quit);
+    }
+
+    #[test]
+    fn test_monitor_forwards_keys() {
+        let monitor = SovereignMonitor::new();
+        assert_eq!(monitor.handle_key(UiKey::Char('p')), UiAction::Redraw);
+        assert!(monitor.ui.lock().unwrap().paused);
+        // Render-only by default; a paused dashboard still redraws once after a key press
+        assert!(!monitor.ui.lock().unwrap().interactive);
+    }
+}
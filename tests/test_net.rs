@@ -0,0 +1,141 @@
+#[cfg(test)]
+mod net_tests {
+    use did_mmap_cache::net::{cursor_url, BackoffPolicy, FailureKind, HostHealth};
+    use did_mmap_cache::pds_ledger::{PdsEntry, PdsLedger};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use url::Url;
+
+    fn exact_policy() -> BackoffPolicy {
+        BackoffPolicy { jitter: 0.0, ..BackoffPolicy::default() }
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    enum Step {
+        Fail(FailureKind),
+        Succeed,
+    }
+
+    /// Plays `steps` against a fresh host and returns the delay answered to each failure.
+    fn script(policy: &BackoffPolicy, health: &mut HostHealth, steps: &[Step]) -> Vec<Option<u64>> {
+        let mut delays = Vec::new();
+        for (t, step) in steps.iter().enumerate() {
+            match step {
+                Step::Fail(kind) => delays.push(policy.on_failure(health, *kind, at(1000 + t as u64)).map(|d| d.as_secs())),
+                Step::Succeed => policy.on_success(health, at(1000 + t as u64)),
+            }
+        }
+        delays
+    }
+
+    #[test]
+    fn test_failures_double_until_a_success_resets() {
+        use FailureKind::*;
+        use Step::*;
+        let policy = exact_policy();
+        let mut health = HostHealth::default();
+        let delays = script(&policy, &mut health, &[
+            Fail(Dropped), Fail(Dropped), Fail(Stalled), Succeed,
+            Fail(Unreachable), Fail(Dropped), Fail(Unreachable),
+        ]);
+        // Drops start from 5s, connect errors from 30s; both share the failure count
+        assert_eq!(delays, vec![Some(5), Some(10), Some(20), Some(30), Some(10), Some(120)]);
+        assert_eq!(health.failures, 3);
+        assert_eq!(health.last_success, 1003);
+        assert_eq!(health.penalty_until, 1006 + 120);
+        assert!(!health.is_available(1100) && health.is_available(1126));
+
+        let delays = script(&policy, &mut health, &[Fail(Unreachable), Fail(Unreachable), Fail(Unreachable), Fail(Unreachable)]);
+        assert_eq!(delays, vec![Some(240), Some(300), Some(300), Some(300)]);
+    }
+
+    #[test]
+    fn test_blacklist_decisions() {
+        let policy = exact_policy();
+        for status in [200, 400, 401, 403, 404, 500, 502, 503] {
+            assert!(policy.should_blacklist(FailureKind::Http(status)), "{}", status);
+        }
+        for status in [301, 408, 429] {
+            assert!(!policy.should_blacklist(FailureKind::Http(status)), "{}", status);
+        }
+        assert!(policy.should_blacklist(FailureKind::BadUrl));
+        assert!(!policy.should_blacklist(FailureKind::Unreachable));
+
+        // A rate limit backs off like any connect error; a 403 ends retrying
+        let mut health = HostHealth::default();
+        let delays = script(&policy, &mut health, &[Step::Fail(FailureKind::Http(429)), Step::Fail(FailureKind::Http(403))]);
+        assert_eq!(delays, vec![Some(30), None]);
+        assert!(health.blacklisted && !health.is_available(u64::MAX));
+    }
+
+    #[test]
+    fn test_connect_errors_are_classified() {
+        let refused = tungstenite::connect("ws://127.0.0.1:1").unwrap_err();
+        assert_eq!(FailureKind::from_connect_error(&refused), FailureKind::Unreachable);
+        let scheme = tungstenite::connect("http://127.0.0.1:1").unwrap_err();
+        assert_eq!(FailureKind::from_connect_error(&scheme), FailureKind::BadUrl);
+    }
+
+    #[test]
+    fn test_jitter_only_shortens() {
+        let policy = BackoffPolicy::default();
+        let mut health = HostHealth::default();
+        for n in 0..10 {
+            let expected = policy.delay(FailureKind::Dropped, n);
+            let delay = policy.on_failure(&mut health, FailureKind::Dropped, SystemTime::now()).unwrap();
+            assert!(delay <= expected && delay >= expected.mul_f64(0.75), "{:?} vs {:?}", delay, expected);
+        }
+    }
+
+    #[test]
+    fn test_message_rate_is_smoothed() {
+        let mut health = HostHealth::default();
+        health.observe_rate(100, Duration::from_secs(10));
+        assert_eq!(health.msg_rate, 10.0);
+        health.observe_rate(0, Duration::from_secs(10));
+        assert!((health.msg_rate - 7.0).abs() < 1e-9);
+        health.observe_rate(5, Duration::ZERO);
+        assert!((health.msg_rate - 7.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_health_survives_restart() {
+        let policy = exact_policy();
+        let mut health = HostHealth::default();
+        policy.on_failure(&mut health, FailureKind::Dropped, at(50));
+        health.observe_rate(30, Duration::from_secs(10));
+
+        let json = serde_json::to_string(&health).unwrap();
+        assert_eq!(serde_json::from_str::<HostHealth>(&json).unwrap(), health);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pds_list.bin");
+        {
+            let mut ledger = PdsLedger::open_or_create(&path).unwrap();
+            let idx = ledger.append(&PdsEntry::new("wss://pds.example").unwrap()).unwrap();
+            ledger.get_entry_mut(idx).unwrap().set_health(&health);
+            let mut banned = health.clone();
+            policy.on_failure(&mut banned, FailureKind::Http(404), at(60));
+            let idx = ledger.append(&PdsEntry::new("wss://private.example").unwrap()).unwrap();
+            ledger.get_entry_mut(idx).unwrap().set_health(&banned);
+            ledger.flush().unwrap();
+        }
+        let ledger = PdsLedger::open_or_create(&path).unwrap();
+        assert_eq!(ledger.get_entry(0).unwrap().health(), health);
+        assert!(ledger.get_entry(1).unwrap().health().blacklisted);
+        assert_eq!(ledger.get_entry(1).unwrap().get_url(), "wss://private.example");
+    }
+
+    #[test]
+    fn test_cursor_url_replaces_only_the_cursor() {
+        let base = Url::parse("wss://relay.example/xrpc/com.atproto.sync.subscribeRepos").unwrap();
+        assert_eq!(cursor_url(&base, None), base);
+        assert_eq!(cursor_url(&base, Some(42)).query(), Some("cursor=42"));
+
+        let resumed = Url::parse("ws://localhost:8080/?since=7&cursor=1").unwrap();
+        assert_eq!(cursor_url(&resumed, Some(9)).as_str(), "ws://localhost:8080/?since=7&cursor=9");
+        assert_eq!(cursor_url(&resumed, None).as_str(), "ws://localhost:8080/?since=7");
+    }
+}
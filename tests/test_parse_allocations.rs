@@ -0,0 +1,93 @@
+#[cfg(test)]
+mod parse_allocations_tests {
+    use did_mmap_cache::parser::core::{parse_input, skip_cbor_value};
+    use libipld::cbor::DagCborCodec;
+    use libipld::codec::Codec;
+    use libipld::Ipld;
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::fs;
+    use std::path::Path;
+
+    /// Counts the allocations made on each thread, so tests running alongside don't interfere.
+    struct CountingAlloc;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAlloc {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: CountingAlloc = CountingAlloc;
+
+    fn allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+        let before = ALLOCATIONS.with(Cell::get);
+        let out = f();
+        (out, ALLOCATIONS.with(Cell::get) - before)
+    }
+
+    fn sample_frames() -> Vec<Vec<u8>> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let raw = fs::read(dir.join("synthetic_sample.raw")).unwrap();
+        let sizes = fs::read(dir.join("synthetic_sample.sizes")).unwrap();
+        let mut offset = 0;
+        sizes
+            .chunks_exact(4)
+            .map(|c| {
+                let size = u32::from_le_bytes(c.try_into().unwrap()) as usize;
+                offset += size;
+                raw[offset - size..offset].to_vec()
+            })
+            .collect()
+    }
+
+    /// `(action, path, cid)` of an op.
+    type OpTriple = (String, String, Option<Vec<u8>>);
+
+    /// `(action, path, cid)` of each op, decoded from the payload with libipld. The CIDs
+    /// come without the 0x00 multibase prefix the raw op bytes carry.
+    fn reference_ops(frame: &[u8]) -> Option<Vec<OpTriple>> {
+        let payload: Ipld = DagCborCodec.decode(&frame[skip_cbor_value(frame, 0).ok()?..]).ok()?;
+        let Ok(Ipld::List(ops)) = payload.get("ops") else { return None };
+        ops.iter()
+            .map(|op| {
+                let text = |key| match op.get(key) {
+                    Ok(Ipld::String(s)) => Some(s.clone()),
+                    _ => None,
+                };
+                let cid = match op.get("cid") {
+                    Ok(Ipld::Link(cid)) => Some(cid.to_bytes()),
+                    _ => None,
+                };
+                Some((text("action")?, text("path")?, cid))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ops_are_borrowed_and_unchanged() {
+        let mut commits = 0;
+        for (i, frame) in sample_frames().iter().enumerate() {
+            let (parsed, count) = allocations(|| parse_input(frame));
+            let Ok(envelope) = parsed else { continue };
+            // Only a commit with more ops than fit inline spills them to the heap
+            assert_eq!(count, usize::from(envelope.ops.spilled()), "frame {} with {} ops", i, envelope.ops.len());
+
+            let Some(expected) = reference_ops(frame) else { continue };
+            let ops: Vec<_> = envelope.ops.iter().map(|op| (op.action.to_string(), op.path.to_string(), op.cid.map(|c| c[1..].to_vec()))).collect();
+            assert_eq!(ops, expected, "frame {}", i);
+            commits += 1;
+        }
+        assert!(commits > 0);
+    }
+}
@@ -0,0 +1,206 @@
+#[cfg(test)]
+mod parse_error_corpus {
+    use did_mmap_cache::parser::core::{parse_input, parse_input_opt, skip_cbor_value, InfoMessage, ParseError};
+    use did_mmap_cache::testutil::cbor::{bytes, event_header, head, link, text};
+
+    fn cid() -> Vec<u8> {
+        let mut c = vec![0x01, 0x71, 0x12, 0x20];
+        c.extend_from_slice(&[0xab; 32]);
+        c
+    }
+
+    /// Commit block: { did, sig }
+    fn commit_block() -> Vec<u8> {
+        let mut b = Vec::new();
+        head(5, 2, &mut b);
+        text("did", &mut b);
+        text("did:plc:corpus", &mut b);
+        text("sig", &mut b);
+        bytes(&[0x5a; 64], &mut b);
+        b
+    }
+
+    fn car() -> Vec<u8> {
+        let mut header = Vec::new();
+        head(5, 1, &mut header);
+        text("version", &mut header);
+        header.push(0x01);
+
+        let mut out = vec![header.len() as u8];
+        out.extend_from_slice(&header);
+        let block = commit_block();
+        out.push((cid().len() + block.len()) as u8);
+        out.extend_from_slice(&cid());
+        out.extend_from_slice(&block);
+        out
+    }
+
+    fn frame_with(t: &str, include_repo: bool, blocks: Option<Vec<u8>>) -> Vec<u8> {
+        let mut f = Vec::new();
+        event_header(t, &mut f);
+
+        let mut pairs = 2;
+        if include_repo { pairs += 1; }
+        if blocks.is_some() { pairs += 1; }
+        head(5, pairs, &mut f);
+        text("seq", &mut f);
+        head(0, 4242, &mut f);
+        if include_repo {
+            text("repo", &mut f);
+            text("did:plc:corpus", &mut f);
+        }
+        text("ops", &mut f);
+        head(4, 1, &mut f);
+        head(5, 3, &mut f);
+        text("action", &mut f);
+        text("create", &mut f);
+        text("path", &mut f);
+        text("app.bsky.feed.post/3k", &mut f);
+        text("cid", &mut f);
+        link(&cid(), &mut f);
+        if let Some(b) = blocks {
+            text("blocks", &mut f);
+            bytes(&b, &mut f);
+        }
+        f
+    }
+
+    /// `#info` frame: { name, message? }, no seq
+    fn info_frame(name: &str, message: Option<&str>) -> Vec<u8> {
+        let mut f = Vec::new();
+        event_header("#info", &mut f);
+        head(5, if message.is_some() { 2 } else { 1 }, &mut f);
+        text("name", &mut f);
+        text(name, &mut f);
+        if let Some(message) = message {
+            text("message", &mut f);
+            text(message, &mut f);
+        }
+        f
+    }
+
+    fn valid_frame() -> Vec<u8> {
+        frame_with("#commit", true, Some(car()))
+    }
+
+    #[test]
+    fn test_valid_frame_parses() {
+        let frame = valid_frame();
+        let env = parse_input(&frame).expect("valid frame");
+        assert_eq!(env.did, Some(&b"did:plc:corpus"[..]));
+        assert_eq!(env.sequence, Some(4242));
+        assert_eq!(env.signature, Some(&[0x5a; 64][..]));
+        assert_eq!(env.ops.len(), 1);
+        assert_eq!(env.ops[0].path, "app.bsky.feed.post/3k");
+        assert!(parse_input_opt(&frame).is_some());
+    }
+
+    #[test]
+    fn test_corpus_specific_errors() {
+        let frame = valid_frame();
+
+        // Empty input
+        assert_eq!(parse_input(&[]).unwrap_err(), ParseError::UnexpectedEof { offset: 0 });
+
+        // Header that promises more bytes than exist
+        assert!(matches!(parse_input(&frame[..3]).unwrap_err(), ParseError::UnexpectedEof { .. }));
+
+        // Header is not a map: an array followed by a payload
+        let mut not_map = vec![0x81, 0x01];
+        not_map.extend_from_slice(&frame[frame.len() - 4..]);
+        assert_eq!(
+            parse_input(&not_map).unwrap_err(),
+            ParseError::WrongMajorType { offset: 0, expected: 5, found: 4 }
+        );
+
+        // Payload replaced by a uint
+        let header_end = skip_cbor_value(&frame, 0).unwrap();
+        let mut bad_payload = frame[..header_end].to_vec();
+        bad_payload.push(0x07);
+        assert_eq!(
+            parse_input(&bad_payload).unwrap_err(),
+            ParseError::WrongMajorType { offset: header_end, expected: 5, found: 0 }
+        );
+
+        // Reserved length encoding (addl = 28) right after the header
+        let mut reserved = frame[..header_end].to_vec();
+        reserved.push(0xbc);
+        assert_eq!(parse_input(&reserved).unwrap_err(), ParseError::InvalidLength { offset: header_end });
+
+        // Commit without repo / without blocks
+        let no_repo = frame_with("#commit", false, Some(car()));
+        assert_eq!(parse_input(&no_repo).unwrap_err(), ParseError::MissingField("repo"));
+        let no_blocks = frame_with("#commit", true, None);
+        assert_eq!(parse_input(&no_blocks).unwrap_err(), ParseError::MissingField("blocks"));
+
+        // Non-commit events are allowed to omit both
+        assert!(parse_input(&frame_with("#identity", false, None)).is_ok());
+
+        // CAR whose block length runs past the end
+        let mut short_car = car();
+        let block_len_pos = 1 + short_car[0] as usize;
+        short_car[block_len_pos] = 0x7f;
+        assert_eq!(
+            parse_input(&frame_with("#commit", true, Some(short_car))).unwrap_err(),
+            ParseError::InvalidCar { reason: "block overruns buffer" }
+        );
+
+        // Empty CAR
+        assert_eq!(
+            parse_input(&frame_with("#commit", true, Some(Vec::new()))).unwrap_err(),
+            ParseError::InvalidCar { reason: "empty blocks" }
+        );
+    }
+
+    #[test]
+    fn test_info_and_sync_frames() {
+        let frame = info_frame("OutdatedCursor", Some("Requested cursor exceeded limit. Possibly missing events"));
+        let env = parse_input(&frame).unwrap();
+        assert_eq!(env.t, Some(&b"#info"[..]));
+        assert_eq!(env.sequence, None);
+        let info = env.info().unwrap();
+        assert_eq!(info, InfoMessage { name: "OutdatedCursor", message: Some("Requested cursor exceeded limit. Possibly missing events") });
+        assert!(info.is_outdated_cursor());
+
+        let other = info_frame("SomethingElse", None);
+        let info = parse_input(&other).unwrap().info().unwrap();
+        assert_eq!((info.name, info.message, info.is_outdated_cursor()), ("SomethingElse", None, false));
+        // Only #info frames have one
+        assert!(parse_input(&valid_frame()).unwrap().info().is_none());
+
+        // A #sync needs its DID and the CAR holding the commit
+        let sync_frame = frame_with("#sync", true, Some(car()));
+        let sync = parse_input(&sync_frame).unwrap();
+        assert_eq!(sync.did, Some(&b"did:plc:corpus"[..]));
+        assert_eq!(sync.commit, Some(&commit_block()[..]));
+        assert_eq!(sync.signature, Some(&[0x5a; 64][..]));
+        assert_eq!(parse_input(&frame_with("#sync", false, Some(car()))).unwrap_err(), ParseError::MissingField("did"));
+        assert_eq!(parse_input(&frame_with("#sync", true, None)).unwrap_err(), ParseError::MissingField("blocks"));
+    }
+
+    #[test]
+    fn test_every_truncation_errors_cleanly() {
+        let frame = valid_frame();
+        let header_end = skip_cbor_value(&frame, 0).unwrap();
+        // Any cut inside the payload must be reported, never panic or yield a partial envelope.
+        for cut in header_end + 1..frame.len() {
+            let err = parse_input(&frame[..cut]).expect_err("truncated frame should fail");
+            assert!(
+                matches!(err, ParseError::UnexpectedEof { .. } | ParseError::InvalidCar { .. }),
+                "cut at {} gave {:?}", cut, err
+            );
+        }
+    }
+
+    #[test]
+    fn test_byte_mutations_never_panic() {
+        let frame = valid_frame();
+        for pos in 0..frame.len() {
+            for flip in [0x01u8, 0x20, 0x80, 0xff] {
+                let mut m = frame.clone();
+                m[pos] ^= flip;
+                let _ = parse_input(&m);
+            }
+        }
+    }
+}
@@ -0,0 +1,76 @@
+#[cfg(test)]
+mod parse_peek_tests {
+    use did_mmap_cache::parser::core::{parse_full, parse_input, peek_frame, skip_cbor_value};
+    use std::fs;
+    use std::path::Path;
+
+    fn sample_frames() -> Vec<Vec<u8>> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let raw = fs::read(dir.join("synthetic_sample.raw")).unwrap();
+        let sizes = fs::read(dir.join("synthetic_sample.sizes")).unwrap();
+        let mut offset = 0;
+        sizes
+            .chunks_exact(4)
+            .map(|c| {
+                let size = u32::from_le_bytes(c.try_into().unwrap()) as usize;
+                offset += size;
+                raw[offset - size..offset].to_vec()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_peek_then_parse_full_matches_parse_input() {
+        let mut commits = 0;
+        for (i, frame) in sample_frames().iter().enumerate() {
+            let Some(peek) = peek_frame(frame) else {
+                // Only something that isn't a header and payload may fail the peek and still parse
+                assert!(parse_input(frame).map_or(true, |e| e.t.is_none()), "frame {}", i);
+                continue;
+            };
+            let full = parse_full(peek, frame);
+            assert_eq!(format!("{:?}", full), format!("{:?}", parse_input(frame)), "frame {}", i);
+
+            if let Ok(envelope) = full {
+                assert_eq!((peek.t, peek.op, peek.seq, peek.did, peek.cid), (envelope.t, envelope.op, envelope.sequence, envelope.did, envelope.cid), "frame {}", i);
+                commits += peek.is_commit() as usize;
+            }
+        }
+        assert!(commits > 0);
+    }
+
+    #[test]
+    fn test_peek_rejects_what_is_not_a_frame() {
+        assert!(peek_frame(&[]).is_none());
+        // A single CBOR value is a bare CAR or nothing, never a header and payload
+        assert!(peek_frame(&[0xa1, 0x61, b't', 0x61, b'x']).is_none());
+
+        let frame = sample_frames().into_iter().find(|f| peek_frame(f).is_some()).unwrap();
+        let header_end = skip_cbor_value(&frame, 0).unwrap();
+        for cut in [1, header_end, header_end + 1, frame.len() - 1] {
+            assert!(peek_frame(&frame[..cut]).is_none(), "cut at {}", cut);
+        }
+    }
+
+    #[cfg(feature = "test-fixtures")]
+    #[test]
+    fn test_peek_of_built_frames() {
+        use did_mmap_cache::testutil::{identity_frame, post_record, FrameBuilder, SigningKey};
+
+        let identity = identity_frame("did:plc:ewvi7nxzyoun6zhxrhs64oiz", 9, Some("alice.example.com"));
+        let peek = peek_frame(&identity).unwrap();
+        assert!(!peek.is_commit());
+        assert_eq!((peek.t, peek.seq, peek.cid), (Some(&b"#identity"[..]), Some(9), None));
+        assert_eq!(peek.did, Some(&b"did:plc:ewvi7nxzyoun6zhxrhs64oiz"[..]));
+
+        let commit = FrameBuilder::new("did:web:carol.example.com", SigningKey::p256_from_seed(0x33))
+            .seq(8)
+            .create("app.bsky.feed.like/3lbfaaaa2s22k", post_record("liked"))
+            .build();
+        let peek = peek_frame(&commit).unwrap();
+        assert!(peek.is_commit());
+        let envelope = parse_full(peek, &commit).unwrap();
+        assert_eq!(envelope.ops.len(), 1);
+        assert_eq!(format!("{:?}", envelope), format!("{:?}", parse_input(&commit).unwrap()));
+    }
+}
@@ -0,0 +1,56 @@
+#[cfg(all(test, feature = "profiling"))]
+mod parse_timings_tests {
+    use did_mmap_cache::parser::core::{parse_input, parse_input_timed, ParseTimings};
+    use std::fs;
+    use std::path::Path;
+
+    fn sample_frames() -> Vec<Vec<u8>> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+        let raw = fs::read(dir.join("synthetic_sample.raw")).unwrap();
+        let sizes = fs::read(dir.join("synthetic_sample.sizes")).unwrap();
+        let mut offset = 0;
+        sizes
+            .chunks_exact(4)
+            .map(|c| {
+                let size = u32::from_le_bytes(c.try_into().unwrap()) as usize;
+                offset += size;
+                raw[offset - size..offset].to_vec()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_timed_parse_matches_plain_parse() {
+        for (i, frame) in sample_frames().iter().enumerate() {
+            let (timed, t) = parse_input_timed(frame);
+            let plain = parse_input(frame).ok();
+            assert_eq!(timed.is_some(), plain.is_some(), "frame {}", i);
+            if let (Some(a), Some(b)) = (timed, plain) {
+                assert_eq!((a.did, a.sequence, a.signature, a.commit, a.ops.len()), (b.did, b.sequence, b.signature, b.commit, b.ops.len()), "frame {}", i);
+            }
+            // Phases never add up to more than the call itself
+            let phases = t.header_ns + t.payload_ns + t.ops_ns + t.car_ns + t.signature_ns;
+            assert!(phases <= t.total_ns, "frame {}: {:?}", i, t);
+        }
+    }
+
+    #[test]
+    fn test_commit_frames_spend_time_in_car_extraction() {
+        let mut total = ParseTimings::default();
+        let mut commits = 0;
+        for frame in sample_frames() {
+            let (env, t) = parse_input_timed(&frame);
+            if env.is_some_and(|e| e.commit.is_some()) {
+                commits += 1;
+                total.accumulate(&t);
+            }
+        }
+        assert!(commits > 0);
+        assert!(total.header_ns > 0 && total.car_ns > 0 && total.total_ns > 0, "{:?}", total);
+
+        // Rejected before any phase starts
+        let (env, t) = parse_input_timed(&[]);
+        assert!(env.is_none());
+        assert_eq!((t.header_ns, t.car_ns), (0, 0));
+    }
+}
@@ -0,0 +1,199 @@
+#[cfg(test)]
+mod path_index_tests {
+    use did_mmap_cache::archive::{ArchiveWriter, MultiShardArchive, PathHash, Segment, SegmentedArchive};
+    use did_mmap_cache::testutil::{post_record, FrameBuilder, SigningKey};
+    use memmap2::Mmap;
+    use std::fs::{self, File};
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    fn path_hash(path: &str) -> u64 {
+        PathHash::default().hash(path)
+    }
+
+    /// A signed #commit creating a post at `path`.
+    fn frame(did: &str, seq: u64, path: &str) -> Vec<u8> {
+        FrameBuilder::new(did, SigningKey::k256_from_seed(0x11)).seq(seq).create(path, post_record("hello")).build()
+    }
+
+    /// A path with the same FxHash as `victim`, a 24-byte path starting `app.bsky`: the
+    /// second 8-byte word is picked, and the third solved for so the hasher's state after
+    /// both matches the victim's.
+    fn fx_collision(victim: &str) -> String {
+        const SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+        let step = |hash: u64, word: &[u8]| (hash.rotate_left(5) ^ u64::from_le_bytes(word.try_into().unwrap())).wrapping_mul(SEED);
+        let bytes = victim.as_bytes();
+        assert_eq!((bytes.len(), &bytes[..8]), (24, &b"app.bsky"[..]));
+        let start = step(0, &bytes[..8]);
+        let target = step(start, &bytes[8..16]).rotate_left(5) ^ u64::from_le_bytes(bytes[16..].try_into().unwrap());
+        (0..1_000_000u32)
+            .find_map(|n| {
+                // Digits low byte first: the low bits of the solved word only depend on these
+                let second: String = format!("{:08}", n).chars().rev().collect();
+                let third = (target ^ step(start, second.as_bytes()).rotate_left(5)).to_le_bytes();
+                third.iter().all(|b| b.is_ascii_graphic()).then(|| format!("app.bsky{}{}", second, String::from_utf8(third.to_vec()).unwrap()))
+            })
+            .unwrap()
+    }
+
+    fn open_segment(dir: &Path, with_index: bool) -> Segment {
+        let bin = unsafe { Mmap::map(&File::open(dir.join("s0_0.bin")).unwrap()).unwrap() };
+        let idx = unsafe { Mmap::map(&File::open(dir.join("s0_0.idx")).unwrap()).unwrap() };
+        let segment = Segment::new(0, bin, idx);
+        if !with_index {
+            return segment;
+        }
+        let pidx = unsafe { Mmap::map(&File::open(dir.join("s0_0.pidx")).unwrap()).unwrap() };
+        segment.with_path_index(pidx)
+    }
+
+    #[test]
+    fn test_binary_search_matches_linear_scan() {
+        let dir = tempdir().unwrap();
+        let mut writer = ArchiveWriter::new(dir.path(), 0, 0, 1000, None).unwrap();
+        let mut paths = Vec::new();
+        for seq in 0..400u64 {
+            // Skip some seqs to leave gaps, and reuse paths so one hash maps to several seqs
+            if seq % 13 == 5 {
+                continue;
+            }
+            let path = format!("app.bsky.feed.post/{}", seq % 150);
+            let did = format!("did:plc:user{}", seq % 9);
+            writer.append_message(seq, &did, &path, format!("msg {}", seq).as_bytes()).unwrap();
+            paths.push(path);
+        }
+        writer.finalize_segment().unwrap();
+
+        let segment = open_segment(dir.path(), true);
+        assert!(segment.has_path_index());
+        for path in &paths {
+            let hash = path_hash(path);
+            let found = segment.find_seq_by_path_hash(hash);
+            assert!(found.is_some(), "{}", path);
+            assert_eq!(found, segment.find_seq_by_path_hash_linear(hash), "{}", path);
+        }
+        // Earliest of several seqs sharing a path
+        assert_eq!(segment.find_seq_by_path_hash(path_hash("app.bsky.feed.post/7")), Some(7));
+        for absent in ["app.bsky.feed.post/999", "app.bsky.feed.like/1", ""] {
+            assert_eq!(segment.find_seq_by_path_hash(path_hash(absent)), None);
+        }
+        for hash in [0, 1, u64::MAX] {
+            assert_eq!(segment.find_seq_by_path_hash(hash), segment.find_seq_by_path_hash_linear(hash));
+        }
+    }
+
+    #[test]
+    fn test_mismatched_path_index_is_ignored() {
+        let dir = tempdir().unwrap();
+        let mut writer = ArchiveWriter::new(dir.path(), 0, 0, 100, None).unwrap();
+        for seq in 0..20u64 {
+            writer.append_message(seq, "did:plc:alice", &format!("app.bsky.feed.post/{}", seq), b"x").unwrap();
+        }
+        writer.finalize_segment().unwrap();
+
+        // Truncated sidecar: not used, lookups still work
+        let pidx = dir.path().join("s0_0.pidx");
+        let bytes = fs::read(&pidx).unwrap();
+        fs::write(&pidx, &bytes[..bytes.len() - 16]).unwrap();
+        let segment = open_segment(dir.path(), true);
+        assert!(!segment.has_path_index());
+        assert_eq!(segment.find_seq_by_path_hash(path_hash("app.bsky.feed.post/12")), Some(12));
+
+        // Right size but pointing at the wrong seq: the idx record check catches it
+        let mut swapped = bytes.clone();
+        for entry in swapped.chunks_mut(16) {
+            entry[8..].copy_from_slice(&0u64.to_le_bytes());
+        }
+        fs::write(&pidx, &swapped).unwrap();
+        let segment = open_segment(dir.path(), true);
+        assert_eq!(segment.find_seq_by_path_hash(path_hash("app.bsky.feed.post/12")), Some(12));
+    }
+
+    #[test]
+    fn test_delete_by_path_on_legacy_segments() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("archive");
+        let archive = MultiShardArchive::new(&root, 2, 50, None).unwrap();
+        for seq in 0..120u64 {
+            let did = format!("did:plc:user{}", seq % 4);
+            let path = format!("app.bsky.feed.post/{}", seq);
+            archive.ingest(seq, &did, path.clone(), frame(&did, seq, &path));
+        }
+        archive.shutdown();
+
+        // Segments written before the path index existed have no sidecar
+        let mut removed = 0;
+        for shard in fs::read_dir(&root).unwrap().flatten().filter(|e| e.path().is_dir()) {
+            for file in fs::read_dir(shard.path()).unwrap().flatten() {
+                if file.path().extension().is_some_and(|x| x == "pidx") {
+                    fs::remove_file(file.path()).unwrap();
+                    removed += 1;
+                }
+            }
+        }
+        assert!(removed > 0);
+
+        let reader = MultiShardArchive::open_readonly(&root, None).unwrap();
+        assert!(reader.get_message_by_seq(42).is_ok());
+        assert_eq!(reader.delete_by_path("did:plc:user2", "app.bsky.feed.post/42"), Some(42));
+        assert!(reader.get_message_by_seq(42).is_err());
+        assert!(reader.get_message_by_seq(43).is_ok());
+    }
+
+    #[test]
+    fn test_fx_collision_cannot_delete_another_dids_record() {
+        let victim = "app.bsky.feed.post/3kab2";
+        let forged = fx_collision(victim);
+        assert_ne!(forged, victim);
+        assert_eq!(PathHash::Fx.hash(&forged), PathHash::Fx.hash(victim));
+        assert_ne!(PathHash::Blake3.hash(&forged), PathHash::Blake3.hash(victim));
+
+        // Segments hashed the old way: bob has a record at the forged path, alice a newer one
+        // at hers, each in a segment of its own
+        let dir = tempdir().unwrap();
+        let archive = MultiShardArchive::new(dir.path(), 1, 1, None).unwrap();
+        archive.set_path_hash(PathHash::Fx);
+        archive.ingest(1, "did:plc:bob", forged.clone(), frame("did:plc:bob", 1, &forged));
+        archive.ingest(2, "did:plc:alice", victim.to_string(), frame("did:plc:alice", 2, victim));
+        archive.shutdown();
+        assert!(!dir.path().join("shard_0/s0_1.phash").exists());
+
+        // The newest seq with the forged path's hash is alice's: what a hash-only delete tombstoned
+        let shard = SegmentedArchive::open_directory(dir.path().join("shard_0"), None, None).unwrap();
+        assert_eq!(shard.find_sequence_by_path(PathHash::Fx.hash(&forged)), Some(2));
+
+        let reader = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        assert_eq!(reader.delete_by_path("did:plc:carol", &forged), None);
+        assert_eq!(reader.delete_by_path("did:plc:bob", &forged), Some(1));
+        assert!(reader.get_message_by_seq(2).is_ok());
+        assert!(reader.get_message_by_seq(1).is_err());
+    }
+
+    #[test]
+    fn test_each_segment_looks_up_with_its_own_hash() {
+        let dir = tempdir().unwrap();
+        let archive = MultiShardArchive::new(dir.path(), 1, 4, None).unwrap();
+        // One segment the old way, then the default
+        archive.set_path_hash(PathHash::Fx);
+        for seq in 0..4u64 {
+            let path = format!("app.bsky.feed.post/{}", seq);
+            archive.ingest(seq, "did:plc:alice", path.clone(), frame("did:plc:alice", seq, &path));
+        }
+        archive.set_path_hash(PathHash::default());
+        for seq in 4..8u64 {
+            let path = format!("app.bsky.feed.post/{}", seq);
+            archive.ingest(seq, "did:plc:alice", path.clone(), frame("did:plc:alice", seq, &path));
+        }
+        archive.shutdown();
+        assert_eq!(fs::read(dir.path().join("shard_0/s0_4.phash")).unwrap(), [PathHash::Blake3.id()]);
+
+        let reader = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        for seq in [1, 6] {
+            assert_eq!(reader.get_record_entry("did:plc:alice", "app.bsky.feed.post", &seq.to_string()).unwrap().0, seq);
+            assert_eq!(reader.delete_by_path("did:plc:alice", &format!("app.bsky.feed.post/{}", seq)), Some(seq));
+        }
+        assert!(reader.get_message_by_seq(1).is_err() && reader.get_message_by_seq(6).is_err());
+        assert!(reader.get_message_by_seq(5).is_ok());
+        assert_eq!("fxhash".parse::<PathHash>(), Ok(PathHash::Fx));
+    }
+}
@@ -0,0 +1,125 @@
+#[cfg(test)]
+mod pds_ledger_tests {
+    use did_mmap_cache::pds_ledger::{PdsEntry, PdsLedger, ENTRY_SIZE, URL_MAX_LEN};
+    use std::fs::{self, OpenOptions};
+    use std::io::{Seek, SeekFrom, Write};
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    fn write_ledger(path: &Path, n: usize) {
+        let mut ledger = PdsLedger::open_or_create(path).unwrap();
+        for i in 0..n {
+            ledger.append(&PdsEntry::new(&format!("wss://pds{}.example", i)).unwrap()).unwrap();
+        }
+        ledger.flush().unwrap();
+    }
+
+    /// Byte offset of entry `index`, past the one-entry header.
+    fn entry_offset(index: usize) -> u64 {
+        ((index + 1) * ENTRY_SIZE) as u64
+    }
+
+    #[test]
+    fn test_repair_clears_torn_last_entry() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("pds_list.bin");
+        write_ledger(&path, 4);
+
+        // A half-finished write: the URL made it, the counters are garbage
+        let mut file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.seek(SeekFrom::Start(entry_offset(3) + 200)).unwrap();
+        file.write_all(&[0xab; 40]).unwrap();
+        drop(file);
+
+        let mut ledger = PdsLedger::open_or_create(&path).unwrap();
+        assert_eq!(ledger.entry_count(), 4);
+        assert!(ledger.get_entry(3).is_none());
+        assert!(ledger.get_entry_mut(3).is_none());
+        assert_eq!(ledger.get_entry(2).unwrap().get_url(), "wss://pds2.example");
+
+        assert_eq!(ledger.repair().unwrap(), 1);
+        assert_eq!(ledger.entry_count(), 3);
+        assert!((0..3).all(|i| ledger.get_entry(i).is_some()));
+        // The freed slot is reused by the next append
+        assert_eq!(ledger.append(&PdsEntry::new("wss://pds3.example").unwrap()).unwrap(), 3);
+        assert_eq!(ledger.repair().unwrap(), 0);
+    }
+
+    #[test]
+    fn test_repair_truncates_partial_entry() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("pds_list.bin");
+        write_ledger(&path, 2);
+        let len = fs::metadata(&path).unwrap().len();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&[0x77; 100]).unwrap();
+        drop(file);
+
+        let mut ledger = PdsLedger::open_or_create(&path).unwrap();
+        assert_eq!(ledger.entry_count(), 2);
+        assert_eq!(ledger.repair().unwrap(), 0);
+        drop(ledger);
+        assert_eq!(fs::metadata(&path).unwrap().len(), len);
+    }
+
+    #[test]
+    fn test_edits_through_get_entry_mut_stay_valid() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("pds_list.bin");
+        write_ledger(&path, 1);
+        {
+            let mut ledger = PdsLedger::open_or_create(&path).unwrap();
+            let mut entry = ledger.get_entry_mut(0).unwrap();
+            entry.fail_count = 7;
+            entry.penalty_until = 1234;
+            drop(entry);
+            ledger.flush().unwrap();
+        }
+        let ledger = PdsLedger::open_or_create(&path).unwrap();
+        let entry = ledger.get_entry(0).unwrap();
+        assert_eq!((entry.fail_count, entry.penalty_until), (7, 1234));
+    }
+
+    #[test]
+    fn test_headerless_ledger_is_upgraded() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("pds_list.bin");
+        // Version 1: entries from byte 0, no checksums, tail preallocated with zeros
+        let mut v1 = vec![0u8; ENTRY_SIZE * 4];
+        for (i, url) in ["wss://a.example", "wss://b.example"].iter().enumerate() {
+            v1[i * ENTRY_SIZE..i * ENTRY_SIZE + url.len()].copy_from_slice(url.as_bytes());
+            v1[i * ENTRY_SIZE + 200] = 3; // fail_count
+        }
+        fs::write(&path, &v1).unwrap();
+
+        let ledger = PdsLedger::open_or_create(&path).unwrap();
+        assert_eq!(ledger.entry_count(), 2);
+        assert_eq!(ledger.get_entry(1).unwrap().get_url(), "wss://b.example");
+        assert_eq!(ledger.get_entry(1).unwrap().fail_count, 3);
+        drop(ledger);
+        assert_eq!(&fs::read(&path).unwrap()[..8], b"PDSLEDGR");
+        assert_eq!(PdsLedger::open_or_create(&path).unwrap().entry_count(), 2);
+    }
+
+    #[test]
+    fn test_empty_urls_are_not_entries() {
+        let dir = tempdir().unwrap();
+        let mut ledger = PdsLedger::open_or_create(dir.path().join("pds_list.bin")).unwrap();
+        assert_eq!(ledger.append(&PdsEntry::new("  wss://pds.example \n").unwrap()).unwrap(), 0);
+        assert_eq!(ledger.get_entry(0).unwrap().get_url(), "wss://pds.example");
+
+        for url in ["", "   ", "pds.example", "ftp://pds.example", "https://"] {
+            assert!(PdsEntry::new(url).is_none(), "{:?}", url);
+        }
+        // Built around `new`, an empty entry still doesn't get in
+        let mut blank = PdsEntry::new("wss://blank.example").unwrap();
+        blank.url = [b' '; URL_MAX_LEN];
+        blank.url[1] = 0;
+        assert!(ledger.append(&blank).is_err());
+
+        assert_eq!(ledger.entry_count(), 1);
+        assert_eq!(ledger.valid_entry_count(), 1);
+        assert!(ledger.get_entry(1).is_none());
+        assert!(ledger.get_entry_mut(1).is_none());
+    }
+}
@@ -0,0 +1,59 @@
+#[cfg(test)]
+mod pending_index_tests {
+    use did_mmap_cache::archive::{ArchiveError, MultiShardArchive};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use tempfile::tempdir;
+
+    fn ingest(archive: &MultiShardArchive, seqs: std::ops::Range<u64>) {
+        for seq in seqs {
+            archive.ingest(seq, "did:plc:alice", format!("app.bsky.feed.post/{}", seq), format!("message {}", seq).into_bytes());
+        }
+    }
+
+    fn wait_until_persisted(archive: &MultiShardArchive) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !archive.pending_index().is_empty() {
+            assert!(Instant::now() < deadline, "segment never persisted");
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_handed_off_segment_is_readable_until_persisted() {
+        let dir = tempdir().unwrap();
+        let archive = MultiShardArchive::new(dir.path(), 1, 3, None).unwrap();
+
+        let pause = archive.pause_persistence();
+        ingest(&archive, 0..3);
+        assert_eq!(archive.pending_index().len(), 1);
+        assert!(!dir.path().join("shard_0/s0_0.idx").exists());
+        assert_eq!(archive.get_message_by_seq(1).unwrap(), b"message 1");
+        let pending_cluster = archive.get_raw_cluster_at_seq(1).unwrap();
+        // Still in the writer's buffer, not handed off yet
+        ingest(&archive, 3..4);
+        assert!(matches!(archive.get_message_by_seq(3), Err(ArchiveError::OutOfRange)));
+
+        drop(pause);
+        wait_until_persisted(&archive);
+        assert!(dir.path().join("shard_0/s0_0.idx").exists());
+        // Served from the segment now, without an explicit refresh
+        assert_eq!(archive.get_message_by_seq(1).unwrap(), b"message 1");
+        assert_eq!(archive.get_raw_cluster_at_seq(1).unwrap(), pending_cluster);
+        assert_eq!(archive.max_seq(), Some(2));
+        archive.shutdown();
+    }
+
+    #[test]
+    fn test_tombstones_apply_to_pending_segments() {
+        let dir = tempdir().unwrap();
+        let archive = MultiShardArchive::new(dir.path(), 1, 3, None).unwrap();
+        let pause = archive.pause_persistence();
+        ingest(&archive, 0..3);
+        archive.mark_deleted(2).unwrap();
+        assert!(matches!(archive.get_message_by_seq(2), Err(ArchiveError::Tombstoned { seq: 2 })));
+        assert_eq!(archive.get_message_streaming(0).unwrap(), b"message 0");
+        drop(pause);
+        archive.shutdown();
+    }
+}
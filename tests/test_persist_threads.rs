@@ -0,0 +1,56 @@
+#[cfg(test)]
+mod persist_threads_tests {
+    use did_mmap_cache::archive::MultiShardArchive;
+    use std::fs;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    fn segment_count(root: &Path, shards: usize) -> usize {
+        (0..shards)
+            .map(|i| fs::read_dir(root.join(format!("shard_{}", i))).unwrap().flatten().filter(|e| e.path().extension().is_some_and(|x| x == "idx")).count())
+            .sum()
+    }
+
+    fn burst(archive: &MultiShardArchive, seqs: std::ops::Range<u64>) {
+        for seq in seqs {
+            let did = format!("did:plc:user{}", seq % 16);
+            archive.ingest(seq, &did, format!("app.bsky.feed.post/{}", seq), format!("message {}", seq).into_bytes());
+        }
+    }
+
+    #[test]
+    fn test_burst_of_segments_all_persisted() {
+        let dir = tempdir().unwrap();
+        let archive = MultiShardArchive::new(dir.path(), 4, 10, None).unwrap();
+        assert!(archive.persist_threads() >= 1 && archive.persist_threads() <= 4);
+        archive.set_persist_threads(3);
+        assert_eq!(archive.persist_threads(), 3);
+        burst(&archive, 0..400);
+        archive.shutdown();
+
+        assert!(segment_count(dir.path(), 4) > 4);
+        let reader = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        for seq in 0..400u64 {
+            assert_eq!(reader.get_message_by_seq(seq).unwrap(), format!("message {}", seq).into_bytes(), "seq {}", seq);
+        }
+    }
+
+    #[test]
+    fn test_resizing_keeps_queued_segments() {
+        let dir = tempdir().unwrap();
+        let archive = MultiShardArchive::new(dir.path(), 2, 5, None).unwrap();
+        burst(&archive, 0..100);
+        // Shrinking waits for what the old threads had queued
+        archive.set_persist_threads(0);
+        assert_eq!(archive.persist_threads(), 1);
+        burst(&archive, 100..200);
+        archive.shutdown();
+        assert_eq!(archive.persist_threads(), 0);
+
+        let reader = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        assert_eq!((reader.min_seq(), reader.max_seq()), (Some(0), Some(199)));
+        for seq in 0..200u64 {
+            assert!(reader.get_message_by_seq(seq).is_ok(), "seq {}", seq);
+        }
+    }
+}
@@ -0,0 +1,141 @@
+#[cfg(test)]
+mod plc_build_tests {
+    use did_mmap_cache::mmap_did_cache::MmapDidCache;
+    use did_mmap_cache::plc::{build_batch, build_streaming, compare_sampled, sample_dids, BuildProgress};
+    use did_mmap_cache::resolver::decode_key_string;
+    use sha2::{Digest, Sha256};
+    use std::io::Cursor;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    const K1: &str = "did:key:zQ3shokFTS3brHcDQrn82RUDfCZESWL1ZdCEJwekUDPQiYBme";
+    const P256: &str = "did:key:zDnaerDaTF5BXEavCrfRZEk316dpbLsfPDZ3WJ5hRTPFU2169";
+    const SLOTS: usize = 1024;
+
+    fn op(did: &str, key: &str) -> String {
+        format!(r#"{{"did":"{}","operation":{{"verificationMethods":{{"atproto":"{}"}}}}}}"#, did, key)
+    }
+
+    fn nullified(did: &str, key: &str) -> String {
+        format!(r#"{{"did":"{}","operation":{{"signingKey":"{}"}},"nullified":true}}"#, did, key)
+    }
+
+    /// rotated: K1 then P256. revoked: nullified after its first op, then a later op.
+    /// stable: one op. Plus a line that isn't a record.
+    fn dump() -> String {
+        [
+            op("did:plc:rotated", K1),
+            op("did:plc:revoked", K1),
+            op("did:plc:stable", P256),
+            "not json".to_string(),
+            nullified("did:plc:revoked", P256),
+            op("did:plc:rotated", P256),
+            op("did:plc:revoked", P256),
+        ]
+        .iter()
+        .map(|l| format!("{}\n", l))
+        .collect()
+    }
+
+    fn batch(dir: &Path, input: &str) -> MmapDidCache {
+        let mut cache = MmapDidCache::create(dir.join("batch.bin"), SLOTS).unwrap();
+        build_batch(Cursor::new(input), &mut cache, |_| {}).unwrap();
+        cache
+    }
+
+    fn streaming(dir: &Path, input: &str) -> MmapDidCache {
+        let mut cache = MmapDidCache::create(dir.join("stream.bin"), SLOTS).unwrap();
+        build_streaming(Cursor::new(input), &mut cache, BuildProgress::default(), 0, |_| Ok(())).unwrap();
+        cache
+    }
+
+    fn key(s: &str) -> Option<([u8; 33], u8)> {
+        decode_key_string(s)
+    }
+
+    #[test]
+    fn test_rotation_and_nullification_match_across_modes() {
+        let dir = tempdir().unwrap();
+        let input = dump();
+        for cache in [batch(dir.path(), &input), streaming(dir.path(), &input)] {
+            assert_eq!(cache.get("did:plc:rotated"), key(P256));
+            assert_eq!(cache.get("did:plc:stable"), key(P256));
+            // The op after the nullified one doesn't bring the DID back
+            assert_eq!(cache.get("did:plc:revoked"), None);
+            assert_eq!(cache.get("did:plc:never"), None);
+        }
+
+        let stream = MmapDidCache::open(dir.path().join("stream.bin")).unwrap();
+        let revoked: [u8; 32] = Sha256::digest(b"did:plc:revoked").into();
+        assert!(stream.is_tombstoned_hashed(&revoked));
+    }
+
+    #[test]
+    fn test_progress_counts_bytes_and_operations() {
+        let dir = tempdir().unwrap();
+        let input = dump();
+        let mut cache = MmapDidCache::create(dir.path().join("c.bin"), SLOTS).unwrap();
+        let mut checkpoints = Vec::new();
+        let done = build_streaming(Cursor::new(&input), &mut cache, BuildProgress::default(), 2, |p| {
+            checkpoints.push(*p);
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(done.offset, input.len() as u64);
+        assert_eq!((done.operations, done.nullified), (5, 1));
+        // Four key writes and one tombstone; the post-nullification op writes nothing
+        assert_eq!(done.writes, 5);
+        // Every second record, then once more at the end
+        assert_eq!(checkpoints.len(), 4);
+        assert_eq!(*checkpoints.last().unwrap(), done);
+        assert!(checkpoints.windows(2).all(|w| w[0].offset <= w[1].offset));
+
+        let batch = build_batch(Cursor::new(&input), &mut MmapDidCache::create(dir.path().join("b.bin"), SLOTS).unwrap(), |_| {}).unwrap();
+        assert_eq!((batch.offset, batch.operations, batch.nullified, batch.writes), (done.offset, 5, 1, 2));
+    }
+
+    #[test]
+    fn test_resume_from_checkpoint_matches_one_shot_build() {
+        let dir = tempdir().unwrap();
+        let input = dump();
+        let checkpoint = dir.path().join("c.bin.progress");
+
+        // Interrupted after the fifth line (the nullification): only that prefix was read
+        let cut = input.match_indices('\n').nth(4).unwrap().0 + 1;
+        let mut cache = MmapDidCache::create(dir.path().join("c.bin"), SLOTS).unwrap();
+        build_streaming(Cursor::new(&input[..cut]), &mut cache, BuildProgress::default(), 1, |p| p.save(&checkpoint)).unwrap();
+        drop(cache);
+
+        let resume = BuildProgress::load(&checkpoint).unwrap().unwrap();
+        assert_eq!(resume.offset, cut as u64);
+        let mut cache = MmapDidCache::open_mut(dir.path().join("c.bin")).unwrap();
+        let done = build_streaming(Cursor::new(&input[cut..]), &mut cache, resume, 1, |_| Ok(())).unwrap();
+        assert_eq!(done.offset, input.len() as u64);
+
+        let one_shot = streaming(dir.path(), &input);
+        let dids: Vec<String> = ["did:plc:rotated", "did:plc:revoked", "did:plc:stable"].map(String::from).to_vec();
+        assert!(compare_sampled(&cache, &one_shot, &dids).is_empty());
+        assert_eq!(cache.get("did:plc:revoked"), None);
+
+        assert_eq!(BuildProgress::load(dir.path().join("missing.progress")).unwrap(), None);
+    }
+
+    #[test]
+    fn test_sampled_comparison_finds_differences() {
+        let dir = tempdir().unwrap();
+        let input = dump();
+        let (b, mut s) = (batch(dir.path(), &input), streaming(dir.path(), &input));
+
+        let all = sample_dids(Cursor::new(&input), 100).unwrap();
+        assert_eq!(all, ["did:plc:revoked", "did:plc:rotated", "did:plc:stable"]);
+        assert!(sample_dids(Cursor::new(&input), 0).unwrap().is_empty());
+        let some = sample_dids(Cursor::new(&input), 2).unwrap();
+        assert!(!some.is_empty() && some.len() <= 2);
+
+        assert!(compare_sampled(&b, &s, &all).is_empty());
+        let (pk, kt) = key(K1).unwrap();
+        assert!(s.atomic_update_or_tombstone("did:plc:stable", Some(kt), Some(&pk)));
+        assert_eq!(compare_sampled(&b, &s, &all), ["did:plc:stable"]);
+    }
+}
@@ -0,0 +1,79 @@
+#[cfg(test)]
+mod plc_directory_base_tests {
+    use did_mmap_cache::resolver::{
+        did_key_to_raw_pubkey, plc_directory_base, resolve_did, resolve_did_with, resolve_handle, resolve_handle_with,
+        ResolverConfig, PLC_DIRECTORY, PLC_DIRECTORY_ENV,
+    };
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    const K1: &str = "did:key:zQ3shokFTS3brHcDQrn82RUDfCZESWL1ZdCEJwekUDPQiYBme";
+
+    /// Answers `/{did}/log/last` and `/{did}/data` for any did:plc with K1 and the handle
+    /// `alice.test`. Returns the base URL and the paths requested.
+    fn mock_plc() -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&requests);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let target = request_line.split(' ').nth(1).unwrap_or_default().to_string();
+                seen.lock().unwrap().push(target.clone());
+
+                let body = if target.ends_with("/log/last") {
+                    format!(r#"{{"verificationMethods":{{"atproto":"{}"}}}}"#, K1)
+                } else {
+                    r#"{"alsoKnownAs":["at://alice.test"]}"#.to_string()
+                };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (format!("http://{}", addr), requests)
+    }
+
+    // One test, since it sets a process-wide environment variable
+    #[test]
+    fn test_lookups_go_to_the_configured_directory() {
+        let (base, requests) = mock_plc();
+        let key = did_key_to_raw_pubkey(K1);
+        assert!(key.is_some());
+
+        // Through the config; a trailing slash is dropped
+        let config = ResolverConfig { plc_directory: Some(format!("{}/", base)), ..Default::default() };
+        assert_eq!(resolve_did_with("did:plc:alice", &config), key);
+        assert_eq!(resolve_handle_with("did:plc:alice", &config).as_deref(), Some("alice.test"));
+        assert_eq!(*requests.lock().unwrap(), ["/did:plc:alice/log/last", "/did:plc:alice/data"]);
+
+        // Through the environment, which the default config and the ingestors fall back to
+        std::env::remove_var(PLC_DIRECTORY_ENV);
+        assert_eq!(plc_directory_base(), PLC_DIRECTORY);
+        std::env::set_var(PLC_DIRECTORY_ENV, &base);
+        assert_eq!(plc_directory_base(), base);
+        assert_eq!(ResolverConfig::default().plc_base(), base);
+        assert_eq!(resolve_did("did:plc:bob"), key);
+        assert_eq!(resolve_handle("did:plc:bob").as_deref(), Some("alice.test"));
+        assert_eq!(requests.lock().unwrap()[2..], ["/did:plc:bob/log/last", "/did:plc:bob/data"]);
+
+        // A config's own directory still wins
+        let other = ResolverConfig { plc_directory: Some("http://127.0.0.1:9".to_string()), ..Default::default() };
+        assert_eq!(other.plc_base(), "http://127.0.0.1:9");
+        std::env::set_var(PLC_DIRECTORY_ENV, "");
+        assert_eq!(plc_directory_base(), PLC_DIRECTORY);
+        std::env::remove_var(PLC_DIRECTORY_ENV);
+    }
+}
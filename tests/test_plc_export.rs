@@ -0,0 +1,187 @@
+#[cfg(test)]
+mod plc_export_tests {
+    use did_mmap_cache::mmap_did_cache::MmapDidCache;
+    use did_mmap_cache::plc::{apply_to_cache, ExportStream, PlcExportError, RateLimit};
+    use did_mmap_cache::resolver::decode_key_string;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    const K1: &str = "did:key:zQ3shokFTS3brHcDQrn82RUDfCZESWL1ZdCEJwekUDPQiYBme";
+    const P256: &str = "did:key:zDnaerDaTF5BXEavCrfRZEk316dpbLsfPDZ3WJ5hRTPFU2169";
+
+    fn ts(i: usize) -> String {
+        format!("2024-01-01T00:00:{:02}.000Z", i)
+    }
+
+    /// alice: K1 with a PDS, then nullified, then P256. bob: P256 next to a key that doesn't
+    /// decode, then K1. carol: a legacy `signingKey`. Plus a line that isn't a record.
+    fn fixture() -> Vec<(String, String)> {
+        let ops = [
+            r#""did":"did:plc:alice","operation":{"verificationMethods":{"atproto":"K1"},"services":{"atproto_pds":{"endpoint":"https://pds.alice.example"}}}"#,
+            r#""did":"did:plc:bob","operation":{"verificationMethods":{"atproto":"P256","zzz":"did:key:zBogus"}}"#,
+            r#""did":"did:plc:carol","operation":{"signingKey":"K1"}"#,
+            r#""did":"did:plc:alice","operation":{"signingKey":"P256"},"nullified":true"#,
+            r#""did":"did:plc:alice","operation":{"verificationMethods":{"atproto":"P256"}}"#,
+            r#""did":"did:plc:bob","operation":{"verificationMethods":{"atproto":"K1"}}"#,
+        ];
+        let mut lines: Vec<(String, String)> = ops
+            .iter()
+            .enumerate()
+            .map(|(i, op)| (ts(i), format!(r#"{{{},"createdAt":"{}"}}"#, op.replace("P256", P256).replace("K1", K1), ts(i))))
+            .collect();
+        // Sorts right after the third record
+        lines.insert(3, (format!("{}x", ts(2)), "not json".to_string()));
+        lines
+    }
+
+    /// Serves `/export` pages of `lines`, each listed under the `createdAt` it sorts at.
+    /// Requests are answered with the next status in `script` instead while there is one.
+    /// Returns the base URL and the requests seen.
+    fn mock_plc(lines: Vec<(String, String)>, script: Vec<u16>) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&requests);
+        thread::spawn(move || {
+            let mut script = script.into_iter();
+            for stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let target = request_line.split(' ').nth(1).unwrap_or_default().to_string();
+                seen.lock().unwrap().push(target.clone());
+
+                let (status, body) = match script.next() {
+                    Some(status) => (status, String::new()),
+                    None => {
+                        let param = |name: &str| {
+                            target.split(['?', '&']).find_map(|p| p.strip_prefix(name)).unwrap_or_default().to_string()
+                        };
+                        let count: usize = param("count=").parse().unwrap();
+                        let after = param("after=");
+                        let page = lines.iter().filter(|(ts, _)| *ts > after).take(count);
+                        (200, page.map(|(_, l)| format!("{}\n", l)).collect())
+                    }
+                };
+                let response = format!(
+                    "HTTP/1.1 {} X\r\nContent-Type: application/jsonlines\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+            }
+        });
+        (format!("http://{}", addr), requests)
+    }
+
+    fn fast() -> RateLimit {
+        RateLimit {
+            initial_delay: Duration::from_millis(20),
+            floor: Duration::from_millis(5),
+            cooldown: Duration::from_millis(10),
+            error_backoff: Duration::from_millis(10),
+            network_backoff: Duration::from_millis(10),
+            tip_wait: Duration::from_millis(10),
+        }
+    }
+
+    #[test]
+    fn test_pages_parse_and_resume() {
+        let dir = tempdir().unwrap();
+        let cursor = dir.path().join("plc.cursor");
+        let (base, requests) = mock_plc(fixture(), vec![]);
+
+        let mut stream = ExportStream::new(&base, "2023-01-01T00:00:00.000Z")
+            .with_cursor_file(&cursor)
+            .unwrap()
+            .page_size(2)
+            .rate_limit(fast());
+        let first: Vec<_> = stream.by_ref().take(3).map(Result::unwrap).collect();
+        assert_eq!(first[0].did, "did:plc:alice");
+        assert_eq!(first[0].pds_endpoint.as_deref(), Some("https://pds.alice.example"));
+        assert_eq!(first[1].signing_keys, vec![P256.to_string(), "did:key:zBogus".to_string()]);
+        assert_eq!(first[1].decoded_key().map(|(t, k)| (k, t)), decode_key_string(P256));
+        assert_eq!(first[2].signing_keys, vec![K1.to_string()]);
+        assert!(!first.iter().any(|op| op.nullified));
+        assert_eq!(stream.cursor(), ts(2));
+        stream.checkpoint().unwrap();
+        assert_eq!(std::fs::read_to_string(&cursor).unwrap().trim(), ts(2));
+
+        // A new stream picks up after the checkpoint, past the line that isn't a record
+        let rest: Vec<_> = ExportStream::new(&base, "ignored")
+            .with_cursor_file(&cursor)
+            .unwrap()
+            .page_size(2)
+            .rate_limit(fast())
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(rest.iter().map(|op| op.created_at.clone()).collect::<Vec<_>>(), vec![ts(3), ts(4), ts(5)]);
+        assert!(rest[0].nullified);
+        assert!(requests.lock().unwrap().iter().any(|r| r.contains(&format!("after={}", ts(2)))));
+    }
+
+    #[test]
+    fn test_apply_until_then_catch_up() {
+        let dir = tempdir().unwrap();
+        let cursor = dir.path().join("plc.cursor");
+        let mut cache = MmapDidCache::create(dir.path().join("cache.bin"), 1024).unwrap();
+        let (base, _) = mock_plc(fixture(), vec![]);
+        let stream = || {
+            ExportStream::new(&base, "2023-01-01T00:00:00.000Z")
+                .with_cursor_file(&cursor)
+                .unwrap()
+                .page_size(2)
+                .rate_limit(fast())
+        };
+
+        let until = chrono::DateTime::parse_from_rfc3339(&ts(2)).unwrap().to_utc();
+        let stats = apply_to_cache(&mut stream(), &mut cache, Some(until)).unwrap();
+        assert_eq!((stats.operations, stats.nullified, stats.writes, stats.no_key), (3, 0, 3, 0));
+        assert_eq!(std::fs::read_to_string(&cursor).unwrap().trim(), ts(2));
+        assert_eq!(cache.get("did:plc:alice"), decode_key_string(K1));
+        assert_eq!(cache.get("did:plc:bob"), decode_key_string(P256));
+        assert_eq!(cache.get("did:plc:carol"), decode_key_string(K1));
+
+        // The nullified op tombstones alice for good; bob rotates
+        let stats = apply_to_cache(&mut stream(), &mut cache, None).unwrap();
+        assert_eq!((stats.operations, stats.nullified, stats.writes), (2, 1, 2));
+        assert_eq!(std::fs::read_to_string(&cursor).unwrap().trim(), ts(5));
+        assert_eq!(cache.get("did:plc:alice"), None);
+        assert_eq!(cache.get("did:plc:bob"), decode_key_string(K1));
+
+        // Caught up: nothing to apply
+        let stats = apply_to_cache(&mut stream(), &mut cache, None).unwrap();
+        assert_eq!(stats.writes, 0);
+    }
+
+    #[test]
+    fn test_rate_limits_and_errors() {
+        // Two 429s and a 503 are retried, and the 429s slow the stream down
+        let (base, requests) = mock_plc(fixture(), vec![429, 503, 429]);
+        let mut stream = ExportStream::new(&base, "2023-01-01T00:00:00.000Z").page_size(10).rate_limit(fast());
+        assert_eq!(stream.by_ref().map(Result::unwrap).count(), 6);
+        assert_eq!(stream.skipped(), 1);
+        assert!(stream.delay() > fast().initial_delay, "delay {:?}", stream.delay());
+        // The three failures, the page, and the empty page at the tip
+        assert_eq!(requests.lock().unwrap().len(), 5);
+
+        let (base, _) = mock_plc(fixture(), vec![429; 10]);
+        let mut stream = ExportStream::new(&base, "2023-01-01T00:00:00.000Z").rate_limit(fast()).max_retries(Some(2));
+        assert!(matches!(stream.next(), Some(Err(PlcExportError::RateLimited))));
+
+        // A 4xx isn't retried
+        let (base, requests) = mock_plc(fixture(), vec![400]);
+        let mut stream = ExportStream::new(&base, "2023-01-01T00:00:00.000Z").rate_limit(fast());
+        assert!(matches!(stream.next(), Some(Err(PlcExportError::Status(400)))));
+        assert_eq!(requests.lock().unwrap().len(), 1);
+    }
+}
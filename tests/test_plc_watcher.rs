@@ -0,0 +1,133 @@
+#[cfg(test)]
+mod plc_watcher_tests {
+    use did_mmap_cache::mmap_did_cache::MmapDidCache;
+    use did_mmap_cache::plc::{ExportStream, RateLimit, RotationEvent, RotationWatcher};
+    use did_mmap_cache::resolver::decode_key_string;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, RwLock};
+    use std::thread;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    const K1: &str = "did:key:zQ3shokFTS3brHcDQrn82RUDfCZESWL1ZdCEJwekUDPQiYBme";
+    const P256: &str = "did:key:zDnaerDaTF5BXEavCrfRZEk316dpbLsfPDZ3WJ5hRTPFU2169";
+
+    fn ts(i: usize) -> String {
+        format!("2024-01-01T00:00:{:02}.000Z", i)
+    }
+
+    /// alice rotates K1 -> P256, bob (K1) is nullified, carol is new, dave repeats his key,
+    /// and bob's later operation must not bring him back.
+    fn fixture() -> Vec<(String, String)> {
+        let ops = [
+            r#""did":"did:plc:alice","operation":{"verificationMethods":{"atproto":"P256"}}"#,
+            r#""did":"did:plc:bob","operation":{"verificationMethods":{"atproto":"P256"}},"nullified":true"#,
+            r#""did":"did:plc:carol","operation":{"verificationMethods":{"atproto":"K1"}}"#,
+            r#""did":"did:plc:dave","operation":{"verificationMethods":{"atproto":"K1"}}"#,
+            r#""did":"did:plc:bob","operation":{"verificationMethods":{"atproto":"P256"}}"#,
+        ];
+        ops.iter()
+            .enumerate()
+            .map(|(i, op)| (ts(i), format!(r#"{{{},"createdAt":"{}"}}"#, op.replace("P256", P256).replace("K1", K1), ts(i))))
+            .collect()
+    }
+
+    /// Serves `/export` pages of `lines` after the requested `createdAt`.
+    fn mock_plc(lines: Vec<(String, String)>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let target = request_line.split(' ').nth(1).unwrap_or_default().to_string();
+                let param = |name: &str| target.split(['?', '&']).find_map(|p| p.strip_prefix(name)).unwrap_or_default().to_string();
+                let count: usize = param("count=").parse().unwrap();
+                let after = param("after=");
+                let body: String = lines.iter().filter(|(ts, _)| *ts > after).take(count).map(|(_, l)| format!("{}\n", l)).collect();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/jsonlines\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                reader.get_mut().write_all(response.as_bytes()).unwrap();
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    fn fast() -> RateLimit {
+        RateLimit {
+            initial_delay: Duration::from_millis(5),
+            floor: Duration::from_millis(5),
+            cooldown: Duration::from_millis(10),
+            error_backoff: Duration::from_millis(10),
+            network_backoff: Duration::from_millis(10),
+            tip_wait: Duration::from_millis(10),
+        }
+    }
+
+    #[test]
+    fn test_watcher_applies_rotations_and_nullifications() {
+        let dir = tempdir().unwrap();
+        let (k1, k1_type) = decode_key_string(K1).unwrap();
+        let (p256, p256_type) = decode_key_string(P256).unwrap();
+        let mut cache = MmapDidCache::create(dir.path().join("cache.bin"), 1024).unwrap();
+        for did in ["did:plc:alice", "did:plc:bob", "did:plc:dave"] {
+            assert!(cache.atomic_update_or_tombstone(did, Some(k1_type), Some(&k1)));
+        }
+        let cache = Arc::new(RwLock::new(cache));
+
+        let cursor = dir.path().join("plc_watch.cursor");
+        let stream = ExportStream::new(&mock_plc(fixture()), "2023-01-01T00:00:00.000Z")
+            .with_cursor_file(&cursor)
+            .unwrap()
+            .page_size(2)
+            .rate_limit(fast());
+        let (watcher, rotations) = RotationWatcher::new(stream, Arc::clone(&cache));
+        let stats = watcher.lag(Duration::ZERO).spawn().unwrap().join().unwrap().unwrap();
+
+        let prefix = hex::encode(&k1[..4]);
+        let events: Vec<RotationEvent> = rotations.iter().collect();
+        assert_eq!(
+            events,
+            vec![
+                RotationEvent { did: "did:plc:alice".into(), old_key_prefix: prefix.clone(), new_key_type: Some(p256_type) },
+                RotationEvent { did: "did:plc:bob".into(), old_key_prefix: prefix, new_key_type: None },
+            ]
+        );
+        assert_eq!((stats.operations, stats.nullified, stats.rotations, stats.new_dids, stats.writes), (4, 1, 2, 1, 3));
+
+        let cache = cache.read().unwrap();
+        assert_eq!(cache.get("did:plc:alice"), Some((p256, p256_type)));
+        assert_eq!(cache.get("did:plc:bob"), None);
+        assert_eq!(cache.get("did:plc:carol"), Some((k1, k1_type)));
+        assert_eq!(cache.get("did:plc:dave"), Some((k1, k1_type)));
+        assert_eq!(std::fs::read_to_string(&cursor).unwrap().trim(), ts(4));
+    }
+
+    #[test]
+    fn test_operations_younger_than_the_lag_wait() {
+        let dir = tempdir().unwrap();
+        let cache = Arc::new(RwLock::new(MmapDidCache::create(dir.path().join("cache.bin"), 1024).unwrap()));
+        let future = "2999-01-01T00:00:00.000Z".to_string();
+        let line = format!(r#"{{"did":"did:plc:late","operation":{{"signingKey":"{}"}},"createdAt":"{}"}}"#, K1, future);
+        let stream = ExportStream::new(&mock_plc(vec![(future, line)]), "2023-01-01T00:00:00.000Z").rate_limit(fast());
+
+        let running = Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let (watcher, _rotations) = RotationWatcher::new(stream, Arc::clone(&cache));
+        let handle = watcher.lag(Duration::from_millis(10)).with_running_flag(Arc::clone(&running)).spawn().unwrap();
+        thread::sleep(Duration::from_millis(200));
+        assert!(!handle.is_finished());
+        running.store(false, std::sync::atomic::Ordering::SeqCst);
+        let stats = handle.join().unwrap().unwrap();
+        assert_eq!(stats.operations, 0);
+        assert_eq!(cache.read().unwrap().get("did:plc:late"), None);
+    }
+}
@@ -0,0 +1,100 @@
+#[cfg(test)]
+mod portability_tests {
+    //! Round trips through the mmap-backed stores using nothing platform specific,
+    //! so the same suite can run on Linux, macOS and Windows CI.
+    use did_mmap_cache::archive::{MultiShardArchive, TombstoneStore};
+    use did_mmap_cache::mmap_did_cache::MmapDidCache;
+    use did_mmap_cache::pds_ledger::{PdsEntry, PdsLedger};
+    use did_mmap_cache::platform::{grow_mapped, set_len_sparse};
+    use memmap2::MmapMut;
+    use std::fs::OpenOptions;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_archive_reopen_round_trip() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("archive");
+
+        let archive = MultiShardArchive::new(&root, 2, 5, None).unwrap();
+        for seq in 1..=23u64 {
+            let did = format!("did:plc:user{}", seq % 3);
+            archive.ingest(seq, &did, format!("app.bsky.feed.post/{}", seq), format!("frame {}", seq).into_bytes());
+        }
+        archive.shutdown();
+        drop(archive);
+
+        let reader = MultiShardArchive::open_readonly(&root, None).unwrap();
+        assert_eq!(reader.min_seq(), Some(1));
+        assert_eq!(reader.get_message_by_seq(17).unwrap(), b"frame 17");
+        reader.mark_deleted(17).unwrap();
+        assert!(reader.get_message_by_seq(17).is_err());
+        drop(reader);
+
+        // Tombstones live in their own mapping and must survive a reopen
+        let reader = MultiShardArchive::open_readonly(&root, None).unwrap();
+        assert!(reader.get_message_by_seq(17).is_err());
+        assert_eq!(reader.get_message_by_seq(23).unwrap(), b"frame 23");
+    }
+
+    #[test]
+    fn test_tombstone_store_reopen() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("tombstones.bin");
+        {
+            let mut store = TombstoneStore::open_or_create(&path).unwrap();
+            store.mark_deleted(4_000_000_000).unwrap();
+            store.mark_deleted(9).unwrap();
+        }
+        let store = TombstoneStore::open_or_create(&path).unwrap();
+        assert!(store.is_deleted(9) && store.is_deleted(4_000_000_000));
+        assert!(!store.is_deleted(10));
+    }
+
+    #[test]
+    fn test_cache_create_update_reopen() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("cache.bin");
+        let key = [0x02; 33];
+        {
+            let mut cache = MmapDidCache::create(&path, 1024).unwrap();
+            assert!(cache.atomic_update_or_tombstone("did:plc:alice", Some(1), Some(&key)));
+        }
+        let cache = MmapDidCache::open(&path).unwrap();
+        assert_eq!(cache.num_slots(), 1024);
+        assert_eq!(cache.get("did:plc:alice"), Some((key, 1)));
+        assert_eq!(cache.get("did:plc:bob"), None);
+    }
+
+    #[test]
+    fn test_ledger_grows_while_mapped() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("pds_ledger.bin");
+        {
+            let mut ledger = PdsLedger::open_or_create(&path).unwrap();
+            // The first entry fills the initial mapping, the second forces a resize
+            for i in 0..3 {
+                let idx = ledger.append(&PdsEntry::new(&format!("https://pds{}.example", i)).unwrap()).unwrap();
+                assert_eq!(idx, i);
+            }
+            ledger.flush().unwrap();
+        }
+        let ledger = PdsLedger::open_or_create(&path).unwrap();
+        assert_eq!(ledger.entry_count(), 3);
+        assert_eq!(ledger.get_entry(2).unwrap().get_url(), "https://pds2.example");
+    }
+
+    #[test]
+    fn test_grow_mapped_keeps_contents() {
+        let dir = tempdir().unwrap();
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true)
+            .open(dir.path().join("grow.bin")).unwrap();
+        set_len_sparse(&file, 16).unwrap();
+        let mut mmap = unsafe { MmapMut::map_mut(&file).unwrap() };
+        mmap[..4].copy_from_slice(b"CVL1");
+
+        grow_mapped(&file, &mut mmap, 1 << 20).unwrap();
+        assert_eq!(mmap.len(), 1 << 20);
+        assert_eq!(&mmap[..4], b"CVL1");
+        assert!(mmap[16..].iter().all(|&b| b == 0));
+    }
+}
@@ -0,0 +1,168 @@
+#[cfg(test)]
+mod records_tests {
+    use did_mmap_cache::parser::records::{decode_record, RecordView, ReplyRef};
+    use did_mmap_cache::testutil::cbor::{head, text};
+
+    enum V<'a> {
+        T(&'a str),
+        A(Vec<&'a str>),
+        M(Vec<(&'a str, V<'a>)>),
+        /// Pre-encoded CBOR, for types the variants above don't cover
+        Raw(Vec<u8>),
+    }
+
+    fn enc(v: &V, out: &mut Vec<u8>) {
+        match v {
+            V::T(s) => text(s, out),
+            V::A(items) => {
+                head(4, items.len() as u64, out);
+                for i in items { text(i, out); }
+            }
+            V::M(pairs) => {
+                // DAG-CBOR key order: length first, then bytewise
+                let mut sorted: Vec<&(&str, V)> = pairs.iter().collect();
+                sorted.sort_by(|a, b| a.0.len().cmp(&b.0.len()).then(a.0.cmp(b.0)));
+                head(5, sorted.len() as u64, out);
+                for (k, val) in sorted {
+                    text(k, out);
+                    enc(val, out);
+                }
+            }
+            V::Raw(bytes) => out.extend_from_slice(bytes),
+        }
+    }
+
+    fn block(v: V) -> Vec<u8> {
+        let mut out = Vec::new();
+        enc(&v, &mut out);
+        out
+    }
+
+    const POST_URI: &str = "at://did:plc:z72i7hdynmk6r22z27h6tvur/app.bsky.feed.post/3kgx2uqq7fl2a";
+    const POST_CID: &str = "bafyreig7ox2b5kmcqjjspzhlenbhhcnqv3fq2uqisd5ixosft2qkyj524e";
+
+    #[test]
+    fn test_decode_post_with_emoji_and_cjk() {
+        let raw = block(V::M(vec![
+            ("$type", V::T("app.bsky.feed.post")),
+            ("text", V::T("おはようございます 🌅 今日もいい天気 ☀️ 你好世界")),
+            ("langs", V::A(vec!["ja", "zh"])),
+            ("createdAt", V::T("2024-01-15T08:30:00.000Z")),
+        ]));
+
+        let view = decode_record(&raw).expect("post should decode");
+        assert_eq!(view, RecordView::Post {
+            text: "おはようございます 🌅 今日もいい天気 ☀️ 你好世界".to_string(),
+            created_at: Some("2024-01-15T08:30:00.000Z".to_string()),
+            reply: None,
+            langs: vec!["ja".to_string(), "zh".to_string()],
+        });
+        assert!(view.to_string().contains("🌅"));
+    }
+
+    #[test]
+    fn test_floats_do_not_desync_map_walk() {
+        let f16 = vec![0xf9, 0x3c, 0x00]; // 1.0
+        let mut f32 = vec![0xfa];
+        f32.extend_from_slice(&51.5074f32.to_be_bytes());
+        let mut f64 = vec![0xfb];
+        f64.extend_from_slice(&(-0.1278f64).to_be_bytes());
+
+        let raw = block(V::M(vec![
+            ("$type", V::T("app.bsky.feed.post")),
+            ("lat", V::Raw(f32)),
+            ("z", V::Raw(f16)),
+            ("lng", V::Raw(f64)),
+            ("text", V::T("checking in from the map")),
+        ]));
+
+        match decode_record(&raw) {
+            Some(RecordView::Post { text, .. }) => assert_eq!(text, "checking in from the map"),
+            other => panic!("expected post, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_decode_reply_post() {
+        let strong = || V::M(vec![("uri", V::T(POST_URI)), ("cid", V::T(POST_CID))]);
+        let raw = block(V::M(vec![
+            ("$type", V::T("app.bsky.feed.post")),
+            ("text", V::T("agreed 👍")),
+            ("reply", V::M(vec![("root", strong()), ("parent", strong())])),
+            ("createdAt", V::T("2024-01-15T08:31:00.000Z")),
+        ]));
+
+        match decode_record(&raw) {
+            Some(RecordView::Post { text, reply, langs, .. }) => {
+                assert_eq!(text, "agreed 👍");
+                assert_eq!(reply, Some(ReplyRef { root_uri: POST_URI.into(), parent_uri: POST_URI.into() }));
+                assert!(langs.is_empty());
+            }
+            other => panic!("expected post, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_decode_like_follow_profile() {
+        let like = block(V::M(vec![
+            ("$type", V::T("app.bsky.feed.like")),
+            ("subject", V::M(vec![("uri", V::T(POST_URI)), ("cid", V::T(POST_CID))])),
+            ("createdAt", V::T("2024-01-15T08:32:00.000Z")),
+        ]));
+        assert_eq!(decode_record(&like), Some(RecordView::Like {
+            subject_uri: POST_URI.to_string(),
+            subject_cid: Some(POST_CID.to_string()),
+        }));
+
+        let follow = block(V::M(vec![
+            ("$type", V::T("app.bsky.graph.follow")),
+            ("subject", V::T("did:plc:ewvi7nxzyoun6zhxrhs64oiz")),
+            ("createdAt", V::T("2024-01-15T08:33:00.000Z")),
+        ]));
+        assert_eq!(decode_record(&follow), Some(RecordView::Follow {
+            subject: "did:plc:ewvi7nxzyoun6zhxrhs64oiz".to_string(),
+        }));
+
+        let profile = block(V::M(vec![
+            ("$type", V::T("app.bsky.actor.profile")),
+            ("displayName", V::T("Ünïcødé 名前")),
+            ("description", V::T("line one\nline two")),
+        ]));
+        let view = decode_record(&profile).unwrap();
+        assert_eq!(view, RecordView::Profile {
+            display_name: Some("Ünïcødé 名前".to_string()),
+            description: Some("line one\nline two".to_string()),
+        });
+        assert!(!view.to_string().contains('\n'));
+    }
+
+    #[test]
+    fn test_unknown_and_non_records() {
+        let repost = block(V::M(vec![
+            ("$type", V::T("app.bsky.feed.repost")),
+            ("createdAt", V::T("2024-01-15T08:34:00.000Z")),
+        ]));
+        assert_eq!(decode_record(&repost), Some(RecordView::Unknown {
+            type_str: "app.bsky.feed.repost".to_string(),
+        }));
+
+        // Commit objects / MST nodes have no $type
+        let commit = block(V::M(vec![("did", V::T("did:plc:abc")), ("rev", V::T("3kgx"))]));
+        assert_eq!(decode_record(&commit), None);
+
+        // Truncated input never panics
+        let post = block(V::M(vec![("$type", V::T("app.bsky.feed.post")), ("text", V::T("hello"))]));
+        for cut in 0..post.len() {
+            let _ = decode_record(&post[..cut]);
+        }
+    }
+
+    #[test]
+    fn test_long_text_truncates_on_char_boundary() {
+        let long: String = "漢".repeat(500);
+        let raw = block(V::M(vec![("$type", V::T("app.bsky.feed.post")), ("text", V::T(&long))]));
+        let summary = decode_record(&raw).unwrap().to_string();
+        assert!(summary.ends_with("..."));
+        assert_eq!(summary.chars().filter(|&c| c == '漢').count(), 120);
+    }
+}
@@ -0,0 +1,327 @@
+#[cfg(test)]
+mod relay_cluster_tests {
+    use did_mmap_cache::archive::{decode_cluster, dict_hash, split_cluster, MultiShardArchive, DEFAULT_MAX_DECOMPRESSED_CLUSTER_BYTES};
+    use did_mmap_cache::ingest::{
+        decode_relay_frame, encode_relay_frame, relay_dict_prefix, ConnectorConfig, Endpoint, FirehoseConnector,
+        FirehoseEvent, Flow,
+    };
+    use std::net::TcpListener;
+    use std::path::Path;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+    use tungstenite::Message;
+
+    /// Two DIDs alternating over seqs 0..20, so every cluster holds every other seq.
+    fn write_archive(root: &Path, dict: Option<Vec<u8>>) -> MultiShardArchive {
+        write_archive_from(root, dict, 0)
+    }
+
+    /// `write_archive` over seqs `first..first + 20`.
+    fn write_archive_from(root: &Path, dict: Option<Vec<u8>>, first: u64) -> MultiShardArchive {
+        let archive = MultiShardArchive::new(root, 1, 50, dict.clone()).unwrap();
+        for seq in first..first + 20 {
+            archive.ingest(seq, &format!("did:plc:user{}", seq % 2), format!("app.bsky.feed.post/{}", seq), msg(seq));
+        }
+        archive.shutdown();
+        MultiShardArchive::open_readonly(root, dict).unwrap()
+    }
+
+    fn msg(seq: u64) -> Vec<u8> {
+        format!("atproto_pattern_message_{}", seq).into_bytes()
+    }
+
+    fn dict() -> Vec<u8> {
+        b"atproto_pattern_".repeat(100)
+    }
+
+    #[test]
+    fn test_decode_cluster_returns_every_record_with_its_seq() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = write_archive(dir.path(), None);
+
+        let records = decode_cluster(&archive.get_raw_cluster_at_seq(4).unwrap(), None, DEFAULT_MAX_DECOMPRESSED_CLUSTER_BYTES).unwrap();
+        let seqs: Vec<u64> = records.iter().map(|(seq, _)| *seq).collect();
+        assert_eq!(seqs, (0..20).step_by(2).collect::<Vec<u64>>());
+        for (seq, data) in &records {
+            assert_eq!(data, &msg(*seq));
+        }
+    }
+
+    #[test]
+    fn test_tombstoned_cluster_keeps_the_same_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = write_archive(dir.path(), None);
+        archive.mark_deleted(6).unwrap();
+
+        let records = decode_cluster(&archive.get_raw_cluster_at_seq(4).unwrap(), None, DEFAULT_MAX_DECOMPRESSED_CLUSTER_BYTES).unwrap();
+        assert_eq!(records.len(), 9);
+        assert!(records.iter().all(|(seq, data)| *seq != 6 && data == &msg(*seq)));
+    }
+
+    #[test]
+    fn test_split_cluster_rejects_truncated_input() {
+        let mut raw = vec![2, 0];
+        raw.extend_from_slice(&7u64.to_le_bytes());
+        raw.extend_from_slice(&3u32.to_le_bytes());
+        assert!(split_cluster(&raw).is_err(), "header claims two entries");
+
+        raw[0] = 1;
+        assert!(split_cluster(&raw).is_err(), "entry runs past the end");
+        raw.extend_from_slice(b"abc");
+        assert_eq!(split_cluster(&raw).unwrap(), vec![(7, &b"abc"[..])]);
+        assert!(split_cluster(&[]).is_err());
+    }
+
+    /// Accepts one connection and speaks the relay protocol: handshake, dictionary, then `clusters`.
+    fn mock_relay(dict: Vec<u8>, advertised_hash: String, clusters: Vec<Vec<u8>>) -> Endpoint {
+        mock_relay_counting(dict, advertised_hash, clusters, 1).0
+    }
+
+    /// Like `mock_relay` for `connections` connections, honouring `have_dict` the way
+    /// `sovereign_relay` does. The counter is the number of times the dictionary was sent.
+    fn mock_relay_counting(dict: Vec<u8>, advertised_hash: String, clusters: Vec<Vec<u8>>, connections: usize) -> (Endpoint, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let dicts_sent = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&dicts_sent);
+        thread::spawn(move || {
+            for _ in 0..connections {
+                let (stream, _) = listener.accept().unwrap();
+                let mut have_dict = None;
+                #[allow(clippy::result_large_err)]
+                let mut ws = tungstenite::accept_hdr(stream, |req: &tungstenite::handshake::server::Request, resp| {
+                    have_dict = url::form_urlencoded::parse(req.uri().query().unwrap_or("").as_bytes())
+                        .find(|(k, _)| k == "have_dict")
+                        .map(|(_, v)| v.into_owned());
+                    Ok(resp)
+                }).unwrap();
+                let dict_sent = have_dict.as_deref() != Some(advertised_hash.as_str());
+                let handshake = serde_json::json!({ "version": 1, "compression": "zstd", "dict_hash": advertised_hash, "dict_sent": dict_sent });
+                ws.send(Message::Text(handshake.to_string())).unwrap();
+                if dict_sent {
+                    ws.send(Message::Binary(dict.clone())).unwrap();
+                    counter.fetch_add(1, Ordering::SeqCst);
+                }
+                for cluster in &clusters {
+                    ws.send(Message::Binary(cluster.clone())).unwrap();
+                }
+                let _ = ws.close(None);
+                let _ = ws.flush();
+            }
+        });
+        (Endpoint::parse(&format!("ws://{}", addr)).unwrap(), dicts_sent)
+    }
+
+    fn relay_config() -> ConnectorConfig {
+        ConnectorConfig {
+            compressed: true,
+            read_timeout: Duration::from_millis(100),
+            connect_error_delay: Duration::from_millis(10),
+            ..ConnectorConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_connector_unpacks_relay_clusters() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = write_archive(dir.path(), Some(dict()));
+        let hash = hex::encode(blake3::hash(&dict()).as_bytes());
+        let clusters = vec![archive.get_raw_cluster_at_seq(0).unwrap(), archive.get_raw_cluster_at_seq(1).unwrap()];
+        let endpoint = mock_relay(dict(), hash, clusters);
+
+        let mut connector = FirehoseConnector::new(vec![endpoint], relay_config(), Arc::new(AtomicBool::new(true)));
+        let mut frames = Vec::new();
+        connector.run(|event| match event {
+            FirehoseEvent::Frame { seq, data, .. } => {
+                frames.push((seq.unwrap(), data));
+                if frames.len() == 20 { Flow::Stop } else { Flow::Continue }
+            }
+            FirehoseEvent::ConnectFailed { error, .. } => panic!("handshake failed: {}", error),
+            _ => Flow::Continue,
+        });
+
+        frames.sort();
+        assert_eq!(frames, (0..20).map(|seq| (seq, msg(seq))).collect::<Vec<_>>());
+        // Resume point is the lowest seq of the last cluster, the odd DID's
+        assert_eq!(connector.cursor(), Some(1));
+    }
+
+    #[test]
+    fn test_connector_rejects_mismatched_dictionary() {
+        let endpoint = mock_relay(dict(), "00".repeat(32), Vec::new());
+        let mut connector = FirehoseConnector::new(vec![endpoint], relay_config(), Arc::new(AtomicBool::new(true)));
+        let mut error = None;
+        connector.run(|event| match event {
+            FirehoseEvent::ConnectFailed { error: e, .. } => {
+                error = Some(e.to_string());
+                Flow::Stop
+            }
+            FirehoseEvent::Frame { .. } => panic!("no frame expected"),
+            _ => Flow::Continue,
+        });
+        assert!(error.unwrap().contains("dict_hash"));
+    }
+
+    /// Runs the connector until `frames` records have arrived, across reconnects.
+    fn collect(connector: &mut FirehoseConnector, frames: usize) -> usize {
+        let mut seen = 0;
+        connector.run(|event| match event {
+            FirehoseEvent::Frame { .. } => {
+                seen += 1;
+                if seen == frames { Flow::Stop } else { Flow::Continue }
+            }
+            FirehoseEvent::ConnectFailed { error, .. } => panic!("handshake failed: {}", error),
+            _ => Flow::Continue,
+        });
+        seen
+    }
+
+    #[test]
+    fn test_held_dictionary_is_not_downloaded_again() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = write_archive(dir.path(), Some(dict()));
+        let cluster = archive.get_raw_cluster_at_seq(0).unwrap();
+
+        // Preloaded and matching: never sent
+        let (endpoint, dicts_sent) = mock_relay_counting(dict(), dict_hash(&dict()), vec![cluster.clone()], 1);
+        let config = ConnectorConfig { relay_dict: Some(dict()), ..relay_config() };
+        let mut connector = FirehoseConnector::new(vec![endpoint], config, Arc::new(AtomicBool::new(true)));
+        assert_eq!(collect(&mut connector, 10), 10);
+        assert_eq!(dicts_sent.load(Ordering::SeqCst), 0);
+
+        // Stale local copy: the relay sends its own once, reconnects then reuse it
+        let (endpoint, dicts_sent) = mock_relay_counting(dict(), dict_hash(&dict()), vec![cluster], 3);
+        let config = ConnectorConfig {
+            relay_dict: Some(b"an older dictionary".to_vec()),
+            reconnect_delay: Duration::from_millis(10),
+            ..relay_config()
+        };
+        let mut connector = FirehoseConnector::new(vec![endpoint], config, Arc::new(AtomicBool::new(true)));
+        assert_eq!(collect(&mut connector, 30), 30);
+        assert_eq!(dicts_sent.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_skipped_dictionary_needs_a_local_copy() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut ws = tungstenite::accept(stream).unwrap();
+            let handshake = serde_json::json!({ "version": 1, "compression": "zstd", "dict_hash": dict_hash(&dict()), "dict_sent": false });
+            ws.send(Message::Text(handshake.to_string())).unwrap();
+            while ws.read().is_ok() {}
+        });
+        let (mut socket, _) = tungstenite::connect(format!("ws://{}", addr)).unwrap();
+        let err = did_mmap_cache::ingest::read_relay_handshake(&mut socket, None).unwrap_err();
+        assert!(err.to_string().contains("none is held locally"));
+    }
+
+    #[test]
+    fn test_segments_record_their_dictionary() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = write_archive(&dir.path().join("dict"), Some(dict()));
+        assert_eq!(archive.dict_hash_at_seq(7), Some(dict_hash(&dict())));
+        assert_eq!(archive.dict_hash_at_seq(20), None);
+
+        let plain = write_archive(&dir.path().join("plain"), None);
+        assert_eq!(plain.dict_hash_at_seq(7), None);
+    }
+
+    #[test]
+    fn test_relay_frame_checks_magic_dictionary_and_length() {
+        let cluster = b"zstd cluster bytes".to_vec();
+        let frame = encode_relay_frame(relay_dict_prefix(&dict()), &cluster);
+        assert_eq!(decode_relay_frame(&frame, &dict()).unwrap(), &cluster[..]);
+
+        let err = decode_relay_frame(&frame, b"another dictionary").unwrap_err();
+        assert!(err.to_string().contains("not the one held"));
+        assert!(decode_relay_frame(&frame[..frame.len() - 1], &dict()).is_err(), "truncated");
+        assert!(decode_relay_frame(&cluster, &dict()).is_err(), "bare cluster");
+        assert!(decode_relay_frame(&frame[..5], &dict()).is_err(), "short header");
+    }
+
+    /// Accepts one connection and requires `framing=2`: handshake with dictionary `first`, then `messages`.
+    fn mock_framed_relay(first: Vec<u8>, messages: Vec<Message>) -> Endpoint {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut framing = None;
+            #[allow(clippy::result_large_err)]
+            let mut ws = tungstenite::accept_hdr(stream, |req: &tungstenite::handshake::server::Request, resp| {
+                framing = url::form_urlencoded::parse(req.uri().query().unwrap_or("").as_bytes())
+                    .find(|(k, _)| k == "framing")
+                    .map(|(_, v)| v.into_owned());
+                Ok(resp)
+            }).unwrap();
+            assert_eq!(framing.as_deref(), Some("2"));
+            let handshake = serde_json::json!({ "version": 1, "compression": "zstd", "dict_hash": dict_hash(&first), "dict_sent": true, "framing": 2 });
+            ws.send(Message::Text(handshake.to_string())).unwrap();
+            ws.send(Message::Binary(first)).unwrap();
+            for message in messages {
+                ws.send(message).unwrap();
+            }
+            let _ = ws.close(None);
+            let _ = ws.flush();
+        });
+        Endpoint::parse(&format!("ws://{}", addr)).unwrap()
+    }
+
+    fn framed(dict: &[u8], archive: &MultiShardArchive, seq: u64) -> Message {
+        Message::Binary(encode_relay_frame(relay_dict_prefix(dict), &archive.get_raw_cluster_at_seq(seq).unwrap()))
+    }
+
+    #[test]
+    fn test_connector_follows_dict_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let newer = b"atproto_pattern_message_".repeat(80);
+        let old = write_archive(&dir.path().join("old"), Some(dict()));
+        let new = write_archive_from(&dir.path().join("new"), Some(newer.clone()), 20);
+        let notice = serde_json::json!({ "event": "dict_change", "dict_hash": dict_hash(&newer), "seq": 20 });
+        let endpoint = mock_framed_relay(dict(), vec![
+            framed(&dict(), &old, 0),
+            framed(&dict(), &old, 1),
+            Message::Text(notice.to_string()),
+            Message::Binary(newer.clone()),
+            framed(&newer, &new, 20),
+            framed(&newer, &new, 21),
+        ]);
+
+        let mut connector = FirehoseConnector::new(vec![endpoint], relay_config(), Arc::new(AtomicBool::new(true)));
+        let mut frames = Vec::new();
+        connector.run(|event| match event {
+            FirehoseEvent::Frame { seq, data, .. } => {
+                frames.push((seq.unwrap(), data));
+                if frames.len() == 40 { Flow::Stop } else { Flow::Continue }
+            }
+            FirehoseEvent::ConnectFailed { error, .. } => panic!("handshake failed: {}", error),
+            _ => Flow::Continue,
+        });
+
+        frames.sort();
+        assert_eq!(frames, (0..40).map(|seq| (seq, msg(seq))).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_connector_drops_cluster_for_unannounced_dictionary() {
+        let dir = tempfile::tempdir().unwrap();
+        let newer = b"atproto_pattern_message_".repeat(80);
+        let new = write_archive(dir.path(), Some(newer.clone()));
+        let endpoint = mock_framed_relay(dict(), vec![framed(&newer, &new, 0)]);
+
+        let mut connector = FirehoseConnector::new(vec![endpoint], relay_config(), Arc::new(AtomicBool::new(true)));
+        let mut error = None;
+        connector.run(|event| match event {
+            FirehoseEvent::Disconnected { reason, .. } => {
+                error = Some(reason.to_string());
+                Flow::Stop
+            }
+            FirehoseEvent::Frame { .. } => panic!("no frame expected"),
+            _ => Flow::Continue,
+        });
+        assert!(error.unwrap().contains("not the one held"));
+    }
+}
@@ -0,0 +1,217 @@
+#[cfg(all(test, feature = "test-fixtures"))]
+mod relay_filter_tests {
+    use did_mmap_cache::archive::{decode_cluster, MultiShardArchive, DEFAULT_MAX_DECOMPRESSED_CLUSTER_BYTES};
+    use did_mmap_cache::parser::core::parse_input;
+    use did_mmap_cache::testutil::{identity_frame, post_record, FrameBuilder, SigningKey};
+    use std::net::{TcpListener, TcpStream};
+    use std::path::Path;
+    use std::process::{Child, Command, Stdio};
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use tungstenite::stream::MaybeTlsStream;
+    use tungstenite::{Message, WebSocket};
+
+    const ALICE: &str = "did:plc:ewvi7nxzyoun6zhxrhs64oiz";
+    const BOB: &str = "did:plc:bobbobbobbobbobbobbobbob";
+
+    /// Posts and likes from alice and bob, plus an identity event of each, at seqs 0..8.
+    fn write_archive(root: &Path, dict: &[u8]) -> Vec<Vec<u8>> {
+        let commit = |did: &str, seed: u8, seq: u64, path: &str| {
+            FrameBuilder::new(did, SigningKey::k256_from_seed(seed)).seq(seq).create(path, post_record("hello")).build()
+        };
+        let frames = vec![
+            commit(ALICE, 1, 0, "app.bsky.feed.post/a1"),
+            commit(BOB, 2, 1, "app.bsky.feed.post/b1"),
+            commit(ALICE, 1, 2, "app.bsky.feed.like/a2"),
+            identity_frame(BOB, 3, Some("bob.example.com")),
+            commit(BOB, 2, 4, "app.bsky.feed.like/b2"),
+            commit(ALICE, 1, 5, "app.bsky.feed.post/a3"),
+            identity_frame(ALICE, 6, None),
+            commit(BOB, 2, 7, "app.bsky.graph.follow/b3"),
+        ];
+        let archive = MultiShardArchive::new(root, 2, 50, Some(dict.to_vec())).unwrap();
+        for (seq, frame) in frames.iter().enumerate() {
+            let env = parse_input(frame).unwrap();
+            let did = std::str::from_utf8(env.did.unwrap()).unwrap();
+            let path = env.ops.first().map_or_else(String::new, |op| op.path.to_string());
+            archive.ingest(seq as u64, did, path, frame.clone());
+        }
+        archive.shutdown();
+        frames
+    }
+
+    struct Relay(Child, u16);
+
+    impl Drop for Relay {
+        fn drop(&mut self) {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+
+    fn start_relay(archive: &Path, dict: &Path) -> Relay {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let child = Command::new(env!("CARGO_BIN_EXE_sovereign_relay"))
+            .args(["--port", &port.to_string(), "--archive"])
+            .arg(archive)
+            .arg("--dict")
+            .arg(dict)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(20);
+        while TcpStream::connect(("127.0.0.1", port)).is_err() {
+            assert!(Instant::now() < deadline, "relay didn't start listening");
+            thread::sleep(Duration::from_millis(50));
+        }
+        Relay(child, port)
+    }
+
+    /// Subscribes with `filter` and returns the handshake and the first `expected` messages,
+    /// after checking that nothing else follows.
+    fn subscribe(port: u16, filter: &str, expected: usize) -> (serde_json::Value, Vec<Vec<u8>>) {
+        let (mut ws, _) = tungstenite::connect(format!("ws://127.0.0.1:{}/", port)).unwrap();
+        ws.send(Message::Text(filter.to_string())).unwrap();
+        let handshake = match ws.read().unwrap() {
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            other => panic!("expected the handshake, got {:?}", other),
+        };
+        let mut frames = Vec::new();
+        while frames.len() < expected {
+            if let Message::Binary(frame) = ws.read().unwrap() {
+                frames.push(frame);
+            }
+        }
+        set_read_timeout(&mut ws, Duration::from_millis(500));
+        assert!(ws.read().is_err(), "more than {} messages", expected);
+        (handshake, frames)
+    }
+
+    fn set_read_timeout(ws: &mut WebSocket<MaybeTlsStream<TcpStream>>, timeout: Duration) {
+        if let MaybeTlsStream::Plain(stream) = ws.get_mut() {
+            stream.set_read_timeout(Some(timeout)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_each_client_gets_its_own_subset() {
+        let dir = tempfile::tempdir().unwrap();
+        let dict = b"atproto_pattern_".repeat(100);
+        let dict_path = dir.path().join("relay.dict");
+        std::fs::write(&dict_path, &dict).unwrap();
+        let frames = write_archive(&dir.path().join("archive"), &dict);
+        let relay = start_relay(&dir.path().join("archive"), &dict_path);
+
+        let posts = thread::spawn(move || subscribe(relay.1, r#"{"filter":{"collections":["app.bsky.feed.post"]}}"#, 3));
+        let (handshake, bobs) = subscribe(relay.1, &format!(r#"{{"filter":{{"dids":["{}"]}}}}"#, BOB), 4);
+        let (post_handshake, posts) = posts.join().unwrap();
+
+        assert_eq!(post_handshake["mode"], "messages");
+        assert_eq!(post_handshake["filter"]["collections"][0], "app.bsky.feed.post");
+        assert_eq!(posts, vec![frames[0].clone(), frames[1].clone(), frames[5].clone()]);
+        assert_eq!(handshake["filter"]["dids"][0], BOB);
+        // bob's identity event comes through along with his commits
+        assert_eq!(bobs, vec![frames[1].clone(), frames[3].clone(), frames[4].clone(), frames[7].clone()]);
+    }
+
+    #[test]
+    fn test_clients_without_a_filter_still_get_clusters() {
+        let dir = tempfile::tempdir().unwrap();
+        let dict = b"atproto_pattern_".repeat(100);
+        let dict_path = dir.path().join("relay.dict");
+        std::fs::write(&dict_path, &dict).unwrap();
+        write_archive(&dir.path().join("archive"), &dict);
+        let relay = start_relay(&dir.path().join("archive"), &dict_path);
+
+        let (mut ws, _) = tungstenite::connect(format!("ws://127.0.0.1:{}/", relay.1)).unwrap();
+        let handshake: serde_json::Value = match ws.read().unwrap() {
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            other => panic!("expected the handshake, got {:?}", other),
+        };
+        assert_eq!(handshake["compression"], "zstd");
+        assert!(handshake.get("mode").is_none());
+        // The dictionary, then the first cluster
+        assert_eq!(ws.read().unwrap(), Message::Binary(dict));
+        assert!(matches!(ws.read().unwrap(), Message::Binary(_)));
+
+        let (mut bad, _) = tungstenite::connect(format!("ws://127.0.0.1:{}/", relay.1)).unwrap();
+        bad.send(Message::Text(r#"{"filter":{"dids":["bob"]}}"#.to_string())).unwrap();
+        assert!(matches!(bad.read().unwrap(), Message::Close(Some(_))));
+    }
+
+    #[test]
+    fn test_forward_stream_skips_tombstones_and_waits_at_the_head() {
+        let dir = tempfile::tempdir().unwrap();
+        let dict = b"atproto_pattern_".repeat(100);
+        let dict_path = dir.path().join("relay.dict");
+        std::fs::write(&dict_path, &dict).unwrap();
+        let frames = write_archive(&dir.path().join("archive"), &dict);
+        MultiShardArchive::open_readonly(dir.path().join("archive"), None).unwrap().mark_deleted(4).unwrap();
+        let relay = start_relay(&dir.path().join("archive"), &dict_path);
+
+        // The tombstone is stepped over rather than read as the end, and the head keeps the
+        // stream open instead of closing it
+        let (_, messages) = subscribe(relay.1, &format!(r#"{{"filter":{{"dids":["{}","{}"]}}}}"#, ALICE, BOB), 7);
+        let expected: Vec<Vec<u8>> = [0, 1, 2, 3, 5, 6, 7].iter().map(|&seq| frames[seq].clone()).collect();
+        assert_eq!(messages, expected);
+    }
+
+    /// Everything up to the relay's Close frame, after the handshake.
+    fn read_until_close(ws: &mut WebSocket<MaybeTlsStream<TcpStream>>) -> (serde_json::Value, Vec<Vec<u8>>) {
+        let handshake = match ws.read().unwrap() {
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            other => panic!("expected the handshake, got {:?}", other),
+        };
+        set_read_timeout(ws, Duration::from_secs(10));
+        let mut binaries = Vec::new();
+        loop {
+            match ws.read().unwrap() {
+                Message::Binary(data) => binaries.push(data),
+                Message::Close(_) => return (handshake, binaries),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn test_desc_order_walks_back_to_the_oldest_seq() {
+        let dir = tempfile::tempdir().unwrap();
+        let dict = b"atproto_pattern_".repeat(100);
+        let dict_path = dir.path().join("relay.dict");
+        std::fs::write(&dict_path, &dict).unwrap();
+        let frames = write_archive(&dir.path().join("archive"), &dict);
+        MultiShardArchive::open_readonly(dir.path().join("archive"), None).unwrap().mark_deleted(4).unwrap();
+        let relay = start_relay(&dir.path().join("archive"), &dict_path);
+
+        // Message mode: every kept frame, newest first
+        let (mut ws, _) = tungstenite::connect(format!("ws://127.0.0.1:{}/?order=desc", relay.1)).unwrap();
+        ws.send(Message::Text(format!(r#"{{"filter":{{"dids":["{}","{}"]}}}}"#, ALICE, BOB))).unwrap();
+        let (handshake, messages) = read_until_close(&mut ws);
+        assert_eq!(handshake["order"], "desc");
+        // Seq 4 is tombstoned
+        let expected: Vec<Vec<u8>> = [7, 6, 5, 3, 2, 1, 0].iter().map(|&seq| frames[seq].clone()).collect();
+        assert_eq!(messages, expected);
+
+        // From a cursor, down to the oldest seq
+        let (mut ws, _) = tungstenite::connect(format!("ws://127.0.0.1:{}/?order=desc&cursor=2", relay.1)).unwrap();
+        ws.send(Message::Text(format!(r#"{{"filter":{{"dids":["{}"]}}}}"#, BOB))).unwrap();
+        let (_, messages) = read_until_close(&mut ws);
+        assert_eq!(messages, vec![frames[1].clone()]);
+
+        // Cluster mode: each cluster once, ordered by its newest seq
+        let (mut ws, _) = tungstenite::connect(format!("ws://127.0.0.1:{}/?order=desc", relay.1)).unwrap();
+        let (handshake, binaries) = read_until_close(&mut ws);
+        assert_eq!(handshake["order"], "desc");
+        assert_eq!(binaries[0], dict);
+        let clusters: Vec<Vec<u64>> = binaries[1..]
+            .iter()
+            .map(|c| decode_cluster(c, Some(&dict), DEFAULT_MAX_DECOMPRESSED_CLUSTER_BYTES).unwrap().into_iter().map(|(seq, _)| seq).collect())
+            .collect();
+        let newest: Vec<u64> = clusters.iter().map(|c| *c.iter().max().unwrap()).collect();
+        assert!(newest.windows(2).all(|w| w[0] > w[1]), "{:?}", clusters);
+        let mut seqs: Vec<u64> = clusters.concat();
+        seqs.sort_unstable();
+        assert_eq!(seqs, vec![0, 1, 2, 3, 5, 6, 7]);
+    }
+}
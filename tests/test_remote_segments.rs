@@ -0,0 +1,131 @@
+#[cfg(all(test, feature = "remote-segments"))]
+mod remote_segments_tests {
+    use axum::extract::Request;
+    use axum::middleware::{self, Next};
+    use axum::Router;
+    use did_mmap_cache::archive::source::{HttpRangeSource, RemoteClusterCache, RemoteManifest};
+    use did_mmap_cache::archive::{decode_cluster, ArchiveError, ArchiveWriter, SegmentSource, SegmentedArchive, DEFAULT_MAX_DECOMPRESSED_CLUSTER_BYTES};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use tempfile::tempdir;
+    use tower_http::services::ServeDir;
+
+    fn post(seq: u64) -> Vec<u8> {
+        format!(r#"{{"text": "post {}", "createdAt": "2024-01-01T00:00:{:02}.000Z"}}"#, seq, seq % 60).into_bytes()
+    }
+
+    /// Serves `root` under `/bucket/` with Range support, counting range requests.
+    fn serve(root: PathBuf) -> (String, Arc<AtomicUsize>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let ranges = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&ranges);
+        thread::spawn(move || {
+            let rt = tokio::runtime::Runtime::new().unwrap();
+            rt.block_on(async move {
+                let app = Router::new().nest_service("/bucket", ServeDir::new(root)).layer(middleware::from_fn(move |req: Request, next: Next| {
+                    let counter = Arc::clone(&counter);
+                    async move {
+                        if req.headers().contains_key(axum::http::header::RANGE) {
+                            counter.fetch_add(1, Ordering::SeqCst);
+                        }
+                        next.run(req).await
+                    }
+                }));
+                axum::serve(tokio::net::TcpListener::from_std(listener).unwrap(), app).await.unwrap();
+            });
+        });
+        (format!("http://{}/bucket/", addr), ranges)
+    }
+
+    /// Moves every file of segment `stem` from `dir` to `bucket`.
+    fn upload(dir: &Path, bucket: &Path, stem: &str) {
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.file_stem().and_then(|s| s.to_str()) == Some(stem) {
+                fs::rename(&path, bucket.join(path.file_name().unwrap())).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn test_remote_segments_read_like_local_ones() {
+        let root = tempdir().unwrap();
+        let (dir, bucket) = (root.path().join("archive"), root.path().join("bucket"));
+        fs::create_dir_all(&bucket).unwrap();
+        let mut writer = ArchiveWriter::new(&dir, 0, 0, 1000, None).unwrap();
+        for start in [0u64, 50, 100] {
+            for seq in start..start + 50 {
+                writer.append_message(seq, &format!("did:plc:author{}", seq % 4), "app.bsky.feed.post/x", &post(seq)).unwrap();
+            }
+            writer.finalize_segment().unwrap();
+        }
+
+        // The two older segments go to the bucket; the newest stays local
+        upload(&dir, &bucket, "s0_0");
+        upload(&dir, &bucket, "s0_50");
+        let (url, ranges) = serve(bucket);
+        let manifest = RemoteManifest {
+            base_url: url,
+            segments: vec!["s0_0".into(), "s0_50".into()],
+            cache_dir: "remote_cache".into(),
+            cache_bytes: 1 << 20,
+        };
+        manifest.save(&dir).unwrap();
+
+        let archive = SegmentedArchive::open_directory(&dir, None, None).unwrap();
+        assert_eq!(archive.segment_count(), 3);
+        assert_eq!((archive.min_seq(), archive.max_seq()), (Some(0), Some(149)));
+        for seq in 0..150 {
+            assert_eq!(archive.get_message_by_seq(seq, None).unwrap(), post(seq), "seq {}", seq);
+        }
+        // One range request per cluster: four DIDs in each remote segment
+        assert_eq!(ranges.load(Ordering::SeqCst), 8);
+        assert!(archive.verify_integrity_at_seq(10, None).unwrap());
+        assert!(archive.verify_integrity_at_seq(60, None).unwrap());
+
+        // A fresh reader, with an empty cluster cache, is served from the disk cache
+        let reopened = SegmentedArchive::open_directory(&dir, None, None).unwrap();
+        for seq in 0..100 {
+            assert_eq!(reopened.get_message_streaming(seq, None).unwrap(), post(seq));
+        }
+        assert_eq!(ranges.load(Ordering::SeqCst), 8);
+
+        // Tombstones filter remote clusters like local ones
+        reopened.mark_deleted(12).unwrap();
+        assert!(matches!(reopened.get_message_by_seq(12, None), Err(ArchiveError::Tombstoned { seq: 12 })));
+        let cluster = decode_cluster(&reopened.get_raw_cluster_at_seq(16).unwrap(), None, DEFAULT_MAX_DECOMPRESSED_CLUSTER_BYTES).unwrap();
+        let seqs: Vec<u64> = cluster.iter().map(|(seq, _)| *seq).collect();
+        assert_eq!(seqs, (0..50).filter(|s| s % 4 == 0 && *s != 12).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_http_range_source_and_cache_budget() {
+        let root = tempdir().unwrap();
+        let (dir, bucket) = (root.path().join("archive"), root.path().join("bucket"));
+        fs::create_dir_all(&bucket).unwrap();
+        let mut writer = ArchiveWriter::new(&dir, 0, 0, 1000, None).unwrap();
+        for seq in 0..40 {
+            writer.append_message(seq, &format!("did:plc:author{}", seq % 8), "app.bsky.feed.post/x", &post(seq)).unwrap();
+        }
+        writer.finalize_segment().unwrap();
+        let bin = fs::read(dir.join("s0_0.bin")).unwrap();
+        upload(&dir, &bucket, "s0_0");
+        let (url, _) = serve(bucket);
+        let manifest = RemoteManifest { base_url: url, segments: vec!["s0_0".into()], cache_dir: "remote_cache".into(), cache_bytes: 64 };
+
+        let clusters = Arc::new(RemoteClusterCache::open(&manifest.cache_path(&dir), manifest.cache_bytes).unwrap());
+        let source = HttpRangeSource::open(reqwest::blocking::Client::new(), &manifest, &dir, "s0_0", Arc::clone(&clusters)).unwrap();
+        assert!(source.exists());
+        assert_eq!(source.bin_len(), bin.len());
+        assert_eq!(&source.get_cluster(3, 40).unwrap()[..], &bin[3..43]);
+        assert_eq!(&source.get_cluster(50, 40).unwrap()[..], &bin[50..90]);
+        // Only the newest fits the 64-byte budget
+        assert_eq!((clusters.len(), clusters.bytes()), (1, 40));
+        assert!(HttpRangeSource::open(reqwest::blocking::Client::new(), &manifest, &dir, "s0_999", clusters).is_err());
+    }
+}
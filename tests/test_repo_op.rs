@@ -0,0 +1,44 @@
+#[cfg(test)]
+mod repo_op_tests {
+    use did_mmap_cache::parser::core::{decode_tid, RepoOp};
+
+    fn op(path: &str) -> RepoOp<'_> {
+        RepoOp { action: "create", path, cid: None }
+    }
+
+    #[test]
+    fn test_path_split() {
+        let post = op("app.bsky.feed.post/3jzfcijpj2z2a");
+        assert_eq!(post.collection(), "app.bsky.feed.post");
+        assert_eq!(post.rkey(), "3jzfcijpj2z2a");
+
+        let profile = op("app.bsky.actor.profile/self");
+        assert_eq!(profile.rkey(), "self");
+        assert_eq!(profile.tid_timestamp(), None);
+
+        let bare = op("app.bsky.feed.like");
+        assert_eq!(bare.collection(), "app.bsky.feed.like");
+        assert_eq!(bare.rkey(), "");
+    }
+
+    #[test]
+    fn test_known_tid_timestamps() {
+        // Example TID from the atproto spec: 2023-06-30T15:03:01.887007Z
+        assert_eq!(op("app.bsky.feed.post/3jzfcijpj2z2a").tid_timestamp(), Some(1_688_137_381_887_007));
+        // 1700000000 s exactly, clock id 7
+        assert_eq!(decode_tid("3ke6kg3wk222b"), Some(1_700_000_000_000_000));
+        assert_eq!(decode_tid("2222222222222"), Some(0));
+
+        // Lexicographic order follows creation time
+        assert!(decode_tid("3jzfcijpj2z2a") < decode_tid("3ke6kg3wk222b"));
+    }
+
+    #[test]
+    fn test_invalid_tids() {
+        assert_eq!(decode_tid("3jzfcijpj2z2"), None); // too short
+        assert_eq!(decode_tid("3jzfcijpj2z2aa"), None); // too long
+        assert_eq!(decode_tid("3jzfcijpj2z21"), None); // '1' not in the alphabet
+        assert_eq!(decode_tid("3JZFCIJPJ2Z2A"), None); // upper case
+        assert_eq!(decode_tid("kzzzzzzzzzzzz"), None); // high bit set
+    }
+}
@@ -0,0 +1,112 @@
+#[cfg(test)]
+mod reshard_tests {
+    use did_mmap_cache::archive::{reshard, ArchiveMeta, MultiShardArchive};
+    use did_mmap_cache::testutil::{post_record, FrameBuilder, SigningKey};
+    use std::io::ErrorKind;
+    use tempfile::tempdir;
+
+    /// A signed #commit creating a post at `path`.
+    fn frame(did: &str, seq: u64, path: &str) -> Vec<u8> {
+        FrameBuilder::new(did, SigningKey::k256_from_seed(0x11)).seq(seq).create(path, post_record("hello")).build()
+    }
+
+    #[test]
+    fn test_shard_count_mismatch() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("archive");
+
+        let archive = MultiShardArchive::new(&root, 4, 100, None).unwrap();
+        archive.shutdown();
+        assert_eq!(ArchiveMeta::load(&root).unwrap(), Some(ArchiveMeta { num_shards: 4 }));
+
+        // Same count reopens fine, a different one is refused
+        MultiShardArchive::new(&root, 4, 100, None).unwrap().shutdown();
+        let err = MultiShardArchive::new(&root, 8, 100, None).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+
+        // Forcing rewrites the recorded count
+        MultiShardArchive::new_with_force(&root, 8, 100, None, true).unwrap().shutdown();
+        assert_eq!(ArchiveMeta::load(&root).unwrap(), Some(ArchiveMeta { num_shards: 8 }));
+
+        // Archives from before the meta file are checked against their shard_N directories
+        let legacy = dir.path().join("legacy");
+        for i in 0..2 {
+            std::fs::create_dir_all(legacy.join(format!("shard_{}", i))).unwrap();
+        }
+        assert!(MultiShardArchive::new(&legacy, 16, 100, None).is_err());
+        MultiShardArchive::new(&legacy, 2, 100, None).unwrap().shutdown();
+    }
+
+    #[test]
+    fn test_reshard_round_trip() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("src");
+        let dst = dir.path().join("dst");
+
+        let archive = MultiShardArchive::new(&src, 4, 7, None).unwrap();
+        let mut expected = Vec::new();
+        for seq in 1000..1060u64 {
+            let did = format!("did:plc:user{}", seq % 13);
+            let path = format!("app.bsky.feed.post/{}", seq);
+            let msg = frame(&did, seq, &path);
+            archive.ingest(seq, &did, path.clone(), msg.clone());
+            expected.push((seq, did, path, msg));
+        }
+        archive.shutdown();
+
+        let reader = MultiShardArchive::open_readonly(&src, None).unwrap();
+        reader.mark_deleted(1005).unwrap();
+        reader.mark_deleted(1042).unwrap();
+        drop(reader);
+
+        let report = reshard(&src, &dst, 8, 7, None, false).unwrap();
+        assert_eq!(report.copied, 58);
+        assert_eq!(report.tombstoned, 2);
+        assert_eq!(report.skipped, 0);
+
+        // Resharding into an existing archive is refused
+        assert_eq!(reshard(&src, &dst, 8, 7, None, false).unwrap_err().kind(), ErrorKind::AlreadyExists);
+
+        let out = MultiShardArchive::open_readonly(&dst, None).unwrap();
+        assert_eq!(out.reader_count(), 8);
+        assert_eq!(out.min_seq(), Some(1000));
+        for (seq, did, path, msg) in &expected {
+            if *seq == 1005 || *seq == 1042 {
+                assert!(out.get_message_by_seq(*seq).is_err(), "seq {} should stay tombstoned", seq);
+                continue;
+            }
+            assert_eq!(&out.get_message_by_seq(*seq).unwrap(), msg, "seq {}", seq);
+            // Path lookups route to the new shard
+            out.delete_by_path(did, path);
+            assert!(out.get_message_by_seq(*seq).is_err(), "delete_by_path missed seq {}", seq);
+        }
+    }
+
+    #[test]
+    fn test_unparseable_frames_fail_unless_dropped() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("src");
+        let dst = dir.path().join("dst");
+
+        let archive = MultiShardArchive::new(&src, 2, 100, None).unwrap();
+        for seq in 0..10u64 {
+            let did = format!("did:plc:user{}", seq % 3);
+            let path = format!("app.bsky.feed.post/{}", seq);
+            let msg = if seq == 4 { b"not a frame".to_vec() } else { frame(&did, seq, &path) };
+            archive.ingest(seq, &did, path, msg);
+        }
+        archive.shutdown();
+
+        let err = reshard(&src, &dst, 4, 100, None, false).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+        // Nothing is left behind, so the reshard can be rerun
+        assert_eq!(ArchiveMeta::load(&dst).unwrap(), None);
+        assert!(!dst.join("shard_0").exists());
+
+        let report = reshard(&src, &dst, 4, 100, None, true).unwrap();
+        assert_eq!((report.copied, report.skipped), (9, 1));
+        let out = MultiShardArchive::open_readonly(&dst, None).unwrap();
+        assert!(out.get_message_by_seq(4).is_err());
+        assert_eq!(out.get_message_by_seq(5).unwrap(), frame("did:plc:user2", 5, "app.bsky.feed.post/5"));
+    }
+}
@@ -0,0 +1,61 @@
+#[cfg(test)]
+mod resolver_cache_tests {
+    use did_mmap_cache::resolver::ResolverCache;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    /// Counts lookups; only `did:plc:good` resolves.
+    fn counting_cache(ttl: Duration, capacity: usize) -> (ResolverCache, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let seen = Arc::clone(&calls);
+        let cache = ResolverCache::new(ttl, capacity).with_fetch(move |did| {
+            seen.fetch_add(1, Ordering::SeqCst);
+            (did == "did:plc:good").then_some(([2; 33], 1))
+        });
+        (cache, calls)
+    }
+
+    #[test]
+    fn test_failure_is_not_retried_within_ttl() {
+        let (cache, calls) = counting_cache(Duration::from_secs(60), 10);
+        assert!(cache.resolve("did:plc:gone").is_none());
+        assert!(cache.resolve("did:plc:gone").is_none());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert!(cache.recently_failed("did:plc:gone"));
+
+        // Successes always go to the fetcher; only failures are cached
+        assert!(cache.resolve("did:plc:good").is_some());
+        assert!(cache.resolve("did:plc:good").is_some());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        cache.forget("did:plc:gone");
+        assert!(cache.resolve("did:plc:gone").is_none());
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_failure_is_retried_after_ttl() {
+        let (cache, calls) = counting_cache(Duration::from_millis(30), 10);
+        assert!(cache.resolve("did:plc:gone").is_none());
+        thread::sleep(Duration::from_millis(50));
+        assert!(!cache.recently_failed("did:plc:gone"));
+        assert!(cache.resolve("did:plc:gone").is_none());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_capacity_forgets_oldest_failure() {
+        let (cache, calls) = counting_cache(Duration::from_secs(60), 2);
+        cache.resolve("did:plc:a");
+        cache.resolve("did:plc:b");
+        cache.resolve("did:plc:a"); // still cached, no lookup
+        cache.resolve("did:plc:c");
+        assert_eq!(cache.negative_len(), 2);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        assert!(!cache.recently_failed("did:plc:a"));
+        assert!(cache.recently_failed("did:plc:b") && cache.recently_failed("did:plc:c"));
+    }
+}
@@ -0,0 +1,112 @@
+#[cfg(test)]
+mod reverify_tests {
+    use did_mmap_cache::archive::MultiShardArchive;
+    use did_mmap_cache::mmap_did_cache::MmapDidCache;
+    use did_mmap_cache::monitor::ErrorType;
+    use did_mmap_cache::verify::{reverify, DidVerifyCounts, VerifyOutcome};
+    use did_mmap_cache::testutil::{post_record, FrameBuilder, SigningKey};
+    use tempfile::tempdir;
+
+    /// A `#commit` frame for `did` signed by `key`; with `tamper` the signature doesn't verify.
+    fn signed_frame(key: SigningKey, did: &str, seq: u8, tamper: bool) -> Vec<u8> {
+        let builder = FrameBuilder::new(did, key).seq(seq as u64).create(format!("app.bsky.feed.post/{}", seq), post_record("hello"));
+        if tamper { builder.tamper_signature() } else { builder }.build()
+    }
+
+    fn alice() -> SigningKey {
+        SigningKey::k256_from_seed(0x11)
+    }
+
+    #[test]
+    fn test_reverify_reports_failures_in_seq_order() {
+        let dir = tempdir().unwrap();
+        let bob = unrelated_key();
+
+        let mut cache = MmapDidCache::create(dir.path().join("cache.bin"), 1024).unwrap();
+        assert!(cache.atomic_update_or_tombstone("did:plc:alice", Some(1), Some(&alice().public_key())));
+        // Bob's cached key is one he never signed with
+        assert!(cache.atomic_update_or_tombstone("did:plc:bob", Some(1), Some(&bob)));
+
+        let archive_dir = dir.path().join("archive");
+        let archive = MultiShardArchive::new(&archive_dir, 2, 50, None).unwrap();
+        let frames: Vec<(&str, Vec<u8>)> = vec![
+            ("did:plc:alice", signed_frame(alice(), "did:plc:alice", 0, false)),
+            ("did:plc:alice", signed_frame(alice(), "did:plc:alice", 1, false)),
+            ("did:plc:alice", signed_frame(alice(), "did:plc:alice", 2, true)),
+            ("did:plc:carol", signed_frame(alice(), "did:plc:carol", 3, false)),
+            ("did:plc:bob", signed_frame(alice(), "did:plc:bob", 4, false)),
+            ("did:plc:alice", b"not a frame".to_vec()),
+            ("did:plc:alice", signed_frame(alice(), "did:plc:alice", 6, false)),
+        ];
+        for (seq, (did, frame)) in frames.into_iter().enumerate() {
+            archive.ingest(seq as u64, did, format!("app.bsky.feed.post/{}", seq), frame);
+        }
+        archive.shutdown();
+        let archive = MultiShardArchive::open_readonly(&archive_dir, None).unwrap();
+
+        let report = reverify(&archive, &cache, None, 3);
+        assert_eq!(report.checked, 7);
+        assert_eq!(report.verified, 3);
+        let failures: Vec<(u64, &str, Option<VerifyOutcome>, ErrorType)> =
+            report.failures.iter().map(|f| (f.seq, f.did.as_str(), f.outcome, f.error)).collect();
+        assert_eq!(failures, vec![
+            (2, "did:plc:alice", Some(VerifyOutcome::Mismatch), ErrorType::InvalidSignature),
+            (3, "did:plc:carol", None, ErrorType::MissingKey),
+            (4, "did:plc:bob", Some(VerifyOutcome::Mismatch), ErrorType::InvalidSignature),
+            (5, "", None, ErrorType::MalformedCbor),
+        ]);
+        assert_eq!(report.by_error.get("InvalidSignature"), Some(&2));
+        assert_eq!(report.by_key_type["secp256k1"].verified, 3);
+        assert_eq!(report.by_key_type["secp256k1"].failed, 2);
+
+        let mut csv = Vec::new();
+        report.write_csv(&mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "seq,did,verdict,error_kind\n\
+            2,did:plc:alice,Mismatch,InvalidSignature\n\
+            3,did:plc:carol,NoKey,MissingKey\n\
+            4,did:plc:bob,Mismatch,InvalidSignature\n\
+            5,,Unparseable,MalformedCbor\n");
+
+        // Same verdicts whatever the worker count, and a range restricts the scan
+        assert_eq!(reverify(&archive, &cache, None, 1), report);
+        let partial = reverify(&archive, &cache, Some(3..5), 2);
+        assert_eq!(partial.checked, 2);
+        assert_eq!(partial.failures.iter().map(|f| f.seq).collect::<Vec<_>>(), vec![3, 4]);
+    }
+
+    #[test]
+    fn test_verify_archived_range_passes_against_the_matching_cache() {
+        let dir = tempdir().unwrap();
+        let mut cache = MmapDidCache::create(dir.path().join("cache.bin"), 1024).unwrap();
+        assert!(cache.atomic_update_or_tombstone("did:plc:alice", Some(1), Some(&alice().public_key())));
+
+        let archive_dir = dir.path().join("archive");
+        let archive = MultiShardArchive::new(&archive_dir, 2, 50, None).unwrap();
+        for seq in 0..3u8 {
+            archive.ingest(seq as u64, "did:plc:alice", format!("app.bsky.feed.post/{}", seq), signed_frame(alice(), "did:plc:alice", seq, false));
+        }
+        archive.shutdown();
+        let archive = MultiShardArchive::open_readonly(&archive_dir, None).unwrap();
+
+        let report = archive.verify_archived_range(0, 10, &cache);
+        assert!(report.passed_all());
+        assert_eq!((report.checked, report.passed), (3, 3));
+        assert_eq!(report.by_did["did:plc:alice"], DidVerifyCounts { passed: 3, failed: 0 });
+        assert_eq!(archive.verify_archived_range(1, 2, &cache).checked, 1);
+
+        // After a key rotation the archived commits no longer match the cache
+        assert!(cache.atomic_update_or_tombstone("did:plc:alice", Some(1), Some(&unrelated_key())));
+        let report = archive.verify_archived_range(0, 3, &cache);
+        assert!(!report.passed_all());
+        assert_eq!(report.by_did["did:plc:alice"], DidVerifyCounts { passed: 0, failed: 3 });
+        assert_eq!(report.sampled_failures.iter().map(|f| (f.seq, f.error)).collect::<Vec<_>>(), vec![
+            (0, ErrorType::InvalidSignature),
+            (1, ErrorType::InvalidSignature),
+            (2, ErrorType::InvalidSignature),
+        ]);
+    }
+
+    fn unrelated_key() -> [u8; 33] {
+        SigningKey::k256_from_seed(0x22).public_key()
+    }
+}
@@ -0,0 +1,60 @@
+#[cfg(test)]
+mod seek_by_time_tests {
+    use did_mmap_cache::archive::MultiShardArchive;
+    use did_mmap_cache::parser::core::decode_tid;
+    use did_mmap_cache::testutil::{post_record, FrameBuilder, SigningKey};
+    use tempfile::tempdir;
+
+    const TID_ALPHABET: &[u8] = b"234567abcdefghijklmnopqrstuvwxyz";
+    const BASE_US: u64 = 1_700_000_000_000_000;
+
+    fn tid(micros: u64) -> String {
+        let v = micros << 10;
+        (0..13).map(|i| TID_ALPHABET[((v >> (5 * (12 - i))) & 31) as usize] as char).collect()
+    }
+
+    /// A signed #commit creating a post at `path`.
+    fn frame(did: &str, seq: u64, path: &str) -> Vec<u8> {
+        FrameBuilder::new(did, SigningKey::k256_from_seed(0x11)).seq(seq).create(path, post_record("hello")).build()
+    }
+
+    #[test]
+    fn test_tid_helper_round_trips() {
+        assert_eq!(decode_tid(&tid(BASE_US)), Some(BASE_US));
+    }
+
+    #[test]
+    fn test_seek_by_time() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("archive");
+
+        // One message per second starting at BASE_US, except a profile record with no TID
+        let archive = MultiShardArchive::new(&root, 2, 10, None).unwrap();
+        for seq in 100..160u64 {
+            let did = format!("did:plc:user{}", seq % 5);
+            let path = if seq == 130 {
+                "app.bsky.actor.profile/self".to_string()
+            } else {
+                format!("app.bsky.feed.post/{}", tid(BASE_US + (seq - 100) * 1_000_000))
+            };
+            archive.ingest(seq, &did, path.clone(), frame(&did, seq, &path));
+        }
+        archive.shutdown();
+
+        let reader = MultiShardArchive::open_readonly(&root, None).unwrap();
+        assert_eq!(reader.seek_by_time(0), Some(100));
+        assert_eq!(reader.seek_by_time(BASE_US + 10 * 1_000_000), Some(110));
+        // Between two messages: the next one
+        assert_eq!(reader.seek_by_time(BASE_US + 10 * 1_000_000 + 1), Some(111));
+        // seq 130 has no timestamp and is stepped over
+        assert_eq!(reader.seek_by_time(BASE_US + 30 * 1_000_000), Some(131));
+        // Seeking backwards after a later seek still finds the earlier message
+        assert_eq!(reader.seek_by_time(BASE_US + 5 * 1_000_000), Some(105));
+        // Later than anything stored
+        assert_eq!(reader.seek_by_time(BASE_US + 3600 * 1_000_000), None);
+
+        // Tombstoned messages are not returned
+        reader.mark_deleted(120).unwrap();
+        assert_eq!(reader.seek_by_time(BASE_US + 20 * 1_000_000), Some(121));
+    }
+}
@@ -0,0 +1,108 @@
+#[cfg(test)]
+mod seq_continuity_tests {
+    use did_mmap_cache::monitor::continuity::{HostContinuity, RECENT_GAPS_PER_HOST};
+    use did_mmap_cache::monitor::{SeqContinuity, SeqStep, SovereignMonitor};
+
+    fn feed(continuity: &SeqContinuity, host: &str, seqs: &[u64]) -> Vec<SeqStep> {
+        seqs.iter().map(|&seq| continuity.observe(host, seq)).collect()
+    }
+
+    fn counters(h: &HostContinuity) -> (u64, u64, u64, u64, u64, u64) {
+        (h.frames, h.gaps, h.missing, h.out_of_order, h.repeats, h.resets)
+    }
+
+    #[test]
+    fn test_monotonic_and_gapped_hosts() {
+        let continuity = SeqContinuity::new();
+        let steps = feed(&continuity, "steady.pds.example", &[1, 2, 3, 4]);
+        assert_eq!(steps, vec![SeqStep::First, SeqStep::Next, SeqStep::Next, SeqStep::Next]);
+        let steady = continuity.host("steady.pds.example").unwrap();
+        assert_eq!(counters(&steady), (4, 0, 0, 0, 0, 0));
+        assert_eq!(steady.last_seq, Some(4));
+
+        let steps = feed(&continuity, "gappy.pds.example", &[10, 11, 15, 16, 100]);
+        assert_eq!(steps[2], SeqStep::Gap { start: 12, end: 14 });
+        assert_eq!(steps[4], SeqStep::Gap { start: 17, end: 99 });
+        let gappy = continuity.host("gappy.pds.example").unwrap();
+        assert_eq!(counters(&gappy), (5, 2, 3 + 83, 0, 0, 0));
+        assert_eq!((gappy.recent_gaps[0].start, gappy.recent_gaps[0].end, gappy.recent_gaps[0].span()), (12, 14, 3));
+        assert!(gappy.recent_gaps[0].observed_at > 0);
+
+        let report = continuity.report();
+        assert_eq!((report.hosts, report.gaps, report.missing), (2, 2, 86));
+        assert_eq!(report.gappiest.len(), 1);
+        assert_eq!(report.gappiest[0].host, "gappy.pds.example");
+        assert_eq!(report.gappiest[0].latest.as_ref().map(|g| (g.start, g.end)), Some((17, 99)));
+    }
+
+    #[test]
+    fn test_reordered_repeated_and_reset_seqs() {
+        let continuity = SeqContinuity::new();
+        // 3 and 4 arrive after 5; the gap they leave is closed again
+        let steps = feed(&continuity, "shuffled.pds.example", &[1, 2, 5, 4, 3, 6, 6]);
+        assert_eq!(steps[2..], [SeqStep::Gap { start: 3, end: 4 }, SeqStep::Late, SeqStep::Late, SeqStep::Next, SeqStep::Repeat]);
+        let shuffled = continuity.host("shuffled.pds.example").unwrap();
+        assert_eq!(counters(&shuffled), (7, 1, 0, 2, 1, 0));
+        assert_eq!(shuffled.recent_gaps[0].late, 2);
+        // Closed gaps don't make a host gappy
+        assert!(continuity.report().gappiest.is_empty());
+
+        // A restarted PDS counting from 1 again continues from there, without a gap
+        let steps = feed(&continuity, "restarted.pds.example", &[500, 501, 1, 2, 3]);
+        assert_eq!(steps[2..], [SeqStep::Reset { from: 501 }, SeqStep::Next, SeqStep::Next]);
+        let restarted = continuity.host("restarted.pds.example").unwrap();
+        assert_eq!(counters(&restarted), (5, 0, 0, 0, 0, 1));
+        assert_eq!(restarted.last_seq, Some(3));
+
+        let report = continuity.report();
+        assert_eq!((report.out_of_order, report.repeats, report.resets), (2, 1, 1));
+    }
+
+    #[test]
+    fn test_gappiest_ranking_and_bounded_history() {
+        let continuity = SeqContinuity::new();
+        for i in 0..8u64 {
+            // Host i skips i + 1 seqs once
+            feed(&continuity, &format!("pds{}.example", i), &[1, i + 3]);
+        }
+        let mut seq = 0;
+        for _ in 0..RECENT_GAPS_PER_HOST + 10 {
+            seq += 2;
+            continuity.observe("flaky.pds.example", seq);
+        }
+        let flaky = continuity.host("flaky.pds.example").unwrap();
+        assert_eq!(flaky.gaps, (RECENT_GAPS_PER_HOST + 9) as u64);
+        assert_eq!(flaky.recent_gaps.len(), RECENT_GAPS_PER_HOST);
+        assert_eq!(flaky.recent_gaps.back().unwrap().end, seq - 1);
+
+        let hosts: Vec<String> = continuity.report().gappiest.into_iter().map(|h| h.host).collect();
+        assert_eq!(hosts, vec!["flaky.pds.example", "pds7.example", "pds6.example", "pds5.example", "pds4.example"]);
+    }
+
+    #[test]
+    fn test_totals_survive_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pds_continuity.json");
+        let monitor = SovereignMonitor::new();
+        feed(&monitor.continuity, "gappy.pds.example", &[1, 2, 10]);
+        monitor.continuity.save(&path).unwrap();
+        assert_eq!(monitor.report().continuity.missing, 7);
+
+        let resumed = SeqContinuity::new();
+        assert_eq!(resumed.load(&path).unwrap(), 1);
+        assert_eq!(resumed.host("gappy.pds.example"), monitor.continuity.host("gappy.pds.example"));
+        // Picks up where the last run stopped
+        assert_eq!(resumed.observe("gappy.pds.example", 11), SeqStep::Next);
+        assert_eq!(resumed.observe("gappy.pds.example", 13), SeqStep::Gap { start: 12, end: 12 });
+        assert_eq!(resumed.report().missing, 8);
+
+        // Starting from the live head keeps the totals but not the position
+        let live = SeqContinuity::new();
+        live.load(&path).unwrap();
+        live.rebase();
+        assert_eq!(live.observe("gappy.pds.example", 90_000), SeqStep::First);
+        assert_eq!(live.report().missing, 7);
+
+        assert_eq!(live.load(dir.path().join("missing.json")).unwrap_err().kind(), std::io::ErrorKind::NotFound);
+    }
+}
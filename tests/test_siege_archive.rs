@@ -0,0 +1,201 @@
+#[cfg(test)]
+mod siege_archive_tests {
+    use did_mmap_cache::aggregate::{FrameDedup, SiegeArchiver, SiegeStats};
+    use did_mmap_cache::archive::MultiShardArchive;
+    use did_mmap_cache::testutil::identity_frame;
+    use std::collections::HashMap;
+    use std::net::TcpListener;
+    use std::sync::atomic::Ordering;
+    use std::sync::{mpsc, Arc};
+    use std::thread;
+    use tempfile::tempdir;
+    use tungstenite::Message;
+
+    /// An `#identity` event for `did:plc:userN` at sequence `seq`.
+    fn frame(seq: u64) -> Vec<u8> {
+        identity_frame(&format!("did:plc:user{}", seq % 7), seq, None)
+    }
+
+    /// A PDS that sends `frames` and closes.
+    fn mock_pds(frames: Vec<Vec<u8>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut ws = tungstenite::accept(stream).unwrap();
+            for f in frames {
+                ws.send(Message::Binary(f)).unwrap();
+            }
+            ws.close(None).unwrap();
+            while ws.read().is_ok() {}
+        });
+        format!("ws://{}/xrpc/com.atproto.sync.subscribeRepos", addr)
+    }
+
+    /// Connects to every endpoint and feeds what arrives through `archiver`, as siege does.
+    fn siege(endpoints: &[String], archiver: &mut SiegeArchiver) -> u64 {
+        let (tx, rx) = mpsc::channel::<(Arc<String>, Vec<u8>)>();
+        for url in endpoints {
+            let (url, tx) = (Arc::new(url.clone()), tx.clone());
+            thread::spawn(move || {
+                let (mut ws, _) = tungstenite::connect(url.as_str()).unwrap();
+                while let Ok(msg) = ws.read() {
+                    if let Message::Binary(data) = msg {
+                        tx.send((Arc::clone(&url), data)).unwrap();
+                    }
+                }
+            });
+        }
+        drop(tx);
+
+        let mut received = 0;
+        for (url, data) in rx {
+            received += 1;
+            let hash: [u8; 32] = blake3::hash(&data).into();
+            archiver.offer(&url, &hash, &data);
+        }
+        received
+    }
+
+    #[test]
+    fn test_dedup_window() {
+        let mut dedup = FrameDedup::new(2);
+        let (a, b, c) = ([1u8; 32], [2u8; 32], [3u8; 32]);
+        assert!(dedup.insert(&a));
+        assert!(!dedup.insert(&a));
+        assert!(dedup.insert(&b));
+        assert!(dedup.insert(&c));
+        // `a` fell out of the exact window, so the bloom hit alone doesn't reject it
+        assert!(dedup.insert(&a));
+        assert!(!dedup.insert(&c));
+    }
+
+    #[test]
+    fn test_bloom_round_trip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("siege_bloom.bin");
+        let seen: Vec<[u8; 32]> = (0..100u8).map(|i| [i; 32]).collect();
+        let mut dedup = FrameDedup::new(4);
+        for hash in &seen {
+            assert!(dedup.insert(hash));
+        }
+        dedup.save_bloom(&path).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 24 + 1024 * 1024);
+
+        // A missing file just leaves the fresh filter in place
+        let mut restarted = FrameDedup::new(4);
+        assert!(!restarted.load_bloom(dir.path().join("missing.bin")).unwrap());
+        assert!(restarted.load_bloom(&path).unwrap());
+        assert!(!restarted.insert(&seen[0]));
+        assert!(!restarted.insert(&seen[99]));
+        let new: Vec<[u8; 32]> = (200..204u8).map(|i| [i; 32]).collect();
+        for hash in &new {
+            assert!(restarted.insert(hash));
+        }
+        // A full window later only the exact set rejects
+        assert!(restarted.insert(&seen[50]));
+        assert!(!restarted.insert(&new[3]));
+
+        std::fs::write(&path, b"SGBF short").unwrap();
+        assert_eq!(restarted.load_bloom(&path).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_overlapping_pds_frames_are_archived_once() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("archive");
+        let first = mock_pds((0..30).map(frame).collect());
+        let second = mock_pds((20..50).map(frame).collect());
+
+        let archive = Arc::new(MultiShardArchive::new(&root, 4, 16, None).unwrap());
+        let stats = Arc::new(SiegeStats::default());
+        let mut archiver = SiegeArchiver::new(Some(archive), Arc::clone(&stats));
+        assert_eq!(siege(&[first.clone(), second.clone()], &mut archiver), 60);
+        archiver.finish();
+
+        assert_eq!(stats.archived.load(Ordering::Relaxed), 50);
+        assert_eq!(stats.pending.load(Ordering::Relaxed), 0);
+        assert_eq!(stats.skipped.load(Ordering::Relaxed), 0);
+
+        let reader = MultiShardArchive::open_readonly(&root, None).unwrap();
+        assert_eq!((reader.min_seq(), reader.max_seq()), (Some(0), Some(49)));
+        let mut copies: HashMap<Vec<u8>, usize> = HashMap::new();
+        for seq in 0..50 {
+            *copies.entry(reader.get_message_by_seq(seq).unwrap()).or_default() += 1;
+        }
+        assert_eq!(copies.len(), 50);
+        for seq in 0..50 {
+            assert_eq!(copies.get(&frame(seq)), Some(&1), "frame {}", seq);
+        }
+
+        // The overlap went to whichever PDS delivered it first; each still has a cursor
+        let cursor_file = dir.path().join("siege_cursors.json");
+        assert_eq!(stats.save_cursors(&cursor_file).unwrap(), 2);
+        let reloaded = SiegeStats::default();
+        assert_eq!(reloaded.load_cursors(&cursor_file).unwrap(), 2);
+        assert!((19..30).contains(&*reloaded.cursors.get(&first).unwrap()));
+        assert_eq!(*reloaded.cursors.get(&second).unwrap(), 49);
+    }
+
+    #[test]
+    fn test_dry_run_writes_nothing() {
+        let dir = tempdir().unwrap();
+        let endpoint = mock_pds((0..10).chain(0..10).map(frame).collect());
+        let stats = Arc::new(SiegeStats::default());
+        let mut archiver = SiegeArchiver::new(None, Arc::clone(&stats));
+        assert_eq!(siege(std::slice::from_ref(&endpoint), &mut archiver), 20);
+        archiver.finish();
+
+        assert_eq!(stats.archived.load(Ordering::Relaxed), 0);
+        assert_eq!(*stats.cursors.get(&endpoint).unwrap(), 9);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+        assert_eq!(SiegeStats::default().load_cursors(dir.path().join("missing.json")).unwrap(), 0);
+    }
+}
+
+#[cfg(all(test, feature = "test-fixtures"))]
+mod siege_verify_tests {
+    use did_mmap_cache::aggregate::{SiegeArchiver, SiegeStats};
+    use did_mmap_cache::archive::MultiShardArchive;
+    use did_mmap_cache::mmap_did_cache::MmapDidCache;
+    use did_mmap_cache::testutil::{identity_frame, post_record, FrameBuilder, SigningKey};
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_only_verified_commits_are_archived() {
+        let dir = tempdir().unwrap();
+        let alice = "did:plc:ewvi7nxzyoun6zhxrhs64oiz";
+        let commit = |seq: u64| FrameBuilder::new(alice, SigningKey::k256_from_seed(0x11)).seq(seq).create(format!("app.bsky.feed.post/3lbf{:09}", seq), post_record("hi"));
+        let mut cache = MmapDidCache::create(dir.path().join("cache.bin"), 64).unwrap();
+        let good = commit(1);
+        cache.atomic_update_or_tombstone(alice, Some(good.key_type()), Some(&good.public_key()));
+        drop(cache);
+
+        let frames = [
+            good.build(),
+            commit(2).tamper_signature().build(),
+            // Signed, but the repo has no key in the cache
+            FrameBuilder::new("did:plc:z72i7hdynmk6r22z27h6tvur", SigningKey::k256_from_seed(0x22)).seq(3).build(),
+            identity_frame(alice, 4, None),
+        ];
+        let root = dir.path().join("archive");
+        let archive = Arc::new(MultiShardArchive::new(&root, 2, 16, None).unwrap());
+        let stats = Arc::new(SiegeStats::default());
+        let cache = MmapDidCache::open(dir.path().join("cache.bin")).unwrap();
+        let mut archiver = SiegeArchiver::new_verifying(Some(archive), Arc::clone(&stats), Some(cache));
+        let url = Arc::new("wss://pds.example".to_string());
+        for frame in &frames {
+            assert!(archiver.offer(&url, blake3::hash(frame).as_bytes(), frame));
+        }
+        archiver.finish();
+
+        assert_eq!(stats.archived.load(Ordering::Relaxed), 2);
+        assert_eq!(stats.unverified.load(Ordering::Relaxed), 2);
+        assert_eq!(*stats.cursors.get(url.as_str()).unwrap(), 4);
+        let reader = MultiShardArchive::open_readonly(&root, None).unwrap();
+        assert_eq!(reader.get_message_by_seq(0).unwrap(), frames[0]);
+        assert_eq!(reader.get_message_by_seq(1).unwrap(), frames[3]);
+    }
+}
@@ -0,0 +1,95 @@
+#[cfg(all(test, feature = "test-fixtures"))]
+mod sovereign_query_tests {
+    use did_mmap_cache::archive::MultiShardArchive;
+    use did_mmap_cache::testutil::{identity_frame, post_record_at, FrameBuilder, SigningKey};
+    use serde_json::Value;
+    use std::path::Path;
+    use std::process::Command;
+    use tempfile::tempdir;
+
+    const ALICE: &str = "did:plc:alice";
+    const BOB: &str = "did:plc:bob";
+
+    fn post(did: &str, seq: u64, rkey: &str, text: &str, created_at: &str) -> Vec<u8> {
+        FrameBuilder::new(did, SigningKey::k256_from_seed(seq as u8))
+            .seq(seq)
+            .create(format!("app.bsky.feed.post/{}", rkey), post_record_at(text, created_at))
+            .build()
+    }
+
+    /// Alice posts at seqs 1 and 3, Bob at 2, and seq 4 is Alice's identity event.
+    fn fixture_archive(dir: &Path) -> Vec<u8> {
+        let archive = MultiShardArchive::new(dir, 2, 10, None).unwrap();
+        let bobs = post(BOB, 2, "b1", "from bob", "2024-06-01T12:00:00.000Z");
+        archive.ingest(1, ALICE, "app.bsky.feed.post/a1".into(), post(ALICE, 1, "a1", "hello", "2024-01-01T00:00:00.000Z"));
+        archive.ingest(2, BOB, "app.bsky.feed.post/b1".into(), bobs.clone());
+        archive.ingest(3, ALICE, "app.bsky.feed.post/a2".into(), post(ALICE, 3, "a2", "later", "2025-01-01T00:00:00.000Z"));
+        archive.ingest(4, ALICE, String::new(), identity_frame(ALICE, 4, Some("alice.test")));
+        archive.shutdown();
+        bobs
+    }
+
+    fn query(dir: &Path, args: &[&str]) -> String {
+        let output = Command::new(env!("CARGO_BIN_EXE_sovereign_query")).arg(dir).args(args).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    fn query_jsonl(dir: &Path, args: &[&str]) -> Vec<Value> {
+        let mut args = args.to_vec();
+        args.extend(["--format", "jsonl"]);
+        query(dir, &args)
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    fn seqs(rows: &[Value]) -> Vec<u64> {
+        rows.iter().map(|r| r["seq"].as_u64().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_each_query_mode() {
+        let dir = tempdir().unwrap();
+        let bobs = fixture_archive(dir.path());
+
+        let by_seq = query_jsonl(dir.path(), &["--seq", "2"]);
+        assert_eq!(by_seq.len(), 1);
+        assert_eq!(by_seq[0]["did"], BOB);
+        assert_eq!(by_seq[0]["event"], "#commit");
+        assert_eq!(by_seq[0]["ops"][0]["path"], "app.bsky.feed.post/b1");
+        assert_eq!(by_seq[0]["record_kind"], "post");
+        assert_eq!(by_seq[0]["record"], "post: from bob");
+        assert_eq!(by_seq[0]["created_at"], "2024-06-01T12:00:00.000Z");
+        assert_eq!(query(dir.path(), &["--seq", "2", "--hex"]).trim(), hex::encode(&bobs));
+
+        let by_did = query_jsonl(dir.path(), &["--did", ALICE]);
+        assert_eq!(seqs(&by_did), vec![1, 3, 4]);
+        assert_eq!(by_did[2]["event"], "#identity");
+
+        let by_path = query_jsonl(dir.path(), &["--path", "at://did:plc:alice/app.bsky.feed.post/a2"]);
+        assert_eq!(seqs(&by_path), vec![3]);
+        assert_eq!(by_path[0]["record"], "post: later");
+
+        let window = query_jsonl(dir.path(), &["--since", "2024-03-01T00:00:00Z", "--until", "2024-12-31T00:00:00Z", "--from", "0"]);
+        assert_eq!(seqs(&window), vec![2]);
+        // 2024-06-01T00:00:00Z; without --since the scan starts at the first stored seq
+        assert_eq!(seqs(&query_jsonl(dir.path(), &["--until", "1717200000"])), vec![1]);
+
+        let table = query(dir.path(), &["--did", BOB]);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].trim_start().starts_with("SEQ"));
+        assert!(lines[1].contains("app.bsky.feed.post/b1") && lines[1].ends_with("post: from bob"));
+    }
+
+    #[test]
+    fn test_missing_record_fails() {
+        let dir = tempdir().unwrap();
+        fixture_archive(dir.path());
+        for args in [&["--path", "at://did:plc:alice/app.bsky.feed.post/nope"][..], &["--seq", "99"], &["--path", "did:plc:alice/post"]] {
+            let output = Command::new(env!("CARGO_BIN_EXE_sovereign_query")).arg(dir.path()).args(args).output().unwrap();
+            assert!(!output.status.success(), "{:?}", args);
+        }
+    }
+}
@@ -0,0 +1,56 @@
+#[cfg(test)]
+mod streaming_read_tests {
+    use did_mmap_cache::archive::MultiShardArchive;
+    use std::path::Path;
+    use tempfile::tempdir;
+
+    /// 200 messages from one DID, so they all land in one large cluster, plus a second DID.
+    fn write_archive(root: &Path, dict: Option<Vec<u8>>) -> MultiShardArchive {
+        let archive = MultiShardArchive::new(root, 1, 1000, dict.clone()).unwrap();
+        for seq in 0..220u64 {
+            let did = if seq % 11 == 0 { "did:plc:other" } else { "did:plc:busy" };
+            archive.ingest(seq, did, format!("app.bsky.feed.post/{}", seq), msg(seq));
+        }
+        archive.shutdown();
+        MultiShardArchive::open_readonly(root, dict).unwrap()
+    }
+
+    fn msg(seq: u64) -> Vec<u8> {
+        let mut m = format!("atproto_pattern_message_{}_", seq).into_bytes();
+        m.extend((0..2000u64).map(|i| (i.wrapping_mul(seq + 7) % 251) as u8));
+        m
+    }
+
+    #[test]
+    fn test_streaming_read_matches_full_read() {
+        for dict in [None, Some(b"atproto_pattern_message_".repeat(50))] {
+            let dir = tempdir().unwrap();
+            let archive = write_archive(dir.path(), dict);
+            // Cold cache: every read is a streaming decode up to that message only
+            for seq in (0..220).rev() {
+                assert_eq!(archive.get_message_streaming(seq).unwrap(), msg(seq), "seq {}", seq);
+            }
+            // Warm cache: the full path fills it, the streaming path then slices from it
+            for seq in 0..220 {
+                assert_eq!(archive.get_message_by_seq(seq).unwrap(), archive.get_message_streaming(seq).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_streaming_read_respects_gaps_and_tombstones() {
+        let dir = tempdir().unwrap();
+        let archive = MultiShardArchive::new(dir.path(), 1, 1000, None).unwrap();
+        archive.ingest(0, "did:plc:a", "p/0".into(), msg(0));
+        archive.ingest(2, "did:plc:a", "p/2".into(), msg(2));
+        archive.ingest(3, "did:plc:a", "p/3".into(), msg(3));
+        archive.shutdown();
+        let archive = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        archive.mark_deleted(3).unwrap();
+
+        assert!(archive.get_message_streaming(1).is_err());
+        assert!(archive.get_message_streaming(3).is_err());
+        assert!(archive.get_message_streaming(99).is_err());
+        assert_eq!(archive.get_message_streaming(2).unwrap(), msg(2));
+    }
+}
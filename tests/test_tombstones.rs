@@ -0,0 +1,63 @@
+#[cfg(test)]
+mod tombstone_tests {
+    use did_mmap_cache::archive::{MultiShardArchive, TombstoneError, TombstoneStore};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_range_marking_across_byte_boundaries() {
+        let dir = tempdir().unwrap();
+        let mut ts = TombstoneStore::open_or_create(dir.path().join("tombstones.bin")).unwrap();
+
+        // Within one byte, then spanning a partial byte, whole words and another partial byte
+        assert_eq!(ts.mark_deleted_range(2, 5).unwrap(), 3);
+        assert_eq!(ts.mark_deleted_range(13, 150).unwrap(), 137);
+        assert_eq!(ts.mark_deleted_range(3, 4).unwrap(), 0);
+        assert_eq!(ts.mark_deleted_range(7, 7).unwrap(), 0);
+        assert!((0..200).all(|seq| ts.is_deleted(seq) == ((2..5).contains(&seq) || (13..150).contains(&seq))));
+        assert_eq!(ts.count(), 140);
+
+        assert_eq!(ts.count_deleted_in(0, 200), 140);
+        assert_eq!(ts.count_deleted_in(4, 14), 2);
+        assert_eq!(ts.count_deleted_in(16, 144), 128);
+        assert_eq!(ts.count_deleted_in(149, 151), 1);
+        assert_eq!(ts.count_deleted_in(150, 150), 0);
+
+        assert_eq!(ts.mark_deleted_many(&[1, 200, 14, 200]).unwrap(), 2);
+        assert!(ts.is_deleted(1) && ts.is_deleted(200));
+        assert_eq!(ts.count(), 142);
+    }
+
+    #[test]
+    fn test_out_of_range_is_an_error() {
+        let dir = tempdir().unwrap();
+        let mut ts = TombstoneStore::open_or_create(dir.path().join("tombstones.bin")).unwrap();
+        let capacity = ts.capacity();
+
+        ts.mark_deleted(capacity - 1).unwrap();
+        assert!(matches!(ts.mark_deleted(capacity), Err(TombstoneError::OutOfRange { seq, .. }) if seq == capacity));
+        assert!(matches!(ts.mark_deleted(u64::MAX), Err(TombstoneError::OutOfRange { .. })));
+        // A range or batch reaching past the end marks nothing
+        assert!(matches!(ts.mark_deleted_range(capacity - 8, capacity + 1), Err(TombstoneError::OutOfRange { .. })));
+        assert!(matches!(ts.mark_deleted_many(&[5, capacity]), Err(TombstoneError::OutOfRange { .. })));
+        assert!(!ts.is_deleted(5) && !ts.is_deleted(capacity - 8));
+        assert_eq!(ts.count(), 1);
+        assert_eq!(ts.count_deleted_in(capacity - 1, u64::MAX), 1);
+    }
+
+    #[test]
+    fn test_bulk_tombstones_persist_after_reopen() {
+        let dir = tempdir().unwrap();
+        {
+            let archive = MultiShardArchive::new(dir.path(), 2, 10, None).unwrap();
+            assert_eq!(archive.mark_deleted_range(100, 1_100).unwrap(), 1_000);
+            assert_eq!(archive.mark_deleted_many(&[5, 2_000]).unwrap(), 2);
+            archive.mark_deleted(7).unwrap();
+            archive.shutdown();
+        }
+
+        let archive = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        assert_eq!(archive.count_deleted_in(0, 3_000), 1_003);
+        assert_eq!(archive.count_deleted_in(99, 101), 1);
+        assert_eq!(archive.count_deleted_in(1_099, 2_001), 2);
+    }
+}
@@ -1,9 +1,7 @@
 #[cfg(test)]
 mod v2_2_tests {
-    use did_mmap_cache::archive::{ArchiveWriter, SegmentedArchive, MultiShardArchive};
+    use did_mmap_cache::archive::{ArchiveError, ArchiveWriter, PathHash, SegmentedArchive, MultiShardArchive};
     use tempfile::tempdir;
-    use fxhash::FxHasher;
-    use std::hash::{Hasher, Hash};
 
     #[test]
     fn test_v2_2_path_hash_and_gaps() {
@@ -33,15 +31,14 @@ mod v2_2_tests {
         assert_eq!(archive.get_message_by_seq(100, None).unwrap(), msg1);
         assert_eq!(archive.get_message_by_seq(105, None).unwrap(), msg2);
         
-        // Check gap (should be io::ErrorKind::NotFound)
-        let gap_res = archive.get_message_by_seq(102, None);
-        assert!(gap_res.is_err());
-        assert_eq!(gap_res.unwrap_err().kind(), std::io::ErrorKind::NotFound);
+        // Check gap, and either side of the stored range
+        assert!(matches!(archive.get_message_by_seq(102, None), Err(ArchiveError::Gap { seq: 102 })));
+        assert!(matches!(archive.get_raw_cluster_at_seq(102), Err(ArchiveError::Gap { seq: 102 })));
+        assert!(matches!(archive.get_message_by_seq(99, None), Err(ArchiveError::NotFound)));
+        assert!(matches!(archive.get_message_by_seq(106, None), Err(ArchiveError::OutOfRange)));
         
         // 3. Test Path-Hash Lookup
-        let mut hasher = FxHasher::default();
-        path2.hash(&mut hasher);
-        let target_hash = hasher.finish();
+        let target_hash = PathHash::default().hash(path2);
         
         let found_seq = archive.find_sequence_by_path(target_hash).unwrap();
         assert_eq!(found_seq, 105);
@@ -70,15 +67,20 @@ mod v2_2_tests {
         assert_eq!(archive_ro.get_message_by_seq(500).unwrap(), msg);
         
         // Mark as deleted
-        archive_ro.mark_deleted(500);
+        archive_ro.mark_deleted(500).unwrap();
         
         // Verify message is now "Not Found" due to tombstone
         let res = archive_ro.get_message_by_seq(500);
-        assert!(res.is_err());
+        assert!(matches!(res, Err(ArchiveError::Tombstoned { seq: 500 })));
         
         // Verify raw cluster filtering
         let cluster_res = archive_ro.get_raw_cluster_at_seq(500);
-        assert!(cluster_res.is_err(), "Raw cluster should be rejected if message is tombstoned");
+        assert!(matches!(cluster_res, Err(ArchiveError::Tombstoned { seq: 500 })), "Raw cluster should be rejected if message is tombstoned");
+
+        // Past the last stored seq is the end of the archive, not a tombstone
+        assert!(matches!(archive_ro.get_raw_cluster_at_seq(501), Err(ArchiveError::OutOfRange)));
+        let io_err: std::io::Error = ArchiveError::Tombstoned { seq: 500 }.into();
+        assert_eq!(io_err.kind(), std::io::ErrorKind::NotFound);
     }
 
     #[test]
@@ -101,4 +103,36 @@ mod v2_2_tests {
         
         assert_eq!(res, msg);
     }
+
+    #[test]
+    fn test_idle_flush_persists_without_shutdown() {
+        let dir = tempdir().unwrap();
+        let archive_dir = dir.path().join("idle_archive");
+
+        // Segment size far above what we ingest, so only the timer can flush
+        let archive = MultiShardArchive::new(&archive_dir, 4, 10_000, None).unwrap();
+        archive.start_idle_flush(std::time::Duration::from_millis(200));
+
+        for seq in 0..10u64 {
+            archive.ingest(seq, &format!("did:plc:idle{}", seq), format!("app.bsky.feed.post/{}", seq), vec![seq as u8; 32]);
+        }
+
+        let count_segments = || {
+            (0..4).map(|i| {
+                std::fs::read_dir(archive_dir.join(format!("shard_{}", i))).unwrap()
+                    .filter(|e| e.as_ref().unwrap().path().extension().is_some_and(|x| x == "idx"))
+                    .count()
+            }).sum::<usize>()
+        };
+        assert_eq!(count_segments(), 0);
+
+        std::thread::sleep(std::time::Duration::from_millis(1000));
+        assert!(count_segments() > 0, "idle shards should have been persisted");
+
+        let reader = MultiShardArchive::open_readonly(&archive_dir, None).unwrap();
+        for seq in 0..10u64 {
+            assert_eq!(reader.get_message_by_seq(seq).unwrap(), vec![seq as u8; 32]);
+        }
+        archive.shutdown();
+    }
 }
@@ -0,0 +1,182 @@
+#[cfg(test)]
+mod verify_outcome_tests {
+    use did_mmap_cache::monitor::ErrorType;
+    use did_mmap_cache::parser::core::{CommitEnvelope, RepoOps};
+    use did_mmap_cache::verify::{
+        verify_commit, verify_commit_detailed, verify_commit_detailed_with, verify_commit_parsed, verify_commit_parsed_with, ParsedKey,
+        SignatureForm, VerifyError, VerifyOptions, VerifyOutcome,
+    };
+    use k256::ecdsa::signature::hazmat::PrehashSigner;
+    use k256::ecdsa::SigningKey;
+    use sha2::Digest;
+
+    // {"pay": "load"}
+    const COMMIT: [u8; 10] = [0xa1, 0x63, b'p', b'a', b'y', 0x63, b'l', b'o', b'a', b'd'];
+
+    fn envelope<'a>(commit: Option<&'a [u8]>, signature: Option<&'a [u8]>) -> CommitEnvelope<'a> {
+        CommitEnvelope {
+            did: None,
+            sequence: None,
+            signature,
+            t: None,
+            op: None,
+            raw: &[],
+            blocks: None,
+            commit,
+            cid: None,
+            record_cid: None,
+            ops: RepoOps::new(),
+            source_type: "test",
+        }
+    }
+
+    /// A fresh secp256k1 key and its signature over `COMMIT`.
+    fn signed() -> ([u8; 33], Vec<u8>) {
+        let signing_key = SigningKey::random(&mut rand::thread_rng());
+        let pubkey: [u8; 33] = signing_key.verifying_key().to_sec1_bytes().as_ref().try_into().unwrap();
+        let mut hasher = sha2::Sha256::new();
+        assert!(did_mmap_cache::parser::canonical::hash_canonical_commit(&COMMIT, &mut hasher));
+        let sig: k256::ecdsa::Signature = signing_key.sign_prehash(&hasher.finalize()).unwrap();
+        (pubkey, sig.to_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_outcomes() {
+        let (pubkey, sig) = signed();
+        let (other_key, _) = signed();
+
+        let cases: [(CommitEnvelope, [u8; 33], u8, VerifyOutcome); 8] = [
+            (envelope(Some(&COMMIT), Some(&sig)), pubkey, 1, VerifyOutcome::Ok),
+            (envelope(None, Some(&sig)), pubkey, 1, VerifyOutcome::NoCommit),
+            (envelope(Some(&COMMIT), None), pubkey, 1, VerifyOutcome::NoSignature),
+            (envelope(Some(&COMMIT), Some(&[])), pubkey, 1, VerifyOutcome::NoSignature),
+            (envelope(Some(&[0xa1, 0x63, b'p']), Some(&sig)), pubkey, 1, VerifyOutcome::CanonicalizationFailed),
+            (envelope(Some(&COMMIT), Some(&sig)), other_key, 1, VerifyOutcome::Mismatch),
+            (envelope(Some(&COMMIT), Some(&sig)), [0xff; 33], 1, VerifyOutcome::BadKey),
+            (envelope(Some(&COMMIT), Some(&sig)), pubkey, 9, VerifyOutcome::BadKey),
+        ];
+        for (i, (env, key, kt, expected)) in cases.iter().enumerate() {
+            assert_eq!(verify_commit_detailed(env, key, *kt), *expected, "case {}", i);
+            assert_eq!(verify_commit(env, key, *kt), expected.is_ok(), "case {}", i);
+        }
+    }
+
+    /// A fresh P-256 key and its low-S signature over `COMMIT`.
+    fn signed_p256() -> ([u8; 33], Vec<u8>) {
+        let signing_key = p256::ecdsa::SigningKey::random(&mut rand::thread_rng());
+        let pubkey: [u8; 33] = signing_key.verifying_key().to_encoded_point(true).as_bytes().try_into().unwrap();
+        let mut hasher = sha2::Sha256::new();
+        assert!(did_mmap_cache::parser::canonical::hash_canonical_commit(&COMMIT, &mut hasher));
+        let sig: p256::ecdsa::Signature = signing_key.sign_prehash(&hasher.finalize()).unwrap();
+        // Unlike k256, p256 doesn't normalize what it signs
+        (pubkey, sig.normalize_s().unwrap_or(sig).to_bytes().to_vec())
+    }
+
+    /// `sig` with s replaced by n - s: the high-S twin of a low-S signature.
+    fn high_s(key_type: u8, sig: &[u8]) -> Vec<u8> {
+        match key_type {
+            1 => {
+                let (r, s) = k256::ecdsa::Signature::from_slice(sig).unwrap().split_scalars();
+                k256::ecdsa::Signature::from_scalars(r, -s).unwrap().to_bytes().to_vec()
+            }
+            _ => {
+                let (r, s) = p256::ecdsa::Signature::from_slice(sig).unwrap().split_scalars();
+                p256::ecdsa::Signature::from_scalars(r, -s).unwrap().to_bytes().to_vec()
+            }
+        }
+    }
+
+    #[test]
+    fn test_parsed_key_both_curves() {
+        for (kt, (pubkey, sig)) in [(1u8, signed()), (2u8, signed_p256())] {
+            let key = ParsedKey::from_bytes(kt, &pubkey).unwrap();
+            assert_eq!(key.key_type(), kt);
+            assert_eq!(verify_commit_parsed(&envelope(Some(&COMMIT), Some(&sig)), &key), Ok(()));
+            assert_eq!(verify_commit_detailed(&envelope(Some(&COMMIT), Some(&sig)), &pubkey, kt), VerifyOutcome::Ok);
+
+            // The same bytes under the other curve's type don't verify
+            let other_kt = 3 - kt;
+            if let Some(wrong) = ParsedKey::from_bytes(other_kt, &pubkey) {
+                assert!(verify_commit_parsed(&envelope(Some(&COMMIT), Some(&sig)), &wrong).is_err());
+            }
+        }
+        assert!(ParsedKey::from_bytes(9, &signed().0).is_none());
+        assert!(ParsedKey::from_bytes(1, &[0xff; 33]).is_none());
+    }
+
+    #[test]
+    fn test_parsed_key_error_branches() {
+        let (pubkey, sig) = signed();
+        let (other_key, _) = signed();
+        let key = ParsedKey::from_bytes(1, &pubkey).unwrap();
+        let other = ParsedKey::from_bytes(1, &other_key).unwrap();
+
+        let cases: [(CommitEnvelope, &ParsedKey, VerifyError); 6] = [
+            (envelope(None, Some(&sig)), &key, VerifyError::NoCommit),
+            (envelope(Some(&COMMIT), None), &key, VerifyError::NoSignature),
+            (envelope(Some(&COMMIT), Some(&[])), &key, VerifyError::NoSignature),
+            (envelope(Some(&[0xa1, 0x63, b'p']), Some(&sig)), &key, VerifyError::CanonicalizationFailed),
+            (envelope(Some(&COMMIT), Some(&sig[..40])), &key, VerifyError::BadSignatureEncoding),
+            (envelope(Some(&COMMIT), Some(&sig)), &other, VerifyError::VerifyFailed),
+        ];
+        for (i, (env, key, expected)) in cases.iter().enumerate() {
+            assert_eq!(verify_commit_parsed(env, key), Err(*expected), "case {}", i);
+        }
+
+        // A badly encoded signature is a mismatch on both paths, and lands in the same bucket
+        for e in [VerifyError::BadSignatureEncoding, VerifyError::VerifyFailed] {
+            assert!(matches!(e.error_type(), ErrorType::InvalidSignature), "{:?}", e);
+        }
+        for e in [VerifyError::NoSignature, VerifyError::NoCommit, VerifyError::CanonicalizationFailed] {
+            assert!(matches!(e.error_type(), ErrorType::MalformedCbor), "{:?}", e);
+        }
+        for (_, _, e) in &cases {
+            assert_eq!(VerifyOutcome::from(Err(*e)).error_type(), Some(e.error_type()), "{:?}", e);
+        }
+        assert_eq!(verify_commit_detailed(&envelope(Some(&COMMIT), Some(&sig[..40])), &pubkey, 1), VerifyOutcome::Mismatch);
+    }
+
+    #[test]
+    fn test_high_s_strict_rejects_normalize_accepts() {
+        let normalize = VerifyOptions { normalize: true, ..Default::default() };
+        let allow = VerifyOptions { allow_high_s: true, ..Default::default() };
+        for (kt, (pubkey, sig)) in [(1u8, signed()), (2u8, signed_p256())] {
+            let flipped = high_s(kt, &sig);
+            assert_ne!(flipped, sig);
+            let key = ParsedKey::from_bytes(kt, &pubkey).unwrap();
+            let env = envelope(Some(&COMMIT), Some(&flipped));
+
+            // Strict by default, on both curves
+            assert_eq!(verify_commit_parsed(&env, &key), Err(VerifyError::VerifyFailed), "key type {}", kt);
+            assert_eq!(verify_commit_detailed(&env, &pubkey, kt), VerifyOutcome::Mismatch, "key type {}", kt);
+            assert!(!verify_commit(&env, &pubkey, kt));
+
+            assert_eq!(verify_commit_parsed_with(&env, &key, &normalize), Ok(SignatureForm::HighS));
+            assert_eq!(verify_commit_detailed_with(&env, &pubkey, kt, &normalize), VerifyOutcome::VerifiedHighS);
+            assert_eq!(verify_commit_detailed_with(&env, &pubkey, kt, &allow), VerifyOutcome::Ok);
+
+            // A low-S signature is untouched by the options
+            let low = envelope(Some(&COMMIT), Some(&sig));
+            assert_eq!(verify_commit_parsed_with(&low, &key, &normalize), Ok(SignatureForm::LowS));
+            assert_eq!(verify_commit_detailed_with(&low, &pubkey, kt, &normalize), VerifyOutcome::Ok);
+        }
+
+        // Normalizing doesn't make someone else's signature verify
+        let (pubkey, _) = signed();
+        let (_, sig) = signed();
+        let forged = high_s(1, &sig);
+        let env = envelope(Some(&COMMIT), Some(&forged));
+        assert_eq!(verify_commit_detailed_with(&env, &pubkey, 1, &normalize), VerifyOutcome::Mismatch);
+    }
+
+    #[test]
+    fn test_error_types_separate_malformed_input_from_bad_signatures() {
+        assert!(VerifyOutcome::Ok.error_type().is_none());
+        assert!(VerifyOutcome::VerifiedHighS.is_ok() && VerifyOutcome::VerifiedHighS.error_type().is_none());
+        for outcome in [VerifyOutcome::NoSignature, VerifyOutcome::NoCommit, VerifyOutcome::CanonicalizationFailed] {
+            assert!(matches!(outcome.error_type(), Some(ErrorType::MalformedCbor)), "{:?}", outcome);
+        }
+        assert!(matches!(VerifyOutcome::Mismatch.error_type(), Some(ErrorType::InvalidSignature)));
+        assert!(matches!(VerifyOutcome::BadKey.error_type(), Some(ErrorType::MissingKey)));
+    }
+}
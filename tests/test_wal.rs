@@ -0,0 +1,178 @@
+#[cfg(test)]
+mod wal_tests {
+    use did_mmap_cache::archive::wal::WAL_FILE;
+    use did_mmap_cache::archive::{ArchiveWriter, MultiShardArchive, SegmentedArchive, WalSync};
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::path::Path;
+    use std::time::Duration;
+    use tempfile::tempdir;
+
+    fn journaled(dir: &Path, max_messages: u64) -> ArchiveWriter {
+        let mut writer = ArchiveWriter::new(dir, 0, 0, max_messages, None).unwrap();
+        writer.enable_wal(WalSync { every_records: 1, every: Duration::ZERO }).unwrap();
+        writer
+    }
+
+    fn append(writer: &mut ArchiveWriter, seqs: std::ops::Range<u64>) {
+        for seq in seqs {
+            let payload = writer.append_message(seq, &format!("did:plc:user{}", seq % 3), &format!("app.bsky.feed.post/{}", seq), format!("message {}", seq).as_bytes()).unwrap();
+            assert!(payload.is_none());
+        }
+    }
+
+    fn sealed_journals(dir: &Path) -> usize {
+        fs::read_dir(dir).unwrap().flatten().filter(|e| {
+            let name = e.file_name().into_string().unwrap();
+            name.starts_with("wal.") && name != WAL_FILE
+        }).count()
+    }
+
+    fn assert_stored(dir: &Path, seqs: std::ops::Range<u64>) {
+        let archive = SegmentedArchive::open_directory(dir, None, None).unwrap();
+        for seq in seqs {
+            assert_eq!(archive.get_message_by_seq(seq, None).unwrap(), format!("message {}", seq).into_bytes(), "seq {}", seq);
+        }
+    }
+
+    #[test]
+    fn test_crashed_writer_is_replayed_into_the_next_segment() {
+        let dir = tempdir().unwrap();
+        let mut writer = journaled(dir.path(), 100);
+        append(&mut writer, 0..5);
+        assert!(writer.wal_bytes() > 0);
+        // A crash: the pending buffer is never persisted
+        drop(writer);
+
+        let mut writer = ArchiveWriter::new(dir.path(), 0, 0, 100, None).unwrap();
+        assert_eq!(writer.wal_replayed, 5);
+        writer.append_message(5, "did:plc:user2", "app.bsky.feed.post/5", b"message 5").unwrap();
+        writer.finalize_segment().unwrap();
+
+        assert!(dir.path().join("s0_0.idx").exists());
+        assert_stored(dir.path(), 0..6);
+        // Nothing journals after the replay unless asked to, so the journal went with the segment
+        assert_eq!(sealed_journals(dir.path()), 0);
+        assert!(!dir.path().join(WAL_FILE).exists());
+        assert_eq!(ArchiveWriter::new(dir.path(), 0, 0, 100, None).unwrap().wal_replayed, 0);
+    }
+
+    #[test]
+    fn test_sealed_journals_replay_as_their_own_segments() {
+        let dir = tempdir().unwrap();
+        let mut writer = journaled(dir.path(), 3);
+        append(&mut writer, 0..2);
+        let unpersisted = writer.append_message(2, "did:plc:user2", "app.bsky.feed.post/2", b"message 2").unwrap().unwrap();
+        assert_eq!(sealed_journals(dir.path()), 1);
+        append(&mut writer, 3..5);
+        drop((writer, unpersisted));
+
+        // The taken segment never reached disk: its journal is written as that segment, and
+        // only wal.log comes back as the pending buffer
+        let mut writer = ArchiveWriter::new(dir.path(), 0, 0, 3, None).unwrap();
+        assert_eq!(writer.wal_replayed, 5);
+        assert_eq!(sealed_journals(dir.path()), 0);
+        assert_stored(dir.path(), 0..3);
+        assert!(!dir.path().join("s0_3.idx").exists());
+
+        writer.enable_wal(WalSync { every_records: 1, every: Duration::ZERO }).unwrap();
+        let payload = writer.append_message(5, "did:plc:user2", "app.bsky.feed.post/5", b"message 5").unwrap().unwrap();
+        assert_eq!(payload.start_seq, 3);
+        ArchiveWriter::persist_payload(payload, None).unwrap();
+        append(&mut writer, 6..8);
+        drop(writer);
+
+        // Only what came after the persisted segment is replayed
+        let mut writer = ArchiveWriter::new(dir.path(), 0, 0, 3, None).unwrap();
+        assert_eq!(writer.wal_replayed, 2);
+        writer.finalize_segment().unwrap();
+        assert_stored(dir.path(), 0..8);
+        let mut segments: Vec<String> = fs::read_dir(dir.path()).unwrap().flatten()
+            .filter_map(|e| e.file_name().into_string().ok()?.strip_suffix(".idx").map(str::to_string))
+            .collect();
+        segments.sort();
+        assert_eq!(segments, ["s0_0", "s0_3", "s0_6"]);
+    }
+
+    #[test]
+    fn test_replay_skips_stored_seqs_and_splits_at_the_segment_size() {
+        let dir = tempdir().unwrap();
+        let mut writer = journaled(dir.path(), 100);
+        append(&mut writer, 0..10);
+        drop(writer);
+        // A copy of the journal that outlived its segment, as a crash between the two leaves it
+        fs::copy(dir.path().join(WAL_FILE), dir.path().join("wal.log.bak")).unwrap();
+
+        // A smaller segment size than the crashed writer's
+        let mut writer = ArchiveWriter::new(dir.path(), 0, 0, 4, None).unwrap();
+        assert_eq!(writer.wal_replayed, 10);
+        assert!(dir.path().join("s0_0.idx").exists() && dir.path().join("s0_4.idx").exists());
+        writer.finalize_segment().unwrap();
+        assert_stored(dir.path(), 0..10);
+        drop(writer);
+
+        fs::rename(dir.path().join("wal.log.bak"), dir.path().join(WAL_FILE)).unwrap();
+        let writer = ArchiveWriter::new(dir.path(), 0, 0, 4, None).unwrap();
+        assert_eq!(writer.wal_replayed, 0);
+        assert_eq!(writer.wal_bytes(), 0);
+        assert!(!dir.path().join("s0_1.idx").exists());
+    }
+
+    #[test]
+    fn test_journaling_after_replay_is_opt_in() {
+        let dir = tempdir().unwrap();
+        let mut writer = journaled(dir.path(), 100);
+        append(&mut writer, 0..3);
+        drop(writer);
+
+        let mut writer = ArchiveWriter::new(dir.path(), 0, 0, 100, None).unwrap();
+        assert_eq!(writer.wal_bytes(), 0);
+        // Not journaled: a second crash loses this one but not the replayed messages
+        append(&mut writer, 3..4);
+        drop(writer);
+        let mut writer = ArchiveWriter::new(dir.path(), 0, 0, 100, None).unwrap();
+        assert_eq!(writer.wal_replayed, 3);
+
+        writer.enable_wal(WalSync::default()).unwrap();
+        assert!(writer.wal_bytes() > 0);
+        append(&mut writer, 4..5);
+        drop(writer);
+        assert_eq!(ArchiveWriter::new(dir.path(), 0, 0, 100, None).unwrap().wal_replayed, 4);
+    }
+
+    #[test]
+    fn test_torn_last_record_is_dropped() {
+        let dir = tempdir().unwrap();
+        let mut writer = journaled(dir.path(), 100);
+        append(&mut writer, 0..3);
+        drop(writer);
+        let mut wal = OpenOptions::new().append(true).open(dir.path().join(WAL_FILE)).unwrap();
+        wal.write_all(&[200, 0, 0, 0, 3, 0]).unwrap();
+
+        let mut writer = ArchiveWriter::new(dir.path(), 0, 0, 100, None).unwrap();
+        assert_eq!(writer.wal_replayed, 3);
+        writer.finalize_segment().unwrap();
+        assert_stored(dir.path(), 0..3);
+    }
+
+    #[test]
+    fn test_archive_replays_every_shard() {
+        let dir = tempdir().unwrap();
+        let archive = MultiShardArchive::new(dir.path(), 4, 1000, None).unwrap();
+        assert_eq!(archive.wal_bytes(), 0);
+        archive.enable_wal(WalSync::default()).unwrap();
+        for seq in 0..40u64 {
+            archive.ingest(seq, &format!("did:plc:user{}", seq), format!("app.bsky.feed.post/{}", seq), format!("message {}", seq).into_bytes());
+        }
+        assert!(archive.wal_bytes() > 0);
+        drop(archive);
+
+        let archive = MultiShardArchive::new(dir.path(), 4, 1000, None).unwrap();
+        assert_eq!(archive.wal_replayed(), 40);
+        archive.shutdown();
+        let reader = MultiShardArchive::open_readonly(dir.path(), None).unwrap();
+        for seq in 0..40u64 {
+            assert_eq!(reader.get_message_by_seq(seq).unwrap(), format!("message {}", seq).into_bytes());
+        }
+    }
+}
@@ -62,6 +62,65 @@ mod more_tests {
         assert!(cache.remove_did(&did));
         assert!(cache.get(&did).is_none());
     }
+
+    #[test]
+    fn test_header_and_resize() {
+        let dir = tempdir().unwrap();
+        let small = dir.path().join("small.bin");
+        let big = dir.path().join("big.bin");
+
+        let mut cache = MmapDidCache::create(&small, 64).unwrap();
+        assert_eq!(cache.num_slots(), 64);
+        let dids: Vec<String> = (0..40).map(|_| random_did()).collect();
+        for (i, did) in dids.iter().enumerate() {
+            let kt = if i % 2 == 0 { 1 } else { 2 };
+            assert!(cache.atomic_update_or_tombstone(did, Some(kt), Some(&[i as u8; 33])));
+        }
+        assert!(cache.remove_did(&dids[0]));
+        drop(cache);
+
+        // Slot count is read back from the header
+        let cache = MmapDidCache::open(&small).unwrap();
+        assert_eq!(cache.num_slots(), 64);
+        assert_eq!(cache.get(&dids[5]), Some(([5u8; 33], 2)));
+
+        let copied = cache.resize_into(&big, 1000).unwrap();
+        assert_eq!(copied, 39);
+
+        let resized = MmapDidCache::open(&big).unwrap();
+        assert_eq!(resized.num_slots(), 1000);
+        assert!(resized.get(&dids[0]).is_none());
+        for (i, did) in dids.iter().enumerate().skip(1) {
+            let kt = if i % 2 == 0 { 1 } else { 2 };
+            assert_eq!(resized.get(did), Some(([i as u8; 33], kt)));
+        }
+
+        // Too small a target fails instead of looping forever
+        assert!(cache.resize_into(dir.path().join("tiny.bin"), 10).is_err());
+    }
+
+    #[test]
+    fn test_flush_then_reopen() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("flush.bin");
+        let mut cache = MmapDidCache::create(&path, 64).unwrap();
+        let (did, other) = (random_did(), random_did());
+        assert!(cache.atomic_update_or_tombstone(&did, Some(1), Some(&[7u8; 33])));
+        cache.flush().unwrap();
+
+        assert!(cache.atomic_update_or_tombstone(&other, Some(2), Some(&[8u8; 33])));
+        let slot = cache.slot_of(&other).unwrap();
+        cache.flush_range(slot).unwrap();
+        assert!(cache.flush_range(64).is_err());
+        assert_eq!(cache.slot_of(&random_did()), None);
+
+        // Read back through a separate read-only mapping while the writer is still open
+        let reader = MmapDidCache::open(&path).unwrap();
+        assert_eq!(reader.get(&did), Some(([7u8; 33], 1)));
+        assert_eq!(reader.get(&other), Some(([8u8; 33], 2)));
+        assert_eq!(reader.slot_of(&other), Some(slot));
+        reader.flush().unwrap();
+    }
 }
 
 #[cfg(test)]